@@ -2,9 +2,11 @@ extern crate proc_macro;
 
 use proc_macro::TokenStream as ProcMacroStream;
 use proc_macro_error::proc_macro_error;
-use quote::quote;
+use quote::{format_ident, quote};
 use semver::Version;
-use syn::{Ident, LitStr};
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::{braced, Attribute, Expr, Ident, LitStr, Token, Type, Visibility};
 
 
 #[proc_macro_error]
@@ -32,10 +34,71 @@ pub fn semver_struct_impl(input: ProcMacroStream) -> ProcMacroStream {
 			in_litstr.span()
 		);
 
+		let name_litstr = LitStr::new(
+			&format!("Version{semver_ident_str}"),
+			in_litstr.span()
+		);
+
+		let (major, minor, patch) = (in_semver.major, in_semver.minor, in_semver.patch);
+
 		quote!{
-			#[derive(Clone, Default, PartialEq, Eq)]
+			#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
 			#[allow(clippy::exhaustive_structs)]
 			pub struct #version_ident;
+
+			#[allow(clippy::missing_trait_methods)]
+			impl crate::version::SchemaVersion for #version_ident {
+				const VERSION: &'static str = #in_litstr;
+
+				#[inline]
+				fn semver() -> ::semver::Version {
+					::semver::Version::new(#major, #minor, #patch)
+				}
+			}
+
+			impl ::core::fmt::Display for #version_ident {
+				#[inline]
+				fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+					f.write_str(#in_litstr)
+				}
+			}
+
+			impl ::core::str::FromStr for #version_ident {
+				type Err = crate::error::ReadConfigError;
+
+				/// Parse the version, telling apart versions that
+				/// are newer than it from ones that aren't versions
+				#[inline]
+				fn from_str(s: &str) -> ::core::result::Result<Self, Self::Err> {
+					if s == #in_litstr {
+						return ::core::result::Result::Ok(#version_ident);
+					}
+
+					let version = ::semver::Version::parse(s)
+						.map_err(|_err| crate::error::ReadConfigError::UnknownVersion(s.to_owned()))?;
+
+					if <Self as crate::version::SchemaVersion>::is_newer(&version) {
+						::core::result::Result::Err(crate::error::ReadConfigError::NewerVersion(version.to_string()))
+					} else {
+						::core::result::Result::Err(crate::error::ReadConfigError::UnknownVersion(version.to_string()))
+					}
+				}
+			}
+
+			impl ::core::cmp::PartialEq<::semver::Version> for #version_ident {
+				#[inline]
+				fn eq(&self, other: &::semver::Version) -> bool {
+					<Self as crate::version::SchemaVersion>::semver() == *other
+				}
+			}
+
+			#[allow(clippy::missing_trait_methods)]
+			impl ::core::cmp::PartialOrd<::semver::Version> for #version_ident {
+				#[inline]
+				fn partial_cmp(&self, other: &::semver::Version) -> ::core::option::Option<::core::cmp::Ordering> {
+					::core::option::Option::Some(<Self as crate::version::SchemaVersion>::semver().cmp(other))
+				}
+			}
 	
 			#[allow(clippy::missing_trait_methods)]
 			impl<'de> ::serde::de::Deserialize<'de> for #version_ident {
@@ -78,6 +141,21 @@ pub fn semver_struct_impl(input: ProcMacroStream) -> ProcMacroStream {
 					serializer.serialize_str(#in_litstr)	
 				}
 			}
+
+			impl ::schemars::JsonSchema for #version_ident {
+				#[inline]
+				fn schema_name() -> ::std::borrow::Cow<'static, str> {
+					::std::borrow::Cow::Borrowed(#name_litstr)
+				}
+
+				#[inline]
+				fn json_schema(_generator: &mut ::schemars::SchemaGenerator) -> ::schemars::Schema {
+					::schemars::json_schema!({
+						"type": "string",
+						"const": #in_litstr
+					})
+				}
+			}
 		}.into()
 	} else {
 		proc_macro_error::abort!(in_litstr, "Input is not semver");
@@ -109,7 +187,7 @@ pub fn environment_struct_impl(input: ProcMacroStream) -> ProcMacroStream {
 		);
 
 		quote! {
-			#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
+			#[derive(Serialize, Deserialize, ::schemars::JsonSchema, Clone, PartialEq, Eq, Default)]
 			pub struct #environ_ident {
 				pub version: crate::version::#version_ident
 			}
@@ -118,3 +196,486 @@ pub fn environment_struct_impl(input: ProcMacroStream) -> ProcMacroStream {
 		proc_macro_error::abort!(in_litstr, "Input is not semver");
 	}
 }
+
+/// How a field of an older config version becomes a field
+/// of the latest one
+struct Upgrade {
+	/// The field of the latest version it moves to, the same
+	/// name if not given
+	target: Option<Ident>,
+
+	/// A function converting the value, applied inside of
+	/// the option for optional fields
+	with: Option<Expr>
+}
+
+/// A field of a config version
+struct VersionField {
+	attrs: Vec<Attribute>,
+	vis: Visibility,
+	ident: Ident,
+	ty: Type,
+	upgrade: Option<Upgrade>
+}
+
+/// A config version, named with its version such as
+/// `TomlConfig0_2_1`
+struct ConfigVersion {
+	attrs: Vec<Attribute>,
+	vis: Visibility,
+	ident: Ident,
+	version: Version,
+	fields: Vec<VersionField>
+}
+
+/// Every version of a config, the latest first, along with
+/// the enum holding any of them
+struct VersionedConfig {
+	any_attrs: Vec<Attribute>,
+	any_vis: Visibility,
+	any_ident: Ident,
+	versions: Vec<ConfigVersion>
+}
+
+impl Parse for VersionField {
+	fn parse(input: ParseStream) -> syn::Result<Self> {
+		let attrs = input.call(Attribute::parse_outer)?;
+		let vis = input.parse()?;
+		let ident = input.parse()?;
+		input.parse::<Token![:]>()?;
+		let ty = input.parse()?;
+
+		let upgrade = if input.peek(Token![=>]) {
+			input.parse::<Token![=>]>()?;
+
+			let target = if input.peek(Ident) && !input.peek(kw::with) {
+				Some(input.parse()?)
+			} else {
+				None
+			};
+
+			let with = if input.peek(kw::with) {
+				input.parse::<kw::with>()?;
+				Some(input.parse()?)
+			} else {
+				None
+			};
+
+			Some(Upgrade { target, with })
+		} else {
+			None
+		};
+
+		Ok(Self { attrs, vis, ident, ty, upgrade })
+	}
+}
+
+impl Parse for ConfigVersion {
+	fn parse(input: ParseStream) -> syn::Result<Self> {
+		let attrs = input.call(Attribute::parse_outer)?;
+		let vis = input.parse()?;
+		input.parse::<Token![struct]>()?;
+		let ident: Ident = input.parse()?;
+
+		let version = version_of(&ident)
+			.ok_or_else(|| syn::Error::new(ident.span(), "Config names must end in their version, such as TomlConfig0_2_2"))?;
+
+		let content;
+		braced!(content in input);
+
+		let fields = Punctuated::<VersionField, Token![,]>::parse_terminated(&content)?
+			.into_iter()
+			.collect();
+
+		Ok(Self { attrs, vis, ident, version, fields })
+	}
+}
+
+impl Parse for VersionedConfig {
+	fn parse(input: ParseStream) -> syn::Result<Self> {
+		let any_attrs = input.call(Attribute::parse_outer)?;
+		let any_vis = input.parse()?;
+		input.parse::<Token![enum]>()?;
+		let any_ident = input.parse()?;
+		input.parse::<Token![;]>()?;
+
+		let mut versions = Vec::new();
+
+		while !input.is_empty() {
+			versions.push(input.parse()?);
+		}
+
+		if versions.is_empty() {
+			return Err(input.error("At least one config version is needed"));
+		}
+
+		Ok(Self { any_attrs, any_vis, any_ident, versions })
+	}
+}
+
+/// The version at the end of a config name, such as
+/// `0.2.1` for `TomlConfig0_2_1`
+fn version_of(ident: &Ident) -> Option<Version> {
+	let name = ident.to_string();
+	let mut parts = name.rsplitn(3, '_');
+
+	let patch = parts.next()?.parse().ok()?;
+	let minor = parts.next()?.parse().ok()?;
+
+	let rest = parts.next()?;
+	let digits = rest.len() - rest.chars().rev().take_while(char::is_ascii_digit).count();
+	let major = rest[digits..].parse().ok()?;
+
+	Some(Version::new(major, minor, patch))
+}
+
+/// Whether a type is written as `Option<...>`
+fn is_option(ty: &Type) -> bool {
+	match ty {
+		Type::Path(path) => path.path.segments.last().is_some_and(|segment| segment.ident == "Option"),
+		_ => false
+	}
+}
+
+/// Check that every field of an older version ends up in
+/// a field of the latest one, and that every field of the
+/// latest one an older version doesn't fill can be `None`.
+/// Otherwise upgrading would drop values or fail with type
+/// errors far from the fields responsible.
+fn check_upgrades(versions: &[ConfigVersion]) -> syn::Result<()> {
+	let latest = &versions[0];
+	let mut errors = Vec::new();
+
+	for version in versions.iter().skip(1) {
+		let mut targets = Vec::new();
+
+		for field in &version.fields {
+			let target = field.upgrade.as_ref()
+				.and_then(|upgrade| upgrade.target.as_ref())
+				.unwrap_or(&field.ident);
+
+			if latest.fields.iter().any(|latest_field| latest_field.ident == *target) {
+				targets.push(target);
+			} else {
+				errors.push(syn::Error::new(target.span(), format!(
+					"`{target}` is not a field of {}, say which field `{}` upgrades into with `=> field`",
+					latest.ident, field.ident
+				)));
+			}
+		}
+
+		let unfilled = latest.fields.iter()
+			.filter(|latest_field| !targets.contains(&&latest_field.ident) && !is_option(&latest_field.ty));
+
+		for latest_field in unfilled {
+			errors.push(syn::Error::new(latest_field.ident.span(), format!(
+				"`{}` is not filled by any field of {}, and only `Option` fields can be left out",
+				latest_field.ident, version.ident
+			)));
+		}
+	}
+
+	match errors.into_iter().reduce(|mut combined, error| {
+		combined.combine(error);
+		combined
+	}) {
+		Some(combined) => Err(combined),
+		None => Ok(())
+	}
+}
+
+/// Declare every version of a config. Each version is
+/// written as a struct named after its version, the latest
+/// first, and gets an `environment` field of its version.
+/// Fields of older versions may say how they upgrade:
+/// `=> target` moves the field into another field of the
+/// latest version, and `with function` converts it, inside
+/// of the option for optional fields. Fields of the latest
+/// version that an older one doesn't have are `None`, so
+/// they must be optional, and fields of older versions
+/// must land in a field of the latest one. Both are
+/// reported as errors at the field.
+///
+/// Generates the structs, the enum of every version with
+/// its `TomlConfig` and `TryFrom<&str>` impls reading the
+/// version a config declares, and the `From` impls
+/// upgrading each version to the latest.
+#[proc_macro_error]
+#[proc_macro]
+pub fn versioned_config(input: ProcMacroStream) -> ProcMacroStream {
+	let VersionedConfig { any_attrs, any_vis, any_ident, versions } = syn::parse_macro_input!(input as VersionedConfig);
+
+	if let Err(err) = check_upgrades(&versions) {
+		return err.to_compile_error().into();
+	}
+
+	let latest = &versions[0];
+	let latest_ident = &latest.ident;
+
+	let structs = versions.iter().map(|version| {
+		let ConfigVersion { attrs, vis, ident, version, fields } = version;
+
+		let environment = format_ident!("Environment{}_{}_{}", version.major, version.minor, version.patch);
+
+		let fields = fields.iter().map(|field| {
+			let VersionField { attrs, vis, ident, ty, .. } = field;
+
+			quote! {
+				#(#attrs)*
+				#vis #ident: #ty
+			}
+		});
+
+		quote! {
+			#(#attrs)*
+			#vis struct #ident {
+				pub environment: crate::resource::tomlconfig::#environment,
+				#(#fields),*
+			}
+		}
+	});
+
+	let arm_ident = |version: &Version| format_ident!("V{}_{}_{}", version.major, version.minor, version.patch);
+
+	let arms = versions.iter().enumerate().map(|(index, version)| {
+		let arm = arm_ident(&version.version);
+		let ident = &version.ident;
+
+		// The latest version is boxed, since it is usually
+		// much larger than the others
+		if index == 0 {
+			quote! { #arm(::std::boxed::Box<#ident>) }
+		} else {
+			quote! { #arm(#ident) }
+		}
+	});
+
+	let parse_arms = versions.iter().enumerate().map(|(index, version)| {
+		let arm = arm_ident(&version.version);
+		let (major, minor, patch) = (version.version.major, version.version.minor, version.version.patch);
+
+		if index == 0 {
+			quote! {
+				(#major, #minor, #patch) => ::core::result::Result::Ok(Self::#arm(::std::boxed::Box::new(crate::resource::tomlconfig::parse(value, strict)?)))
+			}
+		} else {
+			quote! {
+				(#major, #minor, #patch) => ::core::result::Result::Ok(Self::#arm(crate::resource::tomlconfig::parse(value, strict)?))
+			}
+		}
+	});
+
+	let latest_arms = versions.iter().enumerate().map(|(index, version)| {
+		let arm = arm_ident(&version.version);
+
+		if index == 0 {
+			quote! { #any_ident::#arm(config) => *config }
+		} else {
+			quote! { #any_ident::#arm(config) => config.into() }
+		}
+	});
+
+	let upgrades = versions.iter().skip(1).map(|version| {
+		let ident = &version.ident;
+
+		let moved = version.fields.iter().map(|field| {
+			let name = &field.ident;
+			let target = field.upgrade.as_ref()
+				.and_then(|upgrade| upgrade.target.clone())
+				.unwrap_or_else(|| name.clone());
+
+			let value = match field.upgrade.as_ref().and_then(|upgrade| upgrade.with.as_ref()) {
+				Some(with) if is_option(&field.ty) => quote! { value.#name.map(#with) },
+				Some(with) => quote! { (#with)(value.#name) },
+				None => quote! { value.#name }
+			};
+
+			(target, value)
+		}).collect::<Vec<_>>();
+
+		let assigned = latest.fields.iter().map(|latest_field| {
+			let name = &latest_field.ident;
+
+			match moved.iter().find(|(target, _)| target == name) {
+				Some((_, value)) => quote! { #name: #value },
+				None => quote! { #name: ::core::option::Option::None }
+			}
+		});
+
+		quote! {
+			impl ::core::convert::From<#ident> for #latest_ident {
+				#[inline]
+				fn from(value: #ident) -> Self {
+					Self {
+						environment: ::core::default::Default::default(),
+						#(#assigned),*
+					}
+				}
+			}
+		}
+	});
+
+	quote! {
+		#(#any_attrs)*
+		#any_vis enum #any_ident {
+			#(#arms),*
+		}
+
+		impl #any_ident {
+			/// Read a config from a file, rejecting unknown keys
+			/// when `strict` is set
+			///
+			/// # Errors
+			///
+			/// Returns any errors reading or parsing the file
+			#[inline]
+			pub fn read_toml_with(path: &::std::path::Path, strict: bool) -> ::core::result::Result<Self, crate::error::ReadConfigError> {
+				let config_str = ::std::fs::read_to_string(path)?;
+
+				Self::parse_with(config_str.as_str(), strict)
+			}
+
+			/// Parse a config with the schema of the version it
+			/// declares, so errors are reported against that
+			/// schema. Unknown keys are rejected when `strict` is
+			/// set.
+			///
+			/// # Errors
+			///
+			/// Returns any errors parsing the config
+			#[inline]
+			pub fn parse_with(value: &str, strict: bool) -> ::core::result::Result<Self, crate::error::ReadConfigError> {
+				let version = crate::resource::tomlconfig::declared_version(value)?;
+
+				match (version.major, version.minor, version.patch) {
+					#(#parse_arms,)*
+					_ => ::core::result::Result::Err(crate::resource::tomlconfig::unsupported_version(&version))
+				}
+			}
+		}
+
+		#[allow(clippy::missing_trait_methods)]
+		impl crate::resource::tomlconfig::TomlConfig for #any_ident {
+			#[inline]
+			fn read_toml(path: &::std::path::Path) -> ::core::result::Result<Self, crate::error::ReadConfigError> {
+				Self::read_toml_with(path, false)
+			}
+		}
+
+		impl ::core::convert::TryFrom<&str> for #any_ident {
+			type Error = crate::error::ReadConfigError;
+
+			/// Parse a config with the schema of the version it
+			/// declares, ignoring unknown keys
+			#[inline]
+			fn try_from(value: &str) -> ::core::result::Result<Self, Self::Error> {
+				Self::parse_with(value, false)
+			}
+		}
+
+		impl ::core::convert::From<#any_ident> for #latest_ident {
+			#[inline]
+			fn from(value: #any_ident) -> Self {
+				match value {
+					#(#latest_arms),*
+				}
+			}
+		}
+
+		#(#structs)*
+
+		#(#upgrades)*
+	}.into()
+}
+
+/// Keywords of `versioned_config!`
+mod kw {
+	syn::custom_keyword!(with);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{check_upgrades, VersionedConfig};
+
+
+	/// Check the upgrades of a config written like the input
+	/// of `versioned_config!`
+	fn check(input: &str) -> syn::Result<()> {
+		let config = syn::parse_str::<VersionedConfig>(input)?;
+
+		check_upgrades(&config.versions)
+	}
+
+	#[test]
+	fn moved_and_converted_fields_are_accepted() {
+		let result = check("
+			pub enum AnyConfig;
+
+			pub struct Config0_2_1 {
+				pub workspace: Workspace,
+				pub links: Option<Links>,
+				pub scripts: Option<Scripts>
+			}
+
+			pub struct Config0_2_0 {
+				pub repository: OldWorkspace => workspace with Into::into,
+				pub workspace: Option<Links> => links
+			}
+		");
+
+		assert!(result.is_ok());
+	}
+
+	#[test]
+	fn fields_without_a_target_are_rejected() {
+		let result = check("
+			pub enum AnyConfig;
+
+			pub struct Config0_2_1 {
+				pub links: Option<Links>
+			}
+
+			pub struct Config0_2_0 {
+				pub workspace: Option<Links> => linked
+			}
+		");
+
+		assert!(result.is_err_and(|err| err.to_string().contains("`linked` is not a field of Config0_2_1")));
+	}
+
+	#[test]
+	fn removed_fields_are_rejected() {
+		let result = check("
+			pub enum AnyConfig;
+
+			pub struct Config0_2_1 {
+				pub links: Option<Links>
+			}
+
+			pub struct Config0_2_0 {
+				pub links: Option<Links>,
+				pub build: Option<String>
+			}
+		");
+
+		assert!(result.is_err_and(|err| err.to_string().contains("`build` is not a field of Config0_2_1")));
+	}
+
+	#[test]
+	fn required_fields_without_a_source_are_rejected() {
+		let result = check("
+			pub enum AnyConfig;
+
+			pub struct Config0_2_1 {
+				pub workspace: Workspace,
+				pub links: Option<Links>
+			}
+
+			pub struct Config0_2_0 {
+				pub links: Option<Links>
+			}
+		");
+
+		assert!(result.is_err_and(|err| err.to_string().contains("`workspace` is not filled by any field of Config0_2_0")));
+	}
+}
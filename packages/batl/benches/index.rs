@@ -0,0 +1,43 @@
+use batl::index::Index;
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::path::{Path, PathBuf};
+
+
+/// Number of scopes in the generated root
+const SCOPES: usize = 50;
+
+/// Number of repositories in each scope
+const REPOSITORIES: usize = 100;
+
+
+/// Lay out a repository root with a few thousand empty
+/// repositories, in the same shape battalion uses
+fn generate_root() -> PathBuf {
+	let root = std::env::temp_dir().join("batl-bench-index");
+
+	if root.exists() {
+		return root;
+	}
+
+	for scope in 0..SCOPES {
+		for repository in 0..REPOSITORIES {
+			let path = root.join(format!("@scope{scope}")).join(format!("repo{repository}"));
+
+			std::fs::create_dir_all(&path).unwrap();
+			std::fs::write(path.join("batl.toml"), "").unwrap();
+		}
+	}
+
+	root
+}
+
+fn bench_index(c: &mut Criterion) {
+	let root = generate_root();
+	let index = Index::build(&root).unwrap();
+
+	c.bench_function("build", |b| b.iter(|| Index::build(Path::new(&root)).unwrap()));
+	c.bench_function("is_fresh", |b| b.iter(|| index.is_fresh()));
+}
+
+criterion_group!(benches, bench_index);
+criterion_main!(benches);
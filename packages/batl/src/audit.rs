@@ -0,0 +1,149 @@
+use crate::context::BatlContext;
+use crate::error as batlerror;
+use crate::registry::Registry;
+use crate::registry::advisory::Advisory;
+use crate::resource::{Name, Repository, Resource as _};
+use semver::{Op, Version, VersionReq};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+
+/// A dependency version affected by an advisory
+#[derive(Clone, Serialize)]
+#[non_exhaustive]
+pub struct Finding {
+	pub name: Name,
+	pub version: Version,
+	pub advisory: Advisory
+}
+
+/// The advisories affecting the dependencies of a
+/// repository
+#[derive(Clone, Serialize)]
+#[non_exhaustive]
+pub struct Audit {
+	pub name: Name,
+
+	/// The dependency versions that were checked
+	pub checked: Vec<(Name, Version)>,
+
+	/// Dependencies whose version couldn't be pinned down,
+	/// which were not checked
+	pub unresolved: Vec<Name>,
+
+	/// Affected versions, most severe first
+	pub findings: Vec<Finding>
+}
+
+/// Check the pinned versions of every transitive
+/// dependency against the advisories the registry
+/// publishes. Dependencies from a named registry are
+/// checked against that one instead.
+///
+/// # Errors
+///
+/// Returns any registry or network errors, and
+/// `UnknownRegistry` if a dependency names a registry the
+/// batlrc doesn't have
+#[inline]
+pub fn audit(context: &BatlContext, repository: &Repository) -> Result<Audit, batlerror::RegistryError> {
+	let (checked, unresolved) = pinned(context, repository);
+	let mut findings = Vec::new();
+
+	let registry = context.registry();
+	let sources = repository.dependency_registries(context);
+
+	let named = sources.values()
+		.collect::<HashSet<_>>()
+		.into_iter()
+		.map(|registry_name| Registry::named(context.system(), registry_name).map(|named| (registry_name.clone(), named)))
+		.collect::<Result<HashMap<_, _>, _>>()?;
+
+	for pin in &checked {
+		let source = sources.get(&pin.0)
+			.and_then(|registry_name| named.get(registry_name))
+			.unwrap_or(registry);
+
+		findings.extend(
+			source.advisories(&pin.0)?
+				.into_iter()
+				.filter(|advisory| advisory.affects(&pin.1))
+				.map(|advisory| Finding {
+					name: pin.0.clone(),
+					version: pin.1.clone(),
+					advisory
+				})
+		);
+	}
+
+	findings.sort_by(|a, b| b.advisory.severity.cmp(&a.advisory.severity)
+		.then_with(|| a.name.to_string().cmp(&b.name.to_string())));
+
+	Ok(Audit {
+		name: repository.name().clone(),
+		checked,
+		unresolved,
+		findings
+	})
+}
+
+/// The version a requirement pins, when it is a single
+/// `=` comparator with every part given
+///
+/// A bare version such as `1.2.3` is a caret requirement,
+/// so it doesn't pin anything.
+#[inline]
+#[must_use]
+pub fn exact_version(requirement: &str) -> Option<Version> {
+	let parsed = VersionReq::parse(requirement).ok()?;
+
+	let comparator = parsed.comparators.first().filter(|comparator| comparator.op == Op::Exact)?;
+
+	if parsed.comparators.len() != 1 {
+		return None;
+	}
+
+	Some(Version {
+		major: comparator.major,
+		minor: comparator.minor?,
+		patch: comparator.patch?,
+		pre: comparator.pre.clone(),
+		build: semver::BuildMetadata::EMPTY
+	})
+}
+
+/// Pin down the version of every transitive dependency of
+/// a repository
+///
+/// Local dependencies are pinned to their version and
+/// walked into, others to the version an `=` requirement
+/// pins them at. The rest can't be pinned.
+#[inline]
+#[must_use]
+pub fn pinned(context: &BatlContext, repository: &Repository) -> (Vec<(Name, Version)>, Vec<Name>) {
+	let mut seen = HashSet::from([repository.name().clone()]);
+	let mut pending = repository.config().dependencies.clone().into_iter().collect::<Vec<_>>();
+
+	let mut resolved = Vec::new();
+	let mut unresolved = Vec::new();
+
+	while let Some((name, requirement)) = pending.pop() {
+		if !seen.insert(name.clone()) {
+			continue;
+		}
+
+		if let Some(dependency) = Repository::load_in(context, name.clone()).ok().flatten() {
+			pending.extend(dependency.config().dependencies.clone());
+			resolved.push((name, dependency.config().version.clone()));
+		} else if let Some(version) = exact_version(&requirement) {
+			resolved.push((name, version));
+		} else {
+			unresolved.push(name);
+		}
+	}
+
+	resolved.sort_by_key(|pin| pin.0.to_string());
+	unresolved.sort_by_key(ToString::to_string);
+
+	(resolved, unresolved)
+}
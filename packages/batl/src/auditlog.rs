@@ -0,0 +1,163 @@
+use crate::resource::Name;
+use crate::resource::ownership::Ownership;
+use crate::system::Context;
+use serde::{Deserialize, Serialize};
+use std::env::var as env_var;
+use std::io::Write as _;
+use std::path::PathBuf;
+
+
+/// A script run recorded in the audit log, kept in
+/// `gen/history/audit.jsonl` when the batlrc enables it
+#[derive(Serialize, Deserialize, Clone)]
+#[non_exhaustive]
+pub struct Entry {
+	/// When the run finished, in seconds since the unix
+	/// epoch
+	pub time: u64,
+
+	/// The user who ran the script
+	pub user: String,
+
+	/// The machine the script was run from, if known
+	pub host: Option<String>,
+
+	pub repository: Name,
+	pub path: PathBuf,
+
+	/// The script that was run, or `run` for a command given
+	/// to `batl run`
+	pub script: String,
+
+	/// The command the script ran, as written, so values
+	/// interpolated from the environment aren't logged. For
+	/// `batl run` these are its arguments, quoted.
+	pub command: String,
+
+	/// The exit code of the run, if it exited
+	pub code: Option<i32>,
+	pub success: bool
+}
+
+/// Which entries of the audit log to show
+#[derive(Clone, Default)]
+#[non_exhaustive]
+pub struct Query {
+	pub user: Option<String>,
+	pub repository: Option<Name>,
+	pub script: Option<String>,
+
+	/// Only entries recorded at or after this time, in
+	/// seconds since the unix epoch
+	pub since: Option<u64>,
+
+	/// Only entries of failed runs
+	pub failed: bool
+}
+
+impl Entry {
+	/// An entry for a run that just finished, by the user
+	/// running battalion
+	#[inline]
+	#[must_use]
+	pub fn new(repository: Name, path: PathBuf, script: String, command: String, code: Option<i32>, success: bool) -> Self {
+		Self {
+			time: crate::system::unix_time(),
+			user: Ownership::current_user(),
+			host: current_host(),
+			repository,
+			path,
+			script,
+			command,
+			code,
+			success
+		}
+	}
+}
+
+impl Query {
+	/// Whether an entry passes every filter of the query
+	#[inline]
+	#[must_use]
+	pub fn matches(&self, entry: &Entry) -> bool {
+		self.user.as_ref().is_none_or(|user| *user == entry.user)
+			&& self.repository.as_ref().is_none_or(|repository| *repository == entry.repository)
+			&& self.script.as_ref().is_none_or(|script| *script == entry.script)
+			&& self.since.is_none_or(|since| entry.time >= since)
+			&& (!self.failed || !entry.success)
+	}
+}
+
+/// Whether the batlrc of a battalion root enables the
+/// audit log
+#[inline]
+#[must_use]
+pub fn enabled(system: &Context) -> bool {
+	system.batlrc()
+		.and_then(|rc| rc.audit_log)
+		.and_then(|audit_log| audit_log.enabled)
+		.unwrap_or_default()
+}
+
+/// Add an entry to the audit log, if it is enabled. The
+/// log is only ever appended to.
+///
+/// # Errors
+///
+/// Propogates any IO errors while writing the log
+#[inline]
+pub fn record(system: &Context, entry: &Entry) -> Result<(), std::io::Error> {
+	let Some(path) = system.audit_log_path().filter(|_| enabled(system)) else {
+		return Ok(());
+	};
+
+	if let Some(parent) = path.parent() {
+		std::fs::create_dir_all(parent)?;
+	}
+
+	// A single write of the whole line, so appends from
+	// users running scripts at once don't interleave
+	let mut line = serde_json::to_string(entry)
+		.map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+	line.push('\n');
+
+	let mut file = std::fs::OpenOptions::new()
+		.create(true)
+		.append(true)
+		.open(path)?;
+
+	file.write_all(line.as_bytes())
+}
+
+/// Every entry of the audit log, oldest first. Lines that
+/// can't be read are skipped.
+///
+/// # Errors
+///
+/// Propogates any IO errors while reading the log
+#[inline]
+pub fn entries(system: &Context) -> Result<Vec<Entry>, std::io::Error> {
+	let Some(path) = system.audit_log_path() else {
+		return Ok(Vec::new());
+	};
+
+	let contents = match std::fs::read_to_string(path) {
+		Ok(contents) => contents,
+		Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+		Err(err) => return Err(err)
+	};
+
+	Ok(contents.lines()
+		.filter_map(|line| serde_json::from_str(line).ok())
+		.collect())
+}
+
+/// The name of the machine battalion runs on, if it can be
+/// found
+fn current_host() -> Option<String> {
+	env_var("HOSTNAME")
+		.ok()
+		.or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+		.map(|host| host.trim().to_owned())
+		.filter(|host| !host.is_empty())
+}
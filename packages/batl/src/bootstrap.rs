@@ -0,0 +1,221 @@
+use crate::error as batlerror;
+use crate::resource::{Name, BATL_NAME_REGEX};
+use crate::resource::tomlconfig;
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Read as _;
+use std::path::{Component, Path};
+
+
+/// Url scheme of manifests read from disk
+const FILE_SCHEME: &str = "file://";
+
+/// The largest manifest that is downloaded, in bytes
+const MAX_MANIFEST_SIZE: u64 = 1024 * 1024;
+
+
+/// Everything needed to set up a workspace from nothing:
+/// the repositories it uses, where each comes from, and
+/// how they are linked
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Manifest {
+	/// The workspace the repositories are linked into
+	pub workspace: Name,
+
+	/// The repositories, sorted by name
+	pub repositories: Vec<Entry>,
+
+	/// The links of the workspace, sorted by link name
+	pub links: Vec<(String, Name)>
+}
+
+/// A repository of a manifest
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Entry {
+	pub name: Name,
+
+	/// The version fetched from the registry, the latest
+	/// if not given
+	pub version: Option<Version>,
+
+	/// A registry from `[registries]` in the batlrc, if not
+	/// the default one
+	pub registry: Option<String>,
+
+	/// A git url to clone the repository from, instead of
+	/// fetching it from a registry
+	pub git: Option<String>,
+
+	/// The branch to check out, when cloned
+	pub branch: Option<String>
+}
+
+/// Where an entry comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SourceKind {
+	Registry,
+	Git
+}
+
+impl Manifest {
+	/// Parse a manifest
+	///
+	/// # Errors
+	///
+	/// Returns `Invalid` if the manifest can't be parsed, a
+	/// name isn't a valid resource name, a link name isn't a
+	/// single path component, or an entry gives both a git
+	/// url and a registry version
+	#[inline]
+	pub fn from_toml(toml: &str) -> Result<Self, batlerror::BootstrapError> {
+		let latest: TomlManifestLatest = toml::from_str(toml)
+			.map_err(|err| batlerror::BootstrapError::Invalid(err.to_string()))?;
+
+		let mut repositories = latest.repositories
+			.unwrap_or_default()
+			.into_iter()
+			.map(|(name, toml_entry)| Entry {
+				name,
+				version: toml_entry.version,
+				registry: toml_entry.registry,
+				git: toml_entry.git,
+				branch: toml_entry.branch
+			})
+			.collect::<Vec<_>>();
+
+		repositories.sort_by_key(|entry| entry.name.to_string());
+
+		if let Some(mixed) = repositories.iter().find(|entry| entry.git.is_some() && (entry.version.is_some() || entry.registry.is_some())) {
+			return Err(batlerror::BootstrapError::Invalid(format!(
+				"{} has a git url along with a registry version, give only one", mixed.name
+			)));
+		}
+
+		let mut links = latest.links.unwrap_or_default().into_iter().collect::<Vec<_>>();
+		links.sort_by(|a, b| a.0.cmp(&b.0));
+
+		let names = core::iter::once(&latest.workspace.name)
+			.chain(repositories.iter().map(|entry| &entry.name))
+			.chain(links.iter().map(|link| &link.1));
+
+		for name in names {
+			check_name(name)?;
+		}
+
+		// Links become folders of the workspace, so anything
+		// that would lead out of it is refused
+		if let Some(link) = links.iter().find(|link| !is_single_component(&link.0)) {
+			return Err(batlerror::BootstrapError::Invalid(format!("{} is not a valid link name", link.0)));
+		}
+
+		Ok(Self {
+			workspace: latest.workspace.name,
+			repositories,
+			links
+		})
+	}
+
+	/// Download a manifest from a url, or read it from a
+	/// `file://` url or plain path
+	///
+	/// # Errors
+	///
+	/// Returns `Network` if the manifest can't be
+	/// downloaded, `TooLarge` if it is over the size limit,
+	/// and `Invalid` if it can't be parsed. Propogates any IO
+	/// errors while reading it.
+	#[inline]
+	pub fn download(url: &str) -> Result<Self, batlerror::BootstrapError> {
+		if !url.starts_with("http://") && !url.starts_with("https://") {
+			let path = url.strip_prefix(FILE_SCHEME).unwrap_or(url);
+
+			return Self::from_toml(&std::fs::read_to_string(path)?);
+		}
+
+		let response = ureq::get(url).call()
+			.map_err(|err| batlerror::BootstrapError::Network(err.to_string()))?;
+
+		// One byte past the limit tells a manifest that is too
+		// large apart from one that fits exactly
+		let mut bytes = Vec::new();
+		response.into_reader().take(MAX_MANIFEST_SIZE.saturating_add(1)).read_to_end(&mut bytes)?;
+
+		if u64::try_from(bytes.len()).map_or(true, |size| size > MAX_MANIFEST_SIZE) {
+			return Err(batlerror::BootstrapError::TooLarge(MAX_MANIFEST_SIZE));
+		}
+
+		let toml = String::from_utf8(bytes)
+			.map_err(|err| batlerror::BootstrapError::Invalid(err.to_string()))?;
+
+		Self::from_toml(&toml)
+	}
+
+	/// Get the entry of a repository
+	#[inline]
+	#[must_use]
+	pub fn entry(&self, name: &Name) -> Option<&Entry> {
+		self.repositories.iter().find(|entry| entry.name == *name)
+	}
+}
+
+impl Entry {
+	/// Where the repository comes from
+	#[inline]
+	#[must_use]
+	pub const fn source(&self) -> SourceKind {
+		if self.git.is_some() {
+			SourceKind::Git
+		} else {
+			SourceKind::Registry
+		}
+	}
+}
+
+/// Check that a name of the manifest is one that could be
+/// given on the command line, before anything is fetched
+/// into the path it names
+fn check_name(name: &Name) -> Result<(), batlerror::BootstrapError> {
+	if BATL_NAME_REGEX.is_match(&name.to_string()) {
+		Ok(())
+	} else {
+		Err(batlerror::BootstrapError::Invalid(format!("{name} is not a valid resource name")))
+	}
+}
+
+/// Whether a link name is a single plain path component
+fn is_single_component(link: &str) -> bool {
+	let mut components = Path::new(link).components();
+
+	matches!((components.next(), components.next()), (Some(Component::Normal(part)), None) if part == link)
+}
+
+// MANIFEST VERSIONS //
+pub type TomlManifestLatest = TomlManifest0_2_2;
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct TomlManifest0_2_2 {
+	pub environment: tomlconfig::Environment0_2_2,
+	pub workspace: TomlWorkspace0_2_2,
+	pub repositories: Option<HashMap<Name, TomlEntry0_2_2>>,
+	pub links: Option<tomlconfig::Links0_2_2>
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct TomlWorkspace0_2_2 {
+	pub name: Name
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct TomlEntry0_2_2 {
+	pub version: Option<Version>,
+	pub registry: Option<String>,
+	pub git: Option<String>,
+	pub branch: Option<String>
+}
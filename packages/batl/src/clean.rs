@@ -0,0 +1,192 @@
+use core::fmt::{Display, Formatter};
+use core::time::Duration;
+use crate::system::Context;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+
+/// What battalion generates that can be cleaned up to
+/// reclaim space
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum Category {
+	/// Archives generated for publishing, under
+	/// `gen/archives`
+	Archives,
+
+	/// Cached registry responses, under `gen/cache/http`
+	HttpCache,
+
+	/// Logs of scripts, under `gen/logs` of each repository
+	Logs,
+
+	/// Downloads left behind by interrupted fetches, under
+	/// `gen/cache/fetch`
+	Trash,
+
+	/// Outputs of scripts, under `gen/out` of each
+	/// repository, which running the scripts again
+	/// recreates
+	ScriptCache
+}
+
+/// A file that cleaning would remove
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Entry {
+	pub category: Category,
+	pub path: PathBuf,
+
+	/// The size of the file in bytes
+	pub size: u64
+}
+
+impl Category {
+	/// Every category, in the order they are reported
+	pub const ALL: [Self; 5] = [Self::Archives, Self::HttpCache, Self::Logs, Self::Trash, Self::ScriptCache];
+
+	/// The directories holding the files of the category,
+	/// given the repositories to look in for repository
+	/// local files
+	#[inline]
+	#[must_use]
+	pub fn directories(self, system: &Context, repositories: &[PathBuf]) -> Vec<PathBuf> {
+		let in_repositories = |directory: &str| repositories.iter()
+			.map(|repository| repository.join("gen").join(directory))
+			.collect();
+
+		match self {
+			Self::Archives => system.archive_root().into_iter().collect(),
+			Self::HttpCache => system.http_cache_root().into_iter().collect(),
+			Self::Logs => in_repositories("logs"),
+			Self::Trash => system.cache_root().map(|p| p.join("fetch")).into_iter().collect(),
+			Self::ScriptCache => in_repositories("out")
+		}
+	}
+}
+
+impl Display for Category {
+	#[inline]
+	fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+		f.write_str(match *self {
+			Self::Archives => "archives",
+			Self::HttpCache => "http cache",
+			Self::Logs => "logs",
+			Self::Trash => "trash",
+			Self::ScriptCache => "script cache"
+		})
+	}
+}
+
+/// Find the files of the categories, leaving out those
+/// changed more recently than `older_than` ago. Symlinks
+/// are listed themselves, never followed.
+///
+/// # Errors
+///
+/// Propogates any IO errors other than a directory not
+/// existing
+#[inline]
+pub fn collect(system: &Context, repositories: &[PathBuf], categories: &[Category], older_than: Option<Duration>) -> Result<Vec<Entry>, std::io::Error> {
+	let cutoff = older_than.and_then(|age| SystemTime::now().checked_sub(age));
+	let mut entries = Vec::new();
+
+	for category in categories {
+		for directory in category.directories(system, repositories) {
+			let mut files = Vec::new();
+			walk(&directory, &mut files)?;
+
+			for (path, metadata) in files {
+				let old_enough = cutoff.is_none_or(|before| metadata.modified().is_ok_and(|modified| modified <= before));
+
+				if old_enough {
+					entries.push(Entry { category: *category, path, size: metadata.len() });
+				}
+			}
+		}
+	}
+
+	Ok(entries)
+}
+
+/// Remove the files of entries, along with any
+/// directories left empty in the directories of their
+/// categories. Returns the bytes freed.
+///
+/// # Errors
+///
+/// Propogates any IO errors other than a file already
+/// being gone
+#[inline]
+pub fn remove(system: &Context, repositories: &[PathBuf], entries: &[Entry]) -> Result<u64, std::io::Error> {
+	let mut freed = 0u64;
+
+	for entry in entries {
+		match std::fs::remove_file(&entry.path) {
+			Ok(()) => freed = freed.saturating_add(entry.size),
+			Err(err) if err.kind() == std::io::ErrorKind::NotFound => {},
+			Err(err) => return Err(err)
+		}
+	}
+
+	let mut categories = entries.iter().map(|entry| entry.category).collect::<Vec<_>>();
+	categories.sort();
+	categories.dedup();
+
+	for category in categories {
+		for directory in category.directories(system, repositories) {
+			prune(&directory)?;
+		}
+	}
+
+	Ok(freed)
+}
+
+/// Gather every file under a directory, without following
+/// symlinks
+fn walk(directory: &Path, files: &mut Vec<(PathBuf, std::fs::Metadata)>) -> Result<(), std::io::Error> {
+	let listing = match std::fs::read_dir(directory) {
+		Ok(found) => found,
+		Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+		Err(err) => return Err(err)
+	};
+
+	for result in listing {
+		let entry = result?;
+		let metadata = std::fs::symlink_metadata(entry.path())?;
+
+		if metadata.is_dir() {
+			walk(&entry.path(), files)?;
+		} else {
+			files.push((entry.path(), metadata));
+		}
+	}
+
+	Ok(())
+}
+
+/// Remove the empty directories below a directory, keeping
+/// the directory itself
+fn prune(directory: &Path) -> Result<(), std::io::Error> {
+	let listing = match std::fs::read_dir(directory) {
+		Ok(found) => found,
+		Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+		Err(err) => return Err(err)
+	};
+
+	for result in listing {
+		let entry = result?;
+
+		if !std::fs::symlink_metadata(entry.path())?.is_dir() {
+			continue;
+		}
+
+		prune(&entry.path())?;
+
+		if std::fs::read_dir(entry.path())?.next().is_none() {
+			std::fs::remove_dir(entry.path())?;
+		}
+	}
+
+	Ok(())
+}
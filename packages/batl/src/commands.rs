@@ -1,26 +1,88 @@
 use batl::resource::{self as batlres, BatlRc};
-use batl::resource::tomlconfig::{TomlConfig, write_toml};
-use crate::output::success;
-use crate::utils::UtilityError;
-use std::collections::HashMap;
+use batl::resource::tomlconfig::{DependencyLatest, RepositoryGit0_2_2, TomlConfig, write_toml};
+use batl::resource::repository::CreateRepositoryOptions;
+use batl::resource::{RepositoryKind, Resource};
+use batl::resource::kind::INSTALL_SCRIPT;
+use batl::resource::script::{Source as ScriptSource, DEP_CHANGE_SCRIPT};
+use batl::resource::license::Status as LicenseStatus;
+use batl::resource::summary::Dependency;
+use batl::resource::workspace::LinkDrift;
+use batl::registry::Registry;
+use batl::registry::auth::Tokens;
+use batl::resource::batlrc::AuthProvider0_2_2;
+use batl::audit;
+use batl::bootstrap::{Entry as BootstrapEntry, Manifest as BootstrapManifest, SourceKind as BootstrapSource};
+use batl::clean::{self, Category as CleanCategory};
+use batl::context::BatlContext;
+use batl::error as batlerror;
+use batl::docs;
+use batl::executor::Invocation;
+use batl::graph::Graph;
+use batl::runstate::RunState;
+use batl::index::{Index, Kind};
+use batl::integrity::PublisherKey;
+use batl::schema;
+use batl::search;
+use batl::toolchain::{self, ToolStatus};
+use batl::units::ByteSize;
+use batl::upgrade::{Backup as UpgradeBackup, Plan as UpgradePlan};
+use crate::output::{error, info, success, warning};
+use crate::output::name as output_name;
+use crate::utils::{api_notice, apply_overrides, check_tools, expand_names, failure_reason, fetch_repository, newest_fetched, open_url, pick_repositories, relink_fetched, repository_candidates, repository_paths, run_script, status_code, warn_name_mismatch, Batch, ExecOutput, OnConflict, UtilityError, BATL_NAME_REGEX, RESUME_VAR};
+use std::collections::{HashMap, HashSet};
 use std::env::current_dir;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
 
 pub mod workspace;
 pub mod link;
 pub mod repository;
+pub mod artifacts;
+pub mod auth;
+pub mod registry;
+pub mod auditlog;
+pub mod package;
+pub mod policy;
+pub mod script;
+pub mod path;
+pub mod crash;
 
 
-pub fn cmd_setup() -> Result<(), UtilityError> {
+#[derive(Clone, Copy, Default, clap::ValueEnum)]
+pub enum GraphFormat {
+	/// Graphviz dot
+	#[default]
+	Dot,
+	/// A mermaid flowchart, for markdown
+	Mermaid,
+	/// A self-contained page with collapsible nodes
+	Html,
+	/// Each node with the edges leading out of it
+	Json,
+	/// Build rules running a script in dependency order
+	Ninja
+}
+
+
+pub fn cmd_setup(context: &BatlContext) -> Result<(), UtilityError> {
 	#[cfg(target_os = "windows")]
 	crate::utils::windows_symlink_perms()?;
 
-	if batl::system::batl_root().is_some() {
-		return Err(UtilityError::AlreadySetup);
-	}
+	// A root given with --root, BATL_ROOT, or a profile is
+	// set up where it points, unless it already is
+	let system = context.system();
+	let given = system.root_given() || system.profile().is_some();
 
-	let batl_root = dirs::home_dir()
-		.ok_or(UtilityError::ResourceDoesNotExist("Home directory".to_string()))?
-		.join("battalion");
+	let batl_root = match system.batl_root() {
+		Some(root) if root.join(".batlrc").exists() || !given => {
+			return Err(UtilityError::AlreadySetup);
+		},
+		Some(root) => root,
+		None => dirs::home_dir()
+			.ok_or(UtilityError::ResourceDoesNotExist("Home directory".to_string()))?
+			.join("battalion")
+	};
 
 	std::fs::create_dir_all(batl_root.join("workspaces"))?;
 	std::fs::create_dir_all(batl_root.join("repositories"))?;
@@ -34,94 +96,1841 @@ pub fn cmd_setup() -> Result<(), UtilityError> {
 	Ok(())  
 }
 
-pub fn cmd_add(name: String) -> Result<(), UtilityError> {
+pub fn cmd_add(name: Option<String>, interactive: bool, mut batch: Batch, context: &BatlContext) -> Result<(), UtilityError> {
 	let config_path = batlres::repository::TomlConfigLatest::locate(&current_dir()?)
 		.ok_or(UtilityError::ResourceDoesNotExist("Batallion config".to_string()))?;
 
 	let mut config = batlres::repository::TomlConfigLatest::read_toml(&config_path)
 		.map_err(|_| UtilityError::InvalidConfig)?;
 
-	if let Some(mut deps) = config.dependencies {
-		deps.insert(name.as_str().into(), "latest".to_string());
+	let several = interactive || name.as_deref().is_some_and(|name| name.starts_with('@'));
 
-		config.dependencies = Some(deps);
+	let names = if interactive {
+		let existing = config.dependencies.clone().unwrap_or_default();
+
+		let candidates = repository_candidates(true, context)?
+			.into_iter()
+			.filter(|candidate| *candidate != config.repository.name.to_string())
+			.filter(|candidate| !existing.contains_key(&candidate.as_str().into()))
+			.collect();
+
+		pick_repositories("Add dependency", candidates)?
 	} else {
-		let mut deps = HashMap::new();
-		deps.insert(name.as_str().into(), "latest".to_string());
+		name.map(|name| expand_names(&name, context)).transpose()?.unwrap_or_default()
+	};
+
+	if names.is_empty() {
+		info("No dependencies added");
+		return Ok(());
+	}
+
+	let mut deps = config.dependencies.unwrap_or_default();
+	let mut changes = Vec::new();
+	let mut added = Vec::new();
+
+	for name in &names {
+		let outcome = check_dependency(name, &config.repository.name);
+
+		// A single dependency fails as it always has, several
+		// are reported together once the rest are added
+		if several {
+			let valid = outcome.is_ok();
+
+			if !batch.record(name, outcome) {
+				break;
+			}
+
+			if !valid {
+				continue;
+			}
+		} else {
+			outcome?;
+		}
+
+		let dependency = batlres::Repository::load_in(context, name.as_str().into()).ok().flatten();
 
+		if dependency.is_some_and(|dependency| !dependency.config().kind.is_dependable()) {
+			warning(&format!("{} is an app, which shouldn't be depended on", name));
+		}
+
+		let previous = deps.insert(name.as_str().into(), DependencyLatest::Requirement("latest".to_string()))
+			.map(|dependency| dependency.into_parts().0);
+
+		if previous.as_deref() != Some("latest") {
+			changes.push(DepChange::new(name, previous, Some("latest".to_string())));
+		}
+
+		added.push(name);
+	}
+
+	if !added.is_empty() {
 		config.dependencies = Some(deps);
+
+		write_toml(&config_path, &config)?;
 	}
 
-	write_toml(&config_path, &config)?;
+	for name in added {
+		success(&format!("Added dependency {}", name));
+	}
+
+	run_dep_change_hook(&changes, context)?;
+
+	if several {
+		batch.finish()?;
+	}
+
+	Ok(())
+}
 
-	success(&format!("Added dependency {}", name));
+/// Check that a repository can be added as a dependency of
+/// another
+fn check_dependency(name: &str, dependent: &batlres::Name) -> Result<(), UtilityError> {
+	if !BATL_NAME_REGEX.is_match(name) {
+		return Err(UtilityError::InvalidName(name.to_string()));
+	}
+
+	if name == dependent.to_string() {
+		return Err(UtilityError::InvalidName(format!("{} can't depend on itself", name)));
+	}
 
 	Ok(())
 }
 
-pub fn cmd_remove(name: String) -> Result<(), UtilityError> {
+pub fn cmd_remove(name: String, context: &BatlContext) -> Result<(), UtilityError> {
 	let config_path = batlres::repository::TomlConfigLatest::locate(&current_dir()?)
 		.ok_or(UtilityError::ResourceDoesNotExist("Batallion config".to_string()))?;
 
 	let mut config = batlres::repository::TomlConfigLatest::read_toml(&config_path)
 		.map_err(|_| UtilityError::InvalidConfig)?;
 
-	if let Some(mut deps) = config.dependencies {
-		if deps.remove(&name.as_str().into()).is_none() {
+	let previous = if let Some(mut deps) = config.dependencies {
+		let Some(previous) = deps.remove(&name.as_str().into()) else {
 			return Err(UtilityError::ResourceDoesNotExist("Dependency".to_string()))
-		}
+		};
 
 		config.dependencies = Some(deps);
+
+		previous.into_parts().0
 	} else {
 		return Err(UtilityError::ResourceDoesNotExist("Dependency".to_string()));
-	}
+	};
 
 	write_toml(&config_path, &config)?;
 
 	success(&format!("Removed dependency {}", name));
 
+	run_dep_change_hook(&[DepChange::new(&name, Some(previous), None)], context)
+}
+
+pub fn cmd_update(name: String, requirement: String, context: &BatlContext) -> Result<(), UtilityError> {
+	// Checked before anything is written or any hook runs
+	let wanted = semver::VersionReq::parse(&requirement)
+		.map_err(|err| UtilityError::ConfigError(format!("{} is not a version requirement: {}", requirement, err)))?;
+
+	let config_path = batlres::repository::TomlConfigLatest::locate(&current_dir()?)
+		.ok_or(UtilityError::ResourceDoesNotExist("Batallion config".to_string()))?;
+
+	let mut config = batlres::repository::TomlConfigLatest::read_toml(&config_path)
+		.map_err(|_| UtilityError::InvalidConfig)?;
+
+	let mut deps = config.dependencies.unwrap_or_default();
+
+	let Some((previous, registry)) = deps.get(&name.as_str().into()).cloned().map(DependencyLatest::into_parts) else {
+		return Err(UtilityError::ResourceDoesNotExist(format!("Dependency {}", name)));
+	};
+
+	if previous == requirement {
+		info(&format!("{} already requires {}", name, requirement));
+		return Ok(());
+	}
+
+	// The registry it comes from stays the same
+	deps.insert(name.as_str().into(), DependencyLatest::new(requirement.clone(), registry));
+	config.dependencies = Some(deps);
+
+	write_toml(&config_path, &config)?;
+
+	success(&format!("Updated dependency {} from {} to {}", name, previous, requirement));
+
+	// Links pinned to an older version move to the newest
+	// fetched one the requirement allows
+	if let Some(newest) = newest_fetched(&name.as_str().into(), &wanted, context) {
+		relink_fetched(&name.as_str().into(), &newest, context)?;
+	}
+
+	run_dep_change_hook(&[DepChange::new(&name, Some(previous), Some(requirement))], context)
+}
+
+/// A change to one dependency of the current repository,
+/// described to its on-dep-change hook
+struct DepChange {
+	name: String,
+
+	/// The requirement before the change, `None` if the
+	/// dependency was just added
+	from: Option<String>,
+
+	/// The requirement after the change, `None` if the
+	/// dependency was removed
+	to: Option<String>
+}
+
+impl DepChange {
+	fn new(name: &str, from: Option<String>, to: Option<String>) -> Self {
+		Self { name: name.to_string(), from, to }
+	}
+
+	/// Whether the dependency was added, removed, or
+	/// changed
+	fn kind(&self) -> &'static str {
+		match (&self.from, &self.to) {
+			(None, _) => "added",
+			(_, None) => "removed",
+			_ => "changed"
+		}
+	}
+}
+
+/// Run the on-dep-change script of the current repository
+/// once for each change, if it has one. The change is
+/// described in `BATL_DEP_CHANGE`, `BATL_DEP_CHANGE_NAME`,
+/// `BATL_DEP_CHANGE_FROM`, and `BATL_DEP_CHANGE_TO`, which
+/// are empty where they don't apply.
+fn run_dep_change_hook(changes: &[DepChange], context: &BatlContext) -> Result<(), UtilityError> {
+	if changes.is_empty() {
+		return Ok(());
+	}
+
+	let repository = batlres::Repository::locate_then_load_in(context, &current_dir()?)?
+		.ok_or(UtilityError::ResourceDoesNotExist("Repository".to_string()))?;
+
+	let Some(script) = repository.config().scripts.get(DEP_CHANGE_SCRIPT).cloned() else {
+		return Ok(());
+	};
+
+	check_tools(&repository, &script, None)?;
+
+	for change in changes {
+		let mut invocation = Invocation::for_repository(context, &repository, &script);
+
+		invocation.env.extend([
+			("BATL_DEP_CHANGE".to_string(), change.kind().to_string()),
+			("BATL_DEP_CHANGE_NAME".to_string(), change.name.clone()),
+			("BATL_DEP_CHANGE_FROM".to_string(), change.from.clone().unwrap_or_default()),
+			("BATL_DEP_CHANGE_TO".to_string(), change.to.clone().unwrap_or_default())
+		]);
+
+		info(&format!("Running {} for {} {}", DEP_CHANGE_SCRIPT, change.kind(), change.name));
+
+		let status = run_script(&repository, DEP_CHANGE_SCRIPT, &script, invocation, None, ExecOutput::Full, context)?;
+
+		if !status.success() {
+			return Err(UtilityError::ScriptError(format!(
+				"{} failed for {}, batl.toml was still updated", DEP_CHANGE_SCRIPT, change.name
+			)));
+		}
+	}
+
 	Ok(())
 }
 
-pub fn cmd_upgrade() -> Result<(), UtilityError> {
-	let batl_root = batl::system::batl_root()
-		.ok_or(UtilityError::ResourceDoesNotExist("Battalion root".to_string()))?;
+pub fn cmd_upgrade(dry_run: bool, rollback: Option<String>, context: &BatlContext) -> Result<(), UtilityError> {
+	let system = context.system();
+	let backup_root = system.upgrade_backup_root()
+		.ok_or(UtilityError::NotSetup)?;
 
-	if !batl_root.join("gen").exists() {
-		let gen_ = batl_root.join("gen");
+	if let Some(stamp) = rollback {
+		return rollback_upgrade(system, &backup_root, &stamp);
+	}
 
-		std::fs::create_dir(&gen_)?;
-		std::fs::create_dir(&gen_.join("archives"))?;
-		std::fs::create_dir(&gen_.join("archives/repositories"))?;
-		std::fs::create_dir(&gen_.join("archives/workspaces"))?;
+	let plan = UpgradePlan::detect(system)
+		.ok_or(UtilityError::NotSetup)?;
 
-		success("Added gen folder");
+	if plan.is_empty() {
+		success("Already up to date");
+		return Ok(());
 	}
 
-	if batl::system::batlrc().is_none() {
-		let batlrc = BatlRc::default();
-	
-		write_toml(&batl::system::batlrc_path().expect("Nonsensical already checked for root"), &batlrc)?;
+	info("The upgrade will:");
+
+	for change in &plan.changes {
+		println!("  {}", change);
+	}
 
-		success("Added batlrc toml");
+	if dry_run {
+		return Ok(());
 	}
 
+	let backup = plan.apply(&backup_root)?;
+
+	success(&format!(
+		"Upgraded, with a backup in {}. Undo it with `batl upgrade --rollback {}`",
+		backup_root.join(&backup.stamp).display(), backup.stamp
+	));
+
 	Ok(())
 }
 
-pub fn cmd_auth() -> Result<(), UtilityError> {
-	let mut key_prompt = dialoguer::Input::new();
+/// Undo an upgrade from its backup
+fn rollback_upgrade(system: &batl::system::Context, backup_root: &Path, stamp: &str) -> Result<(), UtilityError> {
+	let root = system.batl_root()
+		.ok_or(UtilityError::NotSetup)?;
 
-	let api_key: String = key_prompt.with_prompt("API key").interact()?;
+	let backup = match UpgradeBackup::load(backup_root, stamp) {
+		Ok(backup) => backup,
+		Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+			let stamps = UpgradeBackup::list(backup_root)?;
+
+			if !stamps.is_empty() {
+				info(&format!("Backups: {}", stamps.join(", ")));
+			}
+
+			return Err(UtilityError::ResourceDoesNotExist(format!("Upgrade backup {}", stamp)));
+		},
+		Err(err) if err.kind() == std::io::ErrorKind::InvalidData => {
+			return Err(UtilityError::ConfigError(err.to_string()));
+		},
+		Err(err) => return Err(err.into())
+	};
+
+	for kept in backup.restore(&root, backup_root)? {
+		warning(&format!("Kept {}, it has been used since the upgrade", kept.display()));
+	}
+
+	success(&format!("Rolled back the upgrade of {}", stamp));
+
+	Ok(())
+}
 
-	let mut batlrc = batl::system::batlrc()
+pub fn cmd_auth(device: bool, context: &BatlContext) -> Result<(), UtilityError> {
+	let mut batlrc = context.system().batlrc()
 		.ok_or(UtilityError::ResourceDoesNotExist("BatlRc".to_string()))?;
 
+	if device {
+		let registry = context.registry();
+		let code = registry.request_device_code()?;
+
+		info(&format!("Open {} and enter the code {}", code.verification_uri, code.user_code));
+		open_url(code.url());
+
+		let tokens = registry.await_device_token(&code)?;
+		tokens.save(context.system())?;
+
+		batlrc.api.auth = Some(AuthProvider0_2_2::Device);
+
+		write_toml(&context.system().batlrc_path().expect("Nonsensical just read batlrc"), &batlrc)?;
+
+		success("Signed in with device authorization");
+
+		return Ok(());
+	}
+
+	let mut key_prompt = dialoguer::Input::new();
+
+	let api_key: String = key_prompt.with_prompt("API key").interact()?;
+
 	batlrc.api.credentials = api_key;
+	batlrc.api.auth = None;
+
+	Tokens::clear(context.system())?;
 
-	write_toml(&batl::system::batlrc_path().expect("Nonsensical just read batlrc"), &batlrc)?;
+	write_toml(&context.system().batlrc_path().expect("Nonsensical just read batlrc"), &batlrc)?;
 
 	success("Added new API key");
 
 	Ok(())
 }
+
+pub fn cmd_doctor(name: Option<String>, context: &BatlContext) -> Result<(), UtilityError> {
+	let undecodable = report_undecodable(context)?;
+
+	let repository = match name {
+		Some(val) => Some(batlres::Repository::load_in(context, val.as_str().into())?
+			.ok_or(UtilityError::ResourceDoesNotExist("Repository".to_string()))?),
+		None => batlres::Repository::locate_then_load_in(context, &current_dir()?)?
+	};
+
+	let unavailable = repository.as_ref().map_or(0, report_tools);
+
+	if let Some(repository) = &repository {
+		report_kind(repository, context);
+		warn_name_mismatch(repository, context);
+	}
+
+	if unavailable > 0 {
+		return Err(UtilityError::ToolsUnavailable(format!("{} of the required tools", unavailable)));
+	}
+
+	if undecodable > 0 {
+		return Err(UtilityError::InvalidPath(format!("{} entries with names that are not valid UTF-8", undecodable)));
+	}
+
+	Ok(())
+}
+
+/// Report resource directories whose names can't be
+/// decoded, returning how many there are
+fn report_undecodable(context: &BatlContext) -> Result<usize, UtilityError> {
+	let mut count = 0;
+
+	for kind in [Kind::Repository, Kind::Workspace] {
+		for path in Index::open(context.system(), kind)?.undecodable() {
+			count += 1;
+			error(&format!("{} is not valid UTF-8, rename it to use it with battalion", path.display()));
+		}
+	}
+
+	Ok(count)
+}
+
+/// Warn about a repository that goes against the
+/// conventions of its kind
+fn report_kind(repository: &batlres::Repository, context: &BatlContext) {
+	let config = repository.config();
+
+	if config.kind == RepositoryKind::Tool && !config.scripts.contains_key(INSTALL_SCRIPT) {
+		warning(&format!("{} is a tool without an {} script", repository.name(), INSTALL_SCRIPT));
+	}
+
+	let mut apps = config.dependencies.keys()
+		.filter_map(|name| batlres::Repository::load_in(context, name.clone()).ok().flatten())
+		.filter(|dependency| !dependency.config().kind.is_dependable())
+		.map(|dependency| dependency.name().to_string())
+		.collect::<Vec<_>>();
+	apps.sort();
+
+	for app in apps {
+		warning(&format!("{} depends on {}, which is an app", repository.name(), app));
+	}
+}
+
+/// Report the declared tools of a repository, returning
+/// how many are unavailable
+fn report_tools(repository: &batlres::Repository) -> usize {
+	let mut tools = repository.config().tools.iter().collect::<Vec<_>>();
+	tools.sort_by(|a, b| a.0.cmp(b.0));
+
+	if tools.is_empty() {
+		info(&format!("{} does not declare any tools", repository.name()));
+		return 0;
+	}
+
+	let mut unavailable = 0;
+
+	for (tool, requirement) in tools {
+		match toolchain::check(tool, requirement) {
+			ToolStatus::Satisfied(version) => success(&format!("{} {} ({})", tool, version, requirement)),
+			ToolStatus::Mismatch(version) => {
+				unavailable += 1;
+				error(&format!("{} {} does not match {}", tool, version, requirement));
+			},
+			ToolStatus::Unknown => {
+				unavailable += 1;
+				error(&format!("{} has an unknown version, requires {}", tool, requirement));
+			},
+			_ => {
+				unavailable += 1;
+				error(&format!("{} is missing, requires {}", tool, requirement));
+			}
+		}
+	}
+
+	unavailable
+}
+
+pub fn cmd_keygen(path: PathBuf) -> Result<(), UtilityError> {
+	if path.exists() {
+		return Err(UtilityError::ResourceAlreadyExists(format!("Key file {}", path.display())));
+	}
+
+	let key = PublisherKey::generate();
+	key.save(&path)?;
+
+	success(&format!("Wrote a signing key to {}", path.display()));
+	println!("Public key: {}", key.public_key());
+	info("Set signing.key_file in the batlrc to sign what you publish, and share the public key with those fetching it");
+
+	Ok(())
+}
+
+pub fn cmd_fix_name(name: Option<String>, to_declared: bool, context: &BatlContext) -> Result<(), UtilityError> {
+	let repository = match name {
+		Some(val) => batlres::Repository::load_unverified_in(context, val.as_str().into())?,
+		None => batlres::repository::TomlConfigLatest::locate(&current_dir()?)
+			.and_then(|path| path.parent().map(Path::to_path_buf))
+			.map(|path| batlres::Repository::from_path_unverified(context, &path))
+			.transpose()?
+	}.ok_or(UtilityError::ResourceDoesNotExist("Repository".to_string()))?;
+
+	let Some(declared) = repository.declared_name(context) else {
+		info(&format!("{} already declares the name it is stored under", repository.name()));
+		return Ok(());
+	};
+
+	let stored = repository.name().clone();
+
+	if to_declared {
+		let moved = repository.move_to_declared_name(context).map_err(|err| match err {
+			batlerror::CreateResourceError::AlreadyExists => UtilityError::ResourceAlreadyExists(format!("Repository {}", declared)),
+			_ => err.into()
+		})?;
+
+		success(&format!("Moved {} to {}", stored, moved.name()));
+		info("Workspaces linking the old name need to be linked again");
+	} else {
+		let mut repository = repository;
+		repository.adopt_stored_name()?;
+
+		success(&format!("Renamed {} to {} in its batl.toml", declared, stored));
+	}
+
+	Ok(())
+}
+
+pub fn cmd_scripts(name: Option<String>, context: &BatlContext) -> Result<(), UtilityError> {
+	let repository = match name {
+		Some(val) => batlres::Repository::load_in(context, val.as_str().into())?,
+		None => batlres::Repository::locate_then_load_in(context, &current_dir()?)?
+	}.ok_or(UtilityError::ResourceDoesNotExist("Repository".to_string()))?;
+
+	let user_scripts = context.system().user_scripts();
+
+	let mut scripts = repository.available_scripts(context).into_iter().collect::<Vec<_>>();
+	scripts.sort_by(|a, b| a.0.cmp(&b.0));
+
+	for (script_name, (script, source)) in scripts {
+		let source = match source {
+			ScriptSource::Repository if user_scripts.contains_key(&script_name) => "repository, overrides user",
+			ScriptSource::Repository => "repository",
+			_ => "user"
+		};
+
+		// Interpolated values may be secrets
+		let command = repository.written_command(&script_name).unwrap_or(&script.command);
+
+		println!("{} ({}): {}", script_name, source, command);
+	}
+
+	Ok(())
+}
+
+pub fn cmd_scripts_all(script: Option<String>, missing: bool, filter: Option<String>, context: &BatlContext) -> Result<(), UtilityError> {
+	let repositories = repository_paths(filter.as_deref(), context)?;
+	let paths = repositories.iter().map(|(_, path)| path.clone()).collect::<Vec<_>>();
+
+	let mut lacking = 0;
+
+	for ((name, _), defined) in repositories.iter().zip(search::scripts(context.system(), &paths)) {
+		let scripts = match defined.scripts {
+			Ok(scripts) => scripts,
+			Err(e) => {
+				warning(&format!("Skipped {}: {}", name, e));
+				continue;
+			}
+		};
+
+		let Some(wanted) = &script else {
+			println!("{}: {}", output_name(name), scripts.join(", "));
+			continue;
+		};
+
+		// Names are printed alone, to be piped into other
+		// commands
+		if scripts.contains(wanted) != missing {
+			println!("{}", name);
+			lacking += usize::from(missing);
+		}
+	}
+
+	if let Some(wanted) = script.filter(|_| lacking > 0) {
+		return Err(UtilityError::ScriptNotFound(format!("{} in {} repositories", wanted, lacking)));
+	}
+
+	Ok(())
+}
+
+pub fn cmd_docs(name: Option<String>, port: u16, context: &BatlContext) -> Result<(), UtilityError> {
+	let repository = match name {
+		Some(val) => batlres::Repository::load_in(context, val.as_str().into())?,
+		None => batlres::Repository::locate_then_load_in(context, &current_dir()?)?
+	}.ok_or(UtilityError::ResourceDoesNotExist("Repository".to_string()))?;
+
+	if let Some(script) = repository.script(context, "docs") {
+		check_tools(&repository, &script, None)?;
+
+		let invocation = Invocation::for_repository(context, &repository, &script);
+		let status = run_script(&repository, "docs", &script, invocation, None, ExecOutput::Full, context)?;
+
+		if !status.success() {
+			return Err(UtilityError::ScriptError(format!("Failed with {}", failure_reason(status))));
+		}
+
+		return Ok(());
+	}
+
+	let listener = TcpListener::bind(("127.0.0.1", port))?;
+	let url = format!("http://{}/", listener.local_addr()?);
+
+	info(&format!("Serving docs for {} at {}, press Ctrl-C to stop", repository.name(), url));
+	open_url(&url);
+
+	for stream in listener.incoming().flatten() {
+		drop(serve_docs(stream, &repository, context));
+	}
+
+	Ok(())
+}
+
+/// Answer a single request for a documentation page. The
+/// root path shows the given repository, and `/<name>`
+/// shows any other local repository.
+fn serve_docs(mut stream: TcpStream, root: &batlres::Repository, context: &BatlContext) -> std::io::Result<()> {
+	let mut reader = BufReader::new(&stream);
+
+	let mut request_line = String::new();
+	reader.read_line(&mut request_line)?;
+
+	// Drain the headers so the connection closes cleanly
+	let mut header = String::new();
+	while reader.read_line(&mut header)? > 2 {
+		header.clear();
+	}
+
+	let path = request_line.split_whitespace()
+		.nth(1)
+		.unwrap_or("/")
+		.split('?')
+		.next()
+		.unwrap_or_default()
+		.trim_matches('/');
+
+	let page = match path {
+		"" => Some(docs::render(context, root)),
+		other if BATL_NAME_REGEX.is_match(other) => batlres::Repository::load_in(context, other.into())
+			.ok()
+			.flatten()
+			.map(|repository| docs::render(context, &repository)),
+		_ => None
+	};
+
+	let (status, body) = match page {
+		Some(body) => ("200 OK", body),
+		None => ("404 Not Found", format!("<h1>No repository named {}</h1>", docs::escape(path)))
+	};
+
+	write!(
+		stream,
+		"HTTP/1.1 {}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+		status,
+		body.len(),
+		body
+	)
+}
+
+#[cfg(feature = "serve")]
+pub fn cmd_serve(root: PathBuf, bind: String, token_file: Option<PathBuf>, workers: usize) -> Result<(), UtilityError> {
+	let tokens = token_file
+		.map(|path| batl::registry::server::read_tokens(&path))
+		.transpose()?
+		.unwrap_or_default();
+
+	if tokens.is_empty() {
+		warning("No tokens given, so anyone can fetch and nothing can be published");
+	}
+
+	std::fs::create_dir_all(root.join("pkg"))?;
+
+	let server = batl::registry::server::Server::bind(root.clone(), &bind, tokens)?;
+	let address = server.address().map_or(bind, |address| address.to_string());
+
+	info(&format!("Serving the registry in {} at http://{}, press Ctrl-C to stop", root.display(), address));
+
+	server.run(workers)?;
+
+	Ok(())
+}
+
+pub fn cmd_summary(name: Option<String>, at: Option<semver::Version>, json: bool, context: &BatlContext) -> Result<(), UtilityError> {
+	let repository = match name {
+		Some(val) => batlres::Repository::load_in(context, val.as_str().into())?,
+		None => batlres::Repository::locate_then_load_in(context, &current_dir()?)?
+	}.ok_or(UtilityError::ResourceDoesNotExist("Repository".to_string()))?;
+
+	let repository = match at {
+		Some(version) => {
+			let versioned = format!("{}@{}", repository.name(), version);
+
+			batlres::Repository::load_version_in(context, repository.name().clone(), &version)?
+				.ok_or(UtilityError::ResourceDoesNotExist(format!(
+					"Repository {} (fetch it with `batl repository fetch {}`)", versioned, versioned
+				)))?
+		},
+		None => repository
+	};
+
+	let summary = repository.summarize(context);
+
+	if json {
+		let out = serde_json::to_string_pretty(&summary)
+			.map_err(|e| UtilityError::ConfigError(e.to_string()))?;
+
+		println!("{}", out);
+
+		return Ok(());
+	}
+
+	println!("Name: {}", summary.name);
+	println!("Version: {}", summary.version);
+	println!("Kind: {}", summary.kind);
+
+	if let Some(ownership) = &summary.ownership {
+		if ownership.creator == ownership.last_writer {
+			println!("Owner: {}", ownership.creator);
+		} else {
+			println!("Owner: {} (last changed by {})", ownership.creator, ownership.last_writer);
+		}
+	}
+
+	if !summary.dependencies.is_empty() {
+		println!("Dependencies:");
+		print_dependencies(&summary.dependencies, 1);
+	}
+
+	if !summary.restrictions.is_empty() {
+		println!("Restrictions:");
+
+		for restriction in &summary.restrictions {
+			println!("  {}: {}", restriction.condition, restriction.include);
+
+			let mut dependencies = restriction.dependencies.iter()
+				.map(|(dep_name, version)| format!("{} {}", dep_name, version))
+				.collect::<Vec<_>>();
+			dependencies.sort();
+
+			for dependency in dependencies {
+				println!("    {}", dependency);
+			}
+		}
+	}
+
+	Ok(())
+}
+
+pub fn cmd_licenses(name: Option<String>, detect: bool, json: bool, csv: bool, context: &BatlContext) -> Result<(), UtilityError> {
+	let repository = match name {
+		Some(val) => batlres::Repository::load_in(context, val.as_str().into())?,
+		None => batlres::Repository::locate_then_load_in(context, &current_dir()?)?
+	}.ok_or(UtilityError::ResourceDoesNotExist("Repository".to_string()))?;
+
+	let report = repository.licenses(context, detect);
+
+	if json {
+		let out = serde_json::to_string_pretty(&report)
+			.map_err(|e| UtilityError::ConfigError(e.to_string()))?;
+
+		println!("{}", out);
+
+		return Ok(());
+	}
+
+	if csv {
+		println!("name,version,license,declared,detected,status");
+
+		for entry in &report.dependencies {
+			let status = serde_json::to_value(entry.status)
+				.ok()
+				.and_then(|value| value.as_str().map(ToString::to_string))
+				.unwrap_or_default();
+
+			let fields = [
+				entry.name.to_string(),
+				entry.version.as_ref().map(ToString::to_string).unwrap_or_default(),
+				entry.license().unwrap_or_default().to_string(),
+				entry.declared.clone().unwrap_or_default(),
+				entry.detected.clone().unwrap_or_default(),
+				status
+			];
+
+			println!("{}", fields.iter().map(|field| csv_field(field)).collect::<Vec<_>>().join(","));
+		}
+
+		return Ok(());
+	}
+
+	println!("Name: {} ({})", report.name, report.license.as_deref().unwrap_or("no license declared"));
+
+	let mut counts = HashMap::new();
+
+	for entry in &report.dependencies {
+		let version = entry.version.as_ref().map(ToString::to_string).unwrap_or("-".to_string());
+		let license = entry.license().unwrap_or("unknown");
+
+		println!("  {} {} {}", output_name(&entry.name.to_string()), version, license);
+
+		if entry.status != LicenseStatus::Missing {
+			*counts.entry(license).or_insert(0) += 1;
+		}
+	}
+
+	let mut counts = counts.into_iter().collect::<Vec<_>>();
+	counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+
+	if !counts.is_empty() {
+		let totals = counts.iter()
+			.map(|(license, count)| format!("{} ({})", license, count))
+			.collect::<Vec<_>>();
+
+		info(&format!("Licenses: {}", totals.join(", ")));
+	}
+
+	for entry in &report.dependencies {
+		match entry.status {
+			LicenseStatus::Missing => warning(&format!("{} is not available locally, its license is unknown", entry.name)),
+			LicenseStatus::Unknown => warning(&format!("{} has no known license", entry.name)),
+			LicenseStatus::Mismatch => warning(&format!(
+				"{} declares {} but its license files say {}",
+				entry.name,
+				entry.declared.as_deref().unwrap_or_default(),
+				entry.detected.as_deref().unwrap_or_default()
+			)),
+			_ => {}
+		}
+	}
+
+	Ok(())
+}
+
+/// Quote a CSV field when it needs to be
+fn csv_field(field: &str) -> String {
+	if field.contains([',', '"', '\n']) {
+		format!("\"{}\"", field.replace('"', "\"\""))
+	} else {
+		field.to_string()
+	}
+}
+
+pub fn cmd_why(dependency: String, name: Option<String>, context: &BatlContext) -> Result<(), UtilityError> {
+	if !BATL_NAME_REGEX.is_match(&dependency) {
+		return Err(UtilityError::InvalidName(dependency));
+	}
+
+	let repository = match name {
+		Some(val) => batlres::Repository::load_in(context, val.as_str().into())?,
+		None => batlres::Repository::locate_then_load_in(context, &current_dir()?)?
+	}.ok_or(UtilityError::ResourceDoesNotExist("Repository".to_string()))?;
+
+	let summary = repository.summarize(context);
+	let paths = summary.paths_to(context, &dependency.as_str().into());
+
+	if paths.is_empty() {
+		return Err(UtilityError::ResourceDoesNotExist(format!("Dependency {} of {}", dependency, summary.name)));
+	}
+
+	for path in paths {
+		let steps = path.iter()
+			.map(|step| format!("{} {}", output_name(&step.name.to_string()), step.requirement))
+			.collect::<Vec<_>>();
+
+		let found = match path.last().and_then(|target| target.version.as_ref()) {
+			Some(version) => format!("found {}", version),
+			None => "missing".to_string()
+		};
+
+		println!("{} -> {} ({})", output_name(&summary.name.to_string()), steps.join(" -> "), found);
+	}
+
+	Ok(())
+}
+
+/// A dependency, or with --invert a dependent, as printed
+/// by `batl deps`
+struct DepsNode {
+	name: batlres::Name,
+	version: Option<semver::Version>,
+	requirement: String,
+	cycle: bool,
+	children: Vec<DepsNode>
+}
+
+/// The repositories depending on each repository, with
+/// the version they require and their own version
+type Dependents = HashMap<batlres::Name, Vec<(batlres::Name, String, semver::Version)>>;
+
+impl DepsNode {
+	fn from_dependency(dependency: &Dependency) -> Self {
+		Self {
+			name: dependency.name.clone(),
+			version: dependency.version.clone(),
+			requirement: dependency.requirement.clone(),
+			cycle: dependency.cycle,
+			children: dependency.dependencies.iter().map(Self::from_dependency).collect()
+		}
+	}
+}
+
+pub fn cmd_deps(name: Option<String>, invert: Option<String>, depth: Option<usize>, workspace: Option<String>, context: &BatlContext) -> Result<(), UtilityError> {
+	// Dependencies are marked as linked or not when there is
+	// a workspace to check them against
+	let workspace = match workspace {
+		Some(val) => Some(batlres::Workspace::load_in(context, val.as_str().into())?
+			.ok_or(UtilityError::ResourceDoesNotExist(format!("Workspace {}", val)))?),
+		None => batlres::Workspace::locate_then_load_in(context, &current_dir()?).ok().flatten()
+	};
+
+	let links = workspace.map(|workspace| workspace.links()
+		.into_iter()
+		.map(|(link, target)| (target, link))
+		.collect::<HashMap<_, _>>());
+
+	let (root, children) = match invert {
+		Some(val) => {
+			if !BATL_NAME_REGEX.is_match(&val) {
+				return Err(UtilityError::InvalidName(val));
+			}
+
+			let target = batlres::Name::from(val.as_str());
+			let version = batlres::Repository::load_in(context, target.clone()).ok().flatten()
+				.map(|repository| repository.config().version.clone());
+
+			let dependents = local_dependents(context)?;
+			let children = dependents_tree(&dependents, &mut vec![target.clone()]);
+
+			(describe_root(&target, version.as_ref()), children)
+		},
+		None => {
+			let repository = match name {
+				Some(val) => batlres::Repository::load_in(context, val.as_str().into())?,
+				None => batlres::Repository::locate_then_load_in(context, &current_dir()?)?
+			}.ok_or(UtilityError::ResourceDoesNotExist("Repository".to_string()))?;
+
+			let summary = repository.summarize(context);
+			let children = summary.dependencies.iter().map(DepsNode::from_dependency).collect::<Vec<_>>();
+
+			(describe_root(&summary.name, Some(&summary.version)), children)
+		}
+	};
+
+	println!("{}", root);
+	print_tree(&children, "", 1, depth, links.as_ref(), &mut HashSet::new());
+
+	Ok(())
+}
+
+/// The repositories available locally that depend on each
+/// repository
+fn local_dependents(context: &BatlContext) -> Result<Dependents, UtilityError> {
+	let mut dependents = Dependents::new();
+
+	for (name, _) in repository_paths(None, context)? {
+		let Some(repository) = batlres::Repository::load_in(context, name.as_str().into()).ok().flatten() else {
+			continue;
+		};
+
+		let config = repository.config();
+
+		for (dependency, requirement) in &config.dependencies {
+			dependents.entry(dependency.clone())
+				.or_default()
+				.push((repository.name().clone(), requirement.clone(), config.version.clone()));
+		}
+	}
+
+	Ok(dependents)
+}
+
+/// Build the tree of everything depending on the last of
+/// the ancestors
+fn dependents_tree(dependents: &Dependents, ancestors: &mut Vec<batlres::Name>) -> Vec<DepsNode> {
+	let Some(target) = ancestors.last().cloned() else {
+		return Vec::new();
+	};
+
+	let mut nodes = dependents.get(&target)
+		.into_iter()
+		.flatten()
+		.map(|(dependent, requirement, version)| {
+			let cycle = ancestors.contains(dependent);
+
+			let children = if cycle {
+				Vec::new()
+			} else {
+				ancestors.push(dependent.clone());
+				let children = dependents_tree(dependents, ancestors);
+				ancestors.pop();
+
+				children
+			};
+
+			DepsNode {
+				name: dependent.clone(),
+				version: Some(version.clone()),
+				requirement: requirement.clone(),
+				cycle,
+				children
+			}
+		})
+		.collect::<Vec<_>>();
+
+	nodes.sort_by_key(|node| node.name.to_string());
+
+	nodes
+}
+
+fn describe_root(name: &batlres::Name, version: Option<&semver::Version>) -> String {
+	match version {
+		Some(version) => format!("{} {}", output_name(&name.to_string()), version),
+		None => format!("{} missing", output_name(&name.to_string()))
+	}
+}
+
+/// Print dependencies as a tree, like `cargo tree`. A
+/// repository already shown with its dependencies is
+/// marked with (*) instead of being shown again.
+fn print_tree(nodes: &[DepsNode], prefix: &str, depth: usize, max_depth: Option<usize>, links: Option<&HashMap<batlres::Name, String>>, seen: &mut HashSet<String>) {
+	for (index, node) in nodes.iter().enumerate() {
+		let last = index + 1 == nodes.len();
+		let connector = if last { "└── " } else { "├── " };
+
+		let mut line = format!("{}{}{}", prefix, connector, describe_root(&node.name, node.version.as_ref()));
+		line.push_str(&format!(" (requires {})", node.requirement));
+
+		if let Some(links) = links {
+			match links.get(&node.name) {
+				Some(link) => line.push_str(&format!(" [linked as {}]", link)),
+				None => line.push_str(" [not linked]")
+			}
+		}
+
+		// Only subtrees that were shown in full count as seen
+		let expand = !node.cycle && !node.children.is_empty() && max_depth.is_none_or(|max| depth < max);
+		let repeated = expand
+			&& !seen.insert(format!("{}@{}", node.name, node.version.as_ref().map(|version| version.to_string()).unwrap_or_default()));
+
+		if node.cycle {
+			line.push_str(" (cycle)");
+		} else if repeated {
+			line.push_str(" (*)");
+		}
+
+		println!("{}", line);
+
+		if !expand || repeated {
+			continue;
+		}
+
+		let child_prefix = format!("{}{}", prefix, if last { "    " } else { "│   " });
+		print_tree(&node.children, &child_prefix, depth + 1, max_depth, links, seen);
+	}
+}
+
+fn print_dependencies(dependencies: &[Dependency], depth: usize) {
+	let indent = "  ".repeat(depth);
+
+	for dependency in dependencies {
+		let status = match &dependency.version {
+			_ if dependency.cycle => "cycle".to_string(),
+			Some(version) => version.to_string(),
+			None => "missing".to_string()
+		};
+
+		println!("{}{} {} (requires {})", indent, dependency.name, status, dependency.requirement);
+
+		if dependency.kind.is_some_and(|kind| !kind.is_dependable()) {
+			warning(&format!("{} is an app, which shouldn't be depended on", dependency.name));
+		}
+
+		print_dependencies(&dependency.dependencies, depth + 1);
+	}
+}
+
+pub fn cmd_grep(pattern: String, filter: Option<String>, context: &BatlContext) -> Result<(), UtilityError> {
+	let pattern = regex::Regex::new(&pattern)
+		.map_err(|e| UtilityError::InvalidPattern(e.to_string()))?;
+
+	let repositories = repository_paths(filter.as_deref(), context)?;
+	let roots = repositories.iter().map(|(_, path)| path.clone()).collect::<Vec<_>>();
+
+	for line_match in search::grep(&roots, &pattern) {
+		let Some((name, relative)) = locate(&repositories, &line_match.path) else {
+			continue;
+		};
+
+		println!("{}:{}:{}:{}", output_name(name), relative.display(), line_match.line_number, line_match.line);
+	}
+
+	Ok(())
+}
+
+pub fn cmd_find(glob: String, filter: Option<String>, json: bool, context: &BatlContext) -> Result<(), UtilityError> {
+	let glob = globset::Glob::new(&glob)
+		.map_err(|e| UtilityError::InvalidPattern(e.to_string()))?
+		.compile_matcher();
+
+	let repositories = repository_paths(filter.as_deref(), context)?;
+	let roots = repositories.iter().map(|(_, path)| path.clone()).collect::<Vec<_>>();
+
+	let found = search::find(&roots, &glob);
+
+	let located = found.iter()
+		.filter_map(|path| locate(&repositories, path).map(|(name, relative)| (name, relative, path)));
+
+	if json {
+		let entries = located
+			.map(|(name, relative, path)| serde_json::json!({
+				"repository": name,
+				"path": relative,
+				"absolute": path
+			}))
+			.collect::<Vec<_>>();
+
+		let out = serde_json::to_string_pretty(&entries)
+			.map_err(|e| UtilityError::ConfigError(e.to_string()))?;
+
+		println!("{}", out);
+
+		return Ok(());
+	}
+
+	for (name, relative, _) in located {
+		println!("{}:{}", output_name(name), relative.display());
+	}
+
+	Ok(())
+}
+
+/// Get the repository a found path belongs to, and the
+/// path relative to that repository
+fn locate<'a>(repositories: &'a [(String, PathBuf)], path: &'a Path) -> Option<(&'a str, &'a Path)> {
+	repositories.iter()
+		.filter(|(_, root)| path.starts_with(root))
+		.max_by_key(|(_, root)| root.components().count())
+		.and_then(|(name, root)| Some((name.as_str(), path.strip_prefix(root).ok()?)))
+}
+
+pub fn cmd_audit(name: Option<String>, json: bool, context: &BatlContext) -> Result<(), UtilityError> {
+	let repository = match name {
+		Some(val) => batlres::Repository::load_in(context, val.as_str().into())?,
+		None => batlres::Repository::locate_then_load_in(context, &current_dir()?)?
+	}.ok_or(UtilityError::ResourceDoesNotExist("Repository".to_string()))?;
+
+	let report = audit::audit(context, &repository)?;
+	api_notice(context.registry());
+
+	let result = match report.findings.len() {
+		0 => Ok(()),
+		1 => Err(UtilityError::AdvisoriesFound("1 advisory affects the dependencies".to_string())),
+		count => Err(UtilityError::AdvisoriesFound(format!("{} advisories affect the dependencies", count)))
+	};
+
+	if json {
+		let out = serde_json::to_string_pretty(&report)
+			.map_err(|e| UtilityError::ConfigError(e.to_string()))?;
+
+		println!("{}", out);
+
+		// Keep stdout parseable while still failing CI
+		return result.map_err(|err| UtilityError::Reported(Box::new(err)));
+	}
+
+	for finding in &report.findings {
+		let advisory = &finding.advisory;
+
+		error(&format!(
+			"{} {} {}: {} ({})",
+			output_name(&finding.name.to_string()),
+			finding.version,
+			advisory.id,
+			advisory.summary,
+			advisory.severity
+		));
+
+		match &advisory.fixed_in {
+			Some(fixed) => println!("  Fixed in {}", fixed),
+			None => println!("  No fixed version yet")
+		}
+
+		if let Some(url) = &advisory.url {
+			println!("  {}", url);
+		}
+	}
+
+	for unresolved in &report.unresolved {
+		warning(&format!("{} is not pinned to a version and was not checked", unresolved));
+	}
+
+	if result.is_ok() {
+		success(&format!("No advisories affect the {} checked dependencies of {}", report.checked.len(), report.name));
+	}
+
+	result
+}
+
+pub fn cmd_freeze(tag: String, name: Option<String>, force: bool, context: &BatlContext) -> Result<(), UtilityError> {
+	let repository = match name {
+		Some(val) => batlres::Repository::load_in(context, val.as_str().into())?,
+		None => batlres::Repository::locate_then_load_in(context, &current_dir()?)?
+	}.ok_or(UtilityError::ResourceDoesNotExist("Repository".to_string()))?;
+
+	let freeze = batlres::Freeze::new(context, &tag, &repository)?;
+	freeze.save(context, force)?;
+
+	for entry in &freeze.entries {
+		println!("  {} {}", output_name(&entry.name.to_string()), entry.version);
+	}
+
+	success(&format!("Froze {} repositories as {}", freeze.entries.len(), freeze.tag));
+
+	Ok(())
+}
+
+pub fn cmd_thaw(tag: String, mut batch: Batch, context: &BatlContext) -> Result<(), UtilityError> {
+	let freeze = batlres::Freeze::load(context, &tag)?
+		.ok_or(UtilityError::ResourceDoesNotExist(format!("Freeze {}", tag)))?;
+
+	let registry = context.registry();
+	let mut thawed = HashMap::new();
+
+	for entry in &freeze.entries {
+		if let Some(repository) = entry.locate(context)? {
+			println!("  {} {} {}", output_name(&entry.name.to_string()), entry.version, repository.path().display());
+			thawed.insert(entry.name.clone(), repository);
+			batch.record(&format!("{}@{}", entry.name, entry.version), Ok(()));
+			continue;
+		}
+
+		let outcome = thaw_entry(registry, entry, context).map(|repository| {
+			println!("  {} {} {} (fetched)", output_name(&entry.name.to_string()), entry.version, repository.path().display());
+			thawed.insert(entry.name.clone(), repository);
+		});
+
+		if !batch.record(&format!("{}@{}", entry.name, entry.version), outcome) {
+			break;
+		}
+	}
+
+	if let Some(mut workspace) = batlres::Workspace::locate_then_load_in(context, &current_dir()?)? {
+		let mut links = workspace.links().into_iter().collect::<Vec<_>>();
+		links.sort_by(|a, b| a.0.cmp(&b.0));
+
+		for (link, target) in links {
+			let Some(repository) = thawed.get(&target) else {
+				continue;
+			};
+
+			if std::fs::read_link(workspace.path().join(&link)).is_ok_and(|current| current == repository.path()) {
+				continue;
+			}
+
+			workspace.unlink(&link)?;
+			workspace.create_link(context, &link, repository)?;
+
+			info(&format!("Relinked {} to {}", link, repository.path().display()));
+		}
+	}
+
+	batch.finish()?;
+
+	success(&format!("Thawed {} repositories from {}", thawed.len(), freeze.tag));
+
+	Ok(())
+}
+
+/// Fetch the frozen version of a repository, checking it
+/// matches what was frozen
+fn thaw_entry(registry: &Registry, entry: &batlres::freeze::Entry, context: &BatlContext) -> Result<batlres::Repository, UtilityError> {
+	// A stale copy of the version would be mixed with the
+	// fetched files, so start over
+	if let Some(path) = batlres::Repository::version_path(context, &entry.name, &entry.version).filter(|path| path.exists()) {
+		std::fs::remove_dir_all(path)?;
+	}
+
+	// Frozen from a named registry, so thawed from it too
+	let named = entry.registry.as_deref()
+		.map(|registry_name| Registry::named(context.system(), registry_name))
+		.transpose()?;
+
+	fetch_repository(named.as_ref().unwrap_or(registry), &entry.name, Some(&entry.version), None, context)?;
+
+	entry.locate(context)?
+		.ok_or(UtilityError::ResourceDoesNotExist(format!("Frozen contents of {}@{}, they don't match its checksum", entry.name, entry.version)))
+}
+
+pub fn cmd_bootstrap(url: String, dry_run: bool, mut batch: Batch, context: &BatlContext) -> Result<(), UtilityError> {
+	if context.system().batl_root().is_none() {
+		return Err(UtilityError::NotSetup);
+	}
+
+	let manifest = BootstrapManifest::download(&url)?;
+
+	if dry_run {
+		for entry in &manifest.repositories {
+			let source = match (&entry.git, &entry.version) {
+				(Some(git), _) => format!("clone {}", git),
+				(None, Some(version)) => format!("fetch {}", version),
+				(None, None) => "fetch the latest version".to_string()
+			};
+
+			match &entry.registry {
+				Some(registry) => println!("  {} {} from {}", output_name(&entry.name.to_string()), source, registry),
+				None => println!("  {} {}", output_name(&entry.name.to_string()), source)
+			}
+		}
+
+		for (link, name) in &manifest.links {
+			println!("  link {} to {}", link, output_name(&name.to_string()));
+		}
+
+		info(&format!(
+			"Would set up workspace {} with {} repositories and {} links",
+			manifest.workspace, manifest.repositories.len(), manifest.links.len()
+		));
+
+		return Ok(());
+	}
+
+	let registry = context.registry();
+	let mut ready = HashMap::new();
+	let mut stopped = false;
+
+	for entry in &manifest.repositories {
+		let outcome = bootstrap_entry(registry, entry, context).map(|(repository, action)| {
+			println!("  {} {} ({})", output_name(&entry.name.to_string()), repository.path().display(), action);
+			ready.insert(entry.name.clone(), repository);
+		});
+
+		if !batch.record(&entry.name.to_string(), outcome) {
+			stopped = true;
+			break;
+		}
+	}
+
+	// A run stopped by --fail-fast leaves the workspace as it
+	// was, rather than creating it without its links
+	if stopped {
+		return batch.finish();
+	}
+
+	let mut workspace = match batlres::Workspace::load_in(context, manifest.workspace.clone()) {
+		Ok(Some(workspace)) => workspace,
+		Ok(None) | Err(batlerror::GeneralResourceError::DoesNotExist) => batlres::Workspace::create_in(context, manifest.workspace.clone())?,
+		Err(err) => return Err(err.into())
+	};
+
+	for (link, name) in &manifest.links {
+		let outcome = bootstrap_link(&mut workspace, link, name, ready.get(name), context);
+
+		if !batch.record(&format!("link {}", link), outcome) {
+			break;
+		}
+	}
+
+	batch.finish()?;
+
+	success(&format!("Bootstrapped workspace {} with {} repositories", workspace.name(), ready.len()));
+
+	Ok(())
+}
+
+/// Make a repository of a bootstrap manifest available,
+/// unless it already is. Returns the repository and what
+/// was done.
+fn bootstrap_entry(registry: &Registry, entry: &BootstrapEntry, context: &BatlContext) -> Result<(batlres::Repository, &'static str), UtilityError> {
+	let load = || match &entry.version {
+		Some(version) => batlres::Repository::load_version_in(context, entry.name.clone(), version),
+		None => batlres::Repository::load_in(context, entry.name.clone())
+	};
+
+	if entry.source() == BootstrapSource::Git {
+		let (repository, action) = match load()? {
+			Some(existing) => (existing, "already there"),
+			None => (batlres::Repository::create_in(context, entry.name.clone(), CreateRepositoryOptions::git(RepositoryGit0_2_2 {
+				url: entry.git.clone().unwrap_or_default(),
+				path: "git".to_string(),
+				depth: None,
+				branch: entry.branch.clone(),
+				sparse: None
+			}))?, "cloned")
+		};
+
+		// A clone that failed before is tried again
+		let cloned = repository.config().git.as_ref()
+			.is_none_or(|git| repository.path().join(&git.path).exists());
+
+		if !cloned {
+			repository::scaffold(&repository, None, None)?;
+
+			return Ok((repository, "cloned"));
+		}
+
+		return Ok((repository, action));
+	}
+
+	if let Some(existing) = load()? {
+		return Ok((existing, "already there"));
+	}
+
+	let named = entry.registry.as_deref()
+		.map(|registry_name| Registry::named(context.system(), registry_name))
+		.transpose()?;
+
+	fetch_repository(named.as_ref().unwrap_or(registry), &entry.name, entry.version.as_ref(), Some(OnConflict::Skip), context)?;
+
+	let fetched = load()?
+		.ok_or(UtilityError::ResourceNotCollected(format!("Repository {}", entry.name)))?;
+
+	Ok((fetched, "fetched"))
+}
+
+/// Link a repository into a bootstrapped workspace, using
+/// the local copy when the manifest doesn't list it
+fn bootstrap_link(workspace: &mut batlres::Workspace, link: &str, name: &batlres::Name, ready: Option<&batlres::Repository>, context: &BatlContext) -> Result<(), UtilityError> {
+	match workspace.links().get(link) {
+		Some(existing) if existing == name => return Ok(()),
+		Some(existing) => return Err(UtilityError::ResourceAlreadyExists(format!("Link {} to {}", link, existing))),
+		None => {}
+	}
+
+	let local = match ready {
+		Some(_) => None,
+		None => Some(batlres::Repository::load_in(context, name.clone())?
+			.ok_or(UtilityError::ResourceDoesNotExist(format!("Repository {}", name)))?)
+	};
+
+	let repository = ready.or(local.as_ref())
+		.ok_or(UtilityError::ResourceDoesNotExist(format!("Repository {}", name)))?;
+
+	workspace.create_link(context, link, repository)?;
+
+	Ok(())
+}
+
+pub fn cmd_schema(write: bool, context: &BatlContext) -> Result<(), UtilityError> {
+	let schema = serde_json::to_string_pretty(&schema::batl_toml())
+		.map_err(|e| UtilityError::ConfigError(e.to_string()))?;
+
+	if !write {
+		println!("{}", schema);
+
+		return Ok(());
+	}
+
+	let batl_root = context.system().batl_root()
+		.ok_or(UtilityError::NotSetup)?;
+	let schema_path = context.system().schema_path()
+		.ok_or(UtilityError::NotSetup)?;
+
+	if let Some(parent) = schema_path.parent() {
+		std::fs::create_dir_all(parent)?;
+	}
+
+	std::fs::write(&schema_path, schema)?;
+	success(&format!("Wrote schema to {}", schema_path.display()));
+
+	// Taplo, and editors built on it, pick up schemas for
+	// every batl.toml under the root from here
+	let taplo_path = batl_root.join(".taplo.toml");
+
+	if taplo_path.exists() {
+		info(&format!("{} already exists, leaving it alone", taplo_path.display()));
+	} else {
+		std::fs::write(&taplo_path, format!(
+			"[[rule]]\ninclude = [\"**/batl.toml\"]\n\n[rule.schema]\npath = \"file://{}\"\n",
+			schema_path.display()
+		))?;
+
+		success(&format!("Wrote {}", taplo_path.display()));
+	}
+
+	info(&format!("Other editors can use the directive #:schema file://{}", schema_path.display()));
+
+	Ok(())
+}
+
+pub fn cmd_check(path: Option<PathBuf>) -> Result<(), UtilityError> {
+	let config_path = match path {
+		Some(val) if val.is_dir() => val.join("batl.toml"),
+		Some(val) => val,
+		None => batlres::repository::AnyTomlConfig::locate_possible(&current_dir()?)
+			.map(|dir| dir.join("batl.toml"))
+			.ok_or(UtilityError::ResourceDoesNotExist("batl.toml".to_string()))?
+	};
+
+	let config_str = std::fs::read_to_string(&config_path)?;
+	let problems = schema::check(&config_str)?;
+
+	if problems.is_empty() {
+		success(&format!("{} matches the schema", config_path.display()));
+
+		return Ok(());
+	}
+
+	for problem in &problems {
+		let location = if problem.path.is_empty() { "/" } else { problem.path.as_str() };
+
+		error(&format!("{}: {}", location, problem.message));
+	}
+
+	Err(UtilityError::ConfigError(match problems.len() {
+		1 => format!("{} has 1 problem", config_path.display()),
+		count => format!("{} has {} problems", config_path.display(), count)
+	}))
+}
+
+pub fn cmd_diff_links(name: Option<String>, fix: bool, prune: bool, yes: bool, context: &BatlContext) -> Result<(), UtilityError> {
+	let mut workspace = match name {
+		Some(val) => batlres::Workspace::load_in(context, val.as_str().into())?,
+		None => batlres::Workspace::locate_then_load_in(context, &current_dir()?)?
+	}.ok_or(UtilityError::ResourceDoesNotExist("Workspace".to_string()))?;
+
+	if prune {
+		prune_links(&mut workspace, yes)?;
+	}
+
+	let diffs = workspace.link_drift(context)?;
+
+	if diffs.is_empty() {
+		success(&format!("The links of {} match its config", workspace.name()));
+		return Ok(());
+	}
+
+	let mut remaining = 0;
+
+	for diff in &diffs {
+		let repository = diff.repository.as_ref().map(|name| name.to_string()).unwrap_or_default();
+
+		let (label, detail) = match &diff.drift {
+			LinkDrift::Missing => ("missing", format!("-> {}", repository)),
+			LinkDrift::Extra(target) => ("extra", format!("-> {}", target.display())),
+			LinkDrift::Elsewhere(target) => ("elsewhere", format!("-> {}, expected {}", target.display(), repository)),
+			LinkDrift::Unresolved => ("unresolved", format!("-> {}, which isn't available locally", repository)),
+			LinkDrift::Unmet { requirement, found } => ("unmet", format!("-> {} {}, which doesn't meet {}", repository, found, requirement)),
+			_ => ("drifted", format!("-> {}", repository))
+		};
+
+		println!("  {:<10} {} {}", label, diff.link, detail);
+
+		if !fix {
+			remaining += 1;
+			continue;
+		}
+
+		let fixed = match diff.drift {
+			LinkDrift::Missing | LinkDrift::Elsewhere(_) => workspace.repair_link(context, &diff.link).map(|()| true),
+			LinkDrift::Extra(_) => workspace.remove_extra_link(&diff.link).map(|()| true),
+			_ => Ok(false)
+		};
+
+		match fixed {
+			Ok(true) => info(&format!("Fixed {}", diff.link)),
+			Ok(false) => remaining += 1,
+			Err(err) => {
+				error(&format!("Could not fix {}: {}", diff.link, err));
+				remaining += 1;
+			}
+		}
+	}
+
+	if remaining > 0 {
+		return Err(UtilityError::ConfigError(format!("{} of the links of {} drifted{}", remaining, workspace.name(), if fix { " and could not be fixed" } else { ", fix them with --fix" })));
+	}
+
+	success(&format!("Fixed the links of {}", workspace.name()));
+
+	Ok(())
+}
+
+/// Remove the links to repositories the workspace no
+/// longer depends on, asking first unless `yes` is given
+fn prune_links(workspace: &mut batlres::Workspace, yes: bool) -> Result<(), UtilityError> {
+	let obsolete = workspace.obsolete_links();
+
+	if obsolete.is_empty() {
+		return Ok(());
+	}
+
+	println!("Links of {} to repositories it no longer depends on:", workspace.name());
+
+	for (link, repository) in &obsolete {
+		println!("  {:<10} {} -> {}", "obsolete", link, repository);
+	}
+
+	if !yes {
+		if !console::Term::stdout().is_term() {
+			return Err(UtilityError::ConfigError(format!("{} links would be pruned, confirm with --yes", obsolete.len())));
+		}
+
+		let confirmed = dialoguer::Confirm::new()
+			.with_prompt(format!("Remove {} links?", obsolete.len()))
+			.default(false)
+			.interact()?;
+
+		if !confirmed {
+			info("Kept the obsolete links");
+			return Ok(());
+		}
+	}
+
+	for (link, _) in &obsolete {
+		workspace.unlink(link)?;
+		info(&format!("Removed {}", link));
+	}
+
+	Ok(())
+}
+
+pub fn cmd_graph(name: Option<String>, workspace: Option<String>, format: GraphFormat, script: Option<String>, context: &BatlContext) -> Result<(), UtilityError> {
+	let graph = match (name, workspace) {
+		(Some(val), _) => {
+			let repository = batlres::Repository::load_in(context, val.as_str().into())?
+				.ok_or(UtilityError::ResourceDoesNotExist(format!("Repository {}", val)))?;
+
+			Graph::of_repository(context, &repository)
+		},
+		(None, Some(val)) => {
+			let workspace = batlres::Workspace::load_in(context, val.as_str().into())?
+				.ok_or(UtilityError::ResourceDoesNotExist(format!("Workspace {}", val)))?;
+
+			Graph::of_workspace(context, &workspace)
+		},
+		// A repository config doesn't read as a workspace, so
+		// the closest config decides which is graphed
+		(None, None) => match batlres::Workspace::locate_then_load_in(context, &current_dir()?).ok().flatten() {
+			Some(workspace) => Graph::of_workspace(context, &workspace),
+			None => {
+				let repository = batlres::Repository::locate_then_load_in(context, &current_dir()?)?
+					.ok_or(UtilityError::ResourceDoesNotExist("Workspace or repository".to_string()))?;
+
+				Graph::of_repository(context, &repository)
+			}
+		}
+	};
+
+	match format {
+		GraphFormat::Dot => println!("{}", graph.to_dot()),
+		GraphFormat::Mermaid => println!("{}", graph.to_mermaid()),
+		GraphFormat::Html => println!("{}", graph.to_html()),
+		GraphFormat::Json => println!("{}", graph.to_json()),
+		GraphFormat::Ninja => {
+			let script = script.ok_or(UtilityError::ScriptNotFound("no --script given for ninja".to_string()))?;
+
+			let mut scripted = HashMap::new();
+
+			for node in &graph.nodes {
+				if let Some(repository) = batlres::Repository::load_in(context, node.name.clone())? {
+					if repository.script(context, &script).is_some() {
+						scripted.insert(node.name.clone(), repository.path().to_path_buf());
+					}
+				}
+			}
+
+			if scripted.is_empty() {
+				return Err(UtilityError::ScriptNotFound(script));
+			}
+
+			println!("{}", graph.to_ninja(&script, &scripted));
+		}
+	}
+
+	Ok(())
+}
+
+pub fn cmd_clean(name: Option<String>, dry_run: bool, force: bool, context: &BatlContext) -> Result<(), UtilityError> {
+	let repository = match name {
+		Some(val) => batlres::Repository::load_in(context, val.as_str().into())?,
+		None => batlres::Repository::locate_then_load_in(context, &current_dir()?)?
+	}.ok_or(UtilityError::ResourceDoesNotExist("Repository".to_string()))?;
+
+	let gen_path = repository.gen_path();
+
+	if !gen_path.exists() {
+		success(&format!("{} has no generated files", repository.name()));
+		return Ok(());
+	}
+
+	// Files checked into git under gen would be lost for
+	// good, so they are only removed when asked to
+	let tracked = git2::Repository::open(repository.path())
+		.and_then(|git| git.index())
+		.map(|index| index.iter()
+			.filter_map(|entry| String::from_utf8(entry.path).ok())
+			.filter(|path| path.starts_with("gen/"))
+			.collect::<Vec<_>>())
+		.unwrap_or_default();
+
+	if !tracked.is_empty() && !force {
+		return Err(UtilityError::ResourceNotCollected(format!(
+			"git tracks {} files in {}, such as {}. Clean with --force to remove them anyway",
+			tracked.len(), gen_path.display(), tracked[0]
+		)));
+	}
+
+	if dry_run {
+		let mut entries = std::fs::read_dir(&gen_path)?
+			.map(|entry| entry.map(|found| found.path()))
+			.collect::<Result<Vec<_>, _>>()?;
+
+		entries.sort();
+
+		for path in entries {
+			println!("  would remove {}", path.display());
+		}
+
+		return Ok(());
+	}
+
+	let removed = repository.clean()?;
+
+	for path in &removed {
+		println!("  removed {}", path.display());
+	}
+
+	success(&format!("Cleaned {} ({} entries)", repository.name(), removed.len()));
+
+	Ok(())
+}
+
+pub fn cmd_clean_all(categories: Vec<CleanCategory>, older_than: Option<batl::units::Duration>, dry_run: bool, context: &BatlContext) -> Result<(), UtilityError> {
+	let system = context.system();
+	let categories = if categories.is_empty() { CleanCategory::ALL.to_vec() } else { categories };
+
+	let repositories = repository_paths(None, context)?
+		.into_iter()
+		.map(|(_, path)| path)
+		.collect::<Vec<_>>();
+
+	let entries = clean::collect(system, &repositories, &categories, older_than.map(|age| age.get()))?;
+
+	if entries.is_empty() {
+		success("Nothing to clean");
+		return Ok(());
+	}
+
+	// Sizes are reported before anything is removed, so a
+	// dry run shows the same summary
+	for category in &categories {
+		let (count, size) = entries.iter()
+			.filter(|entry| entry.category == *category)
+			.fold((0, 0_u64), |(count, size), entry| (count + 1, size + entry.size));
+
+		if count > 0 {
+			println!("  {:<14}{:>8}B in {count} files", category.to_string(), ByteSize(size).to_string());
+		}
+	}
+
+	let total = entries.iter().map(|entry| entry.size).sum::<u64>();
+
+	if dry_run {
+		info(&format!("Would free {}B", ByteSize(total)));
+		return Ok(());
+	}
+
+	let freed = clean::remove(system, &repositories, &entries)?;
+
+	success(&format!("Freed {}B", ByteSize(freed)));
+
+	Ok(())
+}
+
+pub fn cmd_resume(id: Option<String>, discard: bool, context: &BatlContext) -> Result<(), UtilityError> {
+	let root = context.system().run_state_root()
+		.ok_or(UtilityError::NotSetup)?;
+
+	let Some(id) = id else {
+		let runs = RunState::list(&root)?;
+
+		if runs.is_empty() {
+			info("No runs to resume");
+			return Ok(());
+		}
+
+		for run in runs {
+			println!("  {}  batl {}  ({} done)", run.id, run.args.join(" "), run.completed.len());
+		}
+
+		return Ok(());
+	};
+
+	let state = match RunState::load(&root, &id) {
+		Ok(state) => state,
+		Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+			return Err(UtilityError::ResourceDoesNotExist(format!("Run {}", id)));
+		},
+		Err(err) => return Err(err.into())
+	};
+
+	if discard {
+		state.remove(&root)?;
+		success(&format!("Discarded run {}", id));
+
+		return Ok(());
+	}
+
+	info(&format!("Resuming batl {}, {} already went through", state.args.join(" "), state.completed.len()));
+
+	if !state.cwd.is_dir() {
+		return Err(UtilityError::ResourceDoesNotExist(format!("Folder {} the run started in", state.cwd.display())));
+	}
+
+	// The run picks up where it left off from the folder
+	// and root it started in, wherever it is resumed from
+	let status = std::process::Command::new(std::env::current_exe()?)
+		.args(&state.args)
+		.current_dir(&state.cwd)
+		.envs(context.system().env())
+		.env("BATL_ROOT", &state.root)
+		.env(RESUME_VAR, &id)
+		.status()?;
+
+	if !status.success() {
+		return Err(UtilityError::Exited(status_code(status)));
+	}
+
+	Ok(())
+}
+
+pub fn cmd_run(name: Option<String>, with: Vec<batlres::VersionedName>, args: Vec<String>, context: &BatlContext) -> Result<(), UtilityError> {
+	let repository = match name {
+		Some(val) => batlres::Repository::load_in(context, val.as_str().into())?,
+		None => batlres::Repository::locate_then_load_in(context, &current_dir()?)?
+	}.ok_or(UtilityError::ResourceDoesNotExist("Repository".to_string()))?;
+
+	let (program, program_args) = args.split_first()
+		.ok_or(UtilityError::ScriptNotFound("no command given".to_string()))?;
+
+	// The command runs as given rather than through a shell,
+	// with the same variables and dependencies as a script.
+	// It is quoted for the configured interpreter only to be
+	// recorded in a form that can be run again.
+	let interpreter = batl::executor::Interpreter::configured(context.system());
+	let command = args.iter().map(|arg| interpreter.quote(arg)).collect::<Vec<_>>().join(" ");
+
+	let mut invocation = Invocation::for_repository(context, &repository, &batlres::Script::new(command.clone()));
+	apply_overrides(&mut invocation, &repository, with, context)?;
+
+	std::fs::create_dir_all(batl::executor::out_path(repository.path()))?;
+
+	let result = std::process::Command::new(program)
+		.current_dir(repository.path())
+		.envs(invocation.environment(repository.path(), |mount| mount.path.clone()))
+		.args(program_args)
+		.status();
+
+	drop(batl::auditlog::record(context.system(), &batl::auditlog::Entry::new(
+		repository.name().clone(),
+		repository.path().to_path_buf(),
+		"run".to_string(),
+		command,
+		result.as_ref().ok().and_then(std::process::ExitStatus::code),
+		result.as_ref().is_ok_and(std::process::ExitStatus::success)
+	)));
+
+	let status = result?;
+
+	// The command stands in for the script, so its exit code
+	// is passed through as is
+	if !status.success() {
+		return Err(UtilityError::Exited(status_code(status)));
+	}
+
+	Ok(())
+}
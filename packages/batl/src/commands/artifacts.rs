@@ -0,0 +1,95 @@
+use batl::context::BatlContext;
+use batl::resource::{Repository, Resource};
+use batl::units::ByteSize;
+use clap::Subcommand;
+use crate::output::*;
+use crate::utils::{UtilityError, BATL_NAME_REGEX};
+use std::env::current_dir;
+use std::path::{Path, PathBuf};
+
+
+#[derive(Subcommand)]
+pub enum Commands {
+	Ls {
+		#[arg(short = 'n')]
+		name: Option<String>
+	},
+	Clean {
+		#[arg(short = 'n')]
+		name: Option<String>
+	}
+}
+
+pub fn run(cmd: Commands, context: &BatlContext) -> Result<(), UtilityError> {
+	match cmd {
+		Commands::Ls { name } => {
+			cmd_ls(name, context)
+		},
+		Commands::Clean { name } => {
+			cmd_clean(name, context)
+		}
+	}
+}
+
+fn load_repository(name: Option<String>, context: &BatlContext) -> Result<Repository, UtilityError> {
+	match name {
+		Some(val) => {
+			if !BATL_NAME_REGEX.is_match(&val) {
+				return Err(UtilityError::InvalidName(val));
+			}
+
+			Repository::load_in(context, val.into())?
+		},
+		None => Repository::locate_then_load_in(context, &current_dir()?)?
+	}.ok_or(UtilityError::ResourceDoesNotExist("Repository".to_string()))
+}
+
+fn cmd_ls(name: Option<String>, context: &BatlContext) -> Result<(), UtilityError> {
+	let repository = load_repository(name, context)?;
+	let out_path = repository.out_path();
+
+	if !out_path.exists() {
+		info("No artifacts");
+		return Ok(());
+	}
+
+	let mut artifacts = Vec::new();
+	collect_files(&out_path, &mut artifacts)?;
+	artifacts.sort();
+
+	for artifact in artifacts {
+		let size = std::fs::metadata(&artifact)?.len();
+		let relative = artifact.strip_prefix(&out_path).unwrap_or(&artifact);
+
+		println!("{:>8}  {}", ByteSize(size).to_string(), relative.display());
+	}
+
+	Ok(())
+}
+
+fn cmd_clean(name: Option<String>, context: &BatlContext) -> Result<(), UtilityError> {
+	let repository = load_repository(name, context)?;
+	let out_path = repository.out_path();
+
+	if out_path.exists() {
+		std::fs::remove_dir_all(&out_path)?;
+	}
+
+	success(&format!("Cleaned artifacts for {}", repository.name()));
+
+	Ok(())
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), UtilityError> {
+	for entry in std::fs::read_dir(dir)? {
+		let path = entry?.path();
+
+		if path.is_dir() {
+			collect_files(&path, files)?;
+		} else {
+			files.push(path);
+		}
+	}
+
+	Ok(())
+}
@@ -0,0 +1,123 @@
+use batl::context::BatlContext;
+use batl::auditlog::{self, Entry, Query};
+use batl::resource::Name;
+use batl::units::Duration;
+use clap::Subcommand;
+use crate::output::*;
+use crate::utils::{UtilityError, BATL_NAME_REGEX};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+
+#[derive(Subcommand)]
+pub enum Commands {
+	/// Show the latest script runs
+	Tail {
+		/// How many runs to show
+		#[arg(long = "count", default_value_t = 20)]
+		count: usize,
+		#[arg(long = "json")]
+		json: bool
+	},
+	/// Show the script runs matching every filter given
+	Query {
+		#[arg(long = "user")]
+		user: Option<String>,
+		/// A repository
+		#[arg(short = 'n')]
+		name: Option<String>,
+		#[arg(long = "script")]
+		script: Option<String>,
+		/// Only runs this recent, such as `1d`
+		#[arg(long = "since")]
+		since: Option<Duration>,
+		/// Only runs that failed
+		#[arg(long = "failed")]
+		failed: bool,
+		#[arg(long = "json")]
+		json: bool
+	}
+}
+
+pub fn run(cmd: Commands, context: &BatlContext) -> Result<(), UtilityError> {
+	match cmd {
+		Commands::Tail { count, json } => {
+			cmd_tail(count, json, context)
+		},
+		Commands::Query { user, name, script, since, failed, json } => {
+			cmd_query(user, name, script, since, failed, json, context)
+		}
+	}
+}
+
+fn cmd_tail(count: usize, json: bool, context: &BatlContext) -> Result<(), UtilityError> {
+	let entries = read_entries(context)?;
+	let skip = entries.len().saturating_sub(count);
+
+	print_entries(&entries[skip..], json)
+}
+
+fn cmd_query(user: Option<String>, name: Option<String>, script: Option<String>, since: Option<Duration>, failed: bool, json: bool, context: &BatlContext) -> Result<(), UtilityError> {
+	if let Some(val) = name.as_ref().filter(|val| !BATL_NAME_REGEX.is_match(val)) {
+		return Err(UtilityError::InvalidName(val.clone()));
+	}
+
+	let mut query = Query::default();
+	query.user = user;
+	query.repository = name.map(|val| Name::from(val.as_str()));
+	query.script = script;
+	query.since = since
+		.and_then(|age| SystemTime::now().checked_sub(age.get()))
+		.and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+		.map(|elapsed| elapsed.as_secs());
+	query.failed = failed;
+
+	let entries = read_entries(context)?
+		.into_iter()
+		.filter(|entry| query.matches(entry))
+		.collect::<Vec<_>>();
+
+	print_entries(&entries, json)
+}
+
+/// Read the audit log, noting when it isn't being kept
+fn read_entries(context: &BatlContext) -> Result<Vec<Entry>, UtilityError> {
+	let system = context.system();
+
+	if !auditlog::enabled(system) {
+		info("The audit log is off, enable it with `[audit_log] enabled = true` in the batlrc");
+	}
+
+	Ok(auditlog::entries(system)?)
+}
+
+fn print_entries(entries: &[Entry], json: bool) -> Result<(), UtilityError> {
+	if json {
+		for entry in entries {
+			println!("{}", serde_json::to_string(entry).map_err(|e| UtilityError::ConfigError(e.to_string()))?);
+		}
+
+		return Ok(());
+	}
+
+	if entries.is_empty() {
+		info("No script runs recorded");
+		return Ok(());
+	}
+
+	for entry in entries {
+		let time = humantime::format_rfc3339_seconds(UNIX_EPOCH + std::time::Duration::from_secs(entry.time));
+		let who = match &entry.host {
+			Some(host) => format!("{}@{}", entry.user, host),
+			None => entry.user.clone()
+		};
+		let status = match (entry.success, entry.code) {
+			(true, _) => "ok".to_string(),
+			(false, Some(code)) => format!("failed ({})", code),
+			(false, None) => "failed".to_string()
+		};
+
+		println!("{}  {}  {}  {}  {}", time, who, entry.repository, entry.script, status);
+	}
+
+	Ok(())
+}
@@ -0,0 +1,50 @@
+use batl::context::BatlContext;
+use batl::registry::auth::Scope;
+use batl::units::Duration;
+use clap::Subcommand;
+use crate::output::*;
+use crate::utils::UtilityError;
+use std::time::UNIX_EPOCH;
+
+
+#[derive(Subcommand)]
+pub enum Commands {
+	#[command(subcommand)]
+	Token(TokenCommands)
+}
+
+#[derive(Subcommand)]
+pub enum TokenCommands {
+	/// Create a token limited to some scopes, such as a
+	/// fetch only token for CI. Use it through BATL_TOKEN.
+	Create {
+		#[arg(long = "scope", required = true)]
+		scope: Vec<Scope>,
+		#[arg(long = "expires")]
+		expires: Option<Duration>
+	}
+}
+
+pub fn run(cmd: Commands, context: &BatlContext) -> Result<(), UtilityError> {
+	match cmd {
+		Commands::Token(TokenCommands::Create { scope, expires }) => {
+			cmd_token_create(scope, expires, context)
+		}
+	}
+}
+
+fn cmd_token_create(scopes: Vec<Scope>, expires: Option<Duration>, context: &BatlContext) -> Result<(), UtilityError> {
+	let created = context.registry().create_token(&scopes, expires.map(|duration| duration.0))?;
+
+	let scope_list: Vec<String> = created.scopes.iter().map(|scope| scope.to_string()).collect();
+
+	let expiry = match created.expires_at {
+		Some(secs) => format!("expires {}", humantime::format_rfc3339_seconds(UNIX_EPOCH + std::time::Duration::from_secs(secs))),
+		None => "does not expire".to_string()
+	};
+
+	success(&format!("Created token with scopes {}, {}", scope_list.join(", "), expiry));
+	println!("{}", created.token);
+
+	Ok(())
+}
@@ -0,0 +1,113 @@
+use batl::context::BatlContext;
+use batl::crash::Report;
+use clap::Subcommand;
+use crate::output::*;
+use crate::utils::UtilityError;
+
+
+#[derive(Subcommand)]
+pub enum Commands {
+	/// List the diagnostic bundles written when batl crashed
+	Ls,
+	/// Show a diagnostic bundle
+	Show {
+		/// The time naming the bundle, as listed by `crash ls`
+		stamp: String
+	}
+}
+
+pub fn run(cmd: Commands, context: &BatlContext) -> Result<(), UtilityError> {
+	match cmd {
+		Commands::Ls => {
+			cmd_ls(context)
+		},
+		Commands::Show { stamp } => {
+			cmd_show(stamp, context)
+		}
+	}
+}
+
+fn cmd_ls(context: &BatlContext) -> Result<(), UtilityError> {
+	let crash_root = context.system().crash_root().ok_or(UtilityError::NotSetup)?;
+	let stamps = Report::list(&crash_root)?;
+
+	if stamps.is_empty() {
+		info("No crashes recorded");
+
+		return Ok(());
+	}
+
+	for stamp in stamps {
+		match Report::load(&crash_root, &stamp) {
+			Ok(report) => println!("{}  {}  {}", stamp, kind_label(&report), first_line(&report.message)),
+			Err(_) => println!("{}  (unreadable)", stamp)
+		}
+	}
+
+	Ok(())
+}
+
+fn cmd_show(stamp: String, context: &BatlContext) -> Result<(), UtilityError> {
+	// Stamps become folder names, so they can't reach out of
+	// the crash root
+	if stamp.is_empty() || !stamp.chars().all(|character| character.is_ascii_alphanumeric() || character == '-') {
+		return Err(UtilityError::ResourceDoesNotExist(format!("Crash {}", stamp)));
+	}
+
+	let crash_root = context.system().crash_root().ok_or(UtilityError::NotSetup)?;
+
+	let report = Report::load(&crash_root, &stamp).map_err(|err| match err.kind() {
+		std::io::ErrorKind::NotFound => UtilityError::ResourceDoesNotExist(format!("Crash {}", stamp)),
+		_ => err.into()
+	})?;
+
+	println!("Crash:           {} ({})", report.stamp, kind_label(&report));
+	println!("Message:         {}", report.message);
+
+	if let Some(location) = &report.location {
+		println!("Location:        {}", location);
+	}
+
+	println!("Command:         batl {}", report.args.join(" "));
+	println!("batl version:    {}", report.batl_version);
+	println!("Config version:  {}", report.config_version);
+
+	if let Some(local) = &report.local_config_version {
+		println!("batl.toml:       {}", local);
+	}
+
+	println!("OS:              {}", report.os);
+	println!("Bundle:          {}", crash_root.join(&report.stamp).display());
+
+	if !report.env.is_empty() {
+		println!("\nEnvironment:");
+
+		let mut env = report.env.iter().collect::<Vec<_>>();
+		env.sort();
+
+		for (key, value) in env {
+			println!("  {}={}", key, value);
+		}
+	}
+
+	if !report.log_tail.is_empty() {
+		println!("\nRecent runs:");
+
+		for line in &report.log_tail {
+			println!("  {}", line);
+		}
+	}
+
+	Ok(())
+}
+
+fn kind_label(report: &Report) -> &'static str {
+	match report.kind {
+		batl::crash::Kind::Panic => "panic",
+		_ => "error"
+	}
+}
+
+fn first_line(message: &str) -> &str {
+	message.lines().next().unwrap_or_default()
+}
@@ -1,21 +1,34 @@
-use batl::resource::{Repository, Resource, Workspace};
+use batl::context::BatlContext;
+use batl::resource::{Repository, Resource, VersionedName, Workspace};
+use batl::units::Duration;
+use batl::usage;
 use clap::{Subcommand, ValueEnum};
-use crate::utils::{UtilityError, BATL_LINK_REGEX, BATL_NAME_REGEX};
+use crate::utils::{failure_reason, run_chain, ExecOutput, pick_repository, repository_candidates, UtilityError, BATL_LINK_REGEX, BATL_NAME_REGEX};
 use crate::output::*;
+use crate::output::name as output_name;
+use std::collections::HashMap;
 use std::env::current_dir;
 
 #[derive(Subcommand)]
 pub enum Commands {
 	Ls,
 	Stats {
-		#[arg(long = "get")]
+		#[arg(long = "get", requires = "name")]
 		get: Option<StatsGet>,
-		name: String
+		/// Show a single link, instead of how recently every
+		/// link was used
+		name: Option<String>,
+		/// Suggest removing links unused for this long
+		#[arg(long = "unused-for", default_value = "90d")]
+		unused_for: Duration
 	},
 	Init {
 		#[arg(short = 'n', long = "name")]
 		name: Option<String>,
-		repo: String
+		#[arg(required_unless_present = "interactive")]
+		repo: Option<String>,
+		#[arg(short = 'i', long = "interactive")]
+		interactive: bool
 	},
 	Delete {
 		name: String
@@ -28,41 +41,56 @@ pub enum Commands {
 	Exec {
 		#[arg(short = 'n')]
 		name: Option<String>,
+		#[arg(long = "on")]
+		on: Option<String>,
+		#[arg(long = "with")]
+		with: Vec<VersionedName>,
+		#[arg(short = 'q', long = "quiet")]
+		quiet: bool,
+		#[arg(long = "summary", conflicts_with = "quiet")]
+		summary: bool,
 		script: String
 	}
 }
 
-pub fn run(cmd: Commands) -> Result<(), UtilityError> {
+pub fn run(cmd: Commands, context: &BatlContext) -> Result<(), UtilityError> {
 	match cmd {
 		Commands::Ls => {
-			cmd_ls()
+			cmd_ls(context)
+		},
+		Commands::Stats { name: Some(name), get, .. } => {
+			cmd_stats(name, get, context)
 		},
-		Commands::Stats { name, get } => {
-			cmd_stats(name, get)
+		Commands::Stats { name: None, unused_for, .. } => {
+			cmd_usage(unused_for, context)
 		},
-		Commands::Init { name, repo } => {
-			cmd_init(name, repo)
+		Commands::Init { name, repo, interactive } => {
+			cmd_init(name, repo, interactive, context)
 		},
 		Commands::Delete { name } => {
-			cmd_delete(name)
+			cmd_delete(name, context)
 		},
 		Commands::Run { name, args } => {
-			cmd_run(name, args)
+			cmd_run(name, args, context)
 		},
-		Commands::Exec { name, script } => {
-			cmd_exec(name, script)
+		Commands::Exec { name, on, with, quiet, summary, script } => {
+			cmd_exec(name, on, with, ExecOutput::from_flags(quiet, summary), script, context)
 		}
 	}
 }
 
-fn cmd_ls() -> Result<(), UtilityError> {
-	let workspace = Workspace::locate_then_load(&current_dir()?)?
+fn cmd_ls(context: &BatlContext) -> Result<(), UtilityError> {
+	let workspace = Workspace::locate_then_load_in(context, &current_dir()?)?
 		.ok_or(UtilityError::ResourceDoesNotExist("Workspace".to_string()))?;
 
 	let links = workspace.links();
+	let pins = workspace.pins();
 
 	for link in links {
-		println!("{}:\t\t{}", link.0, link.1);
+		match pins.get(&link.0) {
+			Some(version) => println!("{}:\t\t{}@{}", link.0, link.1, version),
+			None => println!("{}:\t\t{}", link.0, link.1)
+		}
 	}
 
 	Ok(())
@@ -75,11 +103,11 @@ pub enum StatsGet {
 	Repository
 }
 
-fn cmd_stats(name: String, get: Option<StatsGet>) -> Result<(), UtilityError> {
-	let workspace = Workspace::locate_then_load(&current_dir()?)?
+fn cmd_stats(name: String, get: Option<StatsGet>, context: &BatlContext) -> Result<(), UtilityError> {
+	let workspace = Workspace::locate_then_load_in(context, &current_dir()?)?
 		.ok_or(UtilityError::ResourceDoesNotExist("Workspace".to_string()))?;
 
-	let repository = workspace.link(&name)
+	let repository = workspace.link(context, &name)
 		.ok_or(UtilityError::LinkNotFound)?;
 	
 	let path = repository.path();
@@ -96,33 +124,112 @@ fn cmd_stats(name: String, get: Option<StatsGet>) -> Result<(), UtilityError> {
 	Ok(())
 }
 
-fn cmd_init(name: Option<String>, repo: String) -> Result<(), UtilityError> {
+fn cmd_usage(unused_for: Duration, context: &BatlContext) -> Result<(), UtilityError> {
+	let workspace = Workspace::locate_then_load_in(context, &current_dir()?)?
+		.ok_or(UtilityError::ResourceDoesNotExist("Workspace".to_string()))?;
+
+	let now = batl::system::unix_time();
+
+	let mut unused = Vec::new();
+
+	for link in usage::links(context.system(), &workspace) {
+		let last_used = link.last_used();
+		let idle = last_used.map(|time| now.saturating_sub(time));
+
+		let when = match idle {
+			Some(secs) if secs < 86400 => "today".to_string(),
+			Some(secs) => format!("{} days ago", secs / 86400),
+			None => "never".to_string()
+		};
+
+		println!("{}\t{}\t{}", link.name, output_name(&link.repository.to_string()), when);
+
+		if idle.is_none_or(|secs| secs >= unused_for.get().as_secs()) {
+			unused.push(link.name);
+		}
+	}
+
+	if unused.is_empty() {
+		return Ok(());
+	}
+
+	println!();
+	let threshold = unused_for.get().as_secs();
+	let period = if threshold.is_multiple_of(86400) {
+		format!("{} days", threshold / 86400)
+	} else {
+		unused_for.to_string()
+	};
+
+	info(&format!("Unused for {}, these links can likely be removed:", period));
+
+	for link in unused {
+		println!("  batl link delete {}", link);
+	}
+
+	Ok(())
+}
+
+fn cmd_init(name: Option<String>, repo: Option<String>, interactive: bool, context: &BatlContext) -> Result<(), UtilityError> {
+	let repo = match repo {
+		Some(repo) => repo,
+		None if interactive => {
+			pick_repository("Link repository", &repository_candidates(false, context)?)?
+				.ok_or(UtilityError::ResourceDoesNotExist("Repository".to_string()))?
+		},
+		None => return Err(UtilityError::ResourceDoesNotExist("Repository".to_string()))
+	};
+
+	// name@version links a fetched version, pinning the link
+	// to it
+	let (repo, version) = match repo.parse::<VersionedName>() {
+		Ok(versioned) => (versioned.name.to_string(), Some(versioned.version)),
+		Err(_) => (repo, None)
+	};
+
 	if !BATL_NAME_REGEX.is_match(&repo) {
 		return Err(UtilityError::InvalidName(repo));
 	}
 
-	// TODO: Make random string
-	let name = name.unwrap_or_else(|| unimplemented!());
+	let default_name = repo.rsplit('/').next().unwrap_or(&repo).to_string();
+
+	let name = match name {
+		Some(name) => name,
+		None if interactive => {
+			dialoguer::Input::new()
+				.with_prompt("Link name")
+				.default(default_name)
+				.interact_text()?
+		},
+		None => default_name
+	};
 
 	if !BATL_LINK_REGEX.is_match(&name) {
 		return Err(UtilityError::InvalidName(name));
 	}
 
-	let repo = Repository::load(repo.as_str().into())?
-		.ok_or(UtilityError::ResourceDoesNotExist(format!("Repository {}", repo)))?;
-
-	let mut workspace = Workspace::locate_then_load(&current_dir()?)?
+	let repo = match &version {
+		Some(ver) => Repository::version_path(context, &repo.as_str().into(), ver)
+			.filter(|path| path.exists())
+			.map(|path| Repository::from_path_in(context, &path))
+			.transpose()?
+			.ok_or(UtilityError::ResourceDoesNotExist(format!("Fetched version {}@{}, fetch it first", repo, ver)))?,
+		None => Repository::load_in(context, repo.as_str().into())?
+			.ok_or(UtilityError::ResourceDoesNotExist(format!("Repository {}", repo)))?
+	};
+
+	let mut workspace = Workspace::locate_then_load_in(context, &current_dir()?)?
 		.ok_or(UtilityError::ResourceDoesNotExist("Workspace".to_string()))?;
 
-	workspace.create_link(&name, &repo)?;
+	workspace.create_link(context, &name, &repo)?;
 
 	success(&format!("Initialized link {}", name));
 
 	Ok(())
 }
 
-fn cmd_delete(name: String) -> Result<(), UtilityError> {
-	let mut workspace = Workspace::locate_then_load(&current_dir()?)?
+fn cmd_delete(name: String, context: &BatlContext) -> Result<(), UtilityError> {
+	let mut workspace = Workspace::locate_then_load_in(context, &current_dir()?)?
 		.ok_or(UtilityError::ResourceDoesNotExist("Workspace".to_string()))?;
 
 	workspace.unlink(&name)?;
@@ -132,22 +239,26 @@ fn cmd_delete(name: String) -> Result<(), UtilityError> {
 	Ok(())
 }
 
-fn cmd_run(name: String, args: Vec<String>) -> Result<(), UtilityError> {
-	let workspace = Workspace::locate_then_load(&current_dir()?)?
+fn cmd_run(name: String, args: Vec<String>, context: &BatlContext) -> Result<(), UtilityError> {
+	let workspace = Workspace::locate_then_load_in(context, &current_dir()?)?
 		.ok_or(UtilityError::ResourceDoesNotExist("Workspace".to_string()))?;
 
-	let repository = workspace.link(&name)
+	let repository = workspace.link(context, &name)
 		.ok_or(UtilityError::LinkNotFound)?;
 
+	// Usage only feeds suggestions, so it never fails a run
+	drop(usage::record(context.system(), workspace.name(), &name));
+
 	info(&format!("Running command for link {}\n", name));
 
 	let status = std::process::Command::new(args.first().unwrap())
 		.current_dir(repository.path())
+		.envs(context.system().env())
 		.args(args.iter().skip(1))
 		.status()?;
 
 	if !status.success() {
-		return Err(UtilityError::ScriptError(format!("Exit code {}", status.code().unwrap_or(0))))
+		return Err(UtilityError::ScriptError(format!("Failed with {}", failure_reason(status))))
 	}
 
 	println!();
@@ -156,31 +267,38 @@ fn cmd_run(name: String, args: Vec<String>) -> Result<(), UtilityError> {
 	Ok(())
 }
 
-fn cmd_exec(name: Option<String>, script: String) -> Result<(), UtilityError> {
-	let repository = match &name {
+fn cmd_exec(name: Option<String>, on: Option<String>, with: Vec<VersionedName>, output: ExecOutput, script_name: String, context: &BatlContext) -> Result<(), UtilityError> {
+	let (repository, link_env) = match &name {
 		Some(val) => {
-			let workspace = Workspace::locate_then_load(&current_dir()?)?
+			let workspace = Workspace::locate_then_load_in(context, &current_dir()?)?
 				.ok_or(UtilityError::ResourceDoesNotExist("Workspace".to_string()))?;
 
-			workspace.link(val)
+			drop(usage::record(context.system(), workspace.name(), val));
+
+			(workspace.link(context, val), workspace.link_env(val))
 		},
-		None => Repository::locate_then_load(&current_dir()?)?
-	}.ok_or(UtilityError::ResourceDoesNotExist("Repository".to_string()))?;
+		None => (Repository::locate_then_load_in(context, &current_dir()?)?, HashMap::new())
+	};
 
-	let command = repository.script(&script)
-		.ok_or(UtilityError::ScriptNotFound(script))?;
+	let repository = repository.ok_or(UtilityError::ResourceDoesNotExist("Repository".to_string()))?;
 
-	info(&format!("Running script{}\n", name.map(|s| format!(" for link {}", s)).unwrap_or("".to_string())));
+	if repository.script(context, &script_name).is_none() {
+		return Err(UtilityError::ScriptNotFound(script_name));
+	}
 
-	let status = std::process::Command::new("sh")
-		.current_dir(repository.path())
-		.arg("-c")
-		.arg(command)
-		.status()?;
+	if output == ExecOutput::Full {
+		info(&format!("Running script{}\n", name.as_ref().map(|s| format!(" for link {}", s)).unwrap_or("".to_string())));
+	}
 
+	let link = name.as_deref().map(|link_name| (link_name, link_env));
+	let status = run_chain(&repository, &script_name, link, on, with, output, context)?;
 
 	if !status.success() {
-		return Err(UtilityError::ScriptError(format!("Exit code {}", status.code().unwrap_or(0))))
+		return Err(UtilityError::ScriptError(format!("Failed with {}", failure_reason(status))))
+	}
+
+	if output != ExecOutput::Full {
+		return Ok(());
 	}
 
 	println!("");
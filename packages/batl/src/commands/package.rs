@@ -0,0 +1,108 @@
+use batl::context::BatlContext;
+use batl::resource::{Repository, Resource};
+use batl::resource::archive::Report;
+use batl::secrets::{Finding, Scanner};
+use batl::units::ByteSize;
+use clap::Subcommand;
+use crate::output::*;
+use crate::utils::{UtilityError, BATL_NAME_REGEX};
+use std::env::current_dir;
+
+
+/// Files at least this large are pointed out, in bytes
+const LARGE_FILE: u64 = 10 * 1024 * 1024;
+
+
+#[derive(Subcommand)]
+pub enum Commands {
+	/// List the files an archive of the repository would
+	/// hold, after ignore rules, with their sizes
+	Ls {
+		#[arg(short = 'n')]
+		name: Option<String>,
+		#[arg(long = "json")]
+		json: bool
+	}
+}
+
+pub fn run(cmd: Commands, context: &BatlContext) -> Result<(), UtilityError> {
+	match cmd {
+		Commands::Ls { name, json } => {
+			cmd_ls(name, json, context)
+		}
+	}
+}
+
+fn cmd_ls(name: Option<String>, json: bool, context: &BatlContext) -> Result<(), UtilityError> {
+	let repository = match name {
+		Some(val) => {
+			if !BATL_NAME_REGEX.is_match(&val) {
+				return Err(UtilityError::InvalidName(val));
+			}
+
+			Repository::load_in(context, val.into())?
+		},
+		None => Repository::locate_then_load_in(context, &current_dir()?)?
+	}.ok_or(UtilityError::ResourceDoesNotExist("Repository".to_string()))?;
+
+	let report = Report::of_files(repository.path(), repository.package_files()?)?;
+
+	let scanner = Scanner::new(&repository.config().secrets)
+		.map_err(|e| UtilityError::InvalidPattern(e.to_string()))?;
+
+	let mut secrets = Vec::new();
+
+	for (path, _) in &report.files {
+		secrets.extend(scanner.scan(path, &std::fs::read(repository.path().join(path))?));
+	}
+
+	print_report(&report, &secrets, json)
+}
+
+/// Print the files of a report with their sizes and the
+/// totals, pointing out likely secrets the scan publishing
+/// runs found, and large files
+pub fn print_report(report: &Report, secrets: &[Finding], json: bool) -> Result<(), UtilityError> {
+	if json {
+		let files = report.files.iter()
+			.map(|(path, size)| serde_json::json!({ "path": path, "size": size }))
+			.collect::<Vec<_>>();
+
+		let secrets = secrets.iter()
+			.map(|finding| serde_json::json!({ "path": finding.path, "line": finding.line, "rule": finding.rule }))
+			.collect::<Vec<_>>();
+
+		let out = serde_json::to_string_pretty(&serde_json::json!({
+			"files": files,
+			"secrets": secrets,
+			"size": report.size(),
+			"compressed": report.compressed
+		})).map_err(|e| UtilityError::ConfigError(e.to_string()))?;
+
+		println!("{}", out);
+
+		return Ok(());
+	}
+
+	for (path, size) in &report.files {
+		println!("{:>8}  {}", ByteSize(*size).to_string(), path);
+	}
+
+	println!();
+	info(&format!(
+		"{} files, {}B, about {}B compressed",
+		report.files.len(), ByteSize(report.size()), ByteSize(report.compressed)
+	));
+
+	for finding in secrets {
+		warning(&format!("{}:{} may hold a secret ({}), add it to batl.ignore to leave it out", finding.path, finding.line, finding.rule));
+	}
+
+	for (path, size) in &report.files {
+		if *size >= LARGE_FILE {
+			warning(&format!("{} is {}B", path, ByteSize(*size)));
+		}
+	}
+
+	Ok(())
+}
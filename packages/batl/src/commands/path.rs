@@ -0,0 +1,179 @@
+use batl::context::BatlContext;
+use batl::resource::Name;
+use clap::Subcommand;
+use crate::utils::{UtilityError, BATL_NAME_REGEX};
+use std::path::{Path, PathBuf};
+
+
+#[derive(Subcommand)]
+pub enum Commands {
+	/// Print the folder a name is stored in, such as
+	/// `@namespace/name`
+	Encode {
+		name: String,
+		/// Print the full path under the repository root
+		#[arg(long = "absolute")]
+		absolute: bool,
+		/// Use the workspace root with --absolute
+		#[arg(long = "workspace", requires = "absolute")]
+		workspace: bool
+	},
+	/// Print the name stored in a folder, given relative to
+	/// a resource root or as a full path under one
+	Decode {
+		path: PathBuf
+	}
+}
+
+pub fn run(cmd: Commands, context: &BatlContext) -> Result<(), UtilityError> {
+	match cmd {
+		Commands::Encode { name, absolute, workspace } => {
+			cmd_encode(name, absolute, workspace, context)
+		},
+		Commands::Decode { path } => {
+			cmd_decode(path, context)
+		}
+	}
+}
+
+fn cmd_encode(name: String, absolute: bool, workspace: bool, context: &BatlContext) -> Result<(), UtilityError> {
+	let root = if !absolute {
+		None
+	} else if workspace {
+		Some(context.system().workspace_root().ok_or(UtilityError::NotSetup)?)
+	} else {
+		Some(context.system().repository_root().ok_or(UtilityError::NotSetup)?)
+	};
+
+	println!("{}", encode(&name, root.as_deref())?.display());
+
+	Ok(())
+}
+
+fn cmd_decode(path: PathBuf, context: &BatlContext) -> Result<(), UtilityError> {
+	let roots = [context.system().repository_root(), context.system().workspace_root()];
+
+	println!("{}", decode(&path, &roots.into_iter().flatten().collect::<Vec<_>>())?);
+
+	Ok(())
+}
+
+/// The folder a name is stored in, under `root` if given
+fn encode(name: &str, root: Option<&Path>) -> Result<PathBuf, UtilityError> {
+	if !BATL_NAME_REGEX.is_match(name) {
+		return Err(UtilityError::InvalidName(name.to_string()));
+	}
+
+	let relative = PathBuf::from(&Name::from(name));
+
+	Ok(root.map_or_else(|| relative.clone(), |root| root.join(&relative)))
+}
+
+/// The name stored in a folder, given relative to a
+/// resource root or as a full path under one of `roots`.
+/// Links are followed to the folder they point to.
+fn decode(path: &Path, roots: &[PathBuf]) -> Result<Name, UtilityError> {
+	if path.is_relative() {
+		return Name::from_relative_path(path)
+			.map_err(|e| UtilityError::InvalidPath(e.to_string()));
+	}
+
+	for root in roots {
+		let decoded = Name::from_path_in(root, path)
+			.map_err(|e| UtilityError::InvalidPath(e.to_string()))?;
+
+		if let Some(name) = decoded {
+			return Ok(name);
+		}
+	}
+
+	Err(UtilityError::InvalidPath(format!("{} is not under the repository or workspace root", path.display())))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{decode, encode};
+	use crate::utils::UtilityError;
+	use std::fs;
+	use std::path::{Path, PathBuf};
+
+
+	/// A scratch folder holding a repository and a workspace
+	/// root, removed once dropped
+	struct Roots {
+		base: PathBuf,
+		repositories: PathBuf,
+		workspaces: PathBuf
+	}
+
+	impl Roots {
+		fn new(label: &str) -> Self {
+			let base = std::env::temp_dir().join(format!("batl-path-{}-{}", label, std::process::id()));
+			let repositories = base.join("repositories");
+			let workspaces = base.join("workspaces");
+
+			fs::create_dir_all(&repositories).unwrap();
+			fs::create_dir_all(&workspaces).unwrap();
+
+			Roots { base, repositories, workspaces }
+		}
+
+		fn all(&self) -> Vec<PathBuf> {
+			vec![self.repositories.clone(), self.workspaces.clone()]
+		}
+	}
+
+	impl Drop for Roots {
+		fn drop(&mut self) {
+			drop(fs::remove_dir_all(&self.base));
+		}
+	}
+
+	#[test]
+	fn relative_paths_round_trip() {
+		let path = encode("acme/shop/backend", None).unwrap();
+
+		assert_eq!(path, Path::new("@acme").join("@shop").join("backend"));
+		assert_eq!(decode(&path, &[]).unwrap().to_string(), "acme/shop/backend");
+	}
+
+	#[test]
+	fn invalid_names_and_layouts_are_refused() {
+		assert!(matches!(encode("not a name", None), Err(UtilityError::InvalidName(_))));
+		assert!(matches!(decode(Path::new("acme/backend"), &[]), Err(UtilityError::InvalidPath(_))));
+		assert!(matches!(decode(Path::new("@acme"), &[]), Err(UtilityError::InvalidPath(_))));
+	}
+
+	#[test]
+	fn absolute_paths_decode_under_either_root() {
+		let roots = Roots::new("absolute");
+
+		let repository = encode("acme/backend", Some(&roots.repositories)).unwrap();
+		let workspace = encode("acme/shop", Some(&roots.workspaces)).unwrap();
+
+		assert_eq!(repository, roots.repositories.join("@acme").join("backend"));
+		assert_eq!(decode(&repository, &roots.all()).unwrap().to_string(), "acme/backend");
+		assert_eq!(decode(&workspace, &roots.all()).unwrap().to_string(), "acme/shop");
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn links_decode_to_what_they_point_at() {
+		let roots = Roots::new("link");
+		let target = encode("acme/backend", Some(&roots.repositories)).unwrap();
+		let link = roots.base.join("elsewhere");
+
+		fs::create_dir_all(&target).unwrap();
+		std::os::unix::fs::symlink(&target, &link).unwrap();
+
+		assert_eq!(decode(&link, &roots.all()).unwrap().to_string(), "acme/backend");
+	}
+
+	#[test]
+	fn paths_outside_the_roots_are_refused() {
+		let roots = Roots::new("outside");
+		let outside = roots.base.join("@acme").join("backend");
+
+		assert!(matches!(decode(&outside, &roots.all()), Err(UtilityError::InvalidPath(_))));
+	}
+}
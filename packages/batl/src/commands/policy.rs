@@ -0,0 +1,105 @@
+use batl::context::BatlContext;
+use batl::policy::Policy;
+use batl::resource::{Repository, Resource};
+use clap::Subcommand;
+use crate::output::*;
+use crate::output::name as output_name;
+use crate::utils::{repository_paths, UtilityError, BATL_NAME_REGEX};
+use std::env::current_dir;
+
+
+#[derive(Subcommand)]
+pub enum Commands {
+	/// Check repositories against the policy files that
+	/// apply to them
+	Check {
+		#[arg(short = 'n', conflicts_with = "all")]
+		name: Option<String>,
+		/// Check every repository
+		#[arg(long = "all")]
+		all: bool,
+		/// A name prefix, or an @group from the batlrc
+		#[arg(requires = "all")]
+		filter: Option<String>,
+		/// Print the violations as JSON
+		#[arg(long = "json")]
+		json: bool
+	}
+}
+
+pub fn run(cmd: Commands, context: &BatlContext) -> Result<(), UtilityError> {
+	match cmd {
+		Commands::Check { name, all, filter, json } => {
+			cmd_check(name, all, filter, json, context)
+		}
+	}
+}
+
+fn cmd_check(name: Option<String>, all: bool, filter: Option<String>, json: bool, context: &BatlContext) -> Result<(), UtilityError> {
+	let repositories = if all {
+		repository_paths(filter.as_deref(), context)?
+			.into_iter()
+			.filter_map(|(name, path)| match Repository::from_path_in(context, &path) {
+				Ok(repository) => Some(repository),
+				Err(e) => {
+					warning(&format!("Skipped {}: {}", name, e));
+					None
+				}
+			})
+			.collect::<Vec<_>>()
+	} else {
+		let repository = match name {
+			Some(val) => {
+				if !BATL_NAME_REGEX.is_match(&val) {
+					return Err(UtilityError::InvalidName(val));
+				}
+
+				Repository::load_in(context, val.into())?
+			},
+			None => Repository::locate_then_load_in(context, &current_dir()?)?
+		}.ok_or(UtilityError::ResourceDoesNotExist("Repository".to_string()))?;
+
+		vec![repository]
+	};
+
+	let mut violations = Vec::new();
+	let mut governed = 0;
+
+	for repository in &repositories {
+		let policy = Policy::load_in(context, repository.name())?;
+
+		if !policy.sources.is_empty() {
+			governed += 1;
+		}
+
+		violations.extend(policy.check(context, repository));
+	}
+
+	let result = match violations.len() {
+		0 => Ok(()),
+		1 => Err(UtilityError::PolicyViolations("1 rule is broken".to_string())),
+		count => Err(UtilityError::PolicyViolations(format!("{} rules are broken", count)))
+	};
+
+	if json {
+		let out = serde_json::to_string_pretty(&violations)
+			.map_err(|e| UtilityError::ConfigError(e.to_string()))?;
+
+		println!("{}", out);
+
+		// Keep stdout parseable while still failing CI
+		return result.map_err(|err| UtilityError::Reported(Box::new(err)));
+	}
+
+	for violation in &violations {
+		error(&format!("{}: {}", output_name(&violation.name.to_string()), violation.message));
+	}
+
+	if governed == 0 {
+		warning(&format!("No policy applies, add a {} to the repository root or a namespace folder", batl::policy::POLICY_FILE));
+	} else if violations.is_empty() {
+		success(&format!("Checked {} of {} repositories against a policy, no rules are broken", governed, repositories.len()));
+	}
+
+	result
+}
@@ -0,0 +1,179 @@
+use batl::context::BatlContext;
+use batl::error as batlerror;
+use batl::registry::{AuthStatus, Registry, API_VERSION, TOKEN_ENV};
+use clap::Subcommand;
+use crate::output::*;
+use crate::utils::UtilityError;
+
+
+/// What names the configured registry when mirroring
+const DEFAULT_NAME: &str = "default";
+
+
+#[derive(Subcommand)]
+pub enum Commands {
+	/// Copy published packages from one registry to another,
+	/// skipping versions the destination already has. Each
+	/// registry is a name from `[registries]` in the batlrc,
+	/// `default` for the configured one, or a url. Urls are
+	/// sent no credentials unless `--send-credentials` is
+	/// given.
+	Mirror {
+		src: String,
+		dst: String,
+		filter: Option<String>,
+		#[arg(long = "send-credentials")]
+		send_credentials: bool
+	},
+	/// Check that a registry is reachable, speaks a
+	/// compatible API, and accepts the configured
+	/// credentials. Uses the configured registry when no url
+	/// is given. With `--json`, the report is printed and
+	/// the command succeeds either way.
+	Ping {
+		url: Option<String>,
+		#[arg(long = "json")]
+		json: bool
+	}
+}
+
+pub fn run(cmd: Commands, context: &BatlContext) -> Result<(), UtilityError> {
+	match cmd {
+		Commands::Mirror { src, dst, filter, send_credentials } => {
+			cmd_mirror(src, dst, filter, send_credentials, context)
+		},
+		Commands::Ping { url, json } => {
+			cmd_ping(url, json, context)
+		}
+	}
+}
+
+fn cmd_mirror(src: String, dst: String, filter: Option<String>, send_credentials: bool, context: &BatlContext) -> Result<(), UtilityError> {
+	let source = mirror_registry(&src, send_credentials, context)?;
+	let destination = mirror_registry(&dst, send_credentials, context)?;
+
+	let filter = filter.unwrap_or_default();
+
+	let mut packages = source.search(&filter)?
+		.into_iter()
+		.map(|result| result.name)
+		.filter(|name| name.to_string().starts_with(&filter))
+		.collect::<Vec<_>>();
+
+	packages.sort_by_key(|name| name.to_string());
+
+	let mut copied = 0;
+	let mut skipped = 0;
+
+	for name in packages {
+		let metadata = source.metadata(&name)?;
+
+		let existing = match destination.metadata(&name) {
+			Ok(dst_metadata) => dst_metadata.versions,
+			Err(batlerror::RegistryError::NotFound(_)) => Vec::new(),
+			Err(err) => return Err(err.into())
+		};
+
+		let mut missing = metadata.versions.iter()
+			.filter(|version| !existing.contains(version))
+			.collect::<Vec<_>>();
+		missing.sort();
+
+		skipped += metadata.versions.len() - missing.len();
+
+		for version in missing {
+			let checksum = source.checksum(&name, version)?;
+			let body = source.fetch(&name, Some(version))?;
+			destination.import(&metadata, version, body, checksum.as_ref())?;
+
+			success(&format!("Mirrored {}@{}", name, version));
+			copied += 1;
+		}
+	}
+
+	info(&format!("Mirrored {} versions, {} already present", copied, skipped));
+
+	Ok(())
+}
+
+/// The registry a side of a mirror names. Named registries
+/// bring their own credentials, while urls could point
+/// anywhere, so they only get the configured ones when
+/// asked to.
+fn mirror_registry(given: &str, send_credentials: bool, context: &BatlContext) -> Result<Registry, UtilityError> {
+	if !given.contains("://") {
+		return match Registry::named(context.system(), given) {
+			Err(batlerror::RegistryError::UnknownRegistry(_)) if given == DEFAULT_NAME => Ok(Registry::from_system(context.system())),
+			named => Ok(named?)
+		};
+	}
+
+	let credentials = send_credentials
+		.then(|| std::env::var(TOKEN_ENV).ok().or_else(|| context.system().batlrc().and_then(|batlrc| batlrc.api.key())))
+		.flatten();
+
+	Ok(Registry::new(given, credentials).cached_in(context.system()))
+}
+
+fn cmd_ping(url: Option<String>, json: bool, context: &BatlContext) -> Result<(), UtilityError> {
+	let given = url.map(|val| {
+		let credentials = std::env::var(TOKEN_ENV).ok()
+			.or_else(|| context.system().batlrc().and_then(|batlrc| batlrc.api.key()));
+
+		Registry::new(&val, credentials).cached_in(context.system())
+	});
+
+	let ping = given.as_ref().unwrap_or_else(|| context.registry()).ping();
+
+	if json {
+		let out = serde_json::to_string_pretty(&ping)
+			.map_err(|err| UtilityError::RegistryError(err.to_string()))?;
+
+		println!("{}", out);
+
+		return Ok(());
+	}
+
+	if !ping.reachable {
+		return Err(UtilityError::NetworkError(format!("{} is unreachable: {}", ping.url, ping.error.unwrap_or_default())));
+	}
+
+	match ping.latency_ms {
+		Some(latency) => success(&format!("Reached {} in {} ms", ping.url, latency)),
+		None => success(&format!("Reached {}", ping.url))
+	}
+
+	match (ping.api_version, ping.compatible) {
+		(Some(version), Some(true)) if version > API_VERSION => warning(&format!("API version {}, newer than {}", version, API_VERSION)),
+		(Some(version), Some(true)) => success(&format!("API version {}", version)),
+		(Some(version), _) => error(&format!("API version {} is not compatible with {}", version, API_VERSION)),
+		(None, _) => info("Registry does not report an API version")
+	}
+
+	if !ping.features.is_empty() {
+		info(&format!("Features: {}", ping.features.join(", ")));
+	}
+
+	let scopes = ping.scopes.as_ref()
+		.map(|scopes| scopes.iter().map(ToString::to_string).collect::<Vec<_>>().join(", "))
+		.map(|scopes| format!(" (scopes: {})", scopes))
+		.unwrap_or_default();
+
+	match ping.auth {
+		AuthStatus::Valid => success(&format!("Credentials accepted{}", scopes)),
+		AuthStatus::Invalid => error("Credentials rejected"),
+		AuthStatus::Missing => info("No credentials configured"),
+		_ => info("Credentials could not be checked")
+	}
+
+	if ping.healthy() {
+		return Ok(());
+	}
+
+	let reason = ping.error.unwrap_or_else(|| "incompatible API version".to_string());
+
+	match ping.auth {
+		AuthStatus::Invalid => Err(UtilityError::NotAuthorized(reason)),
+		_ => Err(UtilityError::RegistryError(reason))
+	}
+}
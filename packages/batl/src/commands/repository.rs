@@ -1,179 +1,492 @@
-use batl::resource::{repository, Repository, Resource, Name};
-use batl::resource::repository::CreateRepositoryOptions;
+use batl::context::BatlContext;
+use batl::error as batlerror;
+use batl::history;
+use batl::index::{Index, Kind};
+use batl::integrity::{Checksum, Policy};
+use batl::registry::{Check, Health, Registry};
+use batl::provider::Source;
+use batl::registry::auth::Scope;
+use batl::resolve;
+use batl::resource::{archive, ownership, repository, Archive, Freeze, Ownership, Repository, RepositoryKind, Resource, Name, VersionedName, Workspace};
+use batl::resource::repository::{CopyRepositoryOptions, CreateRepositoryOptions};
 use batl::resource::tomlconfig::{TomlConfig, RepositoryGit0_2_2};
+use batl::secrets::Scanner;
 use clap::Subcommand;
 use console::Term;
 use crate::output::*;
-use crate::utils::{UtilityError, BATL_NAME_REGEX};
+use crate::output::name as output_name;
+use crate::output::path as output_path;
+use crate::utils::{api_notice, expand_names, failure_reason, fetch_manifest, fetch_repository, fetch_sparse, negotiate, relink_fetched, run_chain, Batch, ExecOutput, NameFilter, OnConflict, UtilityError, BATL_NAME_REGEX};
 use envfile::EnvFile;
-use git2::{FetchOptions, RemoteCallbacks, Progress};
-use git2::build::RepoBuilder;
+use git2::{Direction, FetchOptions, IndexEntryExtendedFlag, IndexEntryFlag, Pathspec, PathspecFlags, RemoteCallbacks, ResetType};
+use git2::build::{CheckoutBuilder, RepoBuilder};
+use semver::Version;
+use std::collections::HashMap;
 use std::env::current_dir;
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 
 #[derive(Subcommand)]
 pub enum Commands {
 	Ls {
-		filter: Option<String>
+		/// A name prefix, or an @group from the batlrc
+		filter: Option<String>,
+		/// Only list repositories you created or last changed
+		#[arg(long = "mine")]
+		mine: bool,
+		/// Only list repositories of a kind: library, app,
+		/// tool, or meta
+		#[arg(long = "kind")]
+		kind: Option<RepositoryKind>
 	},
 	Init {
-		name: String
+		#[arg(long = "here")]
+		here: bool,
+		/// What the repository is for: library, app, tool,
+		/// or meta
+		#[arg(long = "kind", default_value_t = RepositoryKind::Library)]
+		kind: RepositoryKind,
+		/// The version to start at, over `init.version` in the
+		/// batlrc
+		#[arg(long = "version")]
+		version: Option<Version>,
+		/// Start without any scripts, neither the placeholders
+		/// nor those from the batlrc
+		#[arg(long = "no-default-scripts")]
+		no_default_scripts: bool,
+		#[arg(required_unless_present = "here")]
+		name: Option<String>
 	},
 	Delete {
-		name: String
+		name: String,
+		/// Delete the repository even if another user
+		/// created it, or other repositories and workspaces
+		/// depend on or link to it
+		#[arg(long = "force")]
+		force: bool
 	},
 	Clone {
+		/// A git url, or a shorthand such as gh:owner/repo
 		url: String,
+		/// Required unless it can be derived from a shorthand
 		#[arg(short = 'o')]
-		name: String
+		name: Option<String>,
+		/// Clone only this many commits of history
+		#[arg(long = "depth")]
+		depth: Option<u32>,
+		/// Check out this branch instead of the remote's
+		/// default
+		#[arg(short = 'b', long = "branch")]
+		branch: Option<String>,
+		/// Check out only this path, can be given more than
+		/// once
+		#[arg(long = "sparse")]
+		sparse: Vec<String>
+	},
+	Scaffold {
+		/// Clone only this many commits of history,
+		/// overriding batl.toml
+		#[arg(long = "depth")]
+		depth: Option<u32>,
+		/// Check out this branch, overriding batl.toml
+		#[arg(short = 'b', long = "branch")]
+		branch: Option<String>
 	},
-	Scaffold,
 	Env {
 		#[arg(short = 'n')]
 		name: Option<String>,
 		var: String
 	},
 	Archive {
-		name: String
+		name: String,
+		/// Also write a compressed copy of the archive, which
+		/// publish uploads in its place
+		#[arg(long = "compress")]
+		compress: Option<Compression>,
+		/// Only re-archive files that changed since the last
+		/// archive, reusing the rest from it
+		#[arg(long = "incremental")]
+		incremental: bool
 	},
 	Publish {
-		name: String
+		name: String,
+		/// Run the check and test scripts, and publish
+		/// their results with the package
+		#[arg(long = "check")]
+		check: bool,
+		/// List the files the archive holds, with their sizes,
+		/// instead of publishing it
+		#[arg(long = "report", conflicts_with = "check")]
+		report: bool,
+		/// Publish even if the secret scan finds likely keys
+		/// or tokens in the archive
+		#[arg(long = "allow-secrets")]
+		allow_secrets: bool
 	},
 	Fetch {
+		/// A name, name@version, shorthand such as
+		/// gh:owner/repo, or @group to fetch each of its
+		/// repositories
+		name: String,
+		/// Only fetch these files or directories, along with
+		/// the batl.toml
+		#[arg(long = "path")]
+		paths: Vec<PathBuf>,
+		/// What to do if the repository is already there with
+		/// local changes, instead of asking
+		#[arg(long = "on-conflict", value_enum, conflicts_with = "paths")]
+		on_conflict: Option<OnConflict>,
+		/// Set up a git remote for the git url the repository
+		/// was published from, so git pull works in the copy
+		#[arg(long = "git-remote")]
+		git_remote: bool,
+		/// Only fetch the batl.toml, into gen/manifests, to
+		/// look at the repository without downloading it
+		#[arg(long = "manifest-only", conflicts_with_all = ["paths", "on_conflict", "git_remote"])]
+		manifest_only: bool,
+		/// Fetch from a registry in [registries] of the
+		/// batlrc. Defaults to the registry the current
+		/// repository names for the dependency, if any.
+		#[arg(long = "registry")]
+		registry: Option<String>,
+		/// Stop at the first repository that fails to fetch,
+		/// when fetching a group
+		#[arg(long = "fail-fast")]
+		fail_fast: bool,
+		/// Report the repositories that failed as json, when
+		/// fetching a group
+		#[arg(long = "json")]
+		json: bool
+	},
+	Search {
+		query: String
+	},
+	Register {
+		name: String,
+		path: PathBuf
+	},
+	Unregister {
+		name: String
+	},
+	Copy {
+		from: String,
+		to: String,
+		#[arg(long = "strip-git")]
+		strip_git: bool
+	},
+	Info {
 		name: String
 	},
 	Which {
 		name: String
 	},
+	/// Print where a repository resolves to and why, the
+	/// way scripts and their dependencies are resolved
+	Resolve {
+		name: String,
+		/// Resolve this exact version
+		#[arg(long = "version")]
+		version: Option<Version>,
+		/// Resolve with these overrides, as exec does
+		#[arg(long = "with")]
+		with: Vec<VersionedName>,
+		/// Resolve the version recorded in this freeze
+		#[arg(long = "freeze")]
+		freeze: Option<String>,
+		#[arg(long = "json")]
+		json: bool
+	},
 	Exec {
+		/// A repository, or an @group to run the script in
+		/// each of its repositories that has it
 		#[arg(short = 'n')]
 		name: Option<String>,
+		#[arg(long = "on")]
+		on: Option<String>,
+		#[arg(long = "with")]
+		with: Vec<VersionedName>,
+		#[arg(short = 'q', long = "quiet")]
+		quiet: bool,
+		#[arg(long = "summary", conflicts_with = "quiet")]
+		summary: bool,
+		/// Run the script again in every repository where it
+		/// failed last time
+		#[arg(long = "retry-failed", conflicts_with_all = ["name", "with"])]
+		retry_failed: bool,
+		/// Stop at the first repository that fails, when
+		/// running in several
+		#[arg(long = "fail-fast")]
+		fail_fast: bool,
+		/// Report the repositories that failed as json, when
+		/// running in several
+		#[arg(long = "json")]
+		json: bool,
+		/// Run the script in every dependency available
+		/// locally, each after its own dependencies, and then
+		/// in the repository itself
+		#[arg(short = 'r', long = "recursive", conflicts_with = "retry_failed")]
+		recursive: bool,
+		/// How many repositories that don't depend on each
+		/// other to run at once, with --recursive. Output is
+		/// prefixed with the repository when above 1.
+		#[arg(short = 'j', long = "jobs", default_value_t = 1, requires = "recursive")]
+		jobs: usize,
+		/// Touch this file once the script succeeds, as build
+		/// tools driving batl keep track of runs with
+		#[arg(long = "stamp", conflicts_with_all = ["retry_failed", "recursive"])]
+		stamp: Option<PathBuf>,
 		script: String
 	}
 }
 
-pub fn run(cmd: Commands) -> Result<(), UtilityError> {
+pub fn run(cmd: Commands, context: &BatlContext) -> Result<(), UtilityError> {
 	match cmd {
-		Commands::Ls { filter } => {
-			cmd_ls(filter)
+		Commands::Ls { filter, mine, kind } => {
+			cmd_ls(filter, mine, kind, context)
 		},
-		Commands::Init { name } => {
-			cmd_init(name)
+		Commands::Init { here, kind, version, no_default_scripts, name } => {
+			cmd_init(here, kind, version, no_default_scripts, name, context)
 		},
-		Commands::Delete { name } => {
-			cmd_delete(name)
+		Commands::Delete { name, force } => {
+			cmd_delete(name, force, context)
 		},
-		Commands::Clone { url, name } => {
-			cmd_clone(url, name)
+		Commands::Clone { url, name, depth, branch, sparse } => {
+			cmd_clone(url, name, depth, branch, sparse, context)
 		},
-		Commands::Scaffold => {
-			cmd_scaffold()
+		Commands::Scaffold { depth, branch } => {
+			cmd_scaffold(depth, branch, context)
 		},
 		Commands::Env { name, var } => {
 			cmd_env(name, var)
 		},
-		Commands::Archive { name } => {
-			cmd_archive(name)
+		Commands::Archive { name, compress, incremental } => {
+			cmd_archive(name, compress, incremental, context)
+		},
+		Commands::Publish { name, check, report, allow_secrets } => {
+			cmd_publish(name, check, report, allow_secrets, context)
+		},
+		Commands::Fetch { name, paths, on_conflict, git_remote, manifest_only, registry, fail_fast, json } => {
+			if name.starts_with('@') {
+				fetch_group(&name, paths, on_conflict, git_remote, manifest_only, registry, Batch::new(fail_fast, json), context)
+			} else {
+				cmd_fetch(name, paths, on_conflict, git_remote, manifest_only, registry, context)
+			}
+		},
+		Commands::Search { query } => {
+			cmd_search(query, context)
+		},
+		Commands::Register { name, path } => {
+			cmd_register(name, path, context)
+		},
+		Commands::Unregister { name } => {
+			cmd_unregister(name, context)
 		},
-		Commands::Publish { name } => {
-			cmd_publish(name)
+		Commands::Copy { from, to, strip_git } => {
+			cmd_copy(from, to, strip_git, context)
 		},
-		Commands::Fetch { name } => {
-			cmd_fetch(name)
+		Commands::Info { name } => {
+			cmd_info(name, context)
+		},
+		Commands::Resolve { name, version, with, freeze, json } => {
+			cmd_resolve(name, version, with, freeze, json, context)
 		},
 		Commands::Which { name } => {
-			cmd_which(name)
+			cmd_which(name, context)
 		},
-		Commands::Exec { name, script } => {
-			cmd_exec(name, script)
+		Commands::Exec { name, on, with, quiet, summary, retry_failed, fail_fast, json, recursive, jobs, stamp, script } => {
+			let output = ExecOutput::from_flags(quiet, summary);
+			let batch = Batch::new(fail_fast, json);
+
+			if retry_failed {
+				cmd_retry_failed(on, output, script, batch, context)
+			} else if recursive {
+				cmd_exec_recursive(name, jobs, on, with, output, script, batch, context)
+			} else {
+				cmd_exec(name, on, with, output, script, batch, context)?;
+
+				stamp.map_or(Ok(()), |stamp| touch_stamp(&stamp))
+			}
 		}
 	}
 }
 
-fn cmd_ls(filter: Option<String>) -> Result<(), UtilityError> {
-	let repo_root = batl::system::repository_root()
-		.ok_or(UtilityError::ResourceDoesNotExist("Repository root".to_string()))?;
+fn cmd_ls(filter: Option<String>, mine: bool, kind: Option<RepositoryKind>, context: &BatlContext) -> Result<(), UtilityError> {
+	let index = Index::open(context.system(), Kind::Repository)?;
 
-	let mut to_search: Vec<(String, PathBuf)> = std::fs::read_dir(repo_root)?
-		.filter_map(|entry| {
-			Some(("".to_string(), entry.ok()?.path()))
-		})
+	let mut found: Vec<(String, PathBuf)> = index.names()
+		.iter()
+		.filter_map(|name| Some((name.to_string(), index.get(name)?.to_path_buf())))
 		.collect();
-	let mut found: Vec<String> = Vec::new();
 
-	while let Some((name, path)) = to_search.pop() {
-		if !path.is_dir() {
+	found.extend(Repository::externals(context).into_iter().map(|(name, path)| (name.to_string(), path)));
+
+	let filter = filter.as_deref().map(|filter| NameFilter::parse(filter, context)).transpose()?;
+
+	for (name, path) in found {
+		if filter.as_ref().is_some_and(|filter| !filter.matches(&name)) {
 			continue;
 		}
 
-		let filename = path.file_name().unwrap().to_str().unwrap();
-
-		if let Some(filename) = filename.strip_prefix('@') {
-			let new_name = filename.to_string();
-			let new_name = format!("{}{}/", name, new_name);
+		let ownership = Ownership::load(&path);
 
-			to_search.extend(
-				std::fs::read_dir(path)?
-					.filter_map(|entry| {
-						Some((new_name.clone(), entry.ok()?.path()))
-					})
-			);
-		} else {
-			found.push(format!("{}{}", name, filename));
+		if mine && !ownership.as_ref().is_some_and(Ownership::is_mine) {
+			continue;
 		}
-	}
 
-	for name in found {
-		if let Some(filter_str) = &filter {
-			if !name.starts_with(filter_str) {
-				continue;
-			}
+		let found_kind = Repository::load_in(context, name.as_str().into())
+			.ok()
+			.flatten()
+			.map(|repository| repository.config().kind);
+
+		if kind.is_some_and(|wanted| found_kind != Some(wanted)) {
+			continue;
 		}
 
-		println!("{}", name);
+		let kind_column = found_kind.map(|found| found.to_string()).unwrap_or_default();
+
+		// Only call out owners in roots shared with others
+		match ownership.filter(|ownership| !ownership.is_mine()) {
+			Some(ownership) => println!("{}\t{}\t({})", output_name(&name), kind_column, ownership.creator),
+			None => println!("{}\t{}", output_name(&name), kind_column)
+		}
 	}
 
 	Ok(())
 }
 
-fn cmd_init(name: String) -> Result<(), UtilityError> {
-	if !BATL_NAME_REGEX.is_match(&name) {
-		return Err(UtilityError::InvalidName(name));
+fn cmd_init(here: bool, kind: RepositoryKind, version: Option<Version>, no_default_scripts: bool, name: Option<String>, context: &BatlContext) -> Result<(), UtilityError> {
+	if let Some(name) = &name {
+		if !BATL_NAME_REGEX.is_match(name) {
+			return Err(UtilityError::InvalidName(name.clone()));
+		}
+	}
+
+	let mut options = CreateRepositoryOptions::kind(kind);
+	options.version = version;
+	options.default_scripts = no_default_scripts.then_some(false);
+
+	if here {
+		let repository = Repository::create_in_place(context, &current_dir()?, name.map(Name::from), options)?;
+
+		success(&format!("Initialized repository {} in place", repository.name()));
+
+		return Ok(());
 	}
 
-	Repository::create(name.into(), Default::default())?;
+	let name = name.ok_or(UtilityError::InvalidName("<>".to_string()))?;
+
+	Repository::create_in(context, name.into(), options)?;
 
 	success("Initialized repository successfully");
 
 	Ok(())
 }
 
-fn cmd_delete(name: String) -> Result<(), UtilityError> {
+fn cmd_delete(name: String, force: bool, context: &BatlContext) -> Result<(), UtilityError> {
 	if !BATL_NAME_REGEX.is_match(&name) {
 		return Err(UtilityError::InvalidName(name));
 	}
 
-	Repository::load(name.into())?
-		.ok_or(UtilityError::ResourceDoesNotExist("Repository".to_string()))?
-		.destroy()?;
+	let repository = Repository::load_in(context, name.into())?
+		.ok_or(UtilityError::ResourceDoesNotExist("Repository".to_string()))?;
+
+	// External repositories are only unregistered, their
+	// files stay where they are
+	if !repository.is_external(context) {
+		ownership::check(repository.path(), force)?;
+	}
+
+	let dependents = dependents_of(repository.name(), context)?;
+
+	for dependent in &dependents {
+		println!("  {}", dependent);
+	}
+
+	if !dependents.is_empty() {
+		if !force {
+			return Err(UtilityError::ResourceNotCollected(format!(
+				"{} is still used by the above, delete it with --force to break them anyway",
+				repository.name()
+			)));
+		}
+
+		warning(&format!("Deleting {} while the above still use it", repository.name()));
+	}
+
+	repository.destroy(context)?;
 
 	success("Deleted repository successfully");
 
 	Ok(())
 }
 
-fn cmd_clone(url: String, name: String) -> Result<(), UtilityError> {
+/// The local repositories that depend on a repository and
+/// the workspaces that depend on or link to it, described
+/// for listing
+fn dependents_of(name: &Name, context: &BatlContext) -> Result<Vec<String>, UtilityError> {
+	let mut dependents = Vec::new();
+
+	let index = Index::open(context.system(), Kind::Repository)?;
+
+	for dependent in index.names() {
+		let Some(repository) = index.get(&dependent).and_then(|path| Repository::from_path_in(context, path).ok()) else {
+			continue;
+		};
+
+		if let Some(requirement) = repository.config().dependencies.get(name) {
+			dependents.push(format!("repository {} depends on it ({})", dependent, requirement));
+		}
+	}
+
+	let index = Index::open(context.system(), Kind::Workspace)?;
+
+	for workspace_name in index.names() {
+		let Some(workspace) = index.get(&workspace_name).and_then(|path| Workspace::from_path_in(context, path).ok()) else {
+			continue;
+		};
+
+		let mut links = workspace.links().into_iter()
+			.filter(|(_, target)| target == name)
+			.map(|(link, _)| link)
+			.collect::<Vec<_>>();
+		links.sort();
+
+		if !links.is_empty() {
+			dependents.push(format!("workspace {} links it as {}", workspace_name, links.join(", ")));
+		} else if let Some(requirement) = workspace.config().dependencies.get(name) {
+			dependents.push(format!("workspace {} depends on it ({})", workspace_name, requirement));
+		}
+	}
+
+	Ok(dependents)
+}
+
+fn cmd_clone(url: String, name: Option<String>, depth: Option<u32>, branch: Option<String>, sparse: Vec<String>, context: &BatlContext) -> Result<(), UtilityError> {
+	let (url, name) = match Source::parse(&url)? {
+		Some(source) => {
+			let derived = source.name().map(|derived| derived.to_string());
+
+			(source.git_url(), name.or(derived))
+		},
+		None => (url, name)
+	};
+
+	let name = name.ok_or(UtilityError::InvalidName("a name is required, pass one with -o".to_string()))?;
+
 	if !BATL_NAME_REGEX.is_match(&name) {
 		return Err(UtilityError::InvalidName(name));
 	}
 
-	Repository::create(
+	Repository::create_in(
+		context,
 		name.into(),
 		CreateRepositoryOptions::git(RepositoryGit0_2_2 {
 			url,
-			path: "git".to_string()
+			path: "git".to_string(),
+			depth,
+			branch,
+			sparse: (!sparse.is_empty()).then_some(sparse)
 		})
 	)?;
 
@@ -182,14 +495,20 @@ fn cmd_clone(url: String, name: String) -> Result<(), UtilityError> {
 	Ok(())
 }
 
-fn cmd_scaffold() -> Result<(), UtilityError> {
-	let repository = Repository::locate_then_load(&current_dir()?)?
+fn cmd_scaffold(depth: Option<u32>, branch: Option<String>, context: &BatlContext) -> Result<(), UtilityError> {
+	let repository = Repository::locate_then_load_in(context, &current_dir()?)?
 		.ok_or(UtilityError::ResourceDoesNotExist("Repository".to_string()))?;
 
+	scaffold(&repository, depth, branch)
+}
+
+/// Clone the git remote of a repository into it, if it
+/// has one
+pub fn scaffold(repository: &Repository, depth: Option<u32>, branch: Option<String>) -> Result<(), UtilityError> {
 	let config = repository.config();
 
 	if let Some(git) = config.git.clone() {
-		let git_path = repository.path().join(git.path);
+		let git_path = repository.path().join(&git.path);
 
 		let mut fetch_callbacks = RemoteCallbacks::new();
 		fetch_callbacks.transfer_progress(transfer_progress);
@@ -197,16 +516,46 @@ fn cmd_scaffold() -> Result<(), UtilityError> {
 		let mut fetch_options = FetchOptions::new();
 		fetch_options.remote_callbacks(fetch_callbacks);
 
-		let result = RepoBuilder::new()
-			.fetch_options(fetch_options)
-			.clone(&git.url, &git_path);
+		if let Some(commits) = depth.or(git.depth) {
+			fetch_options.depth(i32::try_from(commits).unwrap_or(i32::MAX));
+		}
+
+		let mut builder = RepoBuilder::new();
+		builder.fetch_options(fetch_options);
+
+		if let Some(wanted) = branch.as_deref().or(git.branch.as_deref()) {
+			builder.branch(wanted);
+		}
+
+		if !git.sparse.is_empty() {
+			let mut checkout = CheckoutBuilder::new();
+
+			for path in &git.sparse {
+				checkout.path(path.as_str());
+			}
+
+			builder.with_checkout(checkout);
+		}
+
+		let result = builder.clone(&git.url, &git_path);
 
 		println!();
 
-		if let Err(err) = result {
-			println!("{}", err);
+		let cloned = match result {
+			Ok(cloned) => cloned,
+			Err(err) => {
+				println!("{}", err);
 
-			return Err(UtilityError::ResourceNotCollected("Git remote".to_string()));
+				return Err(UtilityError::ResourceNotCollected("Git remote".to_string()));
+			}
+		};
+
+		if !git.sparse.is_empty() {
+			if let Err(err) = sparse_checkout(&cloned, &git.sparse) {
+				println!("{}", err);
+
+				return Err(UtilityError::ResourceNotCollected("Sparse checkout".to_string()));
+			}
 		}
 
 		success("Successfully scaffolded repository");
@@ -215,7 +564,42 @@ fn cmd_scaffold() -> Result<(), UtilityError> {
 	Ok(())
 }
 
-fn transfer_progress(progress: Progress<'_>) -> bool {
+/// Keep a clone sparse for git itself, not only for the
+/// first checkout. Files outside of the paths are indexed
+/// as skip-worktree so they don't show up as deleted, and
+/// the paths are written as the sparse-checkout patterns.
+fn sparse_checkout(cloned: &git2::Repository, paths: &[String]) -> Result<(), git2::Error> {
+	let pathspec = Pathspec::new(paths.iter().map(String::as_str))?;
+	let mut index = cloned.index()?;
+
+	// The checkout only indexed the paths it wrote
+	index.read_tree(&cloned.head()?.peel_to_tree()?)?;
+
+	let skipped = index.iter()
+		.filter(|entry| !pathspec.matches_path(Path::new(&*String::from_utf8_lossy(&entry.path)), PathspecFlags::DEFAULT))
+		.collect::<Vec<_>>();
+
+	for mut entry in skipped {
+		entry.flags |= IndexEntryFlag::EXTENDED.bits();
+		entry.flags_extended |= IndexEntryExtendedFlag::SKIP_WORKTREE.bits();
+		index.add(&entry)?;
+	}
+
+	index.write()?;
+	cloned.config()?.set_bool("core.sparseCheckout", true)?;
+
+	let patterns = paths.iter()
+		.map(|path| format!("/{}\n", path.trim_matches('/')))
+		.collect::<String>();
+
+	let info = cloned.path().join("info");
+
+	std::fs::create_dir_all(&info)
+		.and_then(|()| std::fs::write(info.join("sparse-checkout"), patterns))
+		.map_err(|err| git2::Error::from_str(&err.to_string()))
+}
+
+fn transfer_progress(progress: git2::Progress<'_>) -> bool {
 	let percentage = progress.received_objects() as f64 / progress.total_objects() as f64;
 
 	let mut term = Term::stdout();
@@ -247,75 +631,344 @@ fn cmd_env(name: Option<String>, var: String) -> Result<(), UtilityError> {
 	Ok(())
 }
 
-fn cmd_archive(name: String) -> Result<(), UtilityError> {
-	let repository = Repository::load(name.as_str().into())?
+/// How `repository archive --compress` compresses the
+/// archive that is published
+#[derive(Clone, Copy, clap::ValueEnum)]
+pub enum Compression {
+	Gzip,
+	Zstd
+}
+
+impl From<Compression> for archive::Compression {
+	fn from(compression: Compression) -> Self {
+		match compression {
+			Compression::Gzip => Self::Gzip,
+			Compression::Zstd => Self::Zstd
+		}
+	}
+}
+
+fn cmd_archive(name: String, compress: Option<Compression>, incremental: bool, context: &BatlContext) -> Result<(), UtilityError> {
+	let repository = Repository::load_in(context, name.as_str().into())?
 		.ok_or(UtilityError::ResourceDoesNotExist("Repository".into()))?;
 
-	repository.archive_gen()?;
+	let progress = Progress::new(context, "archive");
+	progress.report("archive", Some(0.0), &name);
+
+	let options = archive::BuildOptions::new(compress.map(Into::into).unwrap_or_default(), incremental);
+	let archive = repository.archive_gen(context, options)?;
+
+	progress.done(&archive.published_path().display().to_string());
 
 	Ok(())
 }
 
-fn cmd_publish(name: String) -> Result<(), UtilityError> {
-	let batlrc = batl::system::batlrc()
-		.ok_or(UtilityError::ResourceDoesNotExist("BatlRc".to_string()))?;
-
-	let repository = Repository::load(name.as_str().into())?
+fn cmd_publish(name: String, check: bool, report: bool, allow_secrets: bool, context: &BatlContext) -> Result<(), UtilityError> {
+	let repository = Repository::load_in(context, name.as_str().into())?
 		.ok_or(UtilityError::ResourceDoesNotExist("Repository".into()))?;
 
-	let archive = repository.archive()
+	if let Some(paths) = repository.sparse_paths() {
+		let listed = paths.iter().map(|path| path.display().to_string()).collect::<Vec<_>>();
+
+		return Err(UtilityError::InvalidPath(format!(
+			"{} was only fetched in part ({}), fetch it in full to publish it", name, listed.join(", ")
+		)));
+	}
+
+	let archive = repository.archive(context)
 		.ok_or(UtilityError::ResourceDoesNotExist("Archive".into()))?;
 
-	let url = format!("https://api.batl.circetools.net/pkg/{}", &repository.name().to_string());
+	if report {
+		let secrets = Scanner::new(&repository.config().secrets)
+			.map_err(|e| UtilityError::InvalidPattern(e.to_string()))?
+			.scan_archive(archive.path())?;
+
+		return crate::commands::package::print_report(&archive.report()?, &secrets, false);
+	}
 
-	let resp = ureq::post(&url)
-		.set("x-api-key", &batlrc.api.credentials)
-		.send(archive.to_file())?;
+	scan_secrets(&repository, &archive, allow_secrets)?;
 
-	if resp.status() == 200 {
-		success(&format!("Published repository {}", name))
+	let progress = Progress::new(context, "publish");
+	progress.report("negotiate", Some(0.0), &name);
+
+	let registry = context.registry();
+	let capabilities = negotiate(registry)?;
+	registry.require_scope(Scope::Publish)?;
+
+	// Registries that list their features but not deltas are
+	// sent the full archive straight away, as are compressed
+	// archives, which deltas can't be taken of
+	let delta = (capabilities.api_version.is_none() || capabilities.supports("delta"))
+		&& archive.compression() == archive::Compression::None;
+
+	let health = if check {
+		progress.report("check", Some(10.0), &name);
+		run_checks(&repository, context)
 	} else {
-		error(&format!("Failed to send repository: status code {}", resp.status()))
+		None
+	};
+
+	let checksum = archive_checksum(&repository, &archive, context)?;
+
+	progress.report("upload", Some(50.0), &archive.published_path().display().to_string());
+
+	if !(delta && publish_delta(registry, &repository, &archive, &checksum, health.as_ref(), &progress)?) {
+		let stream = progress.track(archive.stream()?, "upload", (50, 100));
+		registry.publish(repository.name(), stream, &checksum, health.as_ref(), repository.source_url().as_deref())?;
 	}
 
+	progress.done(&name);
+	success(&format!("Published repository {}", name));
+
 	Ok(())
 }
 
-fn cmd_which(name: String) -> Result<(), UtilityError> {
+/// The checksum published with the archive, signed when a
+/// signing key is configured in the batlrc
+fn archive_checksum(repository: &Repository, archive: &Archive, context: &BatlContext) -> Result<Checksum, UtilityError> {
+	let mut checksum = Checksum::of(archive.published_path())?;
+
+	if let Some(key) = Policy::from_system(context.system()).signing_key()? {
+		checksum.sign(repository.name(), &repository.config().version, &key);
+		info(&format!("Signed with {}", key.public_key()));
+	}
+
+	Ok(checksum)
+}
+
+/// Scan the archive for likely secrets, refusing to publish
+/// when any turn up unless they are allowed through
+fn scan_secrets(repository: &Repository, archive: &Archive, allow_secrets: bool) -> Result<(), UtilityError> {
+	let scanner = Scanner::new(&repository.config().secrets)
+		.map_err(|e| UtilityError::InvalidPattern(e.to_string()))?;
+
+	let findings = scanner.scan_archive(archive.path())?;
+
+	if findings.is_empty() {
+		return Ok(());
+	}
+
+	for finding in &findings {
+		warning(&format!("{}:{}  {}  {}", finding.path, finding.line, finding.rule, finding.excerpt));
+	}
+
+	if allow_secrets {
+		warning("Publishing anyway, as --allow-secrets was given");
+		return Ok(());
+	}
+
+	info("Remove them, list the path or rule under [secrets] allow or allow_rules in batl.toml, or pass --allow-secrets");
+
+	Err(UtilityError::SecretsFound(format!("{} in {}", findings.len(), repository.name())))
+}
+
+/// Publish only the files that changed since the latest
+/// published version. Returns `false` when a full publish
+/// is needed instead, such as for a first publish or when
+/// the registry doesn't support deltas.
+fn publish_delta(registry: &Registry, repository: &Repository, archive: &Archive, checksum: &Checksum, health: Option<&Health>, progress: &Progress<'_>) -> Result<bool, UtilityError> {
+	let latest = match registry.metadata(repository.name()) {
+		Ok(metadata) => metadata.latest,
+		Err(batlerror::RegistryError::NotFound(_)) => None,
+		Err(err) => return Err(err.into())
+	};
+
+	let Some(base) = latest else {
+		return Ok(false);
+	};
+
+	let Some(previous) = registry.manifest(repository.name(), &base)? else {
+		return Ok(false);
+	};
+
+	let manifest = archive.manifest()?;
+	let changed = manifest.changed_since(&previous);
+
+	if changed.len() == manifest.len() + manifest.links.len() {
+		return Ok(false);
+	}
+
+	let delta = archive.delta(&changed, &manifest)?;
+
+	let result = delta.stream()
+		.map_err(batlerror::RegistryError::from)
+		.and_then(|stream| registry.publish_delta(
+			repository.name(),
+			&base,
+			progress.track(stream, "upload", (50, 100)),
+			checksum,
+			health,
+			repository.source_url().as_deref()
+		));
+
+	drop(std::fs::remove_file(delta.path()));
+
+	match result {
+		Ok(()) => {
+			info(&format!("Uploaded {} of {} files changed since {}", changed.len(), manifest.len(), base));
+
+			Ok(true)
+		},
+		Err(batlerror::RegistryError::NotFound(_)) => Ok(false),
+		Err(err) => Err(err.into())
+	}
+}
+
+/// Scripts run by `publish --check`, when declared
+const CHECK_SCRIPTS: [&str; 2] = ["check", "test"];
+
+/// Run the declared check scripts of a repository. A
+/// script that can't be started counts as failed.
+fn run_checks(repository: &Repository, context: &BatlContext) -> Option<Health> {
+	let checks = CHECK_SCRIPTS.iter()
+		.filter(|script_name| repository.script(context, script_name).is_some())
+		.map(|script_name| {
+			info(&format!("Running {}", script_name));
+
+			// Checks go through locks, retries and the run
+			// history like any other script
+			let passed = run_chain(repository, script_name, None, None, Vec::new(), ExecOutput::Full, context)
+				.is_ok_and(|status| status.success());
+
+			if passed {
+				success(&format!("{} passed", script_name));
+			} else {
+				warning(&format!("{} failed", script_name));
+			}
+
+			Check::new(script_name.to_string(), passed)
+		})
+		.collect::<Vec<_>>();
+
+	if checks.is_empty() {
+		warning("No check or test scripts to run, publishing without health");
+
+		return None;
+	}
+
+	Some(Health::new(checks))
+}
+
+fn cmd_which(name: String, context: &BatlContext) -> Result<(), UtilityError> {
 	if !BATL_NAME_REGEX.is_match(&name) {
 		return Err(UtilityError::InvalidName(name));
 	}
 
-	let workspace = Repository::load(name.into())?
-		.ok_or(UtilityError::ResourceDoesNotExist("Workspace".into()))?;
+	let name = Name::from(name.as_str()).normalized(context.settings().name_case);
 
-	println!("{}", workspace.path().to_string_lossy());
+	if let Some(path) = Index::open(context.system(), Kind::Repository)?.get(&name) {
+		output_path(path);
+
+		return Ok(());
+	}
+
+	let repository = Repository::load_in(context, name)?
+		.ok_or(UtilityError::ResourceDoesNotExist("Repository".into()))?;
+
+	output_path(repository.path());
+
+	Ok(())
+}
+
+fn cmd_resolve(name: String, version: Option<Version>, with: Vec<VersionedName>, freeze: Option<String>, json: bool, context: &BatlContext) -> Result<(), UtilityError> {
+	if !BATL_NAME_REGEX.is_match(&name) {
+		return Err(UtilityError::InvalidName(name));
+	}
+
+	let mut options = resolve::Options::overrides(with);
+	options.version = version;
+	options.freeze = freeze
+		.map(|tag| Freeze::load(context, &tag)?.ok_or(UtilityError::ResourceDoesNotExist(format!("Freeze {}", tag))))
+		.transpose()?;
+	options.registries = dependency_registries(context);
+
+	let resolved = resolve::resolve(context, &name.as_str().into(), &options)?
+		.ok_or(UtilityError::ResourceDoesNotExist(format!("Repository {}", name)))?;
+
+	let repository = &resolved.repository;
+	let provenance = &resolved.provenance;
+
+	if json {
+		let out = serde_json::to_string_pretty(&serde_json::json!({
+			"name": repository.name().to_string(),
+			"version": repository.config().version.to_string(),
+			"path": repository.path(),
+			"provenance": provenance
+		})).map_err(|e| UtilityError::ConfigError(e.to_string()))?;
+
+		println!("{}", out);
+
+		return Ok(());
+	}
+
+	output_path(repository.path());
+
+	let location = match provenance.location {
+		resolve::Location::Local => "the repository root",
+		resolve::Location::External => "externals in the batlrc",
+		resolve::Location::Fetched => "the fetched versions",
+		_ => "elsewhere"
+	};
+
+	match &provenance.pin {
+		Some(pin) => {
+			let reason = match (pin.kind, &pin.freeze) {
+				(resolve::PinKind::Override, _) => "an override".to_string(),
+				(resolve::PinKind::Freeze, Some(tag)) => format!("freeze {}", tag),
+				_ => "the requested version".to_string()
+			};
+
+			info(&format!("{}@{} from {}, pinned by {}", repository.name(), pin.version, location, reason));
+		},
+		None => info(&format!("{}@{} from {}", repository.name(), repository.config().version, location))
+	}
+
+	if let Some(registry) = &provenance.registry {
+		info(&format!("Comes from registry {}", registry));
+	}
 
 	Ok(())
 }
 
-fn cmd_exec(name: Option<String>, script: String) -> Result<(), UtilityError> {
+fn cmd_exec(name: Option<String>, on: Option<String>, with: Vec<VersionedName>, output: ExecOutput, script_name: String, batch: Batch, context: &BatlContext) -> Result<(), UtilityError> {
+	if let Some(group) = name.as_deref().filter(|name| name.starts_with('@')) {
+		let members = expand_names(group, context)?.into_iter().map(Name::from).collect();
+
+		return exec_across(members, group, on, with, output, script_name, batch, context);
+	}
+
 	let repository = match &name {
 		Some(val) => {
-			Repository::load(val.as_str().into())?
+			Repository::load_in(context, val.as_str().into())?
 		},
-		None => Repository::locate_then_load(&current_dir()?)?
+		None => Repository::locate_then_load_in(context, &current_dir()?)?
 	}.ok_or(UtilityError::ResourceDoesNotExist("Repository".to_string()))?;
 
-	let command = repository.script(&script)
-		.ok_or(UtilityError::ScriptNotFound(script))?;
+	// Meta repositories run scripts they don't have across
+	// their dependencies, as a workspace would
+	if repository.config().kind == RepositoryKind::Meta && repository.script(context, &script_name).is_none() {
+		let mut members = repository.config().dependencies.keys().cloned().collect::<Vec<_>>();
+		members.sort_by_key(|member| member.to_string());
 
-	info(&format!("Running script{}\n", name.map(|s| format!(" for link {}", s)).unwrap_or("".to_string())));
+		return exec_across(members, &format!("{} or its dependencies", repository.name()), on, with, output, script_name, batch, context);
+	}
 
-	let status = std::process::Command::new("sh")
-		.current_dir(repository.path())
-		.arg("-c")
-		.arg(command)
-		.status()?;
+	if repository.script(context, &script_name).is_none() {
+		return Err(UtilityError::ScriptNotFound(script_name));
+	}
 
+	if output == ExecOutput::Full {
+		info(&format!("Running script{}\n", name.map(|s| format!(" for link {}", s)).unwrap_or("".to_string())));
+	}
+
+	let status = run_chain(&repository, &script_name, None, on, with, output, context)?;
 
 	if !status.success() {
-		return Err(UtilityError::ScriptError(format!("Exit code {}", status.code().unwrap_or(0))))
+		return Err(UtilityError::ScriptError(format!("Failed with {}", failure_reason(status))))
+	}
+
+	if output != ExecOutput::Full {
+		return Ok(());
 	}
 
 	println!();
@@ -324,24 +977,633 @@ fn cmd_exec(name: Option<String>, script: String) -> Result<(), UtilityError> {
 	Ok(())
 }
 
-fn cmd_fetch(name: String) -> Result<(), UtilityError> {
-	let url = format!("https://api.batl.circetools.net/pkg/{}", name);
+/// Create a stamp file, or bring its modification time up
+/// to now if it already exists
+fn touch_stamp(stamp: &Path) -> Result<(), UtilityError> {
+	if let Some(parent) = stamp.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+		std::fs::create_dir_all(parent)?;
+	}
+
+	std::fs::OpenOptions::new().create(true).append(true).open(stamp)?
+		.set_modified(std::time::SystemTime::now())?;
 
-	let resp = ureq::get(&url)
-		.call()?;
+	Ok(())
+}
 
-	let body = resp.into_reader();
-	let mut tar = tar::Archive::new(body);
+/// Run a script in each of several repositories that has
+/// it, skipping the rest
+#[allow(clippy::too_many_arguments)]
+fn exec_across(members: Vec<Name>, label: &str, on: Option<String>, with: Vec<VersionedName>, output: ExecOutput, script_name: String, mut batch: Batch, context: &BatlContext) -> Result<(), UtilityError> {
+	let progress = Progress::new(context, "exec");
+	let total = members.len();
+	batch.resumable(context);
 
-	let repository_path = batl::system::repository_root()
-		.ok_or(UtilityError::ResourceDoesNotExist("Battalion setup".to_string()))?
-		.join(PathBuf::from(&Name::from(name.as_str())));
+	for (done, name) in members.into_iter().enumerate() {
+		progress.report("run", Some(done as f64 / total as f64 * 100.0), &name.to_string());
 
-	std::fs::create_dir_all(&repository_path)?;
+		if batch.already_done(&name.to_string()) {
+			continue;
+		}
+
+		let Some(member) = Repository::load_in(context, name.clone())? else {
+			warning(&format!("{} is not available locally, fetch it to run scripts in it", name));
+			continue;
+		};
+
+		if member.script(context, &script_name).is_none() {
+			continue;
+		}
+
+		if output == ExecOutput::Full {
+			info(&format!("Running script for {}\n", name));
+		}
+
+		// Overrides only apply to members that depend on
+		// what they override
+		let overrides = with.iter()
+			.filter(|versioned| member.config().dependencies.contains_key(&versioned.name))
+			.cloned()
+			.collect();
+
+		let outcome = run_chain(&member, &script_name, None, on.clone(), overrides, output, context)
+			.and_then(script_outcome);
+
+		if !batch.record(&name.to_string(), outcome) {
+			break;
+		}
+	}
 
-	tar.unpack(repository_path)?;
+	let ran = batch.total();
+	progress.done(&format!("{} ran in {} repositories, {} failed", script_name, ran, batch.failed().len()));
+
+	if ran == 0 {
+		return Err(UtilityError::ScriptNotFound(format!("{} in {}", script_name, label)));
+	}
+
+	batch.finish()?;
+
+	if output == ExecOutput::Full {
+		success(&format!("{} passed in {} repositories", script_name, ran));
+	}
+
+	Ok(())
+}
+
+/// Run a script in a repository and every dependency it
+/// has locally, each after its own dependencies. Up to
+/// `jobs` repositories that don't depend on each other run
+/// at once. Repositories whose dependencies failed are
+/// skipped.
+#[allow(clippy::too_many_arguments)]
+fn cmd_exec_recursive(name: Option<String>, jobs: usize, on: Option<String>, with: Vec<VersionedName>, output: ExecOutput, script_name: String, mut batch: Batch, context: &BatlContext) -> Result<(), UtilityError> {
+	let repository = match &name {
+		Some(val) => Repository::load_in(context, val.as_str().into())?,
+		None => Repository::locate_then_load_in(context, &current_dir()?)?
+	}.ok_or(UtilityError::ResourceDoesNotExist("Repository".to_string()))?;
+
+	let jobs = jobs.max(1);
+	let label = format!("{} or its dependencies", repository.name());
+	let mut pending = dependency_order(&repository, context)?;
+
+	// Side by side, whole output would interleave beyond
+	// reading
+	let output = if jobs > 1 && output == ExecOutput::Full { ExecOutput::Prefixed } else { output };
+
+	let progress = Progress::new(context, "exec");
+	let total = pending.len();
+	batch.resumable(context);
+
+	let mut finished: HashMap<Name, bool> = HashMap::new();
+	let mut running = 0;
+	let mut stopped = false;
+	let (sender, receiver) = std::sync::mpsc::channel();
+
+	std::thread::scope(|scope| -> Result<(), UtilityError> {
+		loop {
+			while !stopped && running < jobs {
+				let Some(index) = pending.iter().position(|(_, dependencies)| dependencies.iter().all(|dependency| finished.contains_key(dependency))) else {
+					break;
+				};
+
+				let (member_name, dependencies) = pending.remove(index);
+				progress.report("run", Some(finished.len() as f64 / total as f64 * 100.0), &member_name.to_string());
+
+				if let Some(failed) = dependencies.iter().find(|dependency| finished.get(*dependency) == Some(&false)) {
+					finished.insert(member_name.clone(), false);
+
+					let skipped = Err(UtilityError::ScriptError(format!("Skipped, as its dependency {} failed", failed)));
+					stopped = !batch.record(&member_name.to_string(), skipped);
+
+					continue;
+				}
+
+				if batch.already_done(&member_name.to_string()) {
+					finished.insert(member_name, true);
+					continue;
+				}
+
+				let Some(member) = Repository::load_in(context, member_name.clone())? else {
+					finished.insert(member_name, true);
+					continue;
+				};
+
+				if member.script(context, &script_name).is_none() {
+					finished.insert(member_name, true);
+					continue;
+				}
+
+				// Overrides only apply to members that depend on
+				// what they override
+				let overrides = with.iter()
+					.filter(|versioned| member.config().dependencies.contains_key(&versioned.name))
+					.cloned()
+					.collect();
+
+				if output == ExecOutput::Full {
+					info(&format!("Running script for {}\n", member_name));
+				}
+
+				let sender = sender.clone();
+				let on = on.clone();
+				let script_name = &script_name;
+				let system = context.system().clone();
+				running += 1;
+
+				scope.spawn(move || {
+					// The context can't be shared across threads,
+					// so each run gets its own for the same root
+					let context = BatlContext::for_system(system);
+					let outcome = run_chain(&member, script_name, None, on, overrides, output, &context)
+						.and_then(script_outcome);
+
+					drop(sender.send((member_name, outcome)));
+				});
+			}
+
+			if running == 0 {
+				break;
+			}
+
+			let Ok((member_name, outcome)) = receiver.recv() else {
+				break;
+			};
+
+			running -= 1;
+			finished.insert(member_name.clone(), outcome.is_ok());
+
+			if !batch.record(&member_name.to_string(), outcome) {
+				stopped = true;
+			}
+		}
+
+		Ok(())
+	})?;
+
+	let ran = batch.total();
+	progress.done(&format!("{} ran in {} repositories, {} failed", script_name, ran, batch.failed().len()));
+
+	if ran == 0 {
+		return Err(UtilityError::ScriptNotFound(format!("{} in {}", script_name, label)));
+	}
+
+	batch.finish()?;
+
+	if output == ExecOutput::Full || output == ExecOutput::Prefixed {
+		success(&format!("{} passed in {} repositories", script_name, ran));
+	}
+
+	Ok(())
+}
+
+/// A repository and every dependency it has locally, each
+/// after its own dependencies and with those of them that
+/// are local. Dependencies that aren't available locally
+/// are left out, with a warning.
+fn dependency_order(repository: &Repository, context: &BatlContext) -> Result<Vec<(Name, Vec<Name>)>, UtilityError> {
+	let mut edges: HashMap<Name, Vec<Name>> = HashMap::new();
+	let mut queue = vec![repository.name().clone()];
+
+	while let Some(current) = queue.pop() {
+		if edges.contains_key(&current) {
+			continue;
+		}
+
+		let dependencies = if &current == repository.name() {
+			repository.config().dependencies.keys().cloned().collect::<Vec<_>>()
+		} else {
+			match Repository::load_in(context, current.clone())? {
+				Some(loaded) => loaded.config().dependencies.keys().cloned().collect(),
+				None => Vec::new()
+			}
+		};
+
+		let mut local = Vec::new();
+
+		for dependency in dependencies {
+			if edges.contains_key(&dependency) || Repository::load_in(context, dependency.clone())?.is_some() {
+				queue.push(dependency.clone());
+				local.push(dependency);
+			} else {
+				warning(&format!("{} is not available locally, fetch it to run scripts in it", dependency));
+			}
+		}
+
+		local.sort_by_key(|dependency| dependency.to_string());
+		edges.insert(current, local);
+	}
+
+	// Repositories go once everything they depend on has
+	let mut order: Vec<(Name, Vec<Name>)> = Vec::new();
+	let mut remaining = edges.into_iter().collect::<Vec<_>>();
+	remaining.sort_by_key(|(name, _)| name.to_string());
+
+	while !remaining.is_empty() {
+		let (ready, blocked): (Vec<_>, Vec<_>) = remaining.into_iter()
+			.partition(|(_, dependencies)| dependencies.iter().all(|dependency| order.iter().any(|(placed, _)| placed == dependency)));
+
+		if ready.is_empty() {
+			let cycle = blocked.iter().map(|(name, _)| name.to_string()).collect::<Vec<_>>();
+
+			return Err(UtilityError::ConfigError(format!("Dependency cycle between {}", cycle.join(", "))));
+		}
+
+		order.extend(ready);
+		remaining = blocked;
+	}
+
+	Ok(order)
+}
+
+/// Turn the exit status of a script into an error when it
+/// failed
+fn script_outcome(status: std::process::ExitStatus) -> Result<(), UtilityError> {
+	if status.success() {
+		return Ok(());
+	}
+
+	Err(UtilityError::ScriptError(format!("Failed with {}", failure_reason(status))))
+}
+
+fn cmd_retry_failed(on: Option<String>, output: ExecOutput, script_name: String, mut batch: Batch, context: &BatlContext) -> Result<(), UtilityError> {
+	let failed = history::failed(context.system(), &script_name)?;
+
+	if failed.is_empty() {
+		success(&format!("No repositories failed {} last time", script_name));
+		return Ok(());
+	}
+
+	for name in failed {
+		let Some(repository) = Repository::load_in(context, name.clone())? else {
+			warning(&format!("Repository {} no longer exists", name));
+			continue;
+		};
+
+		if repository.script(context, &script_name).is_none() {
+			warning(&format!("{} no longer has the script {}", name, script_name));
+			continue;
+		}
+
+		if output == ExecOutput::Full {
+			info(&format!("Running script for {}\n", name));
+		}
+
+		let outcome = run_chain(&repository, &script_name, None, on.clone(), Vec::new(), output, context)
+			.and_then(script_outcome);
+
+		if !batch.record(&name.to_string(), outcome) {
+			break;
+		}
+	}
+
+	batch.finish()?;
+
+	success(&format!("{} passed everywhere it failed", script_name));
+
+	Ok(())
+}
+
+/// Fetch each repository of a group, going on past the
+/// ones that fail
+#[allow(clippy::too_many_arguments)]
+fn fetch_group(group: &str, paths: Vec<PathBuf>, on_conflict: Option<OnConflict>, git_remote: bool, manifest_only: bool, registry: Option<String>, mut batch: Batch, context: &BatlContext) -> Result<(), UtilityError> {
+	batch.resumable(context);
+
+	for member in expand_names(group, context)? {
+		if batch.already_done(&member) {
+			continue;
+		}
+
+		let outcome = cmd_fetch(member.clone(), paths.clone(), on_conflict, git_remote, manifest_only, registry.clone(), context);
+
+		if !batch.record(&member, outcome) {
+			break;
+		}
+	}
+
+	batch.finish()
+}
+
+fn cmd_fetch(name: String, paths: Vec<PathBuf>, on_conflict: Option<OnConflict>, git_remote: bool, manifest_only: bool, registry: Option<String>, context: &BatlContext) -> Result<(), UtilityError> {
+	if let Some(source) = Source::parse(&name)? {
+		if !paths.is_empty() || manifest_only {
+			return Err(UtilityError::InvalidPath(format!("{} can only be fetched in full", name)));
+		}
+
+		return cmd_fetch_source(source, git_remote, context);
+	}
+
+	let (name, version) = match name.parse::<VersionedName>() {
+		Ok(versioned) => (versioned.name, Some(versioned.version)),
+		Err(_) => (Name::from(name.as_str()), None)
+	};
+
+	let named = registry.or_else(|| dependency_registries(context).remove(&name))
+		.map(|registry_name| Registry::named(context.system(), &registry_name))
+		.transpose()?;
+	let registry = named.as_ref().unwrap_or_else(|| context.registry());
+
+	if manifest_only {
+		let (fetched, path) = fetch_manifest(registry, &name, version.as_ref(), context)?;
+		success(&format!("Fetched the manifest of {}@{} into {}", name, fetched, path.display()));
+
+		return Ok(());
+	}
+
+	if paths.is_empty() {
+		let Some(path) = fetch_repository(registry, &name, version.as_ref(), on_conflict, context)? else {
+			return Ok(());
+		};
+
+		info(&format!("Unpacked into {}", path.display()));
+
+		// Links pinned to an older version would otherwise
+		// stay there without saying so
+		if let Some(fetched) = &version {
+			relink_fetched(&name, fetched, context)?;
+		}
+
+		if git_remote {
+			match registry.metadata(&name)?.source {
+				Some(url) => setup_git_remote(&path, &url),
+				None => warning(&format!("{} was published without a git url, no remote was set up", name))
+			}
+		}
+	} else {
+		let (_, files) = fetch_sparse(registry, &name, version.as_ref(), &paths, context)?;
+		info(&format!("Fetched {} files", files.len()));
+	}
+
+	match version {
+		Some(ver) => success(&format!("Fetched repository {}@{}", name, ver)),
+		None => success(&format!("Fetched repository {}", name))
+	}
+
+	Ok(())
+}
+
+/// The registries the current repository, or one of the
+/// repositories it depends on, names for its dependencies
+fn dependency_registries(context: &BatlContext) -> HashMap<Name, String> {
+	current_dir().ok()
+		.and_then(|dir| Repository::locate_then_load_in(context, &dir).ok().flatten())
+		.map(|repository| repository.dependency_registries(context))
+		.unwrap_or_default()
+}
+
+/// Download a repository from a provider into the
+/// repository root, turning it into a battalion repository
+/// if it isn't one yet
+fn cmd_fetch_source(source: Source, git_remote: bool, context: &BatlContext) -> Result<(), UtilityError> {
+	let name = source.name()
+		.ok_or(UtilityError::InvalidName(format!("no battalion name for {}, clone it with -o instead", source.path)))?;
+
+	let repository_path = context.system().repository_root()
+		.map(|p| p.join(PathBuf::from(&name)))
+		.ok_or(UtilityError::NotSetup)?;
+
+	if repository_path.exists() {
+		return Err(UtilityError::ResourceAlreadyExists(format!("Repository {} at {}", name, repository_path.display())));
+	}
+
+	info(&format!("Downloading {}", source.archive_url()));
+
+	if let Err(err) = source.download(&repository_path) {
+		// Leave no partial download behind to trip the next
+		// fetch
+		drop(std::fs::remove_dir_all(&repository_path));
+
+		return Err(err.into());
+	}
+
+	if !repository_path.join("batl.toml").exists() {
+		Repository::create_in_place(context, &repository_path, Some(name.clone()), Default::default())?;
+		info("Added a batl.toml");
+	}
+
+	Ownership::touch(&repository_path)?;
+
+	if git_remote {
+		setup_git_remote(&repository_path, &source.git_url());
+	}
 
 	success(&format!("Fetched repository {}", name));
 
 	Ok(())
 }
+
+/// Point the `origin` remote of a fetched copy at the git
+/// url it came from, making it a git repository if it
+/// isn't one. The remote's default branch is fetched and
+/// checked out over the index only, so the files stay as
+/// they were fetched and git shows how they differ.
+/// Failures only warn, since the fetch itself worked.
+fn setup_git_remote(path: &Path, url: &str) {
+	match add_git_remote(path, url) {
+		Ok(Some(branch)) => info(&format!("Tracking {} of {}", branch, url)),
+		Ok(None) => info(&format!("Added remote origin for {}, run git fetch to get its history", url)),
+		Err(err) => warning(&format!("Could not set up a git remote for {}: {}", url, err.message()))
+	}
+}
+
+/// Add the remote and track its default branch. Returns
+/// `None` if the remote couldn't be reached, leaving it
+/// configured for later.
+fn add_git_remote(path: &Path, url: &str) -> Result<Option<String>, git2::Error> {
+	let cloned = match git2::Repository::open(path) {
+		Ok(existing) => existing,
+		Err(_) => git2::Repository::init(path)?
+	};
+
+	if cloned.find_remote("origin").is_ok() {
+		return Err(git2::Error::from_str("it already has a remote named origin"));
+	}
+
+	let mut remote = cloned.remote("origin", url)?;
+
+	if remote.connect(Direction::Fetch).is_err() {
+		return Ok(None);
+	}
+
+	let default = remote.default_branch()?;
+	remote.disconnect()?;
+
+	let default_name = default.as_str()
+		.and_then(|full| full.strip_prefix("refs/heads/"))
+		.ok_or_else(|| git2::Error::from_str("the remote has no default branch"))?
+		.to_string();
+
+	remote.fetch(&[&default_name], None, None)?;
+
+	let commit = cloned.find_reference(&format!("refs/remotes/origin/{}", default_name))?
+		.peel_to_commit()?;
+
+	let mut branch = cloned.branch(&default_name, &commit, false)?;
+	branch.set_upstream(Some(&format!("origin/{}", default_name)))?;
+
+	cloned.set_head(&format!("refs/heads/{}", default_name))?;
+	cloned.reset(commit.as_object(), ResetType::Mixed, None)?;
+
+	Ok(Some(default_name))
+}
+
+fn cmd_search(query: String, context: &BatlContext) -> Result<(), UtilityError> {
+	let registry = context.registry();
+	let results = registry.search(&query)?;
+	api_notice(registry);
+
+	for result in results {
+		let latest = result.latest.map(|v| v.to_string()).unwrap_or("-".to_string());
+
+		match result.description {
+			Some(description) => println!("{}@{}\t{}", result.name, latest, description),
+			None => println!("{}@{}", result.name, latest)
+		}
+	}
+
+	Ok(())
+}
+
+fn cmd_info(name: String, context: &BatlContext) -> Result<(), UtilityError> {
+	if !BATL_NAME_REGEX.is_match(&name) {
+		return Err(UtilityError::InvalidName(name));
+	}
+
+	// A dependency from a named registry is checked against
+	// that one, so the latest version shown is the one it
+	// would update to
+	let named = dependency_registries(context).remove(&name.as_str().into())
+		.map(|registry_name| Registry::named(context.system(), &registry_name))
+		.transpose()?;
+	let registry = named.as_ref().unwrap_or_else(|| context.registry());
+	let metadata = registry.metadata(&name.as_str().into())?;
+	api_notice(registry);
+
+	println!("Name: {}", metadata.name);
+
+	// The registry doesn't know kinds, so they come from
+	// the local copy or a fetched manifest when there is one
+	let local = Repository::load_in(context, name.as_str().into()).ok().flatten()
+		.or_else(|| metadata.latest.as_ref()
+			.and_then(|latest| Repository::load_manifest(context, &metadata.name, latest).ok().flatten()));
+
+	if let Some(repository) = &local {
+		println!("Kind: {}", repository.config().kind);
+	}
+
+	if let Some(description) = metadata.description {
+		println!("Description: {}", description);
+	}
+
+	if let Some(source) = metadata.source {
+		println!("Source: {}", source);
+	}
+
+	if let Some(latest) = metadata.latest {
+		println!("Latest: {}", latest);
+	}
+
+	let versions: Vec<String> = metadata.versions.iter().map(|v| v.to_string()).collect();
+	println!("Versions: {}", versions.join(", "));
+
+	if let Some(repository) = &local {
+		let mut dependencies = repository.config().dependencies.iter()
+			.map(|(dependency, requirement)| match repository.config().registries.get(dependency) {
+				Some(registry) => format!("{} {} ({})", dependency, requirement, registry),
+				None => format!("{} {}", dependency, requirement)
+			})
+			.collect::<Vec<_>>();
+		dependencies.sort();
+
+		if !dependencies.is_empty() {
+			println!("Dependencies: {}", dependencies.join(", "));
+		}
+	}
+
+	let mut checked = metadata.health.iter().collect::<Vec<_>>();
+	checked.sort_by(|a, b| b.0.cmp(a.0));
+
+	if !checked.is_empty() {
+		println!("Health:");
+	}
+
+	for (version, health) in checked {
+		let checks: Vec<String> = health.checks.iter()
+			.map(|check| format!("{} {}", check.script, if check.passed { "passed" } else { "failed" }))
+			.collect();
+
+		let status = if health.passed() { "verified" } else { "failing" };
+
+		println!("  {}: {} ({}, batl {} on {})", version, status, checks.join(", "), health.batl_version, health.platform);
+	}
+
+	Ok(())
+}
+
+fn cmd_register(name: String, path: PathBuf, context: &BatlContext) -> Result<(), UtilityError> {
+	if !BATL_NAME_REGEX.is_match(&name) {
+		return Err(UtilityError::InvalidName(name));
+	}
+
+	let path = path.canonicalize()?;
+
+	if !path.join("batl.toml").exists() {
+		return Err(UtilityError::ResourceDoesNotExist(format!("Batallion config in {}", path.display())));
+	}
+
+	Repository::register_external(context, &name.as_str().into(), &path)?;
+
+	success(&format!("Registered external repository {}", name));
+
+	Ok(())
+}
+
+fn cmd_unregister(name: String, context: &BatlContext) -> Result<(), UtilityError> {
+	if !BATL_NAME_REGEX.is_match(&name) {
+		return Err(UtilityError::InvalidName(name));
+	}
+
+	Repository::unregister_external(context, &name.as_str().into())?;
+
+	success(&format!("Unregistered external repository {}", name));
+
+	Ok(())
+}
+
+fn cmd_copy(from: String, to: String, strip_git: bool, context: &BatlContext) -> Result<(), UtilityError> {
+	if !BATL_NAME_REGEX.is_match(&from) {
+		return Err(UtilityError::InvalidName(from));
+	}
+
+	if !BATL_NAME_REGEX.is_match(&to) {
+		return Err(UtilityError::InvalidName(to));
+	}
+
+	let repository = Repository::load_in(context, from.as_str().into())?
+		.ok_or(UtilityError::ResourceDoesNotExist(format!("Repository {}", from)))?;
+
+	repository.duplicate(context, to.as_str().into(), &CopyRepositoryOptions::strip_git(strip_git))?;
+
+	success(&format!("Copied repository {} to {}", from, to));
+
+	Ok(())
+}
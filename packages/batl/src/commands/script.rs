@@ -0,0 +1,82 @@
+use batl::context::BatlContext;
+use batl::resource::{Repository, Resource};
+use clap::Subcommand;
+use crate::output::*;
+use crate::utils::UtilityError;
+use std::env::current_dir;
+
+
+#[derive(Subcommand)]
+pub enum Commands {
+	/// Add a script to a repository's batl.toml
+	Add {
+		#[arg(short = 'n')]
+		repository: Option<String>,
+		name: String,
+		command: String,
+		/// Replace the command of an existing script,
+		/// keeping the rest of its settings
+		#[arg(long = "force")]
+		force: bool
+	},
+	/// Remove a script from a repository's batl.toml
+	#[command(alias = "remove")]
+	Rm {
+		#[arg(short = 'n')]
+		repository: Option<String>,
+		name: String
+	},
+	/// List the scripts that can be run in a repository
+	Ls {
+		#[arg(short = 'n')]
+		repository: Option<String>
+	}
+}
+
+pub fn run(cmd: Commands, context: &BatlContext) -> Result<(), UtilityError> {
+	match cmd {
+		Commands::Add { repository, name, command, force } => {
+			cmd_add(repository, name, command, force, context)
+		},
+		Commands::Rm { repository, name } => {
+			cmd_rm(repository, name, context)
+		},
+		Commands::Ls { repository } => {
+			super::cmd_scripts(repository, context)
+		}
+	}
+}
+
+fn cmd_add(repository: Option<String>, name: String, command: String, force: bool, context: &BatlContext) -> Result<(), UtilityError> {
+	let mut repository = load(repository, context)?;
+	let replaced = repository.scripts().contains_key(&name);
+
+	repository.add_script(context, &name, command, force)?;
+
+	if replaced {
+		success(&format!("Replaced script {} in {}", name, repository.name()));
+	} else {
+		success(&format!("Added script {} to {}", name, repository.name()));
+	}
+
+	Ok(())
+}
+
+fn cmd_rm(repository: Option<String>, name: String, context: &BatlContext) -> Result<(), UtilityError> {
+	let mut repository = load(repository, context)?;
+
+	repository.remove_script(&name)?;
+
+	success(&format!("Removed script {} from {}", name, repository.name()));
+
+	Ok(())
+}
+
+/// Load the named repository, or the one the current
+/// directory is in
+fn load(name: Option<String>, context: &BatlContext) -> Result<Repository, UtilityError> {
+	match name {
+		Some(val) => Repository::load_in(context, val.as_str().into())?,
+		None => Repository::locate_then_load_in(context, &current_dir()?)?
+	}.ok_or(UtilityError::ResourceDoesNotExist("Repository".to_string()))
+}
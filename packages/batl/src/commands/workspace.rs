@@ -1,6 +1,10 @@
-use batl::resource::{Resource, Name, Workspace};
+use batl::context::BatlContext;
+use batl::index::{Index, Kind};
+use batl::resource::{Resource, Name, Repository, Template, Workspace};
 use clap::Subcommand;
 use crate::output::*;
+use crate::output::name as output_name;
+use crate::output::path as output_path;
 use crate::utils::{UtilityError, BATL_NAME_REGEX};
 use std::path::PathBuf;
 
@@ -11,99 +15,109 @@ pub enum Commands {
 		filter: Option<String>
 	},
 	Init {
-		name: String
+		name: String,
+		/// Link this repository and its local dependencies
+		/// into the new workspace
+		#[arg(long = "from")]
+		from: Option<String>
 	},
 	Delete {
 		name: String
 	},
 	Which {
 		name: String
+	},
+	Template {
+		#[command(subcommand)]
+		cmd: TemplateCommands
+	}
+}
+
+#[derive(Subcommand)]
+pub enum TemplateCommands {
+	Apply {
+		template: String,
+		workspace: String
 	}
 }
 
-pub fn run(cmd: Commands) -> Result<(), UtilityError> {
+pub fn run(cmd: Commands, context: &BatlContext) -> Result<(), UtilityError> {
 	match cmd {
 		Commands::Ls { filter } => {
-			cmd_ls(filter)
+			cmd_ls(filter, context)
 		},
-		Commands::Init { name } => {
-			cmd_init(name)
+		Commands::Init { name, from } => {
+			cmd_init(name, from, context)
 		},
 		Commands::Delete { name } => {
-			cmd_delete(name)
+			cmd_delete(name, context)
 		},
 		Commands::Which { name } => {
-			cmd_which(name)
+			cmd_which(name, context)
+		},
+		Commands::Template { cmd: TemplateCommands::Apply { template, workspace } } => {
+			cmd_template_apply(template, workspace, context)
 		}
 	}
 }
 
-fn cmd_ls(filter: Option<String>) -> Result<(), UtilityError> {
-	let workspace_root = batl::system::workspace_root()
-		.ok_or(UtilityError::ResourceDoesNotExist("Workspace root".to_string()))?;
-
-	let mut to_search: Vec<(String, PathBuf)> = std::fs::read_dir(workspace_root)?
-		.filter_map(|entry| {
-			Some(("".to_string(), entry.ok()?.path()))
-		})
-		.collect();
-	let mut found: Vec<String> = Vec::new();
-
-	while let Some((name, path)) = to_search.pop() {
-		if !path.is_dir() {
-			continue;
-		}
-
-		let filename = path.file_name().unwrap().to_str().unwrap();
+fn cmd_ls(filter: Option<String>, context: &BatlContext) -> Result<(), UtilityError> {
+	let index = Index::open(context.system(), Kind::Workspace)?;
 
-		if filename.starts_with('@') {
-			let new_name = filename[1..].to_string();
-			let new_name = format!("{}{}/", name, new_name);
-
-			to_search.extend(
-				std::fs::read_dir(path)?
-					.filter_map(|entry| {
-						Some((new_name.clone(), entry.ok()?.path()))
-					})
-			);
-		} else {
-			found.push(format!("{}{}", name, filename));
-		}
-	}
-
-	for name in found {
+	for name in index.names().iter().map(|name| name.to_string()) {
 		if let Some(filter_str) = &filter {
 			if !name.starts_with(filter_str) {
 				continue;
 			}
 		}
 
-		println!("{}", name);
+		println!("{}", output_name(&name));
 	}
 
 	Ok(())
 }
 
-fn cmd_init(name: String) -> Result<(), UtilityError> {
+fn cmd_init(name: String, from: Option<String>, context: &BatlContext) -> Result<(), UtilityError> {
 	if !BATL_NAME_REGEX.is_match(&name) {
 		return Err(UtilityError::InvalidName(name));
 	}
 
 	let name: Name = name.into();
 
-	Workspace::create(name.clone())?;
+	match from {
+		Some(from) => {
+			let repository = Repository::load_in(context, from.as_str().into())?
+				.ok_or(UtilityError::ResourceDoesNotExist(format!("Repository {}", from)))?;
+
+			let (workspace, missing) = Workspace::create_from_repository_in(context, name.clone(), &repository)?;
+
+			let mut links = workspace.links().into_iter().collect::<Vec<_>>();
+			links.sort_by(|a, b| a.0.cmp(&b.0));
+
+			for (link, target) in links {
+				println!("  {} -> {}", link, output_name(&target.to_string()));
+			}
+
+			for dependency in missing {
+				warning(&format!("{} is not available locally, so it wasn't linked", dependency));
+			}
+		},
+		None => {
+			Workspace::create_in(context, name.clone())?;
+		}
+	}
 
 	success(&format!("Workspace {} initialized", name.clone()));
 
 	Ok(())
 }
 
-fn cmd_delete(name: String) -> Result<(), UtilityError> {
+fn cmd_delete(name: String, context: &BatlContext) -> Result<(), UtilityError> {
 	if !BATL_NAME_REGEX.is_match(&name) {
 		return Err(UtilityError::InvalidName(name));
 	}
 
-	let workspace = Workspace::load(name.as_str().into())?
+	let workspace = Workspace::load_in(context, name.as_str().into())?
 		.ok_or(UtilityError::ResourceDoesNotExist("Workspace".into()))?;
 
 	workspace.destroy()?;
@@ -113,15 +127,54 @@ fn cmd_delete(name: String) -> Result<(), UtilityError> {
 	Ok(())
 }
 
-fn cmd_which(name: String) -> Result<(), UtilityError> {
+fn cmd_which(name: String, context: &BatlContext) -> Result<(), UtilityError> {
 	if !BATL_NAME_REGEX.is_match(&name) {
 		return Err(UtilityError::InvalidName(name));
 	}
 
-	let workspace = Workspace::load(name.into())?
+	let name = Name::from(name.as_str()).normalized(context.settings().name_case);
+
+	let path = Index::open(context.system(), Kind::Workspace)?
+		.get(&name)
+		.map(|path| path.to_path_buf())
 		.ok_or(UtilityError::ResourceDoesNotExist("Workspace".into()))?;
 
-	println!("{}", workspace.path().to_string_lossy());
+	output_path(&path);
+
+	Ok(())
+}
+
+fn cmd_template_apply(template: String, workspace: String, context: &BatlContext) -> Result<(), UtilityError> {
+	if !BATL_NAME_REGEX.is_match(&workspace) {
+		return Err(UtilityError::InvalidName(workspace));
+	}
+
+	let template_path = PathBuf::from(&template);
+
+	let template = if template_path.is_file() {
+		Template::from_path(&template_path)?
+	} else {
+		if !BATL_NAME_REGEX.is_match(&template) {
+			return Err(UtilityError::InvalidName(template));
+		}
+
+		let name = Name::from(template.as_str());
+
+		match Template::load(context, &name)? {
+			Some(local) => local,
+			None => {
+				info(&format!("Fetching template {}", name));
+
+				let manifest = context.registry().template(&name)?;
+
+				Template::save_toml(context, &manifest)?
+			}
+		}
+	};
+
+	let workspace = template.apply(context, &workspace.into())?;
+
+	success(&format!("Applied template {} to workspace {}", template.name(), workspace.name()));
 
 	Ok(())
 }
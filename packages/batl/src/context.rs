@@ -0,0 +1,206 @@
+#![allow(clippy::module_name_repetitions, reason = "BatlContext is told apart from the system::Context it holds")]
+
+use core::cell::OnceCell;
+use crate::registry::Registry;
+use crate::resource::NameCase;
+use crate::system;
+use serde::Serialize;
+use std::env::var as env_var;
+use std::path::PathBuf;
+
+
+/// Everything an operation looks up about the battalion it
+/// runs in, created once and passed along instead of being
+/// read again from the environment and batlrc at every step
+///
+/// Functions that don't take a context yet detect one on
+/// each call, the same as before contexts existed.
+#[non_exhaustive]
+pub struct BatlContext {
+	/// Where battalion keeps its files
+	system: system::Context,
+
+	/// Settings read from the environment and batlrc
+	settings: Settings,
+
+	/// The registry client, created when first used
+	registry: OnceCell<Registry>,
+
+	/// Where operations report how they are going
+	output: Box<dyn Output>
+}
+
+/// Where operations report how they are going, so each
+/// frontend can show it its own way, also from worker
+/// threads
+pub trait Output: Send + Sync {
+	/// Report a step of a long running operation
+	fn progress(&self, event: &ProgressEvent<'_>);
+}
+
+/// A step of a long running operation, such as a fetch or
+/// a publish
+#[derive(Debug, Clone, Copy, Serialize)]
+#[non_exhaustive]
+pub struct ProgressEvent<'event> {
+	/// The operation as a whole
+	pub operation: &'event str,
+
+	/// The part of the operation being worked on
+	pub phase: &'event str,
+
+	/// How much of the whole operation is done, if known
+	pub percent: Option<f64>,
+
+	/// What is being worked on, such as a name or path
+	pub message: &'event str
+}
+
+/// Output that drops every report, which contexts start
+/// with
+#[derive(Debug, Clone, Copy, Default)]
+#[non_exhaustive]
+pub struct Silent;
+
+/// The settings that change how battalion reads resources
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Settings {
+	/// Reject config keys that aren't part of the schema
+	pub strict: bool,
+
+	/// Treat references to unset environment variables in
+	/// configs as errors
+	pub strict_env: bool,
+
+	/// How the case of resource names is treated
+	pub name_case: NameCase
+}
+
+impl BatlContext {
+	/// Detect the battalion root and read its settings
+	#[inline]
+	#[must_use]
+	pub fn detect() -> Self {
+		Self::for_system(system::Context::detect())
+	}
+
+	/// Detect the battalion root with the given overrides
+	/// taking precedence, and read its settings
+	#[inline]
+	#[must_use]
+	pub fn detect_with(overrides: &system::Overrides) -> Self {
+		Self::for_system(system::Context::detect_with(overrides))
+	}
+
+	/// A context for the given battalion root, skipping
+	/// detection
+	#[inline]
+	#[must_use]
+	pub fn with_root(root: PathBuf) -> Self {
+		Self::for_system(system::Context::with_root(root))
+	}
+
+	/// A context reading its settings from the given
+	/// system context
+	#[inline]
+	#[must_use]
+	pub fn for_system(system: system::Context) -> Self {
+		Self {
+			settings: Settings::read(&system),
+			system,
+			registry: OnceCell::new(),
+			output: Box::new(Silent)
+		}
+	}
+
+	/// Report through the given output instead
+	#[inline]
+	#[must_use]
+	pub fn with_output(mut self, output: Box<dyn Output>) -> Self {
+		self.output = output;
+		self
+	}
+
+	/// Read resources with the given settings instead of
+	/// those of the environment and batlrc
+	#[inline]
+	#[must_use]
+	pub const fn with_settings(mut self, settings: Settings) -> Self {
+		self.settings = settings;
+		self
+	}
+
+	/// Get where battalion keeps its files
+	#[inline]
+	#[must_use]
+	pub const fn system(&self) -> &system::Context {
+		&self.system
+	}
+
+	/// Get the settings read from the environment and
+	/// batlrc
+	#[inline]
+	#[must_use]
+	pub const fn settings(&self) -> &Settings {
+		&self.settings
+	}
+
+	/// Get where operations report how they are going
+	#[inline]
+	#[must_use]
+	pub fn output(&self) -> &dyn Output {
+		self.output.as_ref()
+	}
+
+	/// Get the registry client configured by the batlrc
+	#[inline]
+	pub fn registry(&self) -> &Registry {
+		self.registry.get_or_init(|| Registry::from_system(&self.system))
+	}
+}
+
+impl<'event> ProgressEvent<'event> {
+	#[inline]
+	#[must_use]
+	pub const fn new(operation: &'event str, phase: &'event str, percent: Option<f64>, message: &'event str) -> Self {
+		Self { operation, phase, percent, message }
+	}
+}
+
+impl Output for Silent {
+	#[inline]
+	fn progress(&self, _event: &ProgressEvent<'_>) {}
+}
+
+impl Settings {
+	/// Read the settings of a battalion root. The
+	/// `BATL_STRICT` and `BATL_STRICT_ENV` environment
+	/// variables take precedence over the batlrc.
+	#[inline]
+	#[must_use]
+	pub fn read(system: &system::Context) -> Self {
+		let batlrc = system.batlrc();
+		let config = batlrc.as_ref().and_then(|rc| rc.config.as_ref());
+
+		Self {
+			strict: env_flag("BATL_STRICT")
+				.unwrap_or_else(|| config.and_then(|given| given.strict).unwrap_or_default()),
+			strict_env: env_flag("BATL_STRICT_ENV")
+				.unwrap_or_else(|| config.and_then(|given| given.strict_env).unwrap_or_default()),
+			name_case: batlrc.as_ref()
+				.and_then(|rc| rc.names.as_ref())
+				.and_then(|names| names.case)
+				.map(NameCase::from)
+				.unwrap_or_default()
+		}
+	}
+}
+
+/// Read a flag from the environment, where empty, `0`, and
+/// `false` turn it off. Returns `None` if it isn't set.
+fn env_flag(name: &str) -> Option<bool> {
+	env_var(name)
+		.ok()
+		.map(|flag| !matches!(flag.as_str(), "" | "0" | "false"))
+}
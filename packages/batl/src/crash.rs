@@ -0,0 +1,207 @@
+use crate::resource::tomlconfig;
+use crate::system;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+
+/// Where a bundle keeps its report, within its folder
+const REPORT_FILE: &str = "report.json";
+
+/// How many of the latest script runs a bundle keeps
+const LOG_TAIL_LINES: usize = 20;
+
+/// Environment variables kept besides `BATL_*` ones, which
+/// say how batl was run without saying much about the user
+const KEPT_VARIABLES: [&str; 5] = ["PATH", "SHELL", "TERM", "LANG", "CI"];
+
+/// Parts of variable names and flags whose values are
+/// never written into a bundle
+const SENSITIVE: [&str; 6] = ["TOKEN", "SECRET", "PASSWORD", "KEY", "CREDENTIAL", "AUTH"];
+
+/// What a redacted value is replaced with
+const REDACTED: &str = "<redacted>";
+
+
+/// How batl failed
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum Kind {
+	Panic,
+
+	/// An error batl couldn't explain, such as an IO error
+	Error
+}
+
+/// A diagnostic bundle about a failure, kept locally in
+/// `gen/crash/<time>/` to attach to a bug report. Nothing
+/// is ever sent anywhere.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Report {
+	/// When batl failed, which also names the bundle
+	pub stamp: String,
+	pub kind: Kind,
+	pub message: String,
+
+	/// Where in batl it panicked, for panics
+	pub location: Option<String>,
+
+	/// The arguments batl was run with, with the values of
+	/// sensitive flags redacted
+	pub args: Vec<String>,
+
+	/// The version of batl
+	pub batl_version: String,
+
+	/// The newest config version batl reads
+	pub config_version: String,
+
+	/// The version the batl.toml of the current directory
+	/// declares, if there is one
+	pub local_config_version: Option<String>,
+	pub os: String,
+
+	/// `BATL_*` and a few general variables, with sensitive
+	/// values redacted
+	pub env: HashMap<String, String>,
+
+	/// The latest script runs from the run history
+	pub log_tail: Vec<String>
+}
+
+impl Report {
+	/// Gather a report about a failure of the running batl,
+	/// with the latest runs from the history of its root
+	#[inline]
+	#[must_use]
+	pub fn new(system: &system::Context, kind: Kind, message: String, location: Option<String>) -> Self {
+		let env = std::env::vars()
+			.filter(|variable| variable.0.starts_with("BATL_") || KEPT_VARIABLES.contains(&variable.0.as_str()))
+			.map(|(key, value)| {
+				let redacted = if is_sensitive(&key) { REDACTED.to_owned() } else { value };
+
+				(key, redacted)
+			})
+			.collect();
+
+		let local_config_version = std::env::current_dir().ok()
+			.and_then(|dir| std::fs::read_to_string(dir.join("batl.toml")).ok())
+			.and_then(|config| tomlconfig::declared_version(&config).ok())
+			.map(|version| version.to_string());
+
+		Self {
+			stamp: crate::stamped::now(),
+			kind,
+			message,
+			location,
+			args: redact_args(std::env::args().skip(1)),
+			batl_version: env!("CARGO_PKG_VERSION").to_owned(),
+			config_version: crate::version::LATEST.to_owned(),
+			local_config_version,
+			os: format!("{} {}", std::env::consts::OS, std::env::consts::ARCH),
+			env,
+			log_tail: log_tail(system)
+		}
+	}
+
+	/// Write the bundle under the given root, returning its
+	/// folder
+	///
+	/// # Errors
+	///
+	/// Propogates any IO errors while writing it
+	#[inline]
+	pub fn write(&mut self, crash_root: &Path) -> Result<PathBuf, std::io::Error> {
+		// Failures in the same second get bundles of their own
+		let base = self.stamp.clone();
+		let mut attempt = 1u32;
+
+		while crash_root.join(&self.stamp).exists() {
+			attempt = attempt.saturating_add(1);
+			self.stamp = format!("{base}-{attempt}");
+		}
+
+		let dir = crash_root.join(&self.stamp);
+		std::fs::create_dir_all(&dir)?;
+
+		crate::stamped::write(&dir.join(REPORT_FILE), self)?;
+
+		Ok(dir)
+	}
+
+	/// Load the bundle written at a time
+	///
+	/// # Errors
+	///
+	/// Returns `NotFound` if there is no such bundle.
+	/// Propogates any other IO errors while reading it, and
+	/// fails if its report can't be read.
+	#[inline]
+	pub fn load(crash_root: &Path, stamp: &str) -> Result<Self, std::io::Error> {
+		if !crate::stamped::is_valid(stamp) {
+			return Err(std::io::Error::new(std::io::ErrorKind::NotFound, format!("no bundle {stamp}")));
+		}
+
+		crate::stamped::read(&crash_root.join(stamp).join(REPORT_FILE))
+	}
+
+	/// The times of every bundle, oldest first
+	///
+	/// # Errors
+	///
+	/// Propogates any IO errors while listing the bundles
+	#[inline]
+	pub fn list(crash_root: &Path) -> Result<Vec<String>, std::io::Error> {
+		crate::stamped::list(crash_root, REPORT_FILE)
+	}
+}
+
+/// Whether a variable or flag name may hold a secret
+fn is_sensitive(name: &str) -> bool {
+	let upper = name.to_ascii_uppercase();
+
+	SENSITIVE.iter().any(|part| upper.contains(part))
+}
+
+/// Redact the values of sensitive flags, given either as
+/// `--flag value` or `--flag=value`
+fn redact_args<I>(args: I) -> Vec<String>
+where
+	I: Iterator<Item = String>
+{
+	let mut redact_next = false;
+
+	args.map(|arg| {
+		if redact_next {
+			redact_next = false;
+
+			return REDACTED.to_owned();
+		}
+
+		if !arg.starts_with('-') || !is_sensitive(&arg) {
+			return arg;
+		}
+
+		if let Some((flag, _)) = arg.split_once('=') {
+			return format!("{flag}={REDACTED}");
+		}
+
+		redact_next = true;
+
+		arg
+	}).collect()
+}
+
+/// The latest lines of the run history
+fn log_tail(system: &system::Context) -> Vec<String> {
+	let Some(contents) = system.history_path()
+		.and_then(|path| std::fs::read_to_string(path).ok()) else {
+		return Vec::new();
+	};
+
+	let skip = contents.lines().count().saturating_sub(LOG_TAIL_LINES);
+
+	contents.lines().skip(skip).map(str::to_owned).collect()
+}
@@ -0,0 +1,118 @@
+use crate::context::BatlContext;
+use crate::resource::{Repository, Resource as _};
+use pulldown_cmark::{html, Options, Parser};
+
+
+/// File names a README is looked for under, in order
+const README_NAMES: [&str; 4] = ["README.md", "readme.md", "README", "README.txt"];
+
+/// Styles of the documentation pages
+const STYLE: &str = "\
+body { font-family: system-ui, sans-serif; max-width: 52rem; margin: 2rem auto; padding: 0 1rem; line-height: 1.5; }
+h1 small { color: #777; font-weight: normal; }
+code, pre { background: #f4f4f4; border-radius: 3px; }
+pre { padding: 0.75rem; overflow-x: auto; }
+td { padding: 0.2rem 1rem 0.2rem 0; vertical-align: top; }
+.missing { color: #a33; }";
+
+
+/// Render the documentation page of a repository, with its
+/// README and what its batl.toml declares. Dependencies
+/// that exist locally link to their own page at
+/// `/<name>`.
+#[inline]
+#[must_use]
+pub fn render(context: &BatlContext, repository: &Repository) -> String {
+	let config = repository.config();
+	let name = escape(&repository.name().to_string());
+
+	let mut page = format!(
+		"<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{name}</title>\n<style>\n{STYLE}\n</style>\n</head>\n<body>\n<h1>{name} <small>{}</small></h1>\n",
+		config.version
+	);
+
+	let mut dependencies = config.dependencies.iter().collect::<Vec<_>>();
+	dependencies.sort_by_key(|&(dependency, _)| dependency.to_string());
+
+	if !dependencies.is_empty() {
+		let items = dependencies.into_iter()
+			.map(|(dependency, requirement)| {
+				let dependency_name = escape(&dependency.to_string());
+				let version = escape(requirement);
+
+				if Repository::load_in(context, dependency.clone()).ok().flatten().is_some() {
+					format!("<li><a href=\"/{dependency_name}\">{dependency_name}</a> {version}</li>\n")
+				} else {
+					format!("<li>{dependency_name} {version} <span class=\"missing\">(missing)</span></li>\n")
+				}
+			})
+			.collect::<Vec<_>>();
+
+		page.push_str("<h2>Dependencies</h2>\n<ul>\n");
+		page.push_str(&items.concat());
+		page.push_str("</ul>\n");
+	}
+
+	let mut scripts = config.scripts.iter().collect::<Vec<_>>();
+	scripts.sort_by_key(|&(script, _)| script);
+
+	if !scripts.is_empty() {
+		let rows = scripts.into_iter()
+			.map(|(script, settings)| format!(
+				"<tr><td><code>{}</code></td><td><code>{}</code></td></tr>\n",
+				escape(script),
+				escape(&settings.command)
+			))
+			.collect::<Vec<_>>();
+
+		page.push_str("<h2>Scripts</h2>\n<table>\n");
+		page.push_str(&rows.concat());
+		page.push_str("</table>\n");
+	}
+
+	if let Some(readme) = readme(repository) {
+		page.push_str("<hr>\n");
+		page.push_str(&readme);
+	}
+
+	page.push_str("</body>\n</html>\n");
+
+	page
+}
+
+/// Render the README of a repository to HTML, if it has
+/// one. READMEs that aren't markdown are shown as is.
+#[inline]
+#[must_use]
+pub fn readme(repository: &Repository) -> Option<String> {
+	let (file, contents) = README_NAMES.iter()
+		.find_map(|file| std::fs::read_to_string(repository.path().join(file)).ok().map(|contents| (*file, contents)))?;
+
+	if !file.to_lowercase().ends_with(".md") {
+		return Some(format!("<pre>{}</pre>\n", escape(&contents)));
+	}
+
+	let mut rendered = String::new();
+	html::push_html(&mut rendered, Parser::new_ext(&contents, Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS | Options::ENABLE_FOOTNOTES));
+
+	Some(rendered)
+}
+
+/// Escape text for use in HTML
+#[inline]
+#[must_use]
+pub fn escape(text: &str) -> String {
+	text.chars()
+		.fold(String::with_capacity(text.len()), |mut escaped, c| {
+			match c {
+				'&' => escaped.push_str("&amp;"),
+				'<' => escaped.push_str("&lt;"),
+				'>' => escaped.push_str("&gt;"),
+				'"' => escaped.push_str("&quot;"),
+				'\'' => escaped.push_str("&#39;"),
+				_ => escaped.push(c)
+			}
+
+			escaped
+		})
+}
@@ -1,5 +1,6 @@
 #![allow(clippy::module_name_repetitions)]
 
+use serde::Serialize;
 use thiserror::Error;
 
 
@@ -9,7 +10,19 @@ pub enum ReadConfigError {
 	#[error("{0}")]
 	IoError(#[from] std::io::Error),
 	#[error("{0}")]
-	TomlError(#[from] toml::de::Error)
+	TomlError(#[from] toml::de::Error),
+	#[error("Config does not declare environment.version")]
+	MissingVersion,
+	#[error("Unknown config version {0}")]
+	UnknownVersion(String),
+	#[error("Config written by a newer version of battalion ({0})")]
+	NewerVersion(String),
+	#[error("Unknown keys in config: {0}")]
+	UnknownKeys(String),
+	#[error("Invalid config schema: {0}")]
+	InvalidSchema(String),
+	#[error("Environment variable {0} is not set")]
+	UndefinedVariable(String)
 }
 
 #[derive(Debug, Error)]
@@ -20,7 +33,13 @@ pub enum CreateResourceError {
 	#[error("Battalion not set up")]
 	NotSetup,
 	#[error("Resource already exists")]
-	AlreadyExists
+	AlreadyExists,
+	#[error("Invalid resource name: {0}")]
+	InvalidName(String),
+	#[error("Name collides with existing resource {0}")]
+	Collision(String),
+	#[error("Invalid starter file: {0}")]
+	InvalidStarter(String)
 }
 
 #[derive(Debug, Error)]
@@ -31,7 +50,29 @@ pub enum CreateDependentResourceError {
 	#[error("Error while creating resource: {0}")]
 	Creation(#[from] CreateResourceError),
 	#[error("Error while getting dependents: {0}")]
-	Dependent(#[from] GeneralResourceError)
+	Dependent(#[from] GeneralResourceError),
+	#[error("{0}")]
+	Preflight(#[from] PreflightError),
+	#[error("Error while resolving dependency: {0}")]
+	Resolve(#[from] ResolveError)
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum PreflightError {
+	#[error("Not enough disk space: {0}")]
+	NotEnoughSpace(String),
+	#[error("Path too long: {0}")]
+	PathTooLong(String)
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum PathNameError {
+	#[error("Path is not valid UTF-8: {0}")]
+	Undecodable(String),
+	#[error("Path does not follow the resource layout: {0}")]
+	Layout(String)
 }
 
 #[derive(Debug, Error)]
@@ -39,10 +80,18 @@ pub enum CreateDependentResourceError {
 pub enum GeneralResourceError {
 	#[error("IO Error: {0}")]
 	IoError(#[from] std::io::Error),
+	#[error("Invalid resource path: {0}")]
+	InvalidPath(#[from] PathNameError),
 	#[error("Resource does not exist")]
 	DoesNotExist,
 	#[error("Resource invalid/corrupted")]
-	Invalid
+	Invalid,
+	#[error("Invalid config: {0}")]
+	InvalidConfig(String),
+	#[error("Config written by a newer version of battalion ({0})")]
+	NewerVersion(String),
+	#[error("Declared name does not match where the resource is stored: {0}")]
+	NameMismatch(String)
 }
 
 impl From<ReadConfigError> for GeneralResourceError {
@@ -53,7 +102,13 @@ impl From<ReadConfigError> for GeneralResourceError {
 				e.kind() == std::io::ErrorKind::NotFound
 			} => Self::DoesNotExist,
 			ReadConfigError::IoError(e) => e.into(),
-			ReadConfigError::TomlError(_) => Self::Invalid
+			ReadConfigError::NewerVersion(version) => Self::NewerVersion(version),
+			ReadConfigError::TomlError(e) => Self::InvalidConfig(e.to_string()),
+			ReadConfigError::UnknownVersion(version) => Self::InvalidConfig(format!("Unknown config version {version}")),
+			ReadConfigError::MissingVersion => Self::InvalidConfig("Config does not declare environment.version".to_owned()),
+			ReadConfigError::UnknownKeys(keys) => Self::InvalidConfig(format!("Unknown keys in config: {keys}")),
+			ReadConfigError::InvalidSchema(err) => Self::InvalidConfig(format!("Invalid config schema: {err}")),
+			ReadConfigError::UndefinedVariable(name) => Self::InvalidConfig(format!("Environment variable {name} is not set"))
 		}
 	}
 }
@@ -66,3 +121,277 @@ pub enum DeleteResourceError {
 	#[error("Resource does not exist")]
 	DoesNotExist
 }
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum ExecuteError {
+	#[error("IO Error: {0}")]
+	IoError(#[from] std::io::Error),
+	#[error("Container engine not available: {0}")]
+	EngineUnavailable(String),
+	#[error("Script interpreter not available: {0}")]
+	InterpreterUnavailable(String),
+	#[error("Could not sync to remote host: {0}")]
+	SyncFailed(String),
+	#[error("Script timed out after {0}")]
+	TimedOut(crate::units::Duration),
+	#[error("Invalid lock name: {0}")]
+	InvalidLock(String),
+	#[error("Resource limit can't be enforced: {0}")]
+	LimitUnsupported(String)
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum RegistryError {
+	#[error("Network Error: {0}")]
+	Network(String),
+	#[error("Not authorized: {0}")]
+	Unauthorized(String),
+	#[error("Package not found: {0}")]
+	NotFound(String),
+	#[error("Version already exists: {0}")]
+	VersionExists(String),
+	#[error("Invalid package: {0}")]
+	InvalidPackage(String),
+	#[error("Invalid registry response: {0}")]
+	InvalidResponse(String),
+	#[error("Registry error ({0}): {1}")]
+	Other(u16, String),
+	#[error("Not supported by this registry: {0}")]
+	Unsupported(String),
+	#[error("Token lacks the required scope: {0}")]
+	InsufficientScope(String),
+	#[error("Incompatible registry API: {0}")]
+	IncompatibleApi(String),
+	#[error("No registry named {0} in the batlrc")]
+	UnknownRegistry(String),
+	#[error("IO Error: {0}")]
+	IoError(#[from] std::io::Error)
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum NotifyError {
+	#[error("IO Error: {0}")]
+	IoError(#[from] std::io::Error),
+	#[error("Network Error: {0}")]
+	Network(String),
+	#[error("Notification command failed: {0}")]
+	CommandFailed(String),
+	#[error("Not supported on this platform: {0}")]
+	Unsupported(String)
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum BootstrapError {
+	#[error("IO Error: {0}")]
+	IoError(#[from] std::io::Error),
+	#[error("Network Error: {0}")]
+	Network(String),
+	#[error("Invalid bootstrap manifest: {0}")]
+	Invalid(String),
+	#[error("Bootstrap manifest too large, over {0} bytes")]
+	TooLarge(u64)
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum ResolveError {
+	#[error("Error while loading resource: {0}")]
+	Resource(#[from] GeneralResourceError),
+	#[error("Error while reading freeze: {0}")]
+	Freeze(#[from] FreezeError)
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum FreezeError {
+	#[error("IO Error: {0}")]
+	IoError(#[from] std::io::Error),
+	#[error("Battalion not set up")]
+	NotSetup,
+	#[error("Invalid freeze tag: {0}")]
+	InvalidTag(String),
+	#[error("Freeze already exists: {0}")]
+	AlreadyExists(String),
+	#[error("Dependency not available locally: {0}")]
+	Missing(String),
+	#[error("Error while loading resource: {0}")]
+	Resource(#[from] GeneralResourceError),
+	#[error("Invalid freeze manifest: {0}")]
+	Invalid(String)
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum EditScriptError {
+	#[error("IO Error: {0}")]
+	IoError(#[from] std::io::Error),
+	#[error("Invalid script name: {0}")]
+	InvalidName(String),
+	#[error("Script already exists: {0}")]
+	AlreadyExists(String),
+	#[error("Script does not exist: {0}")]
+	DoesNotExist(String),
+	#[error("Environment variable {0} is not set")]
+	UndefinedVariable(String)
+}
+
+impl From<ReadConfigError> for EditScriptError {
+	#[inline]
+	fn from(value: ReadConfigError) -> Self {
+		match value {
+			ReadConfigError::IoError(e) => e.into(),
+			ReadConfigError::UndefinedVariable(name) => Self::UndefinedVariable(name),
+			other @ (
+				ReadConfigError::TomlError(_)
+				| ReadConfigError::MissingVersion
+				| ReadConfigError::UnknownVersion(_)
+				| ReadConfigError::NewerVersion(_)
+				| ReadConfigError::UnknownKeys(_)
+				| ReadConfigError::InvalidSchema(_)
+			) => Self::IoError(std::io::Error::new(std::io::ErrorKind::InvalidData, other))
+		}
+	}
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum ScriptChainError {
+	#[error("Script {0}, needed by {1}, does not exist")]
+	Missing(String, String),
+	#[error("Scripts depend on each other in a cycle: {0}")]
+	Cycle(String)
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum FetchCheckError {
+	#[error("IO Error: {0}")]
+	IoError(#[from] std::io::Error),
+	#[error("has no batl.toml")]
+	MissingConfig,
+	#[error("has an invalid batl.toml: {0}")]
+	InvalidConfig(#[from] ReadConfigError),
+	#[error("declares name {0} instead of {1}")]
+	NameMismatch(String, String),
+	#[error("declares version {0} instead of {1}")]
+	VersionMismatch(String, String)
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum IntegrityError {
+	#[error("IO Error: {0}")]
+	IoError(#[from] std::io::Error),
+	#[error("has no published checksum")]
+	MissingChecksum,
+	#[error("does not match its checksum")]
+	ChecksumMismatch,
+	#[error("is not signed by a trusted publisher")]
+	Untrusted,
+	#[error("has an invalid signature by {0}")]
+	BadSignature(String),
+	#[error("Invalid key: {0}")]
+	InvalidKey(String)
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum OwnershipError {
+	#[error("IO Error: {0}")]
+	IoError(#[from] std::io::Error),
+	#[error("Created by {0}")]
+	NotOwner(String),
+	#[error("Read only: {0}")]
+	ReadOnly(String)
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum ApplyTemplateError {
+	#[error("IO Error: {0}")]
+	IoError(#[from] std::io::Error),
+	#[error("Error while creating resource: {0}")]
+	Creation(#[from] CreateResourceError),
+	#[error("Error while loading resource: {0}")]
+	Resource(#[from] GeneralResourceError),
+	#[error("Repository missing: {0}")]
+	MissingRepository(String),
+	#[error("Link already points to another repository: {0}")]
+	LinkConflict(String)
+}
+
+/// The failures of a batch operation, collected so the
+/// rest of its items still run
+#[derive(Debug, Clone, Default, Serialize)]
+#[non_exhaustive]
+pub struct BatchError {
+	/// How many items were attempted
+	pub total: usize,
+	pub failures: Vec<BatchFailure>
+}
+
+/// One item of a batch operation that failed
+#[derive(Debug, Clone, Serialize)]
+#[non_exhaustive]
+pub struct BatchFailure {
+	/// What failed, usually a repository name
+	pub item: String,
+	pub message: String,
+
+	/// The exit code the failure would have on its own
+	pub code: i32
+}
+
+impl BatchError {
+	/// Note an item that went through
+	#[inline]
+	pub const fn succeeded(&mut self) {
+		self.total = self.total.saturating_add(1);
+	}
+
+	/// Note an item that failed
+	#[inline]
+	pub fn failed(&mut self, item: String, message: String, code: i32) {
+		self.total = self.total.saturating_add(1);
+		self.failures.push(BatchFailure { item, message, code });
+	}
+
+	/// Whether every item went through
+	#[inline]
+	#[must_use]
+	pub const fn is_empty(&self) -> bool {
+		self.failures.is_empty()
+	}
+
+	/// The exit code of the whole operation: the code the
+	/// failures share, or 1 when they differ
+	#[inline]
+	#[must_use]
+	pub fn exit_code(&self) -> i32 {
+		let mut codes = self.failures.iter().map(|failure| failure.code);
+
+		match codes.next() {
+			None => 0,
+			Some(first) if codes.all(|code| code == first) => first,
+			Some(_) => 1
+		}
+	}
+}
+
+impl core::fmt::Display for BatchError {
+	#[inline]
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		let items = self.failures.iter()
+			.map(|failure| failure.item.as_str())
+			.collect::<Vec<_>>();
+
+		write!(f, "{} of {} failed: {}", self.failures.len(), self.total, items.join(", "))
+	}
+}
+
+#[allow(clippy::missing_trait_methods, reason = "a batch has no single source, and the other methods are deprecated")]
+impl core::error::Error for BatchError {}
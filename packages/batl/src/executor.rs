@@ -0,0 +1,277 @@
+use crate::context::BatlContext;
+use crate::error as batlerror;
+use crate::resolve;
+use crate::resource::{Name, Repository, Resource as _, Script};
+use crate::resource::script::Limits;
+use crate::units::Duration;
+use semver::Version;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::fs::File;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::time::Instant;
+
+pub mod container;
+/// Memory and CPU limits of scripts while they run
+mod limits;
+pub mod lock;
+pub mod shell;
+pub mod ssh;
+
+pub use self::container::Container;
+pub use self::lock::Lock;
+pub use self::shell::{Interpreter, Shell};
+pub use self::ssh::Ssh;
+
+
+/// How often a script with a timeout is checked on
+const POLL_INTERVAL: core::time::Duration = core::time::Duration::from_millis(100);
+
+
+/// A backend capable of running scripts
+pub trait Executor {
+	/// Runs the invocation to completion, returning
+	/// the exit status of the script.
+	/// 
+	/// # Errors
+	/// 
+	/// Returns any errors received while starting or
+	/// waiting for the script
+	fn execute(&self, invocation: &Invocation) -> Result<ExitStatus, batlerror::ExecuteError>;
+}
+
+/// A single script run, independent of the backend
+/// that will execute it
+#[non_exhaustive]
+pub struct Invocation {
+	/// The shell command to run
+	pub command: String,
+
+	/// The directory the command runs in
+	pub workdir: PathBuf,
+
+	/// Other repositories the command has access to
+	pub mounts: Vec<Mount>,
+
+	/// Extra environment variables for the command
+	pub env: HashMap<String, String>,
+
+	/// Resource limits for the command
+	pub limits: Limits,
+
+	/// A file to write the output of the command to, in
+	/// place of the terminal
+	pub log: Option<PathBuf>
+}
+
+/// A repository made available to a script
+#[non_exhaustive]
+pub struct Mount {
+	/// The name of the mounted repository
+	pub name: Name,
+
+	/// The path of the repository on the host
+	pub path: PathBuf,
+
+	/// The version of the mounted repository
+	pub version: Version
+}
+
+impl Invocation {
+	/// Create an invocation for a script inside of a
+	/// repository. Every dependency of the repository
+	/// that exists locally is mounted alongside it. The
+	/// environment of the repository is set under that of
+	/// the script, along with `BATL_REPO_NAME` and
+	/// `BATL_REPO_VERSION`.
+	#[inline]
+	#[must_use]
+	pub fn for_repository(context: &BatlContext, repository: &Repository, script: &Script) -> Self {
+		// Scripts that run batl again use the same root, and
+		// are strict when this run is
+		let mut env = context.system().env();
+		env.extend(repository.config().env.clone());
+		env.extend(script.env.clone());
+		env.insert("BATL_REPO_NAME".to_owned(), repository.name().to_string());
+		env.insert("BATL_REPO_VERSION".to_owned(), repository.config().version.to_string());
+
+		if context.settings().strict {
+			env.insert("BATL_STRICT".to_owned(), "1".to_owned());
+		}
+
+		let mut invocation = Self {
+			command: script.command.clone(),
+			workdir: repository.path().to_path_buf(),
+			mounts: Vec::new(),
+			env,
+			limits: script.limits,
+			log: None
+		};
+
+		let dependencies = repository.config().dependencies.keys()
+			.filter_map(|name| resolve::resolve(context, name, &resolve::Options::default()).ok().flatten());
+
+		for dependency in dependencies {
+			invocation.mount(&dependency.repository);
+		}
+
+		invocation
+	}
+
+	/// Run the script for a link of a workspace, setting the
+	/// environment of the link over the rest and exposing
+	/// its name as `BATL_LINK_NAME`
+	#[inline]
+	pub fn for_link(&mut self, link: &str, env: HashMap<String, String>) {
+		self.env.extend(env);
+		self.env.insert("BATL_LINK_NAME".to_owned(), link.to_owned());
+	}
+
+	/// Make a dependency available to the script, replacing
+	/// any other version of it. Its path and version are
+	/// exposed as `BATL_DEP_<NAME>_PATH` and
+	/// `BATL_DEP_<NAME>_VERSION`.
+	#[inline]
+	pub fn mount(&mut self, dependency: &Repository) {
+		self.mounts.retain(|mount| mount.name != *dependency.name());
+
+		self.mounts.push(Mount {
+			name: dependency.name().clone(),
+			path: dependency.path().to_path_buf(),
+			version: dependency.config().version.clone()
+		});
+	}
+
+	/// The full environment of the script, as seen from
+	/// wherever the executor places the repository and its
+	/// dependencies. Along with the extra variables, this
+	/// sets `BATL_OUT`, and `BATL_DEP_<NAME>_PATH`,
+	/// `BATL_DEP_<NAME>_OUT` and `BATL_DEP_<NAME>_VERSION`
+	/// for every mount.
+	#[inline]
+	#[must_use]
+	pub fn environment<F>(&self, workdir: &Path, mount_path: F) -> HashMap<String, String>
+	where
+		F: Fn(&Mount) -> PathBuf
+	{
+		let mut env = self.env.clone();
+
+		env.insert("BATL_OUT".to_owned(), out_path(workdir).display().to_string());
+
+		for mount in &self.mounts {
+			let key = env_key(&mount.name);
+			let path = mount_path(mount);
+
+			env.insert(format!("BATL_DEP_{key}_PATH"), path.display().to_string());
+			env.insert(format!("BATL_DEP_{key}_OUT"), out_path(&path).display().to_string());
+			env.insert(format!("BATL_DEP_{key}_VERSION"), mount.version.to_string());
+		}
+
+		env
+	}
+}
+
+/// Get the artifact directory of a repository at a path
+#[inline]
+#[must_use]
+pub fn out_path(repository: &Path) -> PathBuf {
+	repository.join("gen").join("out")
+}
+
+/// Get the environment variable form of a name, such
+/// as `LIB_UI` for `lib/ui`
+#[inline]
+#[must_use]
+pub fn env_key(name: &Name) -> String {
+	name.to_string()
+		.chars()
+		.map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+		.collect()
+}
+
+/// Select the executor backend for a script, running it
+/// through the interpreter the batlrc sets unless the
+/// script picks one
+#[inline]
+#[must_use]
+pub fn for_script(system: &crate::system::Context, script: &Script) -> Box<dyn Executor> {
+	match script.container.clone() {
+		Some(container) => Box::new(Container::new(container)),
+		None => Box::new(script.shell.map_or_else(|| Shell::configured(system), Shell::new))
+	}
+}
+
+/// Where the stdout and stderr of a script go, either
+/// the terminal or both into the invocation log
+pub(crate) fn output(invocation: &Invocation) -> Result<(Stdio, Stdio), std::io::Error> {
+	let Some(log) = invocation.log.as_ref() else {
+		return Ok((Stdio::inherit(), Stdio::inherit()));
+	};
+
+	if let Some(parent) = log.parent() {
+		std::fs::create_dir_all(parent)?;
+	}
+
+	let stdout = File::create(log)?;
+	let stderr = stdout.try_clone()?;
+
+	Ok((stdout.into(), stderr.into()))
+}
+
+/// Start scripts with a timeout in a process group of
+/// their own, so that everything they start can be killed
+/// along with them. Other scripts stay in the group of the
+/// terminal, where they can read from it and get its
+/// signals.
+pub(crate) fn grouped(command: &mut Command, timeout: Option<Duration>) -> &mut Command {
+	#[cfg(unix)]
+	if timeout.is_some() {
+		std::os::unix::process::CommandExt::process_group(command, 0);
+	}
+
+	#[cfg(not(unix))]
+	let _ = timeout;
+
+	command
+}
+
+/// Wait for a script to finish, killing it and its process
+/// group if it runs past the timeout
+pub(crate) fn wait(mut child: Child, timeout: Option<Duration>) -> Result<ExitStatus, batlerror::ExecuteError> {
+	let Some(limit) = timeout else {
+		return Ok(child.wait()?);
+	};
+
+	let start = Instant::now();
+
+	loop {
+		if let Some(status) = child.try_wait()? {
+			return Ok(status);
+		}
+
+		if start.elapsed() >= limit.get() {
+			kill(&mut child)?;
+			child.wait()?;
+
+			return Err(batlerror::ExecuteError::TimedOut(limit));
+		}
+
+		std::thread::sleep(POLL_INTERVAL);
+	}
+}
+
+/// Kill a script started by [`grouped`], along with the
+/// processes it started
+fn kill(child: &mut Child) -> Result<(), std::io::Error> {
+	#[cfg(unix)]
+	{
+		let group = rustix::process::Pid::from_child(child);
+
+		// The group is gone when the script exited just now
+		if rustix::process::kill_process_group(group, rustix::process::Signal::Kill).is_ok() {
+			return Ok(());
+		}
+	}
+
+	child.kill()
+}
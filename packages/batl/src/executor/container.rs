@@ -0,0 +1,125 @@
+use crate::error as batlerror;
+use crate::resource::script::Container as ContainerSettings;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+use super::{Executor, Invocation, Mount};
+
+
+/// Where the repository is mounted inside of the container
+const REPOSITORY_MOUNT: &str = "/batl/repository";
+
+/// Where dependencies are mounted inside of the container
+const DEPENDENCY_MOUNT: &str = "/batl/dependencies";
+
+/// Engines that are searched for when none is configured
+const ENGINES: [&str; 2] = ["docker", "podman"];
+
+
+/// Runs scripts inside of a docker or podman container,
+/// with the repository and its dependencies bind-mounted
+pub struct Container {
+	/// The container settings of the script
+	settings: ContainerSettings
+}
+
+impl Container {
+	/// Create a container executor from script settings
+	#[inline]
+	#[must_use]
+	pub const fn new(settings: ContainerSettings) -> Self {
+		Self {
+			settings
+		}
+	}
+
+	/// Find the container engine to use, preferring the
+	/// one set in the script configuration
+	fn engine(&self) -> Result<String, batlerror::ExecuteError> {
+		if let Some(engine) = self.settings.engine.as_ref() {
+			return Ok(engine.clone());
+		}
+
+		ENGINES.iter()
+			.find(|engine| {
+				Command::new(engine)
+					.arg("--version")
+					.stdout(Stdio::null())
+					.stderr(Stdio::null())
+					.status()
+					.is_ok_and(|status| status.success())
+			})
+			.map(|engine| (*engine).to_owned())
+			.ok_or_else(|| batlerror::ExecuteError::EngineUnavailable(ENGINES.join("/")))
+	}
+}
+
+impl Executor for Container {
+	#[inline]
+	fn execute(&self, invocation: &Invocation) -> Result<ExitStatus, batlerror::ExecuteError> {
+		let engine = self.engine()?;
+
+		std::fs::create_dir_all(super::out_path(&invocation.workdir))?;
+		let container_name = format!("batl-{}", rand::random::<u32>());
+
+		let mut command = Command::new(&engine);
+
+		command.args(["run", "--rm", "-i", "--name", &container_name])
+			.arg("-v")
+			.arg(format!("{}:{REPOSITORY_MOUNT}", invocation.workdir.display()))
+			.arg("-w")
+			.arg(REPOSITORY_MOUNT);
+
+		for mount in &invocation.mounts {
+			command.arg("-v")
+				.arg(format!("{}:{}", mount.path.display(), mount_target(mount).display()));
+		}
+
+		let env = invocation.environment(Path::new(REPOSITORY_MOUNT), mount_target);
+
+		command.args(
+			env.iter()
+				.flat_map(|(key, value)| ["-e".to_owned(), format!("{key}={value}")])
+		);
+
+		if let Some(max_memory) = invocation.limits.max_memory {
+			command.arg("--memory").arg(max_memory.bytes().to_string());
+		}
+
+		if let Some(max_cpu) = invocation.limits.max_cpu {
+			command.arg("--ulimit").arg(format!("cpu={}", max_cpu.get().as_secs().max(1)));
+		}
+
+		let (stdout, stderr) = super::output(invocation)?;
+
+		let child = command.arg(&self.settings.image)
+			.arg("sh")
+			.arg("-c")
+			.arg(&invocation.command)
+			.stdout(stdout)
+			.stderr(stderr)
+			.spawn()
+			.map_err(|err| if err.kind() == std::io::ErrorKind::NotFound {
+				batlerror::ExecuteError::EngineUnavailable(engine.clone())
+			} else {
+				err.into()
+			})?;
+
+		let result = super::wait(child, invocation.limits.timeout);
+
+		if let Err(batlerror::ExecuteError::TimedOut(_)) = result {
+			// Killing the client does not always stop the container
+			Command::new(&engine)
+				.args(["rm", "-f", &container_name])
+				.stdout(Stdio::null())
+				.stderr(Stdio::null())
+				.status()?;
+		}
+
+		result
+	}
+}
+
+/// Where a dependency is mounted inside of the container
+fn mount_target(mount: &Mount) -> PathBuf {
+	PathBuf::from(DEPENDENCY_MOUNT).join(mount.name.to_string())
+}
@@ -0,0 +1,240 @@
+use crate::error as batlerror;
+use crate::resource::script::Limits;
+use std::process::{Child, Command};
+
+
+/// The memory and CPU limits of a script while it runs.
+/// On Windows this holds the job object the script is
+/// assigned to, which is closed once it is dropped.
+pub(super) struct Confinement {
+	#[cfg(windows)]
+	job: Option<windows::Job>
+}
+
+/// Set up a command so the memory and CPU limits apply to
+/// the script it starts, along with everything the script
+/// starts in turn
+///
+/// On unix they are set with `setrlimit` in the child
+/// before it runs the script. Windows confines the script
+/// to a job object once it is started instead.
+#[cfg(unix)]
+pub(super) fn prepare(command: &mut Command, limits: &Limits) -> Result<(), batlerror::ExecuteError> {
+	unix::prepare(command, limits)
+}
+
+/// Set up a command so the memory and CPU limits apply to
+/// the script it starts. Windows confines the script to a
+/// job object once it is started, so there is nothing to
+/// set up before.
+#[cfg(windows)]
+#[allow(clippy::unnecessary_wraps, reason = "unix can fail to set up the limits")]
+pub(super) const fn prepare(_command: &mut Command, _limits: &Limits) -> Result<(), batlerror::ExecuteError> {
+	Ok(())
+}
+
+/// Fail with the limits that are set, since there is no way
+/// to enforce them on this platform
+#[cfg(not(any(unix, windows)))]
+pub(super) fn prepare(_command: &mut Command, limits: &Limits) -> Result<(), batlerror::ExecuteError> {
+	unsupported(limits, "on this platform")
+}
+
+/// Apply the limits to a started script, where they can
+/// only be applied after it starts. A script that can't be
+/// confined is killed.
+#[cfg(windows)]
+pub(super) fn confine(child: &mut Child, limits: &Limits) -> Result<Confinement, batlerror::ExecuteError> {
+	let job = windows::Job::confine(child, limits)
+		.inspect_err(|_| drop(child.kill()))?;
+
+	Ok(Confinement { job })
+}
+
+/// Apply the limits to a started script. Outside of Windows
+/// they were set up before it started, so this never fails.
+#[cfg(not(windows))]
+#[allow(clippy::unnecessary_wraps, reason = "windows can fail to confine a started script")]
+pub(super) const fn confine(_child: &mut Child, _limits: &Limits) -> Result<Confinement, batlerror::ExecuteError> {
+	Ok(Confinement {})
+}
+
+/// Fail with the limits that are set, for backends or
+/// platforms that can't enforce them
+pub(super) fn unsupported(limits: &Limits, place: &str) -> Result<(), batlerror::ExecuteError> {
+	let set = [
+		limits.max_memory.map(|_| "max_memory"),
+		limits.max_cpu.map(|_| "max_cpu")
+	].into_iter().flatten().collect::<Vec<_>>();
+
+	if set.is_empty() {
+		return Ok(());
+	}
+
+	Err(batlerror::ExecuteError::LimitUnsupported(format!("{} {place}", set.join(" and "))))
+}
+
+/// Limits set with `setrlimit`
+///
+/// Memory is limited with `RLIMIT_DATA` rather than
+/// `RLIMIT_AS`. Runtimes such as the JVM, Go and Node
+/// reserve far more address space than they use, and fail
+/// to start under an address space limit near what they
+/// actually need.
+#[cfg(unix)]
+mod unix {
+	use crate::error as batlerror;
+	use crate::resource::script::Limits;
+	use crate::units::ByteSize;
+	use rustix::process::{Resource, Rlimit};
+	use std::os::unix::process::CommandExt as _;
+	use std::process::Command;
+
+	/// Lower the limits in the child between fork and exec
+	pub(super) fn prepare(command: &mut Command, limits: &Limits) -> Result<(), batlerror::ExecuteError> {
+		// macOS takes the limit without keeping to it
+		if cfg!(target_os = "macos") && limits.max_memory.is_some() {
+			return Err(batlerror::ExecuteError::LimitUnsupported("max_memory on macOS".to_owned()));
+		}
+
+		let memory = limits.max_memory.map(ByteSize::bytes);
+		let cpu = limits.max_cpu.map(|max_cpu| max_cpu.get().as_secs().max(1));
+
+		if memory.is_none() && cpu.is_none() {
+			return Ok(());
+		}
+
+		let lower_all = move || -> Result<(), std::io::Error> {
+			if let Some(bytes) = memory {
+				lower(Resource::Data, bytes)?;
+			}
+
+			if let Some(seconds) = cpu {
+				lower(Resource::Cpu, seconds)?;
+			}
+
+			Ok(())
+		};
+
+		// SAFETY: the closure only makes the getrlimit and
+		// setrlimit system calls, which are async-signal-safe,
+		// and allocates nothing
+		#[allow(unsafe_code, reason = "pre_exec is the only way to set limits of the child alone")]
+		unsafe {
+			command.pre_exec(lower_all)
+		};
+
+		Ok(())
+	}
+
+	/// Lower a limit, both the soft and hard one, so the
+	/// script can't raise it again. Hard limits that are
+	/// already lower are kept.
+	fn lower(resource: Resource, wanted: u64) -> Result<(), std::io::Error> {
+		let value = rustix::process::getrlimit(resource).maximum
+			.map_or(wanted, |hard| wanted.min(hard));
+
+		rustix::process::setrlimit(resource, Rlimit { current: Some(value), maximum: Some(value) })?;
+
+		Ok(())
+	}
+}
+
+/// Limits set with job objects
+#[cfg(windows)]
+mod windows {
+	use crate::resource::script::Limits;
+	use std::os::windows::io::AsRawHandle as _;
+	use std::process::Child;
+	use windows_sys::Win32::Foundation::{CloseHandle, HANDLE};
+	use windows_sys::Win32::System::JobObjects::{
+		AssignProcessToJobObject,
+		CreateJobObjectW,
+		JobObjectExtendedLimitInformation,
+		SetInformationJobObject,
+		JOBOBJECT_EXTENDED_LIMIT_INFORMATION,
+		JOB_OBJECT_LIMIT_JOB_MEMORY,
+		JOB_OBJECT_LIMIT_JOB_TIME
+	};
+
+	/// Units of 100 nanoseconds in a second, which job
+	/// object times are counted in
+	const TICKS_PER_SECOND: i64 = 10_000_000;
+
+	/// A job object a script is assigned to, closed when
+	/// dropped
+	pub(super) struct Job(HANDLE);
+
+	impl Job {
+		/// Assign a started script to a new job object with
+		/// the limits, or return `None` if there are none.
+		/// Processes the script starts before it is assigned
+		/// are not limited.
+		#[allow(unsafe_code, reason = "job objects are only reached through the win32 api")]
+		pub(super) fn confine(child: &Child, limits: &Limits) -> Result<Option<Self>, std::io::Error> {
+			if limits.max_memory.is_none() && limits.max_cpu.is_none() {
+				return Ok(None);
+			}
+
+			// SAFETY: both arguments may be null, for no
+			// security attributes and no name
+			let handle = unsafe { CreateJobObjectW(core::ptr::null(), core::ptr::null()) };
+
+			if handle == 0 {
+				return Err(std::io::Error::last_os_error());
+			}
+
+			let job = Self(handle);
+
+			// SAFETY: the structure is plain data, for which all
+			// zeroes means no limits
+			let mut info: JOBOBJECT_EXTENDED_LIMIT_INFORMATION = unsafe { core::mem::zeroed() };
+
+			if let Some(max_memory) = limits.max_memory {
+				info.BasicLimitInformation.LimitFlags |= JOB_OBJECT_LIMIT_JOB_MEMORY;
+				info.JobMemoryLimit = usize::try_from(max_memory.bytes()).unwrap_or(usize::MAX);
+			}
+
+			if let Some(max_cpu) = limits.max_cpu {
+				let seconds = i64::try_from(max_cpu.get().as_secs().max(1)).unwrap_or(i64::MAX);
+
+				info.BasicLimitInformation.LimitFlags |= JOB_OBJECT_LIMIT_JOB_TIME;
+				info.BasicLimitInformation.PerJobUserTimeLimit = seconds.saturating_mul(TICKS_PER_SECOND);
+			}
+
+			let size = u32::try_from(core::mem::size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>()).unwrap_or(u32::MAX);
+
+			// SAFETY: the job handle is open, and the
+			// information is the size given for its class
+			let set = unsafe {
+				SetInformationJobObject(job.0, JobObjectExtendedLimitInformation, core::ptr::from_ref(&info).cast(), size)
+			};
+
+			if set == 0 {
+				return Err(std::io::Error::last_os_error());
+			}
+
+			// SAFETY: the job handle is open, and the process
+			// handle is owned by the child, which outlives the
+			// call
+			let assigned = unsafe { AssignProcessToJobObject(job.0, child.as_raw_handle() as HANDLE) };
+
+			if assigned == 0 {
+				return Err(std::io::Error::last_os_error());
+			}
+
+			Ok(Some(job))
+		}
+	}
+
+	impl Drop for Job {
+		#[allow(unsafe_code, reason = "job objects are only reached through the win32 api")]
+		fn drop(&mut self) {
+			// SAFETY: the handle was opened by CreateJobObjectW
+			// and is only closed here
+			unsafe {
+				CloseHandle(self.0);
+			}
+		}
+	}
+}
@@ -0,0 +1,102 @@
+use crate::error as batlerror;
+use crate::system;
+use std::fs::{File, OpenOptions, TryLockError};
+use std::path::PathBuf;
+
+
+/// A named lock shared by every repository, held until
+/// dropped. Locks are files under `gen/locks`, so they
+/// hold across separate runs of batl as well.
+#[non_exhaustive]
+pub struct Lock {
+	/// The name scripts refer to the lock by
+	name: String,
+
+	/// The locked file, unlocked when closed
+	file: File
+}
+
+impl Lock {
+	/// Take a lock, waiting for whoever holds it to
+	/// release it
+	///
+	/// # Errors
+	///
+	/// Returns `InvalidLock` for names that can't be used
+	/// as a file name, and propogates any IO errors
+	#[inline]
+	pub fn acquire(system: &system::Context, name: &str) -> Result<Self, batlerror::ExecuteError> {
+		let file = open(system, name)?;
+		file.lock()?;
+
+		Ok(Self {
+			name: name.to_owned(),
+			file
+		})
+	}
+
+	/// Take a lock if nobody holds it, returning `None`
+	/// otherwise
+	///
+	/// # Errors
+	///
+	/// Returns `InvalidLock` for names that can't be used
+	/// as a file name, and propogates any IO errors
+	#[inline]
+	pub fn try_acquire(system: &system::Context, name: &str) -> Result<Option<Self>, batlerror::ExecuteError> {
+		let file = open(system, name)?;
+
+		match file.try_lock() {
+			Ok(()) => Ok(Some(Self {
+				name: name.to_owned(),
+				file
+			})),
+			Err(TryLockError::WouldBlock) => Ok(None),
+			Err(TryLockError::Error(err)) => Err(err.into())
+		}
+	}
+
+	#[inline]
+	#[must_use]
+	pub fn name(&self) -> &str {
+		&self.name
+	}
+
+	/// Get the file backing a lock
+	///
+	/// # Errors
+	///
+	/// Returns `InvalidLock` for names that can't be used
+	/// as a file name
+	#[inline]
+	pub fn path(system: &system::Context, name: &str) -> Result<Option<PathBuf>, batlerror::ExecuteError> {
+		let valid = !name.is_empty()
+			&& !name.starts_with('.')
+			&& name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'));
+
+		if !valid {
+			return Err(batlerror::ExecuteError::InvalidLock(name.to_owned()));
+		}
+
+		Ok(system.lock_root().map(|root| root.join(format!("{name}.lock"))))
+	}
+}
+
+impl Drop for Lock {
+	#[inline]
+	fn drop(&mut self) {
+		drop(self.file.unlock());
+	}
+}
+
+/// Open the file of a lock, creating it if needed
+fn open(system: &system::Context, name: &str) -> Result<File, batlerror::ExecuteError> {
+	let path = Lock::path(system, name)?
+		.ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "Battalion is not set up"))?;
+
+	if let Some(parent) = path.parent() {
+		std::fs::create_dir_all(parent)?;
+	}
+
+	Ok(OpenOptions::new().create(true).truncate(false).write(true).open(path)?)
+}
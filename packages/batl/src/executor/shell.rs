@@ -0,0 +1,238 @@
+use crate::error as batlerror;
+use crate::resource::tomlconfig::ScriptShell0_2_2;
+use crate::system;
+use std::path::Path;
+use std::process::{Command, ExitStatus};
+use super::{limits, Executor, Invocation};
+
+
+/// Runs scripts directly on the host, through the
+/// interpreter it is given
+#[derive(Clone, Copy)]
+#[non_exhaustive]
+pub struct Shell {
+	pub interpreter: Interpreter
+}
+
+/// A program that runs the command of a script
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Interpreter {
+	/// `sh -c`, the POSIX shell
+	Sh,
+
+	/// `cmd /C`, the Windows command prompt
+	Cmd,
+
+	/// `powershell -Command`, or `pwsh` off Windows
+	PowerShell,
+
+	/// `busybox sh -c`, a POSIX shell for hosts without one
+	Busybox
+}
+
+impl Shell {
+	#[inline]
+	#[must_use]
+	pub const fn new(interpreter: Interpreter) -> Self {
+		Self { interpreter }
+	}
+
+	/// Runs scripts through the interpreter set in the
+	/// batlrc, or the one the platform has
+	#[inline]
+	#[must_use]
+	pub fn configured(system: &system::Context) -> Self {
+		Self::new(Interpreter::configured(system))
+	}
+}
+
+impl Executor for Shell {
+	#[inline]
+	fn execute(&self, invocation: &Invocation) -> Result<ExitStatus, batlerror::ExecuteError> {
+		std::fs::create_dir_all(super::out_path(&invocation.workdir))?;
+		let (stdout, stderr) = super::output(invocation)?;
+
+		let mut process = self.interpreter.command(&invocation.command);
+		limits::prepare(&mut process, &invocation.limits)?;
+
+		let mut child = super::grouped(&mut process, invocation.limits.timeout)
+			.current_dir(&invocation.workdir)
+			.envs(invocation.environment(&invocation.workdir, |mount| mount.path.clone()))
+			.stdout(stdout)
+			.stderr(stderr)
+			.spawn()
+			.map_err(|err| if err.kind() == std::io::ErrorKind::NotFound {
+				batlerror::ExecuteError::InterpreterUnavailable(self.interpreter.program().to_owned())
+			} else {
+				err.into()
+			})?;
+
+		let _confinement = limits::confine(&mut child, &invocation.limits)?;
+
+		super::wait(child, invocation.limits.timeout)
+	}
+}
+
+impl Interpreter {
+	/// The interpreter from `config.shell` in the batlrc,
+	/// or the one detected on the platform
+	#[inline]
+	#[must_use]
+	pub fn configured(system: &system::Context) -> Self {
+		system.batlrc()
+			.and_then(|batlrc| batlrc.config)
+			.and_then(|config| config.shell)
+			.map_or_else(Self::detect, Self::from)
+	}
+
+	/// The interpreter the platform has. Windows uses `sh`
+	/// when one is on the path, such as the one git ships
+	/// with, and PowerShell otherwise.
+	#[inline]
+	#[must_use]
+	pub fn detect() -> Self {
+		if !cfg!(windows) || on_path("sh") {
+			Self::Sh
+		} else {
+			Self::PowerShell
+		}
+	}
+
+	/// A command running a script through the interpreter
+	#[inline]
+	#[must_use]
+	pub fn command(self, script: &str) -> Command {
+		let args: &[&str] = match self {
+			Self::Sh => &["-c"],
+			Self::Cmd => &["/C"],
+			Self::PowerShell => &["-NoProfile", "-NonInteractive", "-Command"],
+			Self::Busybox => &["sh", "-c"]
+		};
+
+		let mut command = Command::new(self.program());
+		command.args(args);
+
+		// cmd doesn't split its command line the way other
+		// programs do, so the script is passed on as written
+		// instead of quoted as an argument
+		#[cfg(windows)]
+		if self == Self::Cmd {
+			use std::os::windows::process::CommandExt as _;
+
+			command.raw_arg(script);
+
+			return command;
+		}
+
+		command.arg(script);
+
+		command
+	}
+
+	/// The program that is run
+	#[inline]
+	#[must_use]
+	pub const fn program(self) -> &'static str {
+		match self {
+			Self::Sh => "sh",
+			Self::Cmd => "cmd",
+			Self::PowerShell if cfg!(windows) => "powershell",
+			Self::PowerShell => "pwsh",
+			Self::Busybox => "busybox"
+		}
+	}
+
+	/// Quote a value so the interpreter reads it as a
+	/// single word, whatever it contains
+	#[inline]
+	#[must_use]
+	pub fn quote(self, value: &str) -> String {
+		match self {
+			Self::Sh | Self::Busybox => format!("'{}'", value.replace('\'', r"'\''")),
+			Self::Cmd => format!("\"{}\"", value.replace('"', "\"\"")),
+			Self::PowerShell => format!("'{}'", value.replace('\'', "''"))
+		}
+	}
+
+	/// Whether the interpreter is a POSIX shell
+	#[inline]
+	#[must_use]
+	pub const fn is_posix(self) -> bool {
+		match self {
+			Self::Sh | Self::Busybox => true,
+			Self::Cmd | Self::PowerShell => false
+		}
+	}
+}
+
+impl From<ScriptShell0_2_2> for Interpreter {
+	#[inline]
+	fn from(value: ScriptShell0_2_2) -> Self {
+		match value {
+			ScriptShell0_2_2::Sh => Self::Sh,
+			ScriptShell0_2_2::Cmd => Self::Cmd,
+			ScriptShell0_2_2::Powershell => Self::PowerShell,
+			ScriptShell0_2_2::Busybox => Self::Busybox
+		}
+	}
+}
+
+impl From<Interpreter> for ScriptShell0_2_2 {
+	#[inline]
+	fn from(value: Interpreter) -> Self {
+		match value {
+			Interpreter::Sh => Self::Sh,
+			Interpreter::Cmd => Self::Cmd,
+			Interpreter::PowerShell => Self::Powershell,
+			Interpreter::Busybox => Self::Busybox
+		}
+	}
+}
+
+/// Whether a program can be found on the path
+fn on_path(program: &str) -> bool {
+	let Some(paths) = std::env::var_os("PATH") else {
+		return false;
+	};
+
+	let file = if cfg!(windows) {
+		format!("{program}.exe")
+	} else {
+		program.to_owned()
+	};
+
+	std::env::split_paths(&paths).any(|dir| Path::new(&dir).join(&file).is_file())
+}
+
+#[cfg(test)]
+#[allow(clippy::panic_in_result_fn, reason = "tests fail on their assertions, and on errors setting them up")]
+mod tests {
+	use super::Interpreter;
+	use core::error::Error;
+
+
+	/// Values that break naive quoting
+	const AWKWARD: [&str; 6] = ["plain", "two words", "it's", r#"say "hi""#, "$HOME `id` $(id)", ""];
+
+	#[test]
+	fn quotes_escape_the_quote_character() {
+		assert_eq!(Interpreter::Sh.quote("it's"), r"'it'\''s'");
+		assert_eq!(Interpreter::Busybox.quote("it's"), r"'it'\''s'");
+		assert_eq!(Interpreter::PowerShell.quote("it's"), "'it''s'");
+		assert_eq!(Interpreter::Cmd.quote(r#"say "hi""#), r#""say ""hi""""#);
+	}
+
+	#[test]
+	#[cfg(unix)]
+	fn sh_reads_quoted_values_as_single_words() -> Result<(), Box<dyn Error>> {
+		for value in AWKWARD {
+			let script = format!("printf '%s|' {}", Interpreter::Sh.quote(value));
+			let output = Interpreter::Sh.command(&script).output()?;
+
+			assert_eq!(String::from_utf8(output.stdout)?, format!("{value}|"));
+		}
+
+		Ok(())
+	}
+}
@@ -0,0 +1,156 @@
+use crate::error as batlerror;
+use crate::resource::batlrc::HostLatest;
+use std::path::Path;
+use std::process::{Command, ExitStatus, Stdio};
+use super::{limits, Executor, Interpreter, Invocation};
+
+
+/// Default remote directory, relative to the remote home
+const DEFAULT_REMOTE_PATH: &str = "batl-remote";
+
+
+/// Runs scripts on a remote host over ssh. The repository
+/// and its dependencies are synced with rsync first, then
+/// the script output is streamed back.
+pub struct Ssh {
+	/// The host configuration from the batlrc
+	host: HostLatest
+}
+
+impl Ssh {
+	/// Create an ssh executor for a configured host
+	#[inline]
+	#[must_use]
+	pub const fn new(host: HostLatest) -> Self {
+		Self {
+			host
+		}
+	}
+
+	/// The remote directory everything is synced into
+	fn remote_root(&self) -> String {
+		self.host.path.clone().unwrap_or_else(|| DEFAULT_REMOTE_PATH.to_owned())
+	}
+
+	/// The ssh command used by both rsync and the script
+	fn ssh_command(&self) -> String {
+		self.host.port.map_or_else(
+			|| "ssh".to_owned(),
+			|port| format!("ssh -p {port}")
+		)
+	}
+
+	/// Build an ssh command for the host
+	fn command(&self) -> Command {
+		let mut command = Command::new("ssh");
+
+		if let Some(port) = self.host.port {
+			command.arg("-p").arg(port.to_string());
+		}
+
+		command.arg(&self.host.address);
+
+		command
+	}
+
+	/// The absolute path of a remote directory, so that
+	/// paths handed to scripts hold up after a `cd`
+	fn resolve(&self, remote: &str) -> Result<String, batlerror::ExecuteError> {
+		if remote.starts_with('/') {
+			return Ok(remote.to_owned());
+		}
+
+		let output = self.command()
+			.arg(format!("cd {} && pwd", Interpreter::Sh.quote(remote)))
+			.output()?;
+
+		if !output.status.success() {
+			return Err(batlerror::ExecuteError::SyncFailed(remote.to_owned()));
+		}
+
+		Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+	}
+
+	/// Mirror a local directory to a remote directory. The
+	/// remote path is sent to rsync as is rather than read
+	/// by the remote shell, so it may hold spaces.
+	fn sync(&self, local: &Path, remote: &str) -> Result<(), batlerror::ExecuteError> {
+		let status = Command::new("rsync")
+			.args(["-az", "--delete", "--mkpath", "--protect-args", "-e"])
+			.arg(self.ssh_command())
+			.arg(format!("{}/", local.display()))
+			.arg(format!("{}:{remote}/", self.host.address))
+			.status()?;
+
+		if status.success() {
+			Ok(())
+		} else {
+			Err(batlerror::ExecuteError::SyncFailed(local.display().to_string()))
+		}
+	}
+}
+
+impl Executor for Ssh {
+	#[inline]
+	fn execute(&self, invocation: &Invocation) -> Result<ExitStatus, batlerror::ExecuteError> {
+		// The limits would only apply to the local ssh client,
+		// and the remote host may not take ulimit
+		limits::unsupported(&invocation.limits, "over ssh")?;
+
+		let root = self.remote_root();
+		let workdir = format!("{root}/repository");
+
+		std::fs::create_dir_all(super::out_path(&invocation.workdir))?;
+
+		self.sync(&invocation.workdir, &workdir)?;
+
+		for mount in &invocation.mounts {
+			self.sync(&mount.path, &format!("{root}/dependencies/{}", mount.name))?;
+		}
+
+		let absolute_root = self.resolve(&root)?;
+
+		let env = invocation.environment(
+			&Path::new(&absolute_root).join("repository"),
+			|mount| Path::new(&absolute_root).join("dependencies").join(mount.name.to_string())
+		);
+
+		let env_args = env.iter()
+			.map(|(key, value)| format!("{key}={}", Interpreter::Sh.quote(value)))
+			.collect::<Vec<_>>()
+			.join(" ");
+
+		// Without a terminal the remote shell outlives ssh, so
+		// it records its process group to be killed by
+		let pid_file = Interpreter::Sh.quote(&format!("{absolute_root}/run-{:08x}.pid", rand::random::<u32>()));
+
+		let remote_command = format!(
+			"cd {} && echo $$ > {pid_file} && env {env_args} sh -c {}; status=$?; rm -f {pid_file}; exit $status",
+			Interpreter::Sh.quote(&workdir),
+			Interpreter::Sh.quote(&invocation.command)
+		);
+
+		let (stdout, stderr) = super::output(invocation)?;
+
+		let mut process = self.command();
+
+		let child = super::grouped(&mut process, invocation.limits.timeout)
+			.arg(remote_command)
+			.stdout(stdout)
+			.stderr(stderr)
+			.spawn()?;
+
+		let result = super::wait(child, invocation.limits.timeout);
+
+		if let Err(batlerror::ExecuteError::TimedOut(_)) = result {
+			// Killing ssh leaves the script running on the host
+			self.command()
+				.arg(format!("kill -KILL -- -$(cat {pid_file}) 2>/dev/null; rm -f {pid_file}"))
+				.stdout(Stdio::null())
+				.stderr(Stdio::null())
+				.status()?;
+		}
+
+		result
+	}
+}
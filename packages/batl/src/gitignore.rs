@@ -0,0 +1,350 @@
+use core::fmt::{Display, Formatter};
+use std::path::Path;
+
+
+/// Prefix of the line that opens a managed block
+const BEGIN_MARKER: &str = "# >>> batl:";
+
+/// Prefix of the line that closes a managed block
+const END_MARKER: &str = "# <<< batl:";
+
+
+/// A `.gitignore` file, split into user lines and blocks
+/// managed by battalion. Only managed blocks are ever
+/// changed, everything else is written back as it was read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Gitignore {
+	/// The lines and blocks of the file, in order
+	segments: Vec<Segment>,
+
+	/// Whether the file uses `\r\n` line endings
+	crlf: bool,
+
+	/// Whether the file ends with a line ending
+	trailing_newline: bool
+}
+
+/// A piece of a `.gitignore` file, either a single user
+/// line or a block managed by battalion
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Segment {
+	/// The id written in the block markers, or `None` for
+	/// a user line
+	id: Option<String>,
+
+	/// The lines of the segment, without the markers
+	lines: Vec<String>
+}
+
+impl Segment {
+	/// The lines of the segment as written in the file,
+	/// including any block markers
+	fn render(&self) -> Vec<String> {
+		self.id.as_ref().map_or_else(
+			|| self.lines.clone(),
+			|id| core::iter::once(format!("{BEGIN_MARKER}{id}"))
+				.chain(self.lines.iter().cloned())
+				.chain(core::iter::once(format!("{END_MARKER}{id}")))
+				.collect()
+		)
+	}
+}
+
+impl Default for Gitignore {
+	#[inline]
+	fn default() -> Self {
+		Self {
+			segments: Vec::new(),
+			crlf: false,
+			trailing_newline: true
+		}
+	}
+}
+
+impl Gitignore {
+	/// Parse the contents of a `.gitignore`. A block whose
+	/// end marker has been removed is kept as user lines,
+	/// so edited markers never swallow the rest of the file.
+	#[inline]
+	#[must_use]
+	pub fn parse(contents: &str) -> Self {
+		let crlf = contents.contains("\r\n");
+		let trailing_newline = contents.is_empty() || contents.ends_with('\n');
+
+		let lines = contents.lines()
+			.map(|line| line.strip_suffix('\r').unwrap_or(line))
+			.collect::<Vec<_>>();
+
+		let mut segments = Vec::new();
+		let mut rest = lines.as_slice();
+
+		while let Some((&line, after)) = rest.split_first() {
+			let block = begin_id(line).and_then(|id| {
+				let end = after.iter().position(|inner| end_id(inner) == Some(id))?;
+
+				Some((id, end))
+			});
+
+			let (segment, remaining) = block.map_or_else(
+				|| (Segment { id: None, lines: vec![line.to_owned()] }, after),
+				|(id, end)| {
+					let (inner, tail) = after.split_at(end);
+
+					let segment = Segment {
+						id: Some(id.to_owned()),
+						lines: inner.iter().map(|inner_line| (*inner_line).to_owned()).collect()
+					};
+
+					(segment, tail.get(1..).unwrap_or_default())
+				}
+			);
+
+			segments.push(segment);
+			rest = remaining;
+		}
+
+		Self {
+			segments,
+			crlf,
+			trailing_newline
+		}
+	}
+
+	/// Read a `.gitignore`, treating a missing file as empty
+	/// 
+	/// # Errors
+	/// 
+	/// Propogates any IO errors other than the file not
+	/// existing
+	#[inline]
+	pub fn load(path: &Path) -> Result<Self, std::io::Error> {
+		match std::fs::read_to_string(path) {
+			Ok(contents) => Ok(Self::parse(&contents)),
+			Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+			Err(err) => Err(err)
+		}
+	}
+
+	/// Write the `.gitignore` back to disk
+	/// 
+	/// # Errors
+	/// 
+	/// Propogates any IO errors
+	#[inline]
+	pub fn save(&self, path: &Path) -> Result<(), std::io::Error> {
+		std::fs::write(path, self.to_string())
+	}
+
+	/// Get the patterns of a managed block
+	#[inline]
+	#[must_use]
+	pub fn block(&self, id: &str) -> Option<&[String]> {
+		self.segments.iter()
+			.find(|segment| segment.id.as_deref() == Some(id))
+			.map(|segment| segment.lines.as_slice())
+	}
+
+	/// Set the patterns of a managed block, adding it to
+	/// the end of the file if it doesn't exist yet. Any
+	/// duplicate blocks with the same id are merged away.
+	#[inline]
+	pub fn set_block(&mut self, id: &str, patterns: Vec<String>) {
+		let mut pending = Some(patterns);
+
+		self.segments.retain_mut(|segment| {
+			if segment.id.as_deref() != Some(id) {
+				return true;
+			}
+
+			pending.take().is_some_and(|new_lines| {
+				segment.lines = new_lines;
+				true
+			})
+		});
+
+		if let Some(new_lines) = pending {
+			self.segments.push(Segment {
+				id: Some(id.to_owned()),
+				lines: new_lines
+			});
+		}
+	}
+
+	/// Remove a managed block. Returns whether anything was
+	/// removed.
+	#[inline]
+	pub fn remove_block(&mut self, id: &str) -> bool {
+		let before = self.segments.len();
+
+		self.segments.retain(|segment| segment.id.as_deref() != Some(id));
+
+		self.segments.len() != before
+	}
+}
+
+impl Display for Gitignore {
+	#[inline]
+	fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+		let line_ending = if self.crlf { "\r\n" } else { "\n" };
+
+		let lines = self.segments.iter()
+			.flat_map(Segment::render)
+			.collect::<Vec<_>>();
+
+		if lines.is_empty() {
+			return Ok(());
+		}
+
+		f.write_str(&lines.join(line_ending))?;
+
+		if self.trailing_newline {
+			f.write_str(line_ending)?;
+		}
+
+		Ok(())
+	}
+}
+
+/// Get the id of a block begin marker
+fn begin_id(line: &str) -> Option<&str> {
+	line.trim().strip_prefix(BEGIN_MARKER).map(str::trim).filter(|id| !id.is_empty())
+}
+
+/// Get the id of a block end marker
+fn end_id(line: &str) -> Option<&str> {
+	line.trim().strip_prefix(END_MARKER).map(str::trim).filter(|id| !id.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Gitignore;
+	use proptest::prelude::*;
+	use std::collections::HashMap;
+
+
+	/// A change to the managed blocks of a `.gitignore`
+	#[derive(Debug, Clone)]
+	enum Edit {
+		Set(String, Vec<String>),
+		Remove(String)
+	}
+
+	/// Lines a user may write, which are never block markers
+	fn user_line() -> impl Strategy<Value = String> {
+		"[a-z0-9/*.!# ]{0,12}"
+	}
+
+	/// Ids of managed blocks, few enough that edits collide
+	fn block_id() -> impl Strategy<Value = String> {
+		prop::sample::select(vec!["links", "gen", "deps"]).prop_map(str::to_owned)
+	}
+
+	/// Any edit to a managed block
+	fn edit() -> impl Strategy<Value = Edit> {
+		prop_oneof![
+			(block_id(), prop::collection::vec("[a-z0-9/*.]{1,12}", 0..5))
+				.prop_map(|(id, patterns)| Edit::Set(id, patterns)),
+			block_id().prop_map(Edit::Remove)
+		]
+	}
+
+	/// The contents of a file of user lines. A last line
+	/// that is empty can only be written with a line ending.
+	fn contents(lines: &[String], crlf: bool, trailing_newline: bool) -> String {
+		let line_ending = if crlf { "\r\n" } else { "\n" };
+		let mut contents = lines.join(line_ending);
+
+		if lines.last().is_some_and(|last| trailing_newline || last.is_empty()) {
+			contents.push_str(line_ending);
+		}
+
+		contents
+	}
+
+	/// Apply an edit to a `.gitignore`
+	fn apply(gitignore: &mut Gitignore, edit: Edit) {
+		match edit {
+			Edit::Set(id, patterns) => gitignore.set_block(&id, patterns),
+			Edit::Remove(id) => {
+				gitignore.remove_block(&id);
+			}
+		}
+	}
+
+	/// The lines of a `.gitignore` outside of managed blocks
+	fn user_lines(gitignore: &Gitignore) -> Vec<String> {
+		gitignore.segments.iter()
+			.filter(|segment| segment.id.is_none())
+			.flat_map(|segment| segment.lines.clone())
+			.collect()
+	}
+
+	proptest! {
+		#[test]
+		fn user_lines_are_never_touched(
+			lines in prop::collection::vec(user_line(), 0..10),
+			crlf in any::<bool>(),
+			trailing_newline in any::<bool>(),
+			edits in prop::collection::vec(edit(), 0..12)
+		) {
+			let original = contents(&lines, crlf, trailing_newline);
+			let mut gitignore = Gitignore::parse(&original);
+
+			for edit in edits {
+				apply(&mut gitignore, edit);
+			}
+
+			let written = gitignore.to_string();
+
+			prop_assert!(written.starts_with(&original));
+			prop_assert_eq!(user_lines(&Gitignore::parse(&written)), lines);
+		}
+
+		#[test]
+		fn blocks_hold_the_last_edit(
+			lines in prop::collection::vec(user_line(), 0..10),
+			edits in prop::collection::vec(edit(), 0..12)
+		) {
+			let mut gitignore = Gitignore::parse(&contents(&lines, false, true));
+			let mut expected = HashMap::new();
+
+			for edit in edits {
+				match edit.clone() {
+					Edit::Set(id, patterns) => expected.insert(id, patterns),
+					Edit::Remove(id) => expected.remove(&id)
+				};
+
+				apply(&mut gitignore, edit);
+			}
+
+			let reparsed = Gitignore::parse(&gitignore.to_string());
+
+			for id in ["links", "gen", "deps"] {
+				prop_assert_eq!(reparsed.block(id), expected.get(id).map(Vec::as_slice));
+			}
+		}
+
+		#[test]
+		fn edits_are_idempotent(
+			lines in prop::collection::vec(user_line(), 0..10),
+			edits in prop::collection::vec(edit(), 0..12),
+			last in edit()
+		) {
+			let mut gitignore = Gitignore::parse(&contents(&lines, false, true));
+
+			for edit in edits {
+				apply(&mut gitignore, edit);
+			}
+
+			apply(&mut gitignore, last.clone());
+			let once = gitignore.to_string();
+
+			apply(&mut gitignore, last.clone());
+			prop_assert_eq!(gitignore.to_string(), once.as_str());
+
+			let mut reread = Gitignore::parse(&once);
+			apply(&mut reread, last);
+			prop_assert_eq!(reread.to_string(), once);
+		}
+	}
+}
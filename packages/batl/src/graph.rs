@@ -0,0 +1,457 @@
+use crate::context::BatlContext;
+use crate::resource::{Name, Repository, Resource as _, Workspace};
+use crate::resource::kind::Kind;
+use crate::resource::summary::{Dependency, Summary};
+use semver::Version;
+use serde_json::json;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+
+/// Styles of the html page, kept inline so the page can be
+/// shared as a single file
+const HTML_STYLE: &str = "body{font-family:system-ui,sans-serif;margin:2em;color:#222}\
+ul{list-style:none;padding-left:1.4em;border-left:1px solid #ddd}\
+summary{cursor:pointer}\
+.version{color:#666}.label{color:#0a5;font-size:.9em}\
+.missing{color:#c00}.cycle{color:#a60}.kind{color:#888;font-size:.8em}\
+button{margin-right:.5em}";
+
+/// Script of the html page, expanding or collapsing every
+/// node at once
+const HTML_SCRIPT: &str = "function toggleAll(open){document.querySelectorAll('details').forEach(d=>d.open=open)}";
+
+/// Where ninja keeps the stamps of scripts that have run,
+/// relative to the build file
+const NINJA_STAMP_DIR: &str = ".batl";
+
+
+/// The dependency structure of a workspace or repository,
+/// which every graph format is rendered from
+#[derive(Clone, Default)]
+#[non_exhaustive]
+pub struct Graph {
+	pub nodes: Vec<Node>,
+
+	/// Edges between nodes, by their index
+	pub edges: Vec<Edge>
+}
+
+/// A workspace or repository in a graph
+#[derive(Clone)]
+#[non_exhaustive]
+pub struct Node {
+	pub name: Name,
+
+	/// The version found locally, or `None` if the
+	/// repository is missing
+	pub version: Option<Version>,
+	pub kind: NodeKind
+}
+
+/// What a node of a graph is
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum NodeKind {
+	Workspace,
+	Repository(Kind),
+
+	/// A repository that isn't available locally
+	Missing
+}
+
+/// A link or dependency between two nodes
+#[derive(Clone)]
+#[non_exhaustive]
+pub struct Edge {
+	pub from: usize,
+	pub to: usize,
+
+	/// The link name, or the version requirement of a
+	/// dependency
+	pub label: String,
+
+	/// Whether the edge leads back to one of its own
+	/// dependents
+	pub cycle: bool
+}
+
+/// Adds nodes and edges to a graph, keeping a single node
+/// per repository
+#[derive(Default)]
+struct Builder {
+	/// The graph built so far
+	graph: Graph,
+
+	/// The node of each repository added
+	repositories: HashMap<Name, usize>
+}
+
+impl Graph {
+	/// The graph of a repository and its transitive
+	/// dependencies, as resolved locally
+	#[inline]
+	#[must_use]
+	pub fn of_repository(context: &BatlContext, repository: &Repository) -> Self {
+		let mut builder = Builder::default();
+		builder.add_summary(&repository.summarize(context));
+
+		builder.graph
+	}
+
+	/// The graph of a workspace, its links, and their
+	/// transitive dependencies
+	#[inline]
+	#[must_use]
+	pub fn of_workspace(context: &BatlContext, workspace: &Workspace) -> Self {
+		let mut builder = Builder::default();
+
+		let root = builder.push(Node {
+			name: workspace.name().clone(),
+			version: Some(workspace.config().version.clone()),
+			kind: NodeKind::Workspace
+		});
+
+		let mut links = workspace.links().into_iter().collect::<Vec<_>>();
+		links.sort_by(|a, b| a.0.cmp(&b.0));
+
+		for link in links {
+			let target = match workspace.link(context, &link.0) {
+				Some(repository) => builder.add_summary(&repository.summarize(context)),
+				None => builder.repository(&link.1, None, NodeKind::Missing).0
+			};
+
+			builder.graph.edges.push(Edge { from: root, to: target, label: link.0, cycle: false });
+		}
+
+		builder.graph
+	}
+
+	/// The nodes an edge leads to from a node, in order
+	#[inline]
+	#[must_use]
+	pub fn children(&self, node: usize) -> Vec<&Edge> {
+		self.edges.iter().filter(|edge| edge.from == node).collect()
+	}
+
+	/// Render the graph in the Graphviz dot language
+	#[inline]
+	#[must_use]
+	pub fn to_dot(&self) -> String {
+		let mut lines = vec!["digraph batl {".to_owned(), "\trankdir=LR;".to_owned()];
+
+		lines.extend(self.nodes.iter().enumerate().map(|(idx, node)| {
+			let style = match node.kind {
+				NodeKind::Workspace => ", shape=box, style=bold",
+				NodeKind::Repository(_) => "",
+				NodeKind::Missing => ", style=dashed"
+			};
+
+			format!("\tn{idx} [label=\"{}\"{style}];", dot_escape(&node.label("\\n")))
+		}));
+
+		lines.extend(self.edges.iter().map(|edge| {
+			let style = if edge.cycle { ", style=dashed" } else { "" };
+
+			format!("\tn{} -> n{} [label=\"{}\"{style}];", edge.from, edge.to, dot_escape(&edge.label))
+		}));
+
+		lines.push("}".to_owned());
+
+		lines.join("\n")
+	}
+
+	/// Render the graph as a mermaid flowchart
+	#[inline]
+	#[must_use]
+	pub fn to_mermaid(&self) -> String {
+		let mut lines = vec!["graph LR".to_owned()];
+
+		lines.extend(self.nodes.iter().enumerate().map(|(idx, node)| {
+			let label = mermaid_escape(&node.label("<br/>"));
+
+			match node.kind {
+				NodeKind::Workspace => format!("\tn{idx}[[\"{label}\"]]"),
+				NodeKind::Repository(_) => format!("\tn{idx}[\"{label}\"]"),
+				NodeKind::Missing => format!("\tn{idx}[\"{label}\"]:::missing")
+			}
+		}));
+
+		lines.extend(self.edges.iter().map(|edge| {
+			let arrow = if edge.cycle { "-.->" } else { "-->" };
+
+			format!("\tn{} {arrow}|\"{}\"| n{}", edge.from, mermaid_escape(&edge.label), edge.to)
+		}));
+
+		lines.push("\tclassDef missing stroke-dasharray: 5 5, color: #c00;".to_owned());
+
+		lines.join("\n")
+	}
+
+	/// Render the graph as a self-contained html page, with
+	/// every node collapsible
+	#[inline]
+	#[must_use]
+	pub fn to_html(&self) -> String {
+		let title = self.nodes.first()
+			.map_or_else(String::new, |root| html_escape(&root.name.to_string()));
+
+		let mut lines = vec![
+			"<!DOCTYPE html>".to_owned(),
+			"<html lang=\"en\">".to_owned(),
+			"<head>".to_owned(),
+			"<meta charset=\"utf-8\">".to_owned(),
+			format!("<title>{title} dependencies</title>"),
+			format!("<style>{HTML_STYLE}</style>"),
+			format!("<script>{HTML_SCRIPT}</script>"),
+			"</head>".to_owned(),
+			"<body>".to_owned(),
+			format!("<h1>{title}</h1>"),
+			"<p><button onclick=\"toggleAll(true)\">Expand all</button><button onclick=\"toggleAll(false)\">Collapse all</button></p>".to_owned()
+		];
+
+		if !self.nodes.is_empty() {
+			lines.push("<ul>".to_owned());
+			self.html_tree(0, None, &mut Vec::new(), &mut lines);
+			lines.push("</ul>".to_owned());
+		}
+
+		lines.extend(["</body>".to_owned(), "</html>".to_owned()]);
+
+		lines.join("\n")
+	}
+
+	/// Render the graph as json, listing the edges leading
+	/// out of each node alongside it
+	#[inline]
+	#[must_use]
+	pub fn to_json(&self) -> String {
+		let nodes = self.nodes.iter().enumerate().map(|(idx, node)| {
+			let kind = match node.kind {
+				NodeKind::Workspace => "workspace".to_owned(),
+				NodeKind::Repository(repository_kind) => repository_kind.to_string(),
+				NodeKind::Missing => "missing".to_owned()
+			};
+
+			let dependencies = self.children(idx).into_iter().filter_map(|edge| {
+				self.nodes.get(edge.to).map(|target| json!({
+					"name": target.name.to_string(),
+					"label": edge.label,
+					"cycle": edge.cycle
+				}))
+			}).collect::<Vec<_>>();
+
+			json!({
+				"name": node.name.to_string(),
+				"version": node.version.as_ref().map(ToString::to_string),
+				"kind": kind,
+				"dependencies": dependencies
+			})
+		}).collect::<Vec<_>>();
+
+		serde_json::to_string_pretty(&json!({ "nodes": nodes })).unwrap_or_default()
+	}
+
+	/// Render the graph as ninja build rules running a
+	/// script in every repository that has it, after it has
+	/// run in their dependencies. `scripted` holds the
+	/// repositories with the script, along with their paths.
+	///
+	/// Each run has batl touch a stamp, keeping the rule free
+	/// of shell commands, so ninja only runs a script
+	/// again once the repository's config or a dependency's
+	/// run is newer. Repositories without the script pass
+	/// their dependencies' stamps along, and edges leading
+	/// back through a cycle are left out.
+	#[inline]
+	#[must_use]
+	pub fn to_ninja(&self, script: &str, scripted: &HashMap<Name, PathBuf>) -> String {
+		let mut lines = vec![
+			format!("# The {script} script across the dependency graph, generated by batl"),
+			"ninja_required_version = 1.3".to_owned(),
+			String::new(),
+			"rule batl".to_owned(),
+			"  command = batl repository exec -n $name --stamp $out $script".to_owned(),
+			"  description = $script $name".to_owned(),
+			String::new()
+		];
+
+		let mut defaults = Vec::new();
+
+		for (idx, node) in self.nodes.iter().enumerate() {
+			if node.kind == NodeKind::Missing {
+				continue;
+			}
+
+			let stamp = ninja_stamp(node, script);
+			let inputs = self.children(idx).into_iter()
+				.filter(|edge| !edge.cycle)
+				.filter_map(|edge| self.nodes.get(edge.to))
+				.filter(|target| target.kind != NodeKind::Missing)
+				.flat_map(|target| [" ".to_owned(), ninja_stamp(target, script)])
+				.collect::<String>();
+
+			match scripted.get(&node.name) {
+				Some(path) => {
+					lines.extend([
+						format!("build {stamp}: batl{inputs} | {}", ninja_escape(&path.join("batl.toml").to_string_lossy())),
+						format!("  name = {}", node.name),
+						format!("  script = {script}"),
+						String::new()
+					]);
+				},
+				None => lines.extend([format!("build {stamp}: phony{inputs}"), String::new()])
+			}
+
+			if idx == 0 {
+				defaults.push(stamp);
+			}
+		}
+
+		if !defaults.is_empty() {
+			lines.push(format!("default {}", defaults.join(" ")));
+		}
+
+		lines.join("\n")
+	}
+
+	/// Write a node and everything below it as nested
+	/// lists, stopping at nodes already on the path to it
+	fn html_tree(&self, node: usize, via: Option<&Edge>, ancestors: &mut Vec<usize>, lines: &mut Vec<String>) {
+		let Some(current) = self.nodes.get(node) else {
+			return;
+		};
+
+		let label = via.map_or_else(String::new, |edge| format!(" <span class=\"label\">{}</span>", html_escape(&edge.label)));
+		let version = current.version.as_ref()
+			.map_or_else(|| " <span class=\"missing\">missing</span>".to_owned(), |found| format!(" <span class=\"version\">{found}</span>"));
+		let kind = match current.kind {
+			NodeKind::Workspace => " <span class=\"kind\">workspace</span>".to_owned(),
+			NodeKind::Repository(repository_kind) => format!(" <span class=\"kind\">{repository_kind}</span>"),
+			NodeKind::Missing => String::new()
+		};
+
+		let heading = format!("<strong>{}</strong>{version}{kind}{label}", html_escape(&current.name.to_string()));
+		let children = self.children(node);
+
+		if ancestors.contains(&node) || via.is_some_and(|edge| edge.cycle) {
+			lines.push(format!("<li>{heading} <span class=\"cycle\">cycle</span></li>"));
+			return;
+		}
+
+		if children.is_empty() {
+			lines.push(format!("<li>{heading}</li>"));
+			return;
+		}
+
+		lines.push(format!("<li><details open><summary>{heading}</summary><ul>"));
+		ancestors.push(node);
+
+		for edge in children {
+			self.html_tree(edge.to, Some(edge), ancestors, lines);
+		}
+
+		ancestors.pop();
+		lines.push("</ul></details></li>".to_owned());
+	}
+}
+
+impl Node {
+	/// The name and version of the node, split across
+	/// lines with the given separator
+	fn label(&self, separator: &str) -> String {
+		self.version.as_ref().map_or_else(
+			|| format!("{}{separator}missing", self.name),
+			|version| format!("{}{separator}{version}", self.name)
+		)
+	}
+}
+
+impl Builder {
+	/// Add a node, returning its index
+	fn push(&mut self, node: Node) -> usize {
+		self.graph.nodes.push(node);
+
+		self.graph.nodes.len().saturating_sub(1)
+	}
+
+	/// Get the node of a repository, adding it if needed.
+	/// Returns whether it was added.
+	fn repository(&mut self, name: &Name, version: Option<Version>, kind: NodeKind) -> (usize, bool) {
+		if let Some(existing) = self.repositories.get(name) {
+			return (*existing, false);
+		}
+
+		let idx = self.push(Node { name: name.clone(), version, kind });
+		self.repositories.insert(name.clone(), idx);
+
+		(idx, true)
+	}
+
+	/// Add a summarized repository and its dependencies,
+	/// returning its node
+	fn add_summary(&mut self, summary: &Summary) -> usize {
+		let (root, added) = self.repository(&summary.name, Some(summary.version.clone()), NodeKind::Repository(summary.kind));
+
+		if added {
+			self.add_dependencies(root, &summary.dependencies);
+		}
+
+		root
+	}
+
+	/// Add the dependencies of a node, recursing into the
+	/// ones not added before
+	fn add_dependencies(&mut self, parent: usize, dependencies: &[Dependency]) {
+		for dependency in dependencies {
+			let kind = match (dependency.version.as_ref(), dependency.kind) {
+				(Some(_), Some(found)) => NodeKind::Repository(found),
+				(Some(_), None) => NodeKind::Repository(Kind::default()),
+				(None, _) => NodeKind::Missing
+			};
+
+			let (node, added) = self.repository(&dependency.name, dependency.version.clone(), kind);
+
+			self.graph.edges.push(Edge {
+				from: parent,
+				to: node,
+				label: dependency.requirement.clone(),
+				cycle: dependency.cycle
+			});
+
+			if added {
+				self.add_dependencies(node, &dependency.dependencies);
+			}
+		}
+	}
+}
+
+/// Escape text for a quoted dot string
+fn dot_escape(text: &str) -> String {
+	text.replace('"', "\\\"")
+}
+
+/// Escape text for a quoted mermaid label
+fn mermaid_escape(text: &str) -> String {
+	text.replace('"', "#quot;")
+}
+
+/// The stamp ninja keeps for a script having run in a node
+fn ninja_stamp(node: &Node, script: &str) -> String {
+	ninja_escape(&format!("{NINJA_STAMP_DIR}/{}/{script}.stamp", node.name))
+}
+
+/// Escape text for a ninja path
+fn ninja_escape(text: &str) -> String {
+	text.replace('$', "$$")
+		.replace(' ', "$ ")
+		.replace(':', "$:")
+}
+
+/// Escape text for html content
+fn html_escape(text: &str) -> String {
+	text.replace('&', "&amp;")
+		.replace('<', "&lt;")
+		.replace('>', "&gt;")
+		.replace('"', "&quot;")
+}
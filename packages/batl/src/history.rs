@@ -0,0 +1,117 @@
+use crate::resource::Name;
+use crate::system;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Write as _;
+
+
+/// A finished script run, kept in `gen/history/runs.jsonl`
+#[derive(Serialize, Deserialize, Clone)]
+#[non_exhaustive]
+pub struct Run {
+	pub repository: Name,
+	pub script: String,
+	pub success: bool,
+
+	/// The exit code of the last attempt, if it exited
+	pub code: Option<i32>,
+
+	/// How many times the script was run, counting retries
+	pub attempts: u32,
+
+	/// When the run started, in seconds since the unix
+	/// epoch
+	pub started: u64,
+
+	/// How long the run took, in milliseconds
+	pub duration_ms: u64
+}
+
+impl Run {
+	#[inline]
+	#[must_use]
+	pub const fn new(repository: Name, script: String, success: bool, code: Option<i32>, attempts: u32, started: u64, duration_ms: u64) -> Self {
+		Self {
+			repository,
+			script,
+			success,
+			code,
+			attempts,
+			started,
+			duration_ms
+		}
+	}
+}
+
+/// Add a run to the history
+///
+/// # Errors
+///
+/// Propogates any IO errors while writing the history
+#[inline]
+pub fn record(system: &system::Context, run: &Run) -> Result<(), std::io::Error> {
+	let Some(path) = system.history_path() else {
+		return Ok(());
+	};
+
+	if let Some(parent) = path.parent() {
+		std::fs::create_dir_all(parent)?;
+	}
+
+	let line = serde_json::to_string(run)
+		.map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+
+	let mut file = std::fs::OpenOptions::new()
+		.create(true)
+		.append(true)
+		.open(path)?;
+
+	writeln!(file, "{line}")
+}
+
+/// Every recorded run, oldest first. Lines that can't be
+/// read are skipped.
+///
+/// # Errors
+///
+/// Propogates any IO errors while reading the history
+#[inline]
+pub fn runs(system: &system::Context) -> Result<Vec<Run>, std::io::Error> {
+	let Some(path) = system.history_path() else {
+		return Ok(Vec::new());
+	};
+
+	let contents = match std::fs::read_to_string(path) {
+		Ok(contents) => contents,
+		Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+		Err(err) => return Err(err)
+	};
+
+	Ok(contents.lines()
+		.filter_map(|line| serde_json::from_str(line).ok())
+		.collect())
+}
+
+/// Repositories whose latest run of a script failed,
+/// sorted by name
+///
+/// # Errors
+///
+/// Propogates any IO errors while reading the history
+#[inline]
+pub fn failed(system: &system::Context, script: &str) -> Result<Vec<Name>, std::io::Error> {
+	let mut latest = HashMap::new();
+
+	for run in runs(system)?.into_iter().filter(|run| run.script == script) {
+		latest.insert(run.repository, run.success);
+	}
+
+	let mut failed = latest.into_iter()
+		.filter(|&(_, success)| !success)
+		.map(|(repository, _)| repository)
+		.collect::<Vec<_>>();
+
+	failed.sort_by_key(ToString::to_string);
+
+	Ok(failed)
+}
@@ -0,0 +1,224 @@
+use rayon::prelude::*;
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use crate::resource::Name;
+use crate::system;
+
+
+/// The kinds of resources that are indexed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Kind {
+	Repository,
+	Workspace
+}
+
+impl Kind {
+	/// Get the root directory resources of this kind are
+	/// stored in
+	#[inline]
+	#[must_use]
+	pub fn root(self, system: &system::Context) -> Option<PathBuf> {
+		match self {
+			Self::Repository => system.repository_root(),
+			Self::Workspace => system.workspace_root()
+		}
+	}
+
+	/// Get the file the index of this kind is stored in
+	fn index_path(self, system: &system::Context) -> Option<PathBuf> {
+		let file = match self {
+			Self::Repository => "repositories.json",
+			Self::Workspace => "workspaces.json"
+		};
+
+		system.index_root().map(|p| p.join(file))
+	}
+}
+
+/// An on-disk listing of every resource under a root.
+/// Configurations are not read while indexing, so
+/// resources are only parsed once they are loaded.
+#[derive(Serialize, Deserialize, Default)]
+#[non_exhaustive]
+pub struct Index {
+	/// Every directory that was scanned, along with when
+	/// it was last modified. A resource can't be added or
+	/// removed without changing one of these.
+	dirs: HashMap<PathBuf, SystemTime>,
+
+	/// Resource names and their paths
+	entries: HashMap<String, PathBuf>,
+
+	/// Entries that were skipped because their names are
+	/// not valid UTF-8
+	#[serde(default)]
+	undecodable: Vec<PathBuf>
+}
+
+impl Index {
+	/// Open the index for a kind of resource in a battalion
+	/// root, rebuilding it if it is missing or stale
+	/// 
+	/// # Errors
+	/// 
+	/// Propogates any IO errors received while rebuilding
+	/// the index. Failing to save the index is not an error.
+	#[inline]
+	pub fn open(system: &system::Context, kind: Kind) -> Result<Self, std::io::Error> {
+		if let Some(index) = Self::read(system, kind).filter(Self::is_fresh) {
+			return Ok(index);
+		}
+
+		let root = kind.root(system)
+			.ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "Battalion root"))?;
+
+		let index = Self::build(&root)?;
+
+		// The index is only a cache, the listing is still valid
+		drop(index.write(system, kind));
+
+		Ok(index)
+	}
+
+	/// Build an index by walking a resource root, with
+	/// each scope walked in parallel
+	/// 
+	/// # Errors
+	/// 
+	/// Propogates any IO errors received while walking
+	#[inline]
+	pub fn build(root: &Path) -> Result<Self, std::io::Error> {
+		let mut index = Self::default();
+
+		walk(root, &[], &mut index)?;
+
+		Ok(index)
+	}
+
+	/// Whether every scanned directory is unchanged since
+	/// the index was built
+	#[inline]
+	#[must_use]
+	pub fn is_fresh(&self) -> bool {
+		!self.dirs.is_empty() && self.dirs.par_iter()
+			.all(|(dir, modified)| modified_time(dir).is_some_and(|time| time == *modified))
+	}
+
+	/// Get the names of every indexed resource, in order
+	#[inline]
+	#[must_use]
+	pub fn names(&self) -> Vec<Name> {
+		let mut names = self.entries.keys().collect::<Vec<_>>();
+		names.sort_unstable();
+
+		names.into_iter().map(|name| Name::from(name.as_str())).collect()
+	}
+
+	/// Get the entries that could not be indexed because
+	/// their names are not valid UTF-8, in order
+	#[inline]
+	#[must_use]
+	pub fn undecodable(&self) -> Vec<&Path> {
+		let mut paths = self.undecodable.iter().map(PathBuf::as_path).collect::<Vec<_>>();
+		paths.sort_unstable();
+
+		paths
+	}
+
+	/// Get the path of an indexed resource
+	#[inline]
+	#[must_use]
+	pub fn get(&self, name: &Name) -> Option<&Path> {
+		self.entries.get(&name.to_string()).map(PathBuf::as_path)
+	}
+
+	/// Read the saved index for a kind of resource
+	fn read(system: &system::Context, kind: Kind) -> Option<Self> {
+		let contents = std::fs::read_to_string(kind.index_path(system)?).ok()?;
+
+		serde_json::from_str(&contents).ok()
+	}
+
+	/// Save the index for a kind of resource
+	fn write(&self, system: &system::Context, kind: Kind) -> Result<(), std::io::Error> {
+		let path = kind.index_path(system)
+			.ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "Battalion root"))?;
+
+		if let Some(parent) = path.parent() {
+			std::fs::create_dir_all(parent)?;
+		}
+
+		let contents = serde_json::to_string(self)
+			.map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+
+		std::fs::write(path, contents)
+	}
+}
+
+/// Get the modification time of a directory
+fn modified_time(path: &Path) -> Option<SystemTime> {
+	std::fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+/// Index a directory, descending into `@` scopes in
+/// parallel
+fn walk(dir: &Path, scope: &[String], index: &mut Index) -> Result<(), std::io::Error> {
+	if let Some(modified) = modified_time(dir) {
+		index.dirs.insert(dir.to_path_buf(), modified);
+	}
+
+	let children = std::fs::read_dir(dir)?
+		.filter_map(|entry| entry.ok().map(|dir_entry| dir_entry.path()))
+		.filter(|path| path.is_dir())
+		.collect::<Vec<_>>();
+
+	let (decodable, undecodable) = children.into_iter()
+		.partition::<Vec<_>, _>(|path| path.file_name().and_then(OsStr::to_str).is_some());
+
+	index.undecodable.extend(undecodable);
+
+	let scopes = decodable.par_iter()
+		.filter_map(|path| {
+			let file_name = path.file_name()?.to_str()?;
+
+			let scope_name = file_name.strip_prefix('@')?;
+
+			let mut inner_scope = scope.to_vec();
+			inner_scope.push(scope_name.to_owned());
+
+			let mut inner = Index::default();
+
+			Some(walk(path, &inner_scope, &mut inner).map(|()| inner))
+		})
+		.collect::<Result<Vec<_>, _>>()?;
+
+	for path in decodable {
+		let Some(file_name) = path.file_name().and_then(|name| name.to_str()) else {
+			continue;
+		};
+
+		if file_name.starts_with('@') {
+			continue;
+		}
+
+		let name = scope.iter()
+			.map(String::as_str)
+			.chain([file_name])
+			.collect::<Vec<_>>()
+			.join("/");
+
+		index.entries.insert(name, path);
+	}
+
+	for inner in scopes {
+		index.dirs.extend(inner.dirs);
+		index.entries.extend(inner.entries);
+		index.undecodable.extend(inner.undecodable);
+	}
+
+	Ok(())
+}
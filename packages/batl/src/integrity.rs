@@ -0,0 +1,301 @@
+use crate::error as batlerror;
+use crate::resource::Name;
+use crate::system::Context;
+use ed25519_dalek::Signer as _;
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest as _, Sha256};
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+
+/// Length of ed25519 keys, in bytes
+const KEY_LENGTH: usize = 32;
+
+/// Length of ed25519 signatures, in bytes
+const SIGNATURE_LENGTH: usize = 64;
+
+
+/// The checksum of a published archive, which the registry
+/// stores next to it, along with the signature of its
+/// publisher when they signed it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Checksum {
+	/// Hex encoded SHA-256 of the archive
+	pub sha256: String,
+
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub signature: Option<Signature>
+}
+
+/// An ed25519 signature over a package version and the
+/// checksum of its archive
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Signature {
+	/// Hex encoded public key of the publisher
+	pub key: String,
+
+	/// Hex encoded signature
+	pub value: String
+}
+
+/// How a fetched archive passed its checks
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Verified {
+	/// It matches its checksum
+	Checksum,
+
+	/// It matches its checksum, which the publisher with
+	/// this public key signed
+	Signed(String)
+}
+
+/// An ed25519 key that archives are signed with as they
+/// are published
+#[non_exhaustive]
+pub struct PublisherKey {
+	/// The secret key
+	key: ed25519_dalek::SigningKey
+}
+
+/// What fetched archives are checked against and how
+/// published ones are signed, from `[signing]` in the
+/// batlrc
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Policy {
+	/// The file holding the key to sign with
+	pub key_file: Option<PathBuf>,
+
+	/// Public keys of trusted publishers, by namespace
+	pub publishers: HashMap<String, Vec<String>>,
+
+	/// Refuse archives without a published checksum
+	pub require_checksums: bool
+}
+
+impl Checksum {
+	/// The unsigned checksum of an archive
+	///
+	/// # Errors
+	///
+	/// Propogates any IO errors while reading the archive
+	#[inline]
+	pub fn of(path: &Path) -> Result<Self, std::io::Error> {
+		Ok(Self {
+			sha256: sha256(path)?,
+			signature: None
+		})
+	}
+
+	/// Sign the checksum as the archive of a package version
+	#[inline]
+	pub fn sign(&mut self, name: &Name, version: &Version, key: &PublisherKey) {
+		let signature = key.key.sign(message(name, version, &self.sha256).as_bytes());
+
+		self.signature = Some(Signature {
+			key: key.public_key(),
+			value: to_hex(&signature.to_bytes())
+		});
+	}
+
+	/// Check an archive of a package version against the
+	/// checksum. When trusted keys are given, the checksum
+	/// must also be signed by one of them, so an empty list
+	/// trusts no archive at all.
+	///
+	/// # Errors
+	///
+	/// Returns `ChecksumMismatch` if the archive doesn't
+	/// match, `Untrusted` if it isn't signed by a trusted
+	/// key, and `BadSignature` if the signature doesn't
+	/// hold. Propogates any IO errors while reading it.
+	#[inline]
+	pub fn verify(&self, path: &Path, name: &Name, version: &Version, trusted: Option<&[String]>) -> Result<Verified, batlerror::IntegrityError> {
+		if !sha256(path)?.eq_ignore_ascii_case(&self.sha256) {
+			return Err(batlerror::IntegrityError::ChecksumMismatch);
+		}
+
+		let Some(signature) = self.signature.as_ref() else {
+			return if trusted.is_none() {
+				Ok(Verified::Checksum)
+			} else {
+				Err(batlerror::IntegrityError::Untrusted)
+			};
+		};
+
+		if trusted.is_some_and(|keys| !keys.iter().any(|key| key.eq_ignore_ascii_case(&signature.key))) {
+			return Err(batlerror::IntegrityError::Untrusted);
+		}
+
+		let bad_signature = || batlerror::IntegrityError::BadSignature(signature.key.clone());
+
+		let key_bytes = from_hex::<KEY_LENGTH>(&signature.key).ok_or_else(bad_signature)?;
+		let value = from_hex::<SIGNATURE_LENGTH>(&signature.value)
+			.map(|bytes| ed25519_dalek::Signature::from_bytes(&bytes))
+			.ok_or_else(bad_signature)?;
+
+		let Ok(key) = ed25519_dalek::VerifyingKey::from_bytes(&key_bytes) else {
+			return Err(bad_signature());
+		};
+
+		if key.verify_strict(message(name, version, &self.sha256.to_ascii_lowercase()).as_bytes(), &value).is_err() {
+			return Err(bad_signature());
+		}
+
+		Ok(Verified::Signed(signature.key.clone()))
+	}
+}
+
+impl PublisherKey {
+	/// A new random key
+	#[inline]
+	#[must_use]
+	pub fn generate() -> Self {
+		Self {
+			key: ed25519_dalek::SigningKey::from_bytes(&rand::random::<[u8; KEY_LENGTH]>())
+		}
+	}
+
+	/// Read a key from a file holding it hex encoded
+	///
+	/// # Errors
+	///
+	/// Returns `InvalidKey` if the file doesn't hold a key,
+	/// and propogates any IO errors while reading it
+	#[inline]
+	pub fn load(path: &Path) -> Result<Self, batlerror::IntegrityError> {
+		let contents = std::fs::read_to_string(path)?;
+
+		let bytes = from_hex::<KEY_LENGTH>(contents.trim())
+			.ok_or_else(|| batlerror::IntegrityError::InvalidKey(path.display().to_string()))?;
+
+		Ok(Self {
+			key: ed25519_dalek::SigningKey::from_bytes(&bytes)
+		})
+	}
+
+	/// Write the key hex encoded to a new file, which only
+	/// its owner can read on unix
+	///
+	/// # Errors
+	///
+	/// Fails if the file already exists, and propogates any
+	/// other IO errors while writing it
+	#[inline]
+	pub fn save(&self, path: &Path) -> Result<(), std::io::Error> {
+		let mut options = File::options();
+		options.write(true).create_new(true);
+
+		#[cfg(unix)]
+		std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+		let mut file = options.open(path)?;
+		std::io::Write::write_all(&mut file, to_hex(&self.key.to_bytes()).as_bytes())?;
+
+		file.sync_all()
+	}
+
+	/// The hex encoded public key, which others trust to
+	/// check archives signed with this key
+	#[inline]
+	#[must_use]
+	pub fn public_key(&self) -> String {
+		to_hex(&self.key.verifying_key().to_bytes())
+	}
+}
+
+impl Policy {
+	/// The policy configured in the batlrc
+	#[inline]
+	#[must_use]
+	pub fn from_system(system: &Context) -> Self {
+		let Some(signing) = system.batlrc().and_then(|batlrc| batlrc.signing) else {
+			return Self::default();
+		};
+
+		Self {
+			key_file: signing.key_file,
+			publishers: signing.publishers.unwrap_or_default(),
+			require_checksums: signing.require_checksums.unwrap_or(false)
+		}
+	}
+
+	/// The keys a package must be signed by, from the most
+	/// specific namespace it is in. Packages outside of
+	/// every listed namespace don't have to be signed, and
+	/// a namespace listed without keys trusts no package.
+	#[inline]
+	#[must_use]
+	pub fn trusted_keys(&self, name: &Name) -> Option<&[String]> {
+		let full_name = name.to_string();
+
+		self.publishers.iter()
+			.filter(|namespace| full_name == *namespace.0 || full_name.starts_with(&format!("{}/", namespace.0)))
+			.max_by_key(|namespace| namespace.0.len())
+			.map(|namespace| namespace.1.as_slice())
+	}
+
+	/// Whether a package can't be fetched without a
+	/// published checksum
+	#[inline]
+	#[must_use]
+	pub fn requires_checksum(&self, name: &Name) -> bool {
+		self.require_checksums || self.trusted_keys(name).is_some()
+	}
+
+	/// The key to sign published archives with, if one is
+	/// configured
+	///
+	/// # Errors
+	///
+	/// Returns `InvalidKey` if the key file doesn't hold a
+	/// key, and propogates any IO errors while reading it
+	#[inline]
+	pub fn signing_key(&self) -> Result<Option<PublisherKey>, batlerror::IntegrityError> {
+		self.key_file.as_deref().map(PublisherKey::load).transpose()
+	}
+}
+
+/// What a publisher signs for a package version, so a
+/// signature can't be moved to another package
+fn message(name: &Name, version: &Version, sha256: &str) -> String {
+	format!("{name}@{version}\n{sha256}")
+}
+
+/// The hex encoded SHA-256 of a file
+fn sha256(path: &Path) -> Result<String, std::io::Error> {
+	let mut hasher = Sha256::new();
+	std::io::copy(&mut File::open(path)?, &mut hasher)?;
+
+	Ok(to_hex(&hasher.finalize()))
+}
+
+/// Encode bytes as lowercase hex
+fn to_hex(bytes: &[u8]) -> String {
+	bytes.iter()
+		.flat_map(|byte| [byte >> 4u8, byte & 0x0f])
+		.filter_map(|nibble| char::from_digit(u32::from(nibble), 16))
+		.collect()
+}
+
+/// Decode hex into exactly `N` bytes
+fn from_hex<const N: usize>(hex: &str) -> Option<[u8; N]> {
+	let mut bytes = Vec::with_capacity(N);
+
+	for pair in hex.as_bytes().chunks(2) {
+		let digits = core::str::from_utf8(pair).ok()?;
+
+		if digits.len() != 2 || !digits.chars().all(|character| character.is_ascii_hexdigit()) {
+			return None;
+		}
+
+		bytes.push(u8::from_str_radix(digits, 16).ok()?);
+	}
+
+	bytes.try_into().ok()
+}
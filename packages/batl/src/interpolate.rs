@@ -0,0 +1,137 @@
+use crate::error::ReadConfigError;
+use std::env::var as env_var;
+
+
+/// Replace environment variable references in a config
+/// value
+///
+/// `${VAR}` is replaced by the value of `VAR`, and
+/// `${VAR:-fallback}` by the fallback when `VAR` is unset or
+/// empty. `$${VAR}` is written out as a literal `${VAR}`.
+/// Anything else, such as `$VAR` or `${#VAR}`, is left for
+/// the shell.
+///
+/// # Errors
+///
+/// Returns `UndefinedVariable` for an unset variable
+/// without a fallback when `strict` is set. Otherwise the
+/// reference is left as it is.
+#[inline]
+pub fn interpolate(text: &str, strict: bool) -> Result<String, ReadConfigError> {
+	let mut interpolated = String::with_capacity(text.len());
+	let mut rest = text;
+
+	while let Some(start) = rest.find('$') {
+		let (before, reference) = rest.split_at(start);
+		interpolated.push_str(before);
+
+		if let Some(after) = reference.strip_prefix("$${") {
+			interpolated.push_str("${");
+			rest = after;
+			continue;
+		}
+
+		let Some(after) = reference.strip_prefix("${") else {
+			interpolated.push('$');
+			rest = reference.get(1..).unwrap_or_default();
+			continue;
+		};
+
+		let Some((expression, remaining)) = after.split_once('}') else {
+			rest = reference;
+			break;
+		};
+
+		let (name, fallback) = expression.split_once(":-")
+			.map_or((expression, None), |(variable, default)| (variable, Some(default)));
+
+		if !is_variable_name(name) {
+			interpolated.push_str("${");
+			rest = after;
+			continue;
+		}
+
+		match (env_var(name).ok(), fallback) {
+			(Some(value), Some(default)) if value.is_empty() => interpolated.push_str(default),
+			(Some(value), _) => interpolated.push_str(&value),
+			(None, Some(default)) => interpolated.push_str(default),
+			(None, None) if strict => return Err(ReadConfigError::UndefinedVariable(name.to_owned())),
+			(None, None) => {
+				interpolated.push_str("${");
+				interpolated.push_str(expression);
+				interpolated.push('}');
+			}
+		}
+
+		rest = remaining;
+	}
+
+	interpolated.push_str(rest);
+
+	Ok(interpolated)
+}
+
+/// Whether text is a name the shell would accept for a
+/// variable
+fn is_variable_name(name: &str) -> bool {
+	name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+		&& name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+#[allow(clippy::panic_in_result_fn, reason = "tests fail on their assertions, and on errors setting them up")]
+mod tests {
+	use super::interpolate;
+	use crate::error::ReadConfigError;
+	use core::error::Error;
+
+
+	/// A variable no environment running the tests sets
+	const UNSET: &str = "BATL_TEST_SURELY_UNSET";
+
+	#[test]
+	fn set_variables_are_replaced() -> Result<(), Box<dyn Error>> {
+		let path = std::env::var("PATH")?;
+
+		assert_eq!(interpolate("PATH=${PATH}", true)?, format!("PATH={path}"));
+		assert_eq!(interpolate("${PATH:-fallback}", true)?, path);
+
+		Ok(())
+	}
+
+	#[test]
+	fn fallbacks_stand_in_for_unset_variables() -> Result<(), Box<dyn Error>> {
+		assert_eq!(interpolate(&format!("${{{UNSET}:-fallback}}"), true)?, "fallback");
+		assert_eq!(interpolate(&format!("${{{UNSET}:-}}"), true)?, "");
+
+		Ok(())
+	}
+
+	#[test]
+	fn escaped_references_are_written_out() -> Result<(), Box<dyn Error>> {
+		let escaped = format!("$${{{UNSET}}} and $${{PATH:-{UNSET}}}");
+
+		assert_eq!(interpolate(&escaped, true)?, format!("${{{UNSET}}} and ${{PATH:-{UNSET}}}"));
+
+		Ok(())
+	}
+
+	#[test]
+	fn shell_syntax_is_left_for_the_shell() -> Result<(), Box<dyn Error>> {
+		for text in ["echo $PATH", "echo ${#ITEMS}", "cost: 5$", "echo ${UNCLOSED"] {
+			assert_eq!(interpolate(text, true)?, text);
+		}
+
+		Ok(())
+	}
+
+	#[test]
+	fn unset_variables_only_fail_when_strict() -> Result<(), Box<dyn Error>> {
+		let text = format!("echo ${{{UNSET}}}");
+
+		assert_eq!(interpolate(&text, false)?, text);
+		assert!(matches!(interpolate(&text, true), Err(ReadConfigError::UndefinedVariable(name)) if name == UNSET));
+
+		Ok(())
+	}
+}
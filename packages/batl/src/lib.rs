@@ -62,7 +62,36 @@
 #![allow(clippy::pub_use)]
 
 
+pub mod audit;
+pub mod auditlog;
+pub mod bootstrap;
+pub mod clean;
+pub mod context;
+pub mod crash;
+pub mod docs;
 pub mod error;
+pub mod executor;
+pub mod gitignore;
+pub mod graph;
+pub mod history;
+pub mod index;
+pub mod integrity;
+pub mod interpolate;
+pub mod notify;
+pub mod policy;
+pub mod preflight;
+pub mod provider;
+pub mod registry;
+pub mod resolve;
+pub mod schema;
+pub mod search;
+pub mod secrets;
+pub mod stamped;
 pub mod system;
+pub mod toolchain;
+pub mod units;
+pub mod upgrade;
+pub mod usage;
 pub mod resource;
+pub mod runstate;
 pub mod version;
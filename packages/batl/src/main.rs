@@ -1,3 +1,7 @@
+use batl::clean::Category as CleanCategory;
+use batl::context::BatlContext;
+use batl::crash::Kind as CrashKind;
+use batl::system::Overrides;
 use clap::{Parser, Subcommand, Args};
 
 mod commands;
@@ -8,7 +12,29 @@ mod utils;
 #[command(name = "batl")]
 #[command(version = env!("CARGO_PKG_VERSION"))]
 #[command(about = "The multi-repo development tool")]
+#[command(after_help = utils::EXIT_CODES)]
 struct Cli {
+	/// Reject unknown keys in batl.toml files
+	#[arg(long = "strict", global = true)]
+	strict: bool,
+
+	/// When to color output
+	#[arg(long = "color", global = true, value_enum, default_value_t)]
+	color: output::ColorChoice,
+
+	/// How to report progress of fetches, publishes,
+	/// archives, and multi-repository scripts
+	#[arg(long = "progress", global = true, value_enum, default_value_t)]
+	progress: output::ProgressFormat,
+
+	/// Use this battalion root instead of finding one
+	#[arg(long = "root", global = true)]
+	root: Option<std::path::PathBuf>,
+
+	/// Use the settings of a profile from the batlrc
+	#[arg(long = "profile", global = true)]
+	profile: Option<String>,
+
 	#[command(subcommand)]
 	subcmd: SubCommand
 }
@@ -18,16 +44,321 @@ enum SubCommand {
 	Workspace(SubCmdArgs<commands::workspace::Commands>),
 	Link(SubCmdArgs<commands::link::Commands>),
 	Repository(SubCmdArgs<commands::repository::Commands>),
+	Artifacts(SubCmdArgs<commands::artifacts::Commands>),
+	Registry(SubCmdArgs<commands::registry::Commands>),
+	/// Show who ran which scripts, from the audit log
+	AuditLog(SubCmdArgs<commands::auditlog::Commands>),
+	/// Look at what an archive of a repository would hold
+	Package(SubCmdArgs<commands::package::Commands>),
+	/// Check repositories against the conventions in
+	/// batl.policy.toml files
+	Policy(SubCmdArgs<commands::policy::Commands>),
+	/// Add, remove and list the scripts in a batl.toml
+	Script(SubCmdArgs<commands::script::Commands>),
+	/// Translate between resource names and the folders
+	/// they are stored in
+	Path(SubCmdArgs<commands::path::Commands>),
+	/// Look at the diagnostic bundles written when batl
+	/// crashed
+	Crash(SubCmdArgs<commands::crash::Commands>),
 	Setup,
 	Add {
-		name: String
+		/// A repository, or an @group to add each of its
+		/// repositories
+		#[arg(required_unless_present = "interactive")]
+		name: Option<String>,
+		#[arg(short = 'i', long = "interactive")]
+		interactive: bool,
+		/// Stop at the first dependency that can't be added,
+		/// when adding several
+		#[arg(long = "fail-fast")]
+		fail_fast: bool,
+		/// Report the dependencies that couldn't be added as
+		/// json, when adding several
+		#[arg(long = "json")]
+		json: bool
 	},
 	#[command(alias = "rm")]
 	Remove {
 		name: String
 	},
-	Upgrade,
-	Auth
+	/// Change the version requirement of a dependency
+	Update {
+		name: String,
+		requirement: String
+	},
+	/// Bring the battalion root up to date, showing the
+	/// changes first and backing up what they overwrite
+	Upgrade {
+		/// Only show what the upgrade would change
+		#[arg(long = "dry-run")]
+		dry_run: bool,
+		/// Undo the upgrade with this backup time
+		#[arg(long = "rollback", conflicts_with = "dry_run")]
+		rollback: Option<String>
+	},
+	Auth {
+		/// Sign in through the browser with the OAuth
+		/// device flow instead of an API key
+		#[arg(long = "device")]
+		device: bool,
+		#[command(subcommand)]
+		subcmd: Option<commands::auth::Commands>
+	},
+	Doctor {
+		#[arg(short = 'n')]
+		name: Option<String>
+	},
+	/// Create a key to sign published archives with,
+	/// printing the public key others trust it by
+	Keygen {
+		/// The file to write the secret key to
+		path: std::path::PathBuf
+	},
+	/// Reconcile the name a repository declares in its
+	/// batl.toml with the one it is stored under, by
+	/// declaring the stored name unless --move is given
+	FixName {
+		#[arg(short = 'n')]
+		name: Option<String>,
+		/// Move the repository to its declared name instead
+		#[arg(long = "move")]
+		to_declared: bool
+	},
+	/// List the scripts of a repository, or with --all,
+	/// which repositories define which scripts
+	Scripts {
+		#[arg(short = 'n', conflicts_with = "all")]
+		name: Option<String>,
+		/// List the scripts of every repository
+		#[arg(long = "all")]
+		all: bool,
+		/// Only list the repositories defining this script
+		#[arg(long = "name", requires = "all")]
+		script: Option<String>,
+		/// List the repositories missing the script instead,
+		/// failing if there are any
+		#[arg(long = "missing", requires = "script")]
+		missing: bool,
+		/// A name prefix, or an @group from the batlrc
+		#[arg(requires = "all")]
+		filter: Option<String>
+	},
+	Grep {
+		pattern: String,
+		/// A name prefix, or an @group from the batlrc
+		filter: Option<String>
+	},
+	Find {
+		glob: String,
+		/// A name prefix, or an @group from the batlrc
+		filter: Option<String>,
+		#[arg(long = "json")]
+		json: bool
+	},
+	Docs {
+		#[arg(short = 'n')]
+		name: Option<String>,
+		#[arg(long = "port", default_value_t = 0)]
+		port: u16
+	},
+	/// Serve a registry stored in a directory over http,
+	/// so it can be published to and fetched from
+	#[cfg(feature = "serve")]
+	Serve {
+		/// The directory packages are stored in, such as a
+		/// mirror
+		root: std::path::PathBuf,
+		#[arg(long = "bind", default_value = "127.0.0.1:8080")]
+		bind: String,
+		/// A file with a token on each line, followed by the
+		/// scopes it grants
+		#[arg(long = "token-file")]
+		token_file: Option<std::path::PathBuf>,
+		#[arg(long = "workers", default_value_t = 4)]
+		workers: usize
+	},
+	Licenses {
+		#[arg(short = 'n')]
+		name: Option<String>,
+		/// Detect licenses from license files as well
+		#[arg(long = "detect")]
+		detect: bool,
+		#[arg(long = "json")]
+		json: bool,
+		#[arg(long = "csv", conflicts_with = "json")]
+		csv: bool
+	},
+	Freeze {
+		tag: String,
+		#[arg(short = 'n')]
+		name: Option<String>,
+		/// Replace an existing freeze with the same tag
+		#[arg(long = "force")]
+		force: bool
+	},
+	Thaw {
+		tag: String,
+		/// Stop at the first repository that can't be
+		/// thawed
+		#[arg(long = "fail-fast")]
+		fail_fast: bool,
+		/// Report the repositories that couldn't be thawed
+		/// as json
+		#[arg(long = "json")]
+		json: bool
+	},
+	/// Set up a workspace and every repository it needs
+	/// from a manifest, given as a url or path
+	Bootstrap {
+		url: String,
+		/// Only show what would be set up
+		#[arg(long = "dry-run")]
+		dry_run: bool,
+		/// Stop at the first repository that can't be set up
+		#[arg(long = "fail-fast")]
+		fail_fast: bool,
+		/// Report what couldn't be set up as json
+		#[arg(long = "json")]
+		json: bool
+	},
+	Audit {
+		#[arg(short = 'n')]
+		name: Option<String>,
+		#[arg(long = "json")]
+		json: bool
+	},
+	Schema {
+		/// Write the schema under the battalion root and
+		/// point editors at it
+		#[arg(long = "write")]
+		write: bool
+	},
+	Check {
+		path: Option<std::path::PathBuf>
+	},
+	Summary {
+		#[arg(short = 'n')]
+		name: Option<String>,
+		#[arg(long = "at")]
+		at: Option<semver::Version>,
+		#[arg(long = "json")]
+		json: bool
+	},
+	/// Show the dependencies of a repository as a tree.
+	/// Inside a workspace, or with -w, each is marked as
+	/// linked or not.
+	Deps {
+		#[arg(short = 'n', conflicts_with = "invert")]
+		name: Option<String>,
+		/// Show what depends on a repository instead, among
+		/// the repositories available locally
+		#[arg(long = "invert")]
+		invert: Option<String>,
+		/// How many levels of dependencies to show
+		#[arg(long = "depth")]
+		depth: Option<usize>,
+		/// A workspace to check links against
+		#[arg(short = 'w', long = "workspace")]
+		workspace: Option<String>
+	},
+	/// Show every path through the dependencies that leads
+	/// to a dependency
+	Why {
+		dependency: String,
+		#[arg(short = 'n')]
+		name: Option<String>
+	},
+	/// Remove the generated files of a repository, such as
+	/// script outputs and logs
+	Clean {
+		#[arg(short = 'n')]
+		name: Option<String>,
+		/// Only list what would be removed
+		#[arg(long = "dry-run")]
+		dry_run: bool,
+		/// Remove generated files even if git tracks some
+		/// of them
+		#[arg(long = "force")]
+		force: bool,
+		/// Clean the caches battalion keeps across every
+		/// repository instead, in every category unless some
+		/// are picked
+		#[arg(long = "all", conflicts_with = "name")]
+		all: bool,
+		/// Archives generated for publishing
+		#[arg(long = "archives", conflicts_with = "name")]
+		archives: bool,
+		/// Cached registry responses
+		#[arg(long = "http-cache", conflicts_with = "name")]
+		http_cache: bool,
+		/// Script logs of every repository
+		#[arg(long = "logs", conflicts_with = "name")]
+		logs: bool,
+		/// Downloads left behind by interrupted fetches
+		#[arg(long = "trash", conflicts_with = "name")]
+		trash: bool,
+		/// Script outputs of every repository
+		#[arg(long = "script-cache", conflicts_with = "name")]
+		script_cache: bool,
+		/// Only clean files unchanged for this long, such as
+		/// `30d`
+		#[arg(long = "older-than", conflicts_with = "name")]
+		older_than: Option<batl::units::Duration>
+	},
+	/// Export the dependency graph of a workspace or
+	/// repository, for sharing outside of the CLI
+	Graph {
+		/// A repository to graph, instead of the workspace or
+		/// repository in the current directory
+		#[arg(short = 'n', conflicts_with = "workspace")]
+		name: Option<String>,
+		/// A workspace to graph
+		#[arg(short = 'w', long = "workspace")]
+		workspace: Option<String>,
+		#[arg(long = "format", value_enum, default_value_t)]
+		format: commands::GraphFormat,
+		/// The script ninja build rules run
+		#[arg(long = "script", required_if_eq("format", "ninja"))]
+		script: Option<String>
+	},
+	/// Show where the links of a workspace have drifted
+	/// from its config
+	DiffLinks {
+		#[arg(short = 'n')]
+		name: Option<String>,
+		/// Recreate missing links, point misdirected ones
+		/// back at their repositories, and remove extra ones
+		#[arg(long = "fix")]
+		fix: bool,
+		/// Remove links to repositories that were removed
+		/// from the workspace dependencies
+		#[arg(long = "prune")]
+		prune: bool,
+		/// Prune without asking first
+		#[arg(long = "yes", requires = "prune")]
+		yes: bool
+	},
+	/// Pick up a multi-repository run that was interrupted
+	/// or failed, skipping what went through. Lists the runs
+	/// that can be resumed without an id.
+	Resume {
+		id: Option<String>,
+		/// Forget the run instead of resuming it
+		#[arg(long = "discard", requires = "id")]
+		discard: bool
+	},
+	/// Run a command in a repository with the environment
+	/// its scripts get, without declaring it as a script
+	Run {
+		#[arg(short = 'n')]
+		name: Option<String>,
+		/// Use a fetched version of a dependency instead
+		#[arg(long = "with")]
+		with: Vec<batl::resource::VersionedName>,
+		#[arg(last = true, required = true)]
+		args: Vec<String>
+	}
 }
 
 #[derive(Args)]
@@ -39,20 +370,157 @@ struct SubCmdArgs<T: Subcommand> {
 
 fn main() {
 	let cli = Cli::parse();
+	utils::take_resume();
+
+	let overrides = Overrides::new(cli.root.map(|root| std::path::absolute(&root).unwrap_or(root)), cli.profile);
+	let context = match cli.progress {
+		output::ProgressFormat::Json => BatlContext::detect_with(&overrides).with_output(Box::new(output::JsonProgress)),
+		output::ProgressFormat::Text => BatlContext::detect_with(&overrides)
+	};
+
+	let context = if cli.strict {
+		let mut settings = *context.settings();
+		settings.strict = true;
+
+		context.with_settings(settings)
+	} else {
+		context
+	};
+
+	output::init(cli.color, context.system());
+	install_panic_hook(context.system().clone());
+
+	if let Some(profile) = overrides.selected_profile().filter(|_| context.system().profile().is_none()) {
+		let err = utils::UtilityError::ConfigError(format!("profile {} is not defined in the batlrc", profile));
+
+		output::error(err.to_string().as_str());
+		std::process::exit(err.exit_code());
+	}
+
+	let notify_title = notify_title(&cli.subcmd);
+	let started = std::time::Instant::now();
 
 	let result = match cli.subcmd {
-		SubCommand::Workspace(args) => commands::workspace::run(args.subcmd),
-		SubCommand::Link(args) => commands::link::run(args.subcmd),
-		SubCommand::Repository(args) => commands::repository::run(args.subcmd),
-		SubCommand::Setup => commands::cmd_setup(),
-		SubCommand::Add { name } => commands::cmd_add(name),
-		SubCommand::Remove { name } => commands::cmd_remove(name),
-		SubCommand::Upgrade => commands::cmd_upgrade(),
-		SubCommand::Auth => commands::cmd_auth()
+		SubCommand::Workspace(args) => commands::workspace::run(args.subcmd, &context),
+		SubCommand::Link(args) => commands::link::run(args.subcmd, &context),
+		SubCommand::Repository(args) => commands::repository::run(args.subcmd, &context),
+		SubCommand::Artifacts(args) => commands::artifacts::run(args.subcmd, &context),
+		SubCommand::Registry(args) => commands::registry::run(args.subcmd, &context),
+		SubCommand::AuditLog(args) => commands::auditlog::run(args.subcmd, &context),
+		SubCommand::Package(args) => commands::package::run(args.subcmd, &context),
+		SubCommand::Policy(args) => commands::policy::run(args.subcmd, &context),
+		SubCommand::Script(args) => commands::script::run(args.subcmd, &context),
+		SubCommand::Path(args) => commands::path::run(args.subcmd, &context),
+		SubCommand::Crash(args) => commands::crash::run(args.subcmd, &context),
+		SubCommand::Setup => commands::cmd_setup(&context),
+		SubCommand::Add { name, interactive, fail_fast, json } => commands::cmd_add(name, interactive, utils::Batch::new(fail_fast, json), &context),
+		SubCommand::Remove { name } => commands::cmd_remove(name, &context),
+		SubCommand::Update { name, requirement } => commands::cmd_update(name, requirement, &context),
+		SubCommand::Upgrade { dry_run, rollback } => commands::cmd_upgrade(dry_run, rollback, &context),
+		SubCommand::Auth { device, subcmd } => match subcmd {
+			Some(cmd) => commands::auth::run(cmd, &context),
+			None => commands::cmd_auth(device, &context)
+		},
+		SubCommand::Doctor { name } => commands::cmd_doctor(name, &context),
+		SubCommand::Keygen { path } => commands::cmd_keygen(path),
+		SubCommand::FixName { name, to_declared } => commands::cmd_fix_name(name, to_declared, &context),
+		SubCommand::Scripts { name, all, script, missing, filter } => if all {
+			commands::cmd_scripts_all(script, missing, filter, &context)
+		} else {
+			commands::cmd_scripts(name, &context)
+		},
+		SubCommand::Grep { pattern, filter } => commands::cmd_grep(pattern, filter, &context),
+		SubCommand::Find { glob, filter, json } => commands::cmd_find(glob, filter, json, &context),
+		SubCommand::Docs { name, port } => commands::cmd_docs(name, port, &context),
+		#[cfg(feature = "serve")]
+		SubCommand::Serve { root, bind, token_file, workers } => commands::cmd_serve(root, bind, token_file, workers),
+		SubCommand::Licenses { name, detect, json, csv } => commands::cmd_licenses(name, detect, json, csv, &context),
+		SubCommand::Freeze { tag, name, force } => commands::cmd_freeze(tag, name, force, &context),
+		SubCommand::Thaw { tag, fail_fast, json } => commands::cmd_thaw(tag, utils::Batch::new(fail_fast, json), &context),
+		SubCommand::Bootstrap { url, dry_run, fail_fast, json } => commands::cmd_bootstrap(url, dry_run, utils::Batch::new(fail_fast, json), &context),
+		SubCommand::Audit { name, json } => commands::cmd_audit(name, json, &context),
+		SubCommand::Schema { write } => commands::cmd_schema(write, &context),
+		SubCommand::Check { path } => commands::cmd_check(path),
+		SubCommand::Summary { name, at, json } => commands::cmd_summary(name, at, json, &context),
+		SubCommand::Deps { name, invert, depth, workspace } => commands::cmd_deps(name, invert, depth, workspace, &context),
+		SubCommand::Why { dependency, name } => commands::cmd_why(dependency, name, &context),
+		SubCommand::DiffLinks { name, fix, prune, yes } => commands::cmd_diff_links(name, fix, prune, yes, &context),
+		SubCommand::Graph { name, workspace, format, script } => commands::cmd_graph(name, workspace, format, script, &context),
+		SubCommand::Clean { name, dry_run, force, all, archives, http_cache, logs, trash, script_cache, older_than } => {
+			let categories = [
+				(archives, CleanCategory::Archives),
+				(http_cache, CleanCategory::HttpCache),
+				(logs, CleanCategory::Logs),
+				(trash, CleanCategory::Trash),
+				(script_cache, CleanCategory::ScriptCache)
+			].into_iter().filter_map(|(picked, category)| picked.then_some(category)).collect::<Vec<_>>();
+
+			if all || !categories.is_empty() || older_than.is_some() {
+				commands::cmd_clean_all(categories, older_than, dry_run, &context)
+			} else {
+				commands::cmd_clean(name, dry_run, force, &context)
+			}
+		},
+		SubCommand::Resume { id, discard } => commands::cmd_resume(id, discard, &context),
+		SubCommand::Run { name, with, args } => commands::cmd_run(name, with, args, &context)
 	};
 
+	if let Some(title) = notify_title {
+		utils::notify_finished(&context, title, result.as_ref().err(), started.elapsed());
+	}
+
 	if let Err(err) = result {
-		output::error(err.to_string().as_str());
-		std::process::exit(1);
+		// A passed through exit code was already explained by
+		// the command that exited with it
+		if !matches!(err, utils::UtilityError::Reported(_) | utils::UtilityError::Exited(_)) {
+			output::error(err.to_string().as_str());
+		}
+
+		if err.is_fatal() {
+			utils::report_crash(context.system(), CrashKind::Error, err.to_string(), None);
+		}
+
+		std::process::exit(err.exit_code());
+	}
+}
+
+/// Write a diagnostic bundle when batl panics, after the
+/// panic is printed as usual
+fn install_panic_hook(system: batl::system::Context) {
+	let default_hook = std::panic::take_hook();
+
+	std::panic::set_hook(Box::new(move |info| {
+		default_hook(info);
+
+		let message = info.payload().downcast_ref::<&str>().map(|message| message.to_string())
+			.or_else(|| info.payload().downcast_ref::<String>().cloned())
+			.unwrap_or_else(|| "Unknown panic".to_owned());
+		let location = info.location().map(|location| location.to_string());
+
+		utils::report_crash(&system, CrashKind::Panic, message, location);
+	}));
+}
+
+/// What a command is called in notifications, for the
+/// commands that run scripts
+fn notify_title(subcmd: &SubCommand) -> Option<String> {
+	match subcmd {
+		SubCommand::Repository(args) => match &args.subcmd {
+			commands::repository::Commands::Exec { name, script, .. } => Some(match name {
+				Some(name) => format!("batl repository exec {} in {}", script, name),
+				None => format!("batl repository exec {}", script)
+			}),
+			_ => None
+		},
+		SubCommand::Link(args) => match &args.subcmd {
+			commands::link::Commands::Exec { name, script, .. } => Some(match name {
+				Some(name) => format!("batl link exec {} in {}", script, name),
+				None => format!("batl link exec {}", script)
+			}),
+			commands::link::Commands::Run { name, .. } => Some(format!("batl link run {}", name)),
+			_ => None
+		},
+		SubCommand::Run { args, .. } => Some(format!("batl run {}", args.join(" "))),
+		_ => None
 	}
 }
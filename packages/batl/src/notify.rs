@@ -0,0 +1,201 @@
+use core::time::Duration;
+use crate::error as batlerror;
+use crate::executor::Interpreter;
+use crate::system::Context;
+use serde::Serialize;
+use std::process::Command;
+
+
+/// Prefix of the variables a notification command is
+/// given the run in
+const ENV_PREFIX: &str = "BATL_NOTIFY_";
+
+
+/// A finished run the user may want to hear about
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Event {
+	/// What ran, such as `batl repository exec build`
+	pub title: String,
+
+	/// How it went
+	pub message: String,
+	pub passed: bool,
+	pub elapsed: Duration
+}
+
+/// Where notifications are sent, from `[notify]` in the
+/// batlrc
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Notifier {
+	/// Runs shorter than this aren't notified about
+	pub after: Duration,
+	pub desktop: bool,
+	pub webhook: Option<String>,
+	pub command: Option<String>,
+
+	/// Runs the notification command
+	pub interpreter: Interpreter
+}
+
+/// The json body posted to a webhook
+#[derive(Serialize)]
+struct Payload<'event> {
+	/// The title and message together, which chat services
+	/// show as is
+	text: String,
+
+	/// The title of the event
+	title: &'event str,
+
+	/// The message of the event
+	message: &'event str,
+
+	/// Whether the run passed
+	passed: bool,
+
+	/// How long the run took, in whole seconds
+	elapsed_secs: u64
+}
+
+impl Event {
+	#[inline]
+	#[must_use]
+	pub const fn new(title: String, message: String, passed: bool, elapsed: Duration) -> Self {
+		Self { title, message, passed, elapsed }
+	}
+
+	/// `passed` or `failed`
+	#[inline]
+	#[must_use]
+	pub const fn status(&self) -> &'static str {
+		if self.passed {
+			"passed"
+		} else {
+			"failed"
+		}
+	}
+}
+
+impl Notifier {
+	/// The notifier configured in the batlrc, or `None` if
+	/// no way of notifying is turned on
+	#[inline]
+	#[must_use]
+	pub fn from_system(system: &Context) -> Option<Self> {
+		let notify = system.batlrc()?.notify?;
+
+		let notifier = Self {
+			after: notify.after.map(|after| after.0).unwrap_or_default(),
+			desktop: notify.desktop.unwrap_or(false),
+			webhook: notify.webhook,
+			command: notify.command,
+			interpreter: Interpreter::configured(system)
+		};
+
+		(notifier.desktop || notifier.webhook.is_some() || notifier.command.is_some())
+			.then_some(notifier)
+	}
+
+	/// Whether a run that took this long is notified about
+	#[inline]
+	#[must_use]
+	pub fn should_notify(&self, elapsed: Duration) -> bool {
+		elapsed >= self.after
+	}
+
+	/// Send an event everywhere configured. A failing
+	/// channel doesn't stop the others, and its error is
+	/// returned among those of every failed channel.
+	#[inline]
+	#[must_use]
+	pub fn notify(&self, event: &Event) -> Vec<batlerror::NotifyError> {
+		let mut errors = Vec::new();
+
+		if self.desktop {
+			errors.extend(notify_desktop(event).err());
+		}
+
+		if let Some(url) = self.webhook.as_ref() {
+			errors.extend(notify_webhook(url, event).err());
+		}
+
+		if let Some(command) = self.command.as_ref() {
+			errors.extend(notify_command(self.interpreter, command, event).err());
+		}
+
+		errors
+	}
+}
+
+/// Show a desktop notification, with `notify-send` or
+/// `osascript`
+fn notify_desktop(event: &Event) -> Result<(), batlerror::NotifyError> {
+	let mut command = if cfg!(target_os = "macos") {
+		let escape = |text: &str| text.replace('\\', "\\\\").replace('"', "\\\"");
+
+		let mut osascript = Command::new("osascript");
+		osascript.arg("-e").arg(format!(
+			"display notification \"{}\" with title \"{}\"",
+			escape(&event.message),
+			escape(&event.title)
+		));
+
+		osascript
+	} else if cfg!(unix) {
+		let mut notify_send = Command::new("notify-send");
+		notify_send.arg(&event.title).arg(&event.message);
+
+		notify_send
+	} else {
+		return Err(batlerror::NotifyError::Unsupported("desktop notifications".to_owned()));
+	};
+
+	check_status(&mut command)
+}
+
+/// Post the event to a webhook as json
+fn notify_webhook(url: &str, event: &Event) -> Result<(), batlerror::NotifyError> {
+	let body = serde_json::to_string(&Payload {
+		text: format!("{}: {}", event.title, event.message),
+		title: &event.title,
+		message: &event.message,
+		passed: event.passed,
+		elapsed_secs: event.elapsed.as_secs()
+	}).map_err(|err| batlerror::NotifyError::Network(err.to_string()))?;
+
+	ureq::post(url)
+		.set("content-type", "application/json")
+		.send_string(&body)
+		.map_err(|err| batlerror::NotifyError::Network(err.to_string()))?;
+
+	Ok(())
+}
+
+/// Run a command through the interpreter, describing the
+/// event in `BATL_NOTIFY_*` variables
+fn notify_command(interpreter: Interpreter, command: &str, event: &Event) -> Result<(), batlerror::NotifyError> {
+	let mut shell = interpreter.command(command);
+
+	shell
+		.env(format!("{ENV_PREFIX}TITLE"), &event.title)
+		.env(format!("{ENV_PREFIX}MESSAGE"), &event.message)
+		.env(format!("{ENV_PREFIX}STATUS"), event.status())
+		.env(format!("{ENV_PREFIX}ELAPSED"), event.elapsed.as_secs().to_string());
+
+	check_status(&mut shell)
+}
+
+/// Run a command, failing if it doesn't exit successfully
+fn check_status(command: &mut Command) -> Result<(), batlerror::NotifyError> {
+	let status = command.status()?;
+
+	if status.success() {
+		Ok(())
+	} else {
+		Err(batlerror::NotifyError::CommandFailed(format!(
+			"{} exited with {status}", command.get_program().to_string_lossy()
+		)))
+	}
+}
@@ -1,14 +1,205 @@
+use batl::context::{BatlContext, Output, ProgressEvent};
+use batl::resource::archive::Stream;
+use batl::resource::batlrc::Theme0_2_2;
+use clap::ValueEnum;
 use colored::*;
+use std::io::Write;
+use std::path::Path;
+use std::sync::OnceLock;
 
 
+#[derive(ValueEnum, Clone, Copy, Default)]
+pub enum ColorChoice {
+	Always,
+	#[default]
+	Auto,
+	Never
+}
+
+/// How long running operations report their progress
+#[derive(ValueEnum, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ProgressFormat {
+	/// Only the usual messages
+	#[default]
+	Text,
+	/// Newline-delimited JSON events on stderr, for
+	/// frontends to follow along
+	Json
+}
+
+struct Theme {
+	success: Color,
+	error: Color,
+	info: Color,
+	warning: Color,
+	namespace: Color,
+	resource: Option<Color>
+}
+
+/// The theme of the batlrc, set once the root is known
+static THEME: OnceLock<Theme> = OnceLock::new();
+
+impl Theme {
+	fn from_batlrc(system: Option<&batl::system::Context>) -> Self {
+		let theme = system.and_then(batl::system::Context::batlrc)
+			.and_then(|batlrc| batlrc.theme)
+			.unwrap_or_default();
+
+		let color = |value: Option<String>, default: Color| {
+			value.and_then(|name| name.parse().ok()).unwrap_or(default)
+		};
+
+		let Theme0_2_2 { success, error, info, warning, namespace, resource, .. } = theme;
+
+		Self {
+			success: color(success, Color::Green),
+			error: color(error, Color::Red),
+			info: color(info, Color::Blue),
+			warning: color(warning, Color::Yellow),
+			namespace: color(namespace, Color::BrightBlack),
+			resource: resource.and_then(|name| name.parse().ok())
+		}
+	}
+}
+
+/// The theme in use, the default one until [`init`] reads
+/// the batlrc
+fn theme() -> &'static Theme {
+	THEME.get_or_init(|| Theme::from_batlrc(None))
+}
+
+/// Decide whether output is colored, following the
+/// NO_COLOR and CLICOLOR conventions in auto mode, and
+/// read the theme from the batlrc of the root
+pub fn init(choice: ColorChoice, system: &batl::system::Context) {
+	drop(THEME.set(Theme::from_batlrc(Some(system))));
+
+	let enabled = match choice {
+		ColorChoice::Always => true,
+		ColorChoice::Never => false,
+		ColorChoice::Auto => auto_color()
+	};
+
+	colored::control::set_override(enabled);
+}
+
+fn auto_color() -> bool {
+	let env = |key: &str| std::env::var(key).ok().filter(|value| !value.is_empty());
+
+	if env("NO_COLOR").is_some() {
+		return false;
+	}
+
+	if env("CLICOLOR_FORCE").is_some_and(|value| value != "0") {
+		return true;
+	}
+
+	if env("CLICOLOR").is_some_and(|value| value == "0") {
+		return false;
+	}
+
+	console::Term::stdout().is_term()
+}
+
 pub fn success(message: &str) {
-	println!("[{}] {}", "OK".green(), message)
+	println!("[{}] {}", "OK".color(theme().success), message)
 }
 
 pub fn error(message: &str) {
-	println!("[{}] {}", "ERR".red(), message)
+	println!("[{}] {}", "ERR".color(theme().error), message)
 }
 
 pub fn info(message: &str) {
-	println!("[{}] {}", "INFO".blue(), message)
+	println!("[{}] {}", "INFO".color(theme().info), message)
+}
+
+pub fn warning(message: &str) {
+	println!("[{}] {}", "WARN".color(theme().warning), message)
+}
+
+/// Print an error to stderr, for commands that keep stdout
+/// for json
+pub fn error_stderr(message: &str) {
+	eprintln!("[{}] {}", "ERR".color(theme().error), message)
+}
+
+/// Print a warning to stderr, for commands that keep stdout
+/// for json
+pub fn warning_stderr(message: &str) {
+	eprintln!("[{}] {}", "WARN".color(theme().warning), message)
+}
+
+/// Color a resource name, marking its namespace apart
+/// from the resource itself
+pub fn name(name: &str) -> String {
+	let (namespace, resource) = match name.rsplit_once('/') {
+		Some((namespace, resource)) => (format!("{}/", namespace).color(theme().namespace).to_string(), resource),
+		None => (String::new(), name)
+	};
+
+	match theme().resource {
+		Some(color) => format!("{}{}", namespace, resource.color(color)),
+		None => format!("{}{}", namespace, resource)
+	}
+}
+
+/// Print a path on its own line, as is. Paths that are
+/// not valid UTF-8 are written as raw bytes where the
+/// platform allows, so they still work in `cd $(...)`.
+pub fn path(path: &Path) {
+	let mut stdout = std::io::stdout().lock();
+
+	#[cfg(unix)]
+	let written = stdout.write_all(std::os::unix::ffi::OsStrExt::as_bytes(path.as_os_str()));
+
+	#[cfg(not(unix))]
+	let written = stdout.write_all(path.display().to_string().as_bytes());
+
+	if written.and_then(|_| stdout.write_all(b"\n")).is_err() {
+		std::process::exit(1);
+	}
+}
+
+/// Output for `--progress json`, writing each progress
+/// report to stderr as a line of JSON
+pub struct JsonProgress;
+
+impl Output for JsonProgress {
+	fn progress(&self, event: &ProgressEvent<'_>) {
+		if let Ok(line) = serde_json::to_string(event) {
+			let mut stderr = std::io::stderr().lock();
+			drop(writeln!(stderr, "{}", line));
+		}
+	}
+}
+
+/// Reports how far along a long running operation is,
+/// through the output of the context it runs in
+pub struct Progress<'context> {
+	operation: &'static str,
+	output: &'context dyn Output
+}
+
+impl<'context> Progress<'context> {
+	pub fn new(context: &'context BatlContext, operation: &'static str) -> Self {
+		Self { operation, output: context.output() }
+	}
+
+	/// Report entering a phase, or moving through one.
+	/// The percent covers the whole operation, if known.
+	pub fn report(&self, phase: &str, percent: Option<f64>, message: &str) {
+		let percent = percent.map(|value| (value.clamp(0.0, 100.0) * 10.0).round() / 10.0);
+
+		self.output.progress(&ProgressEvent::new(self.operation, phase, percent, message));
+	}
+
+	pub fn done(&self, message: &str) {
+		self.report("done", Some(100.0), message);
+	}
+
+	/// Report a phase as an archive streams, between two
+	/// percents of the whole operation
+	pub fn track<'stream, T>(&'stream self, stream: Stream<'stream, T>, phase: &'static str, span: (u8, u8)) -> Stream<'stream, T> {
+		stream.report_to(self.output, self.operation, phase, span)
+	}
 }
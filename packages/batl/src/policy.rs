@@ -0,0 +1,313 @@
+use crate::audit;
+use crate::context::BatlContext;
+use crate::error as batlerror;
+use crate::resource::{Name, Repository, Resource as _};
+use crate::resource::license::Status as LicenseStatus;
+use globset::{Glob, GlobSetBuilder};
+use semver::{Op, VersionReq};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+
+/// The name of policy files, kept at the root of the
+/// repositories and in namespace folders
+pub const POLICY_FILE: &str = "batl.policy.toml";
+
+
+/// The conventions repositories are held to, from every
+/// policy file that applies to them
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Policy {
+	/// Scripts every repository must declare
+	pub required_scripts: Vec<String>,
+
+	/// Licenses repositories and their dependencies may
+	/// have, or `None` to allow any
+	pub allowed_licenses: Option<Vec<String>>,
+
+	/// How tightly dependencies must be pinned
+	pub pinning: Pinning,
+
+	/// Globs of names repositories may not depend on, even
+	/// through other dependencies
+	pub forbidden_dependencies: Vec<String>,
+
+	/// The policy files read, least specific first
+	pub sources: Vec<PathBuf>
+}
+
+/// How tightly the version requirements of dependencies
+/// must be pinned
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum Pinning {
+	/// Any requirement
+	#[default]
+	Any,
+
+	/// Requirements with an upper bound, such as `^1.2` or
+	/// `~1.2.3`, rather than `*` or `>=1.0`
+	Bounded,
+
+	/// A single exact version, such as `=1.2.3`
+	Exact
+}
+
+/// A rule a repository breaks
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Violation {
+	/// The repository breaking the rule
+	pub name: Name,
+
+	pub rule: Rule,
+
+	/// What breaks it, such as the missing script or the
+	/// forbidden dependency
+	pub subject: String,
+
+	pub message: String
+}
+
+/// The kinds of rules a policy has
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum Rule {
+	RequiredScript,
+	AllowedLicense,
+	Pinning,
+	ForbiddenDependency
+}
+
+/// A policy file as it is written
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct PolicyFile {
+	/// Rules for scripts
+	scripts: Option<ScriptRules>,
+
+	/// Rules for licenses
+	licenses: Option<LicenseRules>,
+
+	/// Rules for dependencies
+	dependencies: Option<DependencyRules>
+}
+
+/// The `[scripts]` table of a policy file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ScriptRules {
+	/// Scripts every repository must declare
+	required: Option<Vec<String>>
+}
+
+/// The `[licenses]` table of a policy file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LicenseRules {
+	/// Licenses that are allowed
+	allowed: Option<Vec<String>>
+}
+
+/// The `[dependencies]` table of a policy file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DependencyRules {
+	/// How tightly requirements must be pinned
+	pinning: Option<Pinning>,
+
+	/// Globs of names that may not be depended on
+	forbidden: Option<Vec<String>>
+}
+
+impl Policy {
+	/// The policy for a repository name in the battalion of
+	/// a context, from the policy file at the root of the
+	/// repositories and those in the folders of its
+	/// namespaces. More specific files replace each rule
+	/// they set.
+	///
+	/// # Errors
+	///
+	/// Returns `InvalidConfig` if a policy file can't be
+	/// read, and propogates any other IO errors
+	#[inline]
+	pub fn load_in(context: &BatlContext, name: &Name) -> Result<Self, batlerror::GeneralResourceError> {
+		let mut policy = Self::default();
+
+		let Some(mut directory) = context.system().repository_root() else {
+			return Ok(policy);
+		};
+
+		policy.apply(&directory.join(POLICY_FILE))?;
+
+		let namespaces = PathBuf::from(name);
+
+		for namespace in namespaces.parent().into_iter().flat_map(Path::components) {
+			directory.push(namespace);
+			policy.apply(&directory.join(POLICY_FILE))?;
+		}
+
+		Ok(policy)
+	}
+
+	/// Check a repository against the policy, returning
+	/// every rule it breaks
+	#[inline]
+	#[must_use]
+	pub fn check(&self, context: &BatlContext, repository: &Repository) -> Vec<Violation> {
+		let mut violations = Vec::new();
+		let name = repository.name();
+
+		let violation = |rule: Rule, subject: &str, message: String| Violation {
+			name: name.clone(),
+			rule,
+			subject: subject.to_owned(),
+			message
+		};
+
+		let scripts = repository.scripts();
+
+		for script in self.required_scripts.iter().filter(|script| !scripts.contains_key(*script)) {
+			violations.push(violation(Rule::RequiredScript, script, format!("Missing the {script} script")));
+		}
+
+		if self.allowed_licenses.is_some() {
+			let report = repository.licenses(context, false);
+
+			match report.license.as_deref() {
+				None => violations.push(violation(Rule::AllowedLicense, "", "Declares no license".to_owned())),
+				Some(license) if !self.allows_license(license) => {
+					violations.push(violation(Rule::AllowedLicense, license, format!("Licensed {license}, which is not allowed")));
+				},
+				Some(_) => ()
+			}
+
+			for entry in report.dependencies.iter().filter(|entry| entry.status != LicenseStatus::Missing) {
+				let dependency = entry.name.to_string();
+
+				match entry.license() {
+					None => violations.push(violation(Rule::AllowedLicense, &dependency, format!("Depends on {dependency}, which has no known license"))),
+					Some(license) if !self.allows_license(license) => {
+						violations.push(violation(Rule::AllowedLicense, &dependency, format!("Depends on {dependency}, licensed {license}, which is not allowed")));
+					},
+					Some(_) => ()
+				}
+			}
+		}
+
+		let mut requirements = repository.config().dependencies.iter().collect::<Vec<_>>();
+		requirements.sort_by_key(|requirement| requirement.0.to_string());
+
+		for requirement in requirements.iter().filter(|requirement| !self.pinning.allows(requirement.1)) {
+			violations.push(violation(Rule::Pinning, &requirement.0.to_string(), format!(
+				"Requires {} at {}, which {}",
+				requirement.0,
+				requirement.1,
+				if self.pinning == Pinning::Exact { "is not an exact version" } else { "has no upper bound" }
+			)));
+		}
+
+		if !self.forbidden_dependencies.is_empty() {
+			let mut builder = GlobSetBuilder::new();
+
+			// Patterns were checked as the policy was read
+			for glob in self.forbidden_dependencies.iter().filter_map(|pattern| Glob::new(pattern).ok()) {
+				builder.add(glob);
+			}
+
+			let forbidden = builder.build().unwrap_or_default();
+			let (pinned, unpinned) = audit::pinned(context, repository);
+
+			let mut dependencies = pinned.into_iter().map(|pin| pin.0).chain(unpinned).collect::<Vec<_>>();
+			dependencies.sort_by_key(ToString::to_string);
+
+			for dependency in dependencies.iter().map(ToString::to_string).filter(|dependency| forbidden.is_match(dependency)) {
+				let through = if repository.config().dependencies.keys().any(|direct| direct.to_string() == dependency) {
+					""
+				} else {
+					" through its dependencies"
+				};
+
+				violations.push(violation(Rule::ForbiddenDependency, &dependency, format!("Depends on {dependency}{through}, which is forbidden")));
+			}
+		}
+
+		violations
+	}
+
+	/// Whether a license expression is allowed, either
+	/// whole or through one of its `OR` alternatives
+	fn allows_license(&self, license: &str) -> bool {
+		let Some(allowed) = self.allowed_licenses.as_ref() else {
+			return true;
+		};
+
+		let is_allowed = |expression: &str| allowed.iter().any(|entry| entry.eq_ignore_ascii_case(expression.trim()));
+
+		is_allowed(license) || license.split(" OR ").any(is_allowed)
+	}
+
+	/// Apply the rules of the policy file at a path over
+	/// the policy, if there is one
+	fn apply(&mut self, path: &Path) -> Result<(), batlerror::GeneralResourceError> {
+		let contents = match std::fs::read_to_string(path) {
+			Ok(contents) => contents,
+			Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+			Err(err) => return Err(err.into())
+		};
+
+		let invalid = |message: String| batlerror::GeneralResourceError::InvalidConfig(format!("{}: {message}", path.display()));
+
+		let file: PolicyFile = toml::from_str(&contents)
+			.map_err(|err| invalid(err.to_string()))?;
+
+		if let Some(required) = file.scripts.and_then(|scripts| scripts.required) {
+			self.required_scripts = required;
+		}
+
+		if let Some(allowed) = file.licenses.and_then(|licenses| licenses.allowed) {
+			self.allowed_licenses = Some(allowed);
+		}
+
+		if let Some(dependencies) = file.dependencies {
+			if let Some(pinning) = dependencies.pinning {
+				self.pinning = pinning;
+			}
+
+			if let Some(forbidden) = dependencies.forbidden {
+				if let Some(pattern) = forbidden.iter().find(|pattern| Glob::new(pattern).is_err()) {
+					return Err(invalid(format!("Invalid pattern {pattern}")));
+				}
+
+				self.forbidden_dependencies = forbidden;
+			}
+		}
+
+		self.sources.push(path.to_path_buf());
+
+		Ok(())
+	}
+}
+
+impl Pinning {
+	/// Whether a version requirement is pinned tightly
+	/// enough. Requirements that don't parse are only
+	/// allowed when any requirement is. Exact pinning
+	/// follows the same rule as [`audit::pinned`].
+	fn allows(self, requirement: &str) -> bool {
+		let Ok(parsed) = VersionReq::parse(requirement) else {
+			return self == Self::Any;
+		};
+
+		match self {
+			Self::Any => true,
+			Self::Bounded => parsed.comparators.iter()
+				.any(|comparator| !matches!(comparator.op, Op::Greater | Op::GreaterEq)),
+			Self::Exact => audit::exact_version(requirement).is_some()
+		}
+	}
+}
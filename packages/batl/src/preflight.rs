@@ -0,0 +1,109 @@
+use crate::error::PreflightError;
+use std::path::Path;
+
+
+/// The longest path Windows accepts, unless long paths are
+/// enabled
+pub const MAX_PATH: usize = 260;
+
+/// Space kept free besides what an operation needs, so it
+/// never fills a disk to the last byte
+const HEADROOM: u64 = 16 * MEBIBYTE;
+
+/// Bytes in a mebibyte, which sizes are reported in
+const MEBIBYTE: u64 = 1024 * 1024;
+
+
+/// Check that the filesystem holding a directory has room
+/// to write a number of bytes. Filesystems whose free space
+/// can't be read pass.
+///
+/// # Errors
+///
+/// Returns `NotEnoughSpace` if the bytes won't fit
+#[inline]
+pub fn check_space(directory: &Path, needed: u64) -> Result<(), PreflightError> {
+	let Some(available) = available_space(directory) else {
+		return Ok(());
+	};
+
+	if available >= needed.saturating_add(HEADROOM) {
+		return Ok(());
+	}
+
+	Err(PreflightError::NotEnoughSpace(format!(
+		"{} needs {} MiB but only {} MiB is free, free up space on its disk or move the battalion root with BATL_ROOT",
+		directory.display(),
+		needed.div_ceil(MEBIBYTE),
+		available.div_euclid(MEBIBYTE)
+	)))
+}
+
+/// Check that files written under a directory won't have
+/// paths longer than Windows allows. Always passes on other
+/// platforms.
+///
+/// # Errors
+///
+/// Returns `PathTooLong` with the longest offending path
+#[inline]
+pub fn check_path_lengths<'file, I>(directory: &Path, files: I) -> Result<(), PreflightError>
+where
+	I: IntoIterator<Item = &'file str>
+{
+	if !cfg!(windows) {
+		return Ok(());
+	}
+
+	let longest = files.into_iter()
+		.map(|file| {
+			let path = directory.join(file);
+			let length = wide_length(&path);
+
+			(path, length)
+		})
+		.max_by_key(|&(_, length)| length);
+
+	match longest {
+		Some((path, length)) if length >= MAX_PATH => Err(PreflightError::PathTooLong(format!(
+			"{} is {length} characters, over the {MAX_PATH} Windows allows. Move the battalion root closer to the drive root with BATL_ROOT, or enable long paths in Windows",
+			path.display()
+		))),
+		Some(_) | None => Ok(())
+	}
+}
+
+/// The length of a path in UTF-16 units, which is what
+/// Windows counts against [`MAX_PATH`]
+fn wide_length(path: &Path) -> usize {
+	#[cfg(windows)]
+	return std::os::windows::ffi::OsStrExt::encode_wide(path.as_os_str()).count();
+
+	#[cfg(not(windows))]
+	return path.as_os_str().to_string_lossy().encode_utf16().count();
+}
+
+/// The bytes the current user can still write to the
+/// filesystem holding a path, or `None` if unknown
+#[inline]
+#[must_use]
+pub fn available_space(path: &Path) -> Option<u64> {
+	let existing = path.ancestors().find(|ancestor| ancestor.exists())?;
+
+	filesystem_space(existing)
+}
+
+/// Free space as reported by `statvfs`
+#[cfg(unix)]
+fn filesystem_space(path: &Path) -> Option<u64> {
+	rustix::fs::statvfs(path)
+		.ok()
+		.map(|stats| stats.f_bavail.saturating_mul(stats.f_frsize))
+}
+
+/// Reading free space on other platforms needs unsafe
+/// system calls, so those disks are never checked
+#[cfg(not(unix))]
+const fn filesystem_space(_path: &Path) -> Option<u64> {
+	None
+}
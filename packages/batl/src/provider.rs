@@ -0,0 +1,258 @@
+use crate::error as batlerror;
+use crate::resource::Name;
+use std::path::{Component, Path, PathBuf};
+
+
+/// Every provider a shorthand source can name
+pub const PROVIDERS: [&dyn Provider; 2] = [&GitHub, &GitLab];
+
+/// Reference fetched when a source doesn't name one
+const DEFAULT_REFERENCE: &str = "HEAD";
+
+
+/// A code host outside of the battalion registry, which
+/// shorthand sources such as `gh:owner/repo` are resolved
+/// against
+pub trait Provider: Sync {
+	/// The prefix of the shorthand, such as `gh`
+	fn prefix(&self) -> &'static str;
+
+	/// The first part of the battalion names derived from
+	/// the provider's sources
+	fn scope(&self) -> &'static str;
+
+	/// Whether a path names a repository on the provider
+	fn is_valid(&self, path: &str) -> bool;
+
+	/// The url to clone a repository from
+	fn git_url(&self, path: &str) -> String;
+
+	/// The url of a gzipped tarball of a repository at a
+	/// branch, tag, or commit
+	fn archive_url(&self, path: &str, reference: &str) -> String;
+}
+
+/// Repositories on github.com, as `gh:owner/repo`
+#[non_exhaustive]
+pub struct GitHub;
+
+/// Projects on gitlab.com, as `gl:group/project`, which can
+/// be nested in subgroups
+#[non_exhaustive]
+pub struct GitLab;
+
+/// Code hosted by a provider, written as
+/// `prefix:path[@reference]`
+#[non_exhaustive]
+pub struct Source {
+	pub provider: &'static dyn Provider,
+	pub path: String,
+
+	/// The branch, tag, or commit, if given
+	pub reference: Option<String>
+}
+
+impl Provider for GitHub {
+	#[inline]
+	fn prefix(&self) -> &'static str {
+		"gh"
+	}
+
+	#[inline]
+	fn scope(&self) -> &'static str {
+		"github"
+	}
+
+	#[inline]
+	fn is_valid(&self, path: &str) -> bool {
+		path.split('/').count() == 2 && valid_segments(path)
+	}
+
+	#[inline]
+	fn git_url(&self, path: &str) -> String {
+		format!("https://github.com/{path}.git")
+	}
+
+	#[inline]
+	fn archive_url(&self, path: &str, reference: &str) -> String {
+		format!("https://codeload.github.com/{path}/tar.gz/{reference}")
+	}
+}
+
+impl Provider for GitLab {
+	#[inline]
+	fn prefix(&self) -> &'static str {
+		"gl"
+	}
+
+	#[inline]
+	fn scope(&self) -> &'static str {
+		"gitlab"
+	}
+
+	#[inline]
+	fn is_valid(&self, path: &str) -> bool {
+		path.split('/').count() >= 2 && valid_segments(path)
+	}
+
+	#[inline]
+	fn git_url(&self, path: &str) -> String {
+		format!("https://gitlab.com/{path}.git")
+	}
+
+	#[inline]
+	fn archive_url(&self, path: &str, reference: &str) -> String {
+		let project = path.rsplit('/').next().unwrap_or(path);
+
+		format!("https://gitlab.com/{path}/-/archive/{reference}/{project}-{reference}.tar.gz")
+	}
+}
+
+impl Source {
+	/// Read a shorthand source. Returns `None` if the text
+	/// doesn't start with the prefix of a known provider.
+	///
+	/// # Errors
+	///
+	/// Returns `InvalidPackage` if the path doesn't name a
+	/// repository on the provider
+	#[inline]
+	pub fn parse(source: &str) -> Result<Option<Self>, batlerror::RegistryError> {
+		let Some((prefix, rest)) = source.split_once(':') else {
+			return Ok(None);
+		};
+
+		let Some(provider) = PROVIDERS.into_iter().find(|provider| provider.prefix() == prefix) else {
+			return Ok(None);
+		};
+
+		let (path, reference) = match rest.split_once('@') {
+			Some((path, reference)) => (path, Some(reference.to_owned())),
+			None => (rest, None)
+		};
+
+		if !provider.is_valid(path) || reference.as_ref().is_some_and(|given| !valid_reference(given)) {
+			return Err(batlerror::RegistryError::InvalidPackage(format!("{source} is not a repository on {}", provider.scope())));
+		}
+
+		Ok(Some(Self {
+			provider,
+			path: path.to_owned(),
+			reference
+		}))
+	}
+
+	#[inline]
+	#[must_use]
+	pub fn git_url(&self) -> String {
+		self.provider.git_url(&self.path)
+	}
+
+	#[inline]
+	#[must_use]
+	pub fn archive_url(&self) -> String {
+		self.provider.archive_url(&self.path, self.reference.as_deref().unwrap_or(DEFAULT_REFERENCE))
+	}
+
+	/// Derive a battalion name from the source, such as
+	/// `github/owner/repo` for `gh:Owner/Repo.rs`. Returns
+	/// `None` if a part doesn't start with a letter, since
+	/// names can't.
+	#[inline]
+	#[must_use]
+	pub fn name(&self) -> Option<Name> {
+		let mut parts = vec![self.provider.scope().to_owned()];
+
+		for segment in self.path.split('/') {
+			let part = segment.to_lowercase().replace('.', "-");
+
+			if !part.starts_with(|c: char| c.is_ascii_lowercase()) {
+				return None;
+			}
+
+			parts.push(part);
+		}
+
+		Some(Name::from(parts.join("/")))
+	}
+
+	/// Download the repository and unpack it into a
+	/// directory, leaving out the top directory the
+	/// provider wraps archives in
+	///
+	/// # Errors
+	///
+	/// Returns any network errors, and propogates any IO
+	/// errors while unpacking
+	#[inline]
+	pub fn download(&self, destination: &Path) -> Result<(), batlerror::RegistryError> {
+		let response = ureq::get(&self.archive_url()).call()?;
+		let mut tar = tar::Archive::new(flate2::read::GzDecoder::new(response.into_reader()));
+
+		std::fs::create_dir_all(destination)?;
+
+		for entry in tar.entries()? {
+			let mut file = entry?;
+			let kind = file.header().entry_type();
+
+			// Links could point outside of the destination
+			if !kind.is_file() && !kind.is_dir() {
+				continue;
+			}
+
+			let Some(relative) = strip_top(&file.path()?) else {
+				continue;
+			};
+
+			let target = destination.join(relative);
+
+			if kind.is_dir() {
+				std::fs::create_dir_all(target)?;
+				continue;
+			}
+
+			if let Some(parent) = target.parent() {
+				std::fs::create_dir_all(parent)?;
+			}
+
+			// Streams the file and keeps its mode, so scripts
+			// stay executable
+			file.unpack(&target)?;
+		}
+
+		Ok(())
+	}
+}
+
+/// Whether every segment of a path is a plain name that
+/// providers allow
+fn valid_segments(path: &str) -> bool {
+	path.split('/').all(|segment| {
+		!segment.is_empty()
+			&& !segment.starts_with('.')
+			&& segment.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'))
+	})
+}
+
+/// Whether a branch, tag, or commit can be put in a url
+fn valid_reference(reference: &str) -> bool {
+	!reference.is_empty()
+		&& !reference.split('/').any(|part| part.is_empty() || part == "..")
+		&& reference.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_' | '/'))
+}
+
+/// Drop the first component of an archive path, returning
+/// `None` for the top directory itself and for paths that
+/// would leave the destination
+fn strip_top(path: &Path) -> Option<PathBuf> {
+	let mut components = path.components();
+	components.next();
+
+	let rest = components.as_path();
+
+	if rest.as_os_str().is_empty() || !rest.components().all(|component| matches!(component, Component::Normal(_))) {
+		return None;
+	}
+
+	Some(rest.to_path_buf())
+}
@@ -0,0 +1,1195 @@
+use core::cell::{Cell, RefCell};
+use core::time::Duration;
+use crate::error as batlerror;
+use crate::integrity::Checksum;
+use crate::resource::Name;
+use crate::resource::archive::{Manifest, Span, Stream};
+use crate::resource::batlrc::AuthProvider0_2_2;
+use self::advisory::Advisory;
+use self::auth::{CreateToken, DeviceCode, Scope, ScopedToken, TokenInfo, TokenResponse, Tokens};
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::PathBuf;
+use std::time::Instant;
+
+/// Security advisories published for packages
+pub mod advisory;
+
+/// OAuth device flow and token storage
+pub mod auth;
+
+/// On-disk cache of registry responses
+mod cache;
+
+/// Registries stored in a directory, for mirrors
+mod local;
+
+/// Serving a registry stored in a directory over http
+#[cfg(feature = "serve")]
+pub mod server;
+
+
+/// The public battalion registry
+pub const DEFAULT_URL: &str = "https://api.batl.circetools.net";
+
+/// Environment variable holding a registry token, used
+/// over the batlrc so CI can use scoped tokens
+pub const TOKEN_ENV: &str = "BATL_TOKEN";
+
+/// Feature of registries that serve byte ranges of
+/// archives, so single files can be fetched
+pub const RANGES_FEATURE: &str = "ranges";
+
+/// Minimum time between two requests to the registry
+const MIN_REQUEST_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How many times a rate limited request is retried
+const MAX_RETRIES: u32 = 3;
+
+/// Longest the registry may ask us to wait before a retry
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(30);
+
+/// The registry API version this build speaks. It is sent
+/// with every request, and registries report theirs back
+/// in the same header.
+pub const API_VERSION: u64 = 1;
+
+/// The oldest registry API version this build can use
+pub const MIN_API_VERSION: u64 = 1;
+
+/// Header carrying the API version of either side
+const API_HEADER: &str = "x-batl-api";
+
+/// How long a ping waits for the registry to answer
+const PING_TIMEOUT: Duration = Duration::from_secs(10);
+
+
+/// A client for a battalion package registry
+pub struct Registry {
+	/// Base url of the registry, without a trailing slash
+	url: String,
+
+	/// API key sent with authenticated requests
+	credentials: Option<String>,
+
+	/// OAuth tokens, used instead of the API key when
+	/// present. Refreshed as they expire.
+	tokens: RefCell<Option<Tokens>>,
+
+	/// Where refreshed tokens are stored
+	tokens_path: Option<PathBuf>,
+
+	/// OAuth client id
+	client_id: String,
+
+	/// When the last request was sent, for rate limiting
+	last_request: Cell<Option<Instant>>,
+
+	/// The API version the registry reported in its last
+	/// response
+	api_version: Cell<Option<u64>>,
+
+	/// Where responses are cached and the last request is
+	/// recorded, or `None` to do neither
+	cache_root: Option<PathBuf>
+}
+
+/// Registry metadata for a package
+#[derive(Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct PackageMetadata {
+	pub name: Name,
+	pub description: Option<String>,
+	pub latest: Option<Version>,
+	pub versions: Vec<Version>,
+
+	/// Health recorded for versions that were checked
+	/// before they were published
+	#[serde(default)]
+	pub health: HashMap<Version, Health>,
+
+	/// The git url the package is developed in, if it was
+	/// published with one
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub source: Option<String>
+}
+
+/// The results of a repository's checks, recorded when
+/// it was published
+#[derive(Serialize, Deserialize, Clone)]
+#[non_exhaustive]
+pub struct Health {
+	/// Each script that was run and whether it passed
+	pub checks: Vec<Check>,
+
+	/// The battalion version that ran the checks
+	pub batl_version: String,
+
+	/// The OS and architecture the checks ran on
+	pub platform: String
+}
+
+/// The result of a single script run as a check
+#[derive(Serialize, Deserialize, Clone)]
+#[non_exhaustive]
+pub struct Check {
+	pub script: String,
+	pub passed: bool
+}
+
+impl Health {
+	/// Record check results for this build of battalion
+	/// on this platform
+	#[inline]
+	#[must_use]
+	pub fn new(checks: Vec<Check>) -> Self {
+		Self {
+			checks,
+			batl_version: env!("CARGO_PKG_VERSION").to_owned(),
+			platform: format!("{}-{}", std::env::consts::OS, std::env::consts::ARCH)
+		}
+	}
+
+	/// Whether every check passed
+	#[inline]
+	#[must_use]
+	pub fn passed(&self) -> bool {
+		self.checks.iter().all(|check| check.passed)
+	}
+}
+
+impl Check {
+	/// Record the result of a script
+	#[inline]
+	#[must_use]
+	pub const fn new(script: String, passed: bool) -> Self {
+		Self {
+			script,
+			passed
+		}
+	}
+}
+
+/// The result of probing a registry
+#[derive(Serialize)]
+#[non_exhaustive]
+pub struct Ping {
+	pub url: String,
+
+	/// Whether the registry answered at all
+	pub reachable: bool,
+
+	/// Round trip time of the health request, in
+	/// milliseconds
+	pub latency_ms: Option<u64>,
+
+	/// The API version the registry reports, if it does
+	pub api_version: Option<u64>,
+
+	/// Whether the registry speaks the API version of this
+	/// build, or `None` if it doesn't say
+	pub compatible: Option<bool>,
+
+	/// Optional features the registry offers
+	pub features: Vec<String>,
+
+	pub auth: AuthStatus,
+
+	/// The scopes of the credentials in use, when the
+	/// registry reports them
+	pub scopes: Option<Vec<Scope>>,
+
+	/// What went wrong, if anything
+	pub error: Option<String>
+}
+
+/// Whether the credentials in use are accepted by a
+/// registry
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum AuthStatus {
+	/// Accepted by the registry
+	Valid,
+
+	/// Rejected by the registry
+	Invalid,
+
+	/// No credentials are configured
+	Missing,
+
+	/// The registry couldn't be asked
+	Unknown
+}
+
+/// What a registry reports about itself from `/health`
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[non_exhaustive]
+pub struct Capabilities {
+	/// The newest API version the registry speaks
+	pub api_version: Option<u64>,
+
+	/// The oldest API version the registry still accepts
+	pub min_api_version: Option<u64>,
+
+	/// Optional features the registry offers, such as
+	/// `delta` publishing
+	#[serde(default)]
+	pub features: Vec<String>
+}
+
+/// How the API of a registry relates to this build
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Compatibility {
+	/// The same API version, or the registry doesn't say
+	Compatible,
+
+	/// The registry speaks a newer API version that still
+	/// accepts this build, with features it can't use
+	Newer(u64),
+
+	/// The registry requires at least this API version,
+	/// newer than this build speaks
+	ClientTooOld(u64),
+
+	/// The registry speaks an API version older than this
+	/// build can use
+	RegistryTooOld(u64)
+}
+
+impl Capabilities {
+	/// Whether the registry offers an optional feature
+	#[inline]
+	#[must_use]
+	pub fn supports(&self, feature: &str) -> bool {
+		self.features.iter().any(|offered| offered == feature)
+	}
+
+	/// How the API of the registry relates to this build
+	#[inline]
+	#[must_use]
+	pub const fn compatibility(&self) -> Compatibility {
+		compatibility(self.api_version, self.min_api_version)
+	}
+}
+
+impl Ping {
+	/// Whether the registry is reachable, compatible, and
+	/// doesn't reject the credentials in use
+	#[inline]
+	#[must_use]
+	pub fn healthy(&self) -> bool {
+		self.reachable
+			&& self.error.is_none()
+			&& self.compatible != Some(false)
+			&& self.auth != AuthStatus::Invalid
+	}
+}
+
+/// A package matching a registry search
+#[derive(Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct SearchResult {
+	pub name: Name,
+	pub description: Option<String>,
+	pub latest: Option<Version>
+}
+
+/// The error payload returned by the registry
+#[derive(Serialize, Deserialize)]
+struct ErrorPayload {
+	/// Machine readable error code, such as `version_exists`
+	code: String,
+
+	/// Human readable message
+	message: String,
+
+	/// Extra details, such as the offending namespace
+	details: Option<String>
+}
+
+impl Registry {
+	/// Create a client for the registry at the given url
+	#[inline]
+	#[must_use]
+	pub fn new(url: &str, credentials: Option<String>) -> Self {
+		Self {
+			url: url.trim_end_matches('/').to_owned(),
+			credentials,
+			tokens: RefCell::new(None),
+			tokens_path: None,
+			client_id: auth::DEFAULT_CLIENT_ID.to_owned(),
+			last_request: Cell::new(None),
+			api_version: Cell::new(None),
+			cache_root: None
+		}
+	}
+
+	/// Cache responses in the battalion root of a context,
+	/// and space requests out across runs of batl there
+	#[inline]
+	#[must_use]
+	pub fn cached_in(mut self, system: &crate::system::Context) -> Self {
+		self.cache_root = system.http_cache_root();
+		self
+	}
+
+	/// Create a client from the batlrc of a battalion root,
+	/// falling back to the public registry. A token in
+	/// `BATL_TOKEN` is used over the batlrc, even without
+	/// one. Stored OAuth tokens are used when the batlrc
+	/// selects device auth.
+	#[inline]
+	#[must_use]
+	pub fn from_system(system: &crate::system::Context) -> Self {
+		let token = std::env::var(TOKEN_ENV).ok();
+
+		let Some(batlrc) = system.batlrc() else {
+			return Self::new(DEFAULT_URL, token).cached_in(system);
+		};
+
+		let mut registry = Self::new(
+			batlrc.api.url.as_deref().unwrap_or(DEFAULT_URL),
+			token.or_else(|| batlrc.api.key())
+		).cached_in(system);
+
+		if let Some(client_id) = batlrc.api.client_id {
+			registry.client_id = client_id;
+		}
+
+		if batlrc.api.auth == Some(AuthProvider0_2_2::Device) && std::env::var_os(TOKEN_ENV).is_none() {
+			registry.tokens = RefCell::new(Tokens::load_in(system));
+			registry.tokens_path = system.tokens_path();
+		}
+
+		registry
+	}
+
+	/// Create a client for a registry named in `[registries]`
+	/// of the batlrc. Only its API key is used, since stored
+	/// OAuth tokens belong to the default registry.
+	///
+	/// # Errors
+	///
+	/// Returns `UnknownRegistry` if the batlrc doesn't name
+	/// the registry
+	#[inline]
+	pub fn named(system: &crate::system::Context, name: &str) -> Result<Self, batlerror::RegistryError> {
+		let api = system.batlrc()
+			.and_then(|batlrc| batlrc.registries)
+			.and_then(|mut registries| registries.remove(name))
+			.ok_or_else(|| batlerror::RegistryError::UnknownRegistry(name.to_owned()))?;
+
+		let mut registry = Self::new(api.url.as_deref().unwrap_or(DEFAULT_URL), api.key()).cached_in(system);
+
+		if let Some(client_id) = api.client_id {
+			registry.client_id = client_id;
+		}
+
+		Ok(registry)
+	}
+
+	/// Start an OAuth device authorization
+	/// 
+	/// # Errors
+	/// 
+	/// Returns the registry error if device authorization
+	/// is not supported, or a network error if the registry
+	/// can't be reached
+	#[inline]
+	pub fn request_device_code(&self) -> Result<DeviceCode, batlerror::RegistryError> {
+		let resp = post(&format!("{}/oauth/device", self.url))
+			.send_form(&[("client_id", &self.client_id)])
+			.map_err(batlerror::RegistryError::from)?;
+
+		serde_json::from_reader(resp.into_reader())
+			.map_err(|err| batlerror::RegistryError::InvalidResponse(err.to_string()))
+	}
+
+	/// Poll until the user approves a device authorization,
+	/// waiting as long as the registry asks between polls
+	/// 
+	/// # Errors
+	/// 
+	/// Returns `Unauthorized` if the authorization is denied
+	/// or expires, or a network error if the registry can't
+	/// be reached
+	#[inline]
+	pub fn await_device_token(&self, code: &DeviceCode) -> Result<Tokens, batlerror::RegistryError> {
+		let deadline = Instant::now().checked_add(Duration::from_secs(code.expires_in));
+		let mut interval = Duration::from_secs(code.interval);
+
+		loop {
+			std::thread::sleep(interval);
+
+			if deadline.is_some_and(|limit| Instant::now() >= limit) {
+				return Err(batlerror::RegistryError::Unauthorized("Device code expired".to_owned()));
+			}
+
+			let response = self.token_request(&[
+				("grant_type", "urn:ietf:params:oauth:grant-type:device_code"),
+				("device_code", &code.device_code),
+				("client_id", &self.client_id)
+			])?;
+
+			match response.error.as_deref() {
+				Some("authorization_pending") => {},
+				Some("slow_down") => interval = interval.saturating_add(Duration::from_secs(5)),
+				_ => return token_result(response, None)
+			}
+		}
+	}
+
+	/// Exchange the refresh token for new tokens and store
+	/// them
+	fn refresh(&self, refresh_token: String) -> Result<Tokens, batlerror::RegistryError> {
+		let response = self.token_request(&[
+			("grant_type", "refresh_token"),
+			("refresh_token", &refresh_token),
+			("client_id", &self.client_id)
+		])?;
+
+		let tokens = token_result(response, Some(refresh_token))?;
+
+		if let Some(path) = self.tokens_path.as_deref() {
+			tokens.save_at(path)?;
+		}
+
+		Ok(tokens)
+	}
+
+	/// Send a request to the token endpoint. OAuth errors
+	/// are returned in the response rather than as errors.
+	fn token_request(&self, form: &[(&str, &str)]) -> Result<TokenResponse, batlerror::RegistryError> {
+		let resp = match post(&format!("{}/oauth/token", self.url)).send_form(form) {
+			Ok(ok) => ok,
+			Err(ureq::Error::Status(400 | 401, bad)) => bad,
+			Err(err) => return Err(err.into())
+		};
+
+		serde_json::from_reader(resp.into_reader())
+			.map_err(|err| batlerror::RegistryError::InvalidResponse(err.to_string()))
+	}
+
+	/// Create a token limited to the given scopes, for use
+	/// in automation
+	/// 
+	/// # Errors
+	/// 
+	/// Returns the registry error if the token can't be
+	/// created, or a network error if the registry can't be
+	/// reached
+	#[inline]
+	pub fn create_token(&self, scopes: &[Scope], expires: Option<Duration>) -> Result<ScopedToken, batlerror::RegistryError> {
+		let body = serde_json::to_string(&CreateToken {
+			scopes,
+			expires_in: expires.map(|duration| duration.as_secs())
+		}).map_err(|err| batlerror::RegistryError::InvalidPackage(err.to_string()))?;
+
+		let resp = self.authorize(post(&format!("{}/tokens", self.url)))?
+			.set("content-type", "application/json")
+			.send_string(&body)
+			.map_err(batlerror::RegistryError::from)?;
+
+		serde_json::from_reader(resp.into_reader())
+			.map_err(|err| batlerror::RegistryError::InvalidResponse(err.to_string()))
+	}
+
+	/// Get the scopes of the token in use, or `None` if the
+	/// registry doesn't report them
+	/// 
+	/// # Errors
+	/// 
+	/// Returns any registry or network errors
+	#[inline]
+	pub fn token_info(&self) -> Result<Option<TokenInfo>, batlerror::RegistryError> {
+		if self.local_root().is_some() {
+			return Ok(None);
+		}
+
+		match self.call(&self.authorize(get(&format!("{}/tokens/self", self.url)))?) {
+			Ok(resp) => serde_json::from_reader(resp.into_reader())
+				.map(Some)
+				.map_err(|err| batlerror::RegistryError::InvalidResponse(err.to_string())),
+			Err(batlerror::RegistryError::NotFound(_)) => Ok(None),
+			Err(err) => Err(err)
+		}
+	}
+
+	/// Check that the token in use grants a scope, before
+	/// doing work that would be rejected. Registries that
+	/// don't report scopes are assumed to grant it.
+	/// 
+	/// # Errors
+	/// 
+	/// Returns `InsufficientScope` if the token lacks the
+	/// scope, or any registry or network errors
+	#[inline]
+	pub fn require_scope(&self, scope: Scope) -> Result<(), batlerror::RegistryError> {
+		match self.token_info()? {
+			Some(info) if !info.allows(scope) => Err(batlerror::RegistryError::InsufficientScope(scope.to_string())),
+			_ => Ok(())
+		}
+	}
+
+	/// Discover the API versions and features of the
+	/// registry. Registries without a health endpoint, and
+	/// file registries, are assumed to speak the API of this
+	/// build. File registries can always serve ranges.
+	/// 
+	/// # Errors
+	/// 
+	/// Returns any registry or network errors
+	#[inline]
+	pub fn capabilities(&self) -> Result<Capabilities, batlerror::RegistryError> {
+		if self.local_root().is_some() {
+			return Ok(Capabilities {
+				features: vec![RANGES_FEATURE.to_owned()],
+				..Capabilities::default()
+			});
+		}
+
+		match self.get_json(&format!("{}/health", self.url)) {
+			Err(batlerror::RegistryError::NotFound(_)) => Ok(Capabilities::default()),
+			result => result
+		}
+	}
+
+	/// The API version of the registry, when it reported
+	/// one newer than this build speaks in a response so far
+	#[inline]
+	#[must_use]
+	pub fn newer_api(&self) -> Option<u64> {
+		self.api_version.get().filter(|&version| version > API_VERSION)
+	}
+
+	/// Note the API version the registry reported in a
+	/// response, rejecting registries too old to use
+	fn record_api(&self, resp: &ureq::Response) -> Result<(), batlerror::RegistryError> {
+		let Some(version) = resp.header(API_HEADER).and_then(|value| value.trim().parse().ok()) else {
+			return Ok(());
+		};
+
+		self.api_version.set(Some(version));
+
+		if matches!(compatibility(Some(version), None), Compatibility::RegistryTooOld(_)) {
+			return Err(batlerror::RegistryError::IncompatibleApi(format!(
+				"the registry speaks API version {version}, battalion requires at least {MIN_API_VERSION}"
+			)));
+		}
+
+		Ok(())
+	}
+
+	/// Check the reachability, latency, API version, and
+	/// credentials of the registry in one go. Problems are
+	/// recorded in the result rather than returned.
+	#[inline]
+	#[must_use]
+	pub fn ping(&self) -> Ping {
+		let mut ping = Ping {
+			url: self.url.clone(),
+			reachable: false,
+			latency_ms: None,
+			api_version: None,
+			compatible: None,
+			features: Vec::new(),
+			auth: AuthStatus::Unknown,
+			scopes: None,
+			error: None
+		};
+
+		if let Some(root) = self.local_root() {
+			ping.reachable = root.is_dir();
+
+			if !ping.reachable {
+				ping.error = Some(format!("{} is not a directory", root.display()));
+			}
+
+			return ping;
+		}
+
+		let start = Instant::now();
+		let result = get(&format!("{}/health", self.url))
+			.timeout(PING_TIMEOUT)
+			.call();
+
+		match result {
+			Ok(resp) => {
+				let capabilities = serde_json::from_reader::<_, Capabilities>(resp.into_reader())
+					.unwrap_or_default();
+
+				ping.api_version = capabilities.api_version;
+				ping.compatible = capabilities.api_version.map(|_| !matches!(
+					capabilities.compatibility(),
+					Compatibility::ClientTooOld(_) | Compatibility::RegistryTooOld(_)
+				));
+				ping.features = capabilities.features;
+			},
+			Err(ureq::Error::Status(426, resp)) => {
+				ping.api_version = resp.header(API_HEADER).and_then(|value| value.trim().parse().ok());
+				ping.compatible = Some(false);
+				ping.error = Some(batlerror::RegistryError::from(ureq::Error::Status(426, resp)).to_string());
+			},
+			Err(ureq::Error::Status(status, _)) => {
+				// Registries without a health endpoint still answer
+				if status >= 500 {
+					ping.error = Some(format!("Health check failed with status code {status}"));
+				}
+			},
+			Err(ureq::Error::Transport(transport)) => {
+				ping.error = Some(transport.to_string());
+
+				return ping;
+			}
+		}
+
+		ping.reachable = true;
+		ping.latency_ms = u64::try_from(start.elapsed().as_millis()).ok();
+
+		if self.credentials.is_none() && self.tokens.borrow().is_none() {
+			ping.auth = AuthStatus::Missing;
+
+			return ping;
+		}
+
+		match self.token_info() {
+			Ok(Some(info)) => {
+				ping.auth = AuthStatus::Valid;
+				ping.scopes = Some(info.scopes);
+			},
+			Ok(None) => {},
+			Err(batlerror::RegistryError::Unauthorized(reason)) => {
+				ping.auth = AuthStatus::Invalid;
+				ping.error = Some(reason);
+			},
+			Err(err) => {
+				ping.error.get_or_insert_with(|| err.to_string());
+			}
+		}
+
+		ping
+	}
+
+	/// Add the authentication header to a request
+	fn authorize(&self, request: ureq::Request) -> Result<ureq::Request, batlerror::RegistryError> {
+		let (header_name, header_value) = self.authorization()?;
+
+		Ok(request.set(header_name, &header_value))
+	}
+
+	/// Get the header authenticating a request, refreshing
+	/// OAuth tokens that have expired
+	fn authorization(&self) -> Result<(&'static str, String), batlerror::RegistryError> {
+		let stored = self.tokens.borrow().clone();
+
+		if let Some(tokens) = stored {
+			let current = match tokens.refresh_token.clone() {
+				Some(refresh_token) if tokens.is_expired() => {
+					let refreshed = self.refresh(refresh_token)?;
+					self.tokens.replace(Some(refreshed.clone()));
+
+					refreshed
+				},
+				_ => tokens
+			};
+
+			return Ok(("authorization", format!("Bearer {}", current.access_token)));
+		}
+
+		self.credentials.clone()
+			.map(|key| ("x-api-key", key))
+			.ok_or_else(|| batlerror::RegistryError::Unauthorized("No API key configured".to_owned()))
+	}
+
+	/// Get the directory of a registry stored on disk, as
+	/// given by a `file://` url
+	fn local_root(&self) -> Option<PathBuf> {
+		local::root(&self.url)
+	}
+
+	/// Get the url of a package
+	fn package_url(&self, name: &Name) -> String {
+		format!("{}/pkg/{name}", self.url)
+	}
+
+	/// Send a request, spacing requests out, also from other
+	/// runs of batl, and backing off
+	/// when the registry reports that it is rate limiting
+	fn call(&self, request: &ureq::Request) -> Result<ureq::Response, batlerror::RegistryError> {
+		self.send(request, true)
+	}
+
+	/// Send a request, backing off when the registry reports
+	/// that it is rate limiting. Requests that continue one
+	/// already spaced out, like the ranges of one download,
+	/// aren't spaced out again.
+	fn send(&self, request: &ureq::Request, spaced: bool) -> Result<ureq::Response, batlerror::RegistryError> {
+		let mut attempt = 0;
+
+		loop {
+			if spaced {
+				self.wait_turn();
+			}
+
+			match request.clone().call() {
+				Err(ureq::Error::Status(429 | 503, resp)) if attempt < MAX_RETRIES => {
+					let backoff = resp.header("retry-after")
+						.and_then(|secs| secs.parse().ok())
+						.map_or_else(
+							|| Duration::from_secs(2u64.saturating_pow(attempt)),
+							Duration::from_secs
+						)
+						.min(MAX_RETRY_AFTER);
+
+					std::thread::sleep(backoff);
+					attempt = attempt.saturating_add(1);
+				},
+				result => {
+					let resp = result.map_err(batlerror::RegistryError::from)?;
+					self.record_api(&resp)?;
+
+					return Ok(resp);
+				}
+			}
+		}
+	}
+
+	/// Wait until requests are spaced out, also from other
+	/// runs of batl, then count a new one
+	fn wait_turn(&self) {
+		// Other runs of batl may have sent requests just now
+		let since_last = [self.last_request.get().map(|last| last.elapsed()), self.cache_root.as_deref().and_then(cache::since_last_request)]
+			.into_iter()
+			.flatten()
+			.min();
+
+		if let Some(wait) = since_last.and_then(|elapsed| MIN_REQUEST_INTERVAL.checked_sub(elapsed)) {
+			std::thread::sleep(wait);
+		}
+
+		self.last_request.set(Some(Instant::now()));
+
+		if let Some(root) = self.cache_root.as_deref() {
+			cache::record_request(root);
+		}
+	}
+
+	/// Get a JSON document from the registry, revalidating
+	/// any cached copy with its `ETag`
+	fn get_json<T: DeserializeOwned>(&self, url: &str) -> Result<T, batlerror::RegistryError> {
+		let cached = self.cache_root.as_deref().and_then(|root| cache::read(root, url));
+		let mut request = get(url);
+
+		if let Some(entry) = cached.as_ref() {
+			request = request.set("if-none-match", &entry.etag);
+		}
+
+		let resp = self.call(&request)?;
+
+		let body = match cached {
+			Some(entry) if resp.status() == 304 => entry.body,
+			_ => {
+				let etag = resp.header("etag").map(ToOwned::to_owned);
+				let body = resp.into_string()
+					.map_err(|err| batlerror::RegistryError::Network(err.to_string()))?;
+
+				if let Some((tag, root)) = etag.zip(self.cache_root.as_deref()) {
+					cache::write(root, url, &cache::Entry {
+						etag: tag,
+						body: body.clone()
+					});
+				}
+
+				body
+			}
+		};
+
+		serde_json::from_str(&body)
+			.map_err(|err| batlerror::RegistryError::InvalidResponse(err.to_string()))
+	}
+
+	/// Get the registry metadata of a package
+	/// 
+	/// # Errors
+	/// 
+	/// Returns the registry error if the package can't be
+	/// found, or a network error if the registry can't be
+	/// reached
+	#[inline]
+	pub fn metadata(&self, name: &Name) -> Result<PackageMetadata, batlerror::RegistryError> {
+		if let Some(root) = self.local_root() {
+			return local::metadata(&root, name);
+		}
+
+		self.get_json(&format!("{}/meta", self.package_url(name)))
+	}
+
+	/// Search the registry for packages
+	/// 
+	/// # Errors
+	/// 
+	/// Returns any registry or network errors
+	#[inline]
+	pub fn search(&self, query: &str) -> Result<Vec<SearchResult>, batlerror::RegistryError> {
+		if let Some(root) = self.local_root() {
+			return local::search(&root, query);
+		}
+
+		let url = get(&format!("{}/search", self.url))
+			.query("q", query)
+			.url()
+			.to_owned();
+
+		self.get_json(&url)
+	}
+
+	/// Publish an archive under the given name as it is
+	/// read from the stream, along with its checksum, the
+	/// health of the repository if it was checked and the
+	/// git url it is developed in
+	/// 
+	/// # Errors
+	/// 
+	/// Returns the registry error if the publish is rejected,
+	/// or a network error if the registry can't be reached
+	#[inline]
+	pub fn publish<R: Read>(&self, name: &Name, archive: Stream<'_, R>, checksum: &Checksum, health: Option<&Health>, source: Option<&str>) -> Result<(), batlerror::RegistryError> {
+		self.upload(post(&self.package_url(name)), archive, Some(checksum), health, source)
+	}
+
+	/// Publish a delta archive built against a previously
+	/// published version. The registry rebuilds the full
+	/// archive from the base version and the manifest, and
+	/// stores the checksum of the full archive with it.
+	/// 
+	/// # Errors
+	/// 
+	/// Returns the registry error if the publish is rejected,
+	/// `NotFound` if the registry doesn't support deltas, or
+	/// a network error if the registry can't be reached
+	#[inline]
+	pub fn publish_delta<R: Read>(&self, name: &Name, base: &Version, delta: Stream<'_, R>, checksum: &Checksum, health: Option<&Health>, source: Option<&str>) -> Result<(), batlerror::RegistryError> {
+		let request = post(&format!("{}/delta", self.package_url(name)))
+			.set("x-batl-base", &base.to_string());
+
+		self.upload(request, delta, Some(checksum), health, source)
+	}
+
+	/// Get the checksum stored next to the archive of a
+	/// published version, or `None` if the registry doesn't
+	/// have one
+	/// 
+	/// # Errors
+	/// 
+	/// Returns any registry or network errors
+	#[inline]
+	pub fn checksum(&self, name: &Name, version: &Version) -> Result<Option<Checksum>, batlerror::RegistryError> {
+		if let Some(root) = self.local_root() {
+			return local::checksum(&root, name, version);
+		}
+
+		match self.get_json(&format!("{}/{version}/checksum", self.package_url(name))) {
+			Ok(checksum) => Ok(Some(checksum)),
+			Err(batlerror::RegistryError::NotFound(_)) => Ok(None),
+			Err(err) => Err(err)
+		}
+	}
+
+	/// Get the file manifest of a published version, or
+	/// `None` if the registry doesn't have one
+	/// 
+	/// # Errors
+	/// 
+	/// Returns any registry or network errors, and
+	/// `InvalidResponse` if a path of the manifest leaves the
+	/// repository
+	#[inline]
+	pub fn manifest(&self, name: &Name, version: &Version) -> Result<Option<Manifest>, batlerror::RegistryError> {
+		let manifest = if let Some(root) = self.local_root() {
+			local::manifest(&root, name, version)?
+		} else {
+			match self.get_json::<Manifest>(&format!("{}/{version}/manifest", self.package_url(name))) {
+				Ok(manifest) => manifest,
+				Err(batlerror::RegistryError::NotFound(_)) => return Ok(None),
+				Err(err) => return Err(err)
+			}
+		};
+
+		// Files are written by these paths, and the manifest
+		// is as trusted as the registry serving it
+		manifest.check_paths()
+			.map_err(|err| batlerror::RegistryError::InvalidResponse(err.to_string()))?;
+
+		Ok(Some(manifest))
+	}
+
+	/// Fetch a single file of a published version with a
+	/// ranged request, using where the manifest places it
+	/// in the archive. The contents are checked against the
+	/// manifest.
+	/// 
+	/// # Errors
+	/// 
+	/// Returns `Unsupported` if the manifest doesn't place
+	/// the file or the registry doesn't serve ranges, and
+	/// `InvalidPackage` if the contents don't match
+	#[inline]
+	pub fn fetch_file(&self, name: &Name, version: &Version, manifest: &Manifest, file: &str) -> Result<Vec<u8>, batlerror::RegistryError> {
+		self.fetch_span(name, version, manifest, file, true)
+	}
+
+	/// Fetch several files of a published version like
+	/// [`Registry::fetch_file`], in order. They make up one
+	/// download, so only the first ranged request counts
+	/// against the rate limit.
+	#[inline]
+	pub fn fetch_files<'files>(&'files self, name: &'files Name, version: &'files Version, manifest: &'files Manifest, files: &'files [String]) -> impl Iterator<Item = Result<Vec<u8>, batlerror::RegistryError>> + 'files {
+		files.iter()
+			.enumerate()
+			.map(move |(index, file)| self.fetch_span(name, version, manifest, file, index == 0))
+	}
+
+	/// Fetch a single file with a ranged request, spacing it
+	/// out from other requests when asked to
+	fn fetch_span(&self, name: &Name, version: &Version, manifest: &Manifest, file: &str, spaced: bool) -> Result<Vec<u8>, batlerror::RegistryError> {
+		let span = *manifest.spans.get(file)
+			.ok_or_else(|| batlerror::RegistryError::Unsupported(format!("locating {file} in the archive")))?;
+
+		let contents = if let Some(root) = self.local_root() {
+			local::read_span(&root, name, version, span)?
+		} else if span.size == 0 {
+			Vec::new()
+		} else {
+			self.read_span(&format!("{}/{version}", self.package_url(name)), span, spaced)?
+		};
+
+		if !manifest.verify(file, &contents) {
+			return Err(batlerror::RegistryError::InvalidPackage(format!("{file} does not match the manifest of {name}@{version}")));
+		}
+
+		Ok(contents)
+	}
+
+	/// Read a range of bytes of a download
+	fn read_span(&self, url: &str, span: Span, spaced: bool) -> Result<Vec<u8>, batlerror::RegistryError> {
+		let last = span.offset.saturating_add(span.size).saturating_sub(1);
+		let resp = self.send(&get(url).set("range", &format!("bytes={}-{last}", span.offset)), spaced)?;
+
+		if resp.status() != 206 {
+			return Err(batlerror::RegistryError::Unsupported("ranged fetches".to_owned()));
+		}
+
+		let mut contents = Vec::new();
+		resp.into_reader()
+			.take(span.size)
+			.read_to_end(&mut contents)?;
+
+		Ok(contents)
+	}
+
+	/// Get the advisories published for a package. Packages
+	/// without any, and registries that don't publish
+	/// advisories, have none.
+	/// 
+	/// # Errors
+	/// 
+	/// Returns any registry or network errors
+	#[inline]
+	pub fn advisories(&self, name: &Name) -> Result<Vec<Advisory>, batlerror::RegistryError> {
+		if let Some(root) = self.local_root() {
+			return local::advisories(&root, name);
+		}
+
+		match self.get_json(&format!("{}/advisories", self.package_url(name))) {
+			Err(batlerror::RegistryError::NotFound(_)) => Ok(Vec::new()),
+			result => result
+		}
+	}
+
+	/// Copy a published version from another registry,
+	/// keeping its checksum, health and source. Registries
+	/// stored on disk record the version and metadata
+	/// directly.
+	/// 
+	/// # Errors
+	/// 
+	/// Returns the registry error if the version is
+	/// rejected, or any network or IO errors
+	#[inline]
+	pub fn import<R: Read>(&self, source: &PackageMetadata, version: &Version, mut body: Stream<'_, R>, checksum: Option<&Checksum>) -> Result<(), batlerror::RegistryError> {
+		if let Some(root) = self.local_root() {
+			return local::import(&root, source, version, &mut body, checksum);
+		}
+
+		self.upload(post(&self.package_url(&source.name)), body, checksum, source.health.get(version), source.source.as_deref())
+	}
+
+	/// Upload an archive with credentials, its checksum,
+	/// health, and the git url it is developed in
+	fn upload<R: Read>(&self, request: ureq::Request, body: Stream<'_, R>, checksum: Option<&Checksum>, health: Option<&Health>, source: Option<&str>) -> Result<(), batlerror::RegistryError> {
+		if self.local_root().is_some() {
+			return Err(batlerror::RegistryError::Unsupported("publishing to a file registry, mirror into it instead".to_owned()));
+		}
+
+		let mut authorized = self.authorize(request)?;
+
+		// A known size is sent up front instead of chunking
+		// the body
+		if let Some(size) = body.size_hint() {
+			authorized = authorized.set("content-length", &size.to_string());
+		}
+
+		if let Some(sum) = checksum {
+			let header = serde_json::to_string(sum)
+				.map_err(|err| batlerror::RegistryError::InvalidPackage(err.to_string()))?;
+
+			authorized = authorized.set("x-batl-checksum", &header);
+		}
+
+		if let Some(checked) = health {
+			let header = serde_json::to_string(checked)
+				.map_err(|err| batlerror::RegistryError::InvalidPackage(err.to_string()))?;
+
+			authorized = authorized.set("x-batl-health", &header);
+		}
+
+		if let Some(url) = source {
+			authorized = authorized.set("x-batl-source", url);
+		}
+
+		let resp = authorized
+			.send(body)
+			.map_err(batlerror::RegistryError::from)?;
+
+		self.record_api(&resp)
+	}
+
+	/// Fetch the manifest of a workspace template
+	/// 
+	/// # Errors
+	/// 
+	/// Returns the registry error if the template can't be
+	/// found, or a network error if the registry can't be
+	/// reached
+	#[inline]
+	pub fn template(&self, name: &Name) -> Result<String, batlerror::RegistryError> {
+		if self.local_root().is_some() {
+			return Err(batlerror::RegistryError::Unsupported("templates in file registries".to_owned()));
+		}
+
+		self.call(&get(&format!("{}/template/{name}", self.url)))?
+			.into_string()
+			.map_err(|err| batlerror::RegistryError::Network(err.to_string()))
+	}
+
+	/// Fetch the archive of a package, returning the tar
+	/// stream as it downloads, sized when the registry says
+	/// how large it is. The latest version is fetched when no
+	/// version is given.
+	/// 
+	/// # Errors
+	/// 
+	/// Returns the registry error if the package can't be
+	/// fetched, or a network error if the registry can't be
+	/// reached
+	#[inline]
+	pub fn fetch(&self, name: &Name, version: Option<&Version>) -> Result<Stream<'static, Box<dyn Read + Send + Sync>>, batlerror::RegistryError> {
+		if let Some(root) = self.local_root() {
+			return local::fetch(&root, name, version);
+		}
+
+		let url = version.map_or_else(
+			|| self.package_url(name),
+			|ver| format!("{}/{ver}", self.package_url(name))
+		);
+
+		let resp = self.call(&get(&url))?;
+		let size = resp.header("content-length").and_then(|length| length.parse().ok());
+
+		Ok(Stream::new(resp.into_reader(), size))
+	}
+}
+
+/// Start a GET request, tagged with the API version of
+/// this build
+fn get(url: &str) -> ureq::Request {
+	ureq::get(url).set(API_HEADER, &API_VERSION.to_string())
+}
+
+/// Start a POST request, tagged with the API version of
+/// this build
+fn post(url: &str) -> ureq::Request {
+	ureq::post(url).set(API_HEADER, &API_VERSION.to_string())
+}
+
+/// How a registry API version, and the oldest version it
+/// accepts, relate to this build
+const fn compatibility(api_version: Option<u64>, min_api_version: Option<u64>) -> Compatibility {
+	if let Some(min) = min_api_version {
+		if min > API_VERSION {
+			return Compatibility::ClientTooOld(min);
+		}
+	}
+
+	match api_version {
+		Some(version) if version < MIN_API_VERSION => Compatibility::RegistryTooOld(version),
+		Some(version) if version > API_VERSION => Compatibility::Newer(version),
+		_ => Compatibility::Compatible
+	}
+}
+
+/// Get the tokens from a token endpoint response, or the
+/// reason none were issued
+fn token_result(response: TokenResponse, previous_refresh: Option<String>) -> Result<Tokens, batlerror::RegistryError> {
+	if let Some(code) = response.error.clone() {
+		let reason = response.error_description.map_or_else(|| code.clone(), |description| format!("{description} ({code})"));
+
+		return Err(batlerror::RegistryError::Unauthorized(reason));
+	}
+
+	response.into_tokens(previous_refresh)
+		.ok_or_else(|| batlerror::RegistryError::InvalidResponse("No access token issued".to_owned()))
+}
+
+impl From<ureq::Error> for batlerror::RegistryError {
+	#[inline]
+	fn from(value: ureq::Error) -> Self {
+		let (status, resp) = match value {
+			ureq::Error::Status(status, resp) => (status, resp),
+			ureq::Error::Transport(transport) => return Self::Network(transport.to_string())
+		};
+
+		let Ok(payload) = serde_json::from_reader::<_, ErrorPayload>(resp.into_reader()) else {
+			return match status {
+				401 | 403 => Self::Unauthorized(format!("Status code {status}")),
+				404 => Self::NotFound(format!("Status code {status}")),
+				409 => Self::VersionExists(format!("Status code {status}")),
+				426 => Self::IncompatibleApi(format!("the registry requires a newer API version than {API_VERSION}")),
+				_ => Self::Other(status, format!("Status code {status}"))
+			};
+		};
+
+		if payload.code == "insufficient_scope" {
+			return Self::InsufficientScope(payload.details.unwrap_or(payload.message));
+		}
+
+		let message = payload.details.map_or_else(
+			|| payload.message.clone(),
+			|details| format!("{} ({details})", payload.message)
+		);
+
+		match payload.code.as_str() {
+			"unauthorized" | "forbidden" | "namespace_forbidden" => Self::Unauthorized(message),
+			"not_found" => Self::NotFound(message),
+			"version_exists" => Self::VersionExists(message),
+			"invalid_package" => Self::InvalidPackage(message),
+			"unsupported_api_version" => Self::IncompatibleApi(message),
+			_ => Self::Other(status, message)
+		}
+	}
+}
@@ -0,0 +1,63 @@
+use core::fmt::{Display, Formatter};
+use crate::resource::Name;
+use semver::{Version, VersionReq};
+use serde::{Deserialize, Serialize};
+
+
+/// A published security advisory for a package
+#[derive(Serialize, Deserialize, Clone)]
+#[non_exhaustive]
+pub struct Advisory {
+	/// Identifier of the advisory, such as `BATL-2024-0001`
+	pub id: String,
+
+	/// The affected package
+	pub package: Name,
+
+	/// One line description of the problem
+	pub summary: String,
+
+	pub severity: Severity,
+
+	/// The versions the advisory applies to
+	pub affected: VersionReq,
+
+	/// The first version with a fix, if there is one
+	pub fixed_in: Option<Version>,
+
+	/// Where to read more
+	pub url: Option<String>
+}
+
+/// How severe an advisory is, from least to most
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum Severity {
+	Low,
+	Moderate,
+	High,
+	Critical
+}
+
+impl Advisory {
+	/// Whether a version of the package is affected
+	#[inline]
+	#[must_use]
+	pub fn affects(&self, version: &Version) -> bool {
+		self.affected.matches(version)
+			&& self.fixed_in.as_ref().is_none_or(|fixed| version < fixed)
+	}
+}
+
+impl Display for Severity {
+	#[inline]
+	fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+		f.write_str(match *self {
+			Self::Low => "low",
+			Self::Moderate => "moderate",
+			Self::High => "high",
+			Self::Critical => "critical"
+		})
+	}
+}
@@ -0,0 +1,242 @@
+use core::fmt::{Display, Formatter};
+use core::str::FromStr;
+use serde::{Deserialize, Serialize};
+use std::io::Write as _;
+use std::path::Path;
+
+
+/// OAuth client id used when the batlrc doesn't set one
+pub const DEFAULT_CLIENT_ID: &str = "batl-cli";
+
+/// How long before expiry an access token is refreshed,
+/// in seconds
+const REFRESH_MARGIN: u64 = 60;
+
+
+/// A pending device authorization. The user visits the
+/// verification url and enters the user code while the
+/// client polls for a token.
+#[derive(Deserialize, Clone)]
+#[non_exhaustive]
+pub struct DeviceCode {
+	pub device_code: String,
+	pub user_code: String,
+	pub verification_uri: String,
+
+	/// The verification url with the user code filled in
+	pub verification_uri_complete: Option<String>,
+
+	/// Seconds until the device code expires
+	pub expires_in: u64,
+
+	/// Seconds to wait between polls
+	#[serde(default = "default_interval")]
+	pub interval: u64
+}
+
+/// Tokens issued by a registry through OAuth
+#[derive(Serialize, Deserialize, Clone)]
+#[non_exhaustive]
+pub struct Tokens {
+	pub access_token: String,
+	pub refresh_token: Option<String>,
+
+	/// When the access token expires, in seconds since the
+	/// unix epoch
+	pub expires_at: Option<u64>
+}
+
+/// What a registry token is allowed to do
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum Scope {
+	/// Read metadata and fetch packages
+	Fetch,
+
+	/// Publish new versions
+	Publish
+}
+
+/// A token created for automation, limited to a set of
+/// scopes
+#[derive(Deserialize, Clone)]
+#[non_exhaustive]
+pub struct ScopedToken {
+	pub token: String,
+	pub scopes: Vec<Scope>,
+
+	/// When the token expires, in seconds since the unix
+	/// epoch. Tokens without an expiry last until revoked.
+	pub expires_at: Option<u64>
+}
+
+/// What the registry reports about the token in use
+#[derive(Serialize, Deserialize, Clone)]
+#[non_exhaustive]
+pub struct TokenInfo {
+	pub scopes: Vec<Scope>,
+	pub expires_at: Option<u64>
+}
+
+/// A request to create a scoped token
+#[derive(Serialize)]
+pub(super) struct CreateToken<'scopes> {
+	/// The scopes to grant
+	pub scopes: &'scopes [Scope],
+
+	/// Seconds until the token expires
+	pub expires_in: Option<u64>
+}
+
+/// A token endpoint response, either tokens or an OAuth
+/// error code
+#[derive(Deserialize)]
+pub(super) struct TokenResponse {
+	/// The issued access token
+	pub access_token: Option<String>,
+
+	/// The issued refresh token, if the registry rotates
+	pub refresh_token: Option<String>,
+
+	/// Seconds until the access token expires
+	pub expires_in: Option<u64>,
+
+	/// OAuth error code, such as `authorization_pending`
+	pub error: Option<String>,
+
+	/// Human readable error
+	pub error_description: Option<String>
+}
+
+impl DeviceCode {
+	/// The url the user should open, with the user code
+	/// filled in when the registry supports it
+	#[inline]
+	#[must_use]
+	pub fn url(&self) -> &str {
+		self.verification_uri_complete.as_deref().unwrap_or(&self.verification_uri)
+	}
+}
+
+impl Tokens {
+	/// Read the tokens stored for a battalion root and its
+	/// profile, if there are any
+	#[inline]
+	#[must_use]
+	pub fn load_in(system: &crate::system::Context) -> Option<Self> {
+		let contents = std::fs::read_to_string(system.tokens_path()?).ok()?;
+
+		serde_json::from_str(&contents).ok()
+	}
+
+	/// Store the tokens for a battalion root and its
+	/// profile, readable only by the current user
+	///
+	/// # Errors
+	///
+	/// Propogates any IO errors, or `NotFound` if battalion
+	/// is not set up
+	#[inline]
+	pub fn save(&self, system: &crate::system::Context) -> Result<(), std::io::Error> {
+		let token_path = system.tokens_path()
+			.ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "Battalion root"))?;
+
+		self.save_at(&token_path)
+	}
+
+	/// Store the tokens at a path, readable only by the
+	/// current user
+	pub(super) fn save_at(&self, token_path: &Path) -> Result<(), std::io::Error> {
+		if let Some(parent) = token_path.parent() {
+			std::fs::create_dir_all(parent)?;
+		}
+
+		let contents = serde_json::to_vec(self)
+			.map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+
+		let mut options = std::fs::OpenOptions::new();
+		options.write(true).create(true).truncate(true);
+
+		#[cfg(unix)]
+		std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+		options.open(token_path)?.write_all(&contents)
+	}
+
+	/// Remove the tokens stored for a battalion root and its
+	/// profile
+	///
+	/// # Errors
+	///
+	/// Propogates any IO errors other than there being no
+	/// stored tokens
+	#[inline]
+	pub fn clear(system: &crate::system::Context) -> Result<(), std::io::Error> {
+		let Some(token_path) = system.tokens_path() else {
+			return Ok(());
+		};
+
+		match std::fs::remove_file(token_path) {
+			Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err),
+			_ => Ok(())
+		}
+	}
+
+	/// Whether the access token has expired, or is about to
+	#[inline]
+	#[must_use]
+	pub fn is_expired(&self) -> bool {
+		self.expires_at.is_some_and(|expires_at| crate::system::unix_time().saturating_add(REFRESH_MARGIN) >= expires_at)
+	}
+}
+
+impl Display for Scope {
+	#[inline]
+	fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+		f.write_str(match *self {
+			Self::Fetch => "fetch",
+			Self::Publish => "publish"
+		})
+	}
+}
+
+impl FromStr for Scope {
+	type Err = String;
+
+	#[inline]
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"fetch" => Ok(Self::Fetch),
+			"publish" => Ok(Self::Publish),
+			_ => Err(format!("Unknown scope {s}, expected fetch or publish"))
+		}
+	}
+}
+
+impl TokenInfo {
+	/// Whether the token grants a scope
+	#[inline]
+	#[must_use]
+	pub fn allows(&self, scope: Scope) -> bool {
+		self.scopes.contains(&scope)
+	}
+}
+
+impl TokenResponse {
+	/// Turn a successful response into tokens, keeping the
+	/// previous refresh token if a new one wasn't issued
+	pub(super) fn into_tokens(self, previous_refresh: Option<String>) -> Option<Tokens> {
+		Some(Tokens {
+			access_token: self.access_token?,
+			refresh_token: self.refresh_token.or(previous_refresh),
+			expires_at: self.expires_in.map(|secs| crate::system::unix_time().saturating_add(secs))
+		})
+	}
+}
+
+/// The poll interval when the registry doesn't give one,
+/// as recommended by RFC 8628
+const fn default_interval() -> u64 {
+	5
+}
@@ -0,0 +1,88 @@
+use core::time::Duration;
+use serde::{Serialize, Deserialize};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+
+/// Length of the hashes cache files are named by, in bytes
+const KEY_LENGTH: usize = 16;
+
+
+/// A cached registry response
+#[derive(Serialize, Deserialize)]
+pub(super) struct Entry {
+	/// The `ETag` the registry sent with the response
+	pub etag: String,
+
+	/// The response body
+	pub body: String
+}
+
+/// Get the cache file for a url in a cache root
+fn entry_path(root: &Path, url: &str) -> PathBuf {
+	// The hash has to stay the same across builds of batl,
+	// or upgrading would lose the cache
+	let key = blake2b_simd::Params::new()
+		.hash_length(KEY_LENGTH)
+		.hash(url.as_bytes())
+		.to_hex();
+
+	root.join(format!("{key}.json"))
+}
+
+/// The file holding when a registry request was last sent,
+/// so requests are spaced out across runs of batl
+fn last_request_path(root: &Path) -> PathBuf {
+	root.join("last_request")
+}
+
+/// Read the cached response for a url, if any
+pub(super) fn read(root: &Path, url: &str) -> Option<Entry> {
+	let contents = std::fs::read_to_string(entry_path(root, url)).ok()?;
+
+	serde_json::from_str(&contents).ok()
+}
+
+/// Cache a response for a url. Caching is best effort,
+/// so failures are ignored.
+pub(super) fn write(root: &Path, url: &str, entry: &Entry) {
+	let path = entry_path(root, url);
+
+	if let Some(parent) = path.parent() {
+		if std::fs::create_dir_all(parent).is_err() {
+			return;
+		}
+	}
+
+	if let Ok(contents) = serde_json::to_string(entry) {
+		// Best effort, a missing entry only costs a request
+		drop(std::fs::write(path, contents));
+	}
+}
+
+/// How long ago a registry request was last sent, by this
+/// or any other run of batl
+pub(super) fn since_last_request(root: &Path) -> Option<Duration> {
+	let contents = std::fs::read_to_string(last_request_path(root)).ok()?;
+	let sent = UNIX_EPOCH.checked_add(Duration::from_millis(contents.trim().parse().ok()?))?;
+
+	SystemTime::now().duration_since(sent).ok()
+}
+
+/// Record that a registry request is being sent. Like the
+/// rest of the cache, this is best effort.
+pub(super) fn record_request(root: &Path) {
+	let path = last_request_path(root);
+
+	let Ok(now) = SystemTime::now().duration_since(UNIX_EPOCH) else {
+		return;
+	};
+
+	if let Some(parent) = path.parent() {
+		if std::fs::create_dir_all(parent).is_err() {
+			return;
+		}
+	}
+
+	drop(std::fs::write(path, now.as_millis().to_string()));
+}
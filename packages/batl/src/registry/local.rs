@@ -0,0 +1,236 @@
+use crate::error as batlerror;
+use crate::integrity::Checksum;
+use crate::resource::Name;
+use crate::resource::archive::{Manifest, Span, Stream};
+use semver::Version;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek as _, SeekFrom};
+use std::path::{Path, PathBuf};
+use super::{PackageMetadata, SearchResult};
+use super::advisory::Advisory;
+
+
+/// Url scheme of registries stored in a directory
+const SCHEME: &str = "file://";
+
+/// Name of the metadata file of each package
+const META_FILE: &str = "meta.json";
+
+/// Name of the advisories file of each package
+const ADVISORIES_FILE: &str = "advisories.json";
+
+/// Get the directory of a registry url, or `None` if the
+/// registry is not stored in a directory
+pub(super) fn root(url: &str) -> Option<PathBuf> {
+	url.strip_prefix(SCHEME).map(PathBuf::from)
+}
+
+/// Get the directory of a package
+fn package_dir(root: &Path, name: &Name) -> PathBuf {
+	root.join("pkg").join(name.to_string())
+}
+
+/// Get the archive of a package version
+fn archive_path(root: &Path, name: &Name, version: &Version) -> PathBuf {
+	package_dir(root, name).join(format!("{version}.tar"))
+}
+
+/// Get the checksum stored next to the archive of a
+/// package version
+fn checksum_path(root: &Path, name: &Name, version: &Version) -> PathBuf {
+	package_dir(root, name).join(format!("{version}.checksum.json"))
+}
+
+/// Report missing files as missing packages, like a
+/// registry served over http would
+fn io_error(err: std::io::Error, what: String) -> batlerror::RegistryError {
+	if err.kind() == std::io::ErrorKind::NotFound {
+		batlerror::RegistryError::NotFound(what)
+	} else {
+		batlerror::RegistryError::IoError(err)
+	}
+}
+
+/// Read the metadata of a package
+pub(super) fn metadata(root: &Path, name: &Name) -> Result<PackageMetadata, batlerror::RegistryError> {
+	let path = package_dir(root, name).join(META_FILE);
+
+	let contents = std::fs::read_to_string(&path)
+		.map_err(|err| io_error(err, name.to_string()))?;
+
+	serde_json::from_str(&contents)
+		.map_err(|err| batlerror::RegistryError::InvalidResponse(format!("{}: {err}", path.display())))
+}
+
+/// Read the advisories of a package, kept next to its
+/// metadata in `advisories.json`
+pub(super) fn advisories(root: &Path, name: &Name) -> Result<Vec<Advisory>, batlerror::RegistryError> {
+	let path = package_dir(root, name).join(ADVISORIES_FILE);
+
+	let contents = match std::fs::read_to_string(&path) {
+		Ok(contents) => contents,
+		Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+		Err(err) => return Err(err.into())
+	};
+
+	serde_json::from_str(&contents)
+		.map_err(|err| batlerror::RegistryError::InvalidResponse(format!("{}: {err}", path.display())))
+}
+
+/// Find every package whose name contains the query
+pub(super) fn search(root: &Path, query: &str) -> Result<Vec<SearchResult>, batlerror::RegistryError> {
+	let pkg_root = root.join("pkg");
+
+	if !pkg_root.exists() {
+		return Ok(Vec::new());
+	}
+
+	let walk = ignore::WalkBuilder::new(&pkg_root)
+		.standard_filters(false)
+		.build();
+
+	let mut results = Vec::new();
+
+	for entry in walk {
+		let path = entry.map_err(|err| batlerror::RegistryError::InvalidResponse(err.to_string()))?
+			.into_path();
+
+		if path.file_name().is_none_or(|file| file != META_FILE) {
+			continue;
+		}
+
+		let Some(name) = path.parent()
+			.and_then(|dir| dir.strip_prefix(&pkg_root).ok())
+			.and_then(Path::to_str)
+			.map(Name::from) else {
+			continue;
+		};
+
+		if !name.to_string().contains(query) {
+			continue;
+		}
+
+		let meta = metadata(root, &name)?;
+
+		results.push(SearchResult {
+			name: meta.name,
+			description: meta.description,
+			latest: meta.latest
+		});
+	}
+
+	Ok(results)
+}
+
+/// Open the archive of a package version, or of the
+/// latest version when none is given
+pub(super) fn fetch(root: &Path, name: &Name, version: Option<&Version>) -> Result<Stream<'static, Box<dyn Read + Send + Sync>>, batlerror::RegistryError> {
+	let wanted = match version {
+		Some(ver) => ver.clone(),
+		None => metadata(root, name)?
+			.latest
+			.ok_or_else(|| batlerror::RegistryError::NotFound(format!("{name} has no versions")))?
+	};
+
+	let file = File::open(archive_path(root, name, &wanted))
+		.map_err(|err| io_error(err, format!("{name}@{wanted}")))?;
+	let size = file.metadata()?.len();
+
+	Ok(Stream::new(Box::new(file), Some(size)))
+}
+
+/// Build the manifest of a package version from its archive
+pub(super) fn manifest(root: &Path, name: &Name, version: &Version) -> Result<Manifest, batlerror::RegistryError> {
+	Manifest::of(&archive_path(root, name, version))
+		.map_err(|err| io_error(err, format!("{name}@{version}")))
+}
+
+/// Read the checksum of a package version, or `None` for
+/// versions stored without one
+pub(super) fn checksum(root: &Path, name: &Name, version: &Version) -> Result<Option<Checksum>, batlerror::RegistryError> {
+	let path = checksum_path(root, name, version);
+
+	let contents = match std::fs::read_to_string(&path) {
+		Ok(contents) => contents,
+		Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+		Err(err) => return Err(err.into())
+	};
+
+	serde_json::from_str(&contents)
+		.map(Some)
+		.map_err(|err| batlerror::RegistryError::InvalidResponse(format!("{}: {err}", path.display())))
+}
+
+/// Read a range of bytes of a package version's archive
+pub(super) fn read_span(root: &Path, name: &Name, version: &Version, span: Span) -> Result<Vec<u8>, batlerror::RegistryError> {
+	let mut file = File::open(archive_path(root, name, version))
+		.map_err(|err| io_error(err, format!("{name}@{version}")))?;
+
+	file.seek(SeekFrom::Start(span.offset))?;
+
+	let mut contents = Vec::new();
+	file.take(span.size).read_to_end(&mut contents)?;
+
+	Ok(contents)
+}
+
+/// Store a package version copied from another registry,
+/// merging it into the package metadata and keeping its
+/// checksum next to it
+pub(super) fn import(root: &Path, source: &PackageMetadata, version: &Version, body: &mut dyn Read, checksum: Option<&Checksum>) -> Result<(), batlerror::RegistryError> {
+	let dir = package_dir(root, &source.name);
+	std::fs::create_dir_all(&dir)?;
+
+	let mut meta = match metadata(root, &source.name) {
+		Ok(existing) => existing,
+		Err(batlerror::RegistryError::NotFound(_)) => PackageMetadata {
+			name: source.name.clone(),
+			description: None,
+			latest: None,
+			versions: Vec::new(),
+			health: HashMap::new(),
+			source: None
+		},
+		Err(err) => return Err(err)
+	};
+
+	if meta.versions.contains(version) {
+		return Err(batlerror::RegistryError::VersionExists(format!("{}@{version}", source.name)));
+	}
+
+	let mut archive = File::create(archive_path(root, &source.name, version))?;
+	std::io::copy(body, &mut archive)?;
+
+	if let Some(sum) = checksum {
+		let contents = serde_json::to_string_pretty(sum)
+			.map_err(|err| batlerror::RegistryError::InvalidPackage(err.to_string()))?;
+
+		std::fs::write(checksum_path(root, &source.name, version), contents)?;
+	}
+
+	// Publishes that don't know the description or source
+	// keep the ones stored before
+	if let Some(description) = source.description.as_ref() {
+		meta.description = Some(description.clone());
+	}
+
+	if let Some(url) = source.source.as_ref() {
+		meta.source = Some(url.clone());
+	}
+
+	meta.versions.push(version.clone());
+	meta.versions.sort();
+	meta.latest = meta.versions.last().cloned();
+
+	if let Some(health) = source.health.get(version) {
+		meta.health.insert(version.clone(), health.clone());
+	}
+
+	let contents = serde_json::to_string_pretty(&meta)
+		.map_err(|err| batlerror::RegistryError::InvalidPackage(err.to_string()))?;
+
+	std::fs::write(dir.join(META_FILE), contents)?;
+
+	Ok(())
+}
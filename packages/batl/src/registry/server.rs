@@ -0,0 +1,488 @@
+use crate::error as batlerror;
+use crate::integrity::Checksum;
+use crate::resource::Name;
+use crate::resource::archive::{self, Span};
+use crate::resource::repository::Config;
+use core::net::SocketAddr;
+use semver::Version;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use super::{local, Capabilities, ErrorPayload, Health, PackageMetadata, API_HEADER, API_VERSION, MIN_API_VERSION, RANGES_FEATURE};
+use super::auth::{Scope, TokenInfo};
+use tiny_http::{Header, Method, Request, ResponseBox, StatusCode};
+
+
+/// Every scope, granted to tokens that don't list any
+const ALL_SCOPES: [Scope; 2] = [Scope::Fetch, Scope::Publish];
+
+/// Folder of the registry root that uploads are written to
+/// before they are checked
+const UPLOADS_DIR: &str = "uploads";
+
+
+/// A registry served over http from a directory laid out
+/// like a file registry, so a mirror can be served as is
+#[non_exhaustive]
+pub struct Server {
+	/// The directory packages are stored in
+	root: PathBuf,
+
+	/// The scopes granted to each token
+	tokens: HashMap<String, Vec<Scope>>,
+
+	/// The listening http server
+	http: tiny_http::Server,
+
+	/// Held while a version is stored, so concurrent
+	/// publishes don't overwrite each other's metadata
+	publishing: Mutex<()>
+}
+
+/// What a request asks of a package
+enum Route {
+	/// Its metadata
+	Meta,
+
+	/// Its advisories
+	Advisories,
+
+	/// The checksum of a version
+	Checksum(Version),
+
+	/// The manifest of a version
+	Manifest(Version),
+
+	/// The archive of a version, or of the latest one
+	Archive(Option<Version>),
+
+	/// A delta archive to publish
+	Delta
+}
+
+impl Server {
+	/// Listen on an address, serving the registry stored in
+	/// a directory. Tokens are given with the scopes they
+	/// grant.
+	///
+	/// # Errors
+	///
+	/// Propogates any IO errors while binding the address
+	#[inline]
+	pub fn bind(root: PathBuf, address: &str, tokens: HashMap<String, Vec<Scope>>) -> Result<Self, batlerror::RegistryError> {
+		let http = tiny_http::Server::http(address)
+			.map_err(std::io::Error::other)?;
+
+		Ok(Self {
+			root,
+			tokens,
+			http,
+			publishing: Mutex::new(())
+		})
+	}
+
+	/// The address the server listens on
+	#[inline]
+	#[must_use]
+	pub fn address(&self) -> Option<SocketAddr> {
+		self.http.server_addr().to_ip()
+	}
+
+	/// Answer requests on a number of worker threads until
+	/// the server stops
+	///
+	/// # Errors
+	///
+	/// Propogates any IO errors while receiving requests
+	#[inline]
+	pub fn run(&self, workers: usize) -> Result<(), batlerror::RegistryError> {
+		std::thread::scope(|scope| {
+			let handles = core::iter::repeat_with(|| scope.spawn(|| -> Result<(), std::io::Error> {
+				loop {
+					self.respond(self.http.recv()?);
+				}
+			}))
+				.take(workers.max(1))
+				.collect::<Vec<_>>();
+
+			for handle in handles {
+				handle.join()
+					.map_err(|_panic| std::io::Error::other("A registry worker panicked"))??;
+			}
+
+			Ok(())
+		})
+	}
+
+	/// Answer a single request, tagging the response with
+	/// the API version of this build
+	fn respond(&self, mut request: Request) {
+		let response = self.route(&mut request)
+			.unwrap_or_else(error_response);
+
+		// The client may have gone away, which only matters
+		// to that client
+		drop(request.respond(with_header(response, API_HEADER, &API_VERSION.to_string())));
+	}
+
+	/// Answer a request by its method and path
+	fn route(&self, request: &mut Request) -> Result<ResponseBox, batlerror::RegistryError> {
+		let client_api = find_header(request, API_HEADER)
+			.and_then(|value| value.parse::<u64>().ok());
+
+		if let Some(version) = client_api.filter(|version| *version < MIN_API_VERSION) {
+			return Err(batlerror::RegistryError::IncompatibleApi(format!("API version {version} is older than {MIN_API_VERSION}")));
+		}
+
+		let url = request.url().to_owned();
+		let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+
+		let segments = path.split('/')
+			.filter(|segment| !segment.is_empty())
+			.map(percent_decode)
+			.collect::<Vec<_>>();
+
+		let method = request.method().clone();
+		let not_found = || batlerror::RegistryError::NotFound(path.to_owned());
+
+		let Some((first, rest)) = segments.split_first() else {
+			return Err(not_found());
+		};
+
+		match (first.as_str(), method) {
+			("health", Method::Get) if rest.is_empty() => json(&Capabilities {
+				api_version: Some(API_VERSION),
+				min_api_version: Some(MIN_API_VERSION),
+				features: vec![RANGES_FEATURE.to_owned()]
+			}),
+			("tokens", Method::Get) if rest == ["self"] => json(&TokenInfo {
+				scopes: self.scopes(request)?.to_vec(),
+				expires_at: None
+			}),
+			("search", Method::Get) if rest.is_empty() => {
+				let search = query.split('&')
+					.filter_map(|pair| pair.split_once('='))
+					.find(|pair| pair.0 == "q")
+					.map(|pair| percent_decode(pair.1))
+					.unwrap_or_default();
+
+				self.authorize(request, Scope::Fetch)?;
+
+				json(&local::search(&self.root, &search)?)
+			},
+			("pkg", Method::Get) => {
+				let (name, route) = package_route(rest).ok_or_else(not_found)?;
+				self.authorize(request, Scope::Fetch)?;
+
+				self.get_package(request, &name, route)
+			},
+			("pkg", Method::Post) => match package_route(rest) {
+				Some((_, Route::Delta)) => Err(batlerror::RegistryError::Unsupported("delta publishing".to_owned())),
+				Some((name, Route::Archive(None))) => self.publish(request, &name),
+				Some(_) | None => Err(not_found())
+			},
+			(_, _) => Err(not_found())
+		}
+	}
+
+	/// Answer a request for a package
+	fn get_package(&self, request: &Request, name: &Name, route: Route) -> Result<ResponseBox, batlerror::RegistryError> {
+		match route {
+			Route::Meta => json(&local::metadata(&self.root, name)?),
+			Route::Advisories => json(&local::advisories(&self.root, name)?),
+			Route::Checksum(version) => json(&local::checksum(&self.root, name, &version)?
+				.ok_or_else(|| batlerror::RegistryError::NotFound(format!("A checksum for {name}@{version}")))?),
+			Route::Manifest(version) => json(&local::manifest(&self.root, name, &version)?),
+			Route::Archive(version) => {
+				if let Some((wanted, span)) = version.as_ref().zip(find_header(request, "range").and_then(|value| parse_range(&value))) {
+					let contents = local::read_span(&self.root, name, wanted, span)?;
+					let last = span.offset.saturating_add(span.size).saturating_sub(1);
+
+					let response = tiny_http::Response::from_data(contents)
+						.with_status_code(206)
+						.boxed();
+
+					return Ok(with_header(response, "content-range", &format!("bytes {}-{last}/*", span.offset)));
+				}
+
+				let stream = local::fetch(&self.root, name, version.as_ref())?;
+				let size = stream.size_hint().and_then(|bytes| usize::try_from(bytes).ok());
+
+				Ok(tiny_http::Response::new(StatusCode(200), Vec::new(), stream.into_inner(), size, None).boxed())
+			},
+			Route::Delta => Err(batlerror::RegistryError::NotFound(format!("{name}/delta")))
+		}
+	}
+
+	/// Store a published archive, once it matches its
+	/// checksum and its batl.toml declares the name it is
+	/// published under
+	fn publish(&self, request: &mut Request, name: &Name) -> Result<ResponseBox, batlerror::RegistryError> {
+		self.authorize(request, Scope::Publish)?;
+
+		let invalid = |message: String| batlerror::RegistryError::InvalidPackage(message);
+
+		let checksum_header = find_header(request, "x-batl-checksum")
+			.ok_or_else(|| invalid("Missing the x-batl-checksum header".to_owned()))?;
+
+		let checksum: Checksum = serde_json::from_str(&checksum_header)
+			.map_err(|err| invalid(format!("Invalid checksum: {err}")))?;
+
+		let health = find_header(request, "x-batl-health")
+			.map(|value| serde_json::from_str::<Health>(&value).map_err(|err| invalid(format!("Invalid health: {err}"))))
+			.transpose()?;
+
+		let source = find_header(request, "x-batl-source");
+
+		let uploads = self.root.join(UPLOADS_DIR);
+		std::fs::create_dir_all(&uploads)?;
+
+		let upload = uploads.join(format!("{:016x}.tar", rand::random::<u64>()));
+		let result = receive(request, &upload)
+			.and_then(|()| self.store(&upload, name, &checksum, health, source));
+
+		// The upload was copied into place, or is rejected
+		drop(std::fs::remove_file(&upload));
+		result?;
+
+		json(&())
+	}
+
+	/// Check an uploaded archive and store it as a version
+	/// of a package
+	fn store(&self, upload: &Path, name: &Name, checksum: &Checksum, health: Option<Health>, source: Option<String>) -> Result<(), batlerror::RegistryError> {
+		let invalid = |message: String| batlerror::RegistryError::InvalidPackage(message);
+
+		let contents = archive::read_file(upload, "batl.toml")?
+			.ok_or_else(|| invalid("The archive has no batl.toml".to_owned()))?;
+
+		let text = core::str::from_utf8(&contents)
+			.map_err(|err| invalid(format!("Invalid batl.toml: {err}")))?;
+
+		let config = Config::parse(text)
+			.map_err(|err| invalid(format!("Invalid batl.toml: {err}")))?;
+
+		checksum.verify(upload, name, &config.version, &[])
+			.map_err(|err| invalid(err.to_string()))?;
+
+		if config.name != *name {
+			return Err(invalid(format!("The archive declares name {} instead of {name}", config.name)));
+		}
+
+		let metadata = PackageMetadata {
+			name: name.clone(),
+			description: None,
+			latest: None,
+			versions: Vec::new(),
+			health: health.map(|checked| HashMap::from([(config.version.clone(), checked)])).unwrap_or_default(),
+			source
+		};
+
+		let _guard = self.publishing.lock()
+			.map_err(|_poisoned| std::io::Error::other("A publish panicked"))?;
+
+		local::import(&self.root, &metadata, &config.version, &mut File::open(upload)?, Some(checksum))
+	}
+
+	/// Check that a request is authenticated with a token
+	/// granting a scope. Fetching is open to everyone when
+	/// no tokens are configured.
+	fn authorize(&self, request: &Request, scope: Scope) -> Result<(), batlerror::RegistryError> {
+		if scope == Scope::Fetch && self.tokens.is_empty() {
+			return Ok(());
+		}
+
+		if !self.scopes(request)?.contains(&scope) {
+			return Err(batlerror::RegistryError::InsufficientScope(scope.to_string()));
+		}
+
+		Ok(())
+	}
+
+	/// The scopes of the token a request is authenticated
+	/// with
+	fn scopes(&self, request: &Request) -> Result<&[Scope], batlerror::RegistryError> {
+		let bearer = find_header(request, "authorization")
+			.and_then(|value| value.strip_prefix("Bearer ").map(ToOwned::to_owned));
+
+		let token = find_header(request, "x-api-key")
+			.or(bearer)
+			.ok_or_else(|| batlerror::RegistryError::Unauthorized("No token given".to_owned()))?;
+
+		self.tokens.get(token.trim())
+			.map(Vec::as_slice)
+			.ok_or_else(|| batlerror::RegistryError::Unauthorized("Unknown token".to_owned()))
+	}
+}
+
+/// Read a token file, with a token on each line followed
+/// by the scopes it grants. Tokens without scopes grant
+/// every scope, and lines starting with `#` are skipped.
+///
+/// # Errors
+///
+/// Returns an `InvalidData` error for unknown scopes, and
+/// propogates any IO errors while reading the file
+#[inline]
+pub fn read_tokens(path: &Path) -> Result<HashMap<String, Vec<Scope>>, std::io::Error> {
+	let mut tokens = HashMap::new();
+
+	for line in std::fs::read_to_string(path)?.lines().map(str::trim) {
+		if line.is_empty() || line.starts_with('#') {
+			continue;
+		}
+
+		let mut words = line.split_whitespace();
+		let Some(token) = words.next() else {
+			continue;
+		};
+
+		let mut scopes = words.map(str::parse)
+			.collect::<Result<Vec<Scope>, _>>()
+			.map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{}: {err}", path.display())))?;
+
+		if scopes.is_empty() {
+			scopes = ALL_SCOPES.to_vec();
+		}
+
+		tokens.insert(token.to_owned(), scopes);
+	}
+
+	Ok(tokens)
+}
+
+/// Split the path of a package request into the package
+/// name and what is asked of it. Every part of the name
+/// must be a plain word, so names can't leave the root.
+fn package_route(segments: &[String]) -> Option<(Name, Route)> {
+	let (last, rest) = segments.split_last()?;
+	let version = |segment: &String| Version::parse(segment).ok();
+
+	let (name, route) = match (last.as_str(), rest.split_last()) {
+		("meta", _) => (rest, Route::Meta),
+		("advisories", _) => (rest, Route::Advisories),
+		("delta", _) => (rest, Route::Delta),
+		("checksum", Some((before, name))) => (name, Route::Checksum(version(before)?)),
+		("manifest", Some((before, name))) => (name, Route::Manifest(version(before)?)),
+		(_, _) => version(last).map_or_else(
+			|| (segments, Route::Archive(None)),
+			|found| (rest, Route::Archive(Some(found)))
+		)
+	};
+
+	let plain = |segment: &String| !segment.is_empty()
+		&& segment.chars().all(|character| character.is_ascii_alphanumeric() || character == '-' || character == '_');
+
+	if name.is_empty() || !name.iter().all(plain) {
+		return None;
+	}
+
+	Some((Name::from(name.join("/")), route))
+}
+
+/// Write the body of a request to a file
+fn receive(request: &mut Request, path: &Path) -> Result<(), batlerror::RegistryError> {
+	let mut file = File::create(path)?;
+	std::io::copy(request.as_reader(), &mut file)?;
+
+	Ok(())
+}
+
+/// The value of a request header
+fn find_header(request: &Request, name: &'static str) -> Option<String> {
+	request.headers()
+		.iter()
+		.find(|found| found.field.equiv(name))
+		.map(|found| found.value.as_str().to_owned())
+}
+
+/// Add a header to a response. Headers are only built
+/// from known names and printable values, so none are
+/// dropped.
+fn with_header(response: ResponseBox, name: &str, value: &str) -> ResponseBox {
+	match Header::from_bytes(name.as_bytes(), value.as_bytes()) {
+		Ok(built) => response.with_header(built),
+		Err(()) => response
+	}
+}
+
+/// A JSON response
+fn json<T: Serialize>(body: &T) -> Result<ResponseBox, batlerror::RegistryError> {
+	let contents = serde_json::to_vec(body)
+		.map_err(|err| batlerror::RegistryError::InvalidResponse(err.to_string()))?;
+
+	Ok(with_header(tiny_http::Response::from_data(contents).boxed(), "content-type", "application/json"))
+}
+
+/// The response for an error, with the status and code the
+/// client maps back to it
+fn error_response(err: batlerror::RegistryError) -> ResponseBox {
+	let fallback = err.to_string();
+
+	let (status, code, message, details) = match err {
+		batlerror::RegistryError::Unauthorized(reason) => (401u16, "unauthorized", reason, None),
+		batlerror::RegistryError::InsufficientScope(scope) => (403u16, "insufficient_scope", format!("The token lacks the {scope} scope"), Some(scope)),
+		batlerror::RegistryError::NotFound(what) => (404u16, "not_found", what, None),
+		batlerror::RegistryError::Unsupported(what) => (404u16, "not_found", format!("{what} is not supported"), None),
+		batlerror::RegistryError::VersionExists(version) => (409u16, "version_exists", version, None),
+		batlerror::RegistryError::InvalidPackage(reason) => (400u16, "invalid_package", reason, None),
+		batlerror::RegistryError::IncompatibleApi(reason) => (426u16, "unsupported_api_version", reason, None),
+		batlerror::RegistryError::Network(_)
+		| batlerror::RegistryError::InvalidResponse(_)
+		| batlerror::RegistryError::Other(..)
+		| batlerror::RegistryError::UnknownRegistry(_)
+		| batlerror::RegistryError::IoError(_) => (500u16, "internal", fallback, None)
+	};
+
+	let payload = ErrorPayload {
+		code: code.to_owned(),
+		message,
+		details
+	};
+
+	let body = serde_json::to_vec(&payload).unwrap_or_default();
+
+	let response = tiny_http::Response::from_data(body)
+		.with_status_code(status)
+		.boxed();
+
+	with_header(response, "content-type", "application/json")
+}
+
+/// Parse a `bytes=start-end` range header into a span
+fn parse_range(value: &str) -> Option<Span> {
+	let (start, end) = value.strip_prefix("bytes=")?.split_once('-')?;
+
+	let offset = start.trim().parse::<u64>().ok()?;
+	let last = end.trim().parse::<u64>().ok()?;
+
+	Some(Span {
+		offset,
+		size: last.checked_sub(offset)?.checked_add(1)?
+	})
+}
+
+/// Decode the percent escapes of a url component
+fn percent_decode(component: &str) -> String {
+	let mut bytes = Vec::with_capacity(component.len());
+	let mut rest = component.as_bytes();
+
+	while let Some((&byte, after)) = rest.split_first() {
+		let escaped = after.get(..2)
+			.filter(|_| byte == b'%')
+			.and_then(|digits| core::str::from_utf8(digits).ok())
+			.and_then(|digits| u8::from_str_radix(digits, 16).ok());
+
+		if let Some(decoded) = escaped {
+			bytes.push(decoded);
+			rest = after.get(2..).unwrap_or_default();
+		} else {
+			bytes.push(if byte == b'+' { b' ' } else { byte });
+			rest = after;
+		}
+	}
+
+	String::from_utf8_lossy(&bytes).into_owned()
+}
@@ -0,0 +1,209 @@
+use crate::context::BatlContext;
+use crate::error as batlerror;
+use crate::resource::{Freeze, Name, Repository, Resource as _, VersionedName};
+use semver::Version;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+
+/// What a repository is resolved against, besides the
+/// repositories of the battalion root
+#[derive(Clone, Default)]
+#[non_exhaustive]
+pub struct Options {
+	/// An exact version to resolve, such as one a
+	/// dependency is pinned to
+	pub version: Option<Version>,
+
+	/// Versions that replace whatever would be resolved,
+	/// such as `--with name@version`
+	pub overrides: Vec<VersionedName>,
+
+	/// A freeze whose recorded versions and contents are
+	/// resolved instead
+	pub freeze: Option<Freeze>,
+
+	/// The named registry of each dependency that doesn't
+	/// come from the default one, as given by
+	/// `Repository::dependency_registries`
+	pub registries: HashMap<Name, String>
+}
+
+/// A repository along with where it was found and why
+#[non_exhaustive]
+pub struct ResolvedRepository {
+	pub repository: Repository,
+	pub provenance: Provenance
+}
+
+/// How a repository was resolved
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct Provenance {
+	/// Where the repository was found
+	pub location: Location,
+
+	/// What chose the version, when one was chosen
+	pub pin: Option<Pin>,
+
+	/// The named registry the repository comes from, as
+	/// recorded in the freeze or given by a dependent.
+	/// `None` for the default registry.
+	pub registry: Option<String>
+}
+
+/// Where a resolved repository lives
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum Location {
+	/// Under the repository root
+	Local,
+
+	/// Outside of the root, from `externals` in the batlrc
+	External,
+
+	/// A fetched version, under `gen/versions`
+	Fetched
+}
+
+/// A version chosen ahead of resolving
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct Pin {
+	pub kind: PinKind,
+	pub version: Version,
+
+	/// The tag of the freeze the version was recorded in
+	pub freeze: Option<String>
+}
+
+/// What chose a pinned version
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum PinKind {
+	/// One of the overrides
+	Override,
+
+	/// The freeze
+	Freeze,
+
+	/// The version of the options
+	Requested
+}
+
+impl Options {
+	/// Resolve an exact version
+	#[inline]
+	#[must_use]
+	pub fn version(version: Version) -> Self {
+		Self {
+			version: Some(version),
+			..Self::default()
+		}
+	}
+
+	/// Resolve with overrides
+	#[inline]
+	#[must_use]
+	pub fn overrides(overrides: Vec<VersionedName>) -> Self {
+		Self {
+			overrides,
+			..Self::default()
+		}
+	}
+}
+
+/// Resolve a repository the way every command does
+///
+/// Overrides come first, then the freeze, then the
+/// requested version, each loading only that exact version
+/// from the repository root or the fetched versions.
+/// Without any of them, the repository under the root is
+/// used, falling back to `externals` in the batlrc. A
+/// frozen repository must also match its recorded
+/// checksum.
+///
+/// # Errors
+///
+/// Propogates any errors while loading the repository or
+/// hashing a frozen one. Returns `None` if nothing matches,
+/// without falling back past a pinned version.
+#[inline]
+pub fn resolve(context: &BatlContext, name: &Name, options: &Options) -> Result<Option<ResolvedRepository>, batlerror::ResolveError> {
+	let case = context.settings().name_case;
+	let normal_name = name.clone().normalized(case);
+
+	let overridden = options.overrides.iter()
+		.find(|versioned| versioned.name.clone().normalized(case) == normal_name);
+
+	let registry = options.registries.get(&normal_name).cloned();
+
+	if let Some(versioned) = overridden {
+		let pin = Pin::new(PinKind::Override, versioned.version.clone(), None);
+
+		return Ok(Repository::load_version_in(context, normal_name, &versioned.version)?
+			.map(|repository| ResolvedRepository::new(context, repository, Some(pin), registry)));
+	}
+
+	let frozen = options.freeze.as_ref()
+		.and_then(|freeze| freeze.entries.iter()
+			.find(|entry| entry.name == normal_name)
+			.map(|entry| (freeze, entry)));
+
+	if let Some((freeze, entry)) = frozen {
+		let pin = Pin::new(PinKind::Freeze, entry.version.clone(), Some(freeze.tag.clone()));
+
+		// The freeze records where it was fetched from
+		let frozen_registry = entry.registry.clone().or(registry);
+
+		return Ok(entry.locate(context)?
+			.map(|repository| ResolvedRepository::new(context, repository, Some(pin), frozen_registry)));
+	}
+
+	if let Some(version) = options.version.as_ref() {
+		let pin = Pin::new(PinKind::Requested, version.clone(), None);
+
+		return Ok(Repository::load_version_in(context, normal_name, version)?
+			.map(|repository| ResolvedRepository::new(context, repository, Some(pin), registry)));
+	}
+
+	Ok(Repository::load_in(context, normal_name)?
+		.map(|repository| ResolvedRepository::new(context, repository, None, registry)))
+}
+
+impl ResolvedRepository {
+	/// A resolved repository, locating it within the roots
+	/// of a context
+	fn new(context: &BatlContext, repository: Repository, pin: Option<Pin>, registry: Option<String>) -> Self {
+		let fetched = context.system().version_root()
+			.is_some_and(|root| repository.path().starts_with(root));
+
+		let local = context.system().repository_root()
+			.map(|root| root.join(PathBuf::from(repository.name())))
+			.is_some_and(|path| path == repository.path());
+
+		let location = if fetched {
+			Location::Fetched
+		} else if local {
+			Location::Local
+		} else {
+			Location::External
+		};
+
+		Self {
+			repository,
+			provenance: Provenance { location, pin, registry }
+		}
+	}
+}
+
+impl Pin {
+	#[inline]
+	#[must_use]
+	pub const fn new(kind: PinKind, version: Version, freeze: Option<String>) -> Self {
+		Self { kind, version, freeze }
+	}
+}
@@ -1,23 +1,50 @@
 use core::convert::Infallible;
 use core::fmt::{Display, Formatter};
 use core::str::FromStr;
+use regex::Regex;
+use schemars::{json_schema, JsonSchema, Schema, SchemaGenerator};
 use serde::de::{self, Deserialize, Deserializer, Visitor};
 use serde::ser::{self, Serialize};
+use std::ffi::OsStr;
 use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+use unicode_normalization::UnicodeNormalization as _;
+use crate::error::PathNameError;
 
 pub mod archive;
 pub mod batlrc;
+pub mod freeze;
+pub mod kind;
+pub mod license;
+pub mod ownership;
 pub mod repository;
 pub mod restrict;
+pub mod script;
+pub mod summary;
+pub mod template;
 pub mod tomlconfig;
+pub mod transaction;
 pub mod workspace;
 
 pub use self::archive::Archive;
 pub use self::batlrc::BatlRcLatest as BatlRc;
+pub use self::freeze::Freeze;
+pub use self::kind::Kind as RepositoryKind;
+pub use self::ownership::Ownership;
 pub use self::repository::Repository;
+pub use self::script::Script;
+pub use self::summary::Summary as RepositorySummary;
+pub use self::template::Template;
+pub use self::transaction::Transaction;
 pub use self::workspace::Workspace;
 
 
+/// The names that can be given to repositories and
+/// workspaces, two or more lowercase parts separated by `/`
+#[allow(clippy::unwrap_used, reason = "the pattern is fixed and valid")]
+pub static BATL_NAME_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^[a-z][a-z0-9\-_]*(/[a-z][a-z0-9\-_]*)+$").unwrap());
+
+
 pub trait Resource {
 	type Config;
 
@@ -33,18 +60,142 @@ pub trait Resource {
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Name(Vec<String>);
 
+/// How the case of resource names is treated
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum NameCase {
+	/// Names keep the case they were written in
+	#[default]
+	Preserve,
+
+	/// Names are folded to lowercase
+	Fold
+}
+
 impl Name {
-	/// Create a new battalion resource name
+	/// Create a new battalion resource name. Components
+	/// are normalized to unicode NFC, so names that look
+	/// the same are the same.
 	fn new(components: Vec<String>) -> Self {
-		Self(components)
+		Self(components.into_iter().map(|part| part.nfc().collect()).collect())
+	}
+
+	/// Apply the case policy to the name
+	#[inline]
+	#[must_use]
+	pub fn normalized(self, case: NameCase) -> Self {
+		match case {
+			NameCase::Preserve => self,
+			NameCase::Fold => Self(self.0.iter().map(|part| part.to_lowercase()).collect())
+		}
+	}
+
+	/// Whether two different names would end up in the
+	/// same folder on a case-insensitive filesystem
+	#[inline]
+	#[must_use]
+	pub fn collides_with(&self, other: &Self) -> bool {
+		self != other && self.clone().normalized(NameCase::Fold) == other.clone().normalized(NameCase::Fold)
 	}
 
 	/// Get the path components of a name
 	const fn components(&self) -> &Vec<String> {
 		&self.0
 	}
+
+	/// Get the name of a resource from its path relative
+	/// to a resource root, the inverse of converting a
+	/// name to a path
+	/// 
+	/// # Errors
+	/// 
+	/// Returns `Undecodable` if a component of the path is
+	/// not valid UTF-8, or `Layout` if the path does not
+	/// follow the resource layout
+	#[inline]
+	pub fn from_relative_path(path: &Path) -> Result<Self, PathNameError> {
+		let layout_error = || PathNameError::Layout(path.display().to_string());
+
+		let mut parts = path.iter()
+			.map(component)
+			.collect::<Result<Vec<_>, _>>()?;
+
+		let last = parts.pop().ok_or_else(layout_error)?;
+
+		if last.starts_with('@') {
+			return Err(layout_error());
+		}
+
+		let mut components = parts.into_iter()
+			.map(|part| part.strip_prefix('@').map(ToOwned::to_owned))
+			.collect::<Option<Vec<_>>>()
+			.ok_or_else(layout_error)?;
+
+		components.push(last.to_owned());
+
+		Ok(Self::new(components))
+	}
+
+	/// Get the name of a resource from its path, if it is
+	/// stored under the given resource root. Returns `None`
+	/// for resources stored elsewhere.
+	/// 
+	/// # Errors
+	/// 
+	/// Returns any errors from decoding the path relative
+	/// to the root
+	#[inline]
+	pub fn from_path_in(root: &Path, path: &Path) -> Result<Option<Self>, PathNameError> {
+		let canonical_root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+		let canonical_path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+
+		canonical_path.strip_prefix(&canonical_root)
+			.ok()
+			.map(Self::from_relative_path)
+			.transpose()
+	}
+}
+
+/// Decode a single path component of a resource name
+fn component(part: &OsStr) -> Result<&str, PathNameError> {
+	part.to_str()
+		.ok_or_else(|| PathNameError::Undecodable(part.to_string_lossy().into_owned()))
+}
+
+
+/// A resource name pinned to an exact version, written
+/// as `name@version`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct VersionedName {
+	pub name: Name,
+	pub version: semver::Version
+}
+
+impl FromStr for VersionedName {
+	type Err = String;
+
+	#[inline]
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let (name, version_str) = s.rsplit_once('@')
+			.ok_or_else(|| format!("Expected name@version, got {s}"))?;
+
+		let version = semver::Version::parse(version_str)
+			.map_err(|err| format!("Invalid version {version_str}: {err}"))?;
+
+		Ok(Self {
+			name: Name::from(name),
+			version
+		})
+	}
 }
 
+impl Display for VersionedName {
+	#[inline]
+	fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+		write!(f, "{}@{}", self.name, self.version)
+	}
+}
 
 impl From<&Name> for PathBuf {
 	#[inline]
@@ -68,25 +219,6 @@ impl From<&Name> for PathBuf {
 	}
 }
 
-impl From<&Path> for Name {
-	#[inline]
-	fn from(path: &Path) -> Self {
-		let mut value = path.iter();
-
-		let mut parts = vec![value.next().unwrap_or_default().to_string_lossy().to_string()];
-
-		for val in value {
-			let val_string = val.to_string_lossy().to_string();
-
-			if val_string.starts_with('@') {
-				parts.push(val_string.get(1..).unwrap_or_default().to_owned());
-			}
-		}
-
-		Self::new(parts)
-	}
-}
-
 impl FromStr for Name {
 	type Err = Infallible;
 
@@ -160,6 +292,56 @@ impl Serialize for Name {
 	}
 }
 
+#[allow(clippy::missing_trait_methods)]
+impl JsonSchema for Name {
+	#[inline]
+	#[allow(clippy::std_instead_of_alloc)]
+	fn schema_name() -> std::borrow::Cow<'static, str> {
+		"Name".into()
+	}
+
+	#[inline]
+	fn json_schema(_generator: &mut SchemaGenerator) -> Schema {
+		json_schema!({
+			"description": "A resource name, with parts separated by /",
+			"type": "string",
+			"minLength": 1
+		})
+	}
+}
+
+/// The case-insensitive form of a path component
+fn collision_key(part: &OsStr) -> String {
+	part.to_string_lossy().nfc().collect::<String>().to_lowercase()
+}
+
+/// Checks that creating a resource with the given name
+/// under the root will not collide with an existing
+/// resource whose name only differs by case.
+/// 
+/// # Errors
+/// 
+/// Returns the colliding path, if there is one
+pub(crate) fn check_collision(root: &Path, name: &Name) -> Result<(), crate::error::CreateResourceError> {
+	let mut dir = root.to_path_buf();
+
+	for part in &PathBuf::from(name) {
+		let wanted = collision_key(part);
+
+		for entry in std::fs::read_dir(&dir).into_iter().flatten().flatten() {
+			let existing = entry.file_name();
+
+			if existing != part && collision_key(&existing) == wanted {
+				return Err(crate::error::CreateResourceError::Collision(entry.path().display().to_string()));
+			}
+		}
+
+		dir.push(part);
+	}
+
+	Ok(())
+}
+
 /// Creates a symlink directory, OS independent
 /// 
 /// # Errors
@@ -1,7 +1,38 @@
+use core::time::Duration;
+use crate::context::{Output, ProgressEvent};
 use crate::error as batlerror;
+use crate::preflight;
+use crate::units::ByteSize;
+use flate2::Compression as GzipLevel;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
+use std::io::{BufRead as _, BufReader, Read, Seek as _, Write};
 use std::path::{Path, PathBuf};
-use super::Name;
+use std::time::SystemTime;
+use super::{Name, Transaction};
+
+
+/// Where the manifest is stored inside of a delta archive
+pub const MANIFEST_PATH: &str = ".batl/manifest.json";
+
+/// Length of the file hashes in a manifest, in bytes
+const HASH_LENGTH: usize = 32;
+
+/// The first bytes of a gzip stream
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// The first bytes of a zstd frame
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// How many bytes are read to tell how a file is
+/// compressed
+const MAGIC_LENGTH: u64 = 4;
+
+
+/// Bytes a stream moves between progress reports
+const PROGRESS_STEP: u64 = 1024 * 1024;
 
 
 pub struct Archive {
@@ -9,7 +40,49 @@ pub struct Archive {
 	pub(crate) tar: tar::Archive<File>,
 
 	/// The path of the tar file
-	pub(crate) path: PathBuf
+	pub(crate) path: PathBuf,
+
+	/// The compressed copy of the tar file that is uploaded
+	/// in its place, if there is one
+	pub(crate) compressed: Option<PathBuf>
+}
+
+/// How the archive uploaded when publishing is compressed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Compression {
+	/// A plain tar file
+	#[default]
+	None,
+
+	/// Compressed with gzip
+	Gzip,
+
+	/// Compressed with zstd
+	Zstd
+}
+
+/// Options for generating the archive of a repository
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct BuildOptions {
+	/// How to compress the archive that is uploaded
+	pub compression: Compression,
+
+	/// Only re-archive files that changed since the last
+	/// archive, reusing the rest from it
+	pub incremental: bool
+}
+
+impl BuildOptions {
+	#[inline]
+	#[must_use]
+	pub const fn new(compression: Compression, incremental: bool) -> Self {
+		Self {
+			compression,
+			incremental
+		}
+	}
 }
 
 impl Archive {
@@ -21,7 +94,19 @@ impl Archive {
 	/// Also returns None if the resource does not exist
 	#[inline]
 	pub fn load(name: &Name) -> Result<Option<Self>, batlerror::GeneralResourceError> {
-		let tar_path = crate::system::archive_root().map(|p| p
+		Self::load_in(&crate::system::Context::detect(), name)
+	}
+
+	/// Load the archive with the supplied name from the
+	/// battalion root of a context
+	/// 
+	/// # Errors
+	/// 
+	/// Returns any errors that come up while getting the resource.
+	/// Also returns None if the resource does not exist
+	#[inline]
+	pub fn load_in(system: &crate::system::Context, name: &Name) -> Result<Option<Self>, batlerror::GeneralResourceError> {
+		let tar_path = system.archive_root().map(|p| p
 			.join("repositories")
 			.join(format!("{name}.tar"))
 		);
@@ -30,10 +115,14 @@ impl Archive {
 			let file = File::open(&tar_path)?;
 			let archive = tar::Archive::new(file);
 
+			let compressed = [Compression::Gzip, Compression::Zstd].into_iter()
+				.map(|compression| tar_path.with_extension(compression.extension()))
+				.find(|path| path.exists());
 
 			Ok(Some(Self {
 				path: tar_path,
-				tar: archive
+				tar: archive,
+				compressed
 			}))
 		} else {
 			Ok(None)
@@ -54,4 +143,1108 @@ impl Archive {
 	pub fn to_file(self) -> File {
 		self.tar.into_inner()
 	}
+
+	/// The file uploaded when publishing, which is the
+	/// compressed copy if there is one and the tar file
+	/// otherwise
+	#[inline]
+	#[must_use]
+	pub fn published_path(&self) -> &Path {
+		self.compressed.as_deref().unwrap_or(&self.path)
+	}
+
+	/// How the file uploaded when publishing is compressed
+	#[inline]
+	#[must_use]
+	pub fn compression(&self) -> Compression {
+		self.compressed.as_deref()
+			.and_then(|path| Compression::detect(path).ok())
+			.unwrap_or_default()
+	}
+
+	/// Read the file uploaded when publishing as a stream,
+	/// sized from the file, to upload it without loading it
+	/// whole
+	///
+	/// # Errors
+	///
+	/// Propogates any IO errors while opening the file
+	#[inline]
+	pub fn stream(&self) -> Result<Stream<'static, File>, std::io::Error> {
+		let file = File::open(self.published_path())?;
+		let size = file.metadata()?.len();
+
+		Ok(Stream::new(file, Some(size)))
+	}
+
+	/// Write a downloading tar stream to a path as it is
+	/// read, then open it as an archive. The tar file is
+	/// written beside the path and moved over it once
+	/// complete, so a failed download leaves nothing behind.
+	///
+	/// # Errors
+	///
+	/// Propogates any IO errors while reading the stream or
+	/// writing the file
+	#[inline]
+	pub fn receive<R: Read>(mut source: Stream<'_, R>, path: PathBuf) -> Result<Self, std::io::Error> {
+		let partial = path.with_extension("tar.partial");
+
+		if let Err(err) = write_all_to(&mut source, &partial) {
+			drop(std::fs::remove_file(&partial));
+
+			return Err(err);
+		}
+
+		std::fs::rename(&partial, &path)?;
+
+		Ok(Self {
+			tar: tar::Archive::new(File::open(&path)?),
+			path,
+			compressed: None
+		})
+	}
+
+	/// Archive files of a directory into the tar file at a
+	/// path, then compress a copy of it to upload if asked.
+	/// Incremental builds keep the stamps of every file
+	/// next to the tar file, and copy files whose size and
+	/// modification time or hash haven't changed from the
+	/// previous tar file instead of reading them again.
+	///
+	/// # Errors
+	///
+	/// Propogates any IO errors while reading the files or
+	/// writing the archive
+	pub(crate) fn build(directory: &Path, files: &[(String, u64)], tar_path: PathBuf, options: BuildOptions) -> Result<Self, std::io::Error> {
+		let stamps_path = tar_path.with_extension("files.json");
+
+		let previous = if options.incremental && tar_path.exists() {
+			Stamps::load(&stamps_path)
+		} else {
+			Stamps::default()
+		};
+
+		let mut stamps = Stamps::default();
+
+		if options.incremental {
+			for file in files {
+				stamps.files.insert(file.0.clone(), previous.restamp(&file.0, &directory.join(&file.0))?);
+			}
+		}
+
+		let reused = stamps.files.iter()
+			.filter(|stamp| previous.files.get(stamp.0).is_some_and(|old| old.hash == stamp.1.hash))
+			.map(|stamp| stamp.0.clone())
+			.collect::<HashSet<_>>();
+
+		let rebuild = !options.incremental
+			|| !tar_path.exists()
+			|| reused.len() != files.len()
+			|| reused.len() != previous.files.len();
+
+		if rebuild {
+			let partial = tar_path.with_extension("tar.partial");
+
+			if let Err(err) = write_tar(directory, files, &tar_path, &reused, &partial) {
+				drop(std::fs::remove_file(&partial));
+
+				return Err(err);
+			}
+
+			std::fs::rename(&partial, &tar_path)?;
+		}
+
+		if options.incremental {
+			stamps.save(&stamps_path)?;
+		} else {
+			// Stamps left by an incremental archive no longer
+			// describe the tar file
+			match std::fs::remove_file(&stamps_path) {
+				Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err),
+				Ok(()) | Err(_) => ()
+			}
+		}
+
+		let compressed = options.compression.compress_beside(&tar_path, rebuild)?;
+
+		Ok(Self {
+			tar: tar::Archive::new(File::open(&tar_path)?),
+			path: tar_path,
+			compressed
+		})
+	}
+
+	/// Hash every file in the archive
+	/// 
+	/// # Errors
+	/// 
+	/// Propogates any IO errors while reading the archive
+	#[inline]
+	pub fn manifest(&self) -> Result<Manifest, std::io::Error> {
+		Manifest::of(&self.path)
+	}
+
+	/// List the files of the archive and estimate its
+	/// compressed size
+	/// 
+	/// # Errors
+	/// 
+	/// Propogates any IO errors while reading the archive
+	#[inline]
+	pub fn report(&self) -> Result<Report, std::io::Error> {
+		let mut tar = tar::Archive::new(File::open(&self.path)?);
+		let mut files = Vec::new();
+
+		for entry in tar.entries()? {
+			let file = entry?;
+
+			if file.header().entry_type().is_file() {
+				files.push((entry_path(&file)?, file.size()));
+			}
+		}
+
+		files.sort();
+
+		let mut encoder = GzEncoder::new(ByteCounter::default(), GzipLevel::default());
+		std::io::copy(&mut File::open(&self.path)?, &mut encoder)?;
+
+		Ok(Report { files, compressed: encoder.finish()?.count })
+	}
+
+	/// Build an archive with only the given files, along
+	/// with the manifest of the full archive so it can be
+	/// reassembled. The delta is written next to this
+	/// archive.
+	/// 
+	/// # Errors
+	/// 
+	/// Propogates any IO errors while reading this archive
+	/// or writing the delta
+	#[inline]
+	pub fn delta(&self, changed: &[String], manifest: &Manifest) -> Result<Self, std::io::Error> {
+		let delta_path = self.path.with_extension("delta.tar");
+		let wanted = changed.iter().collect::<HashSet<_>>();
+
+		// Removes the delta again unless it is complete
+		let mut transaction = Transaction::default();
+		transaction.snapshot(&delta_path)?;
+
+		let mut source = tar::Archive::new(File::open(&self.path)?);
+		let mut builder = tar::Builder::new(File::create(&delta_path)?);
+
+		let manifest_json = serde_json::to_vec(manifest)
+			.map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+
+		let mut manifest_header = tar::Header::new_gnu();
+		manifest_header.set_size(manifest_json.len().try_into().unwrap_or(u64::MAX));
+		manifest_header.set_mode(0o644);
+		manifest_header.set_cksum();
+
+		builder.append_data(&mut manifest_header, MANIFEST_PATH, manifest_json.as_slice())?;
+
+		for entry in source.entries()? {
+			let mut file = entry?;
+			let path = entry_path(&file)?;
+
+			if wanted.contains(&path) {
+				let header = file.header().clone();
+				builder.append(&header, &mut file)?;
+			}
+		}
+
+		builder.finish()?;
+
+		let tar = tar::Archive::new(File::open(&delta_path)?);
+		transaction.commit();
+
+		Ok(Self {
+			tar,
+			path: delta_path,
+			compressed: None
+		})
+	}
+}
+
+/// An archive being read or written as it moves, such as
+/// while it uploads or downloads, reporting how far along
+/// it is
+#[non_exhaustive]
+pub struct Stream<'progress, T> {
+	/// The reader or writer streamed through
+	inner: T,
+
+	/// How many bytes are expected, if known
+	size: Option<u64>,
+
+	/// Bytes moved so far
+	transferred: u64,
+
+	/// Where progress is reported, if anywhere
+	reporter: Option<Reporter<'progress>>
+}
+
+/// Reports a stream as a phase of an operation, through the
+/// output of the context it moves in
+struct Reporter<'progress> {
+	/// The output of the context
+	output: &'progress dyn Output,
+
+	/// The operation the stream is part of
+	operation: &'progress str,
+
+	/// The phase of the operation the stream is
+	phase: &'progress str,
+
+	/// The percents of the operation the phase goes from and
+	/// to
+	span: (u8, u8),
+
+	/// Bytes moved at the last report
+	reported: u64
+}
+
+/// What an archive holds, for looking over before it is
+/// published
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Report {
+	/// Every file with its size in bytes, sorted by path
+	pub files: Vec<(String, u64)>,
+
+	/// The size of the archive once compressed with gzip
+	pub compressed: u64
+}
+
+/// Counts the bytes written to it, discarding them
+#[derive(Default)]
+struct ByteCounter {
+	/// Bytes written so far
+	count: u64
+}
+
+/// The size, modification time and hash of every file in
+/// the last incremental archive of a directory
+#[derive(Serialize, Deserialize, Default)]
+struct Stamps {
+	/// Stamps keyed by path within the archive
+	files: HashMap<String, Stamp>
+}
+
+/// What a file was like when it was last archived
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+struct Stamp {
+	/// Size in bytes
+	size: u64,
+
+	/// Modification time, since the unix epoch
+	modified: Duration,
+
+	/// Hex encoded hash of the contents
+	hash: String
+}
+
+/// The hashes of every file in an archive, used to only
+/// upload what changed since the previous version
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Manifest {
+	/// Hex encoded hashes, keyed by path within the archive
+	pub files: HashMap<String, String>,
+
+	/// Where the contents of each file sit in the archive,
+	/// so single files can be read with ranged requests.
+	/// Older manifests don't have them.
+	#[serde(default, skip_serializing_if = "HashMap::is_empty")]
+	pub spans: HashMap<String, Span>,
+
+	/// Hex encoded hashes of the targets of symlinks, keyed
+	/// by path within the archive. Older manifests don't
+	/// have them.
+	#[serde(default, skip_serializing_if = "HashMap::is_empty")]
+	pub links: HashMap<String, String>
+}
+
+/// A local file that unpacking an archive over its
+/// directory would change
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Conflict {
+	/// The path of the file, relative to the directory
+	pub path: String,
+	pub kind: ConflictKind
+}
+
+/// How a local file differs from an archive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ConflictKind {
+	/// The file is in the archive with other contents
+	Modified,
+
+	/// The file is not in the archive at all
+	Added
+}
+
+/// A range of bytes within an archive
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Span {
+	/// Offset of the first byte
+	pub offset: u64,
+
+	/// Number of bytes
+	pub size: u64
+}
+
+impl Compression {
+	/// The extension of tar files compressed this way
+	#[inline]
+	#[must_use]
+	pub const fn extension(self) -> &'static str {
+		match self {
+			Self::None => "tar",
+			Self::Gzip => "tar.gz",
+			Self::Zstd => "tar.zst"
+		}
+	}
+
+	/// Tell how the file at a path is compressed from its
+	/// first bytes
+	///
+	/// # Errors
+	///
+	/// Propogates any IO errors while reading the file
+	#[inline]
+	pub fn detect(path: &Path) -> Result<Self, std::io::Error> {
+		let mut start = Vec::new();
+		File::open(path)?.take(MAGIC_LENGTH).read_to_end(&mut start)?;
+
+		Ok(Self::from_magic(&start))
+	}
+
+	/// Tell how a stream is compressed from its first bytes
+	fn from_magic(start: &[u8]) -> Self {
+		if start.starts_with(&GZIP_MAGIC) {
+			Self::Gzip
+		} else if start.starts_with(&ZSTD_MAGIC) {
+			Self::Zstd
+		} else {
+			Self::None
+		}
+	}
+
+	/// Keep a copy of a tar file compressed this way next
+	/// to it, removing copies compressed any other way. The
+	/// copy is only written again when the tar file was
+	/// rebuilt or it is missing.
+	fn compress_beside(self, tar_path: &Path, rebuilt: bool) -> Result<Option<PathBuf>, std::io::Error> {
+		for other in [Self::Gzip, Self::Zstd] {
+			let other_path = tar_path.with_extension(other.extension());
+
+			if other != self && other_path.exists() {
+				std::fs::remove_file(other_path)?;
+			}
+		}
+
+		if self == Self::None {
+			return Ok(None);
+		}
+
+		let target = tar_path.with_extension(self.extension());
+
+		if rebuilt || !target.exists() {
+			let partial = tar_path.with_extension(format!("{}.partial", self.extension()));
+
+			if let Err(err) = self.compress(tar_path, &partial) {
+				drop(std::fs::remove_file(&partial));
+
+				return Err(err);
+			}
+
+			std::fs::rename(&partial, &target)?;
+		}
+
+		Ok(Some(target))
+	}
+
+	/// Write a copy of a file compressed this way
+	fn compress(self, source: &Path, target: &Path) -> Result<(), std::io::Error> {
+		let mut input = File::open(source)?;
+		let output = File::create(target)?;
+
+		let written = match self {
+			Self::None => {
+				let mut plain = output;
+				std::io::copy(&mut input, &mut plain)?;
+
+				plain
+			},
+			Self::Gzip => {
+				let mut encoder = GzEncoder::new(output, GzipLevel::default());
+				std::io::copy(&mut input, &mut encoder)?;
+
+				encoder.finish()?
+			},
+			Self::Zstd => {
+				let mut encoder = zstd::Encoder::new(output, 0i32)?;
+				std::io::copy(&mut input, &mut encoder)?;
+
+				encoder.finish()?
+			}
+		};
+
+		written.sync_all()
+	}
+}
+
+impl Stamps {
+	/// Read the stamps at a path, starting over when they
+	/// are missing or unreadable
+	fn load(path: &Path) -> Self {
+		std::fs::read(path).ok()
+			.and_then(|contents| serde_json::from_slice(&contents).ok())
+			.unwrap_or_default()
+	}
+
+	/// Write the stamps to a path
+	fn save(&self, path: &Path) -> Result<(), std::io::Error> {
+		let contents = serde_json::to_vec(self)
+			.map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+
+		std::fs::write(path, contents)
+	}
+
+	/// Stamp a file, only hashing it again when its size or
+	/// modification time changed since it was stamped last
+	fn restamp(&self, file: &str, path: &Path) -> Result<Stamp, std::io::Error> {
+		let metadata = std::fs::metadata(path)?;
+		let modified = metadata.modified()?
+			.duration_since(SystemTime::UNIX_EPOCH)
+			.unwrap_or_default();
+
+		if let Some(stamp) = self.files.get(file).filter(|stamp| stamp.size == metadata.len() && stamp.modified == modified) {
+			return Ok(stamp.clone());
+		}
+
+		Ok(Stamp {
+			size: metadata.len(),
+			modified,
+			hash: hash_file(path)?
+		})
+	}
+}
+
+impl Report {
+	/// Report on files of a directory before they are
+	/// archived, estimating the compressed size by
+	/// archiving them without keeping the result
+	/// 
+	/// # Errors
+	/// 
+	/// Propogates any IO errors while reading the files
+	#[inline]
+	pub fn of_files(directory: &Path, files: Vec<(String, u64)>) -> Result<Self, std::io::Error> {
+		let mut builder = tar::Builder::new(GzEncoder::new(ByteCounter::default(), GzipLevel::default()));
+
+		for file in &files {
+			builder.append_path_with_name(directory.join(&file.0), &file.0)?;
+		}
+
+		let compressed = builder.into_inner()?.finish()?.count;
+
+		Ok(Self { files, compressed })
+	}
+
+	/// The total size of the files in bytes
+	#[inline]
+	#[must_use]
+	pub fn size(&self) -> u64 {
+		self.files.iter().map(|file| file.1).sum()
+	}
+}
+
+impl<'progress, T> Stream<'progress, T> {
+	/// Stream through a reader or writer, expecting the given
+	/// number of bytes if known
+	#[inline]
+	#[must_use]
+	pub const fn new(inner: T, size: Option<u64>) -> Self {
+		Self { inner, size, transferred: 0, reporter: None }
+	}
+
+	/// Report progress to the output of a context as the
+	/// stream moves, as a phase of an operation going
+	/// between two percents of it
+	#[inline]
+	#[must_use]
+	pub fn report_to(mut self, output: &'progress dyn Output, operation: &'progress str, phase: &'progress str, span: (u8, u8)) -> Self {
+		self.reporter = Some(Reporter { output, operation, phase, span, reported: 0 });
+
+		self
+	}
+
+	/// How many bytes the stream is expected to move, if
+	/// known
+	#[inline]
+	#[must_use]
+	pub const fn size_hint(&self) -> Option<u64> {
+		self.size
+	}
+
+	/// How many bytes moved so far
+	#[inline]
+	#[must_use]
+	pub const fn transferred(&self) -> u64 {
+		self.transferred
+	}
+
+	#[inline]
+	pub fn into_inner(self) -> T {
+		self.inner
+	}
+
+	/// Count bytes that moved and report them
+	fn advance(&mut self, count: usize) {
+		self.transferred = self.transferred.saturating_add(count.try_into().unwrap_or(u64::MAX));
+
+		let (moved, size) = (self.transferred, self.size);
+
+		if let Some(reporter) = self.reporter.as_mut() {
+			reporter.moved(moved, size);
+		}
+	}
+}
+
+impl Reporter<'_> {
+	/// Report how many bytes moved, every so often and once
+	/// all of them did
+	fn moved(&mut self, moved: u64, size: Option<u64>) {
+		if moved < self.reported.saturating_add(PROGRESS_STEP) && size.is_none_or(|total| moved < total) {
+			return;
+		}
+
+		self.reported = moved;
+
+		let (from, to) = (u128::from(self.span.0), u128::from(self.span.1));
+
+		let percent = size
+			.and_then(|total| to.saturating_sub(from).checked_mul(u128::from(moved.min(total)))?.checked_div(u128::from(total)))
+			.and_then(|covered| u32::try_from(from.saturating_add(covered)).ok())
+			.map(f64::from);
+
+		let message = format!("{}B", ByteSize(moved));
+
+		self.output.progress(&ProgressEvent::new(self.operation, self.phase, percent, &message));
+	}
+}
+
+#[allow(clippy::missing_trait_methods, reason = "the provided methods all read through read")]
+impl<T: Read> Read for Stream<'_, T> {
+	#[inline]
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		let count = self.inner.read(buf)?;
+		self.advance(count);
+
+		Ok(count)
+	}
+}
+
+#[allow(clippy::missing_trait_methods, reason = "the provided methods all write through write")]
+impl<T: Write> Write for Stream<'_, T> {
+	#[inline]
+	fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+		let count = self.inner.write(buf)?;
+		self.advance(count);
+
+		Ok(count)
+	}
+
+	#[inline]
+	fn flush(&mut self) -> std::io::Result<()> {
+		self.inner.flush()
+	}
+}
+
+#[allow(clippy::missing_trait_methods, reason = "the provided methods all write through write")]
+impl std::io::Write for ByteCounter {
+	#[inline]
+	fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+		self.count = self.count.saturating_add(buf.len().try_into().unwrap_or(u64::MAX));
+
+		Ok(buf.len())
+	}
+
+	#[inline]
+	fn flush(&mut self) -> std::io::Result<()> {
+		Ok(())
+	}
+}
+
+impl Manifest {
+	/// Hash every file in the tar file at a path, recording
+	/// where each of them is. Compressed archives can't be
+	/// read in ranges, so their files aren't given spans.
+	/// 
+	/// # Errors
+	/// 
+	/// Propogates any IO errors while reading the archive
+	#[inline]
+	pub fn of(path: &Path) -> Result<Self, std::io::Error> {
+		let compressed = Compression::detect(path)? != Compression::None;
+		let mut tar = tar::Archive::new(open(path)?);
+		let mut files = HashMap::new();
+		let mut spans = HashMap::new();
+		let mut links = HashMap::new();
+
+		for entry in tar.entries()? {
+			let mut file = entry?;
+
+			// A symlink changes with its target
+			if file.header().entry_type().is_symlink() {
+				let target = file.link_name()?.unwrap_or_default();
+				links.insert(entry_path(&file)?, hash(target.to_string_lossy().as_bytes()));
+
+				continue;
+			}
+
+			if !file.header().entry_type().is_file() {
+				continue;
+			}
+
+			let entry_name = entry_path(&file)?;
+
+			let span = Span {
+				offset: file.raw_file_position(),
+				size: file.size()
+			};
+
+			let mut contents = Vec::new();
+			file.read_to_end(&mut contents)?;
+
+			files.insert(entry_name.clone(), hash(&contents));
+
+			if !compressed {
+				spans.insert(entry_name, span);
+			}
+		}
+
+		Ok(Self { files, spans, links })
+	}
+
+	/// Files under any of the given directories or files,
+	/// sorted by path
+	#[inline]
+	#[must_use]
+	pub fn select(&self, paths: &[PathBuf]) -> Vec<String> {
+		let mut selected = self.files.keys()
+			.filter(|file| is_selected(file, paths))
+			.cloned()
+			.collect::<Vec<_>>();
+
+		selected.sort();
+
+		selected
+	}
+
+	/// Check that every path of the manifest stays inside
+	/// of the repository it describes. Manifests come from
+	/// the registry, so nothing is fetched by their paths
+	/// before this.
+	///
+	/// # Errors
+	///
+	/// Returns `InvalidData` naming the first path that
+	/// leaves the repository
+	#[inline]
+	pub fn check_paths(&self) -> Result<(), std::io::Error> {
+		let mut paths = self.files.keys()
+			.chain(self.spans.keys())
+			.chain(self.links.keys());
+
+		paths.find(|path| !is_contained(path)).map_or(Ok(()), |path| Err(std::io::Error::new(
+			std::io::ErrorKind::InvalidData,
+			format!("Manifest path leaves the repository: {path}")
+		)))
+	}
+
+	/// Whether the contents of a file match its hash
+	#[inline]
+	#[must_use]
+	pub fn verify(&self, file: &str, contents: &[u8]) -> bool {
+		self.files.get(file).is_some_and(|file_hash| *file_hash == hash(contents))
+	}
+
+	/// Files and symlinks that are new or different from a
+	/// previous manifest, sorted by path
+	#[inline]
+	#[must_use]
+	pub fn changed_since(&self, previous: &Self) -> Vec<String> {
+		let files = self.files.iter()
+			.filter(|&(path, file_hash)| previous.files.get(path) != Some(file_hash));
+
+		let links = self.links.iter()
+			.filter(|&(path, target_hash)| previous.links.get(path) != Some(target_hash));
+
+		let mut changed = files.chain(links)
+			.map(|(path, _)| path.clone())
+			.collect::<Vec<_>>();
+
+		changed.sort();
+
+		changed
+	}
+
+	/// Local changes in a directory that unpacking the
+	/// archive over it would clobber or mix in, sorted by
+	/// path
+	///
+	/// Every file of the manifest is checked against its
+	/// local copy, hidden or not. Files only found locally
+	/// leave out ignored files, along with `.git`, `.batl`
+	/// and `gen`, which battalion and git keep to
+	/// themselves.
+	///
+	/// # Errors
+	///
+	/// Propogates any IO errors while walking or reading
+	#[inline]
+	pub fn conflicts(&self, directory: &Path) -> Result<Vec<Conflict>, std::io::Error> {
+		let mut conflicts = Vec::new();
+
+		for file in self.files.keys() {
+			let local = directory.join(file);
+
+			if !local.symlink_metadata().is_ok_and(|metadata| metadata.is_file()) {
+				continue;
+			}
+
+			if self.files.get(file) != Some(&hash_file(&local)?) {
+				conflicts.push(Conflict { path: file.clone(), kind: ConflictKind::Modified });
+			}
+		}
+
+		let mut walk_builder = ignore::WalkBuilder::new(directory);
+		walk_builder.hidden(false)
+			.add_custom_ignore_filename("batl.ignore");
+
+		let top = directory.to_path_buf();
+		walk_builder.filter_entry(move |entry| {
+			entry.path().parent() != Some(top.as_path())
+				|| !matches!(entry.file_name().to_str(), Some(".git" | ".batl" | "gen"))
+		});
+
+		for result in walk_builder.build() {
+			let entry = result.map_err(|err| std::io::Error::other(err.to_string()))?;
+
+			if entry.path().is_dir() {
+				continue;
+			}
+
+			let Ok(relative) = entry.path().strip_prefix(directory) else {
+				continue;
+			};
+
+			let file = relative.components()
+				.map(|component| component.as_os_str().to_string_lossy())
+				.collect::<Vec<_>>()
+				.join("/");
+
+			if !self.files.contains_key(&file) {
+				conflicts.push(Conflict { path: file, kind: ConflictKind::Added });
+			}
+		}
+
+		conflicts.sort_by(|a, b| a.path.cmp(&b.path));
+
+		Ok(conflicts)
+	}
+
+	/// The number of files in the manifest
+	#[inline]
+	#[must_use]
+	pub fn len(&self) -> usize {
+		self.files.len()
+	}
+
+	/// Whether the manifest has no files
+	#[inline]
+	#[must_use]
+	pub fn is_empty(&self) -> bool {
+		self.files.is_empty()
+	}
+
+	/// The total size of the files, or `None` for older
+	/// manifests that don't record where files are
+	#[inline]
+	#[must_use]
+	pub fn size(&self) -> Option<u64> {
+		if self.spans.len() < self.files.len() {
+			return None;
+		}
+
+		Some(self.spans.values().map(|span| span.size).sum())
+	}
+
+	/// Check that the files fit in a directory, both in
+	/// free space and in path length
+	///
+	/// # Errors
+	///
+	/// Returns the first check that fails
+	#[inline]
+	pub fn preflight(&self, directory: &Path) -> Result<(), batlerror::PreflightError> {
+		preflight::check_path_lengths(directory, self.files.keys().map(String::as_str))?;
+
+		self.size().map_or(Ok(()), |size| preflight::check_space(directory, size))
+	}
+}
+
+/// Read a tar stream that may be compressed with gzip or
+/// zstd, telling which from its first bytes
+///
+/// # Errors
+///
+/// Propogates any IO errors while reading the first bytes
+#[inline]
+pub fn decode<'reader, R: Read + 'reader>(reader: R) -> Result<Box<dyn Read + 'reader>, std::io::Error> {
+	let mut buffered = BufReader::new(reader);
+	let compression = Compression::from_magic(buffered.fill_buf()?);
+
+	Ok(match compression {
+		Compression::None => Box::new(buffered),
+		Compression::Gzip => Box::new(flate2::bufread::GzDecoder::new(buffered)),
+		Compression::Zstd => Box::new(zstd::Decoder::with_buffer(buffered)?)
+	})
+}
+
+/// Open the tar file at a path, decompressing it if it is
+/// compressed
+///
+/// # Errors
+///
+/// Propogates any IO errors while opening the file
+#[inline]
+pub fn open(path: &Path) -> Result<Box<dyn Read>, std::io::Error> {
+	decode(File::open(path)?)
+}
+
+/// Read one file of the tar file at a path, or `None` if
+/// the archive doesn't have it
+/// 
+/// # Errors
+/// 
+/// Propogates any IO errors while reading the archive
+#[inline]
+pub fn read_file(path: &Path, file: &str) -> Result<Option<Vec<u8>>, std::io::Error> {
+	let mut tar = tar::Archive::new(open(path)?);
+
+	for entry in tar.entries()? {
+		let mut found = entry?;
+
+		// Archives made by hand may prefix their paths with ./
+		let found_path = found.path()?
+			.components()
+			.filter(|component| *component != std::path::Component::CurDir)
+			.collect::<PathBuf>();
+
+		if found.header().entry_type().is_file() && found_path == Path::new(file) {
+			let mut contents = Vec::new();
+			found.read_to_end(&mut contents)?;
+
+			return Ok(Some(contents));
+		}
+	}
+
+	Ok(None)
+}
+
+/// Unpack only the files of an archive under the given
+/// directories or files, and its `batl.toml`, as the
+/// archive is read. Returns the unpacked paths.
+/// 
+/// # Errors
+/// 
+/// Propogates any IO errors while reading the archive or
+/// writing files
+#[inline]
+pub fn unpack_selected<R: std::io::Read>(reader: R, destination: &Path, paths: &[PathBuf]) -> Result<Vec<String>, std::io::Error> {
+	let mut tar = tar::Archive::new(decode(reader)?);
+	let mut unpacked = Vec::new();
+
+	for entry in tar.entries()? {
+		let mut file = entry?;
+
+		if !file.header().entry_type().is_file() {
+			continue;
+		}
+
+		let path = file.path()?.to_string_lossy().into_owned();
+
+		if path == "batl.toml" || is_selected(&path, paths) {
+			// Also guards against paths leaving the destination
+			if file.unpack_in(destination)? {
+				unpacked.push(path);
+			}
+		}
+	}
+
+	Ok(unpacked)
+}
+
+/// Whether an archive path is one of the given files or
+/// inside one of the given directories
+fn is_selected(file: &str, paths: &[PathBuf]) -> bool {
+	let path = Path::new(file);
+
+	is_contained(file) && paths.iter().any(|selected| path.starts_with(selected))
+}
+
+/// Whether an archive path only names plain components,
+/// so joining it to a directory stays inside of it
+fn is_contained(file: &str) -> bool {
+	let path = Path::new(file);
+
+	!file.is_empty() && path.components().all(|component| matches!(component, std::path::Component::Normal(_)))
+}
+
+/// Get the path of an archive entry. Paths that are not
+/// valid UTF-8 can't be listed in a manifest, so they are
+/// rejected rather than mangled.
+fn entry_path<R: Read>(entry: &tar::Entry<'_, R>) -> Result<String, std::io::Error> {
+	let path = entry.path()?;
+
+	path.to_str()
+		.map(ToOwned::to_owned)
+		.ok_or_else(|| std::io::Error::new(
+			std::io::ErrorKind::InvalidData,
+			format!("Archive path is not valid UTF-8: {}", path.display())
+		))
+}
+
+/// Write files of a directory to a new tar file, copying
+/// those that are reused from the previous tar file rather
+/// than reading them again
+fn write_tar(directory: &Path, files: &[(String, u64)], previous: &Path, reused: &HashSet<String>, target: &Path) -> Result<(), std::io::Error> {
+	let mut positions = HashMap::new();
+
+	let mut source = if reused.is_empty() {
+		None
+	} else {
+		let mut tar = tar::Archive::new(File::open(previous)?);
+
+		for entry in tar.entries()? {
+			let file = entry?;
+			let path = entry_path(&file)?;
+
+			if file.header().entry_type().is_file() && reused.contains(&path) {
+				positions.insert(path, (file.header().clone(), file.raw_file_position()));
+			}
+		}
+
+		Some(File::open(previous)?)
+	};
+
+	let mut builder = tar::Builder::new(File::create(target)?);
+
+	for file in files {
+		if let Some((position, old)) = positions.get(&file.0).zip(source.as_mut()) {
+			let mut header = position.0.clone();
+			let size = header.size()?;
+
+			old.seek(std::io::SeekFrom::Start(position.1))?;
+			builder.append_data(&mut header, &file.0, old.take(size))?;
+		} else {
+			builder.append_path_with_name(directory.join(&file.0), &file.0)?;
+		}
+	}
+
+	builder.into_inner()?.sync_all()
+}
+
+/// Write everything a reader holds to a new file
+fn write_all_to<R: Read>(source: &mut R, path: &Path) -> Result<(), std::io::Error> {
+	let mut file = File::create(path)?;
+	std::io::copy(source, &mut file)?;
+
+	file.sync_all()
+}
+
+/// Hash the contents of a file for a manifest
+fn hash(contents: &[u8]) -> String {
+	blake2b_simd::Params::new()
+		.hash_length(HASH_LENGTH)
+		.hash(contents)
+		.to_hex()
+		.to_string()
+}
+
+/// Hash a file like `hash` does, without loading it whole
+fn hash_file(path: &Path) -> Result<String, std::io::Error> {
+	let mut state = blake2b_simd::Params::new()
+		.hash_length(HASH_LENGTH)
+		.to_state();
+
+	std::io::copy(&mut File::open(path)?, &mut state)?;
+
+	Ok(state.finalize().to_hex().to_string())
+}
+
+#[cfg(test)]
+#[allow(clippy::panic_in_result_fn, reason = "tests fail on their assertions, and on errors setting them up")]
+mod tests {
+	use super::Manifest;
+	use core::error::Error;
+	use std::path::PathBuf;
+
+
+	/// A manifest as a malicious registry could serve it,
+	/// with a path under `docs` that climbs out of it
+	const MALICIOUS: &str = r#"{
+	"files": {
+		"batl.toml": "00",
+		"docs/guide.md": "01",
+		"docs/../../../.bashrc": "02"
+	},
+	"spans": {
+		"docs/../../../.bashrc": { "offset": 0, "size": 2 }
+	}
+}"#;
+
+	#[test]
+	fn paths_leaving_the_repository_are_rejected() -> Result<(), Box<dyn Error>> {
+		let manifest: Manifest = serde_json::from_str(MALICIOUS)?;
+
+		assert!(manifest.check_paths().is_err());
+
+		Ok(())
+	}
+
+	#[test]
+	fn paths_leaving_the_repository_are_never_selected() -> Result<(), Box<dyn Error>> {
+		let manifest: Manifest = serde_json::from_str(MALICIOUS)?;
+
+		assert_eq!(manifest.select(&[PathBuf::from("docs")]), vec!["docs/guide.md".to_owned()]);
+
+		Ok(())
+	}
+
+	#[test]
+	fn absolute_paths_are_rejected() -> Result<(), Box<dyn Error>> {
+		let manifest: Manifest = serde_json::from_str(r#"{ "files": { "/etc/passwd": "00" } }"#)?;
+
+		assert!(manifest.check_paths().is_err());
+		assert!(manifest.select(&[PathBuf::from("/etc")]).is_empty());
+
+		Ok(())
+	}
+
+	#[test]
+	fn plain_paths_are_accepted() -> Result<(), Box<dyn Error>> {
+		let manifest: Manifest = serde_json::from_str(r#"{ "files": { "batl.toml": "00", "src/lib.rs": "01" } }"#)?;
+
+		manifest.check_paths()?;
+
+		Ok(())
+	}
 }
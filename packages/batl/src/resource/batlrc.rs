@@ -1,8 +1,295 @@
+use semver::Version;
 use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use crate::units::Duration;
+use super::{tomlconfig, Name, NameCase};
+
+
+/// The API key a new batlrc is written with, until one is
+/// set up
+const PLACEHOLDER_KEY: &str = "YOUR-KEY-GOES-HERE";
 
 
 pub type BatlRcLatest = BatlRc0_2_2;
-pub type BatlRc0_2_2 = BatlRc0_2_1;
+pub type HostLatest = Host0_2_2;
+
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
+#[allow(clippy::exhaustive_structs)]
+pub struct BatlRc0_2_2 {
+	pub api: Api0_2_2,
+	pub hosts: Option<HashMap<String, Host0_2_2>>,
+
+	/// Repositories that live outside of the battalion root
+	pub externals: Option<HashMap<Name, PathBuf>>,
+
+	/// Resource name policy
+	pub names: Option<Names0_2_2>,
+
+	/// How resource configs are read
+	pub config: Option<ConfigOptions0_2_2>,
+
+	/// Scripts available in every repository
+	pub scripts: Option<tomlconfig::Scripts0_2_2>,
+
+	/// Output colors
+	pub theme: Option<Theme0_2_2>,
+
+	/// What new repositories start with
+	pub init: Option<Init0_2_2>,
+
+	/// Named sets of repositories, which commands take as
+	/// `@group` in place of names
+	pub groups: Option<HashMap<String, Vec<Name>>>,
+
+	/// Named sets of settings, selected with `--profile` or
+	/// `BATL_PROFILE`
+	pub profile: Option<HashMap<String, Profile0_2_2>>,
+
+	/// Registries besides the one in `[api]`, which
+	/// dependencies select by name with `registry`
+	pub registries: Option<HashMap<String, Api0_2_2>>,
+
+	/// Recording who runs which scripts
+	pub audit_log: Option<AuditLog0_2_2>,
+
+	/// Telling the user when long script runs finish
+	pub notify: Option<Notify0_2_2>,
+
+	/// Signing published archives and checking fetched ones
+	pub signing: Option<Signing0_2_2>
+}
+
+/// Settings that replace the usual ones while a profile is
+/// selected, to keep environments such as work and
+/// personal apart
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
+#[allow(clippy::exhaustive_structs)]
+pub struct Profile0_2_2 {
+	/// The battalion root, relative to the root whose batlrc
+	/// defines the profile
+	pub root: Option<PathBuf>,
+
+	/// The registry and how to authenticate with it
+	pub api: Option<Api0_2_2>
+}
+
+/// The audit log, kept for shared roots where several
+/// users run scripts
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
+#[allow(clippy::exhaustive_structs)]
+pub struct AuditLog0_2_2 {
+	/// Record every script run in the audit log
+	pub enabled: Option<bool>
+}
+
+/// Where to send a notification when a script run
+/// finishes, for runs long enough to switch tasks during
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
+#[allow(clippy::exhaustive_structs)]
+pub struct Notify0_2_2 {
+	/// Only notify about runs that took at least this
+	/// long, such as `2m`
+	pub after: Option<Duration>,
+
+	/// Show a desktop notification
+	pub desktop: Option<bool>,
+
+	/// A url to post a json summary of the run to
+	pub webhook: Option<String>,
+
+	/// A shell command to run, given the run in
+	/// `BATL_NOTIFY_*` variables
+	pub command: Option<String>
+}
+
+/// Keys published archives are signed with and fetched
+/// ones are checked against
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
+#[allow(clippy::exhaustive_structs)]
+pub struct Signing0_2_2 {
+	/// A file holding the hex encoded ed25519 key archives
+	/// are signed with as they are published
+	pub key_file: Option<PathBuf>,
+
+	/// Hex encoded ed25519 public keys of trusted
+	/// publishers, by namespace such as `acme` or
+	/// `acme/tools`. Packages in a listed namespace must be
+	/// signed by one of its keys, so a namespace without
+	/// keys refuses every package. The most specific
+	/// namespace applies.
+	pub publishers: Option<HashMap<String, Vec<String>>>,
+
+	/// Refuse to fetch archives the registry has no
+	/// checksum for
+	pub require_checksums: Option<bool>
+}
+
+/// Contents of new repositories, besides their batl.toml
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
+#[allow(clippy::exhaustive_structs)]
+pub struct Init0_2_2 {
+	/// Starter files copied into every new repository, as
+	/// paths relative to `templates/starters`. Occurrences
+	/// of `{name}` in them are replaced by the repository
+	/// name.
+	pub files: Option<Vec<PathBuf>>,
+
+	/// The version new repositories start at, `0.1.0` if
+	/// not given
+	pub version: Option<Version>,
+
+	/// Whether new repositories start with scripts, which
+	/// are placeholders unless `scripts` is given
+	pub default_scripts: Option<bool>,
+
+	/// Scripts new repositories start with, in place of the
+	/// placeholders
+	pub scripts: Option<tomlconfig::Scripts0_2_2>,
+
+	/// Platform restrictions new repositories start with, in
+	/// place of requiring the current platform
+	pub restrict: Option<tomlconfig::Restrict0_2_2>,
+
+	/// Settings for new repositories in a namespace, such as
+	/// `acme` or `acme/tools`, over the ones above. The most
+	/// specific namespace applies.
+	pub namespaces: Option<HashMap<String, InitNamespace0_2_2>>
+}
+
+/// What new repositories in a namespace start with, over
+/// the settings of `[init]`
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
+#[allow(clippy::exhaustive_structs)]
+pub struct InitNamespace0_2_2 {
+	pub version: Option<Version>,
+	pub default_scripts: Option<bool>,
+	pub scripts: Option<tomlconfig::Scripts0_2_2>,
+	pub restrict: Option<tomlconfig::Restrict0_2_2>
+}
+
+/// Output colors, by color name (such as `green` or
+/// `bright blue`)
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
+#[allow(clippy::exhaustive_structs)]
+pub struct Theme0_2_2 {
+	pub success: Option<String>,
+	pub error: Option<String>,
+	pub info: Option<String>,
+	pub warning: Option<String>,
+
+	/// The namespace part of resource names
+	pub namespace: Option<String>,
+
+	/// The last part of resource names
+	pub resource: Option<String>
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
+#[allow(clippy::exhaustive_structs)]
+pub struct ConfigOptions0_2_2 {
+	/// Reject unknown keys in batl.toml files
+	pub strict: Option<bool>,
+
+	/// Reject references to unset environment variables in
+	/// batl.toml files
+	pub strict_env: Option<bool>,
+
+	/// The interpreter scripts run through, unless they
+	/// name their own
+	pub shell: Option<tomlconfig::ScriptShell0_2_2>
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
+#[allow(clippy::exhaustive_structs)]
+pub struct Names0_2_2 {
+	pub case: Option<NameCase0_2_2>
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+#[allow(clippy::exhaustive_enums)]
+pub enum NameCase0_2_2 {
+	Preserve,
+	Fold
+}
+
+impl From<NameCase0_2_2> for NameCase {
+	#[inline]
+	fn from(value: NameCase0_2_2) -> Self {
+		match value {
+			NameCase0_2_2::Preserve => Self::Preserve,
+			NameCase0_2_2::Fold => Self::Fold
+		}
+	}
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[allow(clippy::exhaustive_structs)]
+pub struct Api0_2_2 {
+	pub credentials: String,
+
+	/// The registry url, if not the public registry
+	pub url: Option<String>,
+
+	/// How to authenticate with the registry, an API key
+	/// when not set
+	pub auth: Option<AuthProvider0_2_2>,
+
+	/// The OAuth client id for device auth, if not the
+	/// default
+	pub client_id: Option<String>
+}
+
+/// Ways of authenticating with a registry
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+#[allow(clippy::exhaustive_enums)]
+pub enum AuthProvider0_2_2 {
+	/// The static API key in `credentials`
+	Key,
+
+	/// OAuth tokens from the device flow, stored outside
+	/// of the batlrc
+	Device
+}
+
+impl Api0_2_2 {
+	/// The API key in `credentials`, unless it is empty or
+	/// still the placeholder
+	#[inline]
+	#[must_use]
+	pub fn key(&self) -> Option<String> {
+		(!self.credentials.is_empty() && self.credentials != PLACEHOLDER_KEY).then(|| self.credentials.clone())
+	}
+}
+
+impl Default for Api0_2_2 {
+	#[inline]
+	fn default() -> Self {
+		Self {
+			credentials: Api0_2_1::default().credentials,
+			url: None,
+			auth: None,
+			client_id: None
+		}
+	}
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[allow(clippy::exhaustive_structs)]
+pub struct Host0_2_2 {
+	/// The ssh destination, such as `user@buildhost`
+	pub address: String,
+
+	/// The ssh port, if not the default
+	pub port: Option<u16>,
+
+	/// The directory repositories are synced into,
+	/// relative to the remote home directory
+	pub path: Option<String>
+}
 
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
@@ -21,7 +308,7 @@ impl Default for Api0_2_1 {
 	#[inline]
 	fn default() -> Self {
 		Self {
-			credentials: "YOUR-KEY-GOES-HERE".to_owned()
+			credentials: PLACEHOLDER_KEY.to_owned()
 		}
 	}
 }
@@ -0,0 +1,252 @@
+use crate::context::BatlContext;
+use crate::error as batlerror;
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::io::Read as _;
+use std::path::PathBuf;
+use super::{Name, Repository, Resource as _};
+
+
+/// Length of the repository checksums, in bytes
+const CHECKSUM_LENGTH: usize = 32;
+
+
+/// The exact state of a repository and its whole
+/// dependency closure, stored under `gen/freezes` so it
+/// can be restored later
+#[derive(Serialize, Deserialize, Clone)]
+#[non_exhaustive]
+pub struct Freeze {
+	pub tag: String,
+
+	/// The repository that was frozen
+	pub root: Name,
+
+	/// When the freeze was taken, in seconds since the
+	/// unix epoch
+	pub created: u64,
+
+	/// The repository itself and every transitive
+	/// dependency, sorted by name
+	pub entries: Vec<Entry>
+}
+
+/// A single repository of a freeze
+#[derive(Serialize, Deserialize, Clone)]
+#[non_exhaustive]
+pub struct Entry {
+	pub name: Name,
+	pub version: Version,
+
+	/// Where the repository was when frozen
+	pub path: PathBuf,
+
+	/// The checksum of the files that would be published
+	pub checksum: String,
+
+	/// The named registry the repository comes from, if not
+	/// the default one
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub registry: Option<String>
+}
+
+impl Freeze {
+	/// Record the state of a repository and every
+	/// repository it depends on, directly or not
+	///
+	/// # Errors
+	///
+	/// Returns `Missing` if a dependency is not available
+	/// locally, since its state can't be recorded.
+	/// Propogates any IO errors while hashing.
+	#[inline]
+	pub fn new(context: &BatlContext, tag: &str, repository: &Repository) -> Result<Self, batlerror::FreezeError> {
+		validate_tag(tag)?;
+
+		let mut seen = HashSet::from([repository.name().clone()]);
+		let mut pending = repository.config().dependencies.keys().cloned().collect::<Vec<_>>();
+		let mut entries = vec![Entry::of(repository)?];
+
+		while let Some(name) = pending.pop() {
+			if !seen.insert(name.clone()) {
+				continue;
+			}
+
+			let dependency = Repository::load_in(context, name.clone())
+				.ok()
+				.flatten()
+				.ok_or_else(|| batlerror::FreezeError::Missing(name.to_string()))?;
+
+			pending.extend(dependency.config().dependencies.keys().cloned());
+			entries.push(Entry::of(&dependency)?);
+		}
+
+		let mut registries = repository.dependency_registries(context);
+
+		for entry in &mut entries {
+			entry.registry = registries.remove(&entry.name);
+		}
+
+		entries.sort_by_key(|entry| entry.name.to_string());
+
+		Ok(Self {
+			tag: tag.to_owned(),
+			root: repository.name().clone(),
+			created: crate::system::unix_time(),
+			entries
+		})
+	}
+
+	/// Load a freeze by its tag
+	///
+	/// # Errors
+	///
+	/// Propogates any IO errors, and returns `Invalid` if
+	/// the manifest can't be read. Returns `None` if there is
+	/// no freeze with the tag.
+	#[inline]
+	pub fn load(context: &BatlContext, tag: &str) -> Result<Option<Self>, batlerror::FreezeError> {
+		let path = Self::path(context, tag)?;
+
+		let contents = match std::fs::read_to_string(&path) {
+			Ok(contents) => contents,
+			Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+			Err(err) => return Err(err.into())
+		};
+
+		serde_json::from_str(&contents)
+			.map(Some)
+			.map_err(|err| batlerror::FreezeError::Invalid(format!("{}: {err}", path.display())))
+	}
+
+	/// Write the freeze under its tag. Existing freezes are
+	/// only replaced when asked to.
+	///
+	/// # Errors
+	///
+	/// Returns `AlreadyExists` if the tag is taken and
+	/// `replace` is not set. Propogates any IO errors.
+	#[inline]
+	pub fn save(&self, context: &BatlContext, replace: bool) -> Result<(), batlerror::FreezeError> {
+		let path = Self::path(context, &self.tag)?;
+
+		if !replace && path.exists() {
+			return Err(batlerror::FreezeError::AlreadyExists(self.tag.clone()));
+		}
+
+		if let Some(parent) = path.parent() {
+			std::fs::create_dir_all(parent)?;
+		}
+
+		let contents = serde_json::to_string_pretty(self)
+			.map_err(|err| batlerror::FreezeError::Invalid(err.to_string()))?;
+
+		std::fs::write(path, contents)?;
+
+		Ok(())
+	}
+
+	/// Get the path the freeze with a tag is stored at
+	///
+	/// # Errors
+	///
+	/// Returns `InvalidTag` for tags that aren't a plain
+	/// file name, and `NotSetup` without a battalion root
+	#[inline]
+	pub fn path(context: &BatlContext, tag: &str) -> Result<PathBuf, batlerror::FreezeError> {
+		validate_tag(tag)?;
+
+		context.system().freeze_root()
+			.map(|p| p.join(format!("{tag}.json")))
+			.ok_or(batlerror::FreezeError::NotSetup)
+	}
+}
+
+impl Entry {
+	/// Record the current state of a repository
+	///
+	/// # Errors
+	///
+	/// Propogates any IO errors while hashing
+	#[inline]
+	pub fn of(repository: &Repository) -> Result<Self, batlerror::FreezeError> {
+		Ok(Self {
+			name: repository.name().clone(),
+			version: repository.config().version.clone(),
+			path: repository.path().to_path_buf(),
+			checksum: checksum(repository)?,
+			registry: None
+		})
+	}
+
+	/// Find the frozen state of the repository among the
+	/// local repositories and fetched versions. Returns
+	/// `None` if neither is at the frozen version with the
+	/// frozen contents.
+	///
+	/// # Errors
+	///
+	/// Propogates any errors while loading or hashing
+	#[inline]
+	pub fn locate(&self, context: &BatlContext) -> Result<Option<Repository>, batlerror::FreezeError> {
+		let candidates = [
+			Repository::load_in(context, self.name.clone()).ok().flatten(),
+			Repository::version_path(context, &self.name, &self.version)
+				.filter(|path| path.join("batl.toml").exists())
+				.map(|path| Repository::from_path_in(context, &path))
+				.transpose()?
+		];
+
+		for repository in candidates.into_iter().flatten() {
+			if repository.config().version == self.version && checksum(&repository)? == self.checksum {
+				return Ok(Some(repository));
+			}
+		}
+
+		Ok(None)
+	}
+}
+
+/// Hash the files of a repository that would be published
+///
+/// The files are the ones [`Repository::package_files`]
+/// puts in an archive, so a fetched version hashes the same
+/// as the working tree it was published from. The relative
+/// path of each file is hashed along with its contents, so
+/// renames change the checksum too.
+///
+/// # Errors
+///
+/// Propogates any errors while walking or reading
+#[inline]
+pub fn checksum(repository: &Repository) -> Result<String, batlerror::FreezeError> {
+	let mut params = blake2b_simd::Params::new();
+	params.hash_length(CHECKSUM_LENGTH);
+
+	let mut state = params.to_state();
+
+	for (relative, _size) in repository.package_files()? {
+		let mut contents = Vec::new();
+		std::fs::File::open(repository.path().join(&relative))?.read_to_end(&mut contents)?;
+
+		state.update(relative.as_bytes());
+		state.update(b"\0");
+		state.update(params.hash(&contents).as_bytes());
+	}
+
+	Ok(state.finalize().to_hex().to_string())
+}
+
+/// Check that a tag can be used as a file name
+fn validate_tag(tag: &str) -> Result<(), batlerror::FreezeError> {
+	let valid = !tag.is_empty()
+		&& !tag.starts_with('.')
+		&& tag.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_' | '+'));
+
+	if valid {
+		Ok(())
+	} else {
+		Err(batlerror::FreezeError::InvalidTag(tag.to_owned()))
+	}
+}
@@ -0,0 +1,97 @@
+use core::fmt::{Display, Formatter};
+use core::str::FromStr;
+use serde::Serialize;
+use super::tomlconfig;
+
+
+/// The script tools are installed with
+pub const INSTALL_SCRIPT: &str = "install";
+
+
+/// What a repository is for, which decides how battalion
+/// treats it
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum Kind {
+	/// Code for other repositories to depend on
+	#[default]
+	Library,
+
+	/// A program that is run, not depended on
+	App,
+
+	/// A program installed onto the system with its
+	/// `install` script
+	Tool,
+
+	/// A repository that only gathers its dependencies,
+	/// which scripts are run across like a workspace
+	Meta
+}
+
+impl Kind {
+	/// Every kind, in the order they are listed
+	pub const ALL: [Self; 4] = [Self::Library, Self::App, Self::Tool, Self::Meta];
+
+	/// Whether other repositories should depend on
+	/// repositories of this kind
+	#[inline]
+	#[must_use]
+	pub const fn is_dependable(self) -> bool {
+		!matches!(self, Self::App)
+	}
+}
+
+impl Display for Kind {
+	#[inline]
+	fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+		f.write_str(match *self {
+			Self::Library => "library",
+			Self::App => "app",
+			Self::Tool => "tool",
+			Self::Meta => "meta"
+		})
+	}
+}
+
+impl FromStr for Kind {
+	type Err = String;
+
+	#[inline]
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"library" => Ok(Self::Library),
+			"app" => Ok(Self::App),
+			"tool" => Ok(Self::Tool),
+			"meta" => Ok(Self::Meta),
+			_ => Err(format!("Unknown kind {s}, expected library, app, tool, or meta"))
+		}
+	}
+}
+
+impl From<Kind> for Option<tomlconfig::RepositoryKindLatest> {
+	/// Libraries are the default, so they are left out of
+	/// the config
+	#[inline]
+	fn from(value: Kind) -> Self {
+		match value {
+			Kind::Library => None,
+			Kind::App => Some(tomlconfig::RepositoryKind0_2_2::App),
+			Kind::Tool => Some(tomlconfig::RepositoryKind0_2_2::Tool),
+			Kind::Meta => Some(tomlconfig::RepositoryKind0_2_2::Meta)
+		}
+	}
+}
+
+impl From<tomlconfig::RepositoryKind0_2_2> for Kind {
+	#[inline]
+	fn from(value: tomlconfig::RepositoryKind0_2_2) -> Self {
+		match value {
+			tomlconfig::RepositoryKind0_2_2::Library => Self::Library,
+			tomlconfig::RepositoryKind0_2_2::App => Self::App,
+			tomlconfig::RepositoryKind0_2_2::Tool => Self::Tool,
+			tomlconfig::RepositoryKind0_2_2::Meta => Self::Meta
+		}
+	}
+}
@@ -0,0 +1,208 @@
+use crate::context::BatlContext;
+use semver::Version;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::Path;
+use super::{Name, Repository, Resource as _};
+
+
+/// File names licenses are detected from. Files named
+/// after a license, such as `LICENSE-MIT`, are found too.
+const LICENSE_FILES: [&str; 5] = ["LICENSE", "LICENCE", "COPYING", "LICENSE.md", "LICENSE.txt"];
+
+
+/// The licenses of every transitive dependency of a
+/// repository
+#[derive(Clone, Serialize)]
+#[non_exhaustive]
+pub struct Report {
+	pub name: Name,
+
+	/// The license of the repository itself
+	pub license: Option<String>,
+
+	pub dependencies: Vec<Entry>
+}
+
+/// The license of a single dependency
+#[derive(Clone, Serialize)]
+#[non_exhaustive]
+pub struct Entry {
+	pub name: Name,
+
+	/// The version found locally, or `None` if the
+	/// dependency is missing
+	pub version: Option<Version>,
+
+	/// The license in the dependency's batl.toml
+	pub declared: Option<String>,
+
+	/// The license detected from its license files, when
+	/// detection was asked for
+	pub detected: Option<String>,
+
+	pub status: Status
+}
+
+/// How sure a report is of a license
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum Status {
+	/// Declared, and matching the license files if they
+	/// were checked
+	Declared,
+
+	/// Not declared, but found in the license files
+	Detected,
+
+	/// Declared differently than the license files say
+	Mismatch,
+
+	/// Neither declared nor detected
+	Unknown,
+
+	/// The dependency is not available locally
+	Missing
+}
+
+impl Report {
+	/// Walk the dependencies of a repository, recording each
+	/// of them once
+	#[inline]
+	#[must_use]
+	pub fn new(context: &BatlContext, repository: &Repository, detect: bool) -> Self {
+		let mut seen = HashSet::from([repository.name().clone()]);
+		let mut pending = repository.config().dependencies.keys().cloned().collect::<Vec<_>>();
+		let mut dependencies = Vec::new();
+
+		while let Some(name) = pending.pop() {
+			if !seen.insert(name.clone()) {
+				continue;
+			}
+
+			let Some(dependency) = Repository::load_in(context, name.clone()).ok().flatten() else {
+				dependencies.push(Entry {
+					name,
+					version: None,
+					declared: None,
+					detected: None,
+					status: Status::Missing
+				});
+
+				continue;
+			};
+
+			let config = dependency.config();
+			let detected = if detect { self::detect(dependency.path()) } else { None };
+
+			let status = match (config.license.as_ref(), detected.as_ref()) {
+				(Some(declared), Some(found)) if !same(declared, found) => Status::Mismatch,
+				(Some(_), _) => Status::Declared,
+				(None, Some(_)) => Status::Detected,
+				(None, None) => Status::Unknown
+			};
+
+			pending.extend(config.dependencies.keys().cloned());
+
+			dependencies.push(Entry {
+				name,
+				version: Some(config.version.clone()),
+				declared: config.license.clone(),
+				detected,
+				status
+			});
+		}
+
+		dependencies.sort_by_key(|entry| entry.name.to_string());
+
+		Self {
+			name: repository.name().clone(),
+			license: repository.config().license.clone(),
+			dependencies
+		}
+	}
+}
+
+impl Entry {
+	/// The license to report, declared or otherwise detected
+	#[inline]
+	#[must_use]
+	pub fn license(&self) -> Option<&str> {
+		self.declared.as_deref().or(self.detected.as_deref())
+	}
+}
+
+/// Detect the license of a directory from its license
+/// files, as an SPDX expression. Several licenses, such as
+/// `LICENSE-MIT` next to `LICENSE-APACHE`, are joined with
+/// `OR`.
+#[inline]
+#[must_use]
+pub fn detect(path: &Path) -> Option<String> {
+	let mut found = std::fs::read_dir(path).ok()?
+		.filter_map(Result::ok)
+		.filter(|entry| entry.file_name().to_str().is_some_and(is_license_file))
+		.filter_map(|entry| std::fs::read_to_string(entry.path()).ok())
+		.filter_map(|contents| identify(&contents))
+		.collect::<Vec<_>>();
+
+	found.sort_unstable();
+	found.dedup();
+
+	(!found.is_empty()).then(|| found.join(" OR "))
+}
+
+/// Whether a file name looks like it holds a license
+fn is_license_file(file_name: &str) -> bool {
+	let upper = file_name.to_uppercase();
+
+	LICENSE_FILES.iter().any(|&known| upper == known.to_uppercase())
+		|| upper.starts_with("LICENSE-")
+		|| upper.starts_with("LICENCE-")
+}
+
+/// Identify a license from its text by its telltale
+/// phrases
+fn identify(contents: &str) -> Option<&'static str> {
+	let text = contents.to_lowercase().split_whitespace().collect::<Vec<_>>().join(" ");
+	let has = |phrase: &str| text.contains(phrase);
+
+	if has("apache license") && has("version 2.0") {
+		Some("Apache-2.0")
+	} else if has("mozilla public license") && has("2.0") {
+		Some("MPL-2.0")
+	} else if has("gnu affero general public license") {
+		Some("AGPL-3.0")
+	} else if has("gnu lesser general public license") {
+		Some(if has("version 2.1") { "LGPL-2.1" } else { "LGPL-3.0" })
+	} else if has("gnu general public license") {
+		Some(if has("version 2") && !has("version 3") { "GPL-2.0" } else { "GPL-3.0" })
+	} else if has("permission is hereby granted, free of charge") {
+		Some("MIT")
+	} else if has("permission to use, copy, modify, and/or distribute") {
+		Some("ISC")
+	} else if has("redistribution and use in source and binary forms") {
+		Some(if has("neither the name") { "BSD-3-Clause" } else { "BSD-2-Clause" })
+	} else if has("this is free and unencumbered software") {
+		Some("Unlicense")
+	} else {
+		None
+	}
+}
+
+/// Whether a declared license agrees with a detected one,
+/// ignoring case and how alternatives are ordered
+fn same(declared: &str, detected: &str) -> bool {
+	let parts = |expression: &str| {
+		let mut split = expression.to_lowercase()
+			.split(" or ")
+			.map(|part| part.trim().trim_matches(['(', ')']).to_owned())
+			.collect::<Vec<_>>();
+
+		split.sort_unstable();
+		split
+	};
+
+	parts(declared) == parts(detected)
+}
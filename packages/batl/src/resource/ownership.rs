@@ -0,0 +1,145 @@
+use crate::error as batlerror;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+
+/// Where the ownership of a repository is kept, relative to
+/// the repository
+const OWNERSHIP_FILE: &str = ".batl/ownership.json";
+
+
+/// Who created a repository and who last changed it through
+/// batl, for battalion roots shared between users
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Ownership {
+	pub creator: String,
+
+	/// When the repository was created, in seconds since
+	/// the unix epoch
+	pub created: u64,
+
+	pub last_writer: String,
+
+	/// When the repository was last changed, in seconds
+	/// since the unix epoch
+	pub written: u64
+}
+
+impl Ownership {
+	/// The name of the user running batl
+	#[inline]
+	#[must_use]
+	pub fn current_user() -> String {
+		whoami::username()
+	}
+
+	/// Read the ownership of the repository at a path.
+	/// Returns `None` for repositories from before ownership
+	/// was recorded.
+	#[inline]
+	#[must_use]
+	pub fn load(path: &Path) -> Option<Self> {
+		let contents = std::fs::read_to_string(Self::path(path)).ok()?;
+
+		serde_json::from_str(&contents).ok()
+	}
+
+	/// Record the current user as the creator of the
+	/// repository at a path, replacing any ownership it was
+	/// copied with
+	///
+	/// # Errors
+	///
+	/// Propogates any IO errors while writing
+	#[inline]
+	pub fn claim(path: &Path) -> Result<Self, std::io::Error> {
+		let user = Self::current_user();
+		let now = crate::system::unix_time();
+
+		let ownership = Self {
+			creator: user.clone(),
+			created: now,
+			last_writer: user,
+			written: now
+		};
+
+		ownership.save(path)?;
+
+		Ok(ownership)
+	}
+
+	/// Record the current user as the last writer of the
+	/// repository at a path, claiming it if nobody has yet
+	///
+	/// # Errors
+	///
+	/// Propogates any IO errors while writing
+	#[inline]
+	pub fn touch(path: &Path) -> Result<Self, std::io::Error> {
+		let Some(mut ownership) = Self::load(path) else {
+			return Self::claim(path);
+		};
+
+		ownership.last_writer = Self::current_user();
+		ownership.written = crate::system::unix_time();
+		ownership.save(path)?;
+
+		Ok(ownership)
+	}
+
+	/// Whether the current user created or last changed the
+	/// repository
+	#[inline]
+	#[must_use]
+	pub fn is_mine(&self) -> bool {
+		let user = Self::current_user();
+
+		self.creator == user || self.last_writer == user
+	}
+
+	/// Get the ownership file of the repository at a path
+	#[inline]
+	#[must_use]
+	pub fn path(path: &Path) -> PathBuf {
+		path.join(OWNERSHIP_FILE)
+	}
+
+	/// Write the ownership of the repository at a path
+	fn save(&self, path: &Path) -> Result<(), std::io::Error> {
+		let ownership_path = Self::path(path);
+
+		if let Some(parent) = ownership_path.parent() {
+			std::fs::create_dir_all(parent)?;
+		}
+
+		let contents = serde_json::to_string_pretty(self)
+			.map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+
+		std::fs::write(ownership_path, contents)
+	}
+}
+
+/// Check that the current user may make destructive
+/// changes to the repository at a path
+///
+/// Repositories without recorded ownership are anyone's.
+///
+/// # Errors
+///
+/// Returns `NotOwner` if another user created the
+/// repository, unless `force` is set, and `ReadOnly` if its
+/// directory can't be written to either way
+#[inline]
+pub fn check(path: &Path, force: bool) -> Result<(), batlerror::OwnershipError> {
+	if std::fs::metadata(path)?.permissions().readonly() {
+		return Err(batlerror::OwnershipError::ReadOnly(path.display().to_string()));
+	}
+
+	match Ownership::load(path) {
+		Some(ownership) if !force && ownership.creator != Ownership::current_user() => {
+			Err(batlerror::OwnershipError::NotOwner(ownership.creator))
+		},
+		_ => Ok(())
+	}
+}
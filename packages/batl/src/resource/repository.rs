@@ -1,12 +1,42 @@
+use batl_macros::versioned_config;
+use crate::context::BatlContext;
 use crate::error as batlerror;
-use semver::Version;
+use crate::gitignore::Gitignore;
+use crate::interpolate::interpolate;
+use crate::preflight;
+use crate::secrets::Allowlist;
+use semver::{Version, VersionReq};
+use schemars::JsonSchema;
 use serde::{Serialize, Deserialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
-use super::{tomlconfig, Name, Resource};
-use super::archive::Archive;
+use super::{batlrc, tomlconfig, Name, Resource};
+use super::archive::{Archive, BuildOptions};
+use super::kind::{Kind, INSTALL_SCRIPT};
 use super::restrict::{Condition, Settings as RestrictSettings};
-use super::tomlconfig::TomlConfig;
+use super::script::{Script, Source as ScriptSource};
+use super::license::Report as LicenseReport;
+use super::ownership::Ownership;
+use super::summary::Summary;
+use super::tomlconfig::TomlConfig as _;
+use super::transaction::Transaction;
+
+
+/// The placeholder replaced by the repository name in
+/// starter files
+const NAME_PLACEHOLDER: &str = "{name}";
+
+/// Where the paths of a partially fetched repository are
+/// listed, one per line
+const SPARSE_FILE: &str = ".batl/sparse";
+
+/// The directory of a repository that battalion keeps its
+/// generated files in, such as script outputs and logs
+const GEN_DIR: &str = "gen";
+
+/// The id of the managed `.gitignore` block ignoring the
+/// generated files
+const GEN_BLOCK: &str = "gen";
 
 
 pub struct Repository {
@@ -16,6 +46,10 @@ pub struct Repository {
 	/// The repository configuration
 	config: Config,
 
+	/// The configuration as written, before environment
+	/// variables are interpolated, which is what gets saved
+	raw: Config,
+
 	/// The repository name
 	name: Name
 }
@@ -23,7 +57,19 @@ pub struct Repository {
 #[derive(Default)]
 #[non_exhaustive]
 pub struct CreateRepositoryOptions {
-	pub git: Option<tomlconfig::RepositoryGit0_2_2>
+	pub git: Option<tomlconfig::RepositoryGit0_2_2>,
+
+	/// What the repository is for, which decides the
+	/// starter scripts
+	pub kind: Kind,
+
+	/// The version to start at, over the one in `[init]` of
+	/// the batlrc
+	pub version: Option<Version>,
+
+	/// Whether to start with scripts, over
+	/// `init.default_scripts` in the batlrc
+	pub default_scripts: Option<bool>
 }
 
 impl CreateRepositoryOptions {
@@ -31,7 +77,40 @@ impl CreateRepositoryOptions {
 	#[must_use]
 	pub const fn git(git: tomlconfig::RepositoryGit0_2_2) -> Self {
 		Self {
-			git: Some(git)
+			git: Some(git),
+			kind: Kind::Library,
+			version: None,
+			default_scripts: None
+		}
+	}
+
+	#[inline]
+	#[must_use]
+	pub const fn kind(kind: Kind) -> Self {
+		Self {
+			git: None,
+			kind,
+			version: None,
+			default_scripts: None
+		}
+	}
+}
+
+/// Options for copying a repository under a new name
+#[derive(Default)]
+#[non_exhaustive]
+pub struct CopyRepositoryOptions {
+	/// Drop the git configuration and history from the
+	/// copy, keeping the checked out files
+	pub strip_git: bool
+}
+
+impl CopyRepositoryOptions {
+	#[inline]
+	#[must_use]
+	pub const fn strip_git(strip_git: bool) -> Self {
+		Self {
+			strip_git
 		}
 	}
 }
@@ -45,23 +124,307 @@ impl Repository {
 	/// Returns `None` if no repository is found.
 	#[inline]
 	pub fn load(name: Name) -> Result<Option<Self>, batlerror::GeneralResourceError> {
-		let repo_path = crate::system::repository_root()
-			.map(|p| p.join(PathBuf::from(&name)));
+		Self::load_in(&BatlContext::detect(), name)
+	}
+
+	/// Loads the repository at the given name from the
+	/// battalion of a context
+	/// 
+	/// # Errors
+	/// 
+	/// Propogates any errors found along the way
+	/// Returns `None` if no repository is found.
+	#[inline]
+	pub fn load_in(context: &BatlContext, name: Name) -> Result<Option<Self>, batlerror::GeneralResourceError> {
+		let loaded = Self::load_unverified_in(context, name)?;
+
+		if let Some(repository) = loaded.as_ref() {
+			repository.verify_name(context)?;
+		}
+
+		Ok(loaded)
+	}
+
+	/// Loads a repository as `load_in` does, without
+	/// checking its declared name against where it is
+	/// stored. Only meant for reconciling the two.
+	/// 
+	/// # Errors
+	/// 
+	/// Propogates any errors found along the way
+	/// Returns `None` if no repository is found.
+	#[inline]
+	pub fn load_unverified_in(context: &BatlContext, name: Name) -> Result<Option<Self>, batlerror::GeneralResourceError> {
+		let normal_name = name.normalized(context.settings().name_case);
+
+		let repo_path = context.system().repository_root()
+			.map(|p| p.join(PathBuf::from(&normal_name)))
+			.map(|p| if p.join("batl.toml").exists() {
+				p
+			} else {
+				external_path(context.system(), &normal_name).unwrap_or(p)
+			})
+			.filter(|p| p.join("batl.toml").exists());
 
 		if let Some(path) = repo_path {
-			let toml = AnyTomlConfig::read_toml(&path.join("batl.toml"))?;
-			let latest = TomlConfigLatest::from(toml);
+			let toml = AnyTomlConfig::read_toml_with(&path.join("batl.toml"), context.settings().strict)?;
+			let raw = Config::from(TomlConfigLatest::from(toml));
 
 			Ok(Some(Self {
 				path,
-				config: Config::from(latest),
-				name
+				config: raw.interpolated(context.settings().strict_env)?,
+				raw,
+				name: normal_name
 			}))
 		} else {
 			Ok(None)
 		}
 	}
 
+	/// Loads a specific version of a repository from the
+	/// battalion of a context. The repository itself is used
+	/// if it is at that version, otherwise the fetched copy of
+	/// the version is used.
+	/// 
+	/// # Errors
+	/// 
+	/// Propogates any errors found along the way
+	/// Returns `None` if the version is not available locally.
+	#[inline]
+	pub fn load_version_in(context: &BatlContext, name: Name, version: &Version) -> Result<Option<Self>, batlerror::GeneralResourceError> {
+		let normal_name = name.normalized(context.settings().name_case);
+
+		if let Ok(Some(repository)) = Self::load_in(context, normal_name.clone()) {
+			if repository.config.version == *version {
+				return Ok(Some(repository));
+			}
+		}
+
+		let Some(path) = context.system().version_root()
+			.map(|p| p.join(PathBuf::from(&normal_name)).join(version.to_string()))
+			.filter(|p| p.join("batl.toml").exists()) else {
+			return Ok(None);
+		};
+
+		let toml = AnyTomlConfig::read_toml_with(&path.join("batl.toml"), context.settings().strict)?;
+		let raw = Config::from(TomlConfigLatest::from(toml));
+
+		Ok(Some(Self {
+			path,
+			config: raw.interpolated(context.settings().strict_env)?,
+			raw,
+			name: normal_name
+		}))
+	}
+
+	/// Summarize the repository, resolving its
+	/// dependencies against local repositories
+	#[inline]
+	#[must_use]
+	pub fn summarize(&self, context: &BatlContext) -> Summary {
+		Summary::new(context, self)
+	}
+
+	/// Report the licenses of every transitive dependency,
+	/// detecting them from license files when asked to
+	#[inline]
+	#[must_use]
+	pub fn licenses(&self, context: &BatlContext, detect: bool) -> LicenseReport {
+		LicenseReport::new(context, self, detect)
+	}
+
+	/// Get the directory battalion keeps generated files of
+	/// the repository in
+	#[inline]
+	#[must_use]
+	pub fn gen_path(&self) -> PathBuf {
+		self.path.join(GEN_DIR)
+	}
+
+	/// Create the directory for generated files, and make
+	/// sure git ignores it when the repository is a git
+	/// repository. Returns the directory.
+	/// 
+	/// # Errors
+	/// 
+	/// Propogates any IO errors
+	#[inline]
+	pub fn ensure_gen(&self) -> Result<PathBuf, std::io::Error> {
+		let gen_path = self.gen_path();
+		std::fs::create_dir_all(&gen_path)?;
+
+		// Fetched copies aren't checked in anywhere, and a
+		// changed .gitignore would conflict with the next fetch
+		if !self.path.join(".git").exists() {
+			return Ok(gen_path);
+		}
+
+		let gitignore_path = self.path.join(".gitignore");
+		let mut gitignore = Gitignore::load(&gitignore_path)?;
+
+		if gitignore.block(GEN_BLOCK) != Some(gen_patterns().as_slice()) {
+			gitignore.set_block(GEN_BLOCK, gen_patterns());
+			gitignore.save(&gitignore_path)?;
+		}
+
+		Ok(gen_path)
+	}
+
+	/// Remove everything in the directory for generated
+	/// files, returning what was removed. Symlinks are
+	/// removed without following them.
+	/// 
+	/// # Errors
+	/// 
+	/// Returns `Invalid` if the directory is a symlink or a
+	/// file, and propogates any IO errors
+	#[inline]
+	pub fn clean(&self) -> Result<Vec<PathBuf>, batlerror::GeneralResourceError> {
+		let gen_path = self.gen_path();
+
+		let metadata = match std::fs::symlink_metadata(&gen_path) {
+			Ok(found) => found,
+			Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+			Err(err) => return Err(err.into())
+		};
+
+		if !metadata.is_dir() {
+			return Err(batlerror::GeneralResourceError::Invalid);
+		}
+
+		let mut removed = Vec::new();
+
+		for result in std::fs::read_dir(&gen_path)? {
+			let entry = result?;
+			let entry_path = entry.path();
+
+			if entry.file_type()?.is_dir() {
+				std::fs::remove_dir_all(&entry_path)?;
+			} else {
+				std::fs::remove_file(&entry_path)?;
+			}
+
+			removed.push(entry_path);
+		}
+
+		removed.sort();
+
+		Ok(removed)
+	}
+
+	/// Get the directory scripts write artifacts to
+	#[inline]
+	#[must_use]
+	pub fn out_path(&self) -> PathBuf {
+		crate::executor::out_path(&self.path)
+	}
+
+	/// Get the path a fetched version of a repository is
+	/// stored at. Returns `None` if battalion is not set up.
+	#[inline]
+	#[must_use]
+	pub fn version_path(context: &BatlContext, name: &Name, version: &Version) -> Option<PathBuf> {
+		context.system().version_root()
+			.map(|p| p.join(PathBuf::from(name)).join(version.to_string()))
+	}
+
+	/// Whether the repository is a fetched version, stored
+	/// apart from the repository itself
+	#[inline]
+	#[must_use]
+	pub fn is_fetched_version(&self, context: &BatlContext) -> bool {
+		Self::version_path(context, &self.name, &self.config.version)
+			.and_then(|path| path.canonicalize().ok())
+			.zip(self.path.canonicalize().ok())
+			.is_some_and(|(version, own)| version == own)
+	}
+
+	/// Get the directory the batl.toml of a version is
+	/// stored in when only its manifest was fetched.
+	/// Returns `None` if battalion is not set up.
+	#[inline]
+	#[must_use]
+	pub fn manifest_path(context: &BatlContext, name: &Name, version: &Version) -> Option<PathBuf> {
+		context.system().manifest_root()
+			.map(|p| p.join(PathBuf::from(name)).join(version.to_string()))
+	}
+
+	/// Load a version of a repository from its fetched
+	/// manifest, which has the config but none of the files
+	/// 
+	/// # Errors
+	/// 
+	/// Propogates any errors while reading the config
+	/// Returns `None` if the manifest wasn't fetched
+	#[inline]
+	pub fn load_manifest(context: &BatlContext, name: &Name, version: &Version) -> Result<Option<Self>, batlerror::GeneralResourceError> {
+		Self::manifest_path(context, name, version)
+			.filter(|path| path.join("batl.toml").exists())
+			.map(|path| Self::from_path_in(context, &path))
+			.transpose()
+	}
+
+	/// Check that the batl.toml of a fetched repository
+	/// parses and declares the name and version that were
+	/// asked for
+	/// 
+	/// # Errors
+	/// 
+	/// Returns the first check that fails
+	#[inline]
+	pub fn check_fetched(contents: &str, name: &Name, version: Option<&Version>) -> Result<(), batlerror::FetchCheckError> {
+		let config = Config::parse(contents)?;
+
+		if config.name != *name {
+			return Err(batlerror::FetchCheckError::NameMismatch(config.name.to_string(), name.to_string()));
+		}
+
+		if let Some(expected) = version.filter(|expected| **expected != config.version) {
+			return Err(batlerror::FetchCheckError::VersionMismatch(config.version.to_string(), expected.to_string()));
+		}
+
+		Ok(())
+	}
+
+	/// Move a fetch that failed its checks under
+	/// `gen/quarantine`, so it can be looked at without
+	/// being used. Returns where it was moved.
+	/// 
+	/// # Errors
+	/// 
+	/// Propogates any IO errors while moving it
+	#[inline]
+	pub fn quarantine(context: &BatlContext, path: &Path, name: &Name, version: Option<&Version>) -> Result<PathBuf, std::io::Error> {
+		let root = context.system().quarantine_root()
+			.ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "Battalion is not set up"))?;
+
+		let stamp = crate::system::unix_time();
+
+		let version_name = version.map_or_else(|| "latest".to_owned(), ToString::to_string);
+		let extension = path.extension()
+			.filter(|_| path.is_file())
+			.map(|extension| format!(".{}", extension.to_string_lossy()))
+			.unwrap_or_default();
+
+		// Fetches quarantined within the same second are
+		// told apart by a counter
+		let mut target = root.join(PathBuf::from(name)).join(format!("{version_name}-{stamp}{extension}"));
+		let mut attempt = 1u32;
+
+		while target.exists() {
+			target = root.join(PathBuf::from(name)).join(format!("{version_name}-{stamp}-{attempt}{extension}"));
+			attempt = attempt.saturating_add(1);
+		}
+
+		if let Some(parent) = target.parent() {
+			std::fs::create_dir_all(parent)?;
+		}
+
+		std::fs::rename(path, &target)?;
+
+		Ok(target)
+	}
+
 	/// Creates a repository at the given name, with the
 	/// given options.
 	/// 
@@ -70,135 +433,751 @@ impl Repository {
 	/// Propogates any errors found along the way
 	#[inline]
 	pub fn create(name: Name, options: CreateRepositoryOptions) -> Result<Self, batlerror::CreateResourceError> {
-		let repo_path = crate::system::repository_root()
-			.ok_or(batlerror::CreateResourceError::NotSetup)?
-			.join(PathBuf::from(&name));
+		Self::create_in(&BatlContext::detect(), name, options)
+	}
+
+	/// Creates a repository at the given name in the
+	/// battalion of a context, with the given options.
+	/// 
+	/// # Errors
+	/// 
+	/// Propogates any errors found along the way
+	#[inline]
+	pub fn create_in(context: &BatlContext, name: Name, options: CreateRepositoryOptions) -> Result<Self, batlerror::CreateResourceError> {
+		let normal_name = name.normalized(context.settings().name_case);
+
+		let repo_root = context.system().repository_root()
+			.ok_or(batlerror::CreateResourceError::NotSetup)?;
+
+		super::check_collision(&repo_root, &normal_name)?;
+
+		let repo_path = repo_root.join(PathBuf::from(&normal_name));
+
+		if repo_path.exists() {
+			return Err(batlerror::CreateResourceError::AlreadyExists);
+		}
+
+		let mut transaction = Transaction::default();
+		let repository = Self::init_at(context.system(), repo_path, normal_name, options, &mut transaction)?;
+		transaction.commit();
+
+		Ok(repository)
+	}
+
+	/// Turns an existing directory into a repository. If
+	/// the directory is inside of the repository root, the
+	/// name is derived from its path. Otherwise, a name must
+	/// be given and the directory is registered as an
+	/// external repository. If any step fails, the files
+	/// written before it are removed.
+	/// 
+	/// # Errors
+	/// 
+	/// Propogates any errors found along the way
+	#[inline]
+	pub fn create_in_place(context: &BatlContext, path: &Path, name: Option<Name>, options: CreateRepositoryOptions) -> Result<Self, batlerror::CreateResourceError> {
+		if path.join("batl.toml").exists() {
+			return Err(batlerror::CreateResourceError::AlreadyExists);
+		}
+
+		let abs_path = path.canonicalize()?;
+		let repo_root = context.system().repository_root()
+			.ok_or(batlerror::CreateResourceError::NotSetup)
+			.map(|root| root.canonicalize().unwrap_or(root))?;
+
+		let mut transaction = Transaction::default();
+
+		if let Ok(relative) = abs_path.strip_prefix(&repo_root) {
+			let derived = Name::from_relative_path(relative)
+				.map_err(|err| batlerror::CreateResourceError::InvalidName(err.to_string()))?;
+
+			if let Some(given) = name {
+				if given != derived {
+					return Err(batlerror::CreateResourceError::InvalidName(
+						format!("{given} does not match its path, expected {derived}")
+					));
+				}
+			}
+
+			let repository = Self::init_at(context.system(), abs_path, derived, options, &mut transaction)?;
+			transaction.commit();
+
+			return Ok(repository);
+		}
+
+		let external_name = name.ok_or_else(|| batlerror::CreateResourceError::InvalidName(
+			"a name is required outside of the repository root".to_owned()
+		))?;
+
+		let repository = Self::init_at(context.system(), abs_path, external_name, options, &mut transaction)?;
+
+		Self::register_external(context, &repository.name, &repository.path)?;
+		transaction.commit();
+
+		Ok(repository)
+	}
+
+	/// Writes a fresh configuration and the starter files
+	/// for a repository into a directory, creating it if
+	/// needed. Every file is written as part of the
+	/// transaction.
+	fn init_at(system: &crate::system::Context, repo_path: PathBuf, name: Name, options: CreateRepositoryOptions, transaction: &mut Transaction) -> Result<Self, batlerror::CreateResourceError> {
+		let starters = read_starters(system, &name)?;
+		let defaults = init_defaults(system, &name);
+
+		transaction.create_dir_all(&repo_path)?;
+
+		let scripts = if !options.default_scripts.or(defaults.default_scripts).unwrap_or(true) {
+			HashMap::new()
+		} else if let Some(configured) = defaults.scripts {
+			configured
+		} else {
+			placeholder_scripts(options.kind)
+		};
+
+		let restrictions = defaults.restrict.unwrap_or_else(|| {
+			#[cfg(unix)]
+			let restrictor = tomlconfig::RestrictorLatest::Unix;
+
+			#[cfg(target_os = "windows")]
+			let restrictor = tomlconfig::RestrictorLatest::Windows;
+
+			HashMap::from([(restrictor, tomlconfig::RestrictorSettings0_2_2 {
+				include: Some(tomlconfig::RestrictRequirement0_2_2::Require),
+				dependencies: None
+			})])
+		});
+
+		let toml = TomlConfigLatest {
+			environment: tomlconfig::EnvironmentLatest::default(),
+			repository: tomlconfig::RepositoryLatest {
+				name: name.clone(),
+				version: options.version
+					.or(defaults.version)
+					.unwrap_or_else(|| semver::Version::new(0, 1, 0)),
+				git: options.git,
+				license: None,
+				kind: options.kind.into()
+			},
+			scripts: tomlconfig::hashmap_to_option_hashmap(scripts),
+			dependencies: None,
+			restrict: tomlconfig::hashmap_to_option_hashmap(restrictions),
+			tools: None,
+			secrets: None,
+			env: None
+		};
+
+		let toml_path = repo_path.join("batl.toml");
+		transaction.snapshot(&toml_path)?;
+		tomlconfig::write_toml(&toml_path, &toml)?;
+
+		let gitignore_path = repo_path.join(".gitignore");
+		let mut gitignore = Gitignore::load(&gitignore_path)?;
+		gitignore.set_block(GEN_BLOCK, gen_patterns());
+		transaction.write(&gitignore_path, gitignore.to_string().as_bytes())?;
+
+		for (file, contents) in starters {
+			let target = repo_path.join(file);
+
+			if target.exists() {
+				continue;
+			}
+
+			if let Some(parent) = target.parent() {
+				transaction.create_dir_all(parent)?;
+			}
+
+			transaction.write(&target, &contents)?;
+		}
+
+		let ownership_path = Ownership::path(&repo_path);
+
+		if let Some(parent) = ownership_path.parent() {
+			transaction.create_dir_all(parent)?;
+		}
+
+		transaction.snapshot(&ownership_path)?;
+		Ownership::claim(&repo_path)?;
+
+		let config = Config::from(toml);
+
+		Ok(Self {
+			path: repo_path,
+			raw: config.clone(),
+			config,
+			name
+		})
+	}
+
+	/// Records a repository outside of the battalion root
+	/// in the batlrc, so it can be loaded by name.
+	/// 
+	/// # Errors
+	/// 
+	/// Propogates any errors found along the way
+	#[inline]
+	pub fn register_external(context: &BatlContext, name: &Name, path: &Path) -> Result<(), batlerror::CreateResourceError> {
+		let batlrc_path = context.system().batlrc_path()
+			.ok_or(batlerror::CreateResourceError::NotSetup)?;
+
+		let mut batlrc = context.system().batlrc().unwrap_or_default();
+		let externals = batlrc.externals.get_or_insert_with(HashMap::new);
+
+		if externals.contains_key(name) {
+			return Err(batlerror::CreateResourceError::AlreadyExists);
+		}
+
+		externals.insert(name.clone(), path.to_path_buf());
+
+		tomlconfig::write_toml(&batlrc_path, &batlrc)?;
+
+		Ok(())
+	}
+
+	/// Removes an external repository from the batlrc. The
+	/// repository files are left untouched.
+	/// 
+	/// # Errors
+	/// 
+	/// Propogates any errors found along the way
+	#[inline]
+	pub fn unregister_external(context: &BatlContext, name: &Name) -> Result<(), batlerror::DeleteResourceError> {
+		let batlrc_path = context.system().batlrc_path()
+			.ok_or(batlerror::DeleteResourceError::DoesNotExist)?;
+
+		let mut batlrc = context.system().batlrc()
+			.ok_or(batlerror::DeleteResourceError::DoesNotExist)?;
+
+		batlrc.externals
+			.as_mut()
+			.and_then(|externals| externals.remove(name))
+			.ok_or(batlerror::DeleteResourceError::DoesNotExist)?;
+
+		tomlconfig::write_toml(&batlrc_path, &batlrc)?;
+
+		Ok(())
+	}
+
+	/// Get all external repositories from the batlrc
+	#[inline]
+	#[must_use]
+	pub fn externals(context: &BatlContext) -> HashMap<Name, PathBuf> {
+		context.system().batlrc()
+			.and_then(|batlrc| batlrc.externals)
+			.unwrap_or_default()
+	}
+
+	/// Whether the repository lives outside of the
+	/// battalion root
+	#[inline]
+	#[must_use]
+	pub fn is_external(&self, context: &BatlContext) -> bool {
+		context.system().repository_root()
+			.is_none_or(|root| !self.path.starts_with(root))
+	}
+
+	/// The git url the repository is developed in, as
+	/// written in its `batl.toml`, for others to set up a
+	/// remote from. Any user name and password or token is
+	/// left out of the url, and `None` is returned for urls
+	/// that depend on environment variables.
+	#[inline]
+	#[must_use]
+	pub fn source_url(&self) -> Option<String> {
+		let url = self.raw.git.as_ref()?.url.as_str();
+
+		if url.contains("${") {
+			return None;
+		}
+
+		// Urls like git@host:path only name the ssh user
+		let Some((scheme, rest)) = url.split_once("://") else {
+			return Some(url.to_owned());
+		};
+
+		let (authority, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+		let host = authority.rsplit_once('@').map_or(authority, |(_, host)| host);
+
+		Some(format!("{scheme}://{host}{path}"))
+	}
+
+	/// The paths a partially fetched repository was limited
+	/// to, or `None` if the repository is complete
+	#[inline]
+	#[must_use]
+	pub fn sparse_paths(&self) -> Option<Vec<PathBuf>> {
+		std::fs::read_to_string(self.path.join(SPARSE_FILE))
+			.ok()
+			.map(|contents| contents.lines().filter(|line| !line.is_empty()).map(PathBuf::from).collect())
+	}
+
+	/// Record that the repository at a path was only
+	/// partially fetched, limited to the given paths
+	/// 
+	/// # Errors
+	/// 
+	/// Propogates any IO errors while writing the list
+	#[inline]
+	pub fn mark_sparse(path: &Path, paths: &[PathBuf]) -> Result<(), std::io::Error> {
+		let sparse_path = path.join(SPARSE_FILE);
+
+		if let Some(parent) = sparse_path.parent() {
+			std::fs::create_dir_all(parent)?;
+		}
+
+		let lines = paths.iter()
+			.map(|sparse| format!("{}\n", sparse.display()))
+			.collect::<Vec<_>>();
+
+		std::fs::write(sparse_path, lines.concat())
+	}
+
+	/// Record that the repository at a path is complete
+	/// again, after fetching it in full
+	/// 
+	/// # Errors
+	/// 
+	/// Propogates any IO errors while removing the list
+	#[inline]
+	pub fn clear_sparse(path: &Path) -> Result<(), std::io::Error> {
+		match std::fs::remove_file(path.join(SPARSE_FILE)) {
+			Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+			result => result
+		}
+	}
+
+	/// Saves the repository, mainly meant for lower
+	/// level utilities.
+	/// 
+	/// # Errors
+	///
+	/// Propogates any errors found along the way
+	#[inline]
+	pub fn save(&self) -> Result<(), std::io::Error> {
+		let toml = TomlConfigLatest::from(self.raw.clone());
+
+		tomlconfig::write_toml(&self.path().to_path_buf().join("batl.toml"), &toml)?;
+		Ownership::touch(self.path())?;
+
+		Ok(())
+	}
+
+	/// Add a dependency to the repository, keeping the
+	/// existing version if it is already a dependency
+	/// 
+	/// # Errors
+	/// 
+	/// Propogates any IO errors received while saving
+	#[inline]
+	pub fn add_dependency(&mut self, name: Name, version: String) -> Result<(), std::io::Error> {
+		if self.config.dependencies.contains_key(&name) {
+			return Ok(());
+		}
+
+		self.config.dependencies.insert(name.clone(), version.clone());
+		self.raw.dependencies.insert(name, version);
+
+		self.save()
+	}
+
+	/// Add a script running a command to the repository.
+	/// With `replace`, an existing script gets the new
+	/// command and keeps the rest of its settings.
+	/// 
+	/// # Errors
+	/// 
+	/// Returns `InvalidName` if the name can't be given on
+	/// the command line, `AlreadyExists` if the script
+	/// exists and isn't replaced, `UndefinedVariable` if the
+	/// command uses an unset variable with strict env set,
+	/// and propogates any IO errors received while saving
+	#[inline]
+	pub fn add_script(&mut self, context: &BatlContext, name: &str, command: String, replace: bool) -> Result<(), batlerror::EditScriptError> {
+		validate_script_name(name)?;
+
+		if self.raw.scripts.contains_key(name) && !replace {
+			return Err(batlerror::EditScriptError::AlreadyExists(name.to_owned()));
+		}
+
+		// Interpolated the way the repository was loaded, so
+		// the script runs as it would after loading it again
+		let interpolated = interpolate(&command, context.settings().strict_env)?;
+
+		self.raw.scripts.entry(name.to_owned())
+			.or_insert_with(|| Script::new(String::new()))
+			.command = command;
+
+		self.config.scripts.entry(name.to_owned())
+			.or_insert_with(|| Script::new(String::new()))
+			.command = interpolated;
+
+		Ok(self.save()?)
+	}
+
+	/// Remove a script from the repository
+	/// 
+	/// # Errors
+	/// 
+	/// Returns `DoesNotExist` if the repository doesn't
+	/// declare the script, and propogates any IO errors
+	/// received while saving
+	#[inline]
+	pub fn remove_script(&mut self, name: &str) -> Result<(), batlerror::EditScriptError> {
+		if self.raw.scripts.remove(name).is_none() {
+			return Err(batlerror::EditScriptError::DoesNotExist(name.to_owned()));
+		}
+
+		self.config.scripts.remove(name);
+
+		Ok(self.save()?)
+	}
+
+	/// The named registry of every transitive dependency
+	/// that doesn't come from the default one, as given by
+	/// the first repository found depending on it.
+	/// Dependencies that aren't available locally aren't
+	/// walked into.
+	#[inline]
+	#[must_use]
+	pub fn dependency_registries(&self, context: &BatlContext) -> HashMap<Name, String> {
+		let mut registries = self.config.registries.clone();
+
+		let mut seen = HashSet::from([self.name().clone()]);
+		let mut pending = self.config.dependencies.keys().cloned().collect::<Vec<_>>();
+
+		while let Some(name) = pending.pop() {
+			if !seen.insert(name.clone()) {
+				continue;
+			}
+
+			if let Some(dependency) = Self::load_in(context, name).ok().flatten() {
+				let found = dependency.config.registries.clone()
+					.into_iter()
+					.filter(|source| !registries.contains_key(&source.0))
+					.collect::<Vec<_>>();
+
+				registries.extend(found);
+				pending.extend(dependency.config.dependencies.keys().cloned());
+			}
+		}
+
+		registries
+	}
+	
+	/// Loads a repository from an absolute path. This
+	/// is never recommended since there are no safety
+	/// checks on the path, but it is available in case
+	/// a situation calls for it.
+	/// 
+	/// # Errors
+	/// 
+	/// Propogates any errors found along the way
+	#[inline]
+	pub fn from_path(path: &Path) -> Result<Self, batlerror::GeneralResourceError> {
+		Self::from_path_in(&BatlContext::detect(), path)
+	}
+
+	/// Loads a repository from an absolute path, as
+	/// `from_path` does, with the settings of a context
+	/// 
+	/// # Errors
+	/// 
+	/// Propogates any errors found along the way
+	#[inline]
+	pub fn from_path_in(context: &BatlContext, path: &Path) -> Result<Self, batlerror::GeneralResourceError> {
+		let repository = Self::from_path_unverified(context, path)?;
+		repository.verify_name(context)?;
+
+		Ok(repository)
+	}
+
+	/// Loads a repository from an absolute path as
+	/// `from_path_in` does, without checking its declared
+	/// name against where it is stored. Only meant for
+	/// reconciling the two.
+	/// 
+	/// # Errors
+	/// 
+	/// Propogates any errors found along the way
+	#[inline]
+	pub fn from_path_unverified(context: &BatlContext, path: &Path) -> Result<Self, batlerror::GeneralResourceError> {
+		let toml = AnyTomlConfig::read_toml_with(&path.join("batl.toml"), context.settings().strict)?;
+		let raw = Config::from(TomlConfigLatest::from(toml));
+		let config = raw.interpolated(context.settings().strict_env)?;
+
+		let stored_name = context.system().repository_root()
+			.map(|root| Name::from_path_in(&root, path))
+			.transpose()?
+			.flatten();
+
+		Ok(Self {
+			name: stored_name.unwrap_or_else(|| config.name.clone()),
+			path: path.to_path_buf(),
+			config,
+			raw
+		})
+	}
+
+	/// The name declared in the `batl.toml`, if it doesn't
+	/// match the name the repository is stored under, as
+	/// happens when its folder is moved or copied by hand
+	#[inline]
+	#[must_use]
+	pub fn declared_name(&self, context: &BatlContext) -> Option<Name> {
+		let declared = self.raw.name.clone().normalized(context.settings().name_case);
+
+		(declared != self.name).then_some(declared)
+	}
+
+	/// Fail if the declared name doesn't match the stored
+	/// one and the context is strict. Otherwise the mismatch
+	/// is left for `declared_name` to report.
+	fn verify_name(&self, context: &BatlContext) -> Result<(), batlerror::GeneralResourceError> {
+		match self.declared_name(context) {
+			Some(declared) if context.settings().strict => Err(batlerror::GeneralResourceError::NameMismatch(
+				format!("{declared} is stored as {}", self.name)
+			)),
+			Some(_) | None => Ok(())
+		}
+	}
+
+	/// Declare the name the repository is stored under in
+	/// its `batl.toml`
+	/// 
+	/// # Errors
+	/// 
+	/// Propogates any IO errors received while saving
+	#[inline]
+	pub fn adopt_stored_name(&mut self) -> Result<(), std::io::Error> {
+		self.config.name = self.name.clone();
+		self.raw.name = self.name.clone();
+
+		self.save()
+	}
+
+	/// Store the repository under the name declared in its
+	/// `batl.toml`, moving its folder within the battalion
+	/// root or registering an external repository under it.
+	/// Workspace links to the old name are left as they are.
+	/// 
+	/// # Errors
+	/// 
+	/// Returns `AlreadyExists` if a repository is stored
+	/// under the declared name, and `InvalidName` if the
+	/// declared name would be stored inside the repository.
+	/// Propogates any other errors found along the way.
+	#[inline]
+	pub fn move_to_declared_name(self, context: &BatlContext) -> Result<Self, batlerror::CreateResourceError> {
+		let Some(declared) = self.declared_name(context) else {
+			return Ok(self);
+		};
+
+		if self.is_external(context) {
+			let batlrc_path = context.system().batlrc_path()
+				.ok_or(batlerror::CreateResourceError::NotSetup)?;
+
+			let mut batlrc = context.system().batlrc().unwrap_or_default();
+			let externals = batlrc.externals.get_or_insert_with(HashMap::new);
+
+			if externals.contains_key(&declared) {
+				return Err(batlerror::CreateResourceError::AlreadyExists);
+			}
 
-		if repo_path.exists() {
-			return Err(batlerror::CreateResourceError::AlreadyExists);
-		}
+			externals.remove(&self.name);
+			externals.insert(declared.clone(), self.path.clone());
 
-		std::fs::create_dir_all(&repo_path)?;
+			tomlconfig::write_toml(&batlrc_path, &batlrc)?;
 
-		let mut scripts = HashMap::new();
-		scripts.insert("build".to_owned(), "echo \"No build targets\" && exit 1".to_owned());
+			return Ok(Self { name: declared, ..self });
+		}
 
-		let mut restrictions = HashMap::new();
+		let repo_root = context.system().repository_root()
+			.ok_or(batlerror::CreateResourceError::NotSetup)?;
 
-		#[cfg(unix)]
-		let restrictor = tomlconfig::RestrictorLatest::Unix;
+		super::check_collision(&repo_root, &declared)?;
 
-		#[cfg(target_os = "windows")]
-		let restrictor = tomlconfig::RestrictorLatest::Windows;
+		let repo_path = repo_root.join(PathBuf::from(&declared));
 
-		restrictions.insert(restrictor, tomlconfig::RestrictorSettings0_2_2 {
-			include: Some(tomlconfig::RestrictRequirement0_2_2::Require),
-			dependencies: None
-		});
+		if repo_path.exists() {
+			return Err(batlerror::CreateResourceError::AlreadyExists);
+		}
 
-		let toml = TomlConfigLatest {
-			environment: tomlconfig::EnvironmentLatest::default(),
-			repository: tomlconfig::RepositoryLatest {
-				name: name.clone(),
-				version: semver::Version::new(0, 1, 0),
-				git: options.git
-			},
-			scripts: Some(scripts),
-			dependencies: None,
-			restrict: Some(restrictions)
-		};
+		if repo_path.starts_with(&self.path) {
+			return Err(batlerror::CreateResourceError::InvalidName(declared.to_string()));
+		}
+
+		if let Some(parent) = repo_path.parent() {
+			std::fs::create_dir_all(parent)?;
+		}
 
-		tomlconfig::write_toml(&repo_path.join("batl.toml"), &toml)?;
+		std::fs::rename(&self.path, &repo_path)?;
 
 		Ok(Self {
 			path: repo_path,
-			config: toml.into(),
-			name
+			name: declared,
+			..self
 		})
 	}
 
-	/// Saves the repository, mainly meant for lower
-	/// level utilities.
-	/// 
-	/// # Errors
-	///
-	/// Propogates any errors found along the way
-	#[inline]
-	pub fn save(&self) -> Result<(), std::io::Error> {
-		let toml = TomlConfigLatest::from(self.config.clone());
-
-		tomlconfig::write_toml(&self.path().to_path_buf().join("batl.toml"), &toml)
-	}
-	
-	/// Loads a repository from an absolute path. This
-	/// is never recommended since there are no safety
-	/// checks on the path, but it is available in case
-	/// a situation calls for it.
+	/// Searches the path - along with all of its
+	/// parents - for a working configuration.
 	/// 
 	/// # Errors
 	/// 
 	/// Propogates any errors found along the way
+	/// Returns `None` if no repository is found
 	#[inline]
-	pub fn from_path(path: &Path) -> Result<Self, batlerror::GeneralResourceError> {
-		let toml = AnyTomlConfig::read_toml(&path.join("batl.toml"))?;
-		let latest = TomlConfigLatest::from(toml);
-
-		Ok(Self {
-			name: path.into(),
-			path: path.to_path_buf(),
-			config: Config::from(latest)
-		})
+	pub fn locate_then_load(path: &Path) -> Result<Option<Self>, batlerror::GeneralResourceError> {
+		Self::locate_then_load_in(&BatlContext::detect(), path)
 	}
 
-	/// Searches the path - along with all of its
-	/// parents - for a working configuration.
+	/// Searches the path and its parents for a working
+	/// configuration, as `locate_then_load` does, with the
+	/// settings of a context
 	/// 
 	/// # Errors
 	/// 
 	/// Propogates any errors found along the way
 	/// Returns `None` if no repository is found
 	#[inline]
-	pub fn locate_then_load(path: &Path) -> Result<Option<Self>, batlerror::GeneralResourceError> {
+	pub fn locate_then_load_in(context: &BatlContext, path: &Path) -> Result<Option<Self>, batlerror::GeneralResourceError> {
 		TomlConfigLatest::locate(path)
 			.and_then(|p| p.parent().map(Path::to_path_buf))
-			.map(|p| Self::from_path(&p))
+			.map(|p| Self::from_path_in(context, &p))
 			.transpose()
 	}
 
 	/// Get the scripts hashmap
 	#[inline]
 	#[must_use]
-	pub fn scripts(&self) -> HashMap<String, String> {
+	pub fn scripts(&self) -> HashMap<String, Script> {
 		self.config.scripts.clone()
 	}
 
-	/// Get a specific script
+	/// The command of a script of the repository as written
+	/// in its `batl.toml`, before environment variables are
+	/// interpolated into it, for showing it without the
+	/// values they hold
+	#[inline]
+	#[must_use]
+	pub fn written_command(&self, script: &str) -> Option<&str> {
+		self.raw.scripts.get(script).map(|written| written.command.as_str())
+	}
+
+	/// Get every script that can be run in the repository.
+	/// The repository's own scripts take precedence over
+	/// user scripts of the same name.
+	#[inline]
+	#[must_use]
+	pub fn available_scripts(&self, context: &BatlContext) -> HashMap<String, (Script, ScriptSource)> {
+		let mut scripts = context.system().user_scripts()
+			.into_iter()
+			.map(|(name, script)| (name, (script, ScriptSource::User)))
+			.collect::<HashMap<_, _>>();
+
+		scripts.extend(
+			self.scripts()
+				.into_iter()
+				.map(|(name, script)| (name, (script, ScriptSource::Repository)))
+		);
+
+		scripts
+	}
+
+	/// Get a specific script, falling back to the user
+	/// scripts if the repository doesn't define it
 	#[inline]
 	#[must_use]
-	pub fn script(&self, name: &str) -> Option<String> {
-		self.scripts().get(name).cloned()
+	pub fn script(&self, context: &BatlContext, name: &str) -> Option<Script> {
+		self.scripts()
+			.remove(name)
+			.or_else(|| context.system().user_scripts().remove(name))
+	}
+
+	/// The scripts to run for a script, in order: what it
+	/// depends on, each after its own dependencies, then the
+	/// script itself, then its post hooks. Scripts needed
+	/// more than once run the first time only.
+	/// 
+	/// # Errors
+	/// 
+	/// Returns `Missing` for a script that can't be found,
+	/// and `Cycle` for scripts that depend on each other
+	#[inline]
+	pub fn script_chain(&self, context: &BatlContext, name: &str) -> Result<Vec<(String, Script)>, batlerror::ScriptChainError> {
+		let mut chain = Vec::new();
+		self.chain_into(context, name, name, &mut Vec::new(), &mut chain)?;
+
+		Ok(chain)
+	}
+
+	/// Add a script and everything it needs to a chain,
+	/// after what is already in it. `pending` holds the
+	/// scripts waiting on their dependencies, to find
+	/// cycles.
+	fn chain_into(&self, context: &BatlContext, name: &str, needed_by: &str, pending: &mut Vec<String>, chain: &mut Vec<(String, Script)>) -> Result<(), batlerror::ScriptChainError> {
+		if chain.iter().any(|step| step.0 == name) {
+			return Ok(());
+		}
+
+		if let Some(start) = pending.iter().position(|waiting| waiting == name) {
+			let cycle = pending.get(start..)
+				.unwrap_or_default()
+				.iter()
+				.map(String::as_str)
+				.chain([name])
+				.collect::<Vec<_>>();
+
+			return Err(batlerror::ScriptChainError::Cycle(cycle.join(" -> ")));
+		}
+
+		let script = self.script(context, name)
+			.ok_or_else(|| batlerror::ScriptChainError::Missing(name.to_owned(), needed_by.to_owned()))?;
+
+		pending.push(name.to_owned());
+
+		for dependency in &script.depends_on {
+			self.chain_into(context, dependency, name, pending, chain)?;
+		}
+
+		pending.pop();
+
+		let hooks = script.post.clone();
+		chain.push((name.to_owned(), script));
+
+		for hook in &hooks {
+			self.chain_into(context, hook, name, pending, chain)?;
+		}
+
+		Ok(())
 	}
 
 	/// Destroy the repository from the filesystem, this
-	/// is not reversible!
+	/// is not reversible! External repositories are only
+	/// unregistered, their files are left in place.
 	/// 
 	/// # Errors
 	/// Propogates any errors found along the way
 	#[inline]
-	pub fn destroy(self) -> Result<(), batlerror::DeleteResourceError> {
+	pub fn destroy(self, context: &BatlContext) -> Result<(), batlerror::DeleteResourceError> {
+		if self.is_external(context) {
+			return Self::unregister_external(context, &self.name);
+		}
+
 		std::fs::remove_dir_all(self.path())?;
 
 		Ok(())
 	}
 
-	/// Creates an archive, this is deprecated
+	/// The files an archive of the repository holds, after
+	/// the ignore rules, with their sizes in bytes. Paths
+	/// are relative to the repository and sorted.
 	/// 
 	/// # Errors
 	/// 
-	/// Propogates any errors found along the way
-	#[deprecated]
+	/// Returns `Invalid` if the repository can't be walked
 	#[inline]
-	pub fn archive_gen(&self) -> Result<Archive, batlerror::CreateDependentResourceError> {
+	pub fn package_files(&self) -> Result<Vec<(String, u64)>, batlerror::GeneralResourceError> {
 		let mut walk_builder = ignore::WalkBuilder::new(self.path());
 
 		if let Some(git) = self.config().git.clone() {
@@ -207,41 +1186,124 @@ impl Repository {
 
 		walk_builder.add_custom_ignore_filename("batl.ignore");
 
-		let walk = walk_builder.build();
+		let entries = walk_builder.build()
+			.collect::<Result<Vec<_>, _>>()
+			.map_err(|_err| batlerror::GeneralResourceError::Invalid)?;
+
+		let mut files = entries.iter()
+			.filter_map(|entry| {
+				let metadata = std::fs::metadata(entry.path()).ok().filter(std::fs::Metadata::is_file)?;
+				let rel_path = pathdiff::diff_paths(entry.path(), self.path())?;
+
+				Some((rel_path.to_string_lossy().into_owned(), metadata.len()))
+			})
+			.collect::<Vec<_>>();
+
+		files.sort();
+
+		Ok(files)
+	}
+
+	/// Creates an archive, this is deprecated
+	/// 
+	/// The options choose how the archive that is uploaded
+	/// is compressed, and whether files that didn't change
+	/// since the last archive are reused from it.
+	/// 
+	/// # Errors
+	/// 
+	/// Propogates any errors found along the way
+	#[deprecated]
+	#[inline]
+	pub fn archive_gen(&self, context: &BatlContext, options: BuildOptions) -> Result<Archive, batlerror::CreateDependentResourceError> {
+		let files = self.package_files()?;
 
-		let tar_path = crate::system::archive_root()
+		let tar_path = context.system().archive_root()
 			.ok_or(batlerror::CreateResourceError::NotSetup)?
 			.join("repositories")
 			.join(format!("{}.tar", self.name));
 
+		let size = files.iter().map(|file| file.1).sum();
+
 		if let Some(tar_parent) = tar_path.parent() {
+			let tar_name = format!("{}.{}", self.name, options.compression.extension());
+			preflight::check_path_lengths(tar_parent, [tar_name.as_str()])?;
+			preflight::check_space(tar_parent, size)?;
+
 			std::fs::create_dir_all(tar_parent)?;
 		}
 
-		let mut archive = tar::Builder::new(std::fs::File::create(&tar_path)?);
+		Ok(Archive::build(self.path(), &files, tar_path, options)?)
+	}
+
+	/// Copies the repository to a new name. Generated files
+	/// and symbolic links are left out, the name is
+	/// rewritten, and the version is reset. Stripping git
+	/// leaves out the git history and configuration, but
+	/// keeps the checked out files. A copy that fails partway
+	/// is removed.
+	/// 
+	/// # Errors
+	/// 
+	/// Propogates any errors found along the way
+	#[inline]
+	pub fn duplicate(&self, context: &BatlContext, name: Name, options: &CopyRepositoryOptions) -> Result<Self, batlerror::CreateDependentResourceError> {
+		let normal_name = name.normalized(context.settings().name_case);
+
+		let repo_root = context.system().repository_root()
+			.ok_or(batlerror::CreateResourceError::NotSetup)?;
 
-		for result in walk {
-			let entry = result.map_err(|_err| batlerror::GeneralResourceError::Invalid)?;
+		super::check_collision(&repo_root, &normal_name)?;
 
-			let abs_path = entry.path();
+		let repo_path = repo_root.join(PathBuf::from(&normal_name));
 
-			if abs_path.is_dir() {
-				continue;
+		if repo_path.exists() {
+			return Err(batlerror::CreateResourceError::AlreadyExists.into());
+		}
+
+		let mut skipped = vec![self.path.join("gen")];
+
+		// Only the history goes, the checked out files are
+		// kept as part of the copy
+		if options.strip_git {
+			skipped.push(self.path.join(".git"));
+
+			if let Some(git) = self.config.git.as_ref() {
+				skipped.push(self.path.join(&git.path).join(".git"));
 			}
+		}
+
+		// A copy that fails partway is removed again
+		let mut transaction = Transaction::default();
+		transaction.create_dir_all(&repo_path)?;
+
+		copy_dir(&self.path, &repo_path, &skipped)?;
 
-			let rel_path_opt = pathdiff::diff_paths(abs_path, self.path());
+		let mut config = self.config.clone();
+		let mut raw = self.raw.clone();
 
-			if let Some(rel_path) = rel_path_opt {				
-				archive.append_path_with_name(abs_path, rel_path)?;
+		for copied in [&mut config, &mut raw] {
+			copied.name = normal_name.clone();
+			copied.version = Version::new(0, 1, 0);
+
+			if options.strip_git {
+				copied.git = None;
 			}
 		}
 
-		let archive_file = archive.into_inner()?;
+		let copy = Self {
+			path: repo_path,
+			config,
+			raw,
+			name: normal_name
+		};
 
-		Ok(Archive {
-			tar: tar::Archive::new(archive_file),
-			path: tar_path
-		})
+		copy.save()?;
+		Ownership::claim(&copy.path)?;
+
+		transaction.commit();
+
+		Ok(copy)
 	}
 
 	/// Get the archive for this repository
@@ -249,8 +1311,153 @@ impl Repository {
 	/// Returns `None` if it has not been generated
 	#[inline]
 	#[must_use]
-	pub fn archive(&self) -> Option<Archive> {
-		Archive::load(&self.name).ok().flatten()
+	pub fn archive(&self, context: &BatlContext) -> Option<Archive> {
+		Archive::load_in(context.system(), &self.name).ok().flatten()
+	}
+}
+
+/// Recursively copies a directory, leaving out symbolic
+/// links and any of the skipped paths
+fn copy_dir(from: &Path, to: &Path, skipped: &[PathBuf]) -> Result<(), std::io::Error> {
+	std::fs::create_dir_all(to)?;
+
+	for dir_entry in std::fs::read_dir(from)? {
+		let entry = dir_entry?;
+		let path = entry.path();
+		let file_type = entry.file_type()?;
+
+		if file_type.is_symlink() || skipped.contains(&path) {
+			continue;
+		}
+
+		let target = to.join(entry.file_name());
+
+		if file_type.is_dir() {
+			copy_dir(&path, &target, skipped)?;
+		} else {
+			std::fs::copy(&path, &target)?;
+		}
+	}
+
+	Ok(())
+}
+
+/// Read the starter files from the batlrc, with the name
+/// of the new repository filled in. Everything is read up
+/// front so a bad starter doesn't leave a repository half
+/// created.
+fn read_starters(system: &crate::system::Context, name: &Name) -> Result<Vec<(PathBuf, Vec<u8>)>, batlerror::CreateResourceError> {
+	let files = system.starter_files();
+
+	if files.is_empty() {
+		return Ok(Vec::new());
+	}
+
+	let starter_root = system.starter_root()
+		.ok_or(batlerror::CreateResourceError::NotSetup)?;
+
+	files.into_iter()
+		.map(|file| {
+			if !file.components().all(|component| matches!(component, std::path::Component::Normal(_))) {
+				return Err(batlerror::CreateResourceError::InvalidStarter(
+					format!("{} must be relative to {}", file.display(), starter_root.display())
+				));
+			}
+
+			let contents = std::fs::read(starter_root.join(&file)).map_err(|err| {
+				if err.kind() == std::io::ErrorKind::NotFound {
+					batlerror::CreateResourceError::InvalidStarter(
+						format!("{} does not exist in {}", file.display(), starter_root.display())
+					)
+				} else {
+					err.into()
+				}
+			})?;
+
+			let filled = match String::from_utf8(contents) {
+				Ok(text) => text.replace(NAME_PLACEHOLDER, &name.to_string()).into_bytes(),
+				Err(binary) => binary.into_bytes()
+			};
+
+			Ok((file, filled))
+		})
+		.collect()
+}
+
+/// What a new repository starts with, from `[init]` of
+/// the batlrc, with the settings of its most specific
+/// namespace over the rest
+fn init_defaults(system: &crate::system::Context, name: &Name) -> batlrc::InitNamespace0_2_2 {
+	let Some(init) = system.batlrc().and_then(|rc| rc.init) else {
+		return batlrc::InitNamespace0_2_2::default();
+	};
+
+	let full_name = name.to_string();
+
+	let namespace = init.namespaces
+		.unwrap_or_default()
+		.into_iter()
+		.filter(|candidate| full_name.starts_with(&format!("{}/", candidate.0)))
+		.max_by_key(|candidate| candidate.0.len())
+		.map(|candidate| candidate.1)
+		.unwrap_or_default();
+
+	batlrc::InitNamespace0_2_2 {
+		version: namespace.version.or(init.version),
+		default_scripts: namespace.default_scripts.or(init.default_scripts),
+		scripts: namespace.scripts.or(init.scripts),
+		restrict: namespace.restrict.or(init.restrict)
+	}
+}
+
+/// The scripts a new repository starts with when none are
+/// configured, which fail until they are filled in
+fn placeholder_scripts(kind: Kind) -> HashMap<String, tomlconfig::Script0_2_2> {
+	let mut scripts = HashMap::new();
+
+	// Meta repositories run scripts across their
+	// dependencies, so they start without any
+	if kind != Kind::Meta {
+		scripts.insert(
+			"build".to_owned(),
+			tomlconfig::Script0_2_2::Command("echo \"No build targets\" && exit 1".to_owned())
+		);
+	}
+
+	if kind == Kind::Tool {
+		scripts.insert(
+			INSTALL_SCRIPT.to_owned(),
+			tomlconfig::Script0_2_2::Command("echo \"No install steps\" && exit 1".to_owned())
+		);
+	}
+
+	scripts
+}
+
+/// The patterns of the `.gitignore` block ignoring the
+/// generated files
+fn gen_patterns() -> Vec<String> {
+	vec![format!("/{GEN_DIR}/")]
+}
+
+/// Get the path of an external repository from the batlrc
+fn external_path(system: &crate::system::Context, name: &Name) -> Option<PathBuf> {
+	system.batlrc()?
+		.externals?
+		.remove(name)
+}
+
+/// Check that a script name can be given on the command
+/// line, starting with a letter or digit and holding only
+/// those, `-`, `_`, `:` and `.`
+fn validate_script_name(name: &str) -> Result<(), batlerror::EditScriptError> {
+	let valid = name.starts_with(|c: char| c.is_ascii_alphanumeric())
+		&& name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | ':' | '.'));
+
+	if valid {
+		Ok(())
+	} else {
+		Err(batlerror::EditScriptError::InvalidName(name.to_owned()))
 	}
 }
 
@@ -279,116 +1486,120 @@ pub struct Config {
 	pub name: Name,
 	pub version: Version,
 	pub git: Option<GitConfig>,
-	pub scripts: HashMap<String, String>,
+	pub license: Option<String>,
+	pub kind: Kind,
+	pub scripts: HashMap<String, Script>,
 	pub dependencies: HashMap<Name, String>,
-	pub restrict: HashMap<Condition, RestrictSettings>
-}
 
-#[derive(Clone)]
-#[non_exhaustive]
-pub struct GitConfig {
-	pub url: String,
-	pub path: String
-}
+	/// The named registry of each dependency that doesn't
+	/// come from the default one
+	pub registries: HashMap<Name, String>,
+	pub restrict: HashMap<Condition, RestrictSettings>,
+	pub tools: HashMap<String, VersionReq>,
+	pub secrets: Allowlist,
 
-#[non_exhaustive]
-pub enum AnyTomlConfig {
-	V0_2_2(TomlConfig0_2_2),
-	V0_2_1(TomlConfig0_2_1),
-	V0_2_0(TomlConfig0_2_0)
+	/// Environment variables set for every script, under
+	/// those the script sets itself
+	pub env: HashMap<String, String>
 }
 
-#[allow(clippy::missing_trait_methods)]
-impl TomlConfig for AnyTomlConfig {
+impl Config {
+	/// Parse the contents of a batl.toml of any version
+	///
+	/// # Errors
+	///
+	/// Returns the error if the contents can't be parsed
 	#[inline]
-	fn read_toml(path: &Path) -> Result<Self, batlerror::ReadConfigError> {
-		let config_str = std::fs::read_to_string(path)?;
+	pub fn parse(contents: &str) -> Result<Self, batlerror::ReadConfigError> {
+		Ok(Self::from(TomlConfigLatest::from(AnyTomlConfig::try_from(contents)?)))
+	}
 
-		if let Ok(v022) = toml::from_str(&config_str) {
-			return Ok(Self::V0_2_2(v022));
-		}
+	/// Interpolate environment variables into the script
+	/// commands, the environments of the repository and its
+	/// scripts, and git url, failing
+	/// on unset variables when `strict` is set
+	fn interpolated(&self, strict: bool) -> Result<Self, batlerror::ReadConfigError> {
+		let mut config = self.clone();
 
-		if let Ok(v022) = toml::from_str(&config_str) {
-			return Ok(Self::V0_2_1(v022));
-		}
+		config.scripts.values_mut().try_for_each(|script| {
+			script.command = interpolate(&script.command, strict)?;
 
-		Ok(Self::V0_2_0(toml::from_str(&config_str)?))
-	}
-}
+			script.env.values_mut()
+				.try_for_each(|value| interpolate(value, strict).map(|interpolated| *value = interpolated))
+		})?;
 
-impl From<AnyTomlConfig> for TomlConfigLatest {
-	#[inline]
-	fn from(value: AnyTomlConfig) -> Self {
-		match value {
-			AnyTomlConfig::V0_2_0(v020) => v020.into(),
-			AnyTomlConfig::V0_2_1(v021) => v021.into(),
-			AnyTomlConfig::V0_2_2(v022) => v022
+		config.env.values_mut()
+			.try_for_each(|value| interpolate(value, strict).map(|interpolated| *value = interpolated))?;
+
+		if let Some(git) = config.git.as_mut() {
+			git.url = interpolate(&git.url, strict)?;
 		}
+
+		Ok(config)
 	}
 }
 
-// CONFIG VERSIONS //
-pub type TomlConfigLatest = TomlConfig0_2_2;
-
-#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[derive(Clone)]
 #[non_exhaustive]
-pub struct TomlConfig0_2_2 {
-	pub environment: tomlconfig::Environment0_2_2,
-	pub repository: tomlconfig::Repository0_2_2,
-	pub scripts: Option<tomlconfig::Scripts0_2_2>,
-	pub dependencies: Option<tomlconfig::Dependencies0_2_2>,
-	pub restrict: Option<tomlconfig::Restrict0_2_2>
-}
+pub struct GitConfig {
+	pub url: String,
+	pub path: String,
 
-#[derive(Serialize, Deserialize, Clone, PartialEq)]
-#[non_exhaustive]
-pub struct TomlConfig0_2_1 {
-	pub environment: tomlconfig::Environment0_2_1,
-	pub repository: tomlconfig::Repository0_2_1,
-	pub scripts: Option<tomlconfig::Scripts0_2_1>,
-	pub dependencies: Option<tomlconfig::Dependencies0_2_1>
+	/// Clone only this many commits of history, or all of
+	/// it if `None`
+	pub depth: Option<u32>,
+
+	/// The branch to check out, or the remote's default if
+	/// `None`
+	pub branch: Option<String>,
+
+	/// Paths of the working tree to check out, or all of
+	/// it if empty
+	pub sparse: Vec<String>
 }
 
-impl From<TomlConfig0_2_1> for TomlConfigLatest {
-	#[inline]
-	fn from(value: TomlConfig0_2_1) -> Self {
-		Self {
-			environment: tomlconfig::EnvironmentLatest::default(),
-			repository: tomlconfig::RepositoryLatest {
-				name: value.repository.name,
-				version: value.repository.version,
-				git: value.repository.git
-			},
-			scripts: value.scripts,
-			dependencies: value.dependencies,
-			restrict: None
-		}
+// CONFIG VERSIONS //
+pub type TomlConfigLatest = TomlConfig0_2_2;
+
+versioned_config! {
+	#[non_exhaustive]
+	pub enum AnyTomlConfig;
+
+	#[derive(Serialize, Deserialize, JsonSchema, Clone, PartialEq)]
+	#[schemars(rename = "RepositoryConfig")]
+	#[non_exhaustive]
+	pub struct TomlConfig0_2_2 {
+		pub repository: tomlconfig::Repository0_2_2,
+		pub scripts: Option<tomlconfig::Scripts0_2_2>,
+		pub dependencies: Option<tomlconfig::RepositoryDependencies0_2_2>,
+		pub restrict: Option<tomlconfig::Restrict0_2_2>,
+
+		/// Versions of each tool the repository needs, as
+		/// semver requirements
+		#[schemars(with = "Option<HashMap<String, String>>")]
+		pub tools: Option<tomlconfig::Tools0_2_2>,
+
+		/// What the secret scan lets through when publishing
+		pub secrets: Option<tomlconfig::Secrets0_2_2>,
+
+		/// Environment variables set for every script
+		pub env: Option<tomlconfig::Env0_2_2>
 	}
-}
 
-#[derive(Serialize, Deserialize, Clone, PartialEq)]
-#[non_exhaustive]
-pub struct TomlConfig0_2_0 {
-	pub environment: tomlconfig::Environment0_2_0,
-	pub repository: tomlconfig::Repository0_2_0,
-	pub scripts: Option<tomlconfig::Scripts0_2_0>,
-	pub dependencies: Option<tomlconfig::Dependencies0_2_0>
-}
+	#[derive(Serialize, Deserialize, Clone, PartialEq)]
+	#[non_exhaustive]
+	pub struct TomlConfig0_2_1 {
+		pub repository: tomlconfig::Repository0_2_1 => with Into::into,
+		pub scripts: Option<tomlconfig::Scripts0_2_1> => with tomlconfig::upgrade_scripts,
+		pub dependencies: Option<tomlconfig::Dependencies0_2_1> => with tomlconfig::upgrade_dependencies
+	}
 
-impl From<TomlConfig0_2_0> for TomlConfigLatest {
-	#[inline]
-	fn from(value: TomlConfig0_2_0) -> Self {
-		Self {
-			environment: tomlconfig::EnvironmentLatest::default(),
-			repository: tomlconfig::RepositoryLatest {
-				name: value.repository.name,
-				version: value.repository.version,
-				git: value.repository.git
-			},
-			scripts: value.scripts,
-			dependencies: value.dependencies,
-			restrict: None
-		}
+	#[derive(Serialize, Deserialize, Clone, PartialEq)]
+	#[non_exhaustive]
+	pub struct TomlConfig0_2_0 {
+		pub repository: tomlconfig::Repository0_2_0 => with Into::into,
+		pub scripts: Option<tomlconfig::Scripts0_2_0> => with tomlconfig::upgrade_scripts,
+		pub dependencies: Option<tomlconfig::Dependencies0_2_0> => with tomlconfig::upgrade_dependencies
 	}
 }
 
@@ -397,22 +1608,56 @@ impl From<TomlConfig0_2_2> for Config {
 	fn from(value: TomlConfig0_2_2) -> Self {
 		let git = value.repository.git.map(|toml| GitConfig {
 			url: toml.url,
-			path: toml.path
+			path: toml.path,
+			depth: toml.depth,
+			branch: toml.branch,
+			sparse: toml.sparse.unwrap_or_default()
 		});
 
+		let scripts = value.scripts
+			.unwrap_or_default()
+			.into_iter()
+			.map(|(k, v)| (k, v.into()))
+			.collect::<HashMap<_, _>>();
+
 		let restrict = value.restrict
 			.unwrap_or_default()
 			.into_iter()
 			.map(|(k, v)| (k.into(), v.into()))
 			.collect::<HashMap<_, _>>();
 
+		let parts = value.dependencies
+			.unwrap_or_default()
+			.into_iter()
+			.map(|(name, dependency)| (name, dependency.into_parts()))
+			.collect::<Vec<_>>();
+
+		let registries = parts.iter()
+			.filter_map(|part| part.1.1.clone().map(|named| (part.0.clone(), named)))
+			.collect::<HashMap<_, _>>();
+
+		let dependencies = parts.into_iter()
+			.map(|part| (part.0, part.1.0))
+			.collect::<HashMap<_, _>>();
+
+		let secrets = value.secrets.map_or_else(Allowlist::default, |toml| Allowlist::new(
+			toml.allow.unwrap_or_default(),
+			toml.allow_rules.unwrap_or_default()
+		));
+
 		Self {
 			name: value.repository.name,
 			version: value.repository.version,
 			git,
-			scripts: value.scripts.unwrap_or_default(),
-			dependencies: value.dependencies.unwrap_or_default(),
-			restrict
+			license: value.repository.license,
+			kind: value.repository.kind.map(Kind::from).unwrap_or_default(),
+			scripts,
+			dependencies,
+			registries,
+			restrict,
+			tools: value.tools.unwrap_or_default(),
+			secrets,
+			env: value.env.unwrap_or_default()
 		}
 	}
 }
@@ -422,23 +1667,127 @@ impl From<Config> for TomlConfigLatest {
 	fn from(value: Config) -> Self {
 		let git = value.git.map(|conf| tomlconfig::RepositoryGit0_2_2 {
 			url: conf.url,
-			path: conf.path
+			path: conf.path,
+			depth: conf.depth,
+			branch: conf.branch,
+			sparse: (!conf.sparse.is_empty()).then_some(conf.sparse)
 		});
 
+		let scripts = value.scripts.into_iter()
+			.map(|(k, v)| (k, v.into()))
+			.collect::<HashMap<_, _>>();
+
 		let restrict = value.restrict.into_iter()
 			.map(|(k, v)| (k.into(), v.into()))
 			.collect::<HashMap<_, _>>();
 
+		let mut registries = value.registries;
+
+		let dependencies = value.dependencies.into_iter()
+			.map(|(name, requirement)| {
+				let registry = registries.remove(&name);
+				(name, tomlconfig::DependencyLatest::new(requirement, registry))
+			})
+			.collect::<HashMap<_, _>>();
+
 		Self {
 			environment: tomlconfig::EnvironmentLatest::default(),
 			repository: tomlconfig::RepositoryLatest {
 				name: value.name,
 				version: value.version,
-				git
+				git,
+				license: value.license,
+				kind: value.kind.into()
 			},
-			scripts: tomlconfig::hashmap_to_option_hashmap(value.scripts),
-			dependencies: tomlconfig::hashmap_to_option_hashmap(value.dependencies),
-			restrict: tomlconfig::hashmap_to_option_hashmap(restrict)
+			scripts: tomlconfig::hashmap_to_option_hashmap(scripts),
+			dependencies: tomlconfig::hashmap_to_option_hashmap(dependencies),
+			restrict: tomlconfig::hashmap_to_option_hashmap(restrict),
+			tools: tomlconfig::hashmap_to_option_hashmap(value.tools),
+			secrets: (!value.secrets.is_empty()).then(|| tomlconfig::SecretsLatest {
+				allow: (!value.secrets.paths.is_empty()).then_some(value.secrets.paths),
+				allow_rules: (!value.secrets.rules.is_empty()).then_some(value.secrets.rules)
+			}),
+			env: tomlconfig::hashmap_to_option_hashmap(value.env)
 		}
 	}
 }
+
+#[cfg(test)]
+#[allow(clippy::panic_in_result_fn, reason = "tests fail on their assertions, and on errors setting them up")]
+mod tests {
+	use super::{Config, Repository, TomlConfigLatest};
+	use crate::context::BatlContext;
+	use crate::error::ScriptChainError;
+	use core::error::Error;
+	use std::path::PathBuf;
+
+
+	/// A repository with the given scripts, kept in memory
+	fn repository(scripts: &str) -> Result<Repository, Box<dyn Error>> {
+		let toml = format!("[environment]\nversion = \"0.2.2\"\n\n[repository]\nname = \"acme/lib\"\nversion = \"0.1.0\"\n\n[scripts]\n{scripts}");
+		let config = Config::from(toml::from_str::<TomlConfigLatest>(&toml)?);
+
+		Ok(Repository {
+			path: PathBuf::new(),
+			name: config.name.clone(),
+			raw: config.clone(),
+			config
+		})
+	}
+
+	/// A context without user scripts, whose root doesn't
+	/// exist
+	fn context() -> BatlContext {
+		BatlContext::with_root(std::env::temp_dir().join("batl-no-root"))
+	}
+
+	/// The names of the scripts in the chain of a script
+	fn chain(repository: &Repository, name: &str) -> Result<Vec<String>, ScriptChainError> {
+		Ok(repository.script_chain(&context(), name)?.into_iter().map(|step| step.0).collect())
+	}
+
+	#[test]
+	fn dependencies_run_first_and_once() -> Result<(), Box<dyn Error>> {
+		let repository = repository(r#"
+a = { command = "echo a", depends_on = ["b", "c"], post = ["d"] }
+b = { command = "echo b", depends_on = ["c"] }
+c = "echo c"
+d = "echo d"
+"#)?;
+
+		assert_eq!(chain(&repository, "a")?, ["c", "b", "a", "d"]);
+
+		Ok(())
+	}
+
+	#[test]
+	fn cycles_are_reported_in_order() -> Result<(), Box<dyn Error>> {
+		let repository = repository(r#"
+a = { command = "echo a", depends_on = ["b"] }
+b = { command = "echo b", depends_on = ["c"] }
+c = { command = "echo c", depends_on = ["a"] }
+"#)?;
+
+		assert!(matches!(chain(&repository, "a"), Err(ScriptChainError::Cycle(cycle)) if cycle == "a -> b -> c -> a"));
+
+		Ok(())
+	}
+
+	#[test]
+	fn scripts_depending_on_themselves_are_cycles() -> Result<(), Box<dyn Error>> {
+		let repository = repository(r#"a = { command = "echo a", depends_on = ["a"] }"#)?;
+
+		assert!(matches!(chain(&repository, "a"), Err(ScriptChainError::Cycle(cycle)) if cycle == "a -> a"));
+
+		Ok(())
+	}
+
+	#[test]
+	fn missing_scripts_name_what_needs_them() -> Result<(), Box<dyn Error>> {
+		let repository = repository(r#"a = { command = "echo a", post = ["gone"] }"#)?;
+
+		assert!(matches!(chain(&repository, "a"), Err(ScriptChainError::Missing(name, needed_by)) if name == "gone" && needed_by == "a"));
+
+		Ok(())
+	}
+}
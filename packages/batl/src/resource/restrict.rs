@@ -1,9 +1,12 @@
+use core::fmt::{Display, Formatter};
+use serde::Serialize;
 use std::collections::HashMap;
 use super::{tomlconfig::{self, RestrictRequirement0_2_2}, Name};
 
 
 /// A condition that restricts usage of a repository
-#[derive(Clone, PartialEq, Eq, Hash)]
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
 #[non_exhaustive]
 pub enum Condition {
 	Windows,
@@ -12,6 +15,18 @@ pub enum Condition {
 	MacOs
 }
 
+impl Display for Condition {
+	#[inline]
+	fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+		f.write_str(match *self {
+			Self::Windows => "windows",
+			Self::Linux => "linux",
+			Self::Unix => "unix",
+			Self::MacOs => "macos"
+		})
+	}
+}
+
 impl From<Condition> for tomlconfig::RestrictorLatest {
 	#[inline]
 	fn from(value: Condition) -> Self {
@@ -76,7 +91,8 @@ impl From<tomlconfig::RestrictorSettings0_2_2> for Settings {
 }
 
 /// Requirement severity of a condition
-#[derive(Clone)]
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "lowercase")]
 #[non_exhaustive]
 pub enum Requirement {
 	Deny,
@@ -84,6 +100,17 @@ pub enum Requirement {
 	Require
 }
 
+impl Display for Requirement {
+	#[inline]
+	fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+		f.write_str(match *self {
+			Self::Deny => "deny",
+			Self::Allow => "allow",
+			Self::Require => "require"
+		})
+	}
+}
+
 impl From<Requirement> for tomlconfig::RestrictRequirement0_2_2 {
 	#[inline]
 	fn from(value: Requirement) -> Self {
@@ -0,0 +1,216 @@
+use crate::executor::shell::Interpreter;
+use crate::units::{ByteSize, Duration};
+use std::collections::HashMap;
+use super::tomlconfig;
+
+
+/// The script run after a dependency of the repository is
+/// added, removed, or given a new version requirement
+pub const DEP_CHANGE_SCRIPT: &str = "on-dep-change";
+
+
+/// A script that can be run inside of a resource
+#[derive(Clone)]
+#[non_exhaustive]
+pub struct Script {
+	pub command: String,
+	pub container: Option<Container>,
+	pub limits: Limits,
+
+	/// A named lock held while the script runs, so scripts
+	/// sharing it never run at the same time
+	pub lock: Option<String>,
+
+	pub retry: Retry,
+
+	/// Environment variables set for the script
+	pub env: HashMap<String, String>,
+
+	/// The interpreter the command runs through, if not the
+	/// configured one
+	pub shell: Option<Interpreter>,
+
+	/// Scripts run before this one, each after its own
+	pub depends_on: Vec<String>,
+
+	/// Scripts run after this one passes
+	pub post: Vec<String>
+}
+
+/// Where a script available to a repository comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Source {
+	/// Defined in the repository's batl.toml
+	Repository,
+
+	/// Defined in the user's batlrc, shared by every
+	/// repository that doesn't define its own
+	User
+}
+
+impl Script {
+	/// Create a script that runs the given shell command
+	#[inline]
+	#[must_use]
+	pub fn new(command: String) -> Self {
+		Self {
+			command,
+			container: None,
+			limits: Limits::none(),
+			lock: None,
+			retry: Retry::none(),
+			env: HashMap::new(),
+			shell: None,
+			depends_on: Vec::new(),
+			post: Vec::new()
+		}
+	}
+}
+
+impl From<tomlconfig::Script0_2_2> for Script {
+	#[inline]
+	fn from(value: tomlconfig::Script0_2_2) -> Self {
+		match value {
+			tomlconfig::Script0_2_2::Command(command) => Self::new(command),
+			tomlconfig::Script0_2_2::Detailed(detailed) => Self {
+				command: detailed.command,
+				container: detailed.container.map(Container::from),
+				limits: Limits {
+					max_memory: detailed.max_memory,
+					max_cpu: detailed.max_cpu,
+					timeout: detailed.timeout
+				},
+				lock: detailed.lock,
+				retry: Retry {
+					retries: detailed.retries.unwrap_or_default(),
+					delay: detailed.retry_delay
+				},
+				env: detailed.env.unwrap_or_default(),
+				shell: detailed.shell.map(Interpreter::from),
+				depends_on: detailed.depends_on.unwrap_or_default(),
+				post: detailed.post.unwrap_or_default()
+			}
+		}
+	}
+}
+
+impl From<Script> for tomlconfig::Script0_2_2 {
+	#[inline]
+	fn from(value: Script) -> Self {
+		if value.container.is_none() && value.limits.is_none() && value.lock.is_none() && value.retry.is_none() && value.env.is_empty() && value.shell.is_none() && value.depends_on.is_empty() && value.post.is_empty() {
+			return Self::Command(value.command);
+		}
+
+		Self::Detailed(Box::new(tomlconfig::ScriptDetailed0_2_2 {
+			command: value.command,
+			container: value.container.map(tomlconfig::ScriptContainer0_2_2::from),
+			max_memory: value.limits.max_memory,
+			max_cpu: value.limits.max_cpu,
+			timeout: value.limits.timeout,
+			lock: value.lock,
+			retries: (value.retry.retries > 0).then_some(value.retry.retries),
+			retry_delay: value.retry.delay,
+			env: tomlconfig::hashmap_to_option_hashmap(value.env),
+			shell: value.shell.map(tomlconfig::ScriptShell0_2_2::from),
+			depends_on: (!value.depends_on.is_empty()).then_some(value.depends_on),
+			post: (!value.post.is_empty()).then_some(value.post)
+		}))
+	}
+}
+
+/// Resource limits enforced while a script runs
+#[derive(Clone, Copy)]
+#[non_exhaustive]
+pub struct Limits {
+	/// The maximum virtual memory of the script
+	pub max_memory: Option<ByteSize>,
+
+	/// The maximum CPU time of the script
+	pub max_cpu: Option<Duration>,
+
+	/// The maximum wall clock time of the script
+	pub timeout: Option<Duration>
+}
+
+impl Limits {
+	/// No limits at all
+	#[inline]
+	#[must_use]
+	pub const fn none() -> Self {
+		Self {
+			max_memory: None,
+			max_cpu: None,
+			timeout: None
+		}
+	}
+
+	/// Whether no limits are set
+	#[inline]
+	#[must_use]
+	pub const fn is_none(&self) -> bool {
+		self.max_memory.is_none() && self.max_cpu.is_none() && self.timeout.is_none()
+	}
+}
+
+/// How a failing script is retried
+#[derive(Clone, Copy)]
+#[non_exhaustive]
+pub struct Retry {
+	/// How many more times a failed script is run
+	pub retries: u32,
+
+	/// How long to wait before each retry
+	pub delay: Option<Duration>
+}
+
+impl Retry {
+	/// Never retry
+	#[inline]
+	#[must_use]
+	pub const fn none() -> Self {
+		Self {
+			retries: 0,
+			delay: None
+		}
+	}
+
+	/// Whether failures are never retried
+	#[inline]
+	#[must_use]
+	pub const fn is_none(&self) -> bool {
+		self.retries == 0 && self.delay.is_none()
+	}
+}
+
+/// Container settings for a script
+#[derive(Clone)]
+#[non_exhaustive]
+pub struct Container {
+	/// The image to run the script in
+	pub image: String,
+
+	/// The container engine to use, `docker` or `podman`.
+	/// Detected from the path when not set.
+	pub engine: Option<String>
+}
+
+impl From<tomlconfig::ScriptContainer0_2_2> for Container {
+	#[inline]
+	fn from(value: tomlconfig::ScriptContainer0_2_2) -> Self {
+		Self {
+			image: value.image,
+			engine: value.engine
+		}
+	}
+}
+
+impl From<Container> for tomlconfig::ScriptContainer0_2_2 {
+	#[inline]
+	fn from(value: Container) -> Self {
+		Self {
+			image: value.image,
+			engine: value.engine
+		}
+	}
+}
@@ -0,0 +1,156 @@
+use crate::context::BatlContext;
+use semver::Version;
+use serde::Serialize;
+use std::collections::HashMap;
+use super::{Name, NameCase, Ownership, Repository, Resource as _};
+use super::kind::Kind;
+use super::repository::Config;
+use super::restrict::{Condition, Requirement};
+
+
+/// An overview of a repository, with its dependencies
+/// resolved against the repositories available locally
+#[derive(Clone, Serialize)]
+#[non_exhaustive]
+pub struct Summary {
+	pub name: Name,
+	pub version: Version,
+	pub kind: Kind,
+	pub dependencies: Vec<Dependency>,
+	pub restrictions: Vec<Restriction>,
+
+	/// Who created and last changed the repository, if
+	/// recorded
+	pub ownership: Option<Ownership>
+}
+
+/// A dependency of a summarized repository, along with
+/// its own dependencies
+#[derive(Clone, Serialize)]
+#[non_exhaustive]
+pub struct Dependency {
+	pub name: Name,
+
+	/// The version written in the dependent's config
+	pub requirement: String,
+
+	/// The version found locally, or `None` if the
+	/// dependency is missing
+	pub version: Option<Version>,
+
+	/// The kind of the dependency found locally
+	pub kind: Option<Kind>,
+
+	/// Whether the dependency is one of its own
+	/// dependents, in which case it is not recursed into
+	pub cycle: bool,
+
+	pub dependencies: Vec<Self>
+}
+
+/// A platform restriction of a summarized repository
+#[derive(Clone, Serialize)]
+#[non_exhaustive]
+pub struct Restriction {
+	pub condition: Condition,
+	pub include: Requirement,
+	pub dependencies: HashMap<Name, String>
+}
+
+impl Summary {
+	/// Summarize a repository
+	#[inline]
+	#[must_use]
+	pub fn new(context: &BatlContext, repository: &Repository) -> Self {
+		let config = repository.config();
+
+		let mut restrictions = config.restrict.iter()
+			.map(|(condition, settings)| Restriction {
+				condition: condition.clone(),
+				include: settings.include.clone(),
+				dependencies: settings.dependencies.clone()
+			})
+			.collect::<Vec<_>>();
+
+		restrictions.sort_by(|a, b| a.condition.cmp(&b.condition));
+
+		Self {
+			name: repository.name().clone(),
+			version: config.version.clone(),
+			kind: config.kind,
+			dependencies: dependencies(context, config, &mut vec![repository.name().clone()]),
+			restrictions,
+			ownership: Ownership::load(repository.path())
+		}
+	}
+
+	/// Every path through the dependencies to a
+	/// dependency, each as the chain of dependencies from
+	/// the summarized repository down to it
+	#[inline]
+	#[must_use]
+	pub fn paths_to(&self, context: &BatlContext, name: &Name) -> Vec<Vec<&Dependency>> {
+		let case = context.settings().name_case;
+		let target = name.clone().normalized(case);
+
+		let mut paths = Vec::new();
+		collect_paths(&self.dependencies, &target, case, &mut Vec::new(), &mut paths);
+
+		paths
+	}
+}
+
+/// Walk the dependencies depth first, keeping every path
+/// that ends at the named dependency
+fn collect_paths<'summary>(dependencies: &'summary [Dependency], name: &Name, case: NameCase, path: &mut Vec<&'summary Dependency>, paths: &mut Vec<Vec<&'summary Dependency>>) {
+	for dependency in dependencies {
+		path.push(dependency);
+
+		let found = dependency.name.clone().normalized(case) == *name;
+
+		if found {
+			paths.push(path.clone());
+		}
+
+		if !found && !dependency.cycle {
+			collect_paths(&dependency.dependencies, name, case, path, paths);
+		}
+
+		path.pop();
+	}
+}
+
+/// Resolve the dependencies of a config, recursing into
+/// those that are available locally
+fn dependencies(context: &BatlContext, config: &Config, ancestors: &mut Vec<Name>) -> Vec<Dependency> {
+	let mut resolved = config.dependencies.iter()
+		.map(|(name, requirement)| {
+			let cycle = ancestors.contains(name);
+			let repository = Repository::load_in(context, name.clone()).ok().flatten();
+
+			let children = repository.as_ref()
+				.filter(|_| !cycle)
+				.map(|dependency| {
+					ancestors.push(name.clone());
+					let children = dependencies(context, dependency.config(), ancestors);
+					ancestors.pop();
+
+					children
+				})
+				.unwrap_or_default();
+
+			Dependency {
+				name: name.clone(),
+				requirement: requirement.clone(),
+				version: repository.as_ref().map(|dependency| dependency.config().version.clone()),
+				kind: repository.map(|dependency| dependency.config().kind),
+				cycle,
+				dependencies: children
+			}
+		})
+		.collect::<Vec<_>>();
+
+	resolved.sort_by_key(|dependency| dependency.name.to_string());
+
+	resolved
+}
@@ -0,0 +1,239 @@
+use crate::context::BatlContext;
+use crate::error as batlerror;
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use super::{tomlconfig, Name, Repository, Workspace};
+use super::repository::CreateRepositoryOptions;
+
+
+/// The placeholder replaced by the workspace name in the
+/// repository names of a template
+const WORKSPACE_PLACEHOLDER: &str = "{workspace}";
+
+
+/// A reusable set of repositories, their dependencies, and
+/// the workspace links between them
+pub struct Template {
+	/// The name of the template
+	name: Name,
+
+	/// The configuration of the template
+	config: Config
+}
+
+impl Template {
+	/// Load a template from the local template root.
+	/// Returns `None` if it has not been saved locally.
+	/// 
+	/// # Errors
+	/// 
+	/// Propogates any errors found while reading the
+	/// template
+	#[inline]
+	pub fn load(context: &BatlContext, name: &Name) -> Result<Option<Self>, batlerror::GeneralResourceError> {
+		let Some(path) = Self::path_of(context, name) else {
+			return Ok(None);
+		};
+
+		if !path.exists() {
+			return Ok(None);
+		}
+
+		Self::from_path(&path).map(Some)
+	}
+
+	/// Load a template from a manifest file
+	/// 
+	/// # Errors
+	/// 
+	/// Propogates any errors found while reading the
+	/// template
+	#[inline]
+	pub fn from_path(path: &Path) -> Result<Self, batlerror::GeneralResourceError> {
+		let toml = std::fs::read_to_string(path)?;
+
+		Self::from_toml(&toml)
+	}
+
+	/// Parse a template manifest
+	/// 
+	/// # Errors
+	/// 
+	/// Returns `Invalid` if the manifest can't be parsed
+	#[inline]
+	pub fn from_toml(toml: &str) -> Result<Self, batlerror::GeneralResourceError> {
+		let latest: TomlTemplateLatest = toml::from_str(toml)
+			.map_err(|_err| batlerror::GeneralResourceError::Invalid)?;
+
+		Ok(Self {
+			name: latest.template.name.clone(),
+			config: latest.into()
+		})
+	}
+
+	/// Save a template manifest to the local template root,
+	/// so it can be applied without the registry
+	/// 
+	/// # Errors
+	/// 
+	/// Returns `NotSetup` if battalion is not set up, or
+	/// `InvalidName` if the manifest is not a template
+	#[inline]
+	pub fn save_toml(context: &BatlContext, toml: &str) -> Result<Self, batlerror::CreateResourceError> {
+		let template = Self::from_toml(toml)
+			.map_err(|_err| batlerror::CreateResourceError::InvalidName("<template>".to_owned()))?;
+
+		let path = Self::path_of(context, &template.name)
+			.ok_or(batlerror::CreateResourceError::NotSetup)?;
+
+		if let Some(parent) = path.parent() {
+			std::fs::create_dir_all(parent)?;
+		}
+
+		std::fs::write(path, toml)?;
+
+		Ok(template)
+	}
+
+	/// Get the manifest path of a local template
+	fn path_of(context: &BatlContext, name: &Name) -> Option<PathBuf> {
+		context.system().template_root()
+			.map(|p| p.join(PathBuf::from(name)).join("template.toml"))
+	}
+
+	/// Get the name of the template
+	#[inline]
+	#[must_use]
+	pub const fn name(&self) -> &Name {
+		&self.name
+	}
+
+	/// Get the configuration of the template
+	#[inline]
+	#[must_use]
+	pub const fn config(&self) -> &Config {
+		&self.config
+	}
+
+	/// Instantiate the template into a workspace, creating
+	/// the workspace and any repositories that don't exist
+	/// yet. Existing repositories are reused, and only gain
+	/// the dependencies the template adds.
+	/// 
+	/// # Errors
+	/// 
+	/// Propogates any errors found along the way, and
+	/// returns `LinkConflict` if the workspace already has
+	/// a link of the same name to another repository.
+	#[inline]
+	pub fn apply(&self, context: &BatlContext, workspace_name: &Name) -> Result<Workspace, batlerror::ApplyTemplateError> {
+		let mut workspace = match Workspace::load_in(context, workspace_name.clone()) {
+			Ok(Some(workspace)) => workspace,
+			Ok(None) | Err(batlerror::GeneralResourceError::DoesNotExist) => Workspace::create_in(context, workspace_name.clone())?,
+			Err(err) => return Err(err.into())
+		};
+
+		let resolve = |name: &Name| Name::from(
+			name.to_string().replace(WORKSPACE_PLACEHOLDER, &workspace_name.to_string())
+		);
+
+		let mut repositories = self.config.repositories.iter().collect::<Vec<_>>();
+		repositories.sort_by_key(|&(name, _)| name.to_string());
+
+		for (template_name, settings) in repositories {
+			let name = resolve(template_name);
+
+			let mut repository = match Repository::load_in(context, name.clone()) {
+				Ok(Some(repository)) => repository,
+				Ok(None) | Err(batlerror::GeneralResourceError::DoesNotExist) => {
+					Repository::create_in(context, name, CreateRepositoryOptions::default())?
+				},
+				Err(err) => return Err(err.into())
+			};
+
+			settings.dependencies.iter()
+				.try_for_each(|(dependency, version)| repository.add_dependency(resolve(dependency), version.clone()))?;
+		}
+
+		let mut links = self.config.links.iter().collect::<Vec<_>>();
+		links.sort_by(|a, b| a.0.cmp(b.0));
+
+		for (link, template_name) in links {
+			let name = resolve(template_name);
+
+			match workspace.links().get(link) {
+				Some(existing) if *existing == name => continue,
+				Some(_) => return Err(batlerror::ApplyTemplateError::LinkConflict(link.clone())),
+				None => {}
+			}
+
+			let repository = Repository::load_in(context, name.clone())?
+				.ok_or_else(|| batlerror::ApplyTemplateError::MissingRepository(name.to_string()))?;
+
+			workspace.create_link(context, link, &repository)?;
+		}
+
+		Ok(workspace)
+	}
+}
+
+/// The contents of a template
+#[derive(Clone)]
+#[non_exhaustive]
+pub struct Config {
+	pub description: Option<String>,
+	pub repositories: HashMap<Name, RepositorySettings>,
+	pub links: HashMap<String, Name>
+}
+
+/// What a template sets up for a single repository
+#[derive(Clone, Default)]
+#[non_exhaustive]
+pub struct RepositorySettings {
+	pub dependencies: HashMap<Name, String>
+}
+
+impl From<TomlTemplate0_2_2> for Config {
+	#[inline]
+	fn from(value: TomlTemplate0_2_2) -> Self {
+		let repositories = value.repositories
+			.unwrap_or_default()
+			.into_iter()
+			.map(|(name, settings)| (name, RepositorySettings {
+				dependencies: settings.dependencies.unwrap_or_default()
+			}))
+			.collect();
+
+		Self {
+			description: value.template.description,
+			repositories,
+			links: value.links.unwrap_or_default()
+		}
+	}
+}
+
+// TEMPLATE VERSIONS //
+pub type TomlTemplateLatest = TomlTemplate0_2_2;
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct TomlTemplate0_2_2 {
+	pub environment: tomlconfig::Environment0_2_2,
+	pub template: Info0_2_2,
+	pub repositories: Option<HashMap<Name, RepositoryEntry0_2_2>>,
+	pub links: Option<tomlconfig::Links0_2_2>
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Info0_2_2 {
+	pub name: Name,
+	pub description: Option<String>
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RepositoryEntry0_2_2 {
+	pub dependencies: Option<tomlconfig::Dependencies0_2_2>
+}
@@ -4,44 +4,201 @@
 use batl_macros::environment_struct_impl;
 use crate::error::ReadConfigError;
 use crate::resource::Name;
+use crate::units::{ByteSize, Duration};
+use crate::version::{SchemaVersion as _, VersionLatest};
+use schemars::JsonSchema;
 use serde::{Serialize, Deserialize};
 use std::collections::HashMap;
 use std::io::Write;
 use std::path::{Path, PathBuf};
+use toml_edit::{Document, Item, Table, TableLike, Value};
 
 
 pub type EnvironmentLatest = Environment0_2_2;
 pub type RepositoryLatest = Repository0_2_2;
+pub type RepositoryKindLatest = RepositoryKind0_2_2;
 pub type WorkspaceLatest = Workspace0_2_2;
 pub type ScriptsLatest = Scripts0_2_2;
 pub type DependenciesLatest = Dependencies0_2_2;
+pub type RepositoryDependenciesLatest = RepositoryDependencies0_2_2;
+pub type DependencyLatest = Dependency0_2_2;
 pub type RestrictLatest = Restrict0_2_2;
 pub type RestrictorLatest = Restrictor0_2_2;
+pub type ToolsLatest = Tools0_2_2;
+pub type SecretsLatest = Secrets0_2_2;
+pub type EnvLatest = Env0_2_2;
 
 environment_struct_impl!("0.2.0");
 environment_struct_impl!("0.2.1");
 environment_struct_impl!("0.2.2");
 
-#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq)]
 pub struct Repository0_2_2 {
 	pub name: Name,
 	pub version: semver::Version,
-	pub git: Option<RepositoryGit0_2_2>
+	pub git: Option<RepositoryGit0_2_2>,
+
+	/// SPDX license expression, such as `MIT`
+	pub license: Option<String>,
+
+	/// What the repository is for, `library` if not given
+	pub kind: Option<RepositoryKind0_2_2>
 }
 
-#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RepositoryKind0_2_2 {
+	Library,
+	App,
+	Tool,
+	Meta
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq)]
+pub struct RepositoryGit0_2_2 {
+	pub url: String,
+	pub path: String,
+
+	/// Clone only this many commits of history
+	pub depth: Option<u32>,
+
+	/// Check out this branch instead of the remote's
+	/// default
+	pub branch: Option<String>,
+
+	/// Check out only these paths of the working tree
+	pub sparse: Option<Vec<String>>
+}
+
+impl From<RepositoryGit0_2_0> for RepositoryGit0_2_2 {
+	#[inline]
+	fn from(value: RepositoryGit0_2_0) -> Self {
+		Self {
+			url: value.url,
+			path: value.path,
+			depth: None,
+			branch: None,
+			sparse: None
+		}
+	}
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq)]
 pub struct Workspace0_2_2 {
 	pub name: Name,
 	pub version: semver::Version
 }
 
 pub type Links0_2_2 = Links0_2_1;
-pub type RepositoryGit0_2_2 = RepositoryGit0_2_1;
-pub type Scripts0_2_2 = Scripts0_2_1;
+pub type Scripts0_2_2 = HashMap<String, Script0_2_2>;
 pub type Dependencies0_2_2 = Dependencies0_2_1;
+pub type RepositoryDependencies0_2_2 = HashMap<Name, Dependency0_2_2>;
 pub type Restrict0_2_2 = HashMap<Restrictor0_2_2, RestrictorSettings0_2_2>;
+pub type Tools0_2_2 = HashMap<String, semver::VersionReq>;
+pub type Env0_2_2 = HashMap<String, String>;
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum Script0_2_2 {
+	Command(String),
+	Detailed(Box<ScriptDetailed0_2_2>)
+}
 
-#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq)]
+pub struct ScriptDetailed0_2_2 {
+	pub command: String,
+	pub container: Option<ScriptContainer0_2_2>,
+	pub max_memory: Option<ByteSize>,
+	pub max_cpu: Option<Duration>,
+	pub timeout: Option<Duration>,
+	pub lock: Option<String>,
+	pub retries: Option<u32>,
+	pub retry_delay: Option<Duration>,
+
+	/// Environment variables set for the script
+	pub env: Option<HashMap<String, String>>,
+
+	/// The interpreter the command runs through, in place
+	/// of `config.shell` in the batlrc
+	pub shell: Option<ScriptShell0_2_2>,
+
+	/// Scripts run before this one, each after its own
+	pub depends_on: Option<Vec<String>>,
+
+	/// Scripts run after this one passes
+	pub post: Option<Vec<String>>
+}
+
+/// An interpreter for script commands
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+#[allow(clippy::exhaustive_enums)]
+pub enum ScriptShell0_2_2 {
+	Sh,
+	Cmd,
+	Powershell,
+	Busybox
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq)]
+pub struct ScriptContainer0_2_2 {
+	pub image: String,
+	pub engine: Option<String>
+}
+
+/// A dependency of a repository, as a version requirement
+/// or with the registry it comes from
+#[derive(Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum Dependency0_2_2 {
+	Requirement(String),
+	Detailed(DependencyDetailed0_2_2)
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq)]
+pub struct DependencyDetailed0_2_2 {
+	pub version: String,
+
+	/// A registry from `[registries]` in the batlrc, if not
+	/// the default one
+	pub registry: Option<String>
+}
+
+impl Dependency0_2_2 {
+	/// A dependency on a requirement, from the named
+	/// registry if one is given
+	#[inline]
+	#[must_use]
+	pub fn new(requirement: String, registry: Option<String>) -> Self {
+		match registry {
+			Some(named) => Self::Detailed(DependencyDetailed0_2_2 { version: requirement, registry: Some(named) }),
+			None => Self::Requirement(requirement)
+		}
+	}
+
+	/// The version requirement, and the registry the
+	/// dependency comes from if not the default one
+	#[inline]
+	#[must_use]
+	pub fn into_parts(self) -> (String, Option<String>) {
+		match self {
+			Self::Requirement(requirement) => (requirement, None),
+			Self::Detailed(detailed) => (detailed.version, detailed.registry)
+		}
+	}
+}
+
+/// What the secret scan lets through when publishing
+#[derive(Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq)]
+pub struct Secrets0_2_2 {
+	/// Globs of paths that are not scanned
+	pub allow: Option<Vec<String>>,
+
+	/// Names of rules that are turned off
+	pub allow_rules: Option<Vec<String>>
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq, Hash)]
 pub enum Restrictor0_2_2 {
 	Windows,
 	Linux,
@@ -49,13 +206,13 @@ pub enum Restrictor0_2_2 {
 	MacOs
 }
 
-#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq)]
 pub struct RestrictorSettings0_2_2 {
 	pub include: Option<RestrictRequirement0_2_2>,
 	pub dependencies: Option<Dependencies0_2_2>
 }
 
-#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 pub enum RestrictRequirement0_2_2 {
 	Deny,
@@ -87,7 +244,7 @@ pub struct Workspace0_2_0 {
 
 pub type Links0_2_0 = HashMap<String, Name>;
 
-#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq)]
 pub struct RepositoryGit0_2_0 {
 	pub url: String,
 	pub path: String
@@ -96,19 +253,234 @@ pub struct RepositoryGit0_2_0 {
 pub type Scripts0_2_0 = HashMap<String, String>;
 pub type Dependencies0_2_0 = HashMap<Name, String>;
 
+impl From<Repository0_2_0> for Repository0_2_2 {
+	#[inline]
+	fn from(value: Repository0_2_0) -> Self {
+		Self {
+			name: value.name,
+			version: value.version,
+			git: value.git.map(Into::into),
+			license: None,
+			kind: None
+		}
+	}
+}
+
+impl From<Workspace0_2_0> for Workspace0_2_2 {
+	#[inline]
+	fn from(value: Workspace0_2_0) -> Self {
+		Self {
+			name: value.name,
+			version: value.version
+		}
+	}
+}
+
 
 /// Writes a toml struct to a path
 /// 
+/// If a file is already there, it is edited in place
+/// instead, so comments and the order of keys written by
+/// hand are kept. The file is written beside the old one
+/// and moved over it, so a crash while writing leaves the
+/// old one whole.
+/// 
 /// # Errors
 /// 
-/// Propogates any IO errors received while writing the file.
+/// Propogates any errors serializing the data, and any IO
+/// errors received while reading or writing the file.
 #[inline]
 pub fn write_toml<T: serde::Serialize>(path: &Path, data: &T) -> Result<(), std::io::Error> {
-	let mut file = std::fs::File::create(path)?;
+	let fresh = toml::to_string(data).map_err(std::io::Error::other)?;
+
+	let contents = match std::fs::read_to_string(path) {
+		Ok(existing) => edit_document(&existing, &fresh).unwrap_or(fresh),
+		Err(err) if err.kind() == std::io::ErrorKind::NotFound => fresh,
+		Err(err) => return Err(err)
+	};
+
+	let mut partial_name = path.file_name().unwrap_or_default().to_owned();
+	partial_name.push(".partial");
+	let partial = path.with_file_name(partial_name);
+
+	let mut file = std::fs::File::create(&partial)?;
+	file.write_all(contents.as_bytes())?;
+	file.sync_all()?;
+	drop(file);
+
+	std::fs::rename(partial, path)
+}
+
+/// Edit an existing document to hold the values of a
+/// freshly serialized one, touching only what changed.
+/// Returns `None` if either can't be parsed.
+fn edit_document(existing: &str, fresh: &str) -> Option<String> {
+	let mut document = existing.parse::<Document>().ok()?;
+	let updated = fresh.parse::<Document>().ok()?;
+
+	let mut next_position = last_position(document.as_table()).saturating_add(1);
+	edit_table(document.as_table_mut(), updated.as_table(), &mut next_position);
+
+	Some(document.to_string())
+}
+
+/// Edit a table or inline table to match another, keeping
+/// the formatting of the keys both have
+fn edit_table(current: &mut dyn TableLike, updated: &dyn TableLike, next_position: &mut usize) {
+	let removed = current.iter()
+		.map(|(key, _)| key.to_owned())
+		.filter(|key| !updated.contains_key(key))
+		.collect::<Vec<_>>();
+
+	for key in removed {
+		current.remove(&key);
+	}
+
+	for (key, item) in updated.iter() {
+		if let Some(existing) = current.get_mut(key) {
+			edit_item(existing, item, next_position);
+			continue;
+		}
+
+		let mut added = item.clone();
+		place(&mut added, next_position);
+		current.insert(key, added);
+	}
+}
+
+/// Edit an item to match another. Items that changed
+/// shape are replaced outright.
+fn edit_item(current: &mut Item, updated: &Item, next_position: &mut usize) {
+	if let (Some(table), Some(updated_table)) = (current.as_table_like_mut(), updated.as_table_like()) {
+		edit_table(table, updated_table, next_position);
+		return;
+	}
+
+	if let (Some(tables), Some(updated_tables)) = (current.as_array_of_tables_mut(), updated.as_array_of_tables()) {
+		if tables.len() == updated_tables.len() {
+			for (table, updated_table) in tables.iter_mut().zip(updated_tables.iter()) {
+				edit_table(table, updated_table, next_position);
+			}
+
+			return;
+		}
+	}
+
+	if let (Some(value), Some(updated_value)) = (current.as_value_mut(), updated.as_value()) {
+		edit_value(value, updated_value);
+		return;
+	}
+
+	let mut replacement = updated.clone();
+	place(&mut replacement, next_position);
+
+	// Inline tables can only hold values
+	if current.is_value() {
+		if let Ok(value) = replacement.clone().into_value() {
+			replacement = Item::Value(value);
+		}
+	}
+
+	*current = replacement;
+}
+
+/// Edit a value to match another, keeping the whitespace
+/// and comments around it
+fn edit_value(current: &mut Value, updated: &Value) {
+	if same_value(current, updated) {
+		return;
+	}
+
+	if let (Some(array), Some(updated_array)) = (current.as_array_mut(), updated.as_array()) {
+		if array.len() == updated_array.len() {
+			for (element, updated_element) in array.iter_mut().zip(updated_array.iter()) {
+				edit_value(element, updated_element);
+			}
+
+			return;
+		}
+	}
 
-	file.write_all(toml::to_string(data).unwrap_or_default().as_bytes())?;
+	let decor = current.decor().clone();
 
-	Ok(())
+	*current = updated.clone();
+	*current.decor_mut() = decor;
+}
+
+/// Whether two values are the same, regardless of how
+/// they are written
+fn same_value(first: &Value, second: &Value) -> bool {
+	if let (Some(first_str), Some(second_str)) = (first.as_str(), second.as_str()) {
+		return first_str == second_str;
+	}
+
+	let mut first_bare = first.clone();
+	let mut second_bare = second.clone();
+	first_bare.decor_mut().clear();
+	second_bare.decor_mut().clear();
+
+	first_bare.to_string() == second_bare.to_string()
+}
+
+/// Put added tables after every table already in the
+/// document, in the order they are added
+fn place(item: &mut Item, next_position: &mut usize) {
+	if let Some(table) = item.as_table_mut() {
+		place_table(table, next_position);
+		return;
+	}
+
+	if let Some(tables) = item.as_array_of_tables_mut() {
+		for table in tables.iter_mut() {
+			place_table(table, next_position);
+		}
+	}
+}
+
+/// Put a table and the tables within it at the next
+/// positions
+fn place_table(table: &mut Table, next_position: &mut usize) {
+	table.set_position(*next_position);
+	*next_position = next_position.saturating_add(1);
+
+	for (_, child) in table.iter_mut() {
+		place(child, next_position);
+	}
+}
+
+/// The position of the last table in a document
+fn last_position(table: &Table) -> usize {
+	table.iter()
+		.flat_map(|(_, item)| child_tables(item))
+		.map(last_position)
+		.fold(table.position().unwrap_or_default(), usize::max)
+}
+
+/// The tables directly held by an item
+fn child_tables(item: &Item) -> Vec<&Table> {
+	item.as_array_of_tables().map_or_else(
+		|| item.as_table().into_iter().collect(),
+		|tables| tables.iter().collect()
+	)
+}
+
+/// Upgrades a set of plain command scripts to the
+/// latest script format
+#[inline]
+#[must_use]
+pub fn upgrade_scripts(scripts: Scripts0_2_1) -> ScriptsLatest {
+	scripts.into_iter()
+		.map(|(name, command)| (name, Script0_2_2::Command(command)))
+		.collect()
+}
+
+/// Upgrade dependencies from an older version to the latest
+#[inline]
+#[must_use]
+pub fn upgrade_dependencies(dependencies: Dependencies0_2_1) -> RepositoryDependenciesLatest {
+	dependencies.into_iter()
+		.map(|(name, requirement)| (name, Dependency0_2_2::Requirement(requirement)))
+		.collect()
 }
 
 /// Returns `None` if a hashmap is empty
@@ -122,6 +494,79 @@ pub fn hashmap_to_option_hashmap<K, V, S>(map: HashMap<K, V, S>) -> Option<HashM
 	}
 }
 
+/// Just enough of a config to find out which schema it
+/// was written with
+#[derive(Deserialize)]
+struct VersionProbe {
+	/// The environment table of the config
+	environment: Option<EnvironmentProbe>
+}
+
+/// The environment table of a config, for any schema
+#[derive(Deserialize)]
+struct EnvironmentProbe {
+	/// The version the config was written with
+	version: Option<String>
+}
+
+/// Read the `environment.version` a config declares,
+/// without parsing the rest of it
+/// 
+/// # Errors
+/// 
+/// Returns `MissingVersion` if no version is declared,
+/// `UnknownVersion` if it is not a version, and any
+/// toml errors if the config is not valid toml
+#[inline]
+pub fn declared_version(config_str: &str) -> Result<semver::Version, ReadConfigError> {
+	let probe: VersionProbe = toml::from_str(config_str)?;
+
+	let version = probe.environment
+		.and_then(|environment| environment.version)
+		.ok_or(ReadConfigError::MissingVersion)?;
+
+	semver::Version::parse(&version)
+		.map_err(|_err| ReadConfigError::UnknownVersion(version))
+}
+
+/// Parse a config with a single schema. When `strict` is
+/// set, any keys the schema doesn't know about are an
+/// error instead of being ignored.
+/// 
+/// # Errors
+/// 
+/// Returns any toml errors, or `UnknownKeys` when strict
+#[inline]
+pub fn parse<T: serde::de::DeserializeOwned>(config_str: &str, strict: bool) -> Result<T, ReadConfigError> {
+	if !strict {
+		return Ok(toml::from_str(config_str)?);
+	}
+
+	let mut unknown = Vec::new();
+	let config = serde_ignored::deserialize(
+		toml::Deserializer::new(config_str),
+		|path| unknown.push(path.to_string())
+	)?;
+
+	if unknown.is_empty() {
+		Ok(config)
+	} else {
+		Err(ReadConfigError::UnknownKeys(unknown.join(", ")))
+	}
+}
+
+/// The error for a config version that has no schema,
+/// telling apart configs from newer releases
+#[inline]
+#[must_use]
+pub fn unsupported_version(version: &semver::Version) -> ReadConfigError {
+	if VersionLatest::is_newer(version) {
+		ReadConfigError::NewerVersion(version.to_string())
+	} else {
+		ReadConfigError::UnknownVersion(version.to_string())
+	}
+}
+
 pub trait TomlConfig: Sized {
 	/// Reads a toml file from a path
 	/// 
@@ -221,3 +666,136 @@ where
 		Ok(toml::from_str(&config_str)?)
 	}
 }
+
+#[cfg(test)]
+#[allow(clippy::panic_in_result_fn, reason = "tests fail on their assertions, and on errors setting them up")]
+mod tests {
+	use super::{edit_document, write_toml, Script0_2_2};
+	use crate::resource::repository::TomlConfigLatest;
+	use core::error::Error;
+
+
+	/// A `batl.toml` written by hand, with comments, keys in
+	/// an order serde wouldn't pick and inline tables
+	const HAND_WRITTEN: &str = r#"# Maintained by hand, keep the comments
+[environment]
+version = "0.2.2"
+
+[repository]
+name = "acme/lib"
+version = "0.1.0" # bumped on release
+
+[scripts]
+# Runs before every publish
+test = "cargo test"
+build = { command = "cargo build", timeout = "10m" }
+lint = "cargo clippy"
+
+[dependencies]
+"acme/zeta" = "1.0"
+"acme/alpha" = { version = "=2.1.0", registry = "internal" } # pinned for the release
+"#;
+
+	/// The config a document holds
+	fn parse(contents: &str) -> Result<TomlConfigLatest, toml::de::Error> {
+		toml::from_str(contents)
+	}
+
+	/// Serialize a config the way `write_toml` does
+	fn serialize(config: &TomlConfigLatest) -> Result<String, toml::ser::Error> {
+		toml::to_string(config)
+	}
+
+	#[test]
+	fn unchanged_documents_are_kept_byte_for_byte() -> Result<(), Box<dyn Error>> {
+		let fresh = serialize(&parse(HAND_WRITTEN)?)?;
+
+		assert_eq!(edit_document(HAND_WRITTEN, &fresh).as_deref(), Some(HAND_WRITTEN));
+
+		Ok(())
+	}
+
+	#[test]
+	fn changed_values_only_touch_their_value() -> Result<(), Box<dyn Error>> {
+		let mut config = parse(HAND_WRITTEN)?;
+		config.repository.version = semver::Version::new(0, 2, 0);
+
+		let expected = HAND_WRITTEN.replace(r#"version = "0.1.0" # bumped"#, r#"version = "0.2.0" # bumped"#);
+
+		assert_eq!(edit_document(HAND_WRITTEN, &serialize(&config)?), Some(expected));
+
+		Ok(())
+	}
+
+	#[test]
+	fn changed_inline_tables_keep_their_shape() -> Result<(), Box<dyn Error>> {
+		let mut config = parse(HAND_WRITTEN)?;
+		let scripts = config.scripts.get_or_insert_default();
+
+		if let Some(Script0_2_2::Detailed(mut build)) = scripts.remove("build") {
+			build.command = "cargo build --release".to_owned();
+			scripts.insert("build".to_owned(), Script0_2_2::Detailed(build));
+		}
+
+		let expected = HAND_WRITTEN.replace(r#"command = "cargo build","#, r#"command = "cargo build --release","#);
+
+		assert_eq!(edit_document(HAND_WRITTEN, &serialize(&config)?), Some(expected));
+
+		Ok(())
+	}
+
+	#[test]
+	fn removed_keys_only_drop_their_line() -> Result<(), Box<dyn Error>> {
+		let mut config = parse(HAND_WRITTEN)?;
+		config.scripts.get_or_insert_default().remove("lint");
+
+		let expected = HAND_WRITTEN.replace("lint = \"cargo clippy\"\n", "");
+
+		assert_eq!(edit_document(HAND_WRITTEN, &serialize(&config)?), Some(expected));
+
+		Ok(())
+	}
+
+	#[test]
+	fn write_toml_edits_files_in_place() -> Result<(), Box<dyn Error>> {
+		let path = std::env::temp_dir().join(format!("batl-write-toml-{}.toml", std::process::id()));
+		std::fs::write(&path, HAND_WRITTEN)?;
+
+		let mut config = parse(HAND_WRITTEN)?;
+		config.repository.version = semver::Version::new(0, 2, 0);
+
+		let result = write_toml(&path, &config).and_then(|()| std::fs::read_to_string(&path));
+		drop(std::fs::remove_file(&path));
+
+		let expected = HAND_WRITTEN.replace(r#"version = "0.1.0" # bumped"#, r#"version = "0.2.0" # bumped"#);
+
+		assert_eq!(result?, expected);
+
+		Ok(())
+	}
+
+	#[test]
+	fn older_workspaces_upgrade_into_the_moved_fields() -> Result<(), Box<dyn Error>> {
+		use crate::resource::workspace::{AnyTomlConfig, TomlConfigLatest as WorkspaceLatest};
+
+		let old = r#"[environment]
+version = "0.2.1"
+
+[repository]
+name = "acme/ws"
+version = "1.2.0"
+
+[workspace]
+lib = "acme/lib"
+"#;
+
+		let upgraded = WorkspaceLatest::from(AnyTomlConfig::try_from(old)?);
+
+		assert_eq!(upgraded.workspace.name.to_string(), "acme/ws");
+		assert_eq!(upgraded.workspace.version, semver::Version::new(1, 2, 0));
+		assert_eq!(upgraded.links.and_then(|links| links.get("lib").map(ToString::to_string)).as_deref(), Some("acme/lib"));
+		assert!(upgraded.pins.is_none());
+
+		Ok(())
+	}
+}
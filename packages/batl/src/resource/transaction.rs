@@ -0,0 +1,178 @@
+use std::path::{Path, PathBuf};
+
+
+/// A change to the filesystem that undoes a step of a
+/// transaction
+enum Undo {
+	/// Remove a directory that was created, along with
+	/// everything written into it
+	RemoveDir(PathBuf),
+
+	/// Put a file back as it was, removing it if it didn't
+	/// exist
+	Restore(PathBuf, Option<Vec<u8>>),
+
+	/// Remove a symlink that was created
+	RemoveLink(PathBuf),
+
+	/// Recreate a symlink that was removed, from its target
+	/// to the link
+	Relink(PathBuf, PathBuf)
+}
+
+/// The filesystem changes of an operation with several
+/// steps, so a failure partway through doesn't leave half
+/// of them behind
+///
+/// Each step records how to undo itself. Unless the
+/// transaction is committed, the recorded steps are undone
+/// in reverse order when it is dropped, including when an
+/// error is returned early with `?`. Undoing is best
+/// effort, any errors along the way are ignored.
+#[derive(Default)]
+#[non_exhaustive]
+pub struct Transaction {
+	/// How to undo each step, in the order they were taken
+	undo: Vec<Undo>,
+
+	/// Whether the steps are kept
+	committed: bool
+}
+
+impl Transaction {
+	/// Create the directory and any missing parents. Only
+	/// the directories that didn't exist yet are removed on
+	/// rollback.
+	///
+	/// # Errors
+	///
+	/// Propogates any IO errors
+	#[inline]
+	pub fn create_dir_all(&mut self, path: &Path) -> Result<(), std::io::Error> {
+		let missing = path.ancestors()
+			.take_while(|ancestor| !ancestor.as_os_str().is_empty() && !ancestor.exists())
+			.last()
+			.map(Path::to_path_buf);
+
+		std::fs::create_dir_all(path)?;
+
+		if let Some(created) = missing {
+			self.undo.push(Undo::RemoveDir(created));
+		}
+
+		Ok(())
+	}
+
+	/// Remember the contents of a file before it is written
+	/// or removed, so rollback can put it back. Files that
+	/// don't exist yet are removed on rollback.
+	///
+	/// # Errors
+	///
+	/// Propogates any IO errors other than the file not
+	/// existing
+	#[inline]
+	pub fn snapshot(&mut self, path: &Path) -> Result<(), std::io::Error> {
+		let contents = match std::fs::read(path) {
+			Ok(contents) => Some(contents),
+			Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+			Err(err) => return Err(err)
+		};
+
+		self.undo.push(Undo::Restore(path.to_path_buf(), contents));
+
+		Ok(())
+	}
+
+	/// Write a file, restoring what was there on rollback
+	///
+	/// # Errors
+	///
+	/// Propogates any IO errors
+	#[inline]
+	pub fn write(&mut self, path: &Path, contents: &[u8]) -> Result<(), std::io::Error> {
+		self.snapshot(path)?;
+
+		std::fs::write(path, contents)
+	}
+
+	/// Remove a file, restoring it on rollback
+	///
+	/// # Errors
+	///
+	/// Propogates any IO errors
+	#[inline]
+	pub fn remove_file(&mut self, path: &Path) -> Result<(), std::io::Error> {
+		self.snapshot(path)?;
+
+		std::fs::remove_file(path)
+	}
+
+	/// Create a directory symlink, removing it on rollback
+	///
+	/// # Errors
+	///
+	/// Propogates any IO errors
+	#[inline]
+	pub fn symlink_dir(&mut self, original: &Path, link: &Path) -> Result<(), std::io::Error> {
+		super::symlink_dir(original, link)?;
+
+		self.undo.push(Undo::RemoveLink(link.to_path_buf()));
+
+		Ok(())
+	}
+
+	/// Remove a directory symlink, recreating it on
+	/// rollback
+	///
+	/// # Errors
+	///
+	/// Propogates any IO errors
+	#[inline]
+	pub fn remove_link(&mut self, link: &Path) -> Result<(), std::io::Error> {
+		let original = std::fs::read_link(link)?;
+
+		remove_symlink(link)?;
+
+		self.undo.push(Undo::Relink(original, link.to_path_buf()));
+
+		Ok(())
+	}
+
+	/// Keep every step taken
+	#[inline]
+	pub fn commit(mut self) {
+		self.committed = true;
+	}
+
+	/// Undo every step taken so far, newest first
+	fn rollback(&mut self) {
+		while let Some(step) = self.undo.pop() {
+			drop(match step {
+				Undo::RemoveDir(path) => std::fs::remove_dir_all(path),
+				Undo::Restore(path, Some(contents)) => std::fs::write(path, contents),
+				Undo::Restore(path, None) => std::fs::remove_file(path),
+				Undo::RemoveLink(link) => remove_symlink(&link),
+				Undo::Relink(original, link) => super::symlink_dir(&original, &link)
+			});
+		}
+	}
+}
+
+impl Drop for Transaction {
+	#[inline]
+	fn drop(&mut self) {
+		if !self.committed {
+			self.rollback();
+		}
+	}
+}
+
+/// Remove a directory symlink, OS independent
+fn remove_symlink(link: &Path) -> Result<(), std::io::Error> {
+	#[cfg(unix)]
+	return std::fs::remove_file(link);
+
+	#[cfg(target_os = "windows")]
+	return std::fs::remove_dir(link);
+}
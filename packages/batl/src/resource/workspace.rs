@@ -1,13 +1,59 @@
+use batl_macros::versioned_config;
+use crate::context::BatlContext;
 use crate::error as batlerror;
-use semver::Version;
+use crate::gitignore::Gitignore;
+use crate::resolve;
+use semver::{Version, VersionReq};
+use schemars::JsonSchema;
 use serde::{Serialize, Deserialize};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use super::{tomlconfig, Name, Resource};
 use super::repository::Repository;
-use super::tomlconfig::TomlConfig;
+use super::script::Script;
+use super::tomlconfig::TomlConfig as _;
+use super::transaction::Transaction;
 
 
+/// How a link of a workspace differs between its config
+/// and the filesystem
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LinkDrift {
+	/// The link is configured, but there is no symlink
+	Missing,
+
+	/// A symlink into the battalion root that isn't
+	/// configured, with where it leads
+	Extra(PathBuf),
+
+	/// The symlink leads somewhere other than the
+	/// repository or a fetched version of it
+	Elsewhere(PathBuf),
+
+	/// The linked repository doesn't exist locally
+	Unresolved,
+
+	/// The linked version doesn't meet the requirement of
+	/// the workspace dependencies
+	Unmet {
+		requirement: VersionReq,
+		found: Version
+	}
+}
+
+/// A link of a workspace that has drifted
+#[non_exhaustive]
+pub struct LinkDiff {
+	pub link: String,
+
+	/// The repository the link is configured for, `None`
+	/// for extra symlinks
+	pub repository: Option<Name>,
+
+	pub drift: LinkDrift
+}
+
 pub struct Workspace {
 	/// The path of the workspace in the filesystem
 	path: PathBuf,
@@ -28,17 +74,31 @@ impl Workspace {
 	/// Propogates any errors thrown during the process.
 	#[inline]
 	pub fn load(name: Name) -> Result<Option<Self>, batlerror::GeneralResourceError> {
-		let repo_path = crate::system::workspace_root()
-			.map(|p| p.join(PathBuf::from(&name)));
+		Self::load_in(&BatlContext::detect(), name)
+	}
+
+	/// Load the workspace with the given name from the
+	/// battalion of a context. Returns `None` if it could
+	/// not be found.
+	/// 
+	/// # Errors
+	/// 
+	/// Propogates any errors thrown during the process.
+	#[inline]
+	pub fn load_in(context: &BatlContext, name: Name) -> Result<Option<Self>, batlerror::GeneralResourceError> {
+		let normal_name = name.normalized(context.settings().name_case);
+
+		let repo_path = context.system().workspace_root()
+			.map(|p| p.join(PathBuf::from(&normal_name)));
 
 		if let Some(path) = repo_path {
-			let toml = AnyTomlConfig::read_toml(&path.join("batl.toml"))?;
+			let toml = AnyTomlConfig::read_toml_with(&path.join("batl.toml"), context.settings().strict)?;
 			let latest = TomlConfigLatest::from(toml);
 
 			Ok(Some(Self {
 				path,
 				config: Config::from(latest),
-				name
+				name: normal_name
 			}))
 		} else {
 			Ok(None)
@@ -52,22 +112,49 @@ impl Workspace {
 	/// Propogates any errors received during creation.
 	#[inline]
 	pub fn create(name: Name) -> Result<Self, batlerror::CreateResourceError> {
-		let path = crate::system::workspace_root()
-			.ok_or(batlerror::CreateResourceError::NotSetup)?
-			.join(PathBuf::from(&name));
+		Self::create_in(&BatlContext::detect(), name)
+	}
+
+	/// Creates a workspace in the battalion of a context.
+	/// 
+	/// # Errors
+	/// 
+	/// Propogates any errors received during creation.
+	#[inline]
+	pub fn create_in(context: &BatlContext, name: Name) -> Result<Self, batlerror::CreateResourceError> {
+		let mut transaction = Transaction::default();
+		let workspace = Self::init_at(context, name, &mut transaction)?;
+		transaction.commit();
+
+		Ok(workspace)
+	}
+
+	/// Creates the directory and configuration of a new
+	/// workspace as part of a transaction
+	fn init_at(context: &BatlContext, name: Name, transaction: &mut Transaction) -> Result<Self, batlerror::CreateResourceError> {
+		let normal_name = name.normalized(context.settings().name_case);
 
-		std::fs::create_dir_all(&path)?;
+		let workspace_root = context.system().workspace_root()
+			.ok_or(batlerror::CreateResourceError::NotSetup)?;
+
+		super::check_collision(&workspace_root, &normal_name)?;
+
+		let path = workspace_root.join(PathBuf::from(&normal_name));
+
+		transaction.create_dir_all(&path)?;
 
 		let batl_toml_path = path.join("batl.toml");
 		let toml = TomlConfigLatest {
 			environment: tomlconfig::EnvironmentLatest::default(),
 			workspace: tomlconfig::WorkspaceLatest {
-				name: name.clone(),
+				name: normal_name.clone(),
 				version: Version::new(0, 1, 0)
 			},
 			links: None,
+			pins: None,
 			scripts: None,
 			dependencies: None,
+			env: None
 		};
 
 		tomlconfig::write_toml(&batl_toml_path, &toml)?;
@@ -75,10 +162,62 @@ impl Workspace {
 		Ok(Self {
 			path,
 			config: toml.into(),
-			name
+			name: normal_name
 		})
 	}
 
+	/// Creates a workspace in the battalion of a context,
+	/// linking a repository and every dependency of it that
+	/// is available locally, each under the last part of its
+	/// name. Returns the workspace with the dependencies that
+	/// weren't linked. If any link fails, the workspace is
+	/// removed again.
+	/// 
+	/// # Errors
+	/// 
+	/// Propogates any errors received during creation, and
+	/// any errors resolving the dependencies.
+	#[inline]
+	pub fn create_from_repository_in(context: &BatlContext, name: Name, repository: &Repository) -> Result<(Self, Vec<Name>), batlerror::CreateDependentResourceError> {
+		let mut transaction = Transaction::default();
+		let mut workspace = Self::init_at(context, name, &mut transaction)?;
+
+		let mut dependencies = repository.config().dependencies.keys().cloned().collect::<Vec<_>>();
+		dependencies.sort_by_key(ToString::to_string);
+
+		let mut missing = Vec::new();
+
+		workspace.link_by_name(context, repository)?;
+
+		for dependency in dependencies {
+			match resolve::resolve(context, &dependency, &resolve::Options::default())? {
+				Some(resolved) => workspace.link_by_name(context, &resolved.repository)?,
+				None => missing.push(dependency)
+			}
+		}
+
+		transaction.commit();
+
+		Ok((workspace, missing))
+	}
+
+	/// Link a repository under the last part of its name, or
+	/// its whole name when another link has that already
+	fn link_by_name(&mut self, context: &BatlContext, repo: &Repository) -> Result<(), batlerror::CreateResourceError> {
+		let full_name = repo.name().to_string();
+		let short_name = full_name.rsplit('/').next().unwrap_or(&full_name).to_owned();
+
+		// Dependencies from different namespaces may share
+		// the last part of their names
+		let link = if self.links().contains_key(&short_name) {
+			full_name.replace('/', "-")
+		} else {
+			short_name
+		};
+
+		self.create_link(context, &link, repo)
+	}
+
 	/// Saves the workspace to the local filesystem
 	/// 
 	/// # Errors
@@ -99,12 +238,30 @@ impl Workspace {
 	/// Propogates any errors back to the caller
 	#[inline]
 	pub fn from_path(path: &Path) -> Result<Self, batlerror::GeneralResourceError> {
+		Self::from_path_in(&BatlContext::detect(), path)
+	}
+
+	/// Load a workspace from a path as `from_path` does,
+	/// naming it after where it is stored in the battalion
+	/// of a context
+	/// 
+	/// # Errors
+	/// 
+	/// Propogates any errors back to the caller
+	#[inline]
+	pub fn from_path_in(context: &BatlContext, path: &Path) -> Result<Self, batlerror::GeneralResourceError> {
 		let toml = TomlConfigLatest::read_toml(&path.join("batl.toml"))?;
+		let config = Config::from(toml);
+
+		let stored_name = context.system().workspace_root()
+			.map(|root| Name::from_path_in(&root, path))
+			.transpose()?
+			.flatten();
 
 		Ok(Self {
-			name: path.into(),
+			name: stored_name.unwrap_or_else(|| config.name.clone()),
 			path: path.to_path_buf(),
-			config: Config::from(toml)
+			config
 		})
 	}
 
@@ -118,9 +275,20 @@ impl Workspace {
 	/// Propogates any errors to the caller
 	#[inline]
 	pub fn locate_then_load(path: &Path) -> Result<Option<Self>, batlerror::GeneralResourceError> {
+		Self::locate_then_load_in(&BatlContext::detect(), path)
+	}
+
+	/// Find a workspace in the parents of a path, as
+	/// `locate_then_load` does, in the battalion of a context
+	/// 
+	/// # Errors
+	/// 
+	/// Propogates any errors to the caller
+	#[inline]
+	pub fn locate_then_load_in(context: &BatlContext, path: &Path) -> Result<Option<Self>, batlerror::GeneralResourceError> {
 		TomlConfigLatest::locate(path)
 			.and_then(|p| p.parent().map(Path::to_path_buf))
-			.map(|p| Self::from_path(&p))
+			.map(|p| Self::from_path_in(context, &p))
 			.transpose()
 	}
 
@@ -138,21 +306,45 @@ impl Workspace {
 	/// not exist, this will return `None`.
 	#[inline]
 	#[must_use]
-	pub fn link(&self, name: &str) -> Option<Repository> {
+	pub fn link(&self, context: &BatlContext, name: &str) -> Option<Repository> {
 		let res_name = self.links().get(name)?.clone();
 
-		Repository::load(res_name).ok().flatten()
+		let Some(version) = self.config.pins.get(name) else {
+			return Repository::load_in(context, res_name).ok().flatten();
+		};
+
+		let path = Repository::version_path(context, &res_name, version)?;
+
+		Repository::from_path_in(context, &path).ok()
+	}
+
+	/// Get the environment variables set for the scripts of
+	/// a link, which go over those of its repository
+	#[inline]
+	#[must_use]
+	pub fn link_env(&self, name: &str) -> HashMap<String, String> {
+		self.config.env.get(name).cloned().unwrap_or_default()
+	}
+
+	/// Get the fetched version each pinned link leads to,
+	/// by link. Other links lead to their repository.
+	#[inline]
+	#[must_use]
+	pub fn pins(&self) -> HashMap<String, Version> {
+		self.config.pins.clone()
 	}
 
 	/// Given a name and repository, create a workspace
 	/// link. This sets up the folders and symbolic
-	/// links required to do so.
+	/// links required to do so. If any step fails, the
+	/// ones before it are undone. Links to a fetched
+	/// version are pinned to it.
 	/// 
 	/// # Errors
 	/// 
 	/// Returns any errors received in the process.
 	#[inline]
-	pub fn create_link(&mut self, name: &str, repo: &Repository) -> Result<(), batlerror::CreateResourceError> {
+	pub fn create_link(&mut self, context: &BatlContext, name: &str, repo: &Repository) -> Result<(), batlerror::CreateResourceError> {
 		let mut links = self.links();
 
 		if links.contains_key(name) {
@@ -160,17 +352,27 @@ impl Workspace {
 		}
 
 		links.insert(name.to_owned(), repo.name().clone());
-		self.config.links = links;
 
-		super::symlink_dir(repo.path(), &self.path.join(name))?;
+		let mut pins = self.pins();
 
-		self.save()?;
+		if repo.is_fetched_version(context) {
+			pins.insert(name.to_owned(), repo.config().version.clone());
+		} else {
+			pins.remove(name);
+		}
+
+		let mut transaction = Transaction::default();
+		transaction.symlink_dir(repo.path(), &self.path.join(name))?;
+
+		self.set_links(links, pins, &mut transaction)?;
+		transaction.commit();
 
 		Ok(())
 	}
 
 	/// Delete a repository link by name. This removes
-	/// the entry and the symlink from the workspace.
+	/// the entry and the symlink from the workspace. If
+	/// any step fails, the ones before it are undone.
 	/// 
 	/// # Errors
 	/// 
@@ -184,15 +386,286 @@ impl Workspace {
 		}
 
 		links.remove(name);
-		self.config.links = links;
 
-		std::fs::remove_file(self.path.join(name))?;
+		let mut pins = self.pins();
+		pins.remove(name);
 
-		self.save()?;
+		let mut transaction = Transaction::default();
+		transaction.remove_link(&self.path.join(name))?;
+
+		self.set_links(links, pins, &mut transaction)?;
+		transaction.commit();
 
 		Ok(())
 	}
 
+	/// Point every link pinned to an older version of a
+	/// repository at a newer fetched version, as long as it
+	/// meets the workspace dependency on the repository.
+	/// Returns the links that were repointed, sorted.
+	/// 
+	/// # Errors
+	/// 
+	/// Returns `DoesNotExist` if the version hasn't been
+	/// fetched, and propogates any errors while relinking
+	#[inline]
+	pub fn relink(&mut self, context: &BatlContext, name: &Name, version: &Version) -> Result<Vec<String>, batlerror::GeneralResourceError> {
+		let requirement = self.config.dependencies.get(name)
+			.and_then(|requirement| VersionReq::parse(requirement).ok());
+
+		if requirement.is_some_and(|wanted| !wanted.matches(version)) {
+			return Ok(Vec::new());
+		}
+
+		let mut stale = self.links().into_iter()
+			.filter(|pair| pair.1 == *name && self.config.pins.get(&pair.0).is_some_and(|pinned| pinned < version))
+			.map(|(link, _)| link)
+			.collect::<Vec<_>>();
+
+		if stale.is_empty() {
+			return Ok(stale);
+		}
+
+		stale.sort();
+
+		let repository = Repository::version_path(context, name, version)
+			.and_then(|path| Repository::from_path_in(context, &path).ok())
+			.ok_or(batlerror::GeneralResourceError::DoesNotExist)?;
+
+		let mut pins = self.pins();
+		let mut transaction = Transaction::default();
+
+		for link in &stale {
+			let link_path = self.path.join(link);
+
+			if std::fs::symlink_metadata(&link_path).is_ok_and(|metadata| metadata.is_symlink()) {
+				transaction.remove_link(&link_path)?;
+			}
+
+			transaction.symlink_dir(repository.path(), &link_path)?;
+			pins.insert(link.clone(), version.clone());
+		}
+
+		self.set_links(self.links(), pins, &mut transaction)?;
+		transaction.commit();
+
+		Ok(stale)
+	}
+
+	/// Save new links and pins to the config and the
+	/// `.gitignore` as part of a transaction. The links in
+	/// memory are put back if either can't be written.
+	/// 
+	/// # Errors
+	/// 
+	/// Propogates any IO errors to the caller
+	fn set_links(&mut self, links: HashMap<String, Name>, pins: HashMap<String, Version>, transaction: &mut Transaction) -> Result<(), std::io::Error> {
+		let previous = core::mem::replace(&mut self.config.links, links);
+		let previous_pins = core::mem::replace(&mut self.config.pins, pins);
+
+		let result = transaction.snapshot(&self.path.join("batl.toml"))
+			.and_then(|()| self.save())
+			.and_then(|()| transaction.snapshot(&self.path.join(".gitignore")))
+			.and_then(|()| self.ignore_links());
+
+		if result.is_err() {
+			self.config.links = previous;
+			self.config.pins = previous_pins;
+		}
+
+		result
+	}
+
+	/// Compare the configured links with the symlinks in
+	/// the workspace, and the linked versions with the
+	/// workspace dependencies. Results are sorted by link.
+	/// 
+	/// # Errors
+	/// 
+	/// Propogates any IO errors while reading the
+	/// workspace
+	#[inline]
+	pub fn link_drift(&self, context: &BatlContext) -> Result<Vec<LinkDiff>, std::io::Error> {
+		let mut diffs = Vec::new();
+
+		let mut links = self.links().into_iter().collect::<Vec<_>>();
+		links.sort_by(|a, b| a.0.cmp(&b.0));
+
+		for (link, repository) in links {
+			if let Some(drift) = self.drift_of(context, &link, &repository)? {
+				diffs.push(LinkDiff { link, repository: Some(repository), drift });
+			}
+		}
+
+		let managed = [context.system().repository_root(), context.system().version_root()]
+			.into_iter()
+			.flatten()
+			.filter_map(|root| root.canonicalize().ok())
+			.collect::<Vec<_>>();
+
+		for result in std::fs::read_dir(&self.path)? {
+			let entry = result?;
+			let link = entry.file_name().to_string_lossy().into_owned();
+
+			if !entry.file_type()?.is_symlink() || self.config.links.contains_key(&link) {
+				continue;
+			}
+
+			let Ok(target) = entry.path().canonicalize() else {
+				continue;
+			};
+
+			if managed.iter().any(|root| target.starts_with(root)) {
+				diffs.push(LinkDiff { link, repository: None, drift: LinkDrift::Extra(target) });
+			}
+		}
+
+		diffs.sort_by(|a, b| a.link.cmp(&b.link));
+
+		Ok(diffs)
+	}
+
+	/// Links to repositories that are no longer dependencies
+	/// of the workspace, sorted by link. Workspaces that
+	/// don't declare dependencies may link anything, so none
+	/// of their links are obsolete.
+	#[inline]
+	#[must_use]
+	pub fn obsolete_links(&self) -> Vec<(String, Name)> {
+		if self.config.dependencies.is_empty() {
+			return Vec::new();
+		}
+
+		let mut obsolete = self.links()
+			.into_iter()
+			.filter(|link| !self.config.dependencies.contains_key(&link.1))
+			.collect::<Vec<_>>();
+
+		obsolete.sort_by(|a, b| a.0.cmp(&b.0));
+
+		obsolete
+	}
+
+	/// How a configured link has drifted, if it has. Links
+	/// may lead to the repository or any fetched version of
+	/// it, as long as the version meets the requirement.
+	fn drift_of(&self, context: &BatlContext, link: &str, name: &Name) -> Result<Option<LinkDrift>, std::io::Error> {
+		let link_path = self.path.join(link);
+
+		if let Err(err) = std::fs::symlink_metadata(&link_path) {
+			return if err.kind() == std::io::ErrorKind::NotFound {
+				Ok(Some(LinkDrift::Missing))
+			} else {
+				Err(err)
+			};
+		}
+
+		let Some(repository) = self.link(context, link) else {
+			return Ok(Some(LinkDrift::Unresolved));
+		};
+
+		let Ok(target) = link_path.canonicalize() else {
+			return Ok(Some(LinkDrift::Elsewhere(std::fs::read_link(&link_path).unwrap_or(link_path))));
+		};
+
+		let versions = context.system().version_root()
+			.map(|root| root.join(PathBuf::from(name)))
+			.and_then(|path| path.canonicalize().ok());
+
+		let is_repository = repository.path().canonicalize().is_ok_and(|path| path == target);
+		let is_version = versions.is_some_and(|path| target.parent() == Some(path.as_path()));
+
+		if !std::fs::symlink_metadata(&link_path)?.is_symlink() || !(is_repository || is_version) {
+			return Ok(Some(LinkDrift::Elsewhere(target)));
+		}
+
+		let requirement = self.config.dependencies.get(name)
+			.and_then(|requirement| VersionReq::parse(requirement).ok());
+
+		let found = Repository::from_path_in(context, &target).ok().map(|linked| linked.config().version.clone());
+
+		Ok(requirement.zip(found)
+			.filter(|pair| !pair.0.matches(&pair.1))
+			.map(|(wanted, version)| LinkDrift::Unmet { requirement: wanted, found: version }))
+	}
+
+	/// Point the symlink of a configured link back at its
+	/// repository, replacing any symlink that is there
+	/// 
+	/// # Errors
+	/// 
+	/// Returns `DoesNotExist` if the link isn't configured
+	/// or its repository doesn't exist, and `Invalid` if
+	/// something other than a symlink is in the way
+	#[inline]
+	pub fn repair_link(&self, context: &BatlContext, link: &str) -> Result<(), batlerror::GeneralResourceError> {
+		let repository = self.link(context, link)
+			.ok_or(batlerror::GeneralResourceError::DoesNotExist)?;
+
+		let link_path = self.path.join(link);
+
+		let mut transaction = Transaction::default();
+
+		match std::fs::symlink_metadata(&link_path) {
+			Ok(metadata) if metadata.is_symlink() => transaction.remove_link(&link_path)?,
+			Ok(_) => return Err(batlerror::GeneralResourceError::Invalid),
+			Err(err) if err.kind() == std::io::ErrorKind::NotFound => {},
+			Err(err) => return Err(err.into())
+		}
+
+		transaction.symlink_dir(repository.path(), &link_path)?;
+		transaction.commit();
+
+		Ok(())
+	}
+
+	/// Remove a symlink from the workspace that isn't a
+	/// configured link
+	/// 
+	/// # Errors
+	/// 
+	/// Returns `Invalid` if the link is configured, and
+	/// propogates any IO errors
+	#[inline]
+	pub fn remove_extra_link(&self, link: &str) -> Result<(), batlerror::GeneralResourceError> {
+		if self.config.links.contains_key(link) {
+			return Err(batlerror::GeneralResourceError::Invalid);
+		}
+
+		let mut transaction = Transaction::default();
+		transaction.remove_link(&self.path.join(link))?;
+		transaction.commit();
+
+		Ok(())
+	}
+
+	/// Keep the link symlinks out of git, in a managed
+	/// block of the workspace `.gitignore`
+	/// 
+	/// # Errors
+	/// 
+	/// Propogates any IO errors to the caller
+	fn ignore_links(&self) -> Result<(), std::io::Error> {
+		let path = self.path.join(".gitignore");
+		let mut gitignore = Gitignore::load(&path)?;
+
+		let mut links = self.config.links.keys()
+			.map(|link| format!("/{link}"))
+			.collect::<Vec<_>>();
+
+		links.sort();
+
+		if links.is_empty() {
+			if !gitignore.remove_block("links") {
+				return Ok(());
+			}
+		} else {
+			gitignore.set_block("links", links);
+		}
+
+		gitignore.save(&path)
+	}
+
 	/// Destroy the workspace altogether. This is not reversible!
 	/// 
 	/// # Errors
@@ -231,108 +704,60 @@ pub struct Config {
 	pub name: Name,
 	pub version: Version,
 	pub links: HashMap<String, Name>,
-	pub scripts: HashMap<String, String>,
-	pub dependencies: HashMap<Name, String>
-}
 
-#[non_exhaustive]
-pub enum AnyTomlConfig {
-	V0_2_2(TomlConfig0_2_2),
-	V0_2_1(TomlConfig0_2_1),
-	V0_2_0(TomlConfig0_2_0)
-}
-
-#[allow(clippy::missing_trait_methods)]
-impl TomlConfig for AnyTomlConfig {
-	#[inline]
-	fn read_toml(path: &Path) -> Result<Self, batlerror::ReadConfigError> {
-		let config_str = std::fs::read_to_string(path)?;
-
-		if let Ok(v022) = toml::from_str(&config_str) {
-			return Ok(Self::V0_2_2(v022));
-		}
-
-		if let Ok(v022) = toml::from_str(&config_str) {
-			return Ok(Self::V0_2_1(v022));
-		}
-
-		Ok(Self::V0_2_0(toml::from_str(&config_str)?))
-	}
-}
+	/// The fetched version each pinned link leads to, by
+	/// link
+	pub pins: HashMap<String, Version>,
+	pub scripts: HashMap<String, Script>,
+	pub dependencies: HashMap<Name, String>,
 
-impl From<AnyTomlConfig> for TomlConfigLatest {
-	#[inline]
-	fn from(value: AnyTomlConfig) -> Self {
-		match value {
-			AnyTomlConfig::V0_2_0(v020) => v020.into(),
-			AnyTomlConfig::V0_2_1(v021) => v021.into(),
-			AnyTomlConfig::V0_2_2(v022) => v022
-		}
-	}
+	/// Environment variables set for the scripts of each
+	/// link, by link
+	pub env: HashMap<String, HashMap<String, String>>
 }
 
 // CONFIG VERSIONS //
 pub type TomlConfigLatest = TomlConfig0_2_2;
 
-#[derive(Serialize, Deserialize, Clone, PartialEq)]
-#[non_exhaustive]
-pub struct TomlConfig0_2_2 {
-	pub environment: tomlconfig::Environment0_2_2,
-	pub workspace: tomlconfig::Workspace0_2_2,
-	pub links: Option<tomlconfig::Links0_2_2>,
-	pub scripts: Option<tomlconfig::Scripts0_2_2>,
-	pub dependencies: Option<tomlconfig::Dependencies0_2_2>
-}
-
-#[derive(Serialize, Deserialize, Clone, PartialEq)]
-#[non_exhaustive]
-pub struct TomlConfig0_2_1 {
-	pub environment: tomlconfig::Environment0_2_1,
-	pub repository: tomlconfig::Workspace0_2_1,
-	pub workspace: Option<tomlconfig::Links0_2_1>,
-	pub scripts: Option<tomlconfig::Scripts0_2_1>,
-	pub dependencies: Option<tomlconfig::Dependencies0_2_1>
-}
-
-impl From<TomlConfig0_2_1> for TomlConfigLatest {
-	#[inline]
-	fn from(value: TomlConfig0_2_1) -> Self {
-		Self {
-			environment: tomlconfig::EnvironmentLatest::default(),
-			workspace: tomlconfig::WorkspaceLatest {
-				name: value.repository.name,
-				version: value.repository.version
-			},
-			links: value.workspace,
-			scripts: value.scripts,
-			dependencies: value.dependencies
-		}
+versioned_config! {
+	#[non_exhaustive]
+	pub enum AnyTomlConfig;
+
+	#[derive(Serialize, Deserialize, JsonSchema, Clone, PartialEq)]
+	#[schemars(rename = "WorkspaceConfig")]
+	#[non_exhaustive]
+	pub struct TomlConfig0_2_2 {
+		pub workspace: tomlconfig::Workspace0_2_2,
+		pub links: Option<tomlconfig::Links0_2_2>,
+
+		/// The fetched version a link leads to, by link, for
+		/// links pinned to a version instead of following the
+		/// repository
+		pub pins: Option<HashMap<String, Version>>,
+		pub scripts: Option<tomlconfig::Scripts0_2_2>,
+		pub dependencies: Option<tomlconfig::Dependencies0_2_2>,
+
+		/// Environment variables set for the scripts of a
+		/// link, by link, over those of its repository
+		pub env: Option<HashMap<String, tomlconfig::Env0_2_2>>
 	}
-}
 
-#[derive(Serialize, Deserialize, Clone, PartialEq)]
-#[non_exhaustive]
-pub struct TomlConfig0_2_0 {
-	pub environment: tomlconfig::Environment0_2_0,
-	pub repository: tomlconfig::Workspace0_2_0,
-	pub workspace: Option<tomlconfig::Links0_2_0>,
-	pub scripts: Option<tomlconfig::Scripts0_2_0>,
-	pub dependencies: Option<tomlconfig::Dependencies0_2_0>
-}
+	#[derive(Serialize, Deserialize, Clone, PartialEq)]
+	#[non_exhaustive]
+	pub struct TomlConfig0_2_1 {
+		pub repository: tomlconfig::Workspace0_2_1 => workspace with Into::into,
+		pub workspace: Option<tomlconfig::Links0_2_1> => links,
+		pub scripts: Option<tomlconfig::Scripts0_2_1> => with tomlconfig::upgrade_scripts,
+		pub dependencies: Option<tomlconfig::Dependencies0_2_1>
+	}
 
-impl From<TomlConfig0_2_0> for TomlConfigLatest {
-	#[inline]
-	fn from(value: TomlConfig0_2_0) -> Self {
-		Self {
-			environment: tomlconfig::EnvironmentLatest::default(),
-			workspace: tomlconfig::WorkspaceLatest {
-				name: value.repository.name,
-				version: value.repository.version
-			},
-			links: value.workspace,
-			scripts: value.scripts,
-			dependencies: value.dependencies
-		}
+	#[derive(Serialize, Deserialize, Clone, PartialEq)]
+	#[non_exhaustive]
+	pub struct TomlConfig0_2_0 {
+		pub repository: tomlconfig::Workspace0_2_0 => workspace with Into::into,
+		pub workspace: Option<tomlconfig::Links0_2_0> => links,
+		pub scripts: Option<tomlconfig::Scripts0_2_0> => with tomlconfig::upgrade_scripts,
+		pub dependencies: Option<tomlconfig::Dependencies0_2_0>
 	}
 }
 
@@ -343,8 +768,14 @@ impl From<TomlConfig0_2_2> for Config {
 			name: value.workspace.name,
 			version: value.workspace.version,
 			links: value.links.unwrap_or_default(),
-			scripts: value.scripts.unwrap_or_default(),
-			dependencies: value.dependencies.unwrap_or_default()
+			pins: value.pins.unwrap_or_default(),
+			scripts: value.scripts
+				.unwrap_or_default()
+				.into_iter()
+				.map(|(k, v)| (k, v.into()))
+				.collect(),
+			dependencies: value.dependencies.unwrap_or_default(),
+			env: value.env.unwrap_or_default()
 		}
 	}
 }
@@ -352,6 +783,10 @@ impl From<TomlConfig0_2_2> for Config {
 impl From<Config> for TomlConfigLatest {
 	#[inline]
 	fn from(value: Config) -> Self {
+		let scripts = value.scripts.into_iter()
+			.map(|(k, v)| (k, v.into()))
+			.collect::<HashMap<_, _>>();
+
 		Self {
 			environment: tomlconfig::EnvironmentLatest::default(),
 			workspace: tomlconfig::WorkspaceLatest {
@@ -359,8 +794,10 @@ impl From<Config> for TomlConfigLatest {
 				version: value.version
 			},
 			links: tomlconfig::hashmap_to_option_hashmap(value.links),
-			scripts: tomlconfig::hashmap_to_option_hashmap(value.scripts),
-			dependencies: tomlconfig::hashmap_to_option_hashmap(value.dependencies)
+			pins: tomlconfig::hashmap_to_option_hashmap(value.pins),
+			scripts: tomlconfig::hashmap_to_option_hashmap(scripts),
+			dependencies: tomlconfig::hashmap_to_option_hashmap(value.dependencies),
+			env: tomlconfig::hashmap_to_option_hashmap(value.env)
 		}
 	}
 }
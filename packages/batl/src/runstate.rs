@@ -0,0 +1,159 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+
+/// The progress of a command run across several
+/// repositories, kept in `gen/run-state/<id>.json` after
+/// every item so a run that crashed or was interrupted can
+/// be resumed instead of started over
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RunState {
+	pub id: String,
+
+	/// The arguments batl was run with, which a resumed
+	/// run is given again
+	pub args: Vec<String>,
+
+	/// The absolute folder batl was run in, which a resumed
+	/// run goes back to
+	pub cwd: PathBuf,
+
+	/// The absolute battalion root the run went through
+	pub root: PathBuf,
+
+	/// When the run started, in seconds since the unix
+	/// epoch
+	pub started: u64,
+
+	/// The items that went through, which a resumed run
+	/// skips
+	pub completed: Vec<String>
+}
+
+impl RunState {
+	/// A new run of the given arguments in a folder and
+	/// battalion root, named after when it started and the
+	/// process running it
+	#[inline]
+	#[must_use]
+	pub fn new(args: Vec<String>, cwd: PathBuf, root: PathBuf) -> Self {
+		let started = crate::system::unix_time();
+
+		Self {
+			id: format!("{started}-{}", std::process::id()),
+			args,
+			cwd,
+			root,
+			started,
+			completed: Vec::new()
+		}
+	}
+
+	/// Load a run kept under the given root
+	///
+	/// # Errors
+	///
+	/// Returns `NotFound` if there is no such run, and fails
+	/// if its state can't be read. Propogates any other IO
+	/// errors while reading it.
+	#[inline]
+	pub fn load(root: &Path, id: &str) -> Result<Self, std::io::Error> {
+		// Ids become file names, so they can't reach out of
+		// the root
+		if !crate::stamped::is_valid(id) {
+			return Err(std::io::Error::new(std::io::ErrorKind::NotFound, format!("no run {id}")));
+		}
+
+		crate::stamped::read(&state_path(root, id))
+	}
+
+	/// Every run kept under the given root, oldest first.
+	/// States that can't be read are skipped.
+	///
+	/// # Errors
+	///
+	/// Propogates any IO errors while listing the runs
+	#[inline]
+	pub fn list(root: &Path) -> Result<Vec<Self>, std::io::Error> {
+		if !root.exists() {
+			return Ok(Vec::new());
+		}
+
+		let mut runs = Vec::new();
+
+		for dir_entry in std::fs::read_dir(root)? {
+			let path = dir_entry?.path();
+
+			if path.extension().is_none_or(|extension| extension != "json") {
+				continue;
+			}
+
+			runs.extend(crate::stamped::read::<Self>(&path).ok());
+		}
+
+		runs.sort_by(|a, b| a.started.cmp(&b.started).then_with(|| a.id.cmp(&b.id)));
+
+		Ok(runs)
+	}
+
+	/// Whether an item went through
+	#[inline]
+	#[must_use]
+	pub fn is_completed(&self, item: &str) -> bool {
+		self.completed.iter().any(|completed| completed == item)
+	}
+
+	/// Note that an item went through, saving the state right
+	/// away
+	///
+	/// # Errors
+	///
+	/// Propogates any IO errors while saving the state
+	#[inline]
+	pub fn complete(&mut self, root: &Path, item: &str) -> Result<(), std::io::Error> {
+		if !self.is_completed(item) {
+			self.completed.push(item.to_owned());
+		}
+
+		self.save(root)
+	}
+
+	/// Write the state under the given root. It is written
+	/// beside the old state and moved over it, so a crash
+	/// while saving leaves the old one whole.
+	///
+	/// # Errors
+	///
+	/// Propogates any IO errors while writing the state
+	#[inline]
+	pub fn save(&self, root: &Path) -> Result<(), std::io::Error> {
+		std::fs::create_dir_all(root)?;
+
+		let partial = root.join(format!("{}.json.partial", self.id));
+		crate::stamped::write(&partial, self)?;
+		std::fs::rename(partial, state_path(root, &self.id))
+	}
+
+	/// Remove the state, once the run has nothing left to
+	/// resume
+	///
+	/// # Errors
+	///
+	/// Propogates any IO errors while removing it
+	#[inline]
+	pub fn remove(&self, root: &Path) -> Result<(), std::io::Error> {
+		let path = state_path(root, &self.id);
+
+		if path.exists() {
+			std::fs::remove_file(path)?;
+		}
+
+		Ok(())
+	}
+}
+
+/// Where a run is kept under the given root
+fn state_path(root: &Path, id: &str) -> PathBuf {
+	root.join(format!("{id}.json"))
+}
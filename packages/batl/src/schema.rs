@@ -0,0 +1,102 @@
+use crate::error::ReadConfigError;
+use crate::resource::{repository, tomlconfig, workspace};
+use crate::version::{SchemaVersion as _, VersionLatest};
+use schemars::{json_schema, Schema, SchemaGenerator};
+
+
+/// A place where a config doesn't match the schema
+#[derive(Clone)]
+#[non_exhaustive]
+pub struct Problem {
+	/// JSON pointer to the offending value, such as
+	/// `/scripts/build/timeout`
+	pub path: String,
+	pub message: String
+}
+
+/// JSON Schema of the latest repository batl.toml format
+#[inline]
+#[must_use]
+pub fn repository() -> Schema {
+	schemars::schema_for!(repository::TomlConfigLatest)
+}
+
+/// JSON Schema of the latest workspace batl.toml format
+#[inline]
+#[must_use]
+pub fn workspace() -> Schema {
+	schemars::schema_for!(workspace::TomlConfigLatest)
+}
+
+/// JSON Schema of any batl.toml in the latest format,
+/// whether it belongs to a repository or a workspace
+///
+/// This is the schema editors are pointed at.
+#[inline]
+#[must_use]
+pub fn batl_toml() -> Schema {
+	let mut generator = SchemaGenerator::default();
+
+	let repository = generator.subschema_for::<repository::TomlConfigLatest>();
+	let workspace = generator.subschema_for::<workspace::TomlConfigLatest>();
+
+	let mut schema = json_schema!({
+		"$schema": generator.settings().meta_schema,
+		"title": "batl.toml",
+		"description": format!("A battalion repository or workspace config, format {}", crate::version::LATEST),
+		"anyOf": [repository, workspace]
+	});
+
+	schema.insert("$defs".to_owned(), generator.take_definitions(true).into());
+
+	schema
+}
+
+/// Check a batl.toml against the schema of its kind,
+/// returning every place it doesn't match
+///
+/// Configs in an older format are reported as a single
+/// problem, since the schema only describes the latest.
+///
+/// # Errors
+///
+/// Returns any toml errors, `MissingVersion` or
+/// `UnknownVersion` if the version can't be read, and
+/// `NewerVersion` for configs from a newer release
+#[inline]
+pub fn check(config_str: &str) -> Result<Vec<Problem>, ReadConfigError> {
+	let table: toml::Table = toml::from_str(config_str)?;
+	let version = tomlconfig::declared_version(config_str)?;
+
+	let latest = VersionLatest::default();
+
+	if latest != version {
+		if VersionLatest::is_newer(&version) {
+			return Err(ReadConfigError::NewerVersion(version.to_string()));
+		}
+
+		return Ok(vec![Problem {
+			path: "/environment/version".to_owned(),
+			message: format!("Config is in the {version} format, the schema describes {latest}")
+		}]);
+	}
+
+	let schema = if table.contains_key("workspace") {
+		workspace()
+	} else {
+		repository()
+	};
+
+	let validator = jsonschema::validator_for(schema.as_value())
+		.map_err(|err| ReadConfigError::InvalidSchema(err.to_string()))?;
+
+	let instance = serde_json::to_value(&table)
+		.map_err(|err| ReadConfigError::InvalidSchema(err.to_string()))?;
+
+	Ok(validator.iter_errors(&instance)
+		.map(|err| Problem {
+			path: err.instance_path().to_string(),
+			message: err.to_string()
+		})
+		.collect())
+}
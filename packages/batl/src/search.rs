@@ -0,0 +1,177 @@
+use crate::context::BatlContext;
+use crate::error as batlerror;
+use crate::resource::{Repository, Resource as _};
+use crate::system;
+use globset::GlobMatcher;
+use ignore::{WalkBuilder, WalkParallel, WalkState};
+use rayon::prelude::*;
+use regex::Regex;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+
+
+/// A line of a file that matched a search
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct LineMatch {
+	/// The file the line is in
+	pub path: PathBuf,
+
+	/// The line number, starting at 1
+	pub line_number: usize,
+
+	/// The contents of the line
+	pub line: String
+}
+
+/// The scripts a repository defines itself, leaving out
+/// the ones every repository gets from the batlrc
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct DefinedScripts {
+	/// Where the repository is
+	pub path: PathBuf,
+
+	/// The names of its scripts, sorted, or why its config
+	/// couldn't be read
+	pub scripts: Result<Vec<String>, batlerror::GeneralResourceError>
+}
+
+/// Search the contents of every file under the roots,
+/// walked in parallel and respecting ignore rules. Files
+/// that are not valid UTF-8 are skipped.
+#[inline]
+#[must_use]
+pub fn grep(roots: &[PathBuf], pattern: &Regex) -> Vec<LineMatch> {
+	let mut matches = walk_files(roots, |path| {
+		let Ok(contents) = std::fs::read_to_string(path) else {
+			return Vec::new();
+		};
+
+		(1..).zip(contents.lines())
+			.filter(|&(_, line)| pattern.is_match(line))
+			.map(|(line_number, line)| LineMatch {
+				path: path.to_path_buf(),
+				line_number,
+				line: line.to_owned()
+			})
+			.collect()
+	});
+
+	matches.sort_by(|a, b| a.path.cmp(&b.path).then(a.line_number.cmp(&b.line_number)));
+
+	matches
+}
+
+/// Find every file under the roots matching a glob,
+/// walked in parallel and respecting ignore rules. Globs
+/// containing a `/` match the path relative to the root,
+/// others only match the file name.
+#[inline]
+#[must_use]
+pub fn find(roots: &[PathBuf], glob: &GlobMatcher) -> Vec<PathBuf> {
+	let whole_path = glob.glob().glob().contains('/');
+
+	let mut found = walk_files(roots, |path| {
+		let candidate = if whole_path {
+			root_of(roots, path).and_then(|root| path.strip_prefix(root).ok())
+		} else {
+			path.file_name().map(Path::new)
+		};
+
+		candidate
+			.filter(|relative| glob.is_match(relative))
+			.map(|_| path.to_path_buf())
+			.into_iter()
+			.collect()
+	});
+
+	found.sort();
+
+	found
+}
+
+/// Read the scripts of every repository at the given
+/// paths in parallel, kept in the order of the paths. Each
+/// thread reads them with a context of its own for the
+/// battalion root.
+#[inline]
+#[must_use]
+pub fn scripts(system: &system::Context, paths: &[PathBuf]) -> Vec<DefinedScripts> {
+	paths.par_iter()
+		.map_init(|| BatlContext::for_system(system.clone()), |context, path| {
+			let scripts = Repository::from_path_in(context, path).map(|repository| {
+				let mut names = repository.config().scripts.keys().cloned().collect::<Vec<_>>();
+				names.sort();
+
+				names
+			});
+
+			DefinedScripts {
+				path: path.clone(),
+				scripts
+			}
+		})
+		.collect()
+}
+
+/// Get the root a path was found under, preferring the
+/// most specific root when they are nested
+#[inline]
+#[must_use]
+pub fn root_of<'roots>(roots: &'roots [PathBuf], path: &Path) -> Option<&'roots PathBuf> {
+	roots.iter()
+		.filter(|root| path.starts_with(root))
+		.max_by_key(|root| root.components().count())
+}
+
+/// Visit every file under the roots in parallel, collecting
+/// whatever the visitor returns for each of them
+fn walk_files<T, F>(roots: &[PathBuf], visit: F) -> Vec<T>
+where
+	T: Send,
+	F: Fn(&Path) -> Vec<T> + Sync
+{
+	let (sender, receiver) = mpsc::channel();
+
+	if let Some(walker) = walker(roots) {
+		walker.run(|| {
+			let thread_sender = sender.clone();
+			let thread_visit = &visit;
+
+			Box::new(move |result| {
+				let Some(path) = result.ok()
+					.filter(|entry| entry.file_type().is_some_and(|kind| !kind.is_dir()))
+					.map(ignore::DirEntry::into_path) else {
+					return WalkState::Continue;
+				};
+
+				for item in thread_visit(&path) {
+					if thread_sender.send(item).is_err() {
+						return WalkState::Quit;
+					}
+				}
+
+				WalkState::Continue
+			})
+		});
+	}
+
+	drop(sender);
+
+	receiver.into_iter().collect()
+}
+
+/// Build a parallel walker over every root. Ignore rules
+/// apply whether or not a root is a git repository.
+fn walker(roots: &[PathBuf]) -> Option<WalkParallel> {
+	let (first, rest) = roots.split_first()?;
+
+	let mut builder = WalkBuilder::new(first);
+
+	for root in rest {
+		builder.add(root);
+	}
+
+	Some(builder.require_git(false).build_parallel())
+}
@@ -0,0 +1,284 @@
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use regex::Regex;
+use std::fs::File;
+use std::io::Read as _;
+use std::path::Path;
+
+
+/// Patterns of keys and tokens that shouldn't be published,
+/// by the name findings are reported under
+pub const RULES: [(&str, &str); 10] = [
+	("private-key", "-----BEGIN [A-Z ]*PRIVATE KEY( BLOCK)?-----"),
+	("aws-access-key", r"\b(AKIA|ASIA)[0-9A-Z]{16}\b"),
+	("github-token", r"\b(ghp|gho|ghu|ghs|ghr)_[A-Za-z0-9]{36,}\b"),
+	("github-pat", r"\bgithub_pat_[A-Za-z0-9_]{22,}\b"),
+	("gitlab-token", r"\bglpat-[A-Za-z0-9_\-]{20,}\b"),
+	("slack-token", r"\bxox[abposr]-[A-Za-z0-9\-]{10,}\b"),
+	("stripe-key", r"\b(sk|rk)_live_[A-Za-z0-9]{24,}\b"),
+	("google-api-key", r"\bAIza[0-9A-Za-z_\-]{35}\b"),
+	("npm-token", r"\bnpm_[A-Za-z0-9]{36}\b"),
+	("generic-secret", r#"(?i)\b(api[_\-]?key|secret|token|passw(or)?d)\b["']?\s*[:=]\s*["'][^"'\s$]{12,}["']"#)
+];
+
+/// Lines holding this marker are never reported, for test
+/// fixtures and documented examples
+pub const ALLOW_MARKER: &str = "batl:allow-secret";
+
+/// How many bytes at the start of a file are looked at to
+/// tell whether it is binary
+const BINARY_SNIFF: usize = 8000;
+
+/// How many characters of a match are kept when it is shown
+const EXCERPT_LENGTH: usize = 4;
+
+
+/// What a repository allows through the secret scan, from
+/// `[secrets]` in its batl.toml
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Allowlist {
+	/// Globs of paths that are not scanned at all
+	pub paths: Vec<String>,
+
+	/// Names of rules that are turned off
+	pub rules: Vec<String>
+}
+
+/// A likely secret found in a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Finding {
+	/// The path of the file, relative to the repository
+	pub path: String,
+
+	/// The line the secret is on, starting at 1
+	pub line: usize,
+
+	/// The name of the rule that matched
+	pub rule: String,
+
+	/// The start of the match, with the rest hidden
+	pub excerpt: String
+}
+
+/// The rules of a scan, compiled once for every file
+pub struct Scanner {
+	/// The rules that are on, by name
+	rules: Vec<(&'static str, Regex)>,
+
+	/// The paths left out of the scan
+	allowed: GlobSet
+}
+
+impl Allowlist {
+	#[inline]
+	#[must_use]
+	pub const fn new(paths: Vec<String>, rules: Vec<String>) -> Self {
+		Self { paths, rules }
+	}
+
+	/// Whether nothing is allowed through
+	#[inline]
+	#[must_use]
+	pub const fn is_empty(&self) -> bool {
+		self.paths.is_empty() && self.rules.is_empty()
+	}
+}
+
+impl Scanner {
+	/// A scanner with every rule the allowlist doesn't turn
+	/// off
+	///
+	/// # Errors
+	///
+	/// Returns an error if any of the allowed paths is not
+	/// a valid glob
+	#[inline]
+	pub fn new(allowlist: &Allowlist) -> Result<Self, globset::Error> {
+		let rules = RULES.iter()
+			.filter(|rule| !allowlist.rules.iter().any(|allowed| allowed == rule.0))
+			.filter_map(|rule| Regex::new(rule.1).ok().map(|pattern| (rule.0, pattern)))
+			.collect();
+
+		let mut builder = GlobSetBuilder::new();
+
+		for path in &allowlist.paths {
+			builder.add(Glob::new(path)?);
+		}
+
+		Ok(Self { rules, allowed: builder.build()? })
+	}
+
+	/// Scan the contents of one file. Binary files and
+	/// allowed paths are skipped.
+	#[inline]
+	#[must_use]
+	pub fn scan(&self, path: &str, contents: &[u8]) -> Vec<Finding> {
+		if self.allowed.is_match(path) || is_binary(contents) {
+			return Vec::new();
+		}
+
+		let text = String::from_utf8_lossy(contents);
+		let mut findings = Vec::new();
+
+		for (index, line) in text.lines().enumerate() {
+			if line.contains(ALLOW_MARKER) {
+				continue;
+			}
+
+			for rule in &self.rules {
+				if let Some(found) = rule.1.find(line) {
+					findings.push(Finding {
+						path: path.to_owned(),
+						line: index.saturating_add(1),
+						rule: rule.0.to_owned(),
+						excerpt: redact(found.as_str())
+					});
+				}
+			}
+		}
+
+		findings
+	}
+
+	/// Scan every file of a tar archive
+	///
+	/// # Errors
+	///
+	/// Propogates any IO errors while reading the archive
+	#[inline]
+	pub fn scan_archive(&self, path: &Path) -> Result<Vec<Finding>, std::io::Error> {
+		let mut tar = tar::Archive::new(File::open(path)?);
+		let mut findings = Vec::new();
+
+		for entry in tar.entries()? {
+			let mut file = entry?;
+
+			if !file.header().entry_type().is_file() {
+				continue;
+			}
+
+			let file_path = file.path()?.to_string_lossy().into_owned();
+
+			let mut contents = Vec::new();
+			file.read_to_end(&mut contents)?;
+
+			findings.extend(self.scan(&file_path, &contents));
+		}
+
+		findings.sort_by(|a, b| a.path.cmp(&b.path).then(a.line.cmp(&b.line)));
+
+		Ok(findings)
+	}
+}
+
+/// Whether a file looks binary, going by a null byte near
+/// its start
+fn is_binary(contents: &[u8]) -> bool {
+	contents.iter().take(BINARY_SNIFF).any(|byte| *byte == 0)
+}
+
+/// Keep the start of a match so it can be recognised,
+/// without showing the secret itself
+fn redact(found: &str) -> String {
+	let mut excerpt = found.chars().take(EXCERPT_LENGTH).collect::<String>();
+	excerpt.push_str("****");
+
+	excerpt
+}
+
+#[cfg(test)]
+#[allow(clippy::panic_in_result_fn, reason = "tests fail on their assertions, and on errors setting them up")]
+mod tests {
+	use super::{Allowlist, Scanner, RULES};
+	use core::error::Error;
+
+
+	/// A fake secret for each rule. They are put together at
+	/// runtime so the source itself doesn't look like it
+	/// holds any.
+	fn samples() -> Vec<(&'static str, String)> {
+		vec![
+			("private-key", format!("-----BEGIN RSA {} KEY-----", "PRIVATE")),
+			("aws-access-key", format!("AKIA{}", "Q".repeat(16))),
+			("github-token", format!("ghp_{}", "a".repeat(36))),
+			("github-pat", format!("github_pat_{}", "a".repeat(22))),
+			("gitlab-token", format!("glpat-{}", "a".repeat(20))),
+			("slack-token", format!("xoxb-{}", "1".repeat(10))),
+			("stripe-key", format!("sk_live_{}", "a".repeat(24))),
+			("google-api-key", format!("AIza{}", "a".repeat(35))),
+			("npm-token", format!("npm_{}", "a".repeat(36))),
+			("generic-secret", format!("api_key = \"{}\"", "a".repeat(12)))
+		]
+	}
+
+	/// The rules that match a line
+	fn rules_matching(scanner: &Scanner, line: &str) -> Vec<String> {
+		scanner.scan("file.txt", line.as_bytes())
+			.into_iter()
+			.map(|finding| finding.rule)
+			.collect()
+	}
+
+	#[test]
+	fn every_rule_compiles() -> Result<(), Box<dyn Error>> {
+		for rule in RULES {
+			regex::Regex::new(rule.1)?;
+		}
+
+		Ok(())
+	}
+
+	#[test]
+	fn every_rule_finds_its_secret() -> Result<(), Box<dyn Error>> {
+		let scanner = Scanner::new(&Allowlist::default())?;
+
+		assert_eq!(samples().len(), RULES.len());
+
+		for (rule, sample) in samples() {
+			assert!(rules_matching(&scanner, &sample).iter().any(|matched| matched == rule), "{rule} missed {sample}");
+		}
+
+		Ok(())
+	}
+
+	#[test]
+	fn ordinary_code_is_not_reported() -> Result<(), Box<dyn Error>> {
+		let scanner = Scanner::new(&Allowlist::default())?;
+
+		for line in [
+			"let token = read_token()?;",
+			"password = \"${PASSWORD}\"",
+			"api_key = \"short\"",
+			"AKIA is the prefix of access keys"
+		] {
+			assert_eq!(rules_matching(&scanner, line), Vec::<String>::new(), "{line}");
+		}
+
+		Ok(())
+	}
+
+	#[test]
+	fn allowed_lines_paths_and_rules_are_skipped() -> Result<(), Box<dyn Error>> {
+		let key = format!("AKIA{}", "Q".repeat(16));
+		let scanner = Scanner::new(&Allowlist::new(vec!["fixtures/**".to_owned()], vec!["npm-token".to_owned()]))?;
+
+		assert!(scanner.scan("file.txt", format!("{key} # batl:allow-secret").as_bytes()).is_empty());
+		assert!(scanner.scan("fixtures/keys.txt", key.as_bytes()).is_empty());
+		assert!(scanner.scan("file.txt", format!("npm_{}", "a".repeat(36)).as_bytes()).is_empty());
+		assert!(scanner.scan("file.bin", format!("\0{key}").as_bytes()).is_empty());
+
+		Ok(())
+	}
+
+	#[test]
+	fn findings_only_show_the_start_of_the_secret() -> Result<(), Box<dyn Error>> {
+		let scanner = Scanner::new(&Allowlist::default())?;
+		let findings = scanner.scan("file.txt", format!("\nAKIA{}", "Q".repeat(16)).as_bytes());
+
+		assert_eq!(findings.len(), 1);
+		assert_eq!(findings.first().map(|finding| (finding.line, finding.excerpt.as_str())), Some((2, "AKIA****")));
+
+		Ok(())
+	}
+}
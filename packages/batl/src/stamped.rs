@@ -0,0 +1,80 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::path::Path;
+use std::time::SystemTime;
+
+
+/// The current time as a name for a backup, bundle or run,
+/// such as `20240131T120000Z`, which sorts by time and is a
+/// valid file name everywhere
+#[inline]
+#[must_use]
+pub fn now() -> String {
+	humantime::format_rfc3339_seconds(SystemTime::now())
+		.to_string()
+		.chars()
+		.filter(|character| *character != '-' && *character != ':')
+		.collect()
+}
+
+/// Whether a name given by the user can only name an entry
+/// right under its root, and never reach out of it
+#[inline]
+#[must_use]
+pub fn is_valid(name: &str) -> bool {
+	!name.is_empty() && name.chars().all(|character| character.is_ascii_alphanumeric() || character == '-')
+}
+
+/// The names of the folders under a root that hold a
+/// record, oldest first
+///
+/// # Errors
+///
+/// Propogates any IO errors while listing the folders
+#[inline]
+pub fn list(root: &Path, record: &str) -> Result<Vec<String>, std::io::Error> {
+	if !root.exists() {
+		return Ok(Vec::new());
+	}
+
+	let mut names = Vec::new();
+
+	for dir_entry in std::fs::read_dir(root)? {
+		let entry = dir_entry?;
+
+		if entry.path().join(record).exists() {
+			names.push(entry.file_name().to_string_lossy().into_owned());
+		}
+	}
+
+	names.sort();
+
+	Ok(names)
+}
+
+/// Read a json record
+///
+/// # Errors
+///
+/// Propogates any IO errors while reading it, and fails
+/// with `InvalidData` if it can't be parsed
+#[inline]
+pub fn read<T: DeserializeOwned>(path: &Path) -> Result<T, std::io::Error> {
+	let contents = std::fs::read_to_string(path)?;
+
+	serde_json::from_str(&contents)
+		.map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))
+}
+
+/// Write a json record
+///
+/// # Errors
+///
+/// Propogates any IO errors while writing it
+#[inline]
+pub fn write<T: Serialize>(path: &Path, value: &T) -> Result<(), std::io::Error> {
+	let contents = serde_json::to_string_pretty(value)
+		.map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+
+	std::fs::write(path, contents)
+}
@@ -1,81 +1,505 @@
+use crate::resource::{Name, Script};
 use crate::resource::batlrc::BatlRcLatest;
+use std::collections::HashMap;
 use std::env::var as env_var;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 
-/// Get the battalion root path
-#[inline]
-#[must_use]
-pub fn batl_root() -> Option<PathBuf> {
-	// 1. Check BATL_ROOT environment variable
-	if let Ok(batl_root) = env_var("BATL_ROOT") {
-		return Some(PathBuf::from(batl_root));
+/// Where battalion keeps its files, which every root and
+/// path below is found under
+///
+/// The free functions of this module detect a context on
+/// each call. A context can also be made for a root given
+/// up front, such as for tests or isolated environments.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Context {
+	/// The battalion root, or `None` if battalion isn't set
+	/// up
+	root: Option<PathBuf>,
+
+	/// Whether the root was given with `--root` or
+	/// `BATL_ROOT`, rather than detected
+	root_given: bool,
+
+	/// The selected profile, if it is defined
+	profile: Option<ActiveProfile>,
+
+	/// The overrides the context was detected with
+	overrides: Overrides
+}
+
+/// Settings given for a single run of battalion, such as
+/// with `--root` and `--profile`, which detection prefers
+/// over the environment
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Overrides {
+	/// The battalion root to use instead of detecting one
+	pub root: Option<PathBuf>,
+
+	/// The profile to use instead of `BATL_PROFILE`
+	pub profile: Option<String>
+}
+
+/// A profile in use, and the batlrc it is defined in
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ActiveProfile {
+	/// The name of the profile
+	name: String,
+
+	/// The batlrc defining the profile
+	batlrc: PathBuf
+}
+
+impl Overrides {
+	#[inline]
+	#[must_use]
+	pub const fn new(root: Option<PathBuf>, profile: Option<String>) -> Self {
+		Self { root, profile }
 	}
 
-	// 2. Recursively descend from current directory until .batlrc is found
-	if let Ok(mut current_dir) = std::env::current_dir() {
-		loop {
-			if current_dir.join(".batlrc").exists() {
-				return Some(current_dir);
-			}
+	/// Get the profile selected for the run, given here or
+	/// with the `BATL_PROFILE` environment variable, whether
+	/// or not it is defined
+	#[inline]
+	#[must_use]
+	pub fn selected_profile(&self) -> Option<String> {
+		self.profile.clone().or_else(profile_from_env)
+	}
 
-			if !current_dir.pop() {
-				break;
-			}
+	/// The environment variables that pass the overrides on
+	/// to battalion when scripts run it again
+	#[inline]
+	#[must_use]
+	pub fn env(&self) -> HashMap<String, String> {
+		let root = self.root.iter().map(|root| ("BATL_ROOT".to_owned(), root.display().to_string()));
+		let profile = self.profile.iter().map(|profile| ("BATL_PROFILE".to_owned(), profile.clone()));
+
+		root.chain(profile).collect()
+	}
+}
+
+impl Context {
+	/// A context for the given battalion root, skipping
+	/// detection
+	#[inline]
+	#[must_use]
+	pub fn with_root(root: PathBuf) -> Self {
+		Self { root: Some(root), root_given: true, profile: None, overrides: Overrides::default() }
+	}
+
+	/// Detect the battalion root, from the `BATL_ROOT`
+	/// environment variable, then the closest parent of the
+	/// current directory with a `.batlrc`, then a
+	/// `battalion` folder in the home directory
+	///
+	/// When a profile is selected and defined in the batlrc
+	/// of that root, its root is used instead, unless
+	/// `BATL_ROOT` is set.
+	#[inline]
+	#[must_use]
+	pub fn detect() -> Self {
+		Self::detect_with(&Overrides::default())
+	}
+
+	/// Detect the battalion root like [`Context::detect`],
+	/// with the given overrides taking precedence
+	#[inline]
+	#[must_use]
+	pub fn detect_with(overrides: &Overrides) -> Self {
+		let given_root = overrides.root.clone().or_else(|| env_var("BATL_ROOT").ok().map(PathBuf::from));
+		let root = given_root.clone().or_else(detect_root);
+		let root_given = given_root.is_some();
+		let unprofiled = |found| Self { root: found, root_given, profile: None, overrides: overrides.clone() };
+
+		let Some(name) = overrides.selected_profile().filter(|name| is_profile_name(name)) else {
+			return unprofiled(root);
+		};
+
+		let Some(batlrc) = root.as_ref().map(|p| p.join(".batlrc")) else {
+			return unprofiled(root);
+		};
+
+		let Some(profile) = read_batlrc(&batlrc)
+			.and_then(|rc| rc.profile)
+			.and_then(|mut profiles| profiles.remove(&name)) else {
+			return unprofiled(root);
+		};
+
+		let profile_root = profile.root
+			.filter(|_| given_root.is_none())
+			.and_then(|given| root.as_ref().map(|base| base.join(given)));
+
+		Self {
+			root: profile_root.or(root),
+			root_given,
+			profile: Some(ActiveProfile { name, batlrc }),
+			overrides: overrides.clone()
 		}
 	}
 
-	// 3. Check for battalion folder in home directory
-	if let Some(home_dir) = dirs::home_dir() {
-		let batl_dir = home_dir.join("battalion");
+	/// The environment variables that pass the overrides
+	/// this context was detected with on to battalion, when
+	/// scripts run it again
+	#[inline]
+	#[must_use]
+	pub fn env(&self) -> HashMap<String, String> {
+		self.overrides.env()
+	}
 
-		if batl_dir.exists() {
-			return Some(batl_dir);
+	/// Get the name of the profile in use, or `None` if no
+	/// profile is selected or the selected one isn't
+	/// defined
+	#[inline]
+	#[must_use]
+	pub fn profile(&self) -> Option<&str> {
+		self.profile.as_ref().map(|active| active.name.as_str())
+	}
+
+	/// Get the battalion root path
+	#[inline]
+	#[must_use]
+	pub fn batl_root(&self) -> Option<PathBuf> {
+		self.root.clone()
+	}
+
+	/// Whether the battalion root was given with `--root` or
+	/// `BATL_ROOT`, rather than detected
+	#[inline]
+	#[must_use]
+	pub const fn root_given(&self) -> bool {
+		self.root_given
+	}
+
+	/// Get the battalion workspace root
+	#[inline]
+	#[must_use]
+	pub fn workspace_root(&self) -> Option<PathBuf> {
+		self.root.as_ref().map(|p| p.join("workspaces"))
+	}
+
+	/// Get the battalion repository root
+	#[inline]
+	#[must_use]
+	pub fn repository_root(&self) -> Option<PathBuf> {
+		self.root.as_ref().map(|p| p.join("repositories"))
+	}
+
+	/// Get the battalion template root
+	#[inline]
+	#[must_use]
+	pub fn template_root(&self) -> Option<PathBuf> {
+		self.root.as_ref().map(|p| p.join("templates"))
+	}
+
+	/// Get the directory starter files are read from
+	#[inline]
+	#[must_use]
+	pub fn starter_root(&self) -> Option<PathBuf> {
+		self.template_root().map(|p| p.join("starters"))
+	}
+
+	/// Get the battalion generator root
+	#[inline]
+	#[must_use]
+	pub fn gen_root(&self) -> Option<PathBuf> {
+		self.root.as_ref().map(|p| p.join("gen"))
+	}
+
+	/// Get the battalion archive root
+	#[inline]
+	#[must_use]
+	pub fn archive_root(&self) -> Option<PathBuf> {
+		self.gen_root().map(|p| p.join("archives"))
+	}
+
+	/// Get the root of fetched repository versions
+	#[inline]
+	#[must_use]
+	pub fn version_root(&self) -> Option<PathBuf> {
+		self.gen_root().map(|p| p.join("versions"))
+	}
+
+	/// Get the root of the batl.toml files fetched on their
+	/// own, without the rest of their repositories
+	#[inline]
+	#[must_use]
+	pub fn manifest_root(&self) -> Option<PathBuf> {
+		self.gen_root().map(|p| p.join("manifests"))
+	}
+
+	/// Get the root of the copies of files `batl upgrade`
+	/// changed, kept to roll it back
+	#[inline]
+	#[must_use]
+	pub fn upgrade_backup_root(&self) -> Option<PathBuf> {
+		self.gen_root().map(|p| p.join("upgrade-backups"))
+	}
+
+	/// Get the root of fetches that failed their checks,
+	/// kept apart from the repositories
+	#[inline]
+	#[must_use]
+	pub fn quarantine_root(&self) -> Option<PathBuf> {
+		self.gen_root().map(|p| p.join("quarantine"))
+	}
+
+	/// Get the root of the freeze manifests
+	#[inline]
+	#[must_use]
+	pub fn freeze_root(&self) -> Option<PathBuf> {
+		self.gen_root().map(|p| p.join("freezes"))
+	}
+
+	/// Get the path of the script run history
+	#[inline]
+	#[must_use]
+	pub fn history_path(&self) -> Option<PathBuf> {
+		self.gen_root().map(|p| p.join("history").join("runs.jsonl"))
+	}
+
+	/// Get the folder keeping the progress of multi-repo
+	/// runs, so they can be resumed
+	#[inline]
+	#[must_use]
+	pub fn run_state_root(&self) -> Option<PathBuf> {
+		self.gen_root().map(|p| p.join("run-state"))
+	}
+
+	/// Get the folder keeping the diagnostic bundles of
+	/// crashes
+	#[inline]
+	#[must_use]
+	pub fn crash_root(&self) -> Option<PathBuf> {
+		self.gen_root().map(|p| p.join("crash"))
+	}
+
+	/// Get the path of the audit log
+	#[inline]
+	#[must_use]
+	pub fn audit_log_path(&self) -> Option<PathBuf> {
+		self.gen_root().map(|p| p.join("history").join("audit.jsonl"))
+	}
+
+	/// Get the path the batl.toml JSON Schema is written to
+	#[inline]
+	#[must_use]
+	pub fn schema_path(&self) -> Option<PathBuf> {
+		self.gen_root().map(|p| p.join("schema").join("batl.schema.json"))
+	}
+
+	/// Get the path of the recorded link usage
+	#[inline]
+	#[must_use]
+	pub fn link_usage_path(&self) -> Option<PathBuf> {
+		self.gen_root().map(|p| p.join("history").join("links.json"))
+	}
+
+	/// Get the root of the script locks
+	#[inline]
+	#[must_use]
+	pub fn lock_root(&self) -> Option<PathBuf> {
+		self.gen_root().map(|p| p.join("locks"))
+	}
+
+	/// Get the root of the resource indexes
+	#[inline]
+	#[must_use]
+	pub fn index_root(&self) -> Option<PathBuf> {
+		self.gen_root().map(|p| p.join("index"))
+	}
+
+	/// Get the battalion cache root
+	#[inline]
+	#[must_use]
+	pub fn cache_root(&self) -> Option<PathBuf> {
+		self.gen_root().map(|p| p.join("cache"))
+	}
+
+	/// Get the battalion http cache root
+	#[inline]
+	#[must_use]
+	pub fn http_cache_root(&self) -> Option<PathBuf> {
+		self.cache_root().map(|p| p.join("http"))
+	}
+
+	/// Get the battalion batlrc path
+	#[inline]
+	#[must_use]
+	pub fn batlrc_path(&self) -> Option<PathBuf> {
+		self.root.as_ref().map(|p| p.join(".batlrc"))
+	}
+
+	/// Get the battalion RC config, with the registry of
+	/// the profile in use
+	#[inline]
+	#[must_use]
+	pub fn batlrc(&self) -> Option<BatlRcLatest> {
+		let mut batlrc = read_batlrc(&self.batlrc_path()?)?;
+
+		let api = self.profile.as_ref()
+			.and_then(|active| read_batlrc(&active.batlrc)?.profile?.remove(&active.name))
+			.and_then(|profile| profile.api);
+
+		if let Some(profile_api) = api {
+			batlrc.api = profile_api;
 		}
+
+		Some(batlrc)
 	}
 
-	None
+	/// Get where registry tokens are stored, kept apart for
+	/// each profile
+	#[inline]
+	#[must_use]
+	pub fn tokens_path(&self) -> Option<PathBuf> {
+		let auth = self.gen_root()?.join("auth");
+
+		Some(self.profile.as_ref().map_or_else(
+			|| auth.join("tokens.json"),
+			|active| auth.join("profiles").join(&active.name).join("tokens.json")
+		))
+	}
+
+	/// Get the starter files new repositories are created
+	/// with, from the batlrc
+	#[inline]
+	#[must_use]
+	pub fn starter_files(&self) -> Vec<PathBuf> {
+		self.batlrc()
+			.and_then(|rc| rc.init)
+			.and_then(|init| init.files)
+			.unwrap_or_default()
+	}
+
+	/// Get the scripts defined in the batlrc, which are
+	/// available in every repository
+	#[inline]
+	#[must_use]
+	pub fn user_scripts(&self) -> HashMap<String, Script> {
+		self.batlrc()
+			.and_then(|rc| rc.scripts)
+			.unwrap_or_default()
+			.into_iter()
+			.map(|(name, script)| (name, script.into()))
+			.collect()
+	}
+
+	/// Get the repositories of a group defined in the
+	/// batlrc, or `None` if there is no such group
+	#[inline]
+	#[must_use]
+	pub fn group(&self, name: &str) -> Option<Vec<Name>> {
+		self.batlrc()?
+			.groups?
+			.remove(name)
+	}
 }
 
-/// Get the battalion workspace root
+
+/// Get the battalion root path of the detected context
+#[inline]
+#[must_use]
+pub fn batl_root() -> Option<PathBuf> {
+	Context::detect().batl_root()
+}
+
+/// Get the battalion workspace root of the detected context
 #[inline]
 #[must_use]
 pub fn workspace_root() -> Option<PathBuf> {
-	batl_root().map(|p| p.join("workspaces"))
+	Context::detect().workspace_root()
 }
 
-/// Get the battalion repository root
+/// Get the battalion repository root of the detected
+/// context
 #[inline]
 #[must_use]
 pub fn repository_root() -> Option<PathBuf> {
-	batl_root().map(|p| p.join("repositories"))
+	Context::detect().repository_root()
 }
 
-/// Get the battalion generator root
+/// Get the battalion generator root of the detected context
 #[inline]
 #[must_use]
 pub fn gen_root() -> Option<PathBuf> {
-	batl_root().map(|p| p.join("gen"))
+	Context::detect().gen_root()
 }
 
-/// Get the battalion archive root
+/// Get the battalion archive root of the detected context
 #[inline]
 #[must_use]
 pub fn archive_root() -> Option<PathBuf> {
-	gen_root().map(|p| p.join("archives"))
+	Context::detect().archive_root()
 }
 
-/// Get the battalion batlrc path
+/// Get the battalion batlrc path of the detected context
 #[inline]
 #[must_use]
 pub fn batlrc_path() -> Option<PathBuf> {
-	batl_root().map(|p| p.join(".batlrc"))
+	Context::detect().batlrc_path()
 }
 
-/// Get the battalion RC config
+/// Get the battalion RC config of the detected context
 #[inline]
 #[must_use]
 pub fn batlrc() -> Option<BatlRcLatest> {
-	let config_str = std::fs::read_to_string(batlrc_path()?).ok()?;
+	Context::detect().batlrc()
+}
+
+/// Get the profile selected with the `BATL_PROFILE`
+/// environment variable
+fn profile_from_env() -> Option<String> {
+	env_var("BATL_PROFILE").ok().filter(|name| !name.is_empty())
+}
+
+/// The current time, in seconds since the unix epoch
+#[inline]
+#[must_use]
+pub fn unix_time() -> u64 {
+	std::time::SystemTime::now()
+		.duration_since(std::time::UNIX_EPOCH)
+		.map_or(0, |elapsed| elapsed.as_secs())
+}
+
+/// Whether a profile name is safe to use as a directory
+/// name
+fn is_profile_name(name: &str) -> bool {
+	name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Read a batlrc, returning `None` if it is missing or
+/// invalid
+fn read_batlrc(path: &Path) -> Option<BatlRcLatest> {
+	let config_str = std::fs::read_to_string(path).ok()?;
 	toml::from_str(&config_str).ok()
 }
+
+/// Find the battalion root as described in
+/// `Context::detect`, when none is given
+fn detect_root() -> Option<PathBuf> {
+	// 1. Recursively descend from current directory until .batlrc is found
+	if let Ok(mut current_dir) = std::env::current_dir() {
+		loop {
+			if current_dir.join(".batlrc").exists() {
+				return Some(current_dir);
+			}
+
+			if !current_dir.pop() {
+				break;
+			}
+		}
+	}
+
+	// 2. Check for battalion folder in home directory
+	if let Some(home_dir) = dirs::home_dir() {
+		let batl_dir = home_dir.join("battalion");
+
+		if batl_dir.exists() {
+			return Some(batl_dir);
+		}
+	}
+
+	None
+}
@@ -0,0 +1,74 @@
+use regex::Regex;
+use semver::{Version, VersionReq};
+use std::process::Command;
+
+
+/// The state of a required tool on this machine
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ToolStatus {
+	/// The tool is installed at an acceptable version
+	Satisfied(Version),
+
+	/// The tool is installed, but at the wrong version
+	Mismatch(Version),
+
+	/// The tool is installed, but its version could not
+	/// be read from `--version`
+	Unknown,
+
+	/// The tool could not be run
+	Missing
+}
+
+impl ToolStatus {
+	/// Whether the tool can be used for scripts
+	#[inline]
+	#[must_use]
+	pub const fn is_ok(&self) -> bool {
+		matches!(self, Self::Satisfied(_))
+	}
+}
+
+/// Find the installed version of a tool by running it
+/// with `--version`. Returns `None` if the tool cannot be
+/// run, and `Some(None)` if no version could be read.
+#[inline]
+#[must_use]
+pub fn detect(tool: &str) -> Option<Option<Version>> {
+	let output = Command::new(tool)
+		.arg("--version")
+		.output()
+		.ok()?;
+
+	let text = format!(
+		"{}\n{}",
+		String::from_utf8_lossy(&output.stdout),
+		String::from_utf8_lossy(&output.stderr)
+	);
+
+	Some(parse_version(&text))
+}
+
+/// Check a tool against a version requirement
+#[inline]
+#[must_use]
+pub fn check(tool: &str, requirement: &VersionReq) -> ToolStatus {
+	match detect(tool) {
+		None => ToolStatus::Missing,
+		Some(None) => ToolStatus::Unknown,
+		Some(Some(version)) if requirement.matches(&version) => ToolStatus::Satisfied(version),
+		Some(Some(version)) => ToolStatus::Mismatch(version)
+	}
+}
+
+/// Pull the first version number out of `--version`
+/// output, filling in a missing patch version
+fn parse_version(text: &str) -> Option<Version> {
+	let pattern = Regex::new(r"(\d+)\.(\d+)(?:\.(\d+))?").ok()?;
+	let captures = pattern.captures(text)?;
+
+	let part = |idx: usize| captures.get(idx)?.as_str().parse::<u64>().ok();
+
+	Some(Version::new(part(1)?, part(2)?, part(3).unwrap_or_default()))
+}
@@ -0,0 +1,230 @@
+use core::fmt::{Display, Formatter};
+use core::str::FromStr;
+use schemars::{json_schema, JsonSchema, Schema, SchemaGenerator};
+use serde::de::{self, Deserialize, Deserializer, Visitor};
+use serde::ser::{self, Serialize};
+
+
+/// A size in bytes, written in configuration files as
+/// a number with an optional `K`, `M`, `G`, or `T` suffix
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[allow(clippy::exhaustive_structs, reason = "a size is only ever its number of bytes")]
+pub struct ByteSize(pub u64);
+
+/// Suffixes for byte sizes, in increasing powers of 1024
+const BYTE_SUFFIXES: [char; 4] = ['K', 'M', 'G', 'T'];
+
+impl ByteSize {
+	/// Get the size in bytes
+	#[inline]
+	#[must_use]
+	pub const fn bytes(self) -> u64 {
+		self.0
+	}
+}
+
+impl FromStr for ByteSize {
+	type Err = String;
+
+	#[inline]
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let trimmed = s.trim().trim_end_matches(['B', 'b']);
+		let suffix_pos = BYTE_SUFFIXES.iter()
+			.position(|suffix| trimmed.ends_with(*suffix) || trimmed.ends_with(suffix.to_ascii_lowercase()));
+
+		let digits = if suffix_pos.is_some() {
+			trimmed.get(..trimmed.len().saturating_sub(1)).unwrap_or_default()
+		} else {
+			trimmed
+		};
+
+		let base = digits.trim().parse::<u64>()
+			.map_err(|_err| format!("Invalid size: {s}"))?;
+
+		let exponent = u32::try_from(suffix_pos.map_or(0, |pos| pos.saturating_add(1)))
+			.unwrap_or_default();
+
+		1024u64.checked_pow(exponent)
+			.and_then(|multiplier| base.checked_mul(multiplier))
+			.map(Self)
+			.ok_or_else(|| format!("Size too large: {s}"))
+	}
+}
+
+impl Display for ByteSize {
+	#[inline]
+	fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+		let mut value = self.0;
+		let mut suffix = None;
+
+		for candidate in BYTE_SUFFIXES {
+			if value == 0 || !value.is_multiple_of(1024) {
+				break;
+			}
+
+			value = value.div_euclid(1024);
+			suffix = Some(candidate);
+		}
+
+		write!(f, "{value}{}", suffix.map(String::from).unwrap_or_default())
+	}
+}
+
+#[allow(clippy::missing_trait_methods, reason = "the default deserialize_in_place is enough")]
+impl<'de> Deserialize<'de> for ByteSize {
+	#[inline]
+	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+	where
+		D: Deserializer<'de>
+	{
+		/// serde visitor for a byte size
+		struct ByteSizeVisitor;
+
+		impl Visitor<'_> for ByteSizeVisitor {
+			type Value = ByteSize;
+
+			fn expecting(&self, formatter: &mut core::fmt::Formatter) -> core::fmt::Result {
+				formatter.write_str("A size such as \"512M\" or \"2G\"")
+			}
+
+			fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+			where
+				E: de::Error
+			{
+				ByteSize::from_str(v)
+					.map_err(|_err| de::Error::invalid_value(de::Unexpected::Str(v), &self))
+			}
+
+			fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E>
+			where
+				E: de::Error
+			{
+				u64::try_from(v)
+					.map(ByteSize)
+					.map_err(|_err| de::Error::invalid_value(de::Unexpected::Signed(v), &self))
+			}
+		}
+
+		deserializer.deserialize_any(ByteSizeVisitor)
+	}
+}
+
+impl Serialize for ByteSize {
+	#[inline]
+	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+	where
+		S: ser::Serializer
+	{
+		serializer.serialize_str(&format!("{self}"))
+	}
+}
+
+#[allow(clippy::missing_trait_methods, reason = "the default schema settings apply")]
+impl JsonSchema for ByteSize {
+	#[inline]
+	#[allow(clippy::std_instead_of_alloc, reason = "the signature JsonSchema declares")]
+	fn schema_name() -> std::borrow::Cow<'static, str> {
+		"ByteSize".into()
+	}
+
+	#[inline]
+	fn json_schema(_generator: &mut SchemaGenerator) -> Schema {
+		json_schema!({
+			"description": "A size in bytes, with an optional K, M, G, or T suffix",
+			"anyOf": [
+				{ "type": "integer", "minimum": 0 },
+				{ "type": "string", "pattern": "^\\s*[0-9]+\\s*[KkMmGgTt]?[Bb]*\\s*$" }
+			]
+		})
+	}
+}
+
+/// A duration, written in configuration files in a
+/// human readable format such as `10m` or `1h 30m`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[allow(clippy::exhaustive_structs, reason = "a duration is only ever the one it wraps")]
+pub struct Duration(pub core::time::Duration);
+
+impl Duration {
+	/// Get the standard library duration
+	#[inline]
+	#[must_use]
+	pub const fn get(self) -> core::time::Duration {
+		self.0
+	}
+}
+
+impl FromStr for Duration {
+	type Err = String;
+
+	#[inline]
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		humantime::parse_duration(s)
+			.map(Self)
+			.map_err(|err| format!("Invalid duration {s}: {err}"))
+	}
+}
+
+impl Display for Duration {
+	#[inline]
+	fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+		write!(f, "{}", humantime::format_duration(self.0))
+	}
+}
+
+#[allow(clippy::missing_trait_methods, reason = "the default deserialize_in_place is enough")]
+impl<'de> Deserialize<'de> for Duration {
+	#[inline]
+	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+	where
+		D: Deserializer<'de>
+	{
+		/// serde visitor for a human readable duration
+		struct DurationVisitor;
+
+		impl Visitor<'_> for DurationVisitor {
+			type Value = Duration;
+
+			fn expecting(&self, formatter: &mut core::fmt::Formatter) -> core::fmt::Result {
+				formatter.write_str("A duration such as \"30s\" or \"10m\"")
+			}
+
+			fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+			where
+				E: de::Error
+			{
+				Duration::from_str(v)
+					.map_err(|_err| de::Error::invalid_value(de::Unexpected::Str(v), &self))
+			}
+		}
+
+		deserializer.deserialize_str(DurationVisitor)
+	}
+}
+
+impl Serialize for Duration {
+	#[inline]
+	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+	where
+		S: ser::Serializer
+	{
+		serializer.serialize_str(&format!("{self}"))
+	}
+}
+
+#[allow(clippy::missing_trait_methods, reason = "the default schema settings apply")]
+impl JsonSchema for Duration {
+	#[inline]
+	#[allow(clippy::std_instead_of_alloc, reason = "the signature JsonSchema declares")]
+	fn schema_name() -> std::borrow::Cow<'static, str> {
+		"Duration".into()
+	}
+
+	#[inline]
+	fn json_schema(_generator: &mut SchemaGenerator) -> Schema {
+		json_schema!({
+			"description": "A duration such as \"30s\" or \"1h 30m\"",
+			"type": "string"
+		})
+	}
+}
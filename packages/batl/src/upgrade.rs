@@ -0,0 +1,276 @@
+use core::fmt::{Display, Formatter};
+use crate::resource::batlrc::BatlRcLatest;
+use crate::resource::tomlconfig::write_toml;
+use crate::system::Context;
+use serde::{Deserialize, Serialize};
+use std::path::{Component, Path, PathBuf};
+
+
+/// Where a backup records what the upgrade did, within its
+/// folder
+const RECORD_FILE: &str = "backup.json";
+
+/// Where a backup keeps the copies of changed files, within
+/// its folder
+const FILES_DIR: &str = "files";
+
+/// The version of the backup record format, which backups
+/// from newer releases may have moved past
+const BACKUP_VERSION: u32 = 1;
+
+
+/// A change `batl upgrade` makes to a battalion root
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Change {
+	pub kind: ChangeKind,
+	pub path: PathBuf
+}
+
+/// What a change does to its path
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ChangeKind {
+	/// Create a folder the root is missing
+	CreateDir,
+
+	/// Write a default batlrc, over one that can't be read
+	/// if it is there
+	WriteBatlrc
+}
+
+/// Every change an upgrade would make, worked out before
+/// any of them are made
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Plan {
+	/// The battalion root being upgraded
+	pub root: PathBuf,
+	pub changes: Vec<Change>
+}
+
+/// What an upgrade changed, kept with copies of the files
+/// it overwrote so it can be rolled back
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Backup {
+	/// The version of the record format
+	pub version: u32,
+
+	/// When the upgrade ran, which also names the backup
+	pub stamp: String,
+
+	/// Paths the upgrade created, relative to the root
+	pub created: Vec<PathBuf>,
+
+	/// Paths the upgrade overwrote, relative to the root
+	pub modified: Vec<PathBuf>
+}
+
+impl Change {
+	#[inline]
+	#[must_use]
+	pub const fn new(kind: ChangeKind, path: PathBuf) -> Self {
+		Self { kind, path }
+	}
+
+	/// Make the change
+	///
+	/// # Errors
+	///
+	/// Propogates any IO errors while making it
+	#[inline]
+	pub fn apply(&self) -> Result<(), std::io::Error> {
+		match self.kind {
+			ChangeKind::CreateDir => std::fs::create_dir_all(&self.path),
+			ChangeKind::WriteBatlrc => write_toml(&self.path, &BatlRcLatest::default())
+		}
+	}
+}
+
+impl Display for Change {
+	#[inline]
+	fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+		match self.kind {
+			ChangeKind::CreateDir => write!(f, "create {}", self.path.display()),
+			ChangeKind::WriteBatlrc if self.path.exists() => write!(f, "rewrite {}, which can't be read", self.path.display()),
+			ChangeKind::WriteBatlrc => write!(f, "write a default {}", self.path.display())
+		}
+	}
+}
+
+impl Plan {
+	/// Work out what upgrading the root of a context takes.
+	/// Returns `None` if there is no root.
+	#[inline]
+	#[must_use]
+	pub fn detect(system: &Context) -> Option<Self> {
+		let root = system.batl_root()?;
+		let gen_root = system.gen_root()?;
+
+		let folders = [
+			root.join("repositories"),
+			root.join("workspaces"),
+			gen_root.join("archives").join("repositories"),
+			gen_root.join("archives").join("workspaces")
+		];
+
+		let mut changes = folders.into_iter()
+			.filter(|folder| !folder.exists())
+			.map(|folder| Change::new(ChangeKind::CreateDir, folder))
+			.collect::<Vec<_>>();
+
+		if system.batlrc().is_none() {
+			changes.extend(system.batlrc_path().map(|path| Change::new(ChangeKind::WriteBatlrc, path)));
+		}
+
+		Some(Self { root, changes })
+	}
+
+	/// Whether the root is already up to date
+	#[inline]
+	#[must_use]
+	pub const fn is_empty(&self) -> bool {
+		self.changes.is_empty()
+	}
+
+	/// Make every change, copying the files they overwrite
+	/// into a new backup under the given root first
+	///
+	/// # Errors
+	///
+	/// Propogates any IO errors while backing up or making
+	/// the changes. Changes made before an error stay made,
+	/// and are recorded in the backup.
+	#[inline]
+	pub fn apply(&self, backup_root: &Path) -> Result<Backup, std::io::Error> {
+		let mut backup = Backup {
+			version: BACKUP_VERSION,
+			stamp: crate::stamped::now(),
+			created: Vec::new(),
+			modified: Vec::new()
+		};
+
+		let backup_dir = backup_root.join(&backup.stamp);
+		std::fs::create_dir_all(backup_dir.join(FILES_DIR))?;
+
+		for change in &self.changes {
+			let relative = change.path.strip_prefix(&self.root).unwrap_or(&change.path).to_path_buf();
+
+			if change.path.is_file() {
+				let copy = backup_dir.join(FILES_DIR).join(&relative);
+
+				if let Some(parent) = copy.parent() {
+					std::fs::create_dir_all(parent)?;
+				}
+
+				std::fs::copy(&change.path, copy)?;
+				backup.modified.push(relative);
+			} else if !change.path.exists() {
+				backup.created.push(relative);
+			} else {
+				continue;
+			}
+
+			let applied = change.apply();
+			backup.save(backup_root)?;
+			applied?;
+		}
+
+		backup.save(backup_root)?;
+
+		Ok(backup)
+	}
+}
+
+impl Backup {
+	/// Load the backup an upgrade made at a time. Backups
+	/// that were moved, edited to reach outside the root,
+	/// or made by a newer release are refused.
+	///
+	/// # Errors
+	///
+	/// Returns `NotFound` if there is no such backup, and
+	/// `InvalidData` if its record can't be read or trusted.
+	/// Propogates any other IO errors while reading it.
+	#[inline]
+	pub fn load(backup_root: &Path, stamp: &str) -> Result<Self, std::io::Error> {
+		let invalid = |message: String| std::io::Error::new(std::io::ErrorKind::InvalidData, message);
+
+		if !crate::stamped::is_valid(stamp) {
+			return Err(std::io::Error::new(std::io::ErrorKind::NotFound, format!("no backup {stamp}")));
+		}
+
+		let backup = crate::stamped::read::<Self>(&backup_root.join(stamp).join(RECORD_FILE))?;
+
+		if backup.version > BACKUP_VERSION {
+			return Err(invalid(format!("backup {stamp} is from a newer release of batl, with record version {}", backup.version)));
+		}
+
+		if backup.stamp != stamp {
+			return Err(invalid(format!("backup {stamp} records the upgrade of {}", backup.stamp)));
+		}
+
+		let escaping = backup.created.iter()
+			.chain(&backup.modified)
+			.find(|path| path.components().any(|component| !matches!(component, Component::Normal(_))));
+
+		if let Some(path) = escaping {
+			return Err(invalid(format!("backup {stamp} records {}, outside the root", path.display())));
+		}
+
+		Ok(backup)
+	}
+
+	/// The times of every backup, oldest first
+	///
+	/// # Errors
+	///
+	/// Propogates any IO errors while listing the backups
+	#[inline]
+	pub fn list(backup_root: &Path) -> Result<Vec<String>, std::io::Error> {
+		crate::stamped::list(backup_root, RECORD_FILE)
+	}
+
+	/// Undo the upgrade: put back the files it overwrote and
+	/// remove what it created. Folders that have been used
+	/// since are left in place. Returns the folders that
+	/// were left.
+	///
+	/// # Errors
+	///
+	/// Propogates any IO errors while restoring files
+	#[inline]
+	pub fn restore(&self, root: &Path, backup_root: &Path) -> Result<Vec<PathBuf>, std::io::Error> {
+		let files = backup_root.join(&self.stamp).join(FILES_DIR);
+
+		for relative in &self.modified {
+			std::fs::copy(files.join(relative), root.join(relative))?;
+		}
+
+		let mut kept = Vec::new();
+
+		// Deepest first, so nested folders are removed before
+		// their parents
+		for relative in self.created.iter().rev() {
+			let path = root.join(relative);
+
+			let removed = if path.is_dir() {
+				std::fs::remove_dir(&path)
+			} else {
+				std::fs::remove_file(&path)
+			};
+
+			if removed.is_err() && path.exists() {
+				kept.push(path);
+			}
+		}
+
+		Ok(kept)
+	}
+
+	/// Write the record of the backup
+	fn save(&self, backup_root: &Path) -> Result<(), std::io::Error> {
+		crate::stamped::write(&backup_root.join(&self.stamp).join(RECORD_FILE), self)
+	}
+}
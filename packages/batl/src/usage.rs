@@ -0,0 +1,121 @@
+use crate::resource::{Name, Resource as _, Workspace};
+use crate::system;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+
+/// Most files whose access time is sampled per link
+const SAMPLE_SIZE: usize = 500;
+
+/// Links used through batl, by workspace and then link,
+/// in seconds since the unix epoch
+type Recorded = HashMap<String, HashMap<String, u64>>;
+
+
+/// When a link of a workspace was last used
+#[derive(Clone, Serialize)]
+#[non_exhaustive]
+pub struct Link {
+	pub name: String,
+	pub repository: Name,
+
+	/// When a script last ran through the link with batl, in
+	/// seconds since the unix epoch
+	pub recorded: Option<u64>,
+
+	/// The latest access time among a sample of the linked
+	/// files, in seconds since the unix epoch. Filesystems
+	/// mounted with `noatime` don't keep these.
+	pub accessed: Option<u64>
+}
+
+impl Link {
+	/// When the link was last used by any measure
+	#[inline]
+	#[must_use]
+	pub fn last_used(&self) -> Option<u64> {
+		self.recorded.max(self.accessed)
+	}
+}
+
+/// Record that a link of a workspace was just used
+///
+/// # Errors
+///
+/// Propogates any IO errors while writing the usage
+#[inline]
+pub fn record(system: &system::Context, workspace: &Name, link: &str) -> Result<(), std::io::Error> {
+	let Some(path) = system.link_usage_path() else {
+		return Ok(());
+	};
+
+	let mut recorded = read_recorded(system);
+
+	recorded.entry(workspace.to_string())
+		.or_default()
+		.insert(link.to_owned(), crate::system::unix_time());
+
+	if let Some(parent) = path.parent() {
+		std::fs::create_dir_all(parent)?;
+	}
+
+	let contents = serde_json::to_string_pretty(&recorded)
+		.map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+
+	std::fs::write(path, contents)
+}
+
+/// How recently each link of a workspace was used, sorted
+/// by link name
+#[inline]
+#[must_use]
+pub fn links(system: &system::Context, workspace: &Workspace) -> Vec<Link> {
+	let recorded = read_recorded(system)
+		.remove(&workspace.name().to_string())
+		.unwrap_or_default();
+
+	let mut usage = workspace.links()
+		.into_iter()
+		.map(|(name, repository)| Link {
+			accessed: std::fs::canonicalize(workspace.path().join(&name))
+				.ok()
+				.and_then(|target| sample_accessed(&target)),
+			recorded: recorded.get(&name).copied(),
+			name,
+			repository
+		})
+		.collect::<Vec<_>>();
+
+	usage.sort_by(|a, b| a.name.cmp(&b.name));
+
+	usage
+}
+
+/// The latest access time among a sample of the files in a
+/// directory, leaving out ignored and hidden files
+#[inline]
+#[must_use]
+pub fn sample_accessed(path: &Path) -> Option<u64> {
+	let mut walk_builder = ignore::WalkBuilder::new(path);
+	walk_builder.add_custom_ignore_filename("batl.ignore");
+
+	walk_builder.build()
+		.filter_map(Result::ok)
+		.filter(|entry| !entry.path().is_dir())
+		.take(SAMPLE_SIZE)
+		.filter_map(|entry| entry.metadata().ok()?.accessed().ok())
+		.filter_map(|accessed| accessed.duration_since(UNIX_EPOCH).ok())
+		.map(|elapsed| elapsed.as_secs())
+		.max()
+}
+
+/// Read the recorded link usage, treating a missing or
+/// unreadable file as no usage
+fn read_recorded(system: &system::Context) -> Recorded {
+	system.link_usage_path()
+		.and_then(|path| std::fs::read_to_string(path).ok())
+		.and_then(|contents| serde_json::from_str(&contents).ok())
+		.unwrap_or_default()
+}
@@ -1,17 +1,83 @@
 use batl::error as batlerror;
+use batl::executor::{self, Executor, Invocation, Lock, Ssh};
+use batl::auditlog::{self, Entry as AuditEntry};
+use batl::context::BatlContext;
+use batl::crash::{Kind as CrashKind, Report as CrashReport};
+use batl::history::{self, Run};
+use batl::index::{Index, Kind};
+use batl::integrity::{Policy, Verified};
+use batl::notify::{Event as NotifyEvent, Notifier};
+use batl::registry::{Capabilities, Compatibility, Registry, API_VERSION, MIN_API_VERSION, RANGES_FEATURE};
+use batl::resolve;
+use batl::resource::{archive, ownership, Archive, Name, Ownership, Repository, Resource, Script, Transaction, VersionedName, Workspace};
+use batl::runstate::RunState;
+use batl::toolchain::{self, ToolStatus};
+use crate::output::{error, error_stderr, info, name as output_name, success, warning, warning_stderr, Progress};
+use clap::ValueEnum;
+use dialoguer::FuzzySelect;
 use lazy_static::lazy_static;
 use regex::Regex;
+use semver::Version;
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::process::ExitStatus;
+use std::io::{Read, Seek, SeekFrom};
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 use thiserror::Error;
 
-#[cfg(target_os = "windows")]
-use crate::output::error;
 
+pub use batl::resource::BATL_NAME_REGEX;
 
 lazy_static! {
-	pub static ref BATL_NAME_REGEX: Regex = Regex::new(r"^[a-z][a-z0-9\-_]*(/[a-z][a-z0-9\-_]*)+$").unwrap();
 	pub static ref BATL_LINK_REGEX: Regex = Regex::new(r"^[a-zA-Z][a-zA-Z0-9_\-]*$").unwrap();
 }
 
+/// How often the log of a script with prefixed output is
+/// checked for new lines
+const LOG_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// Set by `batl resume` to the run a batl it starts
+/// picks up
+pub const RESUME_VAR: &str = "BATL_RESUME";
+
+/// The run being resumed, taken from the environment so
+/// scripts that call batl again don't resume it too
+static RESUMING: OnceLock<String> = OnceLock::new();
+
+/// Pick up the run `batl resume` asked to continue, if any,
+/// taking it out of the environment
+pub fn take_resume() {
+	if let Ok(id) = std::env::var(RESUME_VAR) {
+		std::env::remove_var(RESUME_VAR);
+		let _ = RESUMING.set(id);
+	}
+}
+
+/// Exit codes shown in `batl --help`, so scripts can tell
+/// failures apart without parsing messages
+pub const EXIT_CODES: &str = "\
+Exit codes:
+  0  Success
+  1  Other error
+  2  Usage error
+  3  Battalion is not setup
+  4  Resource missing
+  5  Script failed
+  6  Network failure
+  7  Not authorized
+  8  Version already exists
+  9  Registry error
+  10 Vulnerable dependencies found
+  11 Secrets found in a package
+  12 Policy violations found
+
+Commands run across several repositories go on past
+failures and exit with the code the failures share, or 1
+when they differ. `run` and `resume` exit with the code of
+the command they stand in for.";
+
 #[derive(Error, Debug)]
 pub enum UtilityError {
 	#[error("IO Error: {0}")]
@@ -22,12 +88,22 @@ pub enum UtilityError {
 	ResourceAlreadyExists(String),
 	#[error("Invalid config")]
 	InvalidConfig,
+	#[error("Invalid config: {0}")]
+	ConfigError(String),
+	#[error("Config written by a newer version of battalion ({0}), upgrade batl to use it")]
+	NewerConfig(String),
 	#[error("Link not found")]
 	LinkNotFound,
 	#[error("Invalid name: {0}")]
 	InvalidName(String),
+	#[error("Invalid path: {0}")]
+	InvalidPath(String),
+	#[error("Invalid pattern: {0}")]
+	InvalidPattern(String),
 	#[error("Already setup")]
 	AlreadySetup,
+	#[error("Battalion is not setup, run `batl setup` first")]
+	NotSetup,
 	#[error("Script not found: {0}")]
 	ScriptNotFound(String),
 	#[error("Script error: {0}")]
@@ -35,17 +111,85 @@ pub enum UtilityError {
 	#[error("Resource cannot be collected: {0}")]
 	ResourceNotCollected(String),
 	#[error("Network Error: {0}")]
-	NetworkError(#[from] ureq::Error),
+	NetworkError(String),
+	#[error("Not authorized: {0}")]
+	NotAuthorized(String),
+	#[error("Version already exists: {0}")]
+	VersionExists(String),
+	#[error("Registry error: {0}")]
+	RegistryError(String),
+	#[error("Tools not available: {0}")]
+	ToolsUnavailable(String),
+	#[error("Vulnerable dependencies: {0}")]
+	AdvisoriesFound(String),
+	#[error("{0}")]
+	PreflightFailed(String),
+	#[error("Possible secrets found: {0}")]
+	SecretsFound(String),
+	#[error("Policy violations: {0}")]
+	PolicyViolations(String),
+	#[error("{0}")]
+	Batch(batlerror::BatchError),
+	/// An error already shown in the output, such as in a
+	/// JSON report, which only sets the exit code
+	#[error("{0}")]
+	Reported(Box<UtilityError>),
+	/// A command batl stood in for failed, whose exit code
+	/// is passed through as is
+	#[error("Exited with code {0}")]
+	Exited(i32),
 	#[error("Unknown")]
 	Unknown
 }
 
+impl UtilityError {
+	/// The process exit code for this error, as listed
+	/// in [`EXIT_CODES`]
+	pub fn exit_code(&self) -> i32 {
+		match self {
+			UtilityError::InvalidName(_) => 2,
+			UtilityError::InvalidPattern(_) => 2,
+			UtilityError::NotSetup => 3,
+			UtilityError::ResourceDoesNotExist(_) => 4,
+			UtilityError::LinkNotFound => 4,
+			UtilityError::ScriptNotFound(_) => 4,
+			UtilityError::ScriptError(_) => 5,
+			UtilityError::NetworkError(_) => 6,
+			UtilityError::NotAuthorized(_) => 7,
+			UtilityError::VersionExists(_) => 8,
+			UtilityError::RegistryError(_) => 9,
+			UtilityError::AdvisoriesFound(_) => 10,
+			UtilityError::SecretsFound(_) => 11,
+			UtilityError::PolicyViolations(_) => 12,
+			UtilityError::Batch(batch) => batch.exit_code(),
+			UtilityError::Reported(err) => err.exit_code(),
+			UtilityError::Exited(code) => *code,
+			_ => 1
+		}
+	}
+
+	/// Whether the error is one batl can't explain, which a
+	/// crash bundle is written for. Missing files, lacking
+	/// permissions and files in the way are left out, since
+	/// they are the user's to fix rather than bugs.
+	pub fn is_fatal(&self) -> bool {
+		match self {
+			UtilityError::IoError(err) => !matches!(
+				err.kind(),
+				std::io::ErrorKind::NotFound | std::io::ErrorKind::PermissionDenied | std::io::ErrorKind::AlreadyExists
+			),
+			UtilityError::Unknown => true,
+			_ => false
+		}
+	}
+}
+
 impl From<batlerror::ReadConfigError> for UtilityError {
 	fn from(value: batlerror::ReadConfigError) -> Self {
 		match value {
 			batlerror::ReadConfigError::IoError(e) => e.into(),
-			batlerror::ReadConfigError::TomlError(_) => UtilityError::InvalidConfig,
-			_ => UtilityError::Unknown
+			batlerror::ReadConfigError::NewerVersion(version) => UtilityError::NewerConfig(version),
+			other => UtilityError::ConfigError(other.to_string())
 		}
 	}
 }
@@ -55,7 +199,11 @@ impl From<batlerror::GeneralResourceError> for UtilityError {
 		match value {
 			batlerror::GeneralResourceError::DoesNotExist => UtilityError::ResourceDoesNotExist("<>".to_string()),
 			batlerror::GeneralResourceError::Invalid => UtilityError::InvalidConfig,
+			batlerror::GeneralResourceError::InvalidConfig(e) => UtilityError::ConfigError(e),
+			batlerror::GeneralResourceError::NewerVersion(version) => UtilityError::NewerConfig(version),
 			batlerror::GeneralResourceError::IoError(e) => e.into(),
+			batlerror::GeneralResourceError::InvalidPath(e) => UtilityError::InvalidPath(e.to_string()),
+			batlerror::GeneralResourceError::NameMismatch(e) => UtilityError::ConfigError(format!("Declared name {}, run `batl fix-name` to reconcile them", e)),
 			_ => UtilityError::Unknown
 		}
 	}
@@ -66,7 +214,10 @@ impl From<batlerror::CreateResourceError> for UtilityError {
 		match value {
 			batlerror::CreateResourceError::AlreadyExists => UtilityError::ResourceAlreadyExists("<>".to_string()),
 			batlerror::CreateResourceError::IoError(e) => e.into(),
-			batlerror::CreateResourceError::NotSetup => UtilityError::ResourceAlreadyExists("Battalion root".to_string()),
+			batlerror::CreateResourceError::NotSetup => UtilityError::NotSetup,
+			batlerror::CreateResourceError::InvalidName(name) => UtilityError::InvalidName(name),
+			batlerror::CreateResourceError::Collision(path) => UtilityError::ResourceAlreadyExists(format!("Case-insensitive match {}", path)),
+			batlerror::CreateResourceError::InvalidStarter(path) => UtilityError::ConfigError(format!("Starter file {}", path)),
 			_ => UtilityError::Unknown
 		}
 	}
@@ -88,6 +239,1311 @@ impl From<batlerror::CreateDependentResourceError> for UtilityError {
 			batlerror::CreateDependentResourceError::Creation(e) => e.into(),
 			batlerror::CreateDependentResourceError::IoError(e) => e.into(),
 			batlerror::CreateDependentResourceError::Dependent(e) => e.into(),
+			batlerror::CreateDependentResourceError::Preflight(e) => e.into(),
+			batlerror::CreateDependentResourceError::Resolve(e) => e.into(),
+			_ => UtilityError::Unknown
+		}
+	}
+}
+
+impl From<batlerror::PreflightError> for UtilityError {
+	fn from(value: batlerror::PreflightError) -> Self {
+		UtilityError::PreflightFailed(value.to_string())
+	}
+}
+
+impl From<batlerror::IntegrityError> for UtilityError {
+	fn from(value: batlerror::IntegrityError) -> Self {
+		match value {
+			batlerror::IntegrityError::IoError(e) => e.into(),
+			batlerror::IntegrityError::InvalidKey(path) => UtilityError::ConfigError(format!("Signing key {} is not a hex encoded ed25519 key", path)),
+			_ => UtilityError::RegistryError(value.to_string())
+		}
+	}
+}
+
+impl From<batlerror::ApplyTemplateError> for UtilityError {
+	fn from(value: batlerror::ApplyTemplateError) -> Self {
+		match value {
+			batlerror::ApplyTemplateError::IoError(e) => e.into(),
+			batlerror::ApplyTemplateError::Creation(e) => e.into(),
+			batlerror::ApplyTemplateError::Resource(e) => e.into(),
+			batlerror::ApplyTemplateError::MissingRepository(name) => UtilityError::ResourceDoesNotExist(format!("Repository {}", name)),
+			batlerror::ApplyTemplateError::LinkConflict(link) => UtilityError::ResourceAlreadyExists(format!("Link {}", link)),
+			_ => UtilityError::Unknown
+		}
+	}
+}
+
+impl From<batlerror::ExecuteError> for UtilityError {
+	fn from(value: batlerror::ExecuteError) -> Self {
+		match value {
+			batlerror::ExecuteError::IoError(e) => e.into(),
+			batlerror::ExecuteError::EngineUnavailable(engine) => UtilityError::ResourceDoesNotExist(format!("Container engine {}", engine)),
+			batlerror::ExecuteError::InterpreterUnavailable(program) => UtilityError::ResourceDoesNotExist(format!("Script interpreter {}", program)),
+			batlerror::ExecuteError::SyncFailed(path) => UtilityError::ScriptError(format!("Could not sync {} to remote host", path)),
+			batlerror::ExecuteError::TimedOut(timeout) => UtilityError::ScriptError(format!("Timed out after {}", timeout)),
+			batlerror::ExecuteError::InvalidLock(lock) => UtilityError::ConfigError(format!("Invalid lock name {}", lock)),
+			batlerror::ExecuteError::LimitUnsupported(limits) => UtilityError::ConfigError(format!("Can't enforce {}", limits)),
+			_ => UtilityError::Unknown
+		}
+	}
+}
+
+pub fn select_executor(script: &Script, on: Option<String>, context: &BatlContext) -> Result<Box<dyn Executor>, UtilityError> {
+	let Some(host_name) = on else {
+		return Ok(executor::for_script(context.system(), script));
+	};
+
+	let host = context.system().batlrc()
+		.and_then(|batlrc| batlrc.hosts)
+		.and_then(|mut hosts| hosts.remove(&host_name))
+		.ok_or(UtilityError::ResourceDoesNotExist(format!("Host {}", host_name)))?;
+
+	Ok(Box::new(Ssh::new(host)))
+}
+
+/// Warn when the registry reported a newer API version
+/// than this build speaks, since it offers features that
+/// can't be used
+pub fn api_notice(registry: &Registry) {
+	if let Some(version) = registry.newer_api() {
+		warn_newer_api(version);
+	}
+}
+
+/// Discover what the registry supports before relying on
+/// it, failing clearly when the APIs are incompatible
+pub fn negotiate(registry: &Registry) -> Result<Capabilities, UtilityError> {
+	let capabilities = registry.capabilities()?;
+
+	match capabilities.compatibility() {
+		Compatibility::ClientTooOld(min) => Err(UtilityError::RegistryError(format!(
+			"The registry requires API version {} or newer, this build of batl speaks {}. Upgrade batl to use it", min, API_VERSION
+		))),
+		Compatibility::RegistryTooOld(version) => Err(UtilityError::RegistryError(format!(
+			"The registry speaks API version {}, older than the oldest this build of batl supports ({})", version, MIN_API_VERSION
+		))),
+		Compatibility::Newer(version) => {
+			warn_newer_api(version);
+
+			Ok(capabilities)
+		},
+		_ => Ok(capabilities)
+	}
+}
+
+fn warn_newer_api(version: u64) {
+	warning(&format!("The registry speaks API version {}, newer than {}. Upgrade batl to use its new features", version, API_VERSION));
+}
+
+/// How much of a script run is shown
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ExecOutput {
+	/// Everything the script prints, as it prints it
+	Full,
+
+	/// Nothing, unless the script fails
+	Quiet,
+
+	/// One line with the status and duration
+	Summary,
+
+	/// Every line the script prints, prefixed with the
+	/// repository, for scripts running side by side
+	Prefixed
+}
+
+impl ExecOutput {
+	pub fn from_flags(quiet: bool, summary: bool) -> Self {
+		if summary {
+			ExecOutput::Summary
+		} else if quiet {
+			ExecOutput::Quiet
+		} else {
+			ExecOutput::Full
+		}
+	}
+}
+
+/// Collects the outcome of each item of a command run
+/// across several repositories, so one failure doesn't stop
+/// the rest, and reports the failures together at the end
+pub struct Batch {
+	errors: batlerror::BatchError,
+	fail_fast: bool,
+	json: bool,
+	/// The progress kept for `batl resume`, and the folder
+	/// it is kept in
+	run: Option<(RunState, PathBuf)>
+}
+
+impl Batch {
+	pub fn new(fail_fast: bool, json: bool) -> Self {
+		Batch { errors: batlerror::BatchError::default(), fail_fast, json, run: None }
+	}
+
+	/// Keep the progress of the batch under `gen/run-state`
+	/// after every item, so an interrupted run can be picked
+	/// up with `batl resume`. Under `batl resume`, the run
+	/// being resumed is picked up instead.
+	pub fn resumable(&mut self, context: &BatlContext) {
+		let Some(root) = context.system().run_state_root() else {
+			return;
+		};
+
+		let state = match RESUMING.get() {
+			Some(id) => match RunState::load(&root, id) {
+				Ok(state) => state,
+				Err(err) => {
+					self.warning(&format!("Couldn't pick up run {}, starting over: {}", id, err));
+					return;
+				}
+			},
+			None => {
+				// Relative paths in the arguments are resolved
+				// again from the same folder and root on resume
+				let cwd = std::env::current_dir().and_then(std::path::absolute);
+				let batl_root = context.system().batl_root().map(std::path::absolute);
+
+				match (cwd, batl_root) {
+					(Ok(cwd), Some(Ok(batl_root))) => RunState::new(std::env::args().skip(1).collect(), cwd, batl_root),
+					_ => {
+						self.warning("Couldn't keep the progress of this run: the current folder or root can't be found");
+						return;
+					}
+				}
+			}
+		};
+
+		if let Err(err) = state.save(&root) {
+			self.warning(&format!("Couldn't keep the progress of this run: {}", err));
+			return;
+		}
+
+		self.run = Some((state, root));
+	}
+
+	/// Warn on stderr when stdout is kept for the json
+	/// report, and as usual otherwise
+	fn warning(&self, message: &str) {
+		if self.json {
+			warning_stderr(message);
+		} else {
+			warning(message);
+		}
+	}
+
+	/// Whether an item went through before the run was
+	/// resumed, in which case it counts as done and is
+	/// skipped
+	pub fn already_done(&mut self, item: &str) -> bool {
+		let done = self.run.as_ref().is_some_and(|(state, _)| state.is_completed(item));
+
+		if done {
+			self.errors.succeeded();
+
+			if !self.json {
+				info(&format!("{} went through before, skipping it", item));
+			}
+		}
+
+		done
+	}
+
+	/// Record how an item went, returning whether to go on
+	/// to the next one
+	pub fn record(&mut self, item: &str, result: Result<(), UtilityError>) -> bool {
+		match result {
+			Ok(()) => {
+				self.errors.succeeded();
+
+				let kept = self.run.as_mut().map_or(Ok(()), |(state, root)| state.complete(root, item));
+
+				if let Err(err) = kept {
+					self.warning(&format!("Couldn't keep the progress of this run: {}", err));
+				}
+
+				true
+			},
+			Err(err) => {
+				if self.json {
+					error_stderr(&format!("{}: {}", item, err));
+				} else {
+					error(&format!("{}: {}", item, err));
+				}
+
+				self.errors.failed(item.to_string(), err.to_string(), err.exit_code());
+
+				!self.fail_fast
+			}
+		}
+	}
+
+	/// How many items were recorded
+	pub fn total(&self) -> usize {
+		self.errors.total
+	}
+
+	/// The items that failed
+	pub fn failed(&self) -> Vec<String> {
+		self.errors.failures.iter().map(|failure| failure.item.clone()).collect()
+	}
+
+	/// Print the report, as json if asked for, and fail if
+	/// any item did
+	pub fn finish(self) -> Result<(), UtilityError> {
+		// Nothing is left to resume once every item went
+		// through
+		let resume = match self.run {
+			Some((state, root)) if self.errors.is_empty() => {
+				if let Err(err) = state.remove(&root) {
+					if self.json {
+						warning_stderr(&format!("Couldn't remove the progress of this run: {}", err));
+					} else {
+						warning(&format!("Couldn't remove the progress of this run: {}", err));
+					}
+				}
+
+				None
+			},
+			Some((state, _)) => Some(state.id),
+			None => None
+		};
+
+		if self.json {
+			let mut report = serde_json::json!({
+				"total": self.errors.total,
+				"failed": self.errors.failures.len(),
+				"failures": self.errors.failures
+			});
+
+			if let Some(id) = &resume {
+				report["resume"] = serde_json::json!(id);
+			}
+
+			println!("{}", serde_json::to_string_pretty(&report).map_err(|e| UtilityError::ConfigError(e.to_string()))?);
+		} else if !self.errors.is_empty() {
+			println!();
+			error("Failures:");
+
+			for failure in &self.errors.failures {
+				println!("  {}  {}", output_name(&failure.item), failure.message);
+			}
+
+			if let Some(id) = &resume {
+				info(&format!("Run `batl resume {}` to retry the failures and whatever didn't run", id));
+			}
+		}
+
+		if self.errors.is_empty() {
+			return Ok(());
+		}
+
+		// The json report already names the failures
+		if self.json {
+			return Err(UtilityError::Reported(Box::new(UtilityError::Batch(self.errors))));
+		}
+
+		Err(UtilityError::Batch(self.errors))
+	}
+}
+
+/// Notify about a finished command as `[notify]` in the
+/// batlrc asks, if it took long enough. Notifications that
+/// can't be sent are only warned about.
+pub fn notify_finished(context: &BatlContext, title: String, err: Option<&UtilityError>, elapsed: std::time::Duration) {
+	let Some(notifier) = Notifier::from_system(context.system()) else {
+		return;
+	};
+
+	if !notifier.should_notify(elapsed) {
+		return;
+	}
+
+	let rounded = std::time::Duration::from_secs(elapsed.as_secs());
+
+	let message = match err {
+		Some(err) => format!("Failed after {}: {}", humantime::format_duration(rounded), err),
+		None => format!("Finished in {}", humantime::format_duration(rounded))
+	};
+
+	let event = NotifyEvent::new(title, message, err.is_none(), elapsed);
+
+	for notify_err in notifier.notify(&event) {
+		warning(&format!("Couldn't send a notification: {}", notify_err));
+	}
+}
+
+/// Write a diagnostic bundle about a crash to
+/// `gen/crash/<time>/`, and tell the user where it is
+pub fn report_crash(system: &batl::system::Context, kind: CrashKind, message: String, location: Option<String>) {
+	let Some(crash_root) = system.crash_root() else {
+		return;
+	};
+
+	let mut report = CrashReport::new(system, kind, message, location);
+
+	match report.write(&crash_root) {
+		Ok(dir) => info(&format!("Wrote a diagnostic bundle to {}, attach it when reporting the bug", dir.display())),
+		Err(err) => warning(&format!("Couldn't write a diagnostic bundle: {}", err))
+	}
+}
+
+/// The exit code to pass on for a command that finished.
+/// Commands killed by a signal give 128 plus the signal,
+/// as shells report them.
+pub fn status_code(status: ExitStatus) -> i32 {
+	#[cfg(unix)]
+	if let Some(signal) = std::os::unix::process::ExitStatusExt::signal(&status) {
+		return 128 + signal;
+	}
+
+	status.code().unwrap_or(1)
+}
+
+/// How a failed command finished, as its exit code or the
+/// signal that killed it
+pub fn failure_reason(status: ExitStatus) -> String {
+	#[cfg(unix)]
+	if let Some(signal) = std::os::unix::process::ExitStatusExt::signal(&status) {
+		return format!("signal {}", signal);
+	}
+
+	match status.code() {
+		Some(code) => format!("exit code {}", code),
+		None => "no exit code".to_string()
+	}
+}
+
+/// Warn about a repository whose `batl.toml` declares a
+/// different name than the one it is stored under
+pub fn warn_name_mismatch(repository: &Repository, context: &BatlContext) {
+	let Some(declared) = repository.declared_name(context) else {
+		return;
+	};
+
+	warning(&format!(
+		"{} declares the name {}, run `batl fix-name -n {}` to reconcile them",
+		repository.name(), declared, repository.name()
+	));
+}
+
+/// Run a script, showing as much of it as asked for. When
+/// the output is not shown in full, it is kept in
+/// `gen/logs/<script>.log` of the repository. Failed runs
+/// are retried as the script asks, and the outcome is
+/// recorded in the run history.
+pub fn run_script(repository: &Repository, script_name: &str, script: &Script, mut invocation: Invocation, on: Option<String>, output: ExecOutput, context: &BatlContext) -> Result<ExitStatus, UtilityError> {
+	warn_name_mismatch(repository, context);
+
+	let executor = select_executor(script, on, context)?;
+
+	// Held until the script is done
+	let _lock = match &script.lock {
+		Some(name) => Some(acquire_lock(name, context)?),
+		None => None
+	};
+
+	let log = repository.ensure_gen()?.join("logs").join(format!("{}.log", script_name));
+
+	if output != ExecOutput::Full {
+		invocation.log = Some(log.clone());
+	}
+
+	let started = SystemTime::now();
+	let start = Instant::now();
+	let mut attempts = 0;
+
+	let mut attempt = || loop {
+		attempts += 1;
+
+		let result = executor.execute(&invocation);
+		let passed = result.as_ref().is_ok_and(ExitStatus::success);
+		let retryable = matches!(result, Ok(_) | Err(batlerror::ExecuteError::TimedOut(_)));
+
+		if passed || !retryable || attempts > script.retry.retries {
+			break result;
+		}
+
+		warning(&format!("{} failed, retrying ({}/{})", script_name, attempts, script.retry.retries));
+
+		if let Some(delay) = script.retry.delay {
+			std::thread::sleep(delay.get());
+		}
+	};
+
+	let result = if output == ExecOutput::Prefixed {
+		// The log of an earlier run would be shown again
+		drop(std::fs::remove_file(&log));
+
+		let prefix = format!("[{}]", output_name(&repository.name().to_string()));
+		let done = AtomicBool::new(false);
+
+		std::thread::scope(|scope| {
+			let follower = scope.spawn(|| follow_log(&log, &prefix, &done));
+			let result = attempt();
+
+			done.store(true, Ordering::Release);
+			drop(follower.join());
+
+			result
+		})
+	} else {
+		attempt()
+	};
+
+	let passed = result.as_ref().is_ok_and(ExitStatus::success);
+
+	drop(history::record(context.system(), &Run::new(
+		repository.name().clone(),
+		script_name.to_string(),
+		passed,
+		result.as_ref().ok().and_then(ExitStatus::code),
+		attempts,
+		started.duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs()),
+		start.elapsed().as_millis().try_into().unwrap_or(u64::MAX)
+	)));
+
+	drop(auditlog::record(context.system(), &AuditEntry::new(
+		repository.name().clone(),
+		repository.path().to_path_buf(),
+		script_name.to_string(),
+		repository.written_command(script_name).unwrap_or(&script.command).to_owned(),
+		result.as_ref().ok().and_then(ExitStatus::code),
+		passed
+	)));
+
+	let elapsed = format!("{:.2}s", start.elapsed().as_secs_f64());
+	let tries = if attempts > 1 { format!(" after {} attempts", attempts) } else { String::new() };
+
+	if output == ExecOutput::Summary || output == ExecOutput::Prefixed {
+		let line = format!("{} {} {}{}", repository.name(), script_name, elapsed, tries);
+
+		if passed {
+			success(&line);
+		} else {
+			error(&format!("{} (output in {})", line, log.display()));
+		}
+	} else if output == ExecOutput::Quiet && !passed {
+		if let Ok(mut file) = std::fs::File::open(&log) {
+			drop(std::io::copy(&mut file, &mut std::io::stdout()));
+		}
+	}
+
+	Ok(result?)
+}
+
+/// Run a script with the scripts it depends on before it
+/// and its post hooks after it, stopping at the first one
+/// that fails. Each script in the chain runs once. Scripts
+/// run for a link get its name and environment.
+pub fn run_chain(repository: &Repository, script_name: &str, link: Option<(&str, HashMap<String, String>)>, on: Option<String>, with: Vec<VersionedName>, output: ExecOutput, context: &BatlContext) -> Result<ExitStatus, UtilityError> {
+	let chain = repository.script_chain(context, script_name)?;
+	let mut ran = None;
+
+	for (name, script) in &chain {
+		if chain.len() > 1 && output == ExecOutput::Full {
+			info(&format!("Running {}\n", name));
+		}
+
+		check_tools(repository, script, on.as_ref())?;
+
+		let mut invocation = Invocation::for_repository(context, repository, script);
+		apply_overrides(&mut invocation, repository, with.clone(), context)?;
+
+		if let Some((link_name, env)) = &link {
+			invocation.for_link(link_name, env.clone());
+		}
+
+		let status = run_script(repository, name, script, invocation, on.clone(), output, context)?;
+
+		if name == script_name {
+			if !status.success() {
+				return Ok(status);
+			}
+
+			ran = Some(status);
+		} else if !status.success() {
+			return Err(UtilityError::ScriptError(format!("{} failed with {}", name, failure_reason(status))));
+		}
+	}
+
+	ran.ok_or(UtilityError::ScriptNotFound(script_name.to_string()))
+}
+
+/// Print the lines written to a script log as they come,
+/// each after a prefix, until the script is done
+fn follow_log(log: &Path, prefix: &str, done: &AtomicBool) {
+	let mut offset = 0;
+	let mut partial = String::new();
+
+	loop {
+		let finished = done.load(Ordering::Acquire);
+
+		if let Ok(mut file) = std::fs::File::open(log) {
+			// A retry starts the log over
+			if file.metadata().map_or(0, |metadata| metadata.len()) < offset {
+				offset = 0;
+				partial.clear();
+			}
+
+			let mut chunk = Vec::new();
+
+			if file.seek(SeekFrom::Start(offset)).is_ok() && file.read_to_end(&mut chunk).is_ok() {
+				offset += chunk.len() as u64;
+				partial.push_str(&String::from_utf8_lossy(&chunk));
+			}
+
+			while let Some(end) = partial.find('\n') {
+				let line = partial.drain(..=end).collect::<String>();
+				println!("{} {}", prefix, line.trim_end());
+			}
+		}
+
+		if finished {
+			if !partial.is_empty() {
+				println!("{} {}", prefix, partial.trim_end());
+			}
+
+			return;
+		}
+
+		std::thread::sleep(LOG_POLL_INTERVAL);
+	}
+}
+
+/// Take a script lock, saying so when another script holds
+/// it and has to be waited for
+fn acquire_lock(name: &str, context: &BatlContext) -> Result<Lock, UtilityError> {
+	if let Some(lock) = Lock::try_acquire(context.system(), name)? {
+		return Ok(lock);
+	}
+
+	info(&format!("Waiting for lock {}", name));
+
+	Ok(Lock::acquire(context.system(), name)?)
+}
+
+/// Make sure the tools a repository declares are present
+/// before running a script locally. Scripts in containers
+/// or on other hosts bring their own tools.
+pub fn check_tools(repository: &Repository, script: &Script, on: Option<&String>) -> Result<(), UtilityError> {
+	if script.container.is_some() || on.is_some() {
+		return Ok(());
+	}
+
+	let mut problems = repository.config().tools.iter()
+		.filter_map(|(tool, requirement)| match toolchain::check(tool, requirement) {
+			ToolStatus::Satisfied(_) => None,
+			ToolStatus::Mismatch(version) => Some(format!("{} {} (requires {})", tool, version, requirement)),
+			ToolStatus::Unknown => Some(format!("{} (unknown version, requires {})", tool, requirement)),
+			_ => Some(format!("{} (missing, requires {})", tool, requirement))
+		})
+		.collect::<Vec<_>>();
+
+	if problems.is_empty() {
+		return Ok(());
+	}
+
+	problems.sort();
+
+	Err(UtilityError::ToolsUnavailable(problems.join(", ")))
+}
+
+/// Names of repositories that can be picked interactively,
+/// local ones first. Registry packages are included when
+/// asked for and the registry can be reached.
+pub fn repository_candidates(include_registry: bool, context: &BatlContext) -> Result<Vec<String>, UtilityError> {
+	let mut candidates = Index::open(context.system(), Kind::Repository)?
+		.names()
+		.iter()
+		.map(|name| name.to_string())
+		.collect::<Vec<_>>();
+
+	candidates.extend(Repository::externals(context).keys().map(|name| name.to_string()));
+
+	if include_registry {
+		match context.registry().search("") {
+			Ok(results) => candidates.extend(
+				results.into_iter()
+					.map(|result| result.name.to_string())
+					.filter(|name| !candidates.contains(name))
+					.collect::<Vec<_>>()
+			),
+			Err(err) => warning(&format!("Registry unavailable, only showing local repositories ({})", err))
+		}
+	}
+
+	Ok(candidates)
+}
+
+/// Open a url in the default browser. This is best
+/// effort, so the url should be printed as well.
+pub fn open_url(url: &str) {
+	#[cfg(target_os = "macos")]
+	let opener = std::process::Command::new("open").arg(url).spawn();
+
+	#[cfg(target_os = "windows")]
+	let opener = std::process::Command::new("cmd").args(["/C", "start", "", url]).spawn();
+
+	#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+	let opener = std::process::Command::new("xdg-open").arg(url).spawn();
+
+	drop(opener);
+}
+
+/// Which repositories a filter lets through, written as a
+/// name prefix or as an `@group` from the batlrc
+pub enum NameFilter {
+	Prefix(String),
+	Group(Vec<String>)
+}
+
+impl NameFilter {
+	pub fn parse(filter: &str, context: &BatlContext) -> Result<Self, UtilityError> {
+		if filter.starts_with('@') {
+			return Ok(Self::Group(expand_names(filter, context)?));
+		}
+
+		Ok(Self::Prefix(filter.to_string()))
+	}
+
+	pub fn matches(&self, name: &str) -> bool {
+		match self {
+			Self::Prefix(prefix) => name.starts_with(prefix.as_str()),
+			Self::Group(names) => names.iter().any(|member| member == name)
+		}
+	}
+}
+
+/// Expand a repository name, or an `@group` from the
+/// batlrc, into the names it stands for
+pub fn expand_names(name: &str, context: &BatlContext) -> Result<Vec<String>, UtilityError> {
+	let Some(group) = name.strip_prefix('@') else {
+		return Ok(vec![name.to_string()]);
+	};
+
+	let members = context.system().group(group)
+		.ok_or(UtilityError::ResourceDoesNotExist(format!("Group {}", group)))?;
+
+	Ok(members.iter().map(|member| member.to_string()).collect())
+}
+
+/// Local repositories whose names pass the filter, along
+/// with their paths, sorted by name
+pub fn repository_paths(filter: Option<&str>, context: &BatlContext) -> Result<Vec<(String, PathBuf)>, UtilityError> {
+	let filter = filter.map(|filter| NameFilter::parse(filter, context)).transpose()?;
+
+	let index = Index::open(context.system(), Kind::Repository)?;
+
+	let mut repositories = index.names()
+		.iter()
+		.filter_map(|name| index.get(name).map(|path| (name.to_string(), path.to_path_buf())))
+		.collect::<Vec<_>>();
+
+	repositories.extend(Repository::externals(context).into_iter().map(|(name, path)| (name.to_string(), path)));
+
+	repositories.retain(|(name, _)| filter.as_ref().is_none_or(|filter| filter.matches(name)));
+	repositories.sort();
+
+	Ok(repositories)
+}
+
+/// Fuzzy pick a single repository, returning `None` if the
+/// prompt is cancelled
+pub fn pick_repository(prompt: &str, candidates: &[String]) -> Result<Option<String>, UtilityError> {
+	let picked = FuzzySelect::new()
+		.with_prompt(prompt)
+		.items(candidates)
+		.interact_opt()?;
+
+	Ok(picked.and_then(|idx| candidates.get(idx).cloned()))
+}
+
+/// Fuzzy pick any number of repositories, one at a time,
+/// until the prompt is finished or cancelled
+pub fn pick_repositories(prompt: &str, mut candidates: Vec<String>) -> Result<Vec<String>, UtilityError> {
+	let mut picked = Vec::new();
+
+	loop {
+		let mut items = vec![format!("[done, {} selected]", picked.len())];
+		items.extend(candidates.iter().cloned());
+
+		let selection = FuzzySelect::new()
+			.with_prompt(prompt)
+			.items(&items)
+			.default(0)
+			.interact_opt()?;
+
+		match selection {
+			Some(idx) if idx > 0 && idx <= candidates.len() => picked.push(candidates.remove(idx - 1)),
+			_ => break
+		}
+	}
+
+	Ok(picked)
+}
+
+/// Resolve dependencies to the requested versions for a
+/// single invocation, without touching the configuration
+pub fn apply_overrides(invocation: &mut Invocation, repository: &Repository, with: Vec<VersionedName>, context: &BatlContext) -> Result<(), UtilityError> {
+	let options = resolve::Options::overrides(with.clone());
+
+	for VersionedName { name, version, .. } in with {
+		if !repository.config().dependencies.contains_key(&name) {
+			return Err(UtilityError::ResourceDoesNotExist(format!("Dependency {}", name)));
+		}
+
+		let dependency = resolve::resolve(context, &name, &options)?
+			.ok_or(UtilityError::ResourceDoesNotExist(format!(
+				"Repository {}@{} (fetch it with `batl repository fetch {}@{}`)", name, version, name, version
+			)))?;
+
+		invocation.mount(&dependency.repository);
+	}
+
+	Ok(())
+}
+
+/// What to do when a fetch would unpack over a repository
+/// with local changes
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OnConflict {
+	/// Replace the local changes with the fetched files
+	Overwrite,
+	/// Keep the local repository as it is
+	Skip,
+	/// Unpack into the directory of the fetched version
+	Alongside
+}
+
+/// Fetch a repository from the registry and unpack it, as
+/// a fetched version when one is given, returning where it
+/// was unpacked
+///
+/// When the repository is already there with local changes,
+/// the archive is not merged into it. Instead the conflict
+/// is resolved as given, or by asking. Returns `None` if
+/// the fetch was skipped. If unpacking fails partway, the
+/// files it wrote are removed or put back.
+pub fn fetch_repository(registry: &Registry, name: &Name, version: Option<&Version>, on_conflict: Option<OnConflict>, context: &BatlContext) -> Result<Option<PathBuf>, UtilityError> {
+	let repository_path = match version {
+		Some(ver) => Repository::version_path(context, name, ver),
+		None => context.system().repository_root()
+			.map(|p| p.join(PathBuf::from(name)))
+	}.ok_or(UtilityError::NotSetup)?;
+
+	let progress = Progress::new(context, "fetch");
+	progress.report("resolve", Some(0.0), &name.to_string());
+
+	let wanted = match version {
+		Some(ver) => ver.clone(),
+		None => registry.metadata(name)?
+			.latest
+			.ok_or(UtilityError::ResourceDoesNotExist(format!("Versions of {}", name)))?
+	};
+
+	let fresh = !repository_path.join("batl.toml").exists();
+	let size = if fresh {
+		preflight_fetch(registry, name, &wanted, &repository_path)?
+	} else {
+		None
+	};
+
+	// The archive is downloaded first, so it can be checked
+	// and compared against existing files before anything
+	// is written
+	let archive_path = fetch_cache_path(name, &wanted, context)?;
+
+	progress.report("download", Some(5.0), &archive_path.display().to_string());
+
+	let body = registry.fetch(name, Some(&wanted))?;
+	let total = body.size_hint().or(size);
+	let body = progress.track(archive::Stream::new(body.into_inner(), total), "download", (5, 80));
+	Archive::receive(body, archive_path.clone())?;
+	api_notice(registry);
+
+	progress.report("verify", Some(80.0), &archive_path.display().to_string());
+	verify_fetched(registry, name, &wanted, &archive_path, context)?;
+
+	progress.report("unpack", Some(85.0), &repository_path.display().to_string());
+
+	let result = if fresh {
+		unpack_new(&archive_path, name, &wanted, repository_path, context).map(Some)
+	} else {
+		unpack_fetched(&archive_path, name, &wanted, repository_path, version.is_none(), on_conflict, context)
+	};
+	drop(std::fs::remove_file(&archive_path));
+
+	match &result {
+		Ok(Some(path)) => progress.done(&path.display().to_string()),
+		Ok(None) => progress.done("skipped"),
+		Err(_) => ()
+	}
+
+	result
+}
+
+/// Where the archive of a package version is downloaded
+/// to before it is unpacked, creating its directory
+fn fetch_cache_path(name: &Name, version: &Version, context: &BatlContext) -> Result<PathBuf, UtilityError> {
+	let archive_path = context.system().cache_root()
+		.map(|p| p.join("fetch").join(PathBuf::from(name)).join(format!("{}.tar", version)))
+		.ok_or(UtilityError::NotSetup)?;
+
+	if let Some(parent) = archive_path.parent() {
+		std::fs::create_dir_all(parent)?;
+	}
+
+	Ok(archive_path)
+}
+
+/// Check a downloaded archive against the checksum its
+/// registry stores next to it, and against the keys of
+/// trusted publishers for its namespace in the batlrc,
+/// before it is unpacked. Archives that fail are moved
+/// under gen/quarantine.
+fn verify_fetched(registry: &Registry, name: &Name, version: &Version, archive_path: &Path, context: &BatlContext) -> Result<(), UtilityError> {
+	let policy = Policy::from_system(context.system());
+
+	let verified = match registry.checksum(name, version)? {
+		Some(checksum) => checksum.verify(archive_path, name, version, policy.trusted_keys(name)),
+		None if policy.requires_checksum(name) => Err(batlerror::IntegrityError::MissingChecksum),
+		None => {
+			warning(&format!("{}@{} has no published checksum, it can't be verified", name, version));
+			return Ok(());
+		}
+	};
+
+	match verified {
+		Ok(Verified::Signed(key)) => {
+			info(&format!("{}@{} is signed by {}", name, version, key));
+			Ok(())
+		},
+		Ok(_) => Ok(()),
+		Err(batlerror::IntegrityError::IoError(err)) => Err(err.into()),
+		Err(reason) => {
+			let moved = Repository::quarantine(context, archive_path, name, Some(version))?;
+
+			Err(UtilityError::RegistryError(format!("Fetched {}@{} {}, moved it to {}", name, version, reason, moved.display())))
+		}
+	}
+}
+
+/// Unpack a downloaded archive where no repository is yet
+fn unpack_new(archive_path: &Path, name: &Name, version: &Version, repository_path: PathBuf, context: &BatlContext) -> Result<PathBuf, UtilityError> {
+	let mut transaction = Transaction::default();
+	transaction.create_dir_all(&repository_path)?;
+
+	tar::Archive::new(archive::open(archive_path)?).unpack(&repository_path)?;
+
+	let contents = std::fs::read_to_string(repository_path.join("batl.toml")).ok();
+	quarantine_unless_valid(contents, &repository_path, name, Some(version), context)?;
+
+	Ownership::touch(&repository_path)?;
+	Repository::clear_sparse(&repository_path)?;
+	transaction.commit();
+
+	Ok(repository_path)
+}
+
+/// Check that a package version fits where it will be
+/// unpacked, before anything is written, returning its
+/// size. Registries that don't serve manifests can't be
+/// checked.
+fn preflight_fetch(registry: &Registry, name: &Name, version: &Version, directory: &Path) -> Result<Option<u64>, UtilityError> {
+	let Some(manifest) = registry.manifest(name, version)? else {
+		return Ok(None);
+	};
+
+	manifest.preflight(directory)?;
+
+	Ok(manifest.size())
+}
+
+/// Unpack a downloaded archive over an existing repository,
+/// resolving any conflicts with its local changes first
+fn unpack_fetched(archive_path: &Path, name: &Name, version: &Version, repository_path: PathBuf, can_alongside: bool, on_conflict: Option<OnConflict>, context: &BatlContext) -> Result<Option<PathBuf>, UtilityError> {
+	let contents = archive::read_file(archive_path, "batl.toml")?
+		.map(|bytes| String::from_utf8_lossy(&bytes).into_owned());
+	quarantine_unless_valid(contents, archive_path, name, Some(version), context)?;
+
+	let manifest = archive::Manifest::of(archive_path)?;
+	manifest.preflight(&repository_path)?;
+
+	let conflicts = manifest.conflicts(&repository_path)?;
+	let mut transaction = Transaction::default();
+
+	let target = if conflicts.is_empty() {
+		repository_path
+	} else {
+		warning(&format!("{} has local changes the fetch would overwrite:", repository_path.display()));
+
+		for conflict in &conflicts {
+			match conflict.kind {
+				archive::ConflictKind::Modified => println!("  modified  {}", conflict.path),
+				archive::ConflictKind::Added => println!("  added     {}", conflict.path),
+				_ => println!("  {}", conflict.path)
+			}
+		}
+
+		match resolve_conflict(&repository_path, can_alongside, on_conflict)? {
+			OnConflict::Overwrite => {
+				ownership::check(&repository_path, false)?;
+
+				for conflict in conflicts.iter().filter(|conflict| conflict.kind == archive::ConflictKind::Added) {
+					transaction.remove_file(&repository_path.join(&conflict.path))?;
+				}
+
+				repository_path
+			},
+			OnConflict::Skip => {
+				info(&format!("Kept {} as it is", repository_path.display()));
+
+				return Ok(None);
+			},
+			OnConflict::Alongside => {
+				let version_path = Repository::version_path(context, name, version)
+					.ok_or(UtilityError::NotSetup)?;
+
+				if version_path.join("batl.toml").exists() && !manifest.conflicts(&version_path)?.is_empty() {
+					return Err(UtilityError::ResourceAlreadyExists(format!("Changed copy of {}@{} at {}", name, version, version_path.display())));
+				}
+
+				manifest.preflight(&version_path)?;
+
+				version_path
+			}
+		}
+	};
+
+	transaction.create_dir_all(&target)?;
+
+	for file in manifest.files.keys() {
+		transaction.snapshot(&target.join(file))?;
+	}
+
+	transaction.snapshot(&Ownership::path(&target))?;
+
+	tar::Archive::new(archive::open(archive_path)?).unpack(&target)?;
+	Ownership::touch(&target)?;
+	Repository::clear_sparse(&target)?;
+	transaction.commit();
+
+	Ok(Some(target))
+}
+
+/// Check that a fetch is the repository that was asked
+/// for, going by its batl.toml, and move it under
+/// gen/quarantine rather than installing it when it isn't
+fn quarantine_unless_valid(contents: Option<String>, fetched: &Path, name: &Name, version: Option<&Version>, context: &BatlContext) -> Result<(), UtilityError> {
+	let checked = contents
+		.ok_or(batlerror::FetchCheckError::MissingConfig)
+		.and_then(|contents| Repository::check_fetched(&contents, name, version));
+
+	let Err(reason) = checked else {
+		return Ok(());
+	};
+
+	let moved = Repository::quarantine(context, fetched, name, version)?;
+
+	Err(UtilityError::RegistryError(format!("Fetched {} {}, moved it to {}", name, reason, moved.display())))
+}
+
+/// Pick what to do about a fetch conflicting with local
+/// changes, asking when it wasn't given up front
+fn resolve_conflict(repository_path: &Path, can_alongside: bool, on_conflict: Option<OnConflict>) -> Result<OnConflict, UtilityError> {
+	if let Some(choice) = on_conflict {
+		if choice == OnConflict::Alongside && !can_alongside {
+			return Err(UtilityError::ResourceAlreadyExists(format!("Changed copy at {}, it is already a fetched version", repository_path.display())));
+		}
+
+		return Ok(choice);
+	}
+
+	if !console::Term::stdout().is_term() {
+		return Err(UtilityError::ResourceAlreadyExists(format!("Local changes in {}, choose what to do with --on-conflict", repository_path.display())));
+	}
+
+	let mut choices = vec![
+		("Overwrite the local changes", OnConflict::Overwrite),
+		("Skip, keeping the local repository", OnConflict::Skip)
+	];
+
+	if can_alongside {
+		choices.push(("Write alongside as a fetched version", OnConflict::Alongside));
+	}
+
+	let picked = dialoguer::Select::new()
+		.with_prompt("Resolve the conflict")
+		.items(&choices.iter().map(|choice| choice.0).collect::<Vec<_>>())
+		.default(1)
+		.interact_opt()?;
+
+	Ok(picked.and_then(|idx| choices.get(idx))
+		.map_or(OnConflict::Skip, |choice| choice.1))
+}
+
+/// Fetch only some paths of a repository from the
+/// registry, along with its `batl.toml`. Single files are
+/// fetched with ranged requests when the registry serves
+/// them, otherwise the archive is streamed and only the
+/// selected paths are unpacked. Returns where the
+/// repository was unpacked and the files fetched.
+pub fn fetch_sparse(registry: &Registry, name: &Name, version: Option<&Version>, paths: &[PathBuf], context: &BatlContext) -> Result<(PathBuf, Vec<String>), UtilityError> {
+	if let Some(invalid) = paths.iter().find(|path| !path.components().all(|component| matches!(component, Component::Normal(_)))) {
+		return Err(UtilityError::InvalidPath(format!("{} is not a path within the repository", invalid.display())));
+	}
+
+	let wanted = match version {
+		Some(ver) => ver.clone(),
+		None => registry.metadata(name)?
+			.latest
+			.ok_or(UtilityError::ResourceDoesNotExist(format!("Versions of {}", name)))?
+	};
+
+	let repository_path = match version {
+		Some(ver) => Repository::version_path(context, name, ver),
+		None => context.system().repository_root()
+			.map(|p| p.join(PathBuf::from(name)))
+	}.ok_or(UtilityError::NotSetup)?;
+
+	// Only ever replace files of partial copies, rather than
+	// mixing a partial fetch into a complete repository
+	if let Ok(existing) = Repository::from_path_in(context, &repository_path) {
+		if existing.sparse_paths().is_none() {
+			return Err(UtilityError::ResourceAlreadyExists(format!("Repository at {}", repository_path.display())));
+		}
+	}
+
+	// Single files can't be checked against the checksum of
+	// the whole archive, so they aren't fetched alone when
+	// one is required
+	let capabilities = registry.capabilities()?;
+	let ranged = capabilities.supports(RANGES_FEATURE)
+		&& !Policy::from_system(context.system()).requires_checksum(name);
+
+	let manifest = if ranged {
+		registry.manifest(name, &wanted)?.filter(|manifest| !manifest.spans.is_empty())
+	} else {
+		None
+	};
+
+	let fetched = if let Some(manifest) = manifest {
+		let mut files = manifest.select(paths);
+		files.push("batl.toml".to_owned());
+
+		if let Some(missing) = paths.iter().find(|path| manifest.select(&[path.to_path_buf()]).is_empty()) {
+			return Err(UtilityError::ResourceDoesNotExist(format!("{} in {}@{}", missing.display(), name, wanted)));
+		}
+
+		std::fs::create_dir_all(&repository_path)?;
+		Repository::mark_sparse(&repository_path, paths)?;
+
+		for (file, contents) in files.iter().zip(registry.fetch_files(name, &wanted, &manifest, &files)) {
+			let contents = contents?;
+			let file_path = repository_path.join(file);
+
+			if let Some(parent) = file_path.parent() {
+				std::fs::create_dir_all(parent)?;
+			}
+
+			std::fs::write(file_path, contents)?;
+		}
+
+		files
+	} else {
+		// The whole archive is downloaded anyway, so it is
+		// checked before anything is written
+		let archive_path = fetch_cache_path(name, &wanted, context)?;
+
+		Archive::receive(registry.fetch(name, Some(&wanted))?, archive_path.clone())?;
+		verify_fetched(registry, name, &wanted, &archive_path, context)?;
+
+		std::fs::create_dir_all(&repository_path)?;
+		Repository::mark_sparse(&repository_path, paths)?;
+
+		let unpacked = archive::unpack_selected(std::fs::File::open(&archive_path)?, &repository_path, paths);
+		drop(std::fs::remove_file(&archive_path));
+		let unpacked = unpacked?;
+
+		if let Some(missing) = paths.iter().find(|path| !unpacked.iter().any(|file| std::path::Path::new(file).starts_with(path))) {
+			return Err(UtilityError::ResourceDoesNotExist(format!("{} in {}@{}", missing.display(), name, wanted)));
+		}
+
+		unpacked
+	};
+
+	api_notice(registry);
+
+	let contents = std::fs::read_to_string(repository_path.join("batl.toml")).ok();
+	quarantine_unless_valid(contents, &repository_path, name, Some(&wanted), context)?;
+
+	Ownership::touch(&repository_path)?;
+
+	Ok((repository_path, fetched))
+}
+
+/// Point the links of the current workspace that are
+/// pinned to an older version of a repository at a newly
+/// fetched version, saying which links moved
+pub fn relink_fetched(name: &Name, version: &Version, context: &BatlContext) -> Result<(), UtilityError> {
+	let Some(mut workspace) = Workspace::locate_then_load_in(context, &std::env::current_dir()?).ok().flatten() else {
+		return Ok(());
+	};
+
+	for link in workspace.relink(context, name, version)? {
+		info(&format!("Relinked {} to {}@{}", link, name, version));
+	}
+
+	Ok(())
+}
+
+/// The newest fetched version of a repository that meets a
+/// requirement, if any
+pub fn newest_fetched(name: &Name, requirement: &semver::VersionReq, context: &BatlContext) -> Option<Version> {
+	let versions = context.system().version_root()?.join(PathBuf::from(name));
+
+	std::fs::read_dir(versions).ok()?
+		.filter_map(|entry| entry.ok()?.file_name().to_str()?.parse::<Version>().ok())
+		.filter(|version| requirement.matches(version))
+		.max()
+}
+
+/// Fetch only the `batl.toml` of a version into the
+/// manifest root, for looking at a repository without
+/// downloading all of it. Returns the version fetched and
+/// where its `batl.toml` is.
+pub fn fetch_manifest(registry: &Registry, name: &Name, version: Option<&Version>, context: &BatlContext) -> Result<(Version, PathBuf), UtilityError> {
+	let wanted = match version {
+		Some(ver) => ver.clone(),
+		None => registry.metadata(name)?
+			.latest
+			.ok_or(UtilityError::ResourceDoesNotExist(format!("Versions of {}", name)))?
+	};
+
+	let manifest_path = Repository::manifest_path(context, name, &wanted)
+		.ok_or(UtilityError::NotSetup)?;
+
+	// Published versions never change, so a stored one is
+	// already current
+	if manifest_path.join("batl.toml").exists() {
+		return Ok((wanted, manifest_path.join("batl.toml")));
+	}
+
+	let manifest = if registry.capabilities()?.supports(RANGES_FEATURE) {
+		registry.manifest(name, &wanted)?.filter(|manifest| manifest.spans.contains_key("batl.toml"))
+	} else {
+		None
+	};
+
+	std::fs::create_dir_all(&manifest_path)?;
+
+	if let Some(manifest) = manifest {
+		let contents = registry.fetch_file(name, &wanted, &manifest, "batl.toml")?;
+		std::fs::write(manifest_path.join("batl.toml"), contents)?;
+	} else {
+		let body = registry.fetch(name, Some(&wanted))?;
+		archive::unpack_selected(body, &manifest_path, &[])?;
+	}
+
+	api_notice(registry);
+
+	if !manifest_path.join("batl.toml").exists() {
+		drop(std::fs::remove_dir_all(&manifest_path));
+		return Err(UtilityError::ResourceDoesNotExist(format!("batl.toml in {}@{}", name, wanted)));
+	}
+
+	Ok((wanted, manifest_path.join("batl.toml")))
+}
+
+impl From<batlerror::BootstrapError> for UtilityError {
+	fn from(value: batlerror::BootstrapError) -> Self {
+		match value {
+			batlerror::BootstrapError::IoError(e) => e.into(),
+			batlerror::BootstrapError::Network(e) => UtilityError::NetworkError(e),
+			batlerror::BootstrapError::Invalid(e) => UtilityError::ConfigError(e),
+			batlerror::BootstrapError::TooLarge(_) => UtilityError::ConfigError(value.to_string()),
+			_ => UtilityError::Unknown
+		}
+	}
+}
+
+impl From<batlerror::ResolveError> for UtilityError {
+	fn from(value: batlerror::ResolveError) -> Self {
+		match value {
+			batlerror::ResolveError::Resource(e) => e.into(),
+			batlerror::ResolveError::Freeze(e) => e.into(),
+			_ => UtilityError::Unknown
+		}
+	}
+}
+
+impl From<batlerror::FreezeError> for UtilityError {
+	fn from(value: batlerror::FreezeError) -> Self {
+		match value {
+			batlerror::FreezeError::IoError(e) => e.into(),
+			batlerror::FreezeError::NotSetup => UtilityError::NotSetup,
+			batlerror::FreezeError::InvalidTag(e) => UtilityError::InvalidName(e),
+			batlerror::FreezeError::AlreadyExists(e) => UtilityError::ResourceAlreadyExists(format!("Freeze {}, replace it with --force", e)),
+			batlerror::FreezeError::Missing(e) => UtilityError::ResourceDoesNotExist(format!("Repository {}", e)),
+			batlerror::FreezeError::Resource(e) => e.into(),
+			batlerror::FreezeError::Invalid(e) => UtilityError::ConfigError(e),
+			_ => UtilityError::Unknown
+		}
+	}
+}
+
+impl From<batlerror::EditScriptError> for UtilityError {
+	fn from(value: batlerror::EditScriptError) -> Self {
+		match value {
+			batlerror::EditScriptError::IoError(e) => e.into(),
+			batlerror::EditScriptError::InvalidName(e) => UtilityError::InvalidName(e),
+			batlerror::EditScriptError::AlreadyExists(e) => UtilityError::ResourceAlreadyExists(format!("Script {}, replace it with --force", e)),
+			batlerror::EditScriptError::DoesNotExist(e) => UtilityError::ScriptNotFound(e),
+			batlerror::EditScriptError::UndefinedVariable(e) => UtilityError::ConfigError(format!("Environment variable {} is not set", e)),
+			_ => UtilityError::Unknown
+		}
+	}
+}
+
+impl From<batlerror::ScriptChainError> for UtilityError {
+	fn from(value: batlerror::ScriptChainError) -> Self {
+		match value {
+			batlerror::ScriptChainError::Missing(script, needed_by) => UtilityError::ScriptNotFound(format!("{} (needed by {})", script, needed_by)),
+			batlerror::ScriptChainError::Cycle(e) => UtilityError::ScriptError(format!("Scripts depend on each other in a cycle: {}", e)),
+			_ => UtilityError::Unknown
+		}
+	}
+}
+
+impl From<batlerror::OwnershipError> for UtilityError {
+	fn from(value: batlerror::OwnershipError) -> Self {
+		match value {
+			batlerror::OwnershipError::IoError(e) => e.into(),
+			batlerror::OwnershipError::NotOwner(e) => UtilityError::NotAuthorized(format!("Repository was created by {}", e)),
+			batlerror::OwnershipError::ReadOnly(e) => UtilityError::NotAuthorized(format!("{} is read only", e)),
+			_ => UtilityError::Unknown
+		}
+	}
+}
+
+impl From<batlerror::RegistryError> for UtilityError {
+	fn from(value: batlerror::RegistryError) -> Self {
+		match value {
+			batlerror::RegistryError::Network(e) => UtilityError::NetworkError(e),
+			batlerror::RegistryError::Unauthorized(e) => UtilityError::NotAuthorized(e),
+			batlerror::RegistryError::NotFound(e) => UtilityError::ResourceDoesNotExist(format!("Package ({})", e)),
+			batlerror::RegistryError::VersionExists(e) => UtilityError::VersionExists(e),
+			batlerror::RegistryError::InvalidPackage(e) => UtilityError::RegistryError(format!("Invalid package: {}", e)),
+			batlerror::RegistryError::InvalidResponse(e) => UtilityError::RegistryError(format!("Invalid response: {}", e)),
+			batlerror::RegistryError::Other(status, e) => UtilityError::RegistryError(format!("{} (status code {})", e, status)),
+			batlerror::RegistryError::InsufficientScope(e) => UtilityError::NotAuthorized(format!("Token lacks the {} scope, create one with `batl auth token create --scope {}`", e, e)),
+			batlerror::RegistryError::Unsupported(e) => UtilityError::RegistryError(format!("Not supported: {}", e)),
+			batlerror::RegistryError::IncompatibleApi(e) => UtilityError::RegistryError(format!("Incompatible API, {}", e)),
+			batlerror::RegistryError::UnknownRegistry(e) => UtilityError::ConfigError(format!("No registry named {} in the batlrc", e)),
+			batlerror::RegistryError::IoError(e) => e.into(),
 			_ => UtilityError::Unknown
 		}
 	}
@@ -1,10 +1,32 @@
 #![allow(clippy::module_name_repetitions)]
 
 use batl_macros::semver_struct_impl;
+use semver::Version;
 
 
 pub type VersionLatest = Version0_2_2;
 
+/// The newest config version this build can read
+pub const LATEST: &str = <VersionLatest as SchemaVersion>::VERSION;
+
+/// A config format version, implemented by the types
+/// `semver_struct_impl!` generates
+pub trait SchemaVersion {
+	/// The version as configs declare it
+	const VERSION: &'static str;
+
+	/// The version as a semver value
+	fn semver() -> Version;
+
+	/// Whether a config declaring a version is newer than
+	/// this one, and so from a newer release
+	#[inline]
+	#[must_use]
+	fn is_newer(version: &Version) -> bool {
+		*version > Self::semver()
+	}
+}
+
 semver_struct_impl!("0.2.0");
 semver_struct_impl!("0.2.1");
 semver_struct_impl!("0.2.2");